@@ -2,9 +2,9 @@
 // This project is dual-licensed under Apache 2.0 and MIT terms.
 // See LICENSE-APACHE and LICENSE-MIT for details.
 
-use std::env;
+use std::{env, fs, path::PathBuf};
 
-const PLATFORMS: [&str; 2] = ["crosvm", "qemu"];
+const PLATFORMS: [&str; 3] = ["auto", "crosvm", "qemu"];
 
 fn main() {
     println!(
@@ -12,6 +12,11 @@ fn main() {
         PLATFORMS.join("\", \"")
     );
 
+    // The library's unit tests run on the host, so don't need a platform or linker scripts.
+    if env::var_os("CARGO_FEATURE_HOST_TEST").is_some() {
+        return;
+    }
+
     let platform = env::var("CARGO_CFG_PLATFORM").expect("Missing platform name");
     assert!(
         PLATFORMS.contains(&platform.as_str()),
@@ -20,5 +25,26 @@ fn main() {
 
     println!("cargo:rustc-link-arg=-Timage.ld");
     println!("cargo:rustc-link-arg=-Tlinker/{platform}.ld");
+    println!("cargo:rustc-link-arg=-Tlinker/symbols.ld");
     println!("cargo:rerun-if-changed=linker/{platform}.ld");
+    println!("cargo:rerun-if-changed=linker/symbols.ld");
+
+    embed_symbols();
+}
+
+/// Copies the symbol table from the file named by `OSDEMO_SYMBOLS`, if any, to be embedded in the
+/// image for backtraces.
+///
+/// The symbol table is expected to be in the format output by `nm -n --defined-only`. If no symbol
+/// table is provided then an empty one is embedded, and backtraces will only show addresses.
+fn embed_symbols() {
+    println!("cargo:rerun-if-env-changed=OSDEMO_SYMBOLS");
+    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap()).join("symbols.txt");
+    let symbols = if let Some(symbols_path) = env::var_os("OSDEMO_SYMBOLS") {
+        println!("cargo:rerun-if-changed={}", symbols_path.to_string_lossy());
+        fs::read(&symbols_path).expect("Failed to read symbol table")
+    } else {
+        Vec::new()
+    };
+    fs::write(out_path, symbols).unwrap();
 }
@@ -0,0 +1,121 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Address Resolution Protocol packets for IPv4 over Ethernet.
+
+use crate::{ethernet::MacAddress, ipv4::Ipv4Address};
+
+/// The EtherType of an ARP packet.
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+
+/// The size of an ARP packet for IPv4 over Ethernet.
+pub const ARP_PACKET_SIZE: usize = 28;
+
+/// The ARP hardware type for Ethernet.
+const HARDWARE_TYPE_ETHERNET: u16 = 1;
+/// The ARP protocol type for IPv4, which is the same as its EtherType.
+const PROTOCOL_TYPE_IPV4: u16 = 0x0800;
+
+/// Whether an ARP packet is asking for an address or answering.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ArpOperation {
+    Request = 1,
+    Reply = 2,
+}
+
+/// An ARP packet mapping between IPv4 and Ethernet addresses.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ArpPacket {
+    pub operation: ArpOperation,
+    pub sender_mac: MacAddress,
+    pub sender_ip: Ipv4Address,
+    /// The target's MAC address, which is ignored in a request.
+    pub target_mac: MacAddress,
+    pub target_ip: Ipv4Address,
+}
+
+impl ArpPacket {
+    /// Parses an ARP packet from the payload of an Ethernet frame.
+    ///
+    /// Returns `None` if it is too short, or isn't for IPv4 over Ethernet.
+    pub fn parse(packet: &[u8]) -> Option<Self> {
+        let packet = packet.first_chunk::<ARP_PACKET_SIZE>()?;
+        let hardware_type = u16::from_be_bytes([packet[0], packet[1]]);
+        let protocol_type = u16::from_be_bytes([packet[2], packet[3]]);
+        if hardware_type != HARDWARE_TYPE_ETHERNET
+            || protocol_type != PROTOCOL_TYPE_IPV4
+            || packet[4] != 6
+            || packet[5] != 4
+        {
+            return None;
+        }
+        let operation = match u16::from_be_bytes([packet[6], packet[7]]) {
+            1 => ArpOperation::Request,
+            2 => ArpOperation::Reply,
+            _ => return None,
+        };
+        Some(Self {
+            operation,
+            sender_mac: MacAddress(packet[8..14].try_into().unwrap()),
+            sender_ip: Ipv4Address(packet[14..18].try_into().unwrap()),
+            target_mac: MacAddress(packet[18..24].try_into().unwrap()),
+            target_ip: Ipv4Address(packet[24..28].try_into().unwrap()),
+        })
+    }
+
+    /// Returns the packet in wire format.
+    pub fn to_bytes(&self) -> [u8; ARP_PACKET_SIZE] {
+        let mut bytes = [0; ARP_PACKET_SIZE];
+        bytes[0..2].copy_from_slice(&HARDWARE_TYPE_ETHERNET.to_be_bytes());
+        bytes[2..4].copy_from_slice(&PROTOCOL_TYPE_IPV4.to_be_bytes());
+        bytes[4] = 6;
+        bytes[5] = 4;
+        bytes[6..8].copy_from_slice(&(self.operation as u16).to_be_bytes());
+        bytes[8..14].copy_from_slice(&self.sender_mac.0);
+        bytes[14..18].copy_from_slice(&self.sender_ip.0);
+        bytes[18..24].copy_from_slice(&self.target_mac.0);
+        bytes[24..28].copy_from_slice(&self.target_ip.0);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_request() {
+        let packet = [
+            0x00, 0x01, 0x08, 0x00, 0x06, 0x04, 0x00, 0x01, 0x52, 0x55, 0x0a, 0x00, 0x02, 0x02,
+            0x0a, 0x00, 0x02, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x02, 0x0f,
+        ];
+        let request = ArpPacket::parse(&packet).unwrap();
+        assert_eq!(
+            request,
+            ArpPacket {
+                operation: ArpOperation::Request,
+                sender_mac: MacAddress([0x52, 0x55, 0x0a, 0x00, 0x02, 0x02]),
+                sender_ip: Ipv4Address([10, 0, 2, 2]),
+                target_mac: MacAddress::default(),
+                target_ip: Ipv4Address([10, 0, 2, 15]),
+            }
+        );
+        assert_eq!(request.to_bytes(), packet);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert_eq!(ArpPacket::parse(&[0; ARP_PACKET_SIZE - 1]), None);
+        let mut packet = ArpPacket {
+            operation: ArpOperation::Reply,
+            sender_mac: MacAddress([1; 6]),
+            sender_ip: Ipv4Address([1; 4]),
+            target_mac: MacAddress([2; 6]),
+            target_ip: Ipv4Address([2; 4]),
+        }
+        .to_bytes();
+        packet[7] = 3;
+        assert_eq!(ArpPacket::parse(&packet), None);
+    }
+}
@@ -0,0 +1,368 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A common interface to block storage devices, whatever their driver.
+
+#[cfg(feature = "drivers")]
+use crate::drivers::{
+    ahci::AhciError, nvme::NvmeError, sdhci::SdhciError, usb_storage::UsbStorageError,
+};
+use crate::{
+    block_cache::BlockCache,
+    block_overlay::BlockOverlay,
+    virtio::{VirtioBlk, VirtioDevice},
+};
+use alloc::{boxed::Box, string::String, vec};
+use core::fmt::{self, Display, Formatter};
+
+/// A block storage device.
+pub trait BlockDevice {
+    /// Returns a short name for the kind of device, such as `virtio`, `nvme` or `ahci`.
+    fn kind(&self) -> &'static str;
+
+    /// Returns a human-readable identifier for the device, such as its model or serial number.
+    fn id(&mut self) -> Result<String, BlockError>;
+
+    /// Returns the size of each block in bytes.
+    fn block_size(&self) -> usize;
+
+    /// Returns the number of blocks on the device.
+    fn capacity(&self) -> u64;
+
+    /// Returns whether the device is read-only.
+    fn readonly(&self) -> bool;
+
+    /// Reads consecutive blocks starting at `block_id` into the given buffer.
+    ///
+    /// The length of the buffer must be a multiple of the block size.
+    fn read_blocks(&mut self, block_id: u64, buf: &mut [u8]) -> Result<(), BlockError>;
+
+    /// Writes the given buffer to consecutive blocks starting at `block_id`.
+    ///
+    /// The length of the buffer must be a multiple of the block size.
+    fn write_blocks(&mut self, block_id: u64, buf: &[u8]) -> Result<(), BlockError>;
+
+    /// Returns the device as a VirtIO block device if it is one, for access to its asynchronous
+    /// request queue.
+    fn as_virtio_blk(&mut self) -> Option<&mut VirtioDevice<VirtioBlk>> {
+        None
+    }
+}
+
+/// An error from a block device driver.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BlockError {
+    /// The device has been write-protected with `CachedBlockDevice::set_write_protected`.
+    WriteProtected,
+    Virtio(virtio_drivers::Error),
+    #[cfg(feature = "drivers")]
+    Nvme(NvmeError),
+    #[cfg(feature = "drivers")]
+    Ahci(AhciError),
+    #[cfg(feature = "drivers")]
+    UsbStorage(UsbStorageError),
+    #[cfg(feature = "drivers")]
+    Sdhci(SdhciError),
+}
+
+impl Display for BlockError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::WriteProtected => write!(f, "Device is write-protected"),
+            Self::Virtio(e) => write!(f, "VirtIO error: {e}"),
+            #[cfg(feature = "drivers")]
+            Self::Nvme(e) => write!(f, "NVMe error: {e}"),
+            #[cfg(feature = "drivers")]
+            Self::Ahci(e) => write!(f, "AHCI error: {e}"),
+            #[cfg(feature = "drivers")]
+            Self::UsbStorage(e) => write!(f, "USB storage error: {e}"),
+            #[cfg(feature = "drivers")]
+            Self::Sdhci(e) => write!(f, "SDHCI error: {e}"),
+        }
+    }
+}
+
+impl From<virtio_drivers::Error> for BlockError {
+    fn from(e: virtio_drivers::Error) -> Self {
+        Self::Virtio(e)
+    }
+}
+
+#[cfg(feature = "drivers")]
+impl From<NvmeError> for BlockError {
+    fn from(e: NvmeError) -> Self {
+        Self::Nvme(e)
+    }
+}
+
+#[cfg(feature = "drivers")]
+impl From<AhciError> for BlockError {
+    fn from(e: AhciError) -> Self {
+        Self::Ahci(e)
+    }
+}
+
+#[cfg(feature = "drivers")]
+impl From<UsbStorageError> for BlockError {
+    fn from(e: UsbStorageError) -> Self {
+        Self::UsbStorage(e)
+    }
+}
+
+#[cfg(feature = "drivers")]
+impl From<SdhciError> for BlockError {
+    fn from(e: SdhciError) -> Self {
+        Self::Sdhci(e)
+    }
+}
+
+/// How much has been read from and written to a block device, including reads satisfied from its
+/// cache and writes to its overlay.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct IoStats {
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+impl Display for IoStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes read, {} bytes written",
+            self.bytes_read, self.bytes_written
+        )
+    }
+}
+
+/// A block device with a cache of recently used blocks in front of it.
+///
+/// Reads which miss the cache also read ahead a few blocks, as most reads are sequential. Writes go
+/// straight through to the device unless write-back is enabled, in which case they are only
+/// written when evicted from the cache or on `sync`.
+///
+/// The device may also be write-protected, or have a copy-on-write overlay in memory put in front
+/// of it so that writes succeed without modifying it.
+pub struct CachedBlockDevice {
+    device: Box<dyn BlockDevice>,
+    cache: BlockCache,
+    write_back: bool,
+    write_protected: bool,
+    /// Blocks written since the overlay was enabled, which take precedence over the device's.
+    overlay: Option<BlockOverlay>,
+    io_stats: IoStats,
+}
+
+impl CachedBlockDevice {
+    /// The number of blocks cached for each device by default.
+    pub const DEFAULT_CACHE_BLOCKS: usize = 16;
+
+    /// The maximum number of blocks read from the device on a cache miss.
+    const READ_AHEAD_BLOCKS: u64 = 8;
+
+    /// Wraps the given device with a write-through cache of the default size.
+    pub fn new(device: Box<dyn BlockDevice>) -> Self {
+        let cache = BlockCache::new(device.block_size(), Self::DEFAULT_CACHE_BLOCKS);
+        Self {
+            device,
+            cache,
+            write_back: false,
+            write_protected: false,
+            overlay: None,
+            io_stats: IoStats::default(),
+        }
+    }
+
+    /// Returns the underlying device, bypassing the cache, write protection and any overlay.
+    pub fn uncached(&mut self) -> &mut dyn BlockDevice {
+        self.device.as_mut()
+    }
+
+    pub fn cache(&self) -> &BlockCache {
+        &self.cache
+    }
+
+    pub fn write_back(&self) -> bool {
+        self.write_back
+    }
+
+    /// Changes the number of blocks cached and whether writes are cached, writing back any dirty
+    /// blocks first.
+    pub fn configure(&mut self, blocks: usize, write_back: bool) -> Result<(), BlockError> {
+        self.sync()?;
+        self.cache.set_capacity(blocks);
+        self.write_back = write_back;
+        Ok(())
+    }
+
+    pub fn write_protected(&self) -> bool {
+        self.write_protected
+    }
+
+    /// Sets whether writes to the device should fail, writing back any dirty blocks first.
+    ///
+    /// Writes still succeed while an overlay is enabled, as they don't reach the device.
+    pub fn set_write_protected(&mut self, write_protected: bool) -> Result<(), BlockError> {
+        self.sync()?;
+        self.write_protected = write_protected;
+        Ok(())
+    }
+
+    /// Returns how much has been read and written through this wrapper since it was created.
+    pub fn io_stats(&self) -> IoStats {
+        self.io_stats
+    }
+
+    pub fn overlay(&self) -> Option<&BlockOverlay> {
+        self.overlay.as_ref()
+    }
+
+    /// Puts an empty copy-on-write overlay in front of the device if it doesn't already have one,
+    /// writing back any dirty blocks first. Until the overlay is discarded, writes only go to it.
+    pub fn enable_overlay(&mut self) -> Result<(), BlockError> {
+        if self.overlay.is_none() {
+            self.sync()?;
+            self.overlay = Some(BlockOverlay::new(self.block_size()));
+        }
+        Ok(())
+    }
+
+    /// Removes the overlay, discarding everything written since it was enabled.
+    ///
+    /// Returns the overlay, or `None` if there wasn't one.
+    pub fn discard_overlay(&mut self) -> Option<BlockOverlay> {
+        // The cache only ever holds the device's contents while there is an overlay, so is still
+        // valid.
+        self.overlay.take()
+    }
+
+    /// Writes all dirty blocks back to the device.
+    pub fn sync(&mut self) -> Result<(), BlockError> {
+        for block_id in self.cache.dirty_blocks() {
+            if let Some(data) = self.cache.peek(block_id) {
+                self.device.write_blocks(block_id, data)?;
+            }
+            self.cache.mark_clean(block_id);
+        }
+        Ok(())
+    }
+
+    /// Reads consecutive blocks from the device through the cache, ignoring any overlay.
+    fn read_blocks_from_device(&mut self, block_id: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        let block_size = self.block_size();
+        if self.cache.capacity() == 0 || !buf.len().is_multiple_of(block_size) {
+            return self.device.read_blocks(block_id, buf);
+        }
+        let blocks = (buf.len() / block_size) as u64;
+        for (index, chunk) in (0..).zip(buf.chunks_exact_mut(block_size)) {
+            let current = block_id + index;
+            if !self.cache.read(current, chunk) {
+                self.fill(current, blocks - index)?;
+                if let Some(data) = self.cache.peek(current) {
+                    chunk.copy_from_slice(data);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes consecutive blocks to the device through the cache.
+    fn write_blocks_to_device(&mut self, block_id: u64, buf: &[u8]) -> Result<(), BlockError> {
+        let block_size = self.block_size();
+        if self.cache.capacity() == 0 || !buf.len().is_multiple_of(block_size) {
+            return self.device.write_blocks(block_id, buf);
+        }
+        if self.write_back && !self.device.readonly() {
+            for (index, data) in (0..).zip(buf.chunks_exact(block_size)) {
+                self.insert(block_id + index, data, true)?;
+            }
+        } else {
+            self.device.write_blocks(block_id, buf)?;
+            // Keep any cached copies up to date.
+            for (index, data) in (0..).zip(buf.chunks_exact(block_size)) {
+                if self.cache.contains(block_id + index) {
+                    self.cache.insert(block_id + index, data, false);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the given block to the cache, first writing back the block it would evict if that is
+    /// dirty.
+    fn insert(&mut self, block_id: u64, data: &[u8], dirty: bool) -> Result<(), BlockError> {
+        if let Some((victim, victim_data)) = self.cache.dirty_victim() {
+            self.device.write_blocks(victim, victim_data)?;
+            self.cache.mark_clean(victim);
+        }
+        self.cache.insert(block_id, data, dirty);
+        Ok(())
+    }
+
+    /// Reads the given block into the cache after a miss, along with up to `wanted - 1` following
+    /// blocks which the caller is about to read, or a few blocks of read-ahead if more.
+    fn fill(&mut self, block_id: u64, wanted: u64) -> Result<(), BlockError> {
+        // Stop before any block which is already cached, so as not to overwrite dirty data.
+        let count = wanted
+            .max(Self::READ_AHEAD_BLOCKS)
+            .min(self.cache.capacity() as u64)
+            .min(self.device.capacity().saturating_sub(block_id))
+            .max(1);
+        let count = (1..count)
+            .find(|&offset| self.cache.contains(block_id + offset))
+            .unwrap_or(count);
+        let block_size = self.block_size();
+        let mut buffer = vec![0; count as usize * block_size];
+        self.device.read_blocks(block_id, &mut buffer)?;
+        for (offset, data) in buffer.chunks_exact(block_size).enumerate() {
+            self.insert(block_id + offset as u64, data, false)?;
+        }
+        self.cache.record_read_ahead(count.saturating_sub(wanted));
+        Ok(())
+    }
+}
+
+impl BlockDevice for CachedBlockDevice {
+    fn kind(&self) -> &'static str {
+        self.device.kind()
+    }
+
+    fn id(&mut self) -> Result<String, BlockError> {
+        self.device.id()
+    }
+
+    fn block_size(&self) -> usize {
+        self.device.block_size()
+    }
+
+    fn capacity(&self) -> u64 {
+        self.device.capacity()
+    }
+
+    fn readonly(&self) -> bool {
+        self.overlay.is_none() && (self.write_protected || self.device.readonly())
+    }
+
+    fn read_blocks(&mut self, block_id: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.read_blocks_from_device(block_id, buf)?;
+        if let Some(overlay) = &self.overlay {
+            for (index, chunk) in (0..).zip(buf.chunks_exact_mut(self.device.block_size())) {
+                overlay.read(block_id + index, chunk);
+            }
+        }
+        self.io_stats.bytes_read += buf.len() as u64;
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, block_id: u64, buf: &[u8]) -> Result<(), BlockError> {
+        if let Some(overlay) = &mut self.overlay {
+            overlay.write(block_id, buf);
+        } else if self.write_protected {
+            return Err(BlockError::WriteProtected);
+        } else {
+            self.write_blocks_to_device(block_id, buf)?;
+        }
+        self.io_stats.bytes_written += buf.len() as u64;
+        Ok(())
+    }
+}
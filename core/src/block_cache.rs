@@ -0,0 +1,311 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A cache of fixed-size blocks from a block device, with least-recently-used eviction.
+//!
+//! The cache only keeps track of block contents; reading from and writing back to the device is
+//! up to the caller.
+
+use alloc::{collections::btree_map::BTreeMap, vec::Vec};
+use core::fmt::{self, Display, Formatter};
+
+/// Statistics about how a block cache has been used.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CacheStats {
+    /// The number of block reads which were satisfied from the cache.
+    pub hits: u64,
+    /// The number of block reads which had to go to the device.
+    pub misses: u64,
+    /// The number of extra blocks read from the device in anticipation of future reads.
+    pub read_ahead: u64,
+    /// The number of dirty blocks which have been written back to the device.
+    pub write_backs: u64,
+    /// The number of blocks which have been evicted to make room for others.
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    /// Returns the percentage of reads which were hits, rounded down.
+    pub fn hit_percent(&self) -> u64 {
+        (self.hits * 100)
+            .checked_div(self.hits + self.misses)
+            .unwrap_or(0)
+    }
+}
+
+impl Display for CacheStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} hits, {} misses ({}% hit rate), {} read ahead, {} written back, {} evicted",
+            self.hits,
+            self.misses,
+            self.hit_percent(),
+            self.read_ahead,
+            self.write_backs,
+            self.evictions
+        )
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Entry {
+    data: Vec<u8>,
+    /// Whether the block has been modified since it was last written to the device.
+    dirty: bool,
+    /// The value of the cache's clock when the block was last used.
+    last_used: u64,
+}
+
+/// A cache of up to a fixed number of blocks, keyed by block ID.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BlockCache {
+    block_size: usize,
+    capacity: usize,
+    entries: BTreeMap<u64, Entry>,
+    /// Incremented every time a block is used, to find the least recently used block.
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl BlockCache {
+    /// Creates a new empty cache for up to `capacity` blocks of `block_size` bytes each.
+    pub fn new(block_size: usize, capacity: usize) -> Self {
+        Self {
+            block_size,
+            capacity,
+            entries: BTreeMap::new(),
+            clock: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Returns the size of each block in bytes.
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Returns the maximum number of blocks which may be cached.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of blocks currently cached.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether no blocks are currently cached.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Returns whether the given block is cached, without affecting statistics or eviction order.
+    pub fn contains(&self, block_id: u64) -> bool {
+        self.entries.contains_key(&block_id)
+    }
+
+    /// Copies the given block into `buf` if it is cached, and records a hit or miss.
+    ///
+    /// Returns whether the block was cached.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buf` isn't exactly one block long.
+    pub fn read(&mut self, block_id: u64, buf: &mut [u8]) -> bool {
+        assert_eq!(buf.len(), self.block_size);
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(&block_id) {
+            entry.last_used = self.clock;
+            buf.copy_from_slice(&entry.data);
+            self.stats.hits += 1;
+            true
+        } else {
+            self.stats.misses += 1;
+            false
+        }
+    }
+
+    /// Records that the given number of blocks were read ahead of when they were needed.
+    pub fn record_read_ahead(&mut self, blocks: u64) {
+        self.stats.read_ahead += blocks;
+    }
+
+    /// Returns the block which would be evicted by inserting a new block, if it is dirty and so
+    /// must be written back first.
+    pub fn dirty_victim(&self) -> Option<(u64, &[u8])> {
+        if self.entries.len() < self.capacity {
+            return None;
+        }
+        let (&block_id, entry) = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)?;
+        entry.dirty.then_some((block_id, entry.data.as_slice()))
+    }
+
+    /// Inserts or replaces the given block, evicting the least recently used block if the cache is
+    /// full.
+    ///
+    /// Any dirty block evicted is discarded, so callers should check `dirty_victim` first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` isn't exactly one block long.
+    pub fn insert(&mut self, block_id: u64, data: &[u8], dirty: bool) {
+        assert_eq!(data.len(), self.block_size);
+        if self.capacity == 0 {
+            return;
+        }
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(&block_id) {
+            entry.data.copy_from_slice(data);
+            entry.dirty |= dirty;
+            entry.last_used = self.clock;
+            return;
+        }
+        if self.entries.len() >= self.capacity {
+            self.evict_one();
+        }
+        self.entries.insert(
+            block_id,
+            Entry {
+                data: data.to_vec(),
+                dirty,
+                last_used: self.clock,
+            },
+        );
+    }
+
+    /// Returns the IDs of all dirty blocks, in ascending order.
+    pub fn dirty_blocks(&self) -> Vec<u64> {
+        self.entries
+            .iter()
+            .filter(|(_, entry)| entry.dirty)
+            .map(|(&block_id, _)| block_id)
+            .collect()
+    }
+
+    /// Returns the contents of the given block if it is cached, without affecting statistics or
+    /// eviction order.
+    pub fn peek(&self, block_id: u64) -> Option<&[u8]> {
+        self.entries
+            .get(&block_id)
+            .map(|entry| entry.data.as_slice())
+    }
+
+    /// Marks the given block as having been written back to the device.
+    pub fn mark_clean(&mut self, block_id: u64) {
+        if let Some(entry) = self.entries.get_mut(&block_id)
+            && entry.dirty
+        {
+            entry.dirty = false;
+            self.stats.write_backs += 1;
+        }
+    }
+
+    /// Changes the maximum number of blocks which may be cached, evicting the least recently used
+    /// blocks if there are now too many.
+    ///
+    /// Any dirty blocks evicted are discarded, so callers should write them back first.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > self.capacity {
+            self.evict_one();
+        }
+    }
+
+    fn evict_one(&mut self) {
+        if let Some(block_id) = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(&block_id, _)| block_id)
+        {
+            self.entries.remove(&block_id);
+            self.stats.evictions += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hits_and_misses() {
+        let mut cache = BlockCache::new(4, 2);
+        let mut buf = [0; 4];
+        assert!(!cache.read(7, &mut buf));
+        cache.insert(7, &[1, 2, 3, 4], false);
+        assert!(cache.read(7, &mut buf));
+        assert_eq!(buf, [1, 2, 3, 4]);
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(cache.stats().hit_percent(), 50);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = BlockCache::new(1, 2);
+        let mut buf = [0; 1];
+        cache.insert(1, &[1], false);
+        cache.insert(2, &[2], false);
+        // Use block 1, so block 2 is now the least recently used.
+        assert!(cache.read(1, &mut buf));
+        cache.insert(3, &[3], false);
+        assert!(cache.contains(1));
+        assert!(!cache.contains(2));
+        assert!(cache.contains(3));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn dirty_blocks() {
+        let mut cache = BlockCache::new(1, 2);
+        cache.insert(5, &[5], true);
+        cache.insert(3, &[3], false);
+        assert_eq!(cache.dirty_victim(), Some((5, [5].as_slice())));
+        cache.insert(3, &[4], true);
+        assert_eq!(cache.dirty_blocks(), vec![3, 5]);
+        assert_eq!(cache.peek(3), Some([4].as_slice()));
+
+        cache.mark_clean(5);
+        cache.mark_clean(5);
+        assert_eq!(cache.dirty_victim(), None);
+        assert_eq!(cache.dirty_blocks(), vec![3]);
+        assert_eq!(cache.stats().write_backs, 1);
+
+        // Inserting a clean copy doesn't lose the dirty flag.
+        cache.insert(3, &[4], false);
+        assert_eq!(cache.dirty_blocks(), vec![3]);
+    }
+
+    #[test]
+    fn resize() {
+        let mut cache = BlockCache::new(1, 3);
+        for block_id in 0..3 {
+            cache.insert(block_id, &[block_id as u8], false);
+        }
+        cache.set_capacity(1);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.contains(2));
+
+        cache.set_capacity(0);
+        assert!(cache.is_empty());
+        cache.insert(4, &[4], false);
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,104 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A copy-on-write overlay of fixed-size blocks kept in memory, so that a block device can be
+//! written to without modifying it.
+//!
+//! The overlay only keeps track of the blocks which have been written; reading the rest from the
+//! underlying device is up to the caller.
+
+use alloc::{collections::btree_map::BTreeMap, vec::Vec};
+
+/// The blocks written to a block device since an overlay was put in front of it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BlockOverlay {
+    block_size: usize,
+    blocks: BTreeMap<u64, Vec<u8>>,
+}
+
+impl BlockOverlay {
+    /// Creates a new empty overlay for blocks of the given size.
+    pub fn new(block_size: usize) -> Self {
+        Self {
+            block_size,
+            blocks: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the number of blocks which have been written to the overlay.
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Returns whether no blocks have been written to the overlay.
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Returns whether the given block has been written to the overlay.
+    pub fn contains(&self, block_id: u64) -> bool {
+        self.blocks.contains_key(&block_id)
+    }
+
+    /// Copies the given block into `buf` if it has been written to the overlay.
+    ///
+    /// Returns whether it had been.
+    pub fn read(&self, block_id: u64, buf: &mut [u8]) -> bool {
+        match self.blocks.get(&block_id) {
+            Some(data) => {
+                buf.copy_from_slice(data);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Writes the given buffer to consecutive blocks starting at `block_id`.
+    ///
+    /// The length of the buffer must be a multiple of the block size.
+    pub fn write(&mut self, block_id: u64, buf: &[u8]) {
+        assert!(buf.len().is_multiple_of(self.block_size));
+        for (index, data) in (0..).zip(buf.chunks_exact(self.block_size)) {
+            self.blocks.insert(block_id + index, data.to_vec());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_unwritten_block() {
+        let overlay = BlockOverlay::new(2);
+        let mut buf = [9; 2];
+        assert!(!overlay.read(3, &mut buf));
+        assert_eq!(buf, [9, 9]);
+        assert!(overlay.is_empty());
+    }
+
+    #[test]
+    fn write_several_blocks() {
+        let mut overlay = BlockOverlay::new(2);
+        overlay.write(5, &[1, 2, 3, 4]);
+        assert_eq!(overlay.len(), 2);
+        assert!(overlay.contains(5));
+        assert!(overlay.contains(6));
+        assert!(!overlay.contains(7));
+        let mut buf = [0; 2];
+        assert!(overlay.read(6, &mut buf));
+        assert_eq!(buf, [3, 4]);
+    }
+
+    #[test]
+    fn overwrite_block() {
+        let mut overlay = BlockOverlay::new(1);
+        overlay.write(0, &[1]);
+        overlay.write(0, &[2]);
+        let mut buf = [0];
+        assert!(overlay.read(0, &mut buf));
+        assert_eq!(buf, [2]);
+        assert_eq!(overlay.len(), 1);
+    }
+}
@@ -0,0 +1,164 @@
+// Copyright 2024 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A console UART which can be written to from anywhere, and read from by its single owner along
+//! with any other input sources.
+
+use crate::{
+    drivers::InterruptDriven,
+    executor::{WakerSlot, block_on},
+    input,
+};
+use arm_gic::IntId;
+use core::{
+    future::poll_fn,
+    task::{Context, Poll},
+};
+use embedded_io::{ErrorType, Read, ReadReady, Write};
+use log::{Log, Metadata, Record};
+use percore::{ExceptionLock, exception_free};
+use spin::mutex::SpinMutex;
+
+/// Woken by the UART interrupt, for a future waiting to read from the console.
+static CONSOLE_WAKER: WakerSlot = WakerSlot::new();
+
+/// A console guarded by a spin mutex so that it may be shared between threads.
+///
+/// Any thread may write to it, but only a single thread may read from it.
+pub struct SharedConsole<T: Send> {
+    pub console: ExceptionLock<SpinMutex<T>>,
+}
+
+impl<T: Send> SharedConsole<T> {
+    /// Wraps the given UART driver to be shared.
+    pub const fn new(console: T) -> Self {
+        Self {
+            console: ExceptionLock::new(SpinMutex::new(console)),
+        }
+    }
+}
+
+impl<T: Send + InterruptDriven> SharedConsole<T> {
+    /// Lets the underlying UART driver handle the given interrupt, and wakes any future waiting to
+    /// read from the console.
+    pub fn handle_irq(&self, intid: IntId) {
+        exception_free(|token| {
+            self.console.borrow(token).lock().handle_irq(intid);
+        });
+        CONSOLE_WAKER.wake();
+    }
+}
+
+impl<T: ErrorType + Send> ErrorType for &SharedConsole<T> {
+    type Error = T::Error;
+}
+
+impl<T: ErrorType + Send + Write> Write for &SharedConsole<T> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        exception_free(|token| self.console.borrow(token).lock().write(buf))
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        exception_free(|token| self.console.borrow(token).lock().flush())
+    }
+}
+
+/// Logs each record as a line on the console.
+impl<T: Send + Write> Log for SharedConsole<T> {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        exception_free(|token| {
+            let console = &mut *self.console.borrow(token).lock();
+            writeln!(console, "[{}] {}", record.level(), record.args()).unwrap();
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+/// The owner of a shared console, who has unique read access.
+///
+/// The reading side can't be shared, as the caller of `ReadReady::read_ready` needs to be
+/// guaranteed that bytes will be available to read when the next call `Read::read`.
+pub struct Console<T: Send + 'static> {
+    shared: &'static SharedConsole<T>,
+}
+
+impl<T: Send + 'static> Console<T> {
+    /// Creates the unique reading side of the given shared console.
+    ///
+    /// There must be at most one `Console` for each `SharedConsole`, or their reads would race.
+    pub fn new(shared: &'static SharedConsole<T>) -> Self {
+        Self { shared }
+    }
+
+    /// Returns a shared writer for the console. This may be copied freely.
+    pub fn shared(&self) -> &'static SharedConsole<T> {
+        self.shared
+    }
+}
+
+impl<T: ErrorType + Send + Write> Write for Console<T> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.shared.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Write::flush(&mut self.shared)
+    }
+}
+
+impl<T: ErrorType + Send + 'static> ErrorType for Console<T> {
+    type Error = T::Error;
+}
+
+impl<T: ErrorType + Read + ReadReady + Send + 'static> Console<T> {
+    /// Reads some bytes from the console or another input source if any are available, or else
+    /// registers to be woken when the UART receives some.
+    pub fn poll_read(
+        &mut self,
+        context: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, T::Error>> {
+        let count = input::read(buf);
+        if count > 0 {
+            return Poll::Ready(Ok(count));
+        }
+        // Register before checking the UART, so that data arriving in between still wakes us.
+        CONSOLE_WAKER.register(context.waker());
+        // Only hold the lock with exceptions masked while checking, not while waiting.
+        exception_free(|token| {
+            let mut console = self.shared.console.borrow(token).lock();
+            match console.read_ready() {
+                Ok(true) => Poll::Ready(console.read(buf)),
+                Ok(false) => Poll::Pending,
+                Err(e) => Poll::Ready(Err(e)),
+            }
+        })
+    }
+
+    /// Reads some bytes from the console or another input source, completing once at least one
+    /// byte is available.
+    pub async fn read_async(&mut self, buf: &mut [u8]) -> Result<usize, T::Error> {
+        poll_fn(|context| self.poll_read(context, buf)).await
+    }
+}
+
+impl<T: ErrorType + Read + ReadReady + Send + 'static> Read for Console<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        block_on(self.read_async(buf))
+    }
+}
+
+impl<T: ErrorType + ReadReady + Send + 'static> ReadReady for Console<T> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        if input::ready() {
+            return Ok(true);
+        }
+        exception_free(|token| self.shared.console.borrow(token).lock().read_ready())
+    }
+}
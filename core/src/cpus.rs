@@ -2,16 +2,24 @@
 // This project is dual-licensed under Apache 2.0 and MIT terms.
 // See LICENSE-APACHE and LICENSE-MIT for details.
 
+//! Identifying CPU cores, and state kept separately for each one.
+
 use crate::FDT;
 use alloc::boxed::Box;
 use arm_sysregs::read_mpidr_el1;
-use core::cell::RefCell;
+use core::{
+    cell::RefCell,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 use dtoolkit::ToCellInt;
 use percore::{Cores, ExceptionLock, PerCore};
 use spin::Lazy;
 
 pub const MPIDR_AFFINITY_MASK: u64 = 0xff00ffffff;
 
+/// The most CPUs which can be used, as set by `limit_cpu_count`.
+static CPU_COUNT_LIMIT: AtomicUsize = AtomicUsize::new(usize::MAX);
+
 /// Reads the MPIDR value and returns the affinity bytes, masking out the other bits.
 pub fn mpidr_affinity() -> u64 {
     read_mpidr_el1().bits() & MPIDR_AFFINITY_MASK
@@ -22,9 +30,31 @@ pub fn current_cpu_index() -> usize {
     mpidr_to_cpu_index(mpidr_affinity()).unwrap()
 }
 
-/// Returns the total number of CPUs on the system.
+/// Returns the index of the current CPU core in the FDT, or `None` if the FDT hasn't been set yet.
+pub fn try_current_cpu_index() -> Option<usize> {
+    FDT.get()?;
+    mpidr_to_cpu_index(mpidr_affinity())
+}
+
+/// Returns the number of CPUs on the system which can be used.
+///
+/// This is the number in the FDT, unless `limit_cpu_count` has set a lower limit.
 pub fn cpu_count() -> usize {
-    FDT.get().unwrap().cpus().unwrap().cpus().count()
+    FDT.get()
+        .unwrap()
+        .cpus()
+        .unwrap()
+        .cpus()
+        .count()
+        .min(CPU_COUNT_LIMIT.load(Ordering::Relaxed))
+}
+
+/// Limits the CPUs which can be used to the first `count` in the FDT, e.g. because the interrupt
+/// controller can't serve the rest.
+///
+/// Per-core state which has already been created for more CPUs is left as it is.
+pub fn limit_cpu_count(count: usize) {
+    CPU_COUNT_LIMIT.store(count, Ordering::Relaxed);
 }
 
 /// Returns the index in the FDT of the CPU core with the given MPIDR affinity fields, if it exists.
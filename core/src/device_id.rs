@@ -0,0 +1,158 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Stable identifiers for devices in the device registry, such as `blk0` or `net1`.
+
+use core::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+/// The kinds of device which may be registered.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum DeviceKind {
+    Uart,
+    Rtc,
+    Block,
+    Network,
+    Console,
+    Vsock,
+    Pci,
+    Usb,
+    Input,
+    Balloon,
+}
+
+impl DeviceKind {
+    /// All device kinds, in the order they are listed.
+    pub const ALL: [Self; 10] = [
+        Self::Uart,
+        Self::Rtc,
+        Self::Block,
+        Self::Network,
+        Self::Console,
+        Self::Vsock,
+        Self::Pci,
+        Self::Usb,
+        Self::Input,
+        Self::Balloon,
+    ];
+
+    /// Returns the prefix used for IDs of devices of this kind.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Self::Uart => "uart",
+            Self::Rtc => "rtc",
+            Self::Block => "blk",
+            Self::Network => "net",
+            Self::Console => "con",
+            Self::Vsock => "vsock",
+            Self::Pci => "pci",
+            Self::Usb => "usb",
+            Self::Input => "input",
+            Self::Balloon => "balloon",
+        }
+    }
+
+    /// Returns a human-readable name for this kind of device.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Uart => "UART",
+            Self::Rtc => "RTC",
+            Self::Block => "block",
+            Self::Network => "network",
+            Self::Console => "console",
+            Self::Vsock => "vsock",
+            Self::Pci => "PCI",
+            Self::Usb => "USB controller",
+            Self::Input => "input",
+            Self::Balloon => "memory balloon",
+        }
+    }
+}
+
+/// The ID of a device, made up of its kind and a number which is unique among devices of that
+/// kind.
+///
+/// Numbers are assigned in the order devices are discovered, and never reused.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct DeviceId {
+    pub kind: DeviceKind,
+    pub number: usize,
+}
+
+impl Display for DeviceId {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.kind.prefix(), self.number)
+    }
+}
+
+/// An error parsing a device ID.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ParseDeviceIdError;
+
+impl FromStr for DeviceId {
+    type Err = ParseDeviceIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DeviceKind::ALL
+            .into_iter()
+            .find_map(|kind| {
+                let number = s.strip_prefix(kind.prefix())?;
+                if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+                    return None;
+                }
+                Some(Self {
+                    kind,
+                    number: number.parse().ok()?,
+                })
+            })
+            .ok_or(ParseDeviceIdError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format() {
+        let id = DeviceId {
+            kind: DeviceKind::Block,
+            number: 2,
+        };
+        assert_eq!(id.to_string(), "blk2");
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            "vsock0".parse(),
+            Ok(DeviceId {
+                kind: DeviceKind::Vsock,
+                number: 0
+            })
+        );
+        assert_eq!(
+            "con12".parse(),
+            Ok(DeviceId {
+                kind: DeviceKind::Console,
+                number: 12
+            })
+        );
+        for kind in DeviceKind::ALL {
+            let id = DeviceId { kind, number: 3 };
+            assert_eq!(id.to_string().parse(), Ok(id));
+        }
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert_eq!("".parse::<DeviceId>(), Err(ParseDeviceIdError));
+        assert_eq!("blk".parse::<DeviceId>(), Err(ParseDeviceIdError));
+        assert_eq!("blk+1".parse::<DeviceId>(), Err(ParseDeviceIdError));
+        assert_eq!("disk0".parse::<DeviceId>(), Err(ParseDeviceIdError));
+        assert_eq!("3".parse::<DeviceId>(), Err(ParseDeviceIdError));
+    }
+}
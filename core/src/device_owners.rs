@@ -0,0 +1,153 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Tracking which subsystem owns each device, so that subsystems which would interfere with each
+//! other take turns.
+//!
+//! For example, a vsock device's connection manager hands out each event to whoever polls it next,
+//! so the shell sessions listening on a vsock port and a command connecting to a vsock peer would
+//! each lose events meant for the other.
+
+use crate::device_id::DeviceId;
+use alloc::collections::btree_map::{BTreeMap, Entry};
+use core::fmt::{self, Display, Formatter};
+
+/// The owners of all devices which are currently claimed.
+#[derive(Debug, Default)]
+pub struct DeviceOwners {
+    /// The owner of each claimed device, and how many claims it holds on it.
+    claims: BTreeMap<DeviceId, (&'static str, usize)>,
+}
+
+impl DeviceOwners {
+    /// Creates an empty set of owners, with no devices claimed.
+    pub const fn new() -> Self {
+        Self {
+            claims: BTreeMap::new(),
+        }
+    }
+
+    /// Claims the given device for the given owner, such as `"sessions"`, so that nothing else can
+    /// claim it until the claim is released.
+    ///
+    /// An owner may hold several claims on the same device at once, such as for two connections.
+    /// Returns an error if the device is owned by something else.
+    pub fn claim(&mut self, id: DeviceId, owner: &'static str) -> Result<DeviceClaim, DeviceBusy> {
+        match self.claims.entry(id) {
+            Entry::Vacant(entry) => {
+                entry.insert((owner, 1));
+            }
+            Entry::Occupied(mut entry) => {
+                let (current_owner, count) = entry.get_mut();
+                if *current_owner != owner {
+                    return Err(DeviceBusy {
+                        id,
+                        owner: *current_owner,
+                    });
+                }
+                *count += 1;
+            }
+        }
+        Ok(DeviceClaim { id })
+    }
+
+    /// Releases the given claim, so that the device no longer has an owner once all of its owner's
+    /// claims have been released.
+    pub fn release(&mut self, claim: DeviceClaim) {
+        let Entry::Occupied(mut entry) = self.claims.entry(claim.id) else {
+            unreachable!("{} was claimed but has no owner", claim.id);
+        };
+        let (_, count) = entry.get_mut();
+        *count -= 1;
+        if *count == 0 {
+            entry.remove();
+        }
+    }
+
+    /// Returns the current owner of the given device, if it is claimed.
+    pub fn owner(&self, id: DeviceId) -> Option<&'static str> {
+        self.claims.get(&id).map(|&(owner, _)| owner)
+    }
+}
+
+/// A claim on a device, which must be given back to `DeviceOwners::release` once the owner has
+/// finished with the device.
+#[must_use]
+#[derive(Debug, Eq, PartialEq)]
+pub struct DeviceClaim {
+    id: DeviceId,
+}
+
+impl DeviceClaim {
+    /// Returns the ID of the claimed device.
+    pub fn id(&self) -> DeviceId {
+        self.id
+    }
+}
+
+/// An error claiming a device which is owned by something else.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DeviceBusy {
+    pub id: DeviceId,
+    /// The current owner of the device.
+    pub owner: &'static str,
+}
+
+impl Display for DeviceBusy {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} is in use by {}", self.id, self.owner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_id::DeviceKind;
+
+    const VSOCK0: DeviceId = DeviceId {
+        kind: DeviceKind::Vsock,
+        number: 0,
+    };
+
+    #[test]
+    fn claim_and_release() {
+        let mut owners = DeviceOwners::new();
+        assert_eq!(owners.owner(VSOCK0), None);
+        let claim = owners.claim(VSOCK0, "sessions").unwrap();
+        assert_eq!(claim.id(), VSOCK0);
+        assert_eq!(owners.owner(VSOCK0), Some("sessions"));
+        owners.release(claim);
+        assert_eq!(owners.owner(VSOCK0), None);
+    }
+
+    #[test]
+    fn busy() {
+        let mut owners = DeviceOwners::new();
+        let claim = owners.claim(VSOCK0, "sessions").unwrap();
+        let error = owners.claim(VSOCK0, "cp").unwrap_err();
+        assert_eq!(
+            error,
+            DeviceBusy {
+                id: VSOCK0,
+                owner: "sessions"
+            }
+        );
+        assert_eq!(error.to_string(), "vsock0 is in use by sessions");
+        owners.release(claim);
+        let claim = owners.claim(VSOCK0, "cp").unwrap();
+        owners.release(claim);
+    }
+
+    #[test]
+    fn nested_claims() {
+        let mut owners = DeviceOwners::new();
+        let first = owners.claim(VSOCK0, "cp").unwrap();
+        let second = owners.claim(VSOCK0, "cp").unwrap();
+        owners.release(first);
+        assert_eq!(owners.owner(VSOCK0), Some("cp"));
+        assert!(owners.claim(VSOCK0, "sessions").is_err());
+        owners.release(second);
+        assert_eq!(owners.owner(VSOCK0), None);
+    }
+}
@@ -0,0 +1,362 @@
+// Copyright 2024 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! The registry of devices which have been found, and the drivers for them.
+
+#[cfg(all(feature = "drivers", feature = "net"))]
+use crate::drivers::e1000::find_e1000_devices;
+#[cfg(feature = "drivers")]
+use crate::drivers::xhci::find_xhci_devices;
+#[cfg(all(feature = "drivers", feature = "block"))]
+use crate::drivers::{
+    ahci::find_ahci_devices, nvme::find_nvme_devices, sdhci::find_sdhci_pci_devices,
+};
+use crate::{
+    block::{BlockDevice, BlockError, CachedBlockDevice},
+    device_id::{DeviceId, DeviceKind},
+    device_owners::DeviceOwners,
+    events::{DeviceEvent, publish},
+    fallible::{OutOfMemory, try_reserve},
+    interrupts::{mask_device_irqs, restore_device_irqs},
+    net::{ManagedInterface, NetworkInterface},
+    pci::PciRootComplex,
+    power::EnergyMeter,
+    rtc::RtcDevice,
+    virtio::{VirtioDevice, VirtioHal, find_virtio_pci_devices},
+    virtio_balloon::VirtioBalloon,
+};
+use alloc::{
+    boxed::Box,
+    collections::{btree_map::BTreeMap, btree_set::BTreeSet},
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use log::error;
+use virtio_drivers::{
+    device::{console::VirtIOConsole, socket::VsockConnectionManager},
+    transport::{SomeTransport, pci::bus::DeviceFunction},
+};
+
+/// An entry in the device registry.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DeviceInfo {
+    pub id: DeviceId,
+    /// The name of the driver for the device.
+    pub driver: &'static str,
+    /// Where the device was found, such as its MMIO address or PCI function.
+    pub location: String,
+    /// A human-readable description of the device, such as its model or capacity.
+    pub description: String,
+}
+
+/// All the devices which have been found, and drivers for them.
+///
+/// Drivers are kept in a separate list for each kind of device, so that commands can use them
+/// directly. The number in each device's ID is its index in the corresponding list.
+pub struct Devices {
+    pub rtc: Box<dyn RtcDevice + Send>,
+    pub energy_meter: Box<dyn EnergyMeter + Send>,
+    pub block: Vec<CachedBlockDevice>,
+    pub net: Vec<Box<dyn NetworkInterface>>,
+    pub console: Vec<VirtioDevice<VirtIOConsole<VirtioHal, SomeTransport<'static>>>>,
+    pub vsock: Vec<VsockConnectionManager<VirtioHal, SomeTransport<'static>>>,
+    pub balloon: Vec<VirtioBalloon>,
+    /// Which subsystem currently owns each device which can't be shared.
+    pub owners: DeviceOwners,
+    /// Every device which has been found, in the order they were found.
+    registry: Vec<DeviceInfo>,
+    /// The PCI functions which have been added to the registry, by root index.
+    registered_pci_functions: BTreeSet<(usize, DeviceFunction)>,
+    /// The state of each device last seen by `poll_changes`, to compare against.
+    last_state: BTreeMap<DeviceId, DeviceState>,
+}
+
+/// The state of a device which `Devices::poll_changes` watches for changes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum DeviceState {
+    Capacity(u64),
+    ConsoleSize { columns: u16, rows: u16 },
+    Link(bool),
+}
+
+impl Devices {
+    /// Creates a new set of devices with the platform's RTC.
+    ///
+    /// The RTC and any other devices which the platform provides aren't added to the registry, so
+    /// the caller should `register` them.
+    pub fn new(rtc: Box<dyn RtcDevice + Send>, energy_meter: Box<dyn EnergyMeter + Send>) -> Self {
+        Self {
+            rtc,
+            energy_meter,
+            block: Vec::new(),
+            net: Vec::new(),
+            console: Vec::new(),
+            vsock: Vec::new(),
+            balloon: Vec::new(),
+            owners: DeviceOwners::new(),
+            registry: Vec::new(),
+            registered_pci_functions: BTreeSet::new(),
+            last_state: BTreeMap::new(),
+        }
+    }
+
+    /// Returns all registered devices, in the order they were found.
+    pub fn registry(&self) -> &[DeviceInfo] {
+        &self.registry
+    }
+
+    /// Returns the registry entry for the device with the given ID, if there is one.
+    pub fn find(&self, id: DeviceId) -> Option<&DeviceInfo> {
+        self.registry.iter().find(|info| info.id == id)
+    }
+
+    /// Adds a device to the registry, assigning it the next ID for its kind.
+    ///
+    /// Devices whose drivers are kept in one of the lists above must be added with the
+    /// corresponding `add_` method instead, so that their number matches their index.
+    ///
+    /// Returns an error without adding anything if there isn't enough memory.
+    pub fn register(
+        &mut self,
+        kind: DeviceKind,
+        driver: &'static str,
+        location: String,
+        description: String,
+    ) -> Result<DeviceId, OutOfMemory> {
+        try_reserve(&mut self.registry, 1)?;
+        let number = self
+            .registry
+            .iter()
+            .filter(|info| info.id.kind == kind)
+            .count();
+        let id = DeviceId { kind, number };
+        self.registry.push(DeviceInfo {
+            id,
+            driver,
+            location,
+            description,
+        });
+        publish(DeviceEvent::Added(id));
+        Ok(id)
+    }
+
+    /// Checks the devices for changes to their capacity, size or link state since they were last
+    /// checked, and publishes an event for each.
+    ///
+    /// Drivers don't yet report these changes from their interrupts, so this should be called
+    /// regularly, such as whenever the shell is woken.
+    pub fn poll_changes(&mut self) {
+        for (number, device) in self.block.iter().enumerate() {
+            let id = DeviceId {
+                kind: DeviceKind::Block,
+                number,
+            };
+            let blocks = device.capacity();
+            update_state(
+                &mut self.last_state,
+                id,
+                DeviceState::Capacity(blocks),
+                || DeviceEvent::CapacityChanged { id, blocks },
+            );
+        }
+        for (number, console) in self.console.iter().enumerate() {
+            let id = DeviceId {
+                kind: DeviceKind::Console,
+                number,
+            };
+            if let Ok(Some(size)) = console.size() {
+                let (columns, rows) = (size.columns, size.rows);
+                let state = DeviceState::ConsoleSize { columns, rows };
+                update_state(&mut self.last_state, id, state, || {
+                    DeviceEvent::ConsoleResized { id, columns, rows }
+                });
+            }
+        }
+        for (number, interface) in self.net.iter().enumerate() {
+            let id = DeviceId {
+                kind: DeviceKind::Network,
+                number,
+            };
+            if let Some(up) = interface.link_up() {
+                update_state(&mut self.last_state, id, DeviceState::Link(up), || {
+                    DeviceEvent::LinkChanged { id, up }
+                });
+            }
+        }
+    }
+
+    /// Quiesces all devices so that the system can be suspended: writes back any dirty cached
+    /// blocks, and masks device interrupts.
+    ///
+    /// Drivers only make VirtIO and other device requests synchronously within a command, so there
+    /// are none in flight between commands and the queues need no further pausing. Call `resume`
+    /// once the system has resumed, or if suspending fails.
+    pub fn quiesce(&mut self) -> Result<(), BlockError> {
+        for device in &mut self.block {
+            device.sync()?;
+        }
+        mask_device_irqs();
+        Ok(())
+    }
+
+    /// Undoes `quiesce` after the system has resumed, unmasking device interrupts again.
+    pub fn resume(&mut self) {
+        restore_device_irqs();
+    }
+
+    /// Adds a block device driver behind a cache, along with its registry entry.
+    ///
+    /// Returns an error without adding anything if there isn't enough memory, in which case the
+    /// driver is dropped.
+    pub fn add_block(
+        &mut self,
+        mut device: Box<dyn BlockDevice>,
+        location: String,
+    ) -> Result<DeviceId, OutOfMemory> {
+        try_reserve(&mut self.block, 1)?;
+        let model = device.id().unwrap_or_else(|e| format!("unknown ({e})"));
+        let description = format!(
+            "\"{}\", capacity {} blocks of {} bytes, {}",
+            model,
+            device.capacity(),
+            device.block_size(),
+            if device.readonly() {
+                "read-only"
+            } else {
+                "read-write"
+            }
+        );
+        let id = self.register(DeviceKind::Block, device.kind(), location, description)?;
+        self.block.push(CachedBlockDevice::new(device));
+        Ok(id)
+    }
+
+    /// Adds a network interface driver, along with its registry entry.
+    ///
+    /// Returns an error without adding anything if there isn't enough memory.
+    pub fn add_net(
+        &mut self,
+        interface: Box<dyn NetworkInterface>,
+        location: String,
+    ) -> Result<DeviceId, OutOfMemory> {
+        try_reserve(&mut self.net, 1)?;
+        let description = format!("MAC {}", interface.mac_address());
+        let id = self.register(DeviceKind::Network, interface.kind(), location, description)?;
+        let index = self.net.len();
+        self.net
+            .push(Box::new(ManagedInterface::new(index, interface)));
+        Ok(id)
+    }
+
+    /// Adds a VirtIO console driver, along with its registry entry.
+    ///
+    /// Returns an error without adding anything if there isn't enough memory.
+    pub fn add_console(
+        &mut self,
+        console: VirtioDevice<VirtIOConsole<VirtioHal, SomeTransport<'static>>>,
+        location: String,
+    ) -> Result<DeviceId, OutOfMemory> {
+        try_reserve(&mut self.console, 1)?;
+        let description = match console.size() {
+            Ok(Some(size)) => format!("{}x{}", size.columns, size.rows),
+            _ => "size unknown".to_string(),
+        };
+        let id = self.register(DeviceKind::Console, "virtio", location, description)?;
+        self.console.push(console);
+        Ok(id)
+    }
+
+    /// Adds a vsock driver, along with its registry entry.
+    ///
+    /// Returns an error without adding anything if there isn't enough memory.
+    pub fn add_vsock(
+        &mut self,
+        vsock: VsockConnectionManager<VirtioHal, SomeTransport<'static>>,
+        location: String,
+    ) -> Result<DeviceId, OutOfMemory> {
+        try_reserve(&mut self.vsock, 1)?;
+        let description = format!("guest CID {}", vsock.guest_cid());
+        let id = self.register(DeviceKind::Vsock, "virtio", location, description)?;
+        self.vsock.push(vsock);
+        Ok(id)
+    }
+
+    /// Adds a memory balloon driver, along with its registry entry.
+    ///
+    /// Returns an error without adding anything if there isn't enough memory.
+    pub fn add_balloon(
+        &mut self,
+        balloon: VirtioBalloon,
+        location: String,
+    ) -> Result<DeviceId, OutOfMemory> {
+        try_reserve(&mut self.balloon, 1)?;
+        let description = if balloon.has_stats() {
+            "with statistics"
+        } else {
+            "without statistics"
+        };
+        let id = self.register(
+            DeviceKind::Balloon,
+            "virtio",
+            location,
+            description.to_string(),
+        )?;
+        self.balloon.push(balloon);
+        Ok(id)
+    }
+
+    /// Adds registry entries for any functions on the given PCI root which don't already have
+    /// one.
+    fn register_pci_functions(&mut self, root_index: usize, pci_root: &mut PciRootComplex) {
+        for (device_function, info) in pci_root.enumerate_devices() {
+            if self
+                .registered_pci_functions
+                .insert((root_index, device_function))
+                && let Err(e) = self.register(
+                    DeviceKind::Pci,
+                    "pci",
+                    format!("root {root_index} {device_function}"),
+                    info.to_string(),
+                )
+            {
+                error!("Error registering PCI function {device_function}: {e}");
+                // Try again on the next rescan.
+                self.registered_pci_functions
+                    .remove(&(root_index, device_function));
+            }
+        }
+    }
+}
+
+/// Records the given state for the given device, and publishes the given event if it has changed
+/// since it was last recorded.
+fn update_state(
+    last_state: &mut BTreeMap<DeviceId, DeviceState>,
+    id: DeviceId,
+    state: DeviceState,
+    event: impl FnOnce() -> DeviceEvent,
+) {
+    if last_state.insert(id, state).is_some_and(|old| old != state) {
+        publish(event());
+    }
+}
+
+/// Adds drivers for all devices on the given PCI root which don't already have one, and registers
+/// any new PCI functions.
+pub fn find_pci_devices(root_index: usize, pci_root: &mut PciRootComplex, devices: &mut Devices) {
+    devices.register_pci_functions(root_index, pci_root);
+    find_virtio_pci_devices(pci_root, devices);
+    #[cfg(all(feature = "drivers", feature = "block"))]
+    {
+        find_nvme_devices(pci_root, devices);
+        find_ahci_devices(pci_root, devices);
+    }
+    #[cfg(all(feature = "drivers", feature = "net"))]
+    find_e1000_devices(pci_root, devices);
+    #[cfg(feature = "drivers")]
+    find_xhci_devices(pci_root, devices);
+    #[cfg(all(feature = "drivers", feature = "block"))]
+    find_sdhci_pci_devices(pci_root, devices);
+}
@@ -0,0 +1,417 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A pool of physically-contiguous memory for buffers which devices access by DMA.
+
+use crate::{
+    pagetable::{phys_to_virt, virt_to_phys},
+    tracked_heap::{HeapStats, TrackedHeap},
+};
+use buddy_system_allocator::FrameAllocator;
+use core::{
+    alloc::Layout,
+    fmt::{self, Display, Formatter},
+    ptr::NonNull,
+    slice,
+};
+use log::info;
+use spin::mutex::SpinMutex;
+use virtio_drivers::PAGE_SIZE;
+
+/// The number of pages reserved for DMA buffers.
+pub const DMA_POOL_PAGES: usize = 64;
+
+/// The number of pages reserved for bounce buffers, on platforms which use them.
+pub const BOUNCE_POOL_PAGES: usize = 16;
+
+/// The alignment of each bounce buffer, so that separate buffers never share a cache line.
+const BOUNCE_BUFFER_ALIGN: usize = 64;
+
+/// Address limit to use for devices which can access all physical memory.
+pub const NO_ADDRESS_LIMIT: usize = usize::MAX;
+
+/// Address limit to use for devices which can only access the first 4 GiB of physical memory.
+pub const ADDRESS_LIMIT_32_BIT: usize = 1 << 32;
+
+/// Page-aligned backing memory for the DMA pool.
+#[repr(C, align(4096))]
+pub struct DmaPoolMemory(pub [u8; DMA_POOL_PAGES * PAGE_SIZE]);
+
+pub static DMA_POOL_MEMORY: SpinMutex<DmaPoolMemory> =
+    SpinMutex::new(DmaPoolMemory([0; DMA_POOL_PAGES * PAGE_SIZE]));
+
+static DMA_POOL: SpinMutex<Option<DmaPool>> = SpinMutex::new(None);
+
+/// Page-aligned backing memory for the bounce buffer pool.
+#[repr(C, align(4096))]
+pub struct BouncePoolMemory(pub [u8; BOUNCE_POOL_PAGES * PAGE_SIZE]);
+
+pub static BOUNCE_POOL_MEMORY: SpinMutex<BouncePoolMemory> =
+    SpinMutex::new(BouncePoolMemory([0; BOUNCE_POOL_PAGES * PAGE_SIZE]));
+
+/// The pool which bounce buffers are allocated from, or `None` if bounce buffers aren't used.
+static BOUNCE_POOL: SpinMutex<Option<TrackedHeap<32>>> = SpinMutex::new(None);
+
+/// The function called for each allocation and free in the DMA pool, if any.
+static TRACE_HOOK: SpinMutex<Option<DmaTraceHook>> = SpinMutex::new(None);
+
+/// An allocation or free in the DMA pool, as reported to the trace hook.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DmaTraceEvent {
+    /// The given number of pages were allocated at the given physical address.
+    Alloc { paddr: usize, pages: usize },
+    /// The given number of pages at the given physical address were freed.
+    Dealloc { paddr: usize, pages: usize },
+}
+
+/// A function which is called for each allocation and free in the DMA pool, from the context of
+/// the caller which made it.
+pub type DmaTraceHook = fn(DmaTraceEvent);
+
+/// The state of a page in the DMA pool.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum PageState {
+    /// The page is part of an allocation.
+    Allocated,
+    /// The page is free, and may still contain whatever it was last used for.
+    Free,
+    /// The page is free, and has been zeroed by `scrub_free_page` since it was freed.
+    Scrubbed,
+}
+
+/// A pool of physically-contiguous pages for DMA buffers, separate from the general heap.
+struct DmaPool {
+    allocator: FrameAllocator<32>,
+    /// The state of each page in the pool, by index from the start.
+    pages: [PageState; DMA_POOL_PAGES],
+    /// The physical address of the first page in the pool.
+    start: usize,
+    /// The total number of pages in the pool.
+    total_pages: usize,
+    /// The number of pages currently allocated, including any rounding up by the allocator.
+    allocated_pages: usize,
+    /// The maximum value `allocated_pages` has reached.
+    peak_pages: usize,
+    /// The number of allocations which have failed.
+    failed_allocations: usize,
+}
+
+impl DmaPool {
+    /// Sets the state of the pages covered by an allocation of the given number of pages at the
+    /// given physical address, including any rounding up by the allocator.
+    fn set_page_states(&mut self, paddr: usize, pages: usize, state: PageState) {
+        let first = (paddr - self.start) / PAGE_SIZE;
+        self.pages[first..first + pages.next_power_of_two()].fill(state);
+    }
+}
+
+/// Statistics about the DMA pool.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DmaPoolStats {
+    /// The physical address of the first page in the pool.
+    pub start: usize,
+    /// The total number of pages in the pool.
+    pub total_pages: usize,
+    /// The number of pages currently allocated.
+    pub allocated_pages: usize,
+    /// The maximum number of pages which have been allocated at once.
+    pub peak_pages: usize,
+    /// The number of allocations which have failed.
+    pub failed_allocations: usize,
+}
+
+impl Display for DmaPoolStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "DMA pool at {:#x}-{:#x}",
+            self.start,
+            self.start + self.total_pages * PAGE_SIZE
+        )?;
+        writeln!(
+            f,
+            "  {} pages total, {} allocated, {} free",
+            self.total_pages,
+            self.allocated_pages,
+            self.total_pages - self.allocated_pages
+        )?;
+        write!(
+            f,
+            "  peak {} pages, {} failed allocations",
+            self.peak_pages, self.failed_allocations
+        )
+    }
+}
+
+/// An error allocating from the DMA pool.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DmaError {
+    /// The DMA pool has not yet been initialised.
+    NotInitialised,
+    /// There weren't enough contiguous free pages in the pool.
+    OutOfMemory { pages: usize, free_pages: usize },
+    /// There wasn't enough contiguous free space in the bounce buffer pool.
+    BounceOutOfMemory { size: usize, free_bytes: usize },
+    /// The allocation couldn't be placed below the address limit requested by the device.
+    AddressLimit {
+        pages: usize,
+        address_limit: usize,
+        pool_start: usize,
+    },
+}
+
+impl Display for DmaError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::NotInitialised => write!(f, "DMA pool not initialised"),
+            Self::OutOfMemory { pages, free_pages } => write!(
+                f,
+                "Not enough contiguous DMA memory for {pages} pages ({free_pages} pages free)"
+            ),
+            Self::BounceOutOfMemory { size, free_bytes } => write!(
+                f,
+                "Not enough contiguous bounce buffer memory for {size} bytes ({free_bytes} bytes \
+                 free)"
+            ),
+            Self::AddressLimit {
+                pages,
+                address_limit,
+                pool_start,
+            } => write!(
+                f,
+                "Can't allocate {pages} DMA pages below {address_limit:#x}, pool starts at \
+                 {pool_start:#x}"
+            ),
+        }
+    }
+}
+
+/// Initialises the DMA pool with the given page-aligned memory.
+pub fn init(memory: &'static mut DmaPoolMemory) {
+    let start = virt_to_phys(memory.0.as_mut_ptr() as usize);
+    let total_pages = memory.0.len() / PAGE_SIZE;
+    info!("DMA pool: {total_pages} pages at {start:#x}");
+    let mut allocator = FrameAllocator::new();
+    allocator.add_frame(start / PAGE_SIZE, start / PAGE_SIZE + total_pages);
+    *DMA_POOL.lock() = Some(DmaPool {
+        allocator,
+        pages: [PageState::Free; DMA_POOL_PAGES],
+        start,
+        total_pages,
+        allocated_pages: 0,
+        peak_pages: 0,
+        failed_allocations: 0,
+    });
+}
+
+/// Allocates the given number of physically-contiguous pages from the DMA pool, and returns the
+/// physical address of the first one.
+///
+/// The whole allocation will be below `address_limit`, which may be `NO_ADDRESS_LIMIT` if the
+/// device can address all physical memory.
+pub fn alloc_pages(pages: usize, address_limit: usize) -> Result<usize, DmaError> {
+    let mut guard = DMA_POOL.lock();
+    let pool = guard.as_mut().ok_or(DmaError::NotInitialised)?;
+    if pool.start + pages * PAGE_SIZE > address_limit {
+        // Nothing in the pool can satisfy this, no need to try.
+        pool.failed_allocations += 1;
+        return Err(DmaError::AddressLimit {
+            pages,
+            address_limit,
+            pool_start: pool.start,
+        });
+    }
+    let Some(frame) = pool.allocator.alloc(pages) else {
+        pool.failed_allocations += 1;
+        return Err(DmaError::OutOfMemory {
+            pages,
+            free_pages: pool.total_pages - pool.allocated_pages,
+        });
+    };
+    let paddr = frame * PAGE_SIZE;
+    if paddr + pages * PAGE_SIZE > address_limit {
+        pool.allocator.dealloc(frame, pages);
+        pool.failed_allocations += 1;
+        return Err(DmaError::AddressLimit {
+            pages,
+            address_limit,
+            pool_start: pool.start,
+        });
+    }
+    pool.allocated_pages += pages.next_power_of_two();
+    pool.set_page_states(paddr, pages, PageState::Allocated);
+    pool.peak_pages = pool.peak_pages.max(pool.allocated_pages);
+    drop(guard);
+    trace(DmaTraceEvent::Alloc { paddr, pages });
+    Ok(paddr)
+}
+
+/// Returns the given pages to the DMA pool.
+///
+/// `paddr` and `pages` must match a previous call to `alloc_pages`.
+pub fn dealloc_pages(paddr: usize, pages: usize) {
+    {
+        let mut pool = DMA_POOL.lock();
+        let pool = pool.as_mut().expect("DMA pool not initialised");
+        pool.allocator.dealloc(paddr / PAGE_SIZE, pages);
+        pool.allocated_pages -= pages.next_power_of_two();
+        pool.set_page_states(paddr, pages, PageState::Free);
+    }
+    trace(DmaTraceEvent::Dealloc { paddr, pages });
+}
+
+/// Calls `scrub` for the first free page in the DMA pool at or after the given index, and returns
+/// the index of the page, or `None` if there are no free pages from there to the end of the pool.
+///
+/// `scrub` is passed the physical address and contents of the page, and whether it has already
+/// been scrubbed since it was last freed. It must leave the page zeroed. The pool is locked while
+/// it runs, so that the page can't be allocated meanwhile.
+pub fn scrub_free_page(from: usize, scrub: impl FnOnce(usize, &mut [u64], bool)) -> Option<usize> {
+    let mut guard = DMA_POOL.lock();
+    let pool = guard.as_mut()?;
+    let index =
+        (from..pool.total_pages).find(|&index| pool.pages[index] != PageState::Allocated)?;
+    let paddr = pool.start + index * PAGE_SIZE;
+    // SAFETY: The page is free and the pool is locked so it can't be allocated, so nothing else
+    // can access it until we return. Pages are aligned, so it is suitably aligned for `u64`.
+    let page = unsafe {
+        slice::from_raw_parts_mut(
+            phys_to_virt(paddr) as *mut u64,
+            PAGE_SIZE / size_of::<u64>(),
+        )
+    };
+    scrub(paddr, page, pool.pages[index] == PageState::Scrubbed);
+    pool.pages[index] = PageState::Scrubbed;
+    Some(index)
+}
+
+/// Sets the function to be called for each allocation and free in the DMA pool, or stops calling
+/// one if `None`.
+pub fn set_trace_hook(hook: Option<DmaTraceHook>) {
+    *TRACE_HOOK.lock() = hook;
+}
+
+/// Calls the trace hook, if any, with the given event.
+fn trace(event: DmaTraceEvent) {
+    // Copy the hook out so that it isn't called with the lock held.
+    let hook = *TRACE_HOOK.lock();
+    if let Some(hook) = hook {
+        hook(event);
+    }
+}
+
+/// Returns statistics about the DMA pool, or `None` if it has not been initialised.
+pub fn stats() -> Option<DmaPoolStats> {
+    DMA_POOL.lock().as_ref().map(|pool| DmaPoolStats {
+        start: pool.start,
+        total_pages: pool.total_pages,
+        allocated_pages: pool.allocated_pages,
+        peak_pages: pool.peak_pages,
+        failed_allocations: pool.failed_allocations,
+    })
+}
+
+/// Initialises the bounce buffer pool with the given memory, so that VirtIO devices are given
+/// copies of buffers in it rather than the buffers themselves.
+///
+/// This is for platforms where the host can only access memory which the guest has explicitly
+/// shared with it, such as protected VMs. The memory given must already be shared.
+pub fn init_bounce_pool(memory: &'static mut BouncePoolMemory) {
+    let start = memory.0.as_mut_ptr() as usize;
+    info!(
+        "Bounce buffer pool: {} bytes at {:#x}",
+        memory.0.len(),
+        virt_to_phys(start)
+    );
+    let mut heap = TrackedHeap::new();
+    // SAFETY: The memory comes from a mutable static reference, which we take ownership of.
+    unsafe {
+        heap.init(start, memory.0.len());
+    }
+    *BOUNCE_POOL.lock() = Some(heap);
+}
+
+/// Returns whether buffers shared with devices must be copied to bounce buffers.
+pub fn bounce_buffers_enabled() -> bool {
+    BOUNCE_POOL.lock().is_some()
+}
+
+/// Allocates a bounce buffer of the given size, and returns its physical address.
+pub fn alloc_bounce(size: usize) -> Result<usize, DmaError> {
+    let mut pool = BOUNCE_POOL.lock();
+    let pool = pool.as_mut().ok_or(DmaError::NotInitialised)?;
+    let buffer = pool
+        .alloc(bounce_layout(size))
+        .ok_or_else(|| DmaError::BounceOutOfMemory {
+            size,
+            free_bytes: pool.stats().free_bytes(),
+        })?;
+    Ok(virt_to_phys(buffer.as_ptr() as usize))
+}
+
+/// Returns the bounce buffer at the given physical address to the pool.
+///
+/// `paddr` and `size` must match a previous call to `alloc_bounce`.
+pub fn dealloc_bounce(paddr: usize, size: usize) {
+    let mut pool = BOUNCE_POOL.lock();
+    let pool = pool.as_mut().expect("Bounce buffer pool not initialised");
+    let buffer = NonNull::new(phys_to_virt(paddr) as *mut u8).unwrap();
+    // SAFETY: Our caller promises that the buffer was allocated by `alloc_bounce` with the same
+    // size, and so with the same layout.
+    unsafe { pool.dealloc(buffer, bounce_layout(size)) }
+}
+
+/// Returns the usage of the bounce buffer pool, or `None` if bounce buffers aren't used.
+pub fn bounce_stats() -> Option<HeapStats> {
+    BOUNCE_POOL.lock().as_ref().map(TrackedHeap::stats)
+}
+
+/// Returns the layout of a bounce buffer of the given size.
+fn bounce_layout(size: usize) -> Layout {
+    Layout::from_size_align(size.max(1), BOUNCE_BUFFER_ALIGN).unwrap()
+}
+
+/// A zeroed, physically-contiguous buffer allocated from the DMA pool, which is returned to the
+/// pool when dropped.
+#[derive(Debug)]
+pub struct DmaBuffer {
+    paddr: usize,
+    pages: usize,
+}
+
+impl DmaBuffer {
+    /// Allocates a buffer of the given number of pages below the given address limit.
+    pub fn new(pages: usize, address_limit: usize) -> Result<Self, DmaError> {
+        let paddr = alloc_pages(pages, address_limit)?;
+        let buffer = Self { paddr, pages };
+        // SAFETY: The pool has just given us these pages so they must be valid and unaliased.
+        unsafe {
+            buffer.as_ptr().write_bytes(0, buffer.size());
+        }
+        Ok(buffer)
+    }
+
+    /// Returns the physical address of the start of the buffer, for the device to use.
+    pub fn paddr(&self) -> usize {
+        self.paddr
+    }
+
+    /// Returns the size of the buffer in bytes.
+    pub fn size(&self) -> usize {
+        self.pages * PAGE_SIZE
+    }
+
+    /// Returns a pointer through which the CPU can access the buffer.
+    ///
+    /// The device may access the buffer concurrently, so accesses should be volatile.
+    pub fn as_ptr(&self) -> *mut u8 {
+        phys_to_virt(self.paddr) as *mut u8
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        dealloc_pages(self.paddr, self.pages);
+    }
+}
@@ -0,0 +1,34 @@
+// Copyright 2024 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Drivers for devices other than VirtIO devices.
+
+#[cfg(feature = "drivers")]
+pub mod ahci;
+#[cfg(feature = "drivers")]
+pub mod e1000;
+pub mod generic_timer;
+#[cfg(feature = "drivers")]
+pub mod nvme;
+mod pl011;
+pub mod pl061;
+#[cfg(feature = "drivers")]
+pub mod sdhci;
+pub mod uart16550;
+#[cfg(feature = "drivers")]
+pub mod usb_storage;
+#[cfg(feature = "drivers")]
+pub mod xhci;
+
+use arm_gic::IntId;
+
+/// Trait for device drivers which can handle interrupts.
+pub trait InterruptDriven {
+    /// Handles the given interrupt for the device.
+    ///
+    /// Note that this may be called with the console locked, so must not try to log anything
+    /// unless the logger defers records logged with IRQs masked rather than writing them to the
+    /// console straight away.
+    fn handle_irq(&mut self, intid: IntId);
+}
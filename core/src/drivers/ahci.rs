@@ -0,0 +1,586 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A minimal polled driver for SATA disks attached to an AHCI controller, such as QEMU's ICH9.
+//!
+//! Each disk uses a single command slot, with one command outstanding at a time. ATAPI devices and
+//! port multipliers aren't supported.
+
+use crate::{
+    block::{BlockDevice, BlockError},
+    devices::Devices,
+    dma::{ADDRESS_LIMIT_32_BIT, DmaBuffer, DmaError, NO_ADDRESS_LIMIT},
+    pci::PciRootComplex,
+};
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use core::{
+    fmt::{self, Display, Formatter},
+    ptr::NonNull,
+    sync::atomic::{Ordering, fence},
+};
+use log::{error, info, warn};
+use virtio_drivers::{
+    PAGE_SIZE,
+    transport::pci::bus::{BarInfo, DeviceFunction, DeviceFunctionInfo},
+};
+
+/// PCI class code for mass storage controllers.
+const PCI_CLASS_STORAGE: u8 = 0x01;
+/// PCI subclass code for SATA controllers.
+const PCI_SUBCLASS_SATA: u8 = 0x06;
+/// PCI programming interface for AHCI.
+const PCI_PROG_IF_AHCI: u8 = 0x01;
+/// The BAR containing the AHCI registers.
+const ABAR_INDEX: usize = 5;
+
+// HBA register offsets.
+const REG_CAP: usize = 0x00;
+const REG_GHC: usize = 0x04;
+const REG_PI: usize = 0x0c;
+const REG_VS: usize = 0x10;
+/// The offset of the first port's registers.
+const REG_PORTS: usize = 0x100;
+/// The size of each port's registers.
+const PORT_REGISTERS_SIZE: usize = 0x80;
+const MAX_PORTS: usize = 32;
+
+const CAP_64BIT_ADDRESSING: u32 = 1 << 31;
+const GHC_AHCI_ENABLE: u32 = 1 << 31;
+const GHC_INTERRUPT_ENABLE: u32 = 1 << 1;
+
+// Port register offsets, relative to the port.
+const PORT_CLB: usize = 0x00;
+const PORT_FB: usize = 0x08;
+const PORT_IS: usize = 0x10;
+const PORT_IE: usize = 0x14;
+const PORT_CMD: usize = 0x18;
+const PORT_TFD: usize = 0x20;
+const PORT_SIG: usize = 0x24;
+const PORT_SSTS: usize = 0x28;
+const PORT_SERR: usize = 0x30;
+const PORT_CI: usize = 0x38;
+
+const CMD_START: u32 = 1 << 0;
+const CMD_FIS_RECEIVE_ENABLE: u32 = 1 << 4;
+const CMD_FIS_RECEIVE_RUNNING: u32 = 1 << 14;
+const CMD_LIST_RUNNING: u32 = 1 << 15;
+const IS_TASK_FILE_ERROR: u32 = 1 << 30;
+const TFD_ERROR: u32 = 1 << 0;
+const TFD_DRQ: u32 = 1 << 3;
+const TFD_BUSY: u32 = 1 << 7;
+/// SSTS.DET value for a device which is present with communication established.
+const SSTS_DET_PRESENT: u32 = 3;
+/// Port signature of a SATA disk.
+const SIGNATURE_ATA: u32 = 0x0000_0101;
+
+// Offsets within the page of per-port command structures.
+const COMMAND_LIST_OFFSET: usize = 0x000;
+const RECEIVED_FIS_OFFSET: usize = 0x400;
+const COMMAND_TABLE_OFFSET: usize = 0x800;
+/// The offset of the PRDT within the command table.
+const PRDT_OFFSET: usize = 0x80;
+
+const FIS_TYPE_REGISTER_H2D: u8 = 0x27;
+/// Set in the second byte of a register FIS to indicate that it contains a command.
+const FIS_COMMAND: u8 = 0x80;
+/// The length of a register FIS, in dwords.
+const REGISTER_FIS_DWORDS: u32 = 5;
+/// Set in a command header for writes to the device.
+const COMMAND_HEADER_WRITE: u32 = 1 << 6;
+/// Set in the device register to use LBA addressing.
+const DEVICE_LBA: u8 = 1 << 6;
+
+const ATA_READ_DMA_EXT: u8 = 0x25;
+const ATA_WRITE_DMA_EXT: u8 = 0x35;
+const ATA_IDENTIFY_DEVICE: u8 = 0xec;
+
+/// The size of an ATA sector, unless the device reports otherwise.
+const DEFAULT_SECTOR_SIZE: usize = 512;
+/// The size of the data returned by IDENTIFY DEVICE.
+const IDENTIFY_SIZE: usize = 512;
+
+/// The number of times to poll the controller before giving up.
+const POLL_LIMIT: u32 = 10_000_000;
+
+/// An error from the AHCI driver.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AhciError {
+    /// BAR 5 isn't a memory BAR.
+    NoRegisters,
+    /// The disk doesn't support 48-bit LBA addressing.
+    NoLba48,
+    /// The disk's sector size is larger than a page.
+    UnsupportedSectorSize(usize),
+    Dma(DmaError),
+    /// The controller didn't respond in time.
+    Timeout,
+    /// A command failed, with the given task file data.
+    CommandFailed {
+        task_file: u32,
+    },
+    /// The buffer length wasn't a multiple of the sector size.
+    UnalignedBuffer,
+    /// The blocks requested are beyond the end of the disk.
+    OutOfRange,
+}
+
+impl Display for AhciError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::NoRegisters => write!(f, "BAR 5 is not a memory BAR"),
+            Self::NoLba48 => write!(f, "Disk doesn't support 48-bit LBA"),
+            Self::UnsupportedSectorSize(size) => write!(f, "Unsupported sector size {size}"),
+            Self::Dma(e) => write!(f, "{e}"),
+            Self::Timeout => write!(f, "Timed out waiting for controller"),
+            Self::CommandFailed { task_file } => {
+                write!(f, "Command failed with task file {task_file:#x}")
+            }
+            Self::UnalignedBuffer => write!(f, "Buffer is not a multiple of the sector size"),
+            Self::OutOfRange => write!(f, "Blocks are beyond the end of the disk"),
+        }
+    }
+}
+
+impl From<DmaError> for AhciError {
+    fn from(e: DmaError) -> Self {
+        Self::Dma(e)
+    }
+}
+
+/// A command header in a port's command list.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+struct CommandHeader {
+    /// FIS length in bits 0-4, flags in bits 5-15, PRDT length in bits 16-31.
+    flags: u32,
+    /// The number of bytes transferred, updated by the controller.
+    prd_byte_count: u32,
+    command_table: u64,
+    reserved: [u32; 4],
+}
+
+/// An entry in a command table's physical region descriptor table.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+struct PrdtEntry {
+    data_base: u64,
+    reserved: u32,
+    /// The byte count minus one in bits 0-21.
+    byte_count: u32,
+}
+
+/// Some memory-mapped registers of the controller.
+#[derive(Debug)]
+struct Registers {
+    base: NonNull<u8>,
+}
+
+impl Registers {
+    fn read32(&self, offset: usize) -> u32 {
+        // SAFETY: `init_ahci` checked that the registers are mapped, and the offset is one of our
+        // register constants.
+        unsafe { self.base.add(offset).cast::<u32>().read_volatile() }
+    }
+
+    fn write32(&self, offset: usize, value: u32) {
+        // SAFETY: `init_ahci` checked that the registers are mapped, and the offset is one of our
+        // register constants.
+        unsafe { self.base.add(offset).cast::<u32>().write_volatile(value) }
+    }
+
+    fn write64(&self, offset: usize, value: u64) {
+        self.write32(offset, value as u32);
+        self.write32(offset + 4, (value >> 32) as u32);
+    }
+
+    /// Polls until the given register has none of the given bits set.
+    fn wait_clear(&self, offset: usize, bits: u32) -> Result<(), AhciError> {
+        for _ in 0..POLL_LIMIT {
+            if self.read32(offset) & bits == 0 {
+                return Ok(());
+            }
+        }
+        Err(AhciError::Timeout)
+    }
+}
+
+/// Driver for a SATA disk on a single AHCI port.
+#[derive(Debug)]
+pub struct AhciDisk {
+    port: usize,
+    /// The registers of the port.
+    registers: Registers,
+    /// The command list, received FIS area and command table for the port's single command slot.
+    commands: DmaBuffer,
+    /// A page for identify data and for bouncing I/O through, so that the caller's buffer needn't
+    /// be page-aligned.
+    buffer: DmaBuffer,
+    sector_size: usize,
+    capacity: u64,
+    model: String,
+    serial: String,
+}
+
+impl AhciDisk {
+    /// Initialises the given port, and identifies the disk attached to it.
+    ///
+    /// # Safety
+    ///
+    /// `registers` must be the address of the port's registers, which must be mapped as device
+    /// memory, and not be used anywhere else.
+    unsafe fn new(
+        port: usize,
+        registers: NonNull<u8>,
+        address_limit: usize,
+    ) -> Result<Self, AhciError> {
+        let registers = Registers { base: registers };
+        let mut disk = Self {
+            port,
+            registers,
+            commands: DmaBuffer::new(1, address_limit)?,
+            buffer: DmaBuffer::new(1, address_limit)?,
+            sector_size: DEFAULT_SECTOR_SIZE,
+            capacity: 0,
+            model: String::new(),
+            serial: String::new(),
+        };
+        disk.start()?;
+        disk.identify()?;
+        Ok(disk)
+    }
+
+    /// Stops the port, points it at our command structures, and starts it again.
+    fn start(&mut self) -> Result<(), AhciError> {
+        let registers = &self.registers;
+        registers.write32(
+            PORT_CMD,
+            registers.read32(PORT_CMD) & !(CMD_START | CMD_FIS_RECEIVE_ENABLE),
+        );
+        registers.wait_clear(PORT_CMD, CMD_LIST_RUNNING | CMD_FIS_RECEIVE_RUNNING)?;
+
+        registers.write64(
+            PORT_CLB,
+            (self.commands.paddr() + COMMAND_LIST_OFFSET) as u64,
+        );
+        registers.write64(
+            PORT_FB,
+            (self.commands.paddr() + RECEIVED_FIS_OFFSET) as u64,
+        );
+        // We poll for completions, so disable all interrupts, and clear any pending status.
+        registers.write32(PORT_IE, 0);
+        registers.write32(PORT_SERR, u32::MAX);
+        registers.write32(PORT_IS, u32::MAX);
+
+        registers.write32(
+            PORT_CMD,
+            registers.read32(PORT_CMD) | CMD_FIS_RECEIVE_ENABLE,
+        );
+        registers.wait_clear(PORT_TFD, TFD_BUSY | TFD_DRQ)?;
+        registers.write32(PORT_CMD, registers.read32(PORT_CMD) | CMD_START);
+        Ok(())
+    }
+
+    /// Issues the given ATA command in slot 0, transferring `length` bytes to or from the start of
+    /// the DMA buffer, and polls for it to complete.
+    fn run(
+        &mut self,
+        command: u8,
+        lba: u64,
+        sectors: u16,
+        length: usize,
+        write: bool,
+    ) -> Result<(), AhciError> {
+        let table = self.commands.paddr() + COMMAND_TABLE_OFFSET;
+        let header = CommandHeader {
+            flags: REGISTER_FIS_DWORDS | if write { COMMAND_HEADER_WRITE } else { 0 } | (1 << 16),
+            prd_byte_count: 0,
+            command_table: table as u64,
+            reserved: [0; 4],
+        };
+        let mut fis = [0u8; 20];
+        fis[0] = FIS_TYPE_REGISTER_H2D;
+        fis[1] = FIS_COMMAND;
+        fis[2] = command;
+        fis[4..7].copy_from_slice(&lba.to_le_bytes()[0..3]);
+        fis[7] = DEVICE_LBA;
+        fis[8..11].copy_from_slice(&lba.to_le_bytes()[3..6]);
+        fis[12..14].copy_from_slice(&sectors.to_le_bytes());
+        let prdt_entry = PrdtEntry {
+            data_base: self.buffer.paddr() as u64,
+            reserved: 0,
+            byte_count: (length - 1) as u32,
+        };
+
+        let commands = self.commands.as_ptr();
+        // SAFETY: All the offsets are within the page-sized buffer and suitably aligned, and the
+        // controller isn't accessing it as there is no command outstanding.
+        unsafe {
+            commands
+                .add(COMMAND_LIST_OFFSET)
+                .cast::<CommandHeader>()
+                .write_volatile(header);
+            let table = commands.add(COMMAND_TABLE_OFFSET);
+            table.copy_from_nonoverlapping(fis.as_ptr(), fis.len());
+            table
+                .add(PRDT_OFFSET)
+                .cast::<PrdtEntry>()
+                .write_volatile(prdt_entry);
+        }
+        // Make sure the command is visible to the controller before issuing it.
+        fence(Ordering::SeqCst);
+        self.registers.write32(PORT_CI, 1);
+
+        let mut polls = 0;
+        while self.registers.read32(PORT_CI) & 1 != 0 {
+            if self.registers.read32(PORT_IS) & IS_TASK_FILE_ERROR != 0 {
+                break;
+            }
+            polls += 1;
+            if polls == POLL_LIMIT {
+                return Err(AhciError::Timeout);
+            }
+        }
+        fence(Ordering::SeqCst);
+        let interrupt_status = self.registers.read32(PORT_IS);
+        self.registers.write32(PORT_IS, interrupt_status);
+        let task_file = self.registers.read32(PORT_TFD);
+        if interrupt_status & IS_TASK_FILE_ERROR != 0 || task_file & TFD_ERROR != 0 {
+            // The port must be restarted to recover from an error.
+            self.start()?;
+            return Err(AhciError::CommandFailed { task_file });
+        }
+        Ok(())
+    }
+
+    /// Reads the disk's identify data.
+    fn identify(&mut self) -> Result<(), AhciError> {
+        self.run(ATA_IDENTIFY_DEVICE, 0, 0, IDENTIFY_SIZE, false)?;
+        let mut identify = [0; IDENTIFY_SIZE];
+        self.copy_from_buffer(&mut identify);
+        let word =
+            |index: usize| u16::from_le_bytes([identify[index * 2], identify[index * 2 + 1]]);
+
+        self.serial = identify_string(&identify[20..40]);
+        self.model = identify_string(&identify[54..94]);
+        if word(83) & (1 << 10) == 0 {
+            return Err(AhciError::NoLba48);
+        }
+        self.capacity = (100..104).rev().fold(0, |capacity, index| {
+            (capacity << 16) | u64::from(word(index))
+        });
+        // Word 106 says whether the logical sector size is in words 117-118.
+        let sector_info = word(106);
+        if sector_info & 0xc000 == 0x4000 && sector_info & (1 << 12) != 0 {
+            self.sector_size = ((usize::from(word(118)) << 16) | usize::from(word(117))) * 2;
+        }
+        if self.sector_size > PAGE_SIZE {
+            return Err(AhciError::UnsupportedSectorSize(self.sector_size));
+        }
+        Ok(())
+    }
+
+    /// Copies the start of the DMA buffer into the given slice.
+    fn copy_from_buffer(&self, data: &mut [u8]) {
+        assert!(data.len() <= self.buffer.size());
+        fence(Ordering::SeqCst);
+        // SAFETY: The buffer is valid for its whole size, and the controller isn't currently
+        // writing to it as there is no command outstanding.
+        unsafe {
+            self.buffer
+                .as_ptr()
+                .copy_to_nonoverlapping(data.as_mut_ptr(), data.len());
+        }
+    }
+
+    /// Copies the given slice to the start of the DMA buffer.
+    fn copy_to_buffer(&mut self, data: &[u8]) {
+        assert!(data.len() <= self.buffer.size());
+        // SAFETY: The buffer is valid for its whole size, and the controller isn't currently
+        // accessing it as there is no command outstanding.
+        unsafe {
+            self.buffer
+                .as_ptr()
+                .copy_from_nonoverlapping(data.as_ptr(), data.len());
+        }
+        fence(Ordering::SeqCst);
+    }
+
+    /// Checks that the given buffer length is a whole number of sectors within the disk.
+    fn check_range(&self, block_id: u64, len: usize) -> Result<(), AhciError> {
+        if !len.is_multiple_of(self.sector_size) {
+            return Err(AhciError::UnalignedBuffer);
+        }
+        let end = block_id
+            .checked_add((len / self.sector_size) as u64)
+            .ok_or(AhciError::OutOfRange)?;
+        if end > self.capacity {
+            return Err(AhciError::OutOfRange);
+        }
+        Ok(())
+    }
+}
+
+impl BlockDevice for AhciDisk {
+    fn kind(&self) -> &'static str {
+        "ahci"
+    }
+
+    fn id(&mut self) -> Result<String, BlockError> {
+        Ok(format!(
+            "{} {} (port {})",
+            self.model, self.serial, self.port
+        ))
+    }
+
+    fn block_size(&self) -> usize {
+        self.sector_size
+    }
+
+    fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    fn readonly(&self) -> bool {
+        false
+    }
+
+    fn read_blocks(&mut self, block_id: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.check_range(block_id, buf.len())?;
+        let mut block_id = block_id;
+        for chunk in buf.chunks_mut(self.buffer.size()) {
+            let sectors = chunk.len() / self.sector_size;
+            self.run(
+                ATA_READ_DMA_EXT,
+                block_id,
+                sectors as u16,
+                chunk.len(),
+                false,
+            )?;
+            self.copy_from_buffer(chunk);
+            block_id += sectors as u64;
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, block_id: u64, buf: &[u8]) -> Result<(), BlockError> {
+        self.check_range(block_id, buf.len())?;
+        let mut block_id = block_id;
+        for chunk in buf.chunks(self.buffer.size()) {
+            let sectors = chunk.len() / self.sector_size;
+            self.copy_to_buffer(chunk);
+            self.run(
+                ATA_WRITE_DMA_EXT,
+                block_id,
+                sectors as u16,
+                chunk.len(),
+                true,
+            )?;
+            block_id += sectors as u64;
+        }
+        Ok(())
+    }
+}
+
+/// Converts an ATA identify string, which has the bytes of each word swapped and is padded with
+/// spaces, to a string.
+fn identify_string(bytes: &[u8]) -> String {
+    let swapped = bytes
+        .chunks_exact(2)
+        .flat_map(|pair| [pair[1], pair[0]])
+        .collect::<Vec<_>>();
+    String::from_utf8_lossy(&swapped)
+        .trim_end_matches([' ', '\0'])
+        .into()
+}
+
+/// Returns whether the given PCI function is an AHCI controller.
+fn is_ahci(info: &DeviceFunctionInfo) -> bool {
+    info.class == PCI_CLASS_STORAGE
+        && info.subclass == PCI_SUBCLASS_SATA
+        && info.prog_if == PCI_PROG_IF_AHCI
+}
+
+/// Finds AHCI controllers on the given PCI root, and adds drivers for the disks attached to them to
+/// the block devices.
+pub fn find_ahci_devices(pci_root: &mut PciRootComplex, devices: &mut Devices) {
+    let controllers = pci_root
+        .unclaimed_devices()
+        .into_iter()
+        .filter(|(_, info)| is_ahci(info))
+        .map(|(device_function, _)| device_function)
+        .collect::<Vec<_>>();
+    for device_function in controllers {
+        info!("Found AHCI controller at {device_function}");
+        match init_ahci(pci_root, device_function, devices) {
+            Ok(()) => pci_root.claim(device_function),
+            Err(e) => error!("Error initialising AHCI controller at {device_function}: {e}"),
+        }
+    }
+}
+
+fn init_ahci(
+    pci_root: &mut PciRootComplex,
+    device_function: DeviceFunction,
+    devices: &mut Devices,
+) -> Result<(), AhciError> {
+    let Ok(bars) = pci_root.device_bars(device_function) else {
+        return Err(AhciError::NoRegisters);
+    };
+    let Some(BarInfo::Memory { address, size, .. }) = bars[ABAR_INDEX] else {
+        return Err(AhciError::NoRegisters);
+    };
+    if address == 0 || size < REG_PORTS as u64 {
+        return Err(AhciError::NoRegisters);
+    }
+    let base = NonNull::new(address as *mut u8).unwrap();
+    // BAR 5 was allocated and mapped as device memory when the PCI root was initialised, we
+    // checked that it is big enough for the HBA registers, and this is the only driver for the
+    // controller.
+    let hba = Registers { base };
+
+    hba.write32(REG_GHC, hba.read32(REG_GHC) | GHC_AHCI_ENABLE);
+    hba.write32(REG_GHC, hba.read32(REG_GHC) & !GHC_INTERRUPT_ENABLE);
+    let version = hba.read32(REG_VS);
+    info!("AHCI version {}.{}", version >> 16, version & 0xffff);
+    let address_limit = if hba.read32(REG_CAP) & CAP_64BIT_ADDRESSING != 0 {
+        NO_ADDRESS_LIMIT
+    } else {
+        ADDRESS_LIMIT_32_BIT
+    };
+
+    let ports_implemented = hba.read32(REG_PI);
+    for port in (0..MAX_PORTS).filter(|port| ports_implemented & (1 << port) != 0) {
+        let offset = REG_PORTS + port * PORT_REGISTERS_SIZE;
+        if (offset + PORT_REGISTERS_SIZE) as u64 > size {
+            warn!("AHCI port {port} is beyond the end of BAR {ABAR_INDEX}");
+            break;
+        }
+        let status = hba.read32(offset + PORT_SSTS);
+        if status & 0xf != SSTS_DET_PRESENT {
+            continue;
+        }
+        let signature = hba.read32(offset + PORT_SIG);
+        if signature != SIGNATURE_ATA {
+            warn!("Ignoring AHCI port {port} with unsupported signature {signature:#010x}");
+            continue;
+        }
+        // SAFETY: Each port's registers are within BAR 5, and are only used by the driver for that
+        // port.
+        match unsafe { AhciDisk::new(port, base.add(offset), address_limit) } {
+            Ok(disk) => {
+                info!(
+                    "AHCI port {port} {}: {} sectors of {} bytes",
+                    disk.model, disk.capacity, disk.sector_size
+                );
+                if let Err(e) =
+                    devices.add_block(Box::new(disk), format!("PCI {device_function} port {port}"))
+                {
+                    error!("Error adding AHCI port {port}: {e}");
+                }
+            }
+            Err(e) => error!("Error initialising AHCI port {port}: {e}"),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,594 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A minimal driver for Intel 8254x (e1000) and 82574 (e1000e) Ethernet controllers attached over
+//! PCI, as emulated by QEMU.
+//!
+//! This uses legacy descriptors with a single receive and transmit queue. Transmission is
+//! synchronous through a single bounce buffer, and reception can wait for a legacy INTx interrupt
+//! if the PCI root has one for the device.
+
+use crate::{
+    devices::Devices,
+    dma::{DmaBuffer, DmaError, NO_ADDRESS_LIMIT},
+    ethernet::MacAddress,
+    interrupts::{setup_device_irq, unmask_device_irq, wait_for_device_irq},
+    net::{LinkSpeed, NetError, NetworkInterface},
+    pci::PciRootComplex,
+};
+use alloc::{boxed::Box, format, vec::Vec};
+use arm_gic::IntId;
+use core::{
+    fmt::{self, Display, Formatter},
+    mem::size_of,
+    ptr::NonNull,
+    sync::atomic::{Ordering, fence},
+};
+use log::{error, info};
+use virtio_drivers::{
+    PAGE_SIZE,
+    transport::pci::bus::{BarInfo, DeviceFunction, DeviceFunctionInfo},
+};
+
+const PCI_VENDOR_INTEL: u16 = 0x8086;
+
+/// PCI device IDs of the controllers which QEMU emulates, and their names.
+const SUPPORTED_DEVICES: [(u16, &str); 4] = [
+    (0x1004, "82544GC"),
+    (0x100e, "82540EM"),
+    (0x100f, "82545EM"),
+    (0x10d3, "82574L"),
+];
+
+// Register offsets.
+const REG_CTRL: usize = 0x0000;
+const REG_STATUS: usize = 0x0008;
+const REG_ICR: usize = 0x00c0;
+const REG_IMS: usize = 0x00d0;
+const REG_IMC: usize = 0x00d8;
+const REG_RCTL: usize = 0x0100;
+const REG_TCTL: usize = 0x0400;
+const REG_TIPG: usize = 0x0410;
+const REG_RDBAL: usize = 0x2800;
+const REG_RDBAH: usize = 0x2804;
+const REG_RDLEN: usize = 0x2808;
+const REG_RDH: usize = 0x2810;
+const REG_RDT: usize = 0x2818;
+const REG_TDBAL: usize = 0x3800;
+const REG_TDBAH: usize = 0x3804;
+const REG_TDLEN: usize = 0x3808;
+const REG_TDH: usize = 0x3810;
+const REG_TDT: usize = 0x3818;
+/// The first of the multicast table array registers.
+const REG_MTA: usize = 0x5200;
+const MTA_ENTRIES: usize = 128;
+const REG_RAL0: usize = 0x5400;
+const REG_RAH0: usize = 0x5404;
+/// The size of the register space which we use.
+const REGISTERS_SIZE: u64 = 0x8000;
+
+const CTRL_LRST: u32 = 1 << 3;
+const CTRL_ASDE: u32 = 1 << 5;
+const CTRL_SLU: u32 = 1 << 6;
+const CTRL_RST: u32 = 1 << 26;
+const CTRL_PHY_RST: u32 = 1 << 31;
+const STATUS_FD: u32 = 1 << 0;
+const STATUS_LU: u32 = 1 << 1;
+const STATUS_SPEED_SHIFT: u32 = 6;
+const STATUS_SPEED_MASK: u32 = 0b11 << STATUS_SPEED_SHIFT;
+/// Receive timer interrupt, raised when a frame has been received.
+const ICR_RXT0: u32 = 1 << 7;
+const RCTL_EN: u32 = 1 << 1;
+/// Accept broadcast frames.
+const RCTL_BAM: u32 = 1 << 15;
+/// Strip the frame check sequence from received frames.
+const RCTL_SECRC: u32 = 1 << 26;
+const TCTL_EN: u32 = 1 << 1;
+/// Pad short frames to the minimum Ethernet frame size.
+const TCTL_PSP: u32 = 1 << 3;
+/// Collision threshold, as recommended by the datasheet.
+const TCTL_CT: u32 = 0x0f << 4;
+/// Collision distance for full duplex, as recommended by the datasheet.
+const TCTL_COLD: u32 = 0x40 << 12;
+/// Inter-packet gap for IEEE 802.3, as recommended by the datasheet.
+const TIPG_DEFAULT: u32 = 0x0060_200a;
+const RAH_AV: u32 = 1 << 31;
+
+/// Descriptor has been written back by the device.
+const DESCRIPTOR_DONE: u8 = 1 << 0;
+/// Descriptor is the end of a frame.
+const DESCRIPTOR_EOP: u8 = 1 << 1;
+const TX_COMMAND_EOP: u8 = 1 << 0;
+/// Insert the frame check sequence.
+const TX_COMMAND_IFCS: u8 = 1 << 1;
+/// Report the status of the descriptor once it has been sent.
+const TX_COMMAND_RS: u8 = 1 << 3;
+
+/// The number of descriptors in each ring. Ring sizes must be a multiple of 128 bytes.
+const RX_DESCRIPTORS: usize = 8;
+const TX_DESCRIPTORS: usize = 8;
+/// The size of each receive buffer, which is the default RCTL.BSIZE.
+const RX_BUFFER_SIZE: usize = 2048;
+/// The offset of the transmit ring in the page of descriptors.
+const TX_RING_OFFSET: usize = PAGE_SIZE / 2;
+
+/// The number of times to poll the controller before giving up.
+const POLL_LIMIT: u32 = 10_000_000;
+
+/// An error from the e1000 driver.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum E1000Error {
+    /// BAR 0 isn't a memory BAR, or is too small.
+    NoRegisters,
+    Dma(DmaError),
+    /// The controller didn't come out of reset in time.
+    ResetTimeout,
+    /// The controller has no valid MAC address.
+    NoMacAddress,
+    /// The controller didn't send a frame in time.
+    TransmitTimeout,
+    /// A frame was received with the given error bits set, and was dropped.
+    ReceiveError(u8),
+}
+
+impl Display for E1000Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::NoRegisters => write!(f, "BAR 0 is not a large enough memory BAR"),
+            Self::Dma(e) => write!(f, "{e}"),
+            Self::ResetTimeout => write!(f, "Timed out waiting for controller reset"),
+            Self::NoMacAddress => write!(f, "Controller has no MAC address"),
+            Self::TransmitTimeout => write!(f, "Timed out waiting for frame to be sent"),
+            Self::ReceiveError(errors) => {
+                write!(f, "Received frame with errors {errors:#04x}")
+            }
+        }
+    }
+}
+
+impl From<DmaError> for E1000Error {
+    fn from(e: DmaError) -> Self {
+        Self::Dma(e)
+    }
+}
+
+/// A legacy receive descriptor.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+struct RxDescriptor {
+    address: u64,
+    length: u16,
+    checksum: u16,
+    status: u8,
+    errors: u8,
+    special: u16,
+}
+
+/// A legacy transmit descriptor.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+struct TxDescriptor {
+    address: u64,
+    length: u16,
+    checksum_offset: u8,
+    command: u8,
+    status: u8,
+    checksum_start: u8,
+    special: u16,
+}
+
+/// The controller's memory-mapped registers.
+#[derive(Debug)]
+struct Registers {
+    base: NonNull<u8>,
+}
+
+impl Registers {
+    fn read32(&self, offset: usize) -> u32 {
+        // SAFETY: `E1000::new`'s caller promised that the registers are mapped, and the offset is
+        // one of our register constants.
+        unsafe { self.base.add(offset).cast::<u32>().read_volatile() }
+    }
+
+    fn write32(&self, offset: usize, value: u32) {
+        // SAFETY: `E1000::new`'s caller promised that the registers are mapped, and the offset is
+        // one of our register constants.
+        unsafe { self.base.add(offset).cast::<u32>().write_volatile(value) }
+    }
+
+    fn write64(&self, low_offset: usize, high_offset: usize, value: u64) {
+        self.write32(low_offset, value as u32);
+        self.write32(high_offset, (value >> 32) as u32);
+    }
+}
+
+/// Driver for an e1000 or e1000e Ethernet controller.
+#[derive(Debug)]
+pub struct E1000 {
+    registers: Registers,
+    mac_address: MacAddress,
+    /// A page holding the receive ring, followed by the transmit ring at `TX_RING_OFFSET`.
+    rings: DmaBuffer,
+    /// `RX_BUFFER_SIZE` bytes for each receive descriptor.
+    rx_buffers: DmaBuffer,
+    /// A page to bounce frames being sent through.
+    tx_buffer: DmaBuffer,
+    /// The next receive descriptor which the device will write back.
+    rx_next: usize,
+    /// The next transmit descriptor to use.
+    tx_next: usize,
+    irq: Option<IntId>,
+}
+
+impl E1000 {
+    /// Resets and initialises the controller with the given registers.
+    ///
+    /// # Safety
+    ///
+    /// `registers` must be the address of the controller's registers, which must be mapped as
+    /// device memory, and not be used anywhere else.
+    pub unsafe fn new(registers: NonNull<u8>) -> Result<Self, E1000Error> {
+        let registers = Registers { base: registers };
+
+        registers.write32(REG_IMC, u32::MAX);
+        registers.write32(REG_CTRL, registers.read32(REG_CTRL) | CTRL_RST);
+        if !(0..POLL_LIMIT).any(|_| registers.read32(REG_CTRL) & CTRL_RST == 0) {
+            return Err(E1000Error::ResetTimeout);
+        }
+        // Reset unmasks interrupts again, so mask them and clear any which are pending.
+        registers.write32(REG_IMC, u32::MAX);
+        registers.read32(REG_ICR);
+        registers.write32(
+            REG_CTRL,
+            (registers.read32(REG_CTRL) | CTRL_SLU | CTRL_ASDE) & !(CTRL_LRST | CTRL_PHY_RST),
+        );
+
+        // The controller loads its MAC address from the EEPROM into the first receive address
+        // registers on reset.
+        let ral = registers.read32(REG_RAL0);
+        let rah = registers.read32(REG_RAH0);
+        let mut mac_address = [0; 6];
+        mac_address[0..4].copy_from_slice(&ral.to_le_bytes());
+        mac_address[4..6].copy_from_slice(&rah.to_le_bytes()[0..2]);
+        let mac_address = MacAddress(mac_address);
+        if rah & RAH_AV == 0 || mac_address.is_zero() {
+            return Err(E1000Error::NoMacAddress);
+        }
+        for i in 0..MTA_ENTRIES {
+            registers.write32(REG_MTA + i * size_of::<u32>(), 0);
+        }
+
+        let mut e1000 = Self {
+            registers,
+            mac_address,
+            rings: DmaBuffer::new(1, NO_ADDRESS_LIMIT)?,
+            rx_buffers: DmaBuffer::new(
+                (RX_DESCRIPTORS * RX_BUFFER_SIZE).div_ceil(PAGE_SIZE),
+                NO_ADDRESS_LIMIT,
+            )?,
+            tx_buffer: DmaBuffer::new(1, NO_ADDRESS_LIMIT)?,
+            rx_next: 0,
+            tx_next: 0,
+            irq: None,
+        };
+        e1000.init_rx();
+        e1000.init_tx();
+        Ok(e1000)
+    }
+
+    /// Sets up the receive ring with all descriptors owned by the device, and enables reception.
+    fn init_rx(&mut self) {
+        for i in 0..RX_DESCRIPTORS {
+            self.write_rx_descriptor(
+                i,
+                RxDescriptor {
+                    address: (self.rx_buffers.paddr() + i * RX_BUFFER_SIZE) as u64,
+                    ..Default::default()
+                },
+            );
+        }
+        fence(Ordering::SeqCst);
+        let registers = &self.registers;
+        registers.write64(REG_RDBAL, REG_RDBAH, self.rings.paddr() as u64);
+        registers.write32(
+            REG_RDLEN,
+            (RX_DESCRIPTORS * size_of::<RxDescriptor>()) as u32,
+        );
+        registers.write32(REG_RDH, 0);
+        // The device owns the descriptors from the head up to but not including the tail, so one
+        // is always left for us.
+        registers.write32(REG_RDT, (RX_DESCRIPTORS - 1) as u32);
+        registers.write32(REG_RCTL, RCTL_EN | RCTL_BAM | RCTL_SECRC);
+    }
+
+    /// Sets up the empty transmit ring and enables transmission.
+    fn init_tx(&mut self) {
+        for i in 0..TX_DESCRIPTORS {
+            self.write_tx_descriptor(i, TxDescriptor::default());
+        }
+        fence(Ordering::SeqCst);
+        let registers = &self.registers;
+        registers.write64(
+            REG_TDBAL,
+            REG_TDBAH,
+            (self.rings.paddr() + TX_RING_OFFSET) as u64,
+        );
+        registers.write32(
+            REG_TDLEN,
+            (TX_DESCRIPTORS * size_of::<TxDescriptor>()) as u32,
+        );
+        registers.write32(REG_TDH, 0);
+        registers.write32(REG_TDT, 0);
+        registers.write32(REG_TIPG, TIPG_DEFAULT);
+        registers.write32(REG_TCTL, TCTL_EN | TCTL_PSP | TCTL_CT | TCTL_COLD);
+    }
+
+    /// Enables the receive interrupt, which must already have been configured in the GIC.
+    fn enable_irq(&mut self, intid: IntId) {
+        self.irq = Some(intid);
+        self.registers.write32(REG_IMS, ICR_RXT0);
+    }
+
+    /// Returns whether the link is up.
+    pub fn link_up(&self) -> bool {
+        self.registers.read32(REG_STATUS) & STATUS_LU != 0
+    }
+
+    /// Returns the speed and duplex which the link has negotiated.
+    pub fn link_speed(&self) -> LinkSpeed {
+        let status = self.registers.read32(REG_STATUS);
+        LinkSpeed {
+            mbps: match (status & STATUS_SPEED_MASK) >> STATUS_SPEED_SHIFT {
+                0b00 => 10,
+                0b01 => 100,
+                _ => 1000,
+            },
+            full_duplex: status & STATUS_FD != 0,
+        }
+    }
+
+    /// Sets the MAC address in the first receive address registers, so that the controller accepts
+    /// unicast frames for it.
+    pub fn set_mac_address(&mut self, mac_address: MacAddress) {
+        let [a, b, c, d, e, f] = mac_address.0;
+        // Clear the valid bit while changing the address, so that the controller never matches
+        // against half of the old one and half of the new one.
+        self.registers.write32(REG_RAH0, 0);
+        self.registers
+            .write32(REG_RAL0, u32::from_le_bytes([a, b, c, d]));
+        self.registers
+            .write32(REG_RAH0, u32::from(u16::from_le_bytes([e, f])) | RAH_AV);
+        self.mac_address = mac_address;
+    }
+
+    /// Brings the link up and enables reception and transmission, or disables them and forces the
+    /// link down.
+    pub fn set_link_up(&mut self, up: bool) {
+        let registers = &self.registers;
+        let ctrl = registers.read32(REG_CTRL);
+        let rctl = registers.read32(REG_RCTL);
+        let tctl = registers.read32(REG_TCTL);
+        if up {
+            registers.write32(REG_CTRL, (ctrl | CTRL_SLU) & !CTRL_LRST);
+            registers.write32(REG_RCTL, rctl | RCTL_EN);
+            registers.write32(REG_TCTL, tctl | TCTL_EN);
+        } else {
+            registers.write32(REG_RCTL, rctl & !RCTL_EN);
+            registers.write32(REG_TCTL, tctl & !TCTL_EN);
+            registers.write32(REG_CTRL, (ctrl & !CTRL_SLU) | CTRL_LRST);
+        }
+    }
+
+    fn rx_descriptor(&self, index: usize) -> *mut RxDescriptor {
+        assert!(index < RX_DESCRIPTORS);
+        // SAFETY: The receive ring fits at the start of the page.
+        unsafe { self.rings.as_ptr().cast::<RxDescriptor>().add(index) }
+    }
+
+    fn tx_descriptor(&self, index: usize) -> *mut TxDescriptor {
+        assert!(index < TX_DESCRIPTORS);
+        // SAFETY: The transmit ring fits in the page after `TX_RING_OFFSET`.
+        unsafe {
+            self.rings
+                .as_ptr()
+                .add(TX_RING_OFFSET)
+                .cast::<TxDescriptor>()
+                .add(index)
+        }
+    }
+
+    fn read_rx_descriptor(&self, index: usize) -> RxDescriptor {
+        // SAFETY: The descriptor is within the ring, which is valid for as long as we are.
+        unsafe { self.rx_descriptor(index).read_volatile() }
+    }
+
+    fn write_rx_descriptor(&mut self, index: usize, descriptor: RxDescriptor) {
+        // SAFETY: The descriptor is within the ring, which is valid for as long as we are.
+        unsafe { self.rx_descriptor(index).write_volatile(descriptor) }
+    }
+
+    fn read_tx_descriptor(&self, index: usize) -> TxDescriptor {
+        // SAFETY: The descriptor is within the ring, which is valid for as long as we are.
+        unsafe { self.tx_descriptor(index).read_volatile() }
+    }
+
+    fn write_tx_descriptor(&mut self, index: usize, descriptor: TxDescriptor) {
+        // SAFETY: The descriptor is within the ring, which is valid for as long as we are.
+        unsafe { self.tx_descriptor(index).write_volatile(descriptor) }
+    }
+
+    /// Returns the next receive descriptor to the device, and moves on to the one after.
+    fn recycle_rx_descriptor(&mut self) {
+        let index = self.rx_next;
+        let mut descriptor = self.read_rx_descriptor(index);
+        descriptor.status = 0;
+        self.write_rx_descriptor(index, descriptor);
+        fence(Ordering::SeqCst);
+        self.registers.write32(REG_RDT, index as u32);
+        self.rx_next = (index + 1) % RX_DESCRIPTORS;
+    }
+}
+
+impl NetworkInterface for E1000 {
+    fn kind(&self) -> &'static str {
+        "e1000"
+    }
+
+    fn mac_address(&self) -> MacAddress {
+        self.mac_address
+    }
+
+    fn send(&mut self, frame: &[u8]) -> Result<(), NetError> {
+        if frame.len() > self.tx_buffer.size() {
+            return Err(NetError::FrameTooLarge(frame.len()));
+        }
+        // SAFETY: The buffer is valid for its whole size, and the controller isn't currently
+        // reading from it as we wait for each frame to be sent before returning.
+        unsafe {
+            self.tx_buffer
+                .as_ptr()
+                .copy_from_nonoverlapping(frame.as_ptr(), frame.len());
+        }
+        let index = self.tx_next;
+        self.write_tx_descriptor(
+            index,
+            TxDescriptor {
+                address: self.tx_buffer.paddr() as u64,
+                length: frame.len() as u16,
+                command: TX_COMMAND_EOP | TX_COMMAND_IFCS | TX_COMMAND_RS,
+                ..Default::default()
+            },
+        );
+        fence(Ordering::SeqCst);
+        self.tx_next = (index + 1) % TX_DESCRIPTORS;
+        self.registers.write32(REG_TDT, self.tx_next as u32);
+        if (0..POLL_LIMIT).any(|_| self.read_tx_descriptor(index).status & DESCRIPTOR_DONE != 0) {
+            Ok(())
+        } else {
+            Err(E1000Error::TransmitTimeout.into())
+        }
+    }
+
+    fn receive(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, NetError> {
+        let descriptor = self.read_rx_descriptor(self.rx_next);
+        if descriptor.status & DESCRIPTOR_DONE == 0 {
+            return Ok(None);
+        }
+        fence(Ordering::SeqCst);
+        let length = usize::from(descriptor.length);
+        let result = if descriptor.errors != 0 {
+            Err(E1000Error::ReceiveError(descriptor.errors).into())
+        } else if descriptor.status & DESCRIPTOR_EOP == 0 {
+            // Our buffers are big enough for any frame without jumbo frames, so this shouldn't
+            // happen.
+            Err(NetError::FrameTooLarge(length))
+        } else if length > buffer.len() {
+            Err(NetError::BufferTooSmall {
+                frame: length,
+                buffer: buffer.len(),
+            })
+        } else {
+            // SAFETY: The descriptor's buffer is within `rx_buffers`, and the device has finished
+            // writing to it as it has set the done bit.
+            unsafe {
+                self.rx_buffers
+                    .as_ptr()
+                    .add(self.rx_next * RX_BUFFER_SIZE)
+                    .copy_to_nonoverlapping(buffer.as_mut_ptr(), length);
+            }
+            Ok(Some(length))
+        };
+        self.recycle_rx_descriptor();
+        result
+    }
+
+    fn wait_for_irq(&mut self) {
+        let Some(intid) = self.irq else {
+            return;
+        };
+        wait_for_device_irq(intid);
+        // Reading the interrupt cause register acknowledges the interrupt.
+        self.registers.read32(REG_ICR);
+        unmask_device_irq(intid);
+    }
+
+    fn link_up(&self) -> Option<bool> {
+        Some(E1000::link_up(self))
+    }
+
+    fn link_speed(&self) -> Option<LinkSpeed> {
+        E1000::link_up(self).then(|| E1000::link_speed(self))
+    }
+
+    fn set_mac_address(&mut self, mac_address: MacAddress) -> Result<(), NetError> {
+        E1000::set_mac_address(self, mac_address);
+        Ok(())
+    }
+
+    fn set_link_up(&mut self, up: bool) -> Result<(), NetError> {
+        E1000::set_link_up(self, up);
+        Ok(())
+    }
+}
+
+/// Returns the name of the given PCI function if it is a supported e1000 or e1000e controller.
+fn e1000_model(info: &DeviceFunctionInfo) -> Option<&'static str> {
+    if info.vendor_id != PCI_VENDOR_INTEL {
+        return None;
+    }
+    SUPPORTED_DEVICES
+        .iter()
+        .find(|(device_id, _)| *device_id == info.device_id)
+        .map(|(_, name)| *name)
+}
+
+/// Finds e1000 and e1000e controllers on the given PCI root, and adds drivers for them to the
+/// network interfaces.
+pub fn find_e1000_devices(pci_root: &mut PciRootComplex, devices: &mut Devices) {
+    let controllers = pci_root
+        .unclaimed_devices()
+        .into_iter()
+        .filter_map(|(device_function, info)| Some((device_function, e1000_model(&info)?)))
+        .collect::<Vec<_>>();
+    for (device_function, model) in controllers {
+        info!("Found Intel {model} Ethernet controller at {device_function}");
+        match init_e1000(pci_root, device_function) {
+            Ok(e1000) => {
+                info!(
+                    "e1000 {}: link {}, interrupt {:?}",
+                    e1000.mac_address,
+                    if e1000.link_up() { "up" } else { "down" },
+                    e1000.irq,
+                );
+                pci_root.claim(device_function);
+                if let Err(e) = devices.add_net(Box::new(e1000), format!("PCI {device_function}")) {
+                    error!("Error adding e1000 controller at {device_function}: {e}");
+                }
+            }
+            Err(e) => error!("Error initialising e1000 controller at {device_function}: {e}"),
+        }
+    }
+}
+
+fn init_e1000(
+    pci_root: &mut PciRootComplex,
+    device_function: DeviceFunction,
+) -> Result<E1000, E1000Error> {
+    let Ok([Some(BarInfo::Memory { address, size, .. }), ..]) = pci_root.bars(device_function)
+    else {
+        return Err(E1000Error::NoRegisters);
+    };
+    if address == 0 || size < REGISTERS_SIZE {
+        return Err(E1000Error::NoRegisters);
+    }
+    // SAFETY: BAR 0 was allocated and mapped as device memory when the PCI root was initialised,
+    // and this is the only driver for the device.
+    let mut e1000 = unsafe { E1000::new(NonNull::new(address as *mut u8).unwrap())? };
+    // Only legacy INTx interrupts are supported, not MSI.
+    if let Some(&(intid, trigger)) = pci_root.irqs.get(&device_function) {
+        setup_device_irq(intid, trigger);
+        e1000.enable_irq(intid);
+    }
+    Ok(e1000)
+}
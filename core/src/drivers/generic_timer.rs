@@ -0,0 +1,287 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A driver for the ARM generic timer's physical and virtual timers (CNTP and CNTV), providing a
+//! monotonic clock, busy-waiting, interrupt-based sleeping and timeouts.
+//!
+//! These are the EL1 timers, except at EL2 with the Virtualization Host Extensions enabled, where
+//! the same registers access the EL2 timers, which raise different PPIs.
+
+use crate::{
+    cpus::{PerCoreState, current_cpu_index, new_per_core_state_with_default},
+    exceptions::vhe_enabled,
+    executor::{Either, block_on, select},
+    interrupts::{end_interrupt, set_private_irq_handler, with_gic},
+    timer::{counter, counter_frequency},
+};
+use arm_gic::IntId;
+use core::{
+    arch::asm,
+    fmt::{self, Display, Formatter},
+    future::poll_fn,
+    hint::spin_loop,
+    ops::Add,
+    task::Poll,
+    time::Duration,
+};
+use percore::exception_free;
+
+/// The PPI raised by the EL1 physical timer, as recommended by the SBSA and used by QEMU and
+/// crosvm.
+pub const PHYSICAL_TIMER_IRQ: IntId = IntId::ppi(14);
+/// The PPI raised by the EL1 virtual timer, as recommended by the SBSA and used by QEMU and crosvm.
+pub const VIRTUAL_TIMER_IRQ: IntId = IntId::ppi(11);
+/// The PPI raised by the EL2 physical timer, as recommended by the SBSA.
+pub const EL2_PHYSICAL_TIMER_IRQ: IntId = IntId::ppi(10);
+/// The PPI raised by the EL2 virtual timer, as recommended by the SBSA.
+pub const EL2_VIRTUAL_TIMER_IRQ: IntId = IntId::ppi(12);
+
+/// The enable bit of a timer control register.
+const CTL_ENABLE: u64 = 1 << 0;
+/// The interrupt mask bit of a timer control register.
+const CTL_IMASK: u64 = 1 << 1;
+
+/// The timer used by `sleep`, which compares against the same virtual counter as `Instant`.
+const SLEEP_TIMER: TimerChannel = TimerChannel::Virtual;
+
+/// The state of `SLEEP_TIMER` on each core.
+static SLEEP_STATE: PerCoreState<SleepState> = new_per_core_state_with_default();
+
+#[derive(Debug, Default)]
+struct SleepState {
+    /// Whether the timer interrupt has been enabled on the core.
+    initialised: bool,
+    /// The counter value which the timer is currently set to fire at, if it is armed.
+    deadline: Option<u64>,
+}
+
+/// One of the timers in the ARM generic timer, for the exception level we are running at.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimerChannel {
+    /// The physical timer, CNTP, which compares against the physical counter.
+    Physical,
+    /// The virtual timer, CNTV, which compares against the virtual counter.
+    Virtual,
+}
+
+impl TimerChannel {
+    /// Returns the PPI which the timer raises when it fires.
+    pub fn irq(self) -> IntId {
+        match (self, vhe_enabled()) {
+            (Self::Physical, false) => PHYSICAL_TIMER_IRQ,
+            (Self::Virtual, false) => VIRTUAL_TIMER_IRQ,
+            (Self::Physical, true) => EL2_PHYSICAL_TIMER_IRQ,
+            (Self::Virtual, true) => EL2_VIRTUAL_TIMER_IRQ,
+        }
+    }
+
+    /// Returns the current value of the counter which the timer compares against.
+    pub fn counter(self) -> u64 {
+        match self {
+            Self::Physical => {
+                let value;
+                // SAFETY: Reading the physical counter has no side effects.
+                unsafe {
+                    asm!("isb", "mrs {}, cntpct_el0", out(reg) value, options(nomem, nostack));
+                }
+                value
+            }
+            Self::Virtual => counter(),
+        }
+    }
+
+    /// Arms the timer to raise its interrupt once its counter reaches `deadline`.
+    pub fn set_deadline(self, deadline: u64) {
+        // SAFETY: Writing the timer registers only affects when the timer interrupt fires.
+        unsafe {
+            match self {
+                Self::Physical => asm!(
+                    "msr cntp_cval_el0, {}",
+                    "msr cntp_ctl_el0, {}",
+                    "isb",
+                    in(reg) deadline,
+                    in(reg) CTL_ENABLE,
+                    options(nomem, nostack),
+                ),
+                Self::Virtual => asm!(
+                    "msr cntv_cval_el0, {}",
+                    "msr cntv_ctl_el0, {}",
+                    "isb",
+                    in(reg) deadline,
+                    in(reg) CTL_ENABLE,
+                    options(nomem, nostack),
+                ),
+            }
+        }
+    }
+
+    /// Disarms the timer, and masks its interrupt so that it stops being raised.
+    pub fn disable(self) {
+        // SAFETY: Writing the timer control register only affects whether the timer interrupt
+        // fires.
+        unsafe {
+            match self {
+                Self::Physical => asm!(
+                    "msr cntp_ctl_el0, {}",
+                    "isb",
+                    in(reg) CTL_IMASK,
+                    options(nomem, nostack),
+                ),
+                Self::Virtual => asm!(
+                    "msr cntv_ctl_el0, {}",
+                    "isb",
+                    in(reg) CTL_IMASK,
+                    options(nomem, nostack),
+                ),
+            }
+        }
+    }
+}
+
+/// A point in time on the monotonic clock, which counts ticks of the virtual counter.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Instant(u64);
+
+impl Instant {
+    /// Returns the current time.
+    pub fn now() -> Self {
+        Self(counter())
+    }
+
+    /// Returns the counter value at this time.
+    pub fn ticks(self) -> u64 {
+        self.0
+    }
+
+    /// Returns the time since `earlier`, or zero if it is actually later.
+    pub fn duration_since(self, earlier: Self) -> Duration {
+        ticks_to_duration(self.0.saturating_sub(earlier.0))
+    }
+
+    /// Returns the time since this instant.
+    pub fn elapsed(self) -> Duration {
+        Self::now().duration_since(self)
+    }
+
+    /// Returns the instant the given duration after this one, or `None` if the counter would
+    /// overflow.
+    pub fn checked_add(self, duration: Duration) -> Option<Self> {
+        self.0.checked_add(duration_to_ticks(duration)?).map(Self)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Self;
+
+    /// Returns the instant the given duration after this one, saturating rather than overflowing.
+    fn add(self, duration: Duration) -> Self {
+        self.checked_add(duration).unwrap_or(Self(u64::MAX))
+    }
+}
+
+/// Returns the current time on the monotonic clock.
+pub fn now() -> Instant {
+    Instant::now()
+}
+
+/// Returns the number of counter ticks in the given duration, rounded up, or `None` if it doesn't
+/// fit in a `u64`.
+fn duration_to_ticks(duration: Duration) -> Option<u64> {
+    let ticks = (duration.as_nanos() * u128::from(counter_frequency())).div_ceil(1_000_000_000);
+    ticks.try_into().ok()
+}
+
+/// Returns the duration of the given number of counter ticks.
+pub fn ticks_to_duration(ticks: u64) -> Duration {
+    let nanos = u128::from(ticks) * 1_000_000_000 / u128::from(counter_frequency());
+    Duration::from_nanos(nanos.try_into().unwrap_or(u64::MAX))
+}
+
+/// Spins until the given duration has passed.
+pub fn busy_wait(duration: Duration) {
+    let deadline = now() + duration;
+    while now() < deadline {
+        spin_loop();
+    }
+}
+
+/// Sleeps until the given duration has passed, waiting for the timer interrupt rather than
+/// spinning.
+pub fn sleep(duration: Duration) {
+    block_on(sleep_async(duration));
+}
+
+/// Completes once the given duration has passed.
+pub async fn sleep_async(duration: Duration) {
+    sleep_until(now() + duration).await
+}
+
+/// Completes once the given instant has been reached.
+///
+/// The virtual timer is armed for the earliest deadline which any future on the current core is
+/// waiting for. The executor polls every future again after each interrupt, so the others rearm it
+/// when it fires.
+pub async fn sleep_until(deadline: Instant) {
+    poll_fn(|_| {
+        if now() >= deadline {
+            return Poll::Ready(());
+        }
+        exception_free(|token| {
+            let state = &mut *SLEEP_STATE.get().borrow_mut(token);
+            if !state.initialised {
+                init_current_cpu();
+                state.initialised = true;
+            }
+            if state.deadline.is_none_or(|armed| deadline.0 < armed) {
+                SLEEP_TIMER.set_deadline(deadline.0);
+                state.deadline = Some(deadline.0);
+            }
+        });
+        // Check again in case the deadline passed while arming the timer.
+        if now() >= deadline {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// The error returned by `timeout` when the future doesn't complete in time.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TimedOut;
+
+impl Display for TimedOut {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Timed out")
+    }
+}
+
+/// Runs the given future until it completes or the given duration has passed, whichever is first.
+pub async fn timeout<F: Future>(duration: Duration, future: F) -> Result<F::Output, TimedOut> {
+    match select(future, sleep_async(duration)).await {
+        Either::Left(output) => Ok(output),
+        Either::Right(()) => Err(TimedOut),
+    }
+}
+
+/// Enables the sleep timer's interrupt on the current core.
+fn init_current_cpu() {
+    let cpu = current_cpu_index();
+    let irq = SLEEP_TIMER.irq();
+    SLEEP_TIMER.disable();
+    set_private_irq_handler(irq, &handle_sleep_timer_irq);
+    with_gic(|gic| {
+        gic.set_interrupt_priority(irq, Some(cpu), 0x80).unwrap();
+        gic.enable_interrupt(irq, Some(cpu), true).unwrap();
+    });
+}
+
+/// Handles the sleep timer's interrupt by disarming it. Whichever futures are still waiting will
+/// arm it again for their deadlines when they are next polled.
+fn handle_sleep_timer_irq(intid: IntId) {
+    SLEEP_TIMER.disable();
+    exception_free(|token| SLEEP_STATE.get().borrow_mut(token).deadline = None);
+    end_interrupt(intid);
+}
@@ -0,0 +1,608 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A minimal polled driver for NVMe controllers attached over PCI.
+//!
+//! This uses only the admin queue and a single I/O queue pair, with one command outstanding at a
+//! time, and only the first namespace.
+
+use crate::{
+    block::{BlockDevice, BlockError},
+    devices::Devices,
+    dma::{DmaBuffer, DmaError, NO_ADDRESS_LIMIT},
+    pci::PciRootComplex,
+};
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use core::{
+    fmt::{self, Display, Formatter},
+    mem::size_of,
+    ptr::NonNull,
+    sync::atomic::{Ordering, fence},
+};
+use log::{error, info};
+use virtio_drivers::{
+    PAGE_SIZE,
+    transport::pci::bus::{BarInfo, DeviceFunction, DeviceFunctionInfo},
+};
+
+/// PCI class code for mass storage controllers.
+const PCI_CLASS_STORAGE: u8 = 0x01;
+/// PCI subclass code for non-volatile memory controllers.
+const PCI_SUBCLASS_NVM: u8 = 0x08;
+/// PCI programming interface for NVM Express.
+const PCI_PROG_IF_NVME: u8 = 0x02;
+
+// Controller register offsets.
+const REG_CAP: usize = 0x00;
+const REG_VS: usize = 0x08;
+const REG_INTMS: usize = 0x0c;
+const REG_CC: usize = 0x14;
+const REG_CSTS: usize = 0x1c;
+const REG_AQA: usize = 0x24;
+const REG_ASQ: usize = 0x28;
+const REG_ACQ: usize = 0x30;
+/// The offset of the first doorbell register.
+const REG_DOORBELLS: usize = 0x1000;
+
+const CC_ENABLE: u32 = 1 << 0;
+/// I/O submission queue entries are 2^6 = 64 bytes.
+const CC_IOSQES: u32 = 6 << 16;
+/// I/O completion queue entries are 2^4 = 16 bytes.
+const CC_IOCQES: u32 = 4 << 20;
+const CSTS_READY: u32 = 1 << 0;
+const CSTS_FATAL: u32 = 1 << 1;
+
+const ADMIN_CREATE_IO_SQ: u8 = 0x01;
+const ADMIN_CREATE_IO_CQ: u8 = 0x05;
+const ADMIN_IDENTIFY: u8 = 0x06;
+const NVM_WRITE: u8 = 0x01;
+const NVM_READ: u8 = 0x02;
+
+const IDENTIFY_NAMESPACE: u32 = 0;
+const IDENTIFY_CONTROLLER: u32 = 1;
+
+/// Queue is physically contiguous, for queue creation commands.
+const QUEUE_PHYSICALLY_CONTIGUOUS: u32 = 1 << 0;
+
+const ADMIN_QUEUE_ID: u16 = 0;
+const IO_QUEUE_ID: u16 = 1;
+/// The namespace which we use for I/O.
+const NAMESPACE_ID: u32 = 1;
+
+/// The number of entries in each queue. This is small enough for each queue to fit in a page.
+const QUEUE_ENTRIES: u16 = 32;
+
+/// The number of times to poll the controller before giving up.
+const POLL_LIMIT: u32 = 10_000_000;
+
+/// An error from the NVMe driver.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NvmeError {
+    /// BAR 0 isn't a memory BAR.
+    NoRegisters,
+    /// The controller doesn't support 4 KiB memory pages.
+    UnsupportedPageSize,
+    /// The controller doesn't support queues as big as we use.
+    UnsupportedQueueSize,
+    /// The namespace's block size is larger than a page.
+    UnsupportedBlockSize(usize),
+    Dma(DmaError),
+    /// The controller didn't respond in time.
+    Timeout,
+    /// The controller reported a fatal status.
+    ControllerFatal,
+    /// A command completed with the given non-zero status.
+    CommandFailed {
+        status: u16,
+    },
+    /// The buffer length wasn't a multiple of the block size.
+    UnalignedBuffer,
+    /// The blocks requested are beyond the end of the namespace.
+    OutOfRange,
+}
+
+impl Display for NvmeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::NoRegisters => write!(f, "BAR 0 is not a memory BAR"),
+            Self::UnsupportedPageSize => write!(f, "Controller doesn't support 4 KiB pages"),
+            Self::UnsupportedQueueSize => write!(f, "Controller's maximum queue size is too small"),
+            Self::UnsupportedBlockSize(size) => write!(f, "Unsupported block size {size}"),
+            Self::Dma(e) => write!(f, "{e}"),
+            Self::Timeout => write!(f, "Timed out waiting for controller"),
+            Self::ControllerFatal => write!(f, "Controller fatal status"),
+            Self::CommandFailed { status } => write!(f, "Command failed with status {status:#x}"),
+            Self::UnalignedBuffer => write!(f, "Buffer is not a multiple of the block size"),
+            Self::OutOfRange => write!(f, "Blocks are beyond the end of the namespace"),
+        }
+    }
+}
+
+impl From<DmaError> for NvmeError {
+    fn from(e: DmaError) -> Self {
+        Self::Dma(e)
+    }
+}
+
+/// An entry in a submission queue.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+struct SubmissionEntry {
+    /// Opcode in bits 0-7, command ID in bits 16-31.
+    cdw0: u32,
+    nsid: u32,
+    reserved: u64,
+    metadata: u64,
+    prp1: u64,
+    prp2: u64,
+    cdw10: u32,
+    cdw11: u32,
+    cdw12: u32,
+    cdw13: u32,
+    cdw14: u32,
+    cdw15: u32,
+}
+
+/// An entry in a completion queue.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+struct CompletionEntry {
+    result: u32,
+    reserved: u32,
+    sq_head: u16,
+    sq_id: u16,
+    command_id: u16,
+    /// Phase tag in bit 0, status in bits 1-15.
+    status: u16,
+}
+
+/// The controller's memory-mapped registers.
+#[derive(Debug)]
+struct Registers {
+    base: NonNull<u8>,
+    /// The distance between doorbell registers, in bytes.
+    doorbell_stride: usize,
+}
+
+impl Registers {
+    fn read32(&self, offset: usize) -> u32 {
+        // SAFETY: `Nvme::new`'s caller promised that the registers are mapped, and the offset is
+        // one of our register constants.
+        unsafe { self.base.add(offset).cast::<u32>().read_volatile() }
+    }
+
+    fn write32(&self, offset: usize, value: u32) {
+        // SAFETY: `Nvme::new`'s caller promised that the registers are mapped, and the offset is
+        // one of our register constants.
+        unsafe { self.base.add(offset).cast::<u32>().write_volatile(value) }
+    }
+
+    fn read64(&self, offset: usize) -> u64 {
+        u64::from(self.read32(offset)) | (u64::from(self.read32(offset + 4)) << 32)
+    }
+
+    fn write64(&self, offset: usize, value: u64) {
+        self.write32(offset, value as u32);
+        self.write32(offset + 4, (value >> 32) as u32);
+    }
+
+    /// Writes the submission queue tail doorbell of the given queue.
+    fn ring_submission_doorbell(&self, queue_id: u16, tail: u16) {
+        self.write32(
+            REG_DOORBELLS + 2 * usize::from(queue_id) * self.doorbell_stride,
+            tail.into(),
+        );
+    }
+
+    /// Writes the completion queue head doorbell of the given queue.
+    fn ring_completion_doorbell(&self, queue_id: u16, head: u16) {
+        self.write32(
+            REG_DOORBELLS + (2 * usize::from(queue_id) + 1) * self.doorbell_stride,
+            head.into(),
+        );
+    }
+
+    /// Waits until the controller's ready bit has the given value.
+    fn wait_ready(&self, ready: bool) -> Result<(), NvmeError> {
+        for _ in 0..POLL_LIMIT {
+            let status = self.read32(REG_CSTS);
+            if status & CSTS_FATAL != 0 {
+                return Err(NvmeError::ControllerFatal);
+            }
+            if (status & CSTS_READY != 0) == ready {
+                return Ok(());
+            }
+        }
+        Err(NvmeError::Timeout)
+    }
+}
+
+/// A submission and completion queue pair.
+#[derive(Debug)]
+struct QueuePair {
+    id: u16,
+    submission: DmaBuffer,
+    completion: DmaBuffer,
+    submission_tail: u16,
+    completion_head: u16,
+    /// The phase tag which marks new completion entries.
+    phase: bool,
+    next_command_id: u16,
+}
+
+impl QueuePair {
+    fn new(id: u16) -> Result<Self, NvmeError> {
+        Ok(Self {
+            id,
+            submission: DmaBuffer::new(1, NO_ADDRESS_LIMIT)?,
+            completion: DmaBuffer::new(1, NO_ADDRESS_LIMIT)?,
+            submission_tail: 0,
+            completion_head: 0,
+            phase: true,
+            next_command_id: 0,
+        })
+    }
+
+    /// Submits the given command and polls for it to complete, returning the command-specific
+    /// result.
+    fn run(
+        &mut self,
+        registers: &Registers,
+        opcode: u8,
+        mut entry: SubmissionEntry,
+    ) -> Result<u32, NvmeError> {
+        let command_id = self.next_command_id;
+        self.next_command_id = self.next_command_id.wrapping_add(1);
+        entry.cdw0 = u32::from(opcode) | (u32::from(command_id) << 16);
+
+        let submission = self.submission.as_ptr().cast::<SubmissionEntry>();
+        // SAFETY: The tail is always less than `QUEUE_ENTRIES`, and the buffer is a page which is
+        // big enough for that many entries.
+        unsafe {
+            submission
+                .add(self.submission_tail.into())
+                .write_volatile(entry);
+        }
+        self.submission_tail = (self.submission_tail + 1) % QUEUE_ENTRIES;
+        // Make sure the entry is visible to the controller before ringing the doorbell.
+        fence(Ordering::SeqCst);
+        registers.ring_submission_doorbell(self.id, self.submission_tail);
+
+        let completion = self.completion.as_ptr().cast::<CompletionEntry>();
+        let mut polls = 0;
+        let result = loop {
+            // SAFETY: The head is always less than `QUEUE_ENTRIES`, and the buffer is a page which
+            // is big enough for that many entries.
+            let result = unsafe { completion.add(self.completion_head.into()).read_volatile() };
+            if (result.status & 1 != 0) == self.phase {
+                break result;
+            }
+            polls += 1;
+            if polls == POLL_LIMIT {
+                return Err(NvmeError::Timeout);
+            }
+        };
+        fence(Ordering::SeqCst);
+        self.completion_head = (self.completion_head + 1) % QUEUE_ENTRIES;
+        if self.completion_head == 0 {
+            self.phase = !self.phase;
+        }
+        registers.ring_completion_doorbell(self.id, self.completion_head);
+
+        // We only ever have one command outstanding, so this must be the completion for it.
+        assert_eq!(result.command_id, command_id);
+        let status = result.status >> 1;
+        if status != 0 {
+            return Err(NvmeError::CommandFailed { status });
+        }
+        Ok(result.result)
+    }
+}
+
+/// Driver for an NVMe controller.
+#[derive(Debug)]
+pub struct Nvme {
+    registers: Registers,
+    admin: QueuePair,
+    io: QueuePair,
+    /// A page for identify data and for bouncing I/O through, so that the caller's buffer needn't
+    /// be page-aligned.
+    buffer: DmaBuffer,
+    block_size: usize,
+    capacity: u64,
+    model: String,
+    serial: String,
+}
+
+impl Nvme {
+    /// Initialises the NVMe controller with the given registers, and its first namespace.
+    ///
+    /// # Safety
+    ///
+    /// `registers` must be the address of the controller's registers, which must be mapped as
+    /// device memory, and not be used anywhere else.
+    pub unsafe fn new(registers: NonNull<u8>) -> Result<Self, NvmeError> {
+        let mut registers = Registers {
+            base: registers,
+            doorbell_stride: 0,
+        };
+        let capabilities = registers.read64(REG_CAP);
+        registers.doorbell_stride = 4 << ((capabilities >> 32) & 0xf);
+        // CAP.MPSMIN is the log2 of the minimum page size, minus 12.
+        if (capabilities >> 48) & 0xf != 0 {
+            return Err(NvmeError::UnsupportedPageSize);
+        }
+        // CAP.MQES is zero-based.
+        if capabilities & 0xffff < u64::from(QUEUE_ENTRIES - 1) {
+            return Err(NvmeError::UnsupportedQueueSize);
+        }
+        let version = registers.read32(REG_VS);
+        info!(
+            "NVMe controller version {}.{}",
+            version >> 16,
+            (version >> 8) & 0xff
+        );
+
+        // Reset the controller.
+        registers.write32(REG_CC, registers.read32(REG_CC) & !CC_ENABLE);
+        registers.wait_ready(false)?;
+
+        let admin = QueuePair::new(ADMIN_QUEUE_ID)?;
+        registers.write32(
+            REG_AQA,
+            (u32::from(QUEUE_ENTRIES - 1) << 16) | u32::from(QUEUE_ENTRIES - 1),
+        );
+        registers.write64(REG_ASQ, admin.submission.paddr() as u64);
+        registers.write64(REG_ACQ, admin.completion.paddr() as u64);
+        // We poll for completions, so mask all interrupts.
+        registers.write32(REG_INTMS, u32::MAX);
+        registers.write32(REG_CC, CC_ENABLE | CC_IOSQES | CC_IOCQES);
+        registers.wait_ready(true)?;
+
+        let mut nvme = Self {
+            registers,
+            admin,
+            io: QueuePair::new(IO_QUEUE_ID)?,
+            buffer: DmaBuffer::new(1, NO_ADDRESS_LIMIT)?,
+            block_size: 0,
+            capacity: 0,
+            model: String::new(),
+            serial: String::new(),
+        };
+        nvme.identify()?;
+        nvme.create_io_queues()?;
+        Ok(nvme)
+    }
+
+    /// Runs an admin command.
+    fn admin_command(&mut self, opcode: u8, entry: SubmissionEntry) -> Result<u32, NvmeError> {
+        self.admin.run(&self.registers, opcode, entry)
+    }
+
+    /// Reads the controller and namespace identify data.
+    fn identify(&mut self) -> Result<(), NvmeError> {
+        self.admin_command(
+            ADMIN_IDENTIFY,
+            SubmissionEntry {
+                prp1: self.buffer.paddr() as u64,
+                cdw10: IDENTIFY_CONTROLLER,
+                ..Default::default()
+            },
+        )?;
+        let mut identify = [0; 64];
+        self.copy_from_buffer(&mut identify);
+        self.serial = identify_string(&identify[4..24]);
+        self.model = identify_string(&identify[24..64]);
+
+        self.admin_command(
+            ADMIN_IDENTIFY,
+            SubmissionEntry {
+                nsid: NAMESPACE_ID,
+                prp1: self.buffer.paddr() as u64,
+                cdw10: IDENTIFY_NAMESPACE,
+                ..Default::default()
+            },
+        )?;
+        let mut identify = [0; 192];
+        self.copy_from_buffer(&mut identify);
+        self.capacity = u64::from_le_bytes(identify[0..8].try_into().unwrap());
+        let format_index = usize::from(identify[26] & 0xf);
+        let format = u32::from_le_bytes(
+            identify[128 + format_index * 4..132 + format_index * 4]
+                .try_into()
+                .unwrap(),
+        );
+        self.block_size = 1 << ((format >> 16) & 0xff);
+        if self.block_size > PAGE_SIZE {
+            return Err(NvmeError::UnsupportedBlockSize(self.block_size));
+        }
+        Ok(())
+    }
+
+    /// Creates the I/O completion and submission queues.
+    fn create_io_queues(&mut self) -> Result<(), NvmeError> {
+        let queue_size = u32::from(QUEUE_ENTRIES - 1) << 16;
+        self.admin_command(
+            ADMIN_CREATE_IO_CQ,
+            SubmissionEntry {
+                prp1: self.io.completion.paddr() as u64,
+                cdw10: queue_size | u32::from(IO_QUEUE_ID),
+                // Interrupts are left disabled.
+                cdw11: QUEUE_PHYSICALLY_CONTIGUOUS,
+                ..Default::default()
+            },
+        )?;
+        self.admin_command(
+            ADMIN_CREATE_IO_SQ,
+            SubmissionEntry {
+                prp1: self.io.submission.paddr() as u64,
+                cdw10: queue_size | u32::from(IO_QUEUE_ID),
+                cdw11: (u32::from(IO_QUEUE_ID) << 16) | QUEUE_PHYSICALLY_CONTIGUOUS,
+                ..Default::default()
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Copies the start of the DMA buffer into the given slice.
+    fn copy_from_buffer(&self, data: &mut [u8]) {
+        assert!(data.len() <= self.buffer.size());
+        fence(Ordering::SeqCst);
+        // SAFETY: The buffer is valid for its whole size, and the controller isn't currently
+        // writing to it as there is no command outstanding.
+        unsafe {
+            self.buffer
+                .as_ptr()
+                .copy_to_nonoverlapping(data.as_mut_ptr(), data.len());
+        }
+    }
+
+    /// Copies the given slice to the start of the DMA buffer.
+    fn copy_to_buffer(&mut self, data: &[u8]) {
+        assert!(data.len() <= self.buffer.size());
+        // SAFETY: The buffer is valid for its whole size, and the controller isn't currently
+        // accessing it as there is no command outstanding.
+        unsafe {
+            self.buffer
+                .as_ptr()
+                .copy_from_nonoverlapping(data.as_ptr(), data.len());
+        }
+        fence(Ordering::SeqCst);
+    }
+
+    /// Runs a read or write command for the given blocks, which must fit in the DMA buffer.
+    fn io_command(&mut self, opcode: u8, block_id: u64, blocks: usize) -> Result<(), NvmeError> {
+        self.io.run(
+            &self.registers,
+            opcode,
+            SubmissionEntry {
+                nsid: NAMESPACE_ID,
+                prp1: self.buffer.paddr() as u64,
+                cdw10: block_id as u32,
+                cdw11: (block_id >> 32) as u32,
+                // The number of blocks is zero-based.
+                cdw12: (blocks - 1) as u32,
+                ..Default::default()
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Checks that the given buffer length is a whole number of blocks within the namespace.
+    fn check_range(&self, block_id: u64, len: usize) -> Result<(), NvmeError> {
+        if !len.is_multiple_of(self.block_size) {
+            return Err(NvmeError::UnalignedBuffer);
+        }
+        let end = block_id
+            .checked_add((len / self.block_size) as u64)
+            .ok_or(NvmeError::OutOfRange)?;
+        if end > self.capacity {
+            return Err(NvmeError::OutOfRange);
+        }
+        Ok(())
+    }
+}
+
+impl BlockDevice for Nvme {
+    fn kind(&self) -> &'static str {
+        "nvme"
+    }
+
+    fn id(&mut self) -> Result<String, BlockError> {
+        Ok(format!("{} {}", self.model, self.serial))
+    }
+
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    fn readonly(&self) -> bool {
+        false
+    }
+
+    fn read_blocks(&mut self, block_id: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.check_range(block_id, buf.len())?;
+        let mut block_id = block_id;
+        for chunk in buf.chunks_mut(self.buffer.size()) {
+            let blocks = chunk.len() / self.block_size;
+            self.io_command(NVM_READ, block_id, blocks)?;
+            self.copy_from_buffer(chunk);
+            block_id += blocks as u64;
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, block_id: u64, buf: &[u8]) -> Result<(), BlockError> {
+        self.check_range(block_id, buf.len())?;
+        let mut block_id = block_id;
+        for chunk in buf.chunks(self.buffer.size()) {
+            let blocks = chunk.len() / self.block_size;
+            self.copy_to_buffer(chunk);
+            self.io_command(NVM_WRITE, block_id, blocks)?;
+            block_id += blocks as u64;
+        }
+        Ok(())
+    }
+}
+
+/// Converts a space-padded ASCII string from identify data to a string.
+fn identify_string(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .trim_end_matches([' ', '\0'])
+        .into()
+}
+
+/// Returns whether the given PCI function is an NVMe controller.
+fn is_nvme(info: &DeviceFunctionInfo) -> bool {
+    info.class == PCI_CLASS_STORAGE
+        && info.subclass == PCI_SUBCLASS_NVM
+        && info.prog_if == PCI_PROG_IF_NVME
+}
+
+/// Finds NVMe controllers on the given PCI root, and adds drivers for them to the block devices.
+pub fn find_nvme_devices(pci_root: &mut PciRootComplex, devices: &mut Devices) {
+    let controllers = pci_root
+        .unclaimed_devices()
+        .into_iter()
+        .filter(|(_, info)| is_nvme(info))
+        .map(|(device_function, _)| device_function)
+        .collect::<Vec<_>>();
+    for device_function in controllers {
+        info!("Found NVMe controller at {device_function}");
+        match init_nvme(pci_root, device_function) {
+            Ok(nvme) => {
+                info!(
+                    "NVMe {}: {} blocks of {} bytes",
+                    nvme.model, nvme.capacity, nvme.block_size
+                );
+                pci_root.claim(device_function);
+                if let Err(e) = devices.add_block(Box::new(nvme), format!("PCI {device_function}"))
+                {
+                    error!("Error adding NVMe controller at {device_function}: {e}");
+                }
+            }
+            Err(e) => error!("Error initialising NVMe controller at {device_function}: {e}"),
+        }
+    }
+}
+
+fn init_nvme(
+    pci_root: &mut PciRootComplex,
+    device_function: DeviceFunction,
+) -> Result<Nvme, NvmeError> {
+    let Ok([Some(BarInfo::Memory { address, size, .. }), ..]) = pci_root.bars(device_function)
+    else {
+        return Err(NvmeError::NoRegisters);
+    };
+    if address == 0 || size < (REG_DOORBELLS + 4 * size_of::<u32>()) as u64 {
+        return Err(NvmeError::NoRegisters);
+    }
+    // SAFETY: BAR 0 was allocated and mapped as device memory when the PCI root was initialised,
+    // and this is the only driver for the device.
+    unsafe { Nvme::new(NonNull::new(address as *mut u8).unwrap()) }
+}
@@ -2,13 +2,16 @@
 // This project is dual-licensed under Apache 2.0 and MIT terms.
 // See LICENSE-APACHE and LICENSE-MIT for details.
 
+//! Interrupt handling for the PL011 UART.
+
 use super::InterruptDriven;
-use arm_gic::{IntId, InterruptGroup, gicv3::GicCpuInterface};
+use crate::interrupts::end_interrupt;
+use arm_gic::IntId;
 use arm_pl011_uart::{Interrupts, Uart};
 
 impl InterruptDriven for Uart<'_> {
     fn handle_irq(&mut self, intid: IntId) {
         self.clear_interrupts(Interrupts::RXI);
-        GicCpuInterface::end_interrupt(intid, InterruptGroup::Group1);
+        end_interrupt(intid);
     }
 }
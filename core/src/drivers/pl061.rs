@@ -0,0 +1,88 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A minimal driver for the Arm PL061 GPIO controller, enough to take interrupts from input lines
+//! such as a power button.
+
+use core::ptr::NonNull;
+
+/// The data direction register, with a bit set for each output line.
+const GPIODIR: usize = 0x400;
+/// The interrupt sense register, with a bit set for each level-sensitive line.
+const GPIOIS: usize = 0x404;
+/// The interrupt both-edges register, with a bit set for each line which interrupts on both edges.
+const GPIOIBE: usize = 0x408;
+/// The interrupt event register, with a bit set for each line which interrupts on a rising edge or
+/// high level.
+const GPIOIEV: usize = 0x40c;
+/// The interrupt mask register, with a bit set for each line whose interrupt is enabled.
+const GPIOIE: usize = 0x410;
+/// The masked interrupt status register.
+const GPIOMIS: usize = 0x418;
+/// The interrupt clear register.
+const GPIOIC: usize = 0x41c;
+
+/// A PL061 GPIO controller.
+#[derive(Debug)]
+pub struct Pl061 {
+    base: NonNull<u8>,
+}
+
+// SAFETY: The registers can be accessed from any core, and `&mut self` is required to change them.
+unsafe impl Send for Pl061 {}
+
+impl Pl061 {
+    /// Creates a driver for the PL061 with registers at the given address.
+    ///
+    /// # Safety
+    ///
+    /// The registers must be mapped as device memory, and not be used by anything else for as long
+    /// as the driver exists.
+    pub unsafe fn new(base: NonNull<u8>) -> Self {
+        Self { base }
+    }
+
+    /// Configures the given line as an input which interrupts on its rising edge, and enables its
+    /// interrupt.
+    pub fn enable_rising_edge_interrupt(&mut self, line: u8) {
+        let bit = 1 << line;
+        self.modify(GPIOIE, bit, 0);
+        self.modify(GPIODIR, bit, 0);
+        self.modify(GPIOIS, bit, 0);
+        self.modify(GPIOIBE, bit, 0);
+        self.modify(GPIOIEV, 0, bit);
+        self.write(GPIOIC, bit);
+        self.modify(GPIOIE, 0, bit);
+    }
+
+    /// Disables the interrupt for the given line.
+    pub fn disable_interrupt(&mut self, line: u8) {
+        self.modify(GPIOIE, 1 << line, 0);
+    }
+
+    /// Returns a bit for each line with an interrupt pending, and clears them.
+    pub fn take_interrupts(&mut self) -> u8 {
+        let pending = self.read(GPIOMIS);
+        self.write(GPIOIC, pending);
+        pending
+    }
+
+    fn read(&self, offset: usize) -> u8 {
+        // SAFETY: `Pl061::new` requires that the registers are mapped, and the offset is one of our
+        // register constants.
+        unsafe { self.base.add(offset).read_volatile() }
+    }
+
+    fn write(&mut self, offset: usize, value: u8) {
+        // SAFETY: `Pl061::new` requires that the registers are mapped, and the offset is one of our
+        // register constants.
+        unsafe { self.base.add(offset).write_volatile(value) }
+    }
+
+    /// Clears the bits in `clear` and then sets the bits in `set` of the given register.
+    fn modify(&mut self, offset: usize, clear: u8, set: u8) {
+        let value = self.read(offset);
+        self.write(offset, value & !clear | set);
+    }
+}
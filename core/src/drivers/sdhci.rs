@@ -0,0 +1,844 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A minimal polled driver for SD cards and eMMC devices attached to a standard SD host controller,
+//! either on PCI such as QEMU's `sdhci-pci`, or described by the device tree such as on the
+//! Raspberry Pi.
+//!
+//! Data is transferred with programmed I/O through the buffer data port rather than DMA, on a
+//! 1-bit bus at the default speed. Only a single slot per controller is supported.
+
+use crate::{
+    block::{BlockDevice, BlockError},
+    devices::Devices,
+    fallible::OutOfMemory,
+    fdt::is_compatible,
+    pci::PciRootComplex,
+    sd::{
+        BLOCK_SIZE, CardKind, Cid, CsdCapacity, DEFAULT_CLOCK_HZ, EXT_CSD_SIZE,
+        IDENTIFICATION_CLOCK_HZ, IF_COND_ARGUMENT, OCR_HIGH_CAPACITY, OCR_READY,
+        OCR_VOLTAGE_WINDOW, clock_divisor, csd_capacity, divided_clock, ext_csd_sectors,
+        r2_from_response,
+    },
+};
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use core::{
+    fmt::{self, Display, Formatter},
+    ptr::NonNull,
+};
+use dtoolkit::{Node, fdt::Fdt};
+use log::{debug, error, info};
+use virtio_drivers::transport::pci::bus::{BarInfo, DeviceFunction, DeviceFunctionInfo};
+
+/// PCI class code for base system peripherals.
+const PCI_CLASS_SYSTEM: u8 = 0x08;
+/// PCI subclass for SD host controllers.
+const PCI_SUBCLASS_SD_HOST: u8 = 0x05;
+
+/// Device tree compatible strings for standard SD host controllers.
+pub const SDHCI_COMPATIBLE: &[&str] = &[
+    "arasan,sdhci-5.1",
+    "arasan,sdhci-8.9a",
+    "brcm,bcm2711-emmc2",
+    "brcm,bcm2835-sdhci",
+];
+
+// Controller registers.
+const REG_BLOCK_SIZE: usize = 0x04;
+const REG_BLOCK_COUNT: usize = 0x06;
+const REG_ARGUMENT: usize = 0x08;
+const REG_TRANSFER_MODE: usize = 0x0c;
+const REG_COMMAND: usize = 0x0e;
+const REG_RESPONSE: usize = 0x10;
+const REG_BUFFER_DATA: usize = 0x20;
+const REG_PRESENT_STATE: usize = 0x24;
+const REG_POWER_CONTROL: usize = 0x29;
+const REG_CLOCK_CONTROL: usize = 0x2c;
+const REG_TIMEOUT_CONTROL: usize = 0x2e;
+const REG_SOFTWARE_RESET: usize = 0x2f;
+const REG_NORMAL_INT_STATUS: usize = 0x30;
+const REG_ERROR_INT_STATUS: usize = 0x32;
+const REG_NORMAL_INT_STATUS_ENABLE: usize = 0x34;
+const REG_ERROR_INT_STATUS_ENABLE: usize = 0x36;
+const REG_NORMAL_INT_SIGNAL_ENABLE: usize = 0x38;
+const REG_ERROR_INT_SIGNAL_ENABLE: usize = 0x3a;
+const REG_CAPABILITIES: usize = 0x40;
+const REG_HOST_VERSION: usize = 0xfe;
+/// The size of the standard register set for a slot.
+const REGISTERS_SIZE: u64 = 0x100;
+
+const TRANSFER_BLOCK_COUNT_ENABLE: u16 = 1 << 1;
+const TRANSFER_AUTO_CMD12: u16 = 1 << 2;
+const TRANSFER_READ: u16 = 1 << 4;
+const TRANSFER_MULTI_BLOCK: u16 = 1 << 5;
+
+const COMMAND_DATA_PRESENT: u16 = 1 << 5;
+
+const PRESENT_COMMAND_INHIBIT: u32 = 1 << 0;
+const PRESENT_DATA_INHIBIT: u32 = 1 << 1;
+const PRESENT_CARD_INSERTED: u32 = 1 << 16;
+/// The level of the write protect switch, which is low if the card is write protected.
+const PRESENT_WRITE_ENABLED: u32 = 1 << 19;
+
+const POWER_ON: u8 = 1 << 0;
+const POWER_3_3V: u8 = 0b111 << 1;
+const POWER_3_0V: u8 = 0b110 << 1;
+const POWER_1_8V: u8 = 0b101 << 1;
+
+const CLOCK_INTERNAL_ENABLE: u16 = 1 << 0;
+const CLOCK_INTERNAL_STABLE: u16 = 1 << 1;
+const CLOCK_SD_ENABLE: u16 = 1 << 2;
+
+/// The longest data timeout, in units of the timeout clock.
+const TIMEOUT_MAX: u8 = 0x0e;
+
+const RESET_ALL: u8 = 1 << 0;
+const RESET_COMMAND: u8 = 1 << 1;
+const RESET_DATA: u8 = 1 << 2;
+
+const INT_COMMAND_COMPLETE: u16 = 1 << 0;
+const INT_TRANSFER_COMPLETE: u16 = 1 << 1;
+const INT_BUFFER_WRITE_READY: u16 = 1 << 4;
+const INT_BUFFER_READ_READY: u16 = 1 << 5;
+const INT_ERROR: u16 = 1 << 15;
+const ERROR_COMMAND_TIMEOUT: u16 = 1 << 0;
+
+const CAPABILITY_3_3V: u32 = 1 << 24;
+const CAPABILITY_3_0V: u32 = 1 << 25;
+const CAPABILITY_1_8V: u32 = 1 << 26;
+
+/// Host controller specification version 3.00, which has 10-bit clock divisors and an 8-bit base
+/// clock frequency.
+const SPEC_VERSION_3: u16 = 2;
+
+// Card commands.
+const GO_IDLE_STATE: u8 = 0;
+const MMC_SEND_OP_COND: u8 = 1;
+const ALL_SEND_CID: u8 = 2;
+/// SEND_RELATIVE_ADDR for SD, or SET_RELATIVE_ADDR for MMC.
+const RELATIVE_ADDR: u8 = 3;
+const SELECT_CARD: u8 = 7;
+/// SEND_IF_COND for SD, or SEND_EXT_CSD for MMC.
+const SEND_IF_COND_OR_EXT_CSD: u8 = 8;
+const SEND_CSD: u8 = 9;
+const SET_BLOCKLEN: u8 = 16;
+const READ_SINGLE_BLOCK: u8 = 17;
+const READ_MULTIPLE_BLOCK: u8 = 18;
+const WRITE_BLOCK: u8 = 24;
+const WRITE_MULTIPLE_BLOCK: u8 = 25;
+const APP_CMD: u8 = 55;
+const SD_SEND_OP_COND: u8 = 41;
+
+/// The relative card address we assign to MMC devices. SD cards choose their own.
+const MMC_RELATIVE_ADDRESS: u32 = 1;
+
+/// The maximum number of blocks in a single transfer, limited by the block count register.
+const MAX_TRANSFER_BLOCKS: usize = u16::MAX as usize;
+
+/// How many times to poll a register before giving up.
+const POLL_LIMIT: u32 = 10_000_000;
+/// How many times to ask the card for its operating conditions while it powers up.
+const OP_COND_ATTEMPTS: u32 = 100_000;
+
+/// An error from the SDHCI driver.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SdhciError {
+    /// The controller doesn't have a suitable memory region for its registers.
+    NoRegisters,
+    /// There is no card in the slot.
+    NoCard,
+    /// The controller doesn't say what its base clock frequency is.
+    NoBaseClock,
+    /// The controller doesn't support any voltage which we know about.
+    UnsupportedVoltage,
+    /// The controller didn't respond in time.
+    Timeout,
+    /// The card didn't respond to the given command.
+    CommandTimeout(u8),
+    /// The given command failed, with the given error interrupt status.
+    CommandFailed { command: u8, status: u16 },
+    /// The card didn't finish powering up in time.
+    NotReady,
+    /// The card's CSD register has a structure version we don't support.
+    UnsupportedCsd(u8),
+    /// The buffer length wasn't a multiple of the block size.
+    UnalignedBuffer,
+    /// The blocks requested are beyond the end of the card.
+    OutOfRange,
+    /// There wasn't enough memory to add the card to the block devices.
+    OutOfMemory(OutOfMemory),
+}
+
+impl Display for SdhciError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::NoRegisters => write!(f, "No suitable register region"),
+            Self::NoCard => write!(f, "No card inserted"),
+            Self::NoBaseClock => write!(f, "Base clock frequency not specified"),
+            Self::UnsupportedVoltage => write!(f, "No supported bus voltage"),
+            Self::Timeout => write!(f, "Timed out waiting for controller"),
+            Self::CommandTimeout(command) => write!(f, "No response to CMD{command}"),
+            Self::CommandFailed { command, status } => {
+                write!(f, "CMD{command} failed with error status {status:#06x}")
+            }
+            Self::NotReady => write!(f, "Card didn't finish powering up"),
+            Self::UnsupportedCsd(structure) => {
+                write!(f, "Unsupported CSD structure version {structure}")
+            }
+            Self::UnalignedBuffer => write!(f, "Buffer is not a multiple of the block size"),
+            Self::OutOfRange => write!(f, "Blocks are beyond the end of the card"),
+            Self::OutOfMemory(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// The type of response expected to a command.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Response {
+    None,
+    /// A 48-bit response with a CRC and command index, such as R1, R6 or R7.
+    Short,
+    /// A short response after which the card may signal busy on the data line.
+    ShortBusy,
+    /// A 136-bit response containing the CID or CSD.
+    Long,
+    /// A 48-bit response containing the OCR, with no CRC or command index.
+    OperatingConditions,
+}
+
+impl Response {
+    /// Returns the bits of the command register for the response type.
+    fn command_flags(self) -> u16 {
+        const LENGTH_136: u16 = 0b01;
+        const LENGTH_48: u16 = 0b10;
+        const LENGTH_48_BUSY: u16 = 0b11;
+        const CRC_CHECK: u16 = 1 << 3;
+        const INDEX_CHECK: u16 = 1 << 4;
+        match self {
+            Self::None => 0,
+            Self::Short => LENGTH_48 | CRC_CHECK | INDEX_CHECK,
+            Self::ShortBusy => LENGTH_48_BUSY | CRC_CHECK | INDEX_CHECK,
+            Self::Long => LENGTH_136 | CRC_CHECK,
+            Self::OperatingConditions => LENGTH_48,
+        }
+    }
+}
+
+/// The memory-mapped registers of a controller slot.
+#[derive(Debug)]
+struct Registers {
+    base: NonNull<u8>,
+}
+
+impl Registers {
+    fn read8(&self, offset: usize) -> u8 {
+        // SAFETY: `Sdhci::new` requires that the registers are mapped, and the offset is one of our
+        // register constants.
+        unsafe { self.base.add(offset).read_volatile() }
+    }
+
+    fn write8(&self, offset: usize, value: u8) {
+        // SAFETY: `Sdhci::new` requires that the registers are mapped, and the offset is one of our
+        // register constants.
+        unsafe { self.base.add(offset).write_volatile(value) }
+    }
+
+    fn read16(&self, offset: usize) -> u16 {
+        // SAFETY: `Sdhci::new` requires that the registers are mapped, and the offset is one of our
+        // register constants.
+        unsafe { self.base.add(offset).cast::<u16>().read_volatile() }
+    }
+
+    fn write16(&self, offset: usize, value: u16) {
+        // SAFETY: `Sdhci::new` requires that the registers are mapped, and the offset is one of our
+        // register constants.
+        unsafe { self.base.add(offset).cast::<u16>().write_volatile(value) }
+    }
+
+    fn read32(&self, offset: usize) -> u32 {
+        // SAFETY: `Sdhci::new` requires that the registers are mapped, and the offset is one of our
+        // register constants.
+        unsafe { self.base.add(offset).cast::<u32>().read_volatile() }
+    }
+
+    fn write32(&self, offset: usize, value: u32) {
+        // SAFETY: `Sdhci::new` requires that the registers are mapped, and the offset is one of our
+        // register constants.
+        unsafe { self.base.add(offset).cast::<u32>().write_volatile(value) }
+    }
+
+    /// Polls until the given register has none of the given bits set.
+    fn wait_clear32(&self, offset: usize, bits: u32) -> Result<(), SdhciError> {
+        for _ in 0..POLL_LIMIT {
+            if self.read32(offset) & bits == 0 {
+                return Ok(());
+            }
+        }
+        Err(SdhciError::Timeout)
+    }
+
+    /// Starts the given software reset and polls until it completes.
+    fn reset(&self, reset: u8) -> Result<(), SdhciError> {
+        self.write8(REG_SOFTWARE_RESET, reset);
+        for _ in 0..POLL_LIMIT {
+            if self.read8(REG_SOFTWARE_RESET) & reset == 0 {
+                return Ok(());
+            }
+        }
+        Err(SdhciError::Timeout)
+    }
+}
+
+/// Driver for the card in the single slot of an SD host controller.
+#[derive(Debug)]
+pub struct Sdhci {
+    registers: Registers,
+    /// Whether the controller supports 10-bit clock divisors.
+    ten_bit_divisor: bool,
+    base_clock_hz: u32,
+    kind: CardKind,
+    /// Whether the card is addressed in blocks rather than bytes.
+    high_capacity: bool,
+    relative_address: u32,
+    cid: Cid,
+    capacity: u64,
+    readonly: bool,
+}
+
+impl Sdhci {
+    /// Resets the controller, powers up the card in its slot and identifies it.
+    ///
+    /// # Safety
+    ///
+    /// `registers` must be the address of the slot's standard registers, which must be mapped as
+    /// device memory, and not be used anywhere else.
+    unsafe fn new(registers: NonNull<u8>) -> Result<Self, SdhciError> {
+        let registers = Registers { base: registers };
+        registers.reset(RESET_ALL)?;
+
+        let spec_version = registers.read16(REG_HOST_VERSION) & 0xff;
+        let capabilities = registers.read32(REG_CAPABILITIES);
+        let base_clock_mask = if spec_version >= SPEC_VERSION_3 {
+            0xff
+        } else {
+            0x3f
+        };
+        let base_clock_hz = ((capabilities >> 8) & base_clock_mask) * 1_000_000;
+        if base_clock_hz == 0 {
+            return Err(SdhciError::NoBaseClock);
+        }
+        if registers.read32(REG_PRESENT_STATE) & PRESENT_CARD_INSERTED == 0 {
+            return Err(SdhciError::NoCard);
+        }
+
+        let mut sdhci = Self {
+            registers,
+            ten_bit_divisor: spec_version >= SPEC_VERSION_3,
+            base_clock_hz,
+            kind: CardKind::Sd,
+            high_capacity: false,
+            relative_address: 0,
+            cid: Cid {
+                manufacturer_id: 0,
+                product_name: String::new(),
+                revision: 0,
+                serial: 0,
+            },
+            capacity: 0,
+            readonly: false,
+        };
+        sdhci.power_on(capabilities)?;
+        sdhci.set_clock(IDENTIFICATION_CLOCK_HZ)?;
+        let registers = &sdhci.registers;
+        registers.write8(REG_TIMEOUT_CONTROL, TIMEOUT_MAX);
+        // We poll for completions, so enable all status bits but don't signal any interrupts.
+        registers.write16(REG_NORMAL_INT_STATUS_ENABLE, u16::MAX);
+        registers.write16(REG_ERROR_INT_STATUS_ENABLE, u16::MAX);
+        registers.write16(REG_NORMAL_INT_SIGNAL_ENABLE, 0);
+        registers.write16(REG_ERROR_INT_SIGNAL_ENABLE, 0);
+
+        sdhci.identify()?;
+        sdhci.set_clock(DEFAULT_CLOCK_HZ)?;
+        sdhci.readonly = sdhci.registers.read32(REG_PRESENT_STATE) & PRESENT_WRITE_ENABLED == 0;
+        Ok(sdhci)
+    }
+
+    /// Turns on bus power at the highest voltage the controller supports.
+    fn power_on(&mut self, capabilities: u32) -> Result<(), SdhciError> {
+        let voltage = if capabilities & CAPABILITY_3_3V != 0 {
+            POWER_3_3V
+        } else if capabilities & CAPABILITY_3_0V != 0 {
+            POWER_3_0V
+        } else if capabilities & CAPABILITY_1_8V != 0 {
+            POWER_1_8V
+        } else {
+            return Err(SdhciError::UnsupportedVoltage);
+        };
+        self.registers.write8(REG_POWER_CONTROL, voltage);
+        self.registers.write8(REG_POWER_CONTROL, voltage | POWER_ON);
+        Ok(())
+    }
+
+    /// Sets the SD clock to the fastest frequency no more than the given frequency.
+    fn set_clock(&mut self, target_hz: u32) -> Result<(), SdhciError> {
+        let divisor = clock_divisor(self.base_clock_hz, target_hz, self.ten_bit_divisor);
+        // The low 8 bits of the divisor go in bits 8-15, and the upper 2 bits in bits 6-7.
+        let divisor_bits = ((divisor & 0xff) << 8) | ((divisor >> 8) << 6);
+        let registers = &self.registers;
+        registers.write16(REG_CLOCK_CONTROL, 0);
+        registers.write16(REG_CLOCK_CONTROL, divisor_bits | CLOCK_INTERNAL_ENABLE);
+        let mut polls = 0;
+        while registers.read16(REG_CLOCK_CONTROL) & CLOCK_INTERNAL_STABLE == 0 {
+            polls += 1;
+            if polls == POLL_LIMIT {
+                return Err(SdhciError::Timeout);
+            }
+        }
+        registers.write16(
+            REG_CLOCK_CONTROL,
+            divisor_bits | CLOCK_INTERNAL_ENABLE | CLOCK_SD_ENABLE,
+        );
+        debug!(
+            "SD clock set to {} Hz",
+            divided_clock(self.base_clock_hz, divisor)
+        );
+        Ok(())
+    }
+
+    /// Sends the given command, and waits for its response.
+    ///
+    /// If `transfer` is given then the command transfers that many blocks of data with the given
+    /// transfer mode, which the caller must then read or write through the buffer data port.
+    fn command(
+        &mut self,
+        command: u8,
+        argument: u32,
+        response: Response,
+        transfer: Option<(u16, u16)>,
+    ) -> Result<[u32; 4], SdhciError> {
+        let registers = &self.registers;
+        let mut inhibit = PRESENT_COMMAND_INHIBIT;
+        if transfer.is_some() || response == Response::ShortBusy {
+            inhibit |= PRESENT_DATA_INHIBIT;
+        }
+        registers.wait_clear32(REG_PRESENT_STATE, inhibit)?;
+        registers.write16(REG_NORMAL_INT_STATUS, u16::MAX);
+        registers.write16(REG_ERROR_INT_STATUS, u16::MAX);
+
+        let mut command_register = (u16::from(command) << 8) | response.command_flags();
+        if let Some((blocks, transfer_mode)) = transfer {
+            registers.write16(REG_BLOCK_SIZE, BLOCK_SIZE as u16);
+            registers.write16(REG_BLOCK_COUNT, blocks);
+            registers.write16(REG_TRANSFER_MODE, transfer_mode);
+            command_register |= COMMAND_DATA_PRESENT;
+        }
+        registers.write32(REG_ARGUMENT, argument);
+        registers.write16(REG_COMMAND, command_register);
+
+        self.wait_for(command, INT_COMMAND_COMPLETE)?;
+        let registers = &self.registers;
+        let response_words = [0, 1, 2, 3].map(|index| registers.read32(REG_RESPONSE + index * 4));
+        if response == Response::ShortBusy && transfer.is_none() {
+            self.wait_for(command, INT_TRANSFER_COMPLETE)?;
+        }
+        Ok(response_words)
+    }
+
+    /// Polls until the given normal interrupt status bit is set, then clears it.
+    ///
+    /// If an error is reported instead then the command and data lines are reset.
+    fn wait_for(&mut self, command: u8, bit: u16) -> Result<(), SdhciError> {
+        let registers = &self.registers;
+        for _ in 0..POLL_LIMIT {
+            let status = registers.read16(REG_NORMAL_INT_STATUS);
+            if status & INT_ERROR != 0 {
+                let error_status = registers.read16(REG_ERROR_INT_STATUS);
+                registers.write16(REG_ERROR_INT_STATUS, u16::MAX);
+                registers.write16(REG_NORMAL_INT_STATUS, u16::MAX);
+                registers.reset(RESET_COMMAND | RESET_DATA)?;
+                return Err(if error_status & ERROR_COMMAND_TIMEOUT != 0 {
+                    SdhciError::CommandTimeout(command)
+                } else {
+                    SdhciError::CommandFailed {
+                        command,
+                        status: error_status,
+                    }
+                });
+            }
+            if status & bit != 0 {
+                registers.write16(REG_NORMAL_INT_STATUS, bit);
+                return Ok(());
+            }
+        }
+        registers.reset(RESET_COMMAND | RESET_DATA)?;
+        Err(SdhciError::Timeout)
+    }
+
+    /// Sends an application-specific command to an SD card.
+    fn app_command(
+        &mut self,
+        command: u8,
+        argument: u32,
+        response: Response,
+    ) -> Result<[u32; 4], SdhciError> {
+        self.command(APP_CMD, self.relative_address << 16, Response::Short, None)?;
+        self.command(command, argument, response, None)
+    }
+
+    /// Resets the card, works out whether it is SD or MMC, and reads its identification and
+    /// capacity.
+    fn identify(&mut self) -> Result<(), SdhciError> {
+        self.command(GO_IDLE_STATE, 0, Response::None, None)?;
+
+        // Only SD cards version 2.0 and later respond to SEND_IF_COND, and only they may be high
+        // capacity.
+        let version_2 = match self.command(
+            SEND_IF_COND_OR_EXT_CSD,
+            IF_COND_ARGUMENT,
+            Response::Short,
+            None,
+        ) {
+            Ok(response) => response[0] & 0xfff == IF_COND_ARGUMENT,
+            Err(SdhciError::CommandTimeout(_)) => false,
+            Err(e) => return Err(e),
+        };
+
+        // MMC devices don't respond to APP_CMD.
+        let ocr = match self.command(APP_CMD, 0, Response::Short, None) {
+            Ok(_) => {
+                let host_capacity = if version_2 { OCR_HIGH_CAPACITY } else { 0 };
+                self.wait_until_ready(|sdhci| {
+                    sdhci.app_command(
+                        SD_SEND_OP_COND,
+                        OCR_VOLTAGE_WINDOW | host_capacity,
+                        Response::OperatingConditions,
+                    )
+                })?
+            }
+            Err(SdhciError::CommandTimeout(_)) => {
+                self.kind = CardKind::Mmc;
+                self.command(GO_IDLE_STATE, 0, Response::None, None)?;
+                self.wait_until_ready(|sdhci| {
+                    sdhci.command(
+                        MMC_SEND_OP_COND,
+                        OCR_VOLTAGE_WINDOW | OCR_HIGH_CAPACITY,
+                        Response::OperatingConditions,
+                        None,
+                    )
+                })?
+            }
+            Err(e) => return Err(e),
+        };
+        self.high_capacity = ocr & OCR_HIGH_CAPACITY != 0;
+
+        let cid = r2_from_response(self.command(ALL_SEND_CID, 0, Response::Long, None)?);
+        self.cid = Cid::parse(cid, self.kind);
+        self.relative_address = match self.kind {
+            CardKind::Sd => self.command(RELATIVE_ADDR, 0, Response::Short, None)?[0] >> 16,
+            CardKind::Mmc => {
+                self.command(
+                    RELATIVE_ADDR,
+                    MMC_RELATIVE_ADDRESS << 16,
+                    Response::Short,
+                    None,
+                )?;
+                MMC_RELATIVE_ADDRESS
+            }
+        };
+        let csd = r2_from_response(self.command(
+            SEND_CSD,
+            self.relative_address << 16,
+            Response::Long,
+            None,
+        )?);
+        self.command(
+            SELECT_CARD,
+            self.relative_address << 16,
+            Response::ShortBusy,
+            None,
+        )?;
+        if !self.high_capacity {
+            self.command(SET_BLOCKLEN, BLOCK_SIZE as u32, Response::Short, None)?;
+        }
+
+        self.capacity = match csd_capacity(csd, self.kind) {
+            CsdCapacity::Blocks(blocks) => blocks,
+            CsdCapacity::ExtendedCsd => {
+                let mut ext_csd = [0; EXT_CSD_SIZE];
+                self.command(
+                    SEND_IF_COND_OR_EXT_CSD,
+                    0,
+                    Response::Short,
+                    Some((1, TRANSFER_READ)),
+                )?;
+                self.read_data(SEND_IF_COND_OR_EXT_CSD, &mut ext_csd)?;
+                ext_csd_sectors(&ext_csd)
+            }
+            CsdCapacity::UnsupportedStructure(structure) => {
+                return Err(SdhciError::UnsupportedCsd(structure));
+            }
+        };
+        Ok(())
+    }
+
+    /// Repeats the given operating conditions command until the card says it has finished powering
+    /// up, and returns its final OCR.
+    fn wait_until_ready(
+        &mut self,
+        send_op_cond: impl Fn(&mut Self) -> Result<[u32; 4], SdhciError>,
+    ) -> Result<u32, SdhciError> {
+        for _ in 0..OP_COND_ATTEMPTS {
+            let ocr = send_op_cond(self)?[0];
+            if ocr & OCR_READY != 0 {
+                return Ok(ocr);
+            }
+        }
+        Err(SdhciError::NotReady)
+    }
+
+    /// Reads the blocks of data for the current command through the buffer data port, and waits
+    /// for the transfer to complete.
+    fn read_data(&mut self, command: u8, buf: &mut [u8]) -> Result<(), SdhciError> {
+        for block in buf.chunks_exact_mut(BLOCK_SIZE) {
+            self.wait_for(command, INT_BUFFER_READ_READY)?;
+            for word in block.chunks_exact_mut(4) {
+                word.copy_from_slice(&self.registers.read32(REG_BUFFER_DATA).to_le_bytes());
+            }
+        }
+        self.wait_for(command, INT_TRANSFER_COMPLETE)
+    }
+
+    /// Writes the blocks of data for the current command through the buffer data port, and waits
+    /// for the transfer to complete.
+    fn write_data(&mut self, command: u8, buf: &[u8]) -> Result<(), SdhciError> {
+        for block in buf.chunks_exact(BLOCK_SIZE) {
+            self.wait_for(command, INT_BUFFER_WRITE_READY)?;
+            for word in block.chunks_exact(4) {
+                self.registers.write32(
+                    REG_BUFFER_DATA,
+                    u32::from_le_bytes(word.try_into().unwrap()),
+                );
+            }
+        }
+        self.wait_for(command, INT_TRANSFER_COMPLETE)
+    }
+
+    /// Sends a read or write command for the given blocks, choosing between single and multiple
+    /// block commands.
+    fn start_transfer(
+        &mut self,
+        block_id: u64,
+        blocks: usize,
+        read: bool,
+    ) -> Result<u8, SdhciError> {
+        let (command, mut transfer_mode) = match (read, blocks) {
+            (true, 1) => (READ_SINGLE_BLOCK, TRANSFER_READ),
+            (true, _) => (
+                READ_MULTIPLE_BLOCK,
+                TRANSFER_READ | TRANSFER_MULTI_BLOCK | TRANSFER_AUTO_CMD12,
+            ),
+            (false, 1) => (WRITE_BLOCK, 0),
+            (false, _) => (
+                WRITE_MULTIPLE_BLOCK,
+                TRANSFER_MULTI_BLOCK | TRANSFER_AUTO_CMD12,
+            ),
+        };
+        transfer_mode |= TRANSFER_BLOCK_COUNT_ENABLE;
+        // Standard capacity cards are addressed in bytes.
+        let address = if self.high_capacity {
+            block_id
+        } else {
+            block_id * BLOCK_SIZE as u64
+        };
+        self.command(
+            command,
+            address as u32,
+            Response::Short,
+            Some((blocks as u16, transfer_mode)),
+        )?;
+        Ok(command)
+    }
+
+    /// Checks that the given buffer length is a whole number of blocks within the card.
+    fn check_range(&self, block_id: u64, len: usize) -> Result<(), SdhciError> {
+        if !len.is_multiple_of(BLOCK_SIZE) {
+            return Err(SdhciError::UnalignedBuffer);
+        }
+        let end = block_id
+            .checked_add((len / BLOCK_SIZE) as u64)
+            .ok_or(SdhciError::OutOfRange)?;
+        if end > self.capacity {
+            return Err(SdhciError::OutOfRange);
+        }
+        Ok(())
+    }
+}
+
+impl BlockDevice for Sdhci {
+    fn kind(&self) -> &'static str {
+        "sdhci"
+    }
+
+    fn id(&mut self) -> Result<String, BlockError> {
+        let kind = match self.kind {
+            CardKind::Sd => "SD",
+            CardKind::Mmc => "MMC",
+        };
+        Ok(format!(
+            "{kind} {} rev {}.{} serial {:#010x}",
+            self.cid.product_name,
+            self.cid.revision >> 4,
+            self.cid.revision & 0xf,
+            self.cid.serial
+        ))
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    fn readonly(&self) -> bool {
+        self.readonly
+    }
+
+    fn read_blocks(&mut self, block_id: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.check_range(block_id, buf.len())?;
+        let mut block_id = block_id;
+        for chunk in buf.chunks_mut(MAX_TRANSFER_BLOCKS * BLOCK_SIZE) {
+            let blocks = chunk.len() / BLOCK_SIZE;
+            let command = self.start_transfer(block_id, blocks, true)?;
+            self.read_data(command, chunk)?;
+            block_id += blocks as u64;
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, block_id: u64, buf: &[u8]) -> Result<(), BlockError> {
+        self.check_range(block_id, buf.len())?;
+        let mut block_id = block_id;
+        for chunk in buf.chunks(MAX_TRANSFER_BLOCKS * BLOCK_SIZE) {
+            let blocks = chunk.len() / BLOCK_SIZE;
+            let command = self.start_transfer(block_id, blocks, false)?;
+            self.write_data(command, chunk)?;
+            block_id += blocks as u64;
+        }
+        Ok(())
+    }
+}
+
+/// Initialises the card in the slot with the given registers, and adds it to the block devices.
+///
+/// # Safety
+///
+/// `registers` must be the address of the slot's standard registers, which must be mapped as
+/// device memory, and not be used anywhere else.
+unsafe fn add_card(
+    registers: NonNull<u8>,
+    location: String,
+    devices: &mut Devices,
+) -> Result<(), SdhciError> {
+    // SAFETY: Our caller promised that the registers are mapped and not used anywhere else.
+    let sdhci = unsafe { Sdhci::new(registers)? };
+    info!(
+        "SDHCI {location}: {:?} card {} with {} blocks{}",
+        sdhci.kind,
+        sdhci.cid.product_name,
+        sdhci.capacity,
+        if sdhci.readonly { ", read-only" } else { "" }
+    );
+    devices
+        .add_block(Box::new(sdhci), location)
+        .map_err(SdhciError::OutOfMemory)?;
+    Ok(())
+}
+
+/// Returns whether the given PCI function is an SD host controller.
+fn is_sdhci(info: &DeviceFunctionInfo) -> bool {
+    info.class == PCI_CLASS_SYSTEM && info.subclass == PCI_SUBCLASS_SD_HOST
+}
+
+/// Finds SD host controllers on the given PCI root, and adds drivers for the cards in them to the
+/// block devices.
+pub fn find_sdhci_pci_devices(pci_root: &mut PciRootComplex, devices: &mut Devices) {
+    let controllers = pci_root
+        .unclaimed_devices()
+        .into_iter()
+        .filter(|(_, info)| is_sdhci(info))
+        .map(|(device_function, _)| device_function)
+        .collect::<Vec<_>>();
+    for device_function in controllers {
+        info!("Found SD host controller at {device_function}");
+        match init_sdhci_pci(pci_root, device_function, devices) {
+            Ok(()) => pci_root.claim(device_function),
+            Err(e) => error!("Error initialising SD host controller at {device_function}: {e}"),
+        }
+    }
+}
+
+fn init_sdhci_pci(
+    pci_root: &mut PciRootComplex,
+    device_function: DeviceFunction,
+    devices: &mut Devices,
+) -> Result<(), SdhciError> {
+    let Ok(bars) = pci_root.device_bars(device_function) else {
+        return Err(SdhciError::NoRegisters);
+    };
+    let Some(BarInfo::Memory { address, size, .. }) = bars[0] else {
+        return Err(SdhciError::NoRegisters);
+    };
+    if address == 0 || size < REGISTERS_SIZE {
+        return Err(SdhciError::NoRegisters);
+    }
+    // SAFETY: BAR 0 was allocated and mapped as device memory when the PCI root was initialised,
+    // we checked that it is big enough for the slot registers, and this is the only driver for the
+    // controller.
+    unsafe {
+        add_card(
+            NonNull::new(address as *mut u8).unwrap(),
+            format!("PCI {device_function}"),
+            devices,
+        )
+    }
+}
+
+/// Finds SD host controllers in the given device tree, and adds drivers for the cards in them to
+/// the block devices.
+///
+/// # Safety
+///
+/// Any SD host controllers in the given device tree must exist and be mapped appropriately, and
+/// must not be constructed anywhere else.
+pub unsafe fn find_sdhci_mmio_devices(fdt: &Fdt, devices: &mut Devices) {
+    for node in fdt.root().children() {
+        if !is_compatible(&node, SDHCI_COMPATIBLE) {
+            continue;
+        }
+        info!("Found SD host controller {}", node.name());
+        let Some(region) = node.reg().ok().flatten().and_then(|mut reg| reg.next()) else {
+            error!("SD host controller {} has no registers", node.name());
+            continue;
+        };
+        let address = region.address::<u64>().unwrap();
+        if region.size::<u64>().unwrap() < REGISTERS_SIZE {
+            error!("SD host controller {} registers are too small", node.name());
+            continue;
+        }
+        // SAFETY: The caller promised that the device tree is correct, and that the controller is
+        // mapped and not used elsewhere.
+        let result = unsafe {
+            add_card(
+                NonNull::new(address as *mut u8).unwrap(),
+                format!("MMIO {address:#x}"),
+                devices,
+            )
+        };
+        if let Err(e) = result {
+            error!("Error initialising SD host controller {}: {e}", node.name());
+        }
+    }
+}
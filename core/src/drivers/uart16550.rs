@@ -0,0 +1,276 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A driver for 16550-compatible UARTs, with interrupt-driven receive.
+//!
+//! The register layout and line settings come from the device tree with [`Uart16550Config`], so
+//! this works both for PC-style UARTs such as crosvm's and for those on SoCs with word-wide
+//! registers.
+
+use super::InterruptDriven;
+use crate::{
+    interrupts::end_interrupt,
+    uart16550_config::{RegisterWidth, Uart16550Config},
+};
+use arm_gic::IntId;
+use core::{convert::Infallible, hint::spin_loop, ptr::NonNull};
+use embedded_io::{ErrorType, Read, ReadReady, Write, WriteReady};
+
+/// The receive buffer register, when DLAB is clear.
+const RBR: usize = 0;
+/// The transmit holding register, when DLAB is clear.
+const THR: usize = 0;
+/// The low byte of the divisor latch, when DLAB is set.
+const DLL: usize = 0;
+/// The interrupt enable register, when DLAB is clear.
+const IER: usize = 1;
+/// The high byte of the divisor latch, when DLAB is set.
+const DLM: usize = 1;
+/// The FIFO control register, which is write-only.
+const FCR: usize = 2;
+/// The line control register.
+const LCR: usize = 3;
+/// The modem control register.
+const MCR: usize = 4;
+/// The line status register.
+const LSR: usize = 5;
+
+/// Enables the received data available interrupt, which also covers the character timeout.
+const IER_RDA: u8 = 1 << 0;
+
+/// Enables the FIFOs.
+const FCR_ENABLE: u8 = 1 << 0;
+/// Clears the receive FIFO.
+const FCR_CLEAR_RX: u8 = 1 << 1;
+/// Clears the transmit FIFO.
+const FCR_CLEAR_TX: u8 = 1 << 2;
+
+/// 8 data bits, no parity and 1 stop bit.
+const LCR_8N1: u8 = 0b11;
+/// The divisor latch access bit, which switches the first two registers to the divisor latch.
+const LCR_DLAB: u8 = 1 << 7;
+
+/// Asserts data terminal ready.
+const MCR_DTR: u8 = 1 << 0;
+/// Asserts request to send.
+const MCR_RTS: u8 = 1 << 1;
+/// The auxiliary output which PC-style UARTs use to gate their interrupt line.
+const MCR_OUT2: u8 = 1 << 3;
+
+/// There is data in the receive buffer or FIFO.
+const LSR_DR: u8 = 1 << 0;
+/// The transmit holding register or FIFO is empty.
+const LSR_THRE: u8 = 1 << 5;
+/// The transmitter is completely idle.
+const LSR_TEMT: u8 = 1 << 6;
+
+/// The number of received bytes which can be buffered by the driver, in addition to the FIFO.
+const RX_BUFFER_SIZE: usize = 64;
+
+/// Reads the given register of the UART at the given address.
+///
+/// # Safety
+///
+/// `base` must be the address of a 16550-compatible UART with the given layout, mapped as device
+/// memory.
+unsafe fn read_register(base: *mut u8, config: &Uart16550Config, register: usize) -> u8 {
+    let address = base.wrapping_add(config.register_offset(register));
+    // SAFETY: Our caller promises that the register is mapped and valid to read.
+    unsafe {
+        match config.reg_width {
+            RegisterWidth::U8 => address.read_volatile(),
+            RegisterWidth::U32 => address.cast::<u32>().read_volatile() as u8,
+        }
+    }
+}
+
+/// Writes the given register of the UART at the given address.
+///
+/// # Safety
+///
+/// `base` must be the address of a 16550-compatible UART with the given layout, mapped as device
+/// memory.
+unsafe fn write_register(base: *mut u8, config: &Uart16550Config, register: usize, value: u8) {
+    let address = base.wrapping_add(config.register_offset(register));
+    // SAFETY: Our caller promises that the register is mapped and valid to write.
+    unsafe {
+        match config.reg_width {
+            RegisterWidth::U8 => address.write_volatile(value),
+            RegisterWidth::U32 => address.cast::<u32>().write_volatile(value.into()),
+        }
+    }
+}
+
+/// Writes a byte to the UART at the given address by polling, without a driver instance.
+///
+/// This is intended for early output before the driver is set up, or after it has stopped being
+/// used.
+///
+/// # Safety
+///
+/// `base` must be the address of a 16550-compatible UART with the given layout, mapped as device
+/// memory. Nothing else may be accessing the UART at the same time.
+pub unsafe fn write_byte_polled(base: usize, config: &Uart16550Config, byte: u8) {
+    let base = base as *mut u8;
+    // SAFETY: Our caller promises that the UART is mapped and not being accessed by anything else.
+    // Reading the line status register and writing the transmit holding register don't affect
+    // anything else.
+    unsafe {
+        while read_register(base, config, LSR) & LSR_THRE == 0 {
+            spin_loop();
+        }
+        write_register(base, config, THR, byte);
+    }
+}
+
+/// A driver for a 16550-compatible UART.
+///
+/// Received bytes are moved from the UART's FIFO to a buffer in the driver by the interrupt
+/// handler, so that they aren't lost if the FIFO overflows before they are read.
+pub struct Uart16550 {
+    base: NonNull<u8>,
+    config: Uart16550Config,
+    rx_buffer: [u8; RX_BUFFER_SIZE],
+    /// The index in `rx_buffer` of the oldest byte received.
+    rx_start: usize,
+    /// The number of bytes in `rx_buffer`.
+    rx_len: usize,
+}
+
+// SAFETY: The UART's registers can be accessed from any CPU core, and `&mut self` is required for
+// all accesses.
+unsafe impl Send for Uart16550 {}
+
+impl Uart16550 {
+    /// Creates a driver for the UART at the given address, with the given register layout.
+    ///
+    /// This doesn't touch the UART; call `init` to set it up.
+    ///
+    /// # Safety
+    ///
+    /// `base` must be the address of a 16550-compatible UART with the given layout, mapped as
+    /// device memory. Nothing else may access it while the driver exists.
+    pub unsafe fn new(base: NonNull<u8>, config: Uart16550Config) -> Self {
+        Self {
+            base,
+            config,
+            rx_buffer: [0; RX_BUFFER_SIZE],
+            rx_start: 0,
+            rx_len: 0,
+        }
+    }
+
+    /// Sets the baud rate if the UART's clock frequency is known, sets the line to 8N1, enables
+    /// and clears the FIFOs, and enables the receive interrupt.
+    pub fn init(&mut self) {
+        self.write_register(IER, 0);
+        if let Some(divisor) = self.config.divisor() {
+            let [low, high] = divisor.to_le_bytes();
+            self.write_register(LCR, LCR_DLAB);
+            self.write_register(DLL, low);
+            self.write_register(DLM, high);
+        }
+        self.write_register(LCR, LCR_8N1);
+        // Leave the receive trigger level at 1 byte, so as not to rely on the character timeout,
+        // which not all emulations implement.
+        self.write_register(FCR, FCR_ENABLE | FCR_CLEAR_RX | FCR_CLEAR_TX);
+        self.write_register(MCR, MCR_DTR | MCR_RTS | MCR_OUT2);
+        self.write_register(IER, IER_RDA);
+    }
+
+    fn read_register(&self, register: usize) -> u8 {
+        // SAFETY: The caller of `new` promised that `base` is a suitable UART, and we have
+        // exclusive access to it.
+        unsafe { read_register(self.base.as_ptr(), &self.config, register) }
+    }
+
+    fn write_register(&mut self, register: usize, value: u8) {
+        // SAFETY: The caller of `new` promised that `base` is a suitable UART, and we have
+        // exclusive access to it.
+        unsafe { write_register(self.base.as_ptr(), &self.config, register, value) }
+    }
+
+    /// Moves any bytes in the UART's receive FIFO to the driver's buffer.
+    fn receive(&mut self) {
+        while self.read_register(LSR) & LSR_DR != 0 {
+            let byte = self.read_register(RBR);
+            // If the buffer is full then drop the byte, as nothing is reading from the console.
+            if self.rx_len < RX_BUFFER_SIZE {
+                self.rx_buffer[(self.rx_start + self.rx_len) % RX_BUFFER_SIZE] = byte;
+                self.rx_len += 1;
+            }
+        }
+    }
+
+    /// Moves as many bytes as fit from the driver's buffer to the given buffer, and returns how
+    /// many were moved.
+    fn take_received(&mut self, buf: &mut [u8]) -> usize {
+        let count = buf.len().min(self.rx_len);
+        for byte in &mut buf[..count] {
+            *byte = self.rx_buffer[self.rx_start];
+            self.rx_start = (self.rx_start + 1) % RX_BUFFER_SIZE;
+        }
+        self.rx_len -= count;
+        count
+    }
+}
+
+impl InterruptDriven for Uart16550 {
+    fn handle_irq(&mut self, intid: IntId) {
+        // Reading the received bytes clears the interrupt.
+        self.receive();
+        end_interrupt(intid);
+    }
+}
+
+impl ErrorType for Uart16550 {
+    type Error = Infallible;
+}
+
+impl Read for Uart16550 {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            self.receive();
+            if self.rx_len > 0 {
+                return Ok(self.take_received(buf));
+            }
+            spin_loop();
+        }
+    }
+}
+
+impl ReadReady for Uart16550 {
+    fn read_ready(&mut self) -> Result<bool, Infallible> {
+        self.receive();
+        Ok(self.rx_len > 0)
+    }
+}
+
+impl Write for Uart16550 {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        for &byte in buf {
+            while self.read_register(LSR) & LSR_THRE == 0 {
+                spin_loop();
+            }
+            self.write_register(THR, byte);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        while self.read_register(LSR) & LSR_TEMT == 0 {
+            spin_loop();
+        }
+        Ok(())
+    }
+}
+
+impl WriteReady for Uart16550 {
+    fn write_ready(&mut self) -> Result<bool, Infallible> {
+        Ok(self.read_register(LSR) & LSR_THRE != 0)
+    }
+}
@@ -0,0 +1,360 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A driver for USB mass storage devices using SCSI commands over the bulk-only transport, such as
+//! USB sticks, attached to an XHCI controller.
+
+use crate::{
+    block::{BlockDevice, BlockError},
+    dma::{DmaBuffer, DmaError, NO_ADDRESS_LIMIT},
+    drivers::xhci::{BulkEndpoints, SharedXhci, XhciError},
+    scsi::{
+        CBW_SIZE, CSW_SIZE, Capacity, Command, CommandBlockWrapper, CommandStatus,
+        CommandStatusWrapper, INQUIRY_DATA_SIZE, InquiryData, MODE_SENSE_6_HEADER_SIZE,
+        READ_CAPACITY_10_DATA_SIZE, SENSE_DATA_SIZE, SenseData, mode_sense_6_write_protected,
+    },
+};
+use alloc::{format, string::String};
+use core::{
+    fmt::{self, Display, Formatter},
+    sync::atomic::{Ordering, fence},
+};
+use log::info;
+
+/// The offset of the command status wrapper in the wrapper buffer, after the command block wrapper.
+const CSW_OFFSET: usize = 64;
+
+/// The SCSI peripheral device type for direct access block devices.
+const DIRECT_ACCESS_DEVICE: u8 = 0x00;
+
+/// The number of times to try TEST UNIT READY while the device reports unit attention conditions.
+const READY_ATTEMPTS: usize = 3;
+
+/// An error from the USB mass storage driver.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UsbStorageError {
+    Xhci(XhciError),
+    Dma(DmaError),
+    /// The device returned an invalid command status wrapper.
+    InvalidStatus,
+    /// A command failed.
+    CommandFailed,
+    /// The device didn't follow the bulk-only transport protocol.
+    PhaseError,
+    /// The device isn't a direct access block device, but the given SCSI peripheral device type.
+    NotBlockDevice(u8),
+    /// The device's block size is zero or larger than a page.
+    UnsupportedBlockSize(u32),
+    /// The device has too many blocks for READ CAPACITY (10).
+    TooLarge,
+    /// The buffer length wasn't a multiple of the block size.
+    UnalignedBuffer,
+    /// The blocks requested are beyond the end of the device.
+    OutOfRange,
+}
+
+impl Display for UsbStorageError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Xhci(e) => write!(f, "{e}"),
+            Self::Dma(e) => write!(f, "{e}"),
+            Self::InvalidStatus => write!(f, "Invalid command status wrapper"),
+            Self::CommandFailed => write!(f, "Command failed"),
+            Self::PhaseError => write!(f, "Phase error"),
+            Self::NotBlockDevice(device_type) => {
+                write!(
+                    f,
+                    "Not a block device, but SCSI device type {device_type:#x}"
+                )
+            }
+            Self::UnsupportedBlockSize(size) => write!(f, "Unsupported block size {size}"),
+            Self::TooLarge => write!(f, "Too many blocks"),
+            Self::UnalignedBuffer => write!(f, "Buffer is not a multiple of the block size"),
+            Self::OutOfRange => write!(f, "Blocks are beyond the end of the device"),
+        }
+    }
+}
+
+impl From<XhciError> for UsbStorageError {
+    fn from(e: XhciError) -> Self {
+        Self::Xhci(e)
+    }
+}
+
+impl From<DmaError> for UsbStorageError {
+    fn from(e: DmaError) -> Self {
+        Self::Dma(e)
+    }
+}
+
+/// A USB mass storage device, using logical unit 0 only.
+pub struct UsbStorage {
+    controller: SharedXhci,
+    endpoints: BulkEndpoints,
+    /// The command block wrapper, followed by the command status wrapper at `CSW_OFFSET`.
+    wrappers: DmaBuffer,
+    /// A page for the data stage of commands, so that the caller's buffer needn't be
+    /// page-aligned.
+    buffer: DmaBuffer,
+    next_tag: u32,
+    block_size: usize,
+    capacity: u64,
+    readonly: bool,
+    vendor: String,
+    product: String,
+}
+
+impl UsbStorage {
+    /// Identifies the device with the given endpoints on the given controller, and waits for it to
+    /// be ready.
+    pub fn new(controller: SharedXhci, endpoints: BulkEndpoints) -> Result<Self, UsbStorageError> {
+        let mut storage = Self {
+            controller,
+            endpoints,
+            wrappers: DmaBuffer::new(1, NO_ADDRESS_LIMIT)?,
+            buffer: DmaBuffer::new(1, NO_ADDRESS_LIMIT)?,
+            next_tag: 1,
+            block_size: 0,
+            capacity: 0,
+            readonly: false,
+            vendor: String::new(),
+            product: String::new(),
+        };
+
+        let mut inquiry = [0; INQUIRY_DATA_SIZE];
+        storage.run(Command::inquiry(), true, inquiry.len())?;
+        storage.copy_from_buffer(&mut inquiry);
+        let inquiry = InquiryData::parse(&inquiry).ok_or(UsbStorageError::InvalidStatus)?;
+        if inquiry.peripheral_device_type != DIRECT_ACCESS_DEVICE {
+            return Err(UsbStorageError::NotBlockDevice(
+                inquiry.peripheral_device_type,
+            ));
+        }
+        storage.vendor = inquiry.vendor.into();
+        storage.product = inquiry.product.into();
+
+        storage.wait_until_ready()?;
+
+        let mut capacity = [0; READ_CAPACITY_10_DATA_SIZE];
+        storage.run(Command::read_capacity_10(), true, capacity.len())?;
+        storage.copy_from_buffer(&mut capacity);
+        let capacity = Capacity::parse(&capacity).ok_or(UsbStorageError::InvalidStatus)?;
+        if capacity.block_size == 0 || capacity.block_size as usize > storage.buffer.size() {
+            return Err(UsbStorageError::UnsupportedBlockSize(capacity.block_size));
+        }
+        storage.block_size = capacity.block_size as usize;
+        storage.capacity = capacity.blocks().ok_or(UsbStorageError::TooLarge)?;
+
+        // Not all devices support MODE SENSE, so assume they are writable if it fails.
+        let mut header = [0; MODE_SENSE_6_HEADER_SIZE];
+        if storage
+            .run(Command::mode_sense_6(), true, header.len())
+            .is_ok()
+        {
+            storage.copy_from_buffer(&mut header);
+            storage.readonly = mode_sense_6_write_protected(&header).unwrap_or(false);
+        }
+
+        info!(
+            "USB storage on port {}: \"{} {}\", {} blocks of {} bytes",
+            storage.endpoints.port(),
+            storage.vendor,
+            storage.product,
+            storage.capacity,
+            storage.block_size
+        );
+        Ok(storage)
+    }
+
+    /// Waits for the device to be ready, reading the sense data after each failure to clear any
+    /// unit attention condition such as the reset at power on.
+    fn wait_until_ready(&mut self) -> Result<(), UsbStorageError> {
+        let mut result = Ok(());
+        for _ in 0..READY_ATTEMPTS {
+            result = self.run(Command::test_unit_ready(), false, 0).map(|_| ());
+            if result != Err(UsbStorageError::CommandFailed) {
+                break;
+            }
+            let mut sense = [0; SENSE_DATA_SIZE];
+            self.run(Command::request_sense(), true, sense.len())?;
+            self.copy_from_buffer(&mut sense);
+            if let Some(sense) = SenseData::parse(&sense)
+                && sense.key != SenseData::KEY_UNIT_ATTENTION
+            {
+                info!(
+                    "USB storage not ready: sense key {:#x}, ASC {:#x}, ASCQ {:#x}",
+                    sense.key, sense.additional_code, sense.additional_qualifier
+                );
+            }
+        }
+        result
+    }
+
+    /// Runs the given SCSI command with a data stage of the given length using the DMA buffer, and
+    /// returns the number of bytes actually transferred.
+    fn run(
+        &mut self,
+        command: Command,
+        data_in: bool,
+        length: usize,
+    ) -> Result<usize, UsbStorageError> {
+        assert!(length <= self.buffer.size());
+        let tag = self.next_tag;
+        self.next_tag = self.next_tag.wrapping_add(1);
+        let cbw = CommandBlockWrapper {
+            tag,
+            data_length: length as u32,
+            data_in,
+            lun: 0,
+            command,
+        };
+        // SAFETY: The wrapper buffer is valid for its whole size, and the controller isn't
+        // currently accessing it as there is no command outstanding.
+        unsafe {
+            self.wrappers
+                .as_ptr()
+                .copy_from_nonoverlapping(cbw.to_bytes().as_ptr(), CBW_SIZE);
+        }
+        fence(Ordering::SeqCst);
+
+        let mut controller = self.controller.lock();
+        controller.bulk_transfer(&mut self.endpoints, false, self.wrappers.paddr(), CBW_SIZE)?;
+        let transferred = if length > 0 {
+            controller.bulk_transfer(&mut self.endpoints, data_in, self.buffer.paddr(), length)?
+        } else {
+            0
+        };
+        controller.bulk_transfer(
+            &mut self.endpoints,
+            true,
+            self.wrappers.paddr() + CSW_OFFSET,
+            CSW_SIZE,
+        )?;
+        drop(controller);
+
+        let mut csw = [0; CSW_SIZE];
+        fence(Ordering::SeqCst);
+        // SAFETY: The range is within the wrapper buffer, and the controller has finished writing
+        // to it.
+        unsafe {
+            self.wrappers
+                .as_ptr()
+                .add(CSW_OFFSET)
+                .copy_to_nonoverlapping(csw.as_mut_ptr(), CSW_SIZE);
+        }
+        let csw = CommandStatusWrapper::parse(&csw)
+            .filter(|csw| csw.tag == tag)
+            .ok_or(UsbStorageError::InvalidStatus)?;
+        match csw.status {
+            CommandStatus::Passed => Ok(transferred),
+            CommandStatus::Failed => Err(UsbStorageError::CommandFailed),
+            CommandStatus::PhaseError => Err(UsbStorageError::PhaseError),
+        }
+    }
+
+    /// Copies the start of the DMA buffer into the given slice.
+    fn copy_from_buffer(&self, data: &mut [u8]) {
+        assert!(data.len() <= self.buffer.size());
+        fence(Ordering::SeqCst);
+        // SAFETY: The buffer is valid for its whole size, and the controller isn't currently
+        // writing to it as there is no command outstanding.
+        unsafe {
+            self.buffer
+                .as_ptr()
+                .copy_to_nonoverlapping(data.as_mut_ptr(), data.len());
+        }
+    }
+
+    /// Copies the given slice to the start of the DMA buffer.
+    fn copy_to_buffer(&mut self, data: &[u8]) {
+        assert!(data.len() <= self.buffer.size());
+        // SAFETY: The buffer is valid for its whole size, and the controller isn't currently
+        // accessing it as there is no command outstanding.
+        unsafe {
+            self.buffer
+                .as_ptr()
+                .copy_from_nonoverlapping(data.as_ptr(), data.len());
+        }
+        fence(Ordering::SeqCst);
+    }
+
+    /// Checks that the given buffer length is a whole number of blocks within the device.
+    fn check_range(&self, block_id: u64, len: usize) -> Result<(), UsbStorageError> {
+        if !len.is_multiple_of(self.block_size) {
+            return Err(UsbStorageError::UnalignedBuffer);
+        }
+        let end = block_id
+            .checked_add((len / self.block_size) as u64)
+            .ok_or(UsbStorageError::OutOfRange)?;
+        if end > self.capacity {
+            return Err(UsbStorageError::OutOfRange);
+        }
+        Ok(())
+    }
+
+    /// Returns the size of the largest chunk which can be transferred by a single command.
+    fn max_chunk_size(&self) -> usize {
+        self.buffer.size() / self.block_size * self.block_size
+    }
+}
+
+impl BlockDevice for UsbStorage {
+    fn kind(&self) -> &'static str {
+        "usb"
+    }
+
+    fn id(&mut self) -> Result<String, BlockError> {
+        Ok(format!(
+            "{} {} (port {})",
+            self.vendor,
+            self.product,
+            self.endpoints.port()
+        ))
+    }
+
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    fn readonly(&self) -> bool {
+        self.readonly
+    }
+
+    fn read_blocks(&mut self, block_id: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.check_range(block_id, buf.len())?;
+        let mut block_id = block_id;
+        for chunk in buf.chunks_mut(self.max_chunk_size()) {
+            let blocks = chunk.len() / self.block_size;
+            // The capacity fits in 32 bits, so the block ID does too.
+            self.run(
+                Command::read_10(block_id as u32, blocks as u16),
+                true,
+                chunk.len(),
+            )?;
+            self.copy_from_buffer(chunk);
+            block_id += blocks as u64;
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, block_id: u64, buf: &[u8]) -> Result<(), BlockError> {
+        self.check_range(block_id, buf.len())?;
+        let mut block_id = block_id;
+        for chunk in buf.chunks(self.max_chunk_size()) {
+            let blocks = chunk.len() / self.block_size;
+            self.copy_to_buffer(chunk);
+            self.run(
+                Command::write_10(block_id as u32, blocks as u16),
+                false,
+                chunk.len(),
+            )?;
+            block_id += blocks as u64;
+        }
+        Ok(())
+    }
+}
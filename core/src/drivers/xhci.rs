@@ -0,0 +1,1285 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A minimal driver for XHCI USB host controllers attached over PCI, supporting only HID boot
+//! protocol keyboards and bulk-only mass storage devices attached directly to root hub ports.
+//!
+//! Devices are enumerated once when the controller is found, with commands and control transfers
+//! polled. Keyboards are then used as console input: the controller is polled for reports by the
+//! console's read loop, and its interrupt, if any, wakes the CPU so that key presses are noticed.
+//! Mass storage devices are driven by `usb_storage`, with their transfers polled.
+
+#[cfg(feature = "block")]
+use crate::drivers::usb_storage::UsbStorage;
+use crate::{
+    device_id::{DeviceId, DeviceKind},
+    devices::Devices,
+    dma::{DmaBuffer, DmaError, NO_ADDRESS_LIMIT},
+    hid_keyboard::{BOOT_REPORT_SIZE, BootKeyboard},
+    input::{InputSource, add_source},
+    interrupts::{setup_device_irq, take_device_irq, unmask_device_irq},
+    pci::PciRootComplex,
+    usb::{
+        BootKeyboardInterface, CONFIGURATION_DESCRIPTOR_SIZE, DESCRIPTOR_CONFIGURATION,
+        DESCRIPTOR_DEVICE, DEVICE_DESCRIPTOR_SIZE, DeviceDescriptor, MassStorageInterface,
+        SetupPacket, configuration_total_length, find_boot_keyboard, find_mass_storage,
+    },
+};
+use alloc::{boxed::Box, collections::vec_deque::VecDeque, format, sync::Arc, vec::Vec};
+use arm_gic::IntId;
+use core::{
+    fmt::{self, Display, Formatter},
+    mem::size_of,
+    ptr::NonNull,
+    sync::atomic::{Ordering, fence},
+};
+use log::{error, info, warn};
+use spin::mutex::SpinMutex;
+use virtio_drivers::{
+    PAGE_SIZE,
+    transport::pci::bus::{BarInfo, DeviceFunction, DeviceFunctionInfo},
+};
+
+/// PCI class code for serial bus controllers.
+const PCI_CLASS_SERIAL_BUS: u8 = 0x0c;
+/// PCI subclass code for USB controllers.
+const PCI_SUBCLASS_USB: u8 = 0x03;
+/// PCI programming interface for XHCI.
+const PCI_PROG_IF_XHCI: u8 = 0x30;
+
+// Capability register offsets.
+const CAP_CAPLENGTH: usize = 0x00;
+const CAP_HCSPARAMS1: usize = 0x04;
+const CAP_HCSPARAMS2: usize = 0x08;
+const CAP_HCCPARAMS1: usize = 0x10;
+const CAP_DBOFF: usize = 0x14;
+const CAP_RTSOFF: usize = 0x18;
+
+// Operational register offsets.
+const OP_USBCMD: usize = 0x00;
+const OP_USBSTS: usize = 0x04;
+const OP_CRCR: usize = 0x18;
+const OP_DCBAAP: usize = 0x30;
+const OP_CONFIG: usize = 0x38;
+const OP_PORTSC: usize = 0x400;
+const PORT_REGISTERS_SIZE: usize = 0x10;
+
+// Interrupter 0 register offsets, relative to the runtime registers.
+const IR0_IMAN: usize = 0x20;
+const IR0_ERSTSZ: usize = 0x28;
+const IR0_ERSTBA: usize = 0x30;
+const IR0_ERDP: usize = 0x38;
+
+const USBCMD_RUN: u32 = 1 << 0;
+const USBCMD_HCRST: u32 = 1 << 1;
+const USBCMD_INTE: u32 = 1 << 2;
+const USBSTS_HCH: u32 = 1 << 0;
+const USBSTS_EINT: u32 = 1 << 3;
+const USBSTS_CNR: u32 = 1 << 11;
+/// Context size flag in HCCPARAMS1, set if contexts are 64 bytes rather than 32.
+const HCCPARAMS1_CSZ: u32 = 1 << 2;
+const CRCR_RCS: u64 = 1 << 0;
+const IMAN_IP: u32 = 1 << 0;
+const IMAN_IE: u32 = 1 << 1;
+const ERDP_EHB: u64 = 1 << 3;
+
+const PORTSC_CCS: u32 = 1 << 0;
+const PORTSC_PED: u32 = 1 << 1;
+const PORTSC_PR: u32 = 1 << 4;
+/// The status change bits, which are cleared by writing 1.
+const PORTSC_CHANGE_BITS: u32 = 0x7f << 17;
+const PORTSC_PRC: u32 = 1 << 21;
+
+// Port speed IDs.
+const SPEED_FULL: u32 = 1;
+const SPEED_LOW: u32 = 2;
+const SPEED_HIGH: u32 = 3;
+
+// TRB types.
+const TRB_NORMAL: u32 = 1;
+const TRB_SETUP: u32 = 2;
+const TRB_DATA: u32 = 3;
+const TRB_STATUS: u32 = 4;
+const TRB_LINK: u32 = 6;
+const TRB_ENABLE_SLOT: u32 = 9;
+const TRB_ADDRESS_DEVICE: u32 = 11;
+const TRB_CONFIGURE_ENDPOINT: u32 = 12;
+const TRB_EVALUATE_CONTEXT: u32 = 13;
+const TRB_TRANSFER_EVENT: u32 = 32;
+const TRB_COMMAND_COMPLETION: u32 = 33;
+
+const TRB_CYCLE: u32 = 1 << 0;
+/// Toggle cycle, for link TRBs.
+const TRB_TC: u32 = 1 << 1;
+/// Interrupt on short packet.
+const TRB_ISP: u32 = 1 << 2;
+/// Interrupt on completion.
+const TRB_IOC: u32 = 1 << 5;
+/// Immediate data, for setup stage TRBs.
+const TRB_IDT: u32 = 1 << 6;
+/// Data or status stage direction from device to host.
+const TRB_DIR_IN: u32 = 1 << 16;
+/// Setup stage transfer types.
+const TRT_NO_DATA: u32 = 0 << 16;
+const TRT_OUT_DATA: u32 = 2 << 16;
+const TRT_IN_DATA: u32 = 3 << 16;
+
+const COMPLETION_SUCCESS: u32 = 1;
+const COMPLETION_SHORT_PACKET: u32 = 13;
+
+// Endpoint types for endpoint contexts.
+const ENDPOINT_TYPE_BULK_OUT: u32 = 2;
+const ENDPOINT_TYPE_CONTROL: u32 = 4;
+const ENDPOINT_TYPE_BULK_IN: u32 = 6;
+const ENDPOINT_TYPE_INTERRUPT_IN: u32 = 7;
+/// Device context index of the default control endpoint.
+const CONTROL_ENDPOINT_DCI: u8 = 1;
+
+/// The maximum number of device slots we enable.
+const MAX_SLOTS: u8 = 8;
+/// The number of TRBs in each ring, which fills a page.
+const RING_TRBS: usize = PAGE_SIZE / size_of::<Trb>();
+/// The offset of the event ring segment table in the page with the device context base address
+/// array, which is big enough for `MAX_SLOTS` entries.
+const ERST_OFFSET: usize = PAGE_SIZE / 2;
+/// The offset of the buffer for interrupt transfer reports in each device's data page. Control
+/// transfer data goes before it.
+const REPORT_OFFSET: usize = PAGE_SIZE / 2;
+
+/// The number of times to poll the controller before giving up.
+const POLL_LIMIT: u32 = 10_000_000;
+
+/// An error from the XHCI driver.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum XhciError {
+    /// BAR 0 isn't a memory BAR, or is too small.
+    NoRegisters,
+    Dma(DmaError),
+    /// The controller didn't respond in time.
+    Timeout,
+    /// A command or transfer completed with the given completion code.
+    Failed(u32),
+    /// The controller needs scratchpad buffers, which we don't provide.
+    ScratchpadUnsupported,
+    /// A descriptor was invalid.
+    InvalidDescriptor,
+}
+
+impl Display for XhciError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::NoRegisters => write!(f, "BAR 0 is not a large enough memory BAR"),
+            Self::Dma(e) => write!(f, "{e}"),
+            Self::Timeout => write!(f, "Timed out waiting for controller"),
+            Self::Failed(code) => write!(f, "Failed with completion code {code}"),
+            Self::ScratchpadUnsupported => write!(f, "Scratchpad buffers are not supported"),
+            Self::InvalidDescriptor => write!(f, "Invalid descriptor"),
+        }
+    }
+}
+
+impl From<DmaError> for XhciError {
+    fn from(e: DmaError) -> Self {
+        Self::Dma(e)
+    }
+}
+
+/// A transfer request block, the entries in command, transfer and event rings.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+struct Trb {
+    parameter: u64,
+    status: u32,
+    control: u32,
+}
+
+impl Trb {
+    fn new(trb_type: u32, parameter: u64, status: u32, control: u32) -> Self {
+        Self {
+            parameter,
+            status,
+            control: trb_type << 10 | control,
+        }
+    }
+
+    fn trb_type(&self) -> u32 {
+        (self.control >> 10) & 0x3f
+    }
+
+    fn completion_code(&self) -> u32 {
+        self.status >> 24
+    }
+
+    fn slot_id(&self) -> u8 {
+        (self.control >> 24) as u8
+    }
+
+    /// Returns the number of bytes of the transfer which were not used, for transfer events.
+    fn residual_length(&self) -> usize {
+        (self.status & 0x00ff_ffff) as usize
+    }
+
+    /// Returns the device context index of the endpoint, for transfer events.
+    fn endpoint_id(&self) -> u8 {
+        ((self.control >> 16) & 0x1f) as u8
+    }
+
+    /// Returns an error unless the completion code indicates success.
+    fn check_completion(&self) -> Result<(), XhciError> {
+        match self.completion_code() {
+            COMPLETION_SUCCESS | COMPLETION_SHORT_PACKET => Ok(()),
+            code => Err(XhciError::Failed(code)),
+        }
+    }
+}
+
+/// A command or transfer ring, with a link TRB at the end back to the start.
+#[derive(Debug)]
+struct Ring {
+    buffer: DmaBuffer,
+    /// The index of the next TRB to write.
+    enqueue: usize,
+    /// The producer cycle state.
+    cycle: bool,
+}
+
+impl Ring {
+    fn new() -> Result<Self, DmaError> {
+        let mut ring = Self {
+            buffer: DmaBuffer::new(1, NO_ADDRESS_LIMIT)?,
+            enqueue: 0,
+            cycle: true,
+        };
+        ring.write(
+            RING_TRBS - 1,
+            Trb::new(TRB_LINK, ring.buffer.paddr() as u64, 0, TRB_TC),
+        );
+        Ok(ring)
+    }
+
+    fn paddr(&self) -> u64 {
+        self.buffer.paddr() as u64
+    }
+
+    /// Writes the given TRB at the given index, writing the control field containing the cycle
+    /// bit last so that the controller doesn't see a partial TRB.
+    fn write(&mut self, index: usize, trb: Trb) {
+        assert!(index < RING_TRBS);
+        // SAFETY: The index is within the ring, which fills the buffer.
+        unsafe {
+            let entry = self.buffer.as_ptr().cast::<Trb>().add(index);
+            (&raw mut (*entry).parameter).write_volatile(trb.parameter);
+            (&raw mut (*entry).status).write_volatile(trb.status);
+            fence(Ordering::SeqCst);
+            (&raw mut (*entry).control).write_volatile(trb.control);
+        }
+    }
+
+    /// Adds the given TRB to the ring, and returns its physical address.
+    fn push(&mut self, mut trb: Trb) -> u64 {
+        trb.control = (trb.control & !TRB_CYCLE) | u32::from(self.cycle);
+        let index = self.enqueue;
+        self.write(index, trb);
+        self.enqueue += 1;
+        if self.enqueue == RING_TRBS - 1 {
+            // Hand the link TRB to the controller, and wrap around.
+            let link = Trb::new(TRB_LINK, self.paddr(), 0, TRB_TC | u32::from(self.cycle));
+            self.write(RING_TRBS - 1, link);
+            self.enqueue = 0;
+            self.cycle = !self.cycle;
+        }
+        self.paddr() + (index * size_of::<Trb>()) as u64
+    }
+}
+
+/// The event ring, with a single segment.
+#[derive(Debug)]
+struct EventRing {
+    buffer: DmaBuffer,
+    /// The index of the next TRB to read.
+    dequeue: usize,
+    /// The consumer cycle state.
+    cycle: bool,
+}
+
+impl EventRing {
+    fn new() -> Result<Self, DmaError> {
+        Ok(Self {
+            buffer: DmaBuffer::new(1, NO_ADDRESS_LIMIT)?,
+            dequeue: 0,
+            cycle: true,
+        })
+    }
+
+    /// Returns the next event, if the controller has written one.
+    fn next(&mut self) -> Option<Trb> {
+        // SAFETY: The index is within the ring, which fills the buffer.
+        let trb = unsafe {
+            self.buffer
+                .as_ptr()
+                .cast::<Trb>()
+                .add(self.dequeue)
+                .read_volatile()
+        };
+        if (trb.control & TRB_CYCLE != 0) != self.cycle {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+        self.dequeue += 1;
+        if self.dequeue == RING_TRBS {
+            self.dequeue = 0;
+            self.cycle = !self.cycle;
+        }
+        Some(trb)
+    }
+
+    /// Returns the physical address of the next TRB to read, to tell the controller how far we
+    /// have got.
+    fn dequeue_pointer(&self) -> u64 {
+        (self.buffer.paddr() + self.dequeue * size_of::<Trb>()) as u64
+    }
+}
+
+/// The controller's memory-mapped registers.
+#[derive(Debug)]
+struct Registers {
+    base: NonNull<u8>,
+    /// The offset of the operational registers.
+    operational: usize,
+    /// The offset of the runtime registers.
+    runtime: usize,
+    /// The offset of the doorbell array.
+    doorbells: usize,
+}
+
+impl Registers {
+    fn read32(&self, offset: usize) -> u32 {
+        // SAFETY: `Xhci::new`'s caller promised that the registers are mapped, and the offset is
+        // one of our register constants relative to a register block within the BAR.
+        unsafe { self.base.add(offset).cast::<u32>().read_volatile() }
+    }
+
+    fn write32(&self, offset: usize, value: u32) {
+        // SAFETY: `Xhci::new`'s caller promised that the registers are mapped, and the offset is
+        // one of our register constants relative to a register block within the BAR.
+        unsafe { self.base.add(offset).cast::<u32>().write_volatile(value) }
+    }
+
+    fn write64(&self, offset: usize, value: u64) {
+        self.write32(offset, value as u32);
+        self.write32(offset + 4, (value >> 32) as u32);
+    }
+
+    fn read_operational(&self, offset: usize) -> u32 {
+        self.read32(self.operational + offset)
+    }
+
+    fn write_operational(&self, offset: usize, value: u32) {
+        self.write32(self.operational + offset, value);
+    }
+
+    fn write_operational64(&self, offset: usize, value: u64) {
+        self.write64(self.operational + offset, value);
+    }
+
+    fn read_portsc(&self, port: u8) -> u32 {
+        self.read_operational(OP_PORTSC + usize::from(port - 1) * PORT_REGISTERS_SIZE)
+    }
+
+    fn write_portsc(&self, port: u8, value: u32) {
+        self.write_operational(
+            OP_PORTSC + usize::from(port - 1) * PORT_REGISTERS_SIZE,
+            value,
+        );
+    }
+
+    fn ring_doorbell(&self, slot: u8, target: u8) {
+        self.write32(
+            self.doorbells + usize::from(slot) * size_of::<u32>(),
+            target.into(),
+        );
+    }
+
+    /// Polls until the given operational register has the given bits clear.
+    fn wait_clear(&self, offset: usize, bits: u32) -> Result<(), XhciError> {
+        if (0..POLL_LIMIT).any(|_| self.read_operational(offset) & bits == 0) {
+            Ok(())
+        } else {
+            Err(XhciError::Timeout)
+        }
+    }
+}
+
+/// A device attached to a root hub port, which has been addressed.
+#[derive(Debug)]
+struct UsbDevice {
+    slot: u8,
+    port: u8,
+    speed: u32,
+    /// The input context for commands, which must be page-aligned.
+    input_context: DmaBuffer,
+    /// The output device context which the controller maintains.
+    device_context: DmaBuffer,
+    control_ring: Ring,
+    /// Control transfer data, followed by the interrupt transfer buffer at `REPORT_OFFSET` for
+    /// keyboards.
+    data: DmaBuffer,
+}
+
+impl UsbDevice {
+    /// Returns a pointer to the given dword of the input context with the given index, where 0
+    /// is the input control context, 1 is the slot context and 2 onwards are the endpoints.
+    fn input_context_field(&self, context_size: usize, context: u8, dword: usize) -> *mut u32 {
+        assert!(usize::from(context + 1) * context_size <= self.input_context.size());
+        assert!(dword < context_size / size_of::<u32>());
+        // SAFETY: We just checked that the field is within the buffer.
+        unsafe {
+            self.input_context
+                .as_ptr()
+                .add(usize::from(context) * context_size)
+                .cast::<u32>()
+                .add(dword)
+        }
+    }
+
+    fn write_input_context(&mut self, context_size: usize, context: u8, dword: usize, value: u32) {
+        // SAFETY: The field is within the input context, which the controller only reads while
+        // a command is running.
+        unsafe {
+            self.input_context_field(context_size, context, dword)
+                .write_volatile(value);
+        }
+    }
+
+    /// Clears the input context and sets the flags for the contexts which a command should add.
+    fn reset_input_context(&mut self, context_size: usize, add_flags: u32) {
+        // SAFETY: The buffer is valid for its whole size, and the controller only reads it while a
+        // command is running.
+        unsafe {
+            self.input_context
+                .as_ptr()
+                .write_bytes(0, self.input_context.size());
+        }
+        self.write_input_context(context_size, 0, 1, add_flags);
+    }
+
+    /// Writes the slot context in the input context.
+    fn write_slot_context(&mut self, context_size: usize, context_entries: u8) {
+        self.write_input_context(
+            context_size,
+            1,
+            0,
+            u32::from(context_entries) << 27 | self.speed << 20,
+        );
+        self.write_input_context(context_size, 1, 1, u32::from(self.port) << 16);
+    }
+
+    /// Writes the endpoint context for the given device context index in the input context.
+    fn write_endpoint_context(
+        &mut self,
+        context_size: usize,
+        dci: u8,
+        endpoint_type: u32,
+        max_packet_size: u16,
+        interval: u8,
+        dequeue: u64,
+    ) {
+        let context = dci + 1;
+        self.write_input_context(context_size, context, 0, u32::from(interval) << 16);
+        // Allow 3 errors before halting the endpoint.
+        self.write_input_context(
+            context_size,
+            context,
+            1,
+            u32::from(max_packet_size) << 16 | endpoint_type << 3 | 3 << 1,
+        );
+        self.write_input_context(context_size, context, 2, dequeue as u32 | 1);
+        self.write_input_context(context_size, context, 3, (dequeue >> 32) as u32);
+        let average_length = if endpoint_type == ENDPOINT_TYPE_CONTROL {
+            8
+        } else {
+            u32::from(max_packet_size)
+        };
+        // Only periodic endpoints have a maximum payload per service interval.
+        let max_esit_payload = if endpoint_type == ENDPOINT_TYPE_INTERRUPT_IN {
+            u32::from(max_packet_size)
+        } else {
+            0
+        };
+        self.write_input_context(
+            context_size,
+            context,
+            4,
+            max_esit_payload << 16 | average_length,
+        );
+    }
+
+    /// Copies the given amount of control transfer data from the start of the data buffer.
+    fn read_data(&self, data: &mut [u8]) {
+        assert!(data.len() <= REPORT_OFFSET);
+        fence(Ordering::SeqCst);
+        // SAFETY: The range is within the buffer, and the controller isn't writing to it as the
+        // transfer has completed.
+        unsafe {
+            self.data
+                .as_ptr()
+                .copy_to_nonoverlapping(data.as_mut_ptr(), data.len());
+        }
+    }
+}
+
+/// A USB HID boot keyboard attached to a root hub port.
+#[derive(Debug)]
+struct Keyboard {
+    device: UsbDevice,
+    interrupt_ring: Ring,
+    /// The device context index of the interrupt IN endpoint.
+    endpoint: u8,
+    report_size: u16,
+    state: BootKeyboard,
+}
+
+impl Keyboard {
+    /// Queues a transfer on the interrupt endpoint for the next report.
+    fn queue_report_transfer(&mut self, registers: &Registers) {
+        self.interrupt_ring.push(Trb::new(
+            TRB_NORMAL,
+            (self.device.data.paddr() + REPORT_OFFSET) as u64,
+            self.report_size.into(),
+            TRB_IOC | TRB_ISP,
+        ));
+        registers.ring_doorbell(self.device.slot, self.endpoint);
+    }
+
+    /// Handles a transfer event for the interrupt endpoint, passing any report to the keyboard
+    /// state and queueing a transfer for the next one.
+    fn handle_report_event(
+        &mut self,
+        event: &Trb,
+        registers: &Registers,
+        input: &mut VecDeque<u8>,
+    ) {
+        if let Err(e) = event.check_completion() {
+            warn!(
+                "USB keyboard on port {} interrupt transfer error: {e}",
+                self.device.port
+            );
+            return;
+        }
+        let length = usize::from(self.report_size).saturating_sub(event.residual_length());
+        let mut report = [0; BOOT_REPORT_SIZE];
+        let length = length.min(report.len());
+        fence(Ordering::SeqCst);
+        // SAFETY: The report buffer is within the data buffer, and the controller has finished
+        // writing to it.
+        unsafe {
+            self.device
+                .data
+                .as_ptr()
+                .add(REPORT_OFFSET)
+                .copy_to_nonoverlapping(report.as_mut_ptr(), length);
+        }
+        self.state
+            .handle_report(&report[..length], |bytes| input.extend(bytes));
+        self.queue_report_transfer(registers);
+    }
+}
+
+/// The bulk endpoints of a mass storage device attached to a root hub port.
+#[derive(Debug)]
+pub struct BulkEndpoints {
+    device: UsbDevice,
+    in_ring: Ring,
+    out_ring: Ring,
+    /// The device context index of the bulk IN endpoint.
+    in_dci: u8,
+    /// The device context index of the bulk OUT endpoint.
+    out_dci: u8,
+}
+
+impl BulkEndpoints {
+    /// Returns the root hub port the device is attached to.
+    pub fn port(&self) -> u8 {
+        self.device.port
+    }
+}
+
+/// A device found on a root hub port which we have a driver for.
+#[derive(Debug)]
+enum PortDevice {
+    Keyboard(Keyboard),
+    Storage(BulkEndpoints),
+}
+
+/// Driver for an XHCI controller and the keyboards attached to it.
+///
+/// Mass storage devices attached to the controller are driven separately, using `bulk_transfer`.
+#[derive(Debug)]
+pub struct Xhci {
+    registers: Registers,
+    /// The size of each device context data structure, either 32 or 64 bytes.
+    context_size: usize,
+    max_ports: u8,
+    /// The device context base address array, followed by the event ring segment table at
+    /// `ERST_OFFSET`.
+    contexts: DmaBuffer,
+    command_ring: Ring,
+    event_ring: EventRing,
+    keyboards: Vec<Keyboard>,
+    /// Input from keyboard reports which were handled while waiting for other events, and haven't
+    /// yet been passed on by `InputSource::poll`.
+    pending_input: VecDeque<u8>,
+    irq: Option<IntId>,
+}
+
+// SAFETY: The registers are only accessed through `&mut Xhci` or from the thread which owns it.
+unsafe impl Send for Xhci {}
+
+impl Xhci {
+    /// Resets and starts the controller with the given registers.
+    ///
+    /// # Safety
+    ///
+    /// `registers` must be the address of the controller's registers, which must be mapped as
+    /// device memory, and not be used anywhere else. `size` must be the size of the register
+    /// BAR.
+    pub unsafe fn new(registers: NonNull<u8>, size: usize) -> Result<Self, XhciError> {
+        let mut registers = Registers {
+            base: registers,
+            operational: 0,
+            runtime: 0,
+            doorbells: 0,
+        };
+        registers.operational = (registers.read32(CAP_CAPLENGTH) & 0xff) as usize;
+        registers.runtime = (registers.read32(CAP_RTSOFF) & !0x1f) as usize;
+        registers.doorbells = (registers.read32(CAP_DBOFF) & !0x3) as usize;
+        let hcsparams1 = registers.read32(CAP_HCSPARAMS1);
+        let max_slots = (hcsparams1 & 0xff) as u8;
+        let max_ports = (hcsparams1 >> 24) as u8;
+        if registers.operational + OP_PORTSC + usize::from(max_ports) * PORT_REGISTERS_SIZE > size
+            || registers.runtime + IR0_ERDP + size_of::<u64>() > size
+            || registers.doorbells + (usize::from(max_slots) + 1) * size_of::<u32>() > size
+        {
+            return Err(XhciError::NoRegisters);
+        }
+        let hcsparams2 = registers.read32(CAP_HCSPARAMS2);
+        let scratchpad_buffers = ((hcsparams2 >> 21) & 0x1f) << 5 | (hcsparams2 >> 27);
+        if scratchpad_buffers != 0 {
+            return Err(XhciError::ScratchpadUnsupported);
+        }
+        let context_size = if registers.read32(CAP_HCCPARAMS1) & HCCPARAMS1_CSZ != 0 {
+            64
+        } else {
+            32
+        };
+
+        // Stop and reset the controller.
+        registers.write_operational(
+            OP_USBCMD,
+            registers.read_operational(OP_USBCMD) & !USBCMD_RUN,
+        );
+        if !(0..POLL_LIMIT).any(|_| registers.read_operational(OP_USBSTS) & USBSTS_HCH != 0) {
+            return Err(XhciError::Timeout);
+        }
+        registers.write_operational(OP_USBCMD, USBCMD_HCRST);
+        registers.wait_clear(OP_USBCMD, USBCMD_HCRST)?;
+        registers.wait_clear(OP_USBSTS, USBSTS_CNR)?;
+
+        let xhci = Self {
+            registers,
+            context_size,
+            max_ports,
+            contexts: DmaBuffer::new(1, NO_ADDRESS_LIMIT)?,
+            command_ring: Ring::new()?,
+            event_ring: EventRing::new()?,
+            keyboards: Vec::new(),
+            pending_input: VecDeque::new(),
+            irq: None,
+        };
+        let registers = &xhci.registers;
+        registers.write_operational(OP_CONFIG, max_slots.min(MAX_SLOTS).into());
+        registers.write_operational64(OP_DCBAAP, xhci.contexts.paddr() as u64);
+        registers.write_operational64(OP_CRCR, xhci.command_ring.paddr() | CRCR_RCS);
+
+        // Set up the event ring segment table with a single segment.
+        let erst_paddr = (xhci.contexts.paddr() + ERST_OFFSET) as u64;
+        // SAFETY: The segment table entry is within the contexts buffer, and the controller isn't
+        // using it yet.
+        unsafe {
+            let erst = xhci.contexts.as_ptr().add(ERST_OFFSET);
+            erst.cast::<u64>()
+                .write_volatile(xhci.event_ring.buffer.paddr() as u64);
+            erst.add(8).cast::<u32>().write_volatile(RING_TRBS as u32);
+        }
+        fence(Ordering::SeqCst);
+        registers.write32(registers.runtime + IR0_ERSTSZ, 1);
+        registers.write64(
+            registers.runtime + IR0_ERDP,
+            xhci.event_ring.dequeue_pointer(),
+        );
+        registers.write64(registers.runtime + IR0_ERSTBA, erst_paddr);
+
+        registers.write_operational(OP_USBCMD, USBCMD_RUN);
+        registers.wait_clear(OP_USBSTS, USBSTS_HCH)?;
+        Ok(xhci)
+    }
+
+    /// Returns the next event from the controller which isn't a keyboard report, handling any
+    /// keyboard reports before it.
+    fn next_event(&mut self) -> Option<Trb> {
+        while let Some(event) = self.event_ring.next() {
+            self.update_dequeue_pointer();
+            if event.trb_type() == TRB_TRANSFER_EVENT
+                && let Some(keyboard) = self.keyboards.iter_mut().find(|keyboard| {
+                    keyboard.device.slot == event.slot_id()
+                        && keyboard.endpoint == event.endpoint_id()
+                })
+            {
+                keyboard.handle_report_event(&event, &self.registers, &mut self.pending_input);
+            } else {
+                return Some(event);
+            }
+        }
+        None
+    }
+
+    /// Waits for the next event of the given type from the controller, ignoring others such as
+    /// port status change events.
+    fn wait_for_event(&mut self, trb_type: u32) -> Result<Trb, XhciError> {
+        for _ in 0..POLL_LIMIT {
+            if let Some(event) = self.next_event()
+                && event.trb_type() == trb_type
+            {
+                return Ok(event);
+            }
+        }
+        Err(XhciError::Timeout)
+    }
+
+    /// Waits for the transfer event for the TRB at the given physical address on the given slot.
+    fn wait_for_transfer(&mut self, slot: u8, paddr: u64) -> Result<Trb, XhciError> {
+        loop {
+            let event = self.wait_for_event(TRB_TRANSFER_EVENT)?;
+            if event.slot_id() == slot && event.parameter == paddr {
+                event.check_completion()?;
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Tells the controller how far we have got through the event ring.
+    fn update_dequeue_pointer(&self) {
+        self.registers.write64(
+            self.registers.runtime + IR0_ERDP,
+            self.event_ring.dequeue_pointer() | ERDP_EHB,
+        );
+    }
+
+    /// Runs the given command and waits for it to complete, returning the completion event.
+    fn command(&mut self, trb: Trb) -> Result<Trb, XhciError> {
+        let paddr = self.command_ring.push(trb);
+        self.registers.ring_doorbell(0, 0);
+        loop {
+            let event = self.wait_for_event(TRB_COMMAND_COMPLETION)?;
+            if event.parameter == paddr {
+                event.check_completion()?;
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Runs a control transfer on the given device's default control endpoint, with any data
+    /// stage using the start of its data buffer.
+    fn control_transfer(
+        &mut self,
+        device: &mut UsbDevice,
+        setup: SetupPacket,
+    ) -> Result<(), XhciError> {
+        let transfer_type = match (setup.length, setup.is_device_to_host()) {
+            (0, _) => TRT_NO_DATA,
+            (_, true) => TRT_IN_DATA,
+            (_, false) => TRT_OUT_DATA,
+        };
+        let ring = &mut device.control_ring;
+        ring.push(Trb::new(
+            TRB_SETUP,
+            setup.to_u64(),
+            8,
+            TRB_IDT | transfer_type,
+        ));
+        let data_in = if setup.is_device_to_host() {
+            TRB_DIR_IN
+        } else {
+            0
+        };
+        if setup.length > 0 {
+            ring.push(Trb::new(
+                TRB_DATA,
+                device.data.paddr() as u64,
+                setup.length.into(),
+                data_in,
+            ));
+        }
+        // The status stage is in the opposite direction to the data stage, or IN if there is none.
+        let status_in = if setup.length > 0 && data_in != 0 {
+            0
+        } else {
+            TRB_DIR_IN
+        };
+        let paddr = ring.push(Trb::new(TRB_STATUS, 0, 0, TRB_IOC | status_in));
+        self.registers
+            .ring_doorbell(device.slot, CONTROL_ENDPOINT_DCI);
+        self.wait_for_transfer(device.slot, paddr)?;
+        Ok(())
+    }
+
+    /// Transfers up to `length` bytes to or from the given physical address on one of the given
+    /// bulk endpoints, and returns the number of bytes actually transferred.
+    ///
+    /// The buffer must not cross a 64 KiB boundary.
+    pub fn bulk_transfer(
+        &mut self,
+        endpoints: &mut BulkEndpoints,
+        data_in: bool,
+        paddr: usize,
+        length: usize,
+    ) -> Result<usize, XhciError> {
+        assert_eq!(paddr >> 16, (paddr + length.max(1) - 1) >> 16);
+        let (ring, dci) = if data_in {
+            (&mut endpoints.in_ring, endpoints.in_dci)
+        } else {
+            (&mut endpoints.out_ring, endpoints.out_dci)
+        };
+        let trb_paddr = ring.push(Trb::new(
+            TRB_NORMAL,
+            paddr as u64,
+            length as u32,
+            TRB_IOC | TRB_ISP,
+        ));
+        self.registers.ring_doorbell(endpoints.device.slot, dci);
+        let event = self.wait_for_transfer(endpoints.device.slot, trb_paddr)?;
+        Ok(length.saturating_sub(event.residual_length()))
+    }
+
+    /// Resets the given root hub port if a device is connected to it, and returns the device's
+    /// speed once the port is enabled.
+    fn reset_port(&self, port: u8) -> Option<u32> {
+        let portsc = self.registers.read_portsc(port);
+        if portsc & PORTSC_CCS == 0 {
+            return None;
+        }
+        // Writing 1 to PED or a change bit would clear it, so mask them out.
+        let preserved = portsc & !(PORTSC_PED | PORTSC_CHANGE_BITS);
+        if portsc & PORTSC_PED == 0 {
+            self.registers.write_portsc(port, preserved | PORTSC_PR);
+            if !(0..POLL_LIMIT).any(|_| self.registers.read_portsc(port) & PORTSC_PRC != 0) {
+                warn!("Timed out resetting USB port {port}");
+                return None;
+            }
+        }
+        let portsc = self.registers.read_portsc(port);
+        self.registers
+            .write_portsc(port, portsc & !PORTSC_PED | PORTSC_CHANGE_BITS & portsc);
+        if portsc & PORTSC_PED == 0 {
+            warn!("USB port {port} not enabled after reset");
+            return None;
+        }
+        Some((portsc >> 10) & 0xf)
+    }
+
+    /// Enumerates the device on the given port, and sets it up if it is a boot keyboard or mass
+    /// storage device.
+    fn init_port(&mut self, port: u8, speed: u32) -> Result<Option<PortDevice>, XhciError> {
+        let event = self.command(Trb::new(TRB_ENABLE_SLOT, 0, 0, 0))?;
+        let slot = event.slot_id();
+        let mut device = UsbDevice {
+            slot,
+            port,
+            speed,
+            input_context: DmaBuffer::new(1, NO_ADDRESS_LIMIT)?,
+            device_context: DmaBuffer::new(1, NO_ADDRESS_LIMIT)?,
+            control_ring: Ring::new()?,
+            data: DmaBuffer::new(1, NO_ADDRESS_LIMIT)?,
+        };
+        // SAFETY: The slot ID is less than `MAX_SLOTS`, so its entry is within the device context
+        // base address array, and the controller doesn't read it until the Address Device command.
+        unsafe {
+            self.contexts
+                .as_ptr()
+                .cast::<u64>()
+                .add(slot.into())
+                .write_volatile(device.device_context.paddr() as u64);
+        }
+
+        // Address the device, with a guess at the maximum packet size for the default control
+        // endpoint based on its speed.
+        let max_packet_size0 = match speed {
+            SPEED_LOW | SPEED_FULL => 8,
+            SPEED_HIGH => 64,
+            _ => 512,
+        };
+        let context_size = self.context_size;
+        device.reset_input_context(context_size, 0b11);
+        device.write_slot_context(context_size, 1);
+        let control_dequeue = device.control_ring.paddr();
+        device.write_endpoint_context(
+            context_size,
+            CONTROL_ENDPOINT_DCI,
+            ENDPOINT_TYPE_CONTROL,
+            max_packet_size0,
+            0,
+            control_dequeue,
+        );
+        fence(Ordering::SeqCst);
+        self.command(Trb::new(
+            TRB_ADDRESS_DEVICE,
+            device.input_context.paddr() as u64,
+            0,
+            u32::from(slot) << 24,
+        ))?;
+
+        // Check the real maximum packet size from the start of the device descriptor.
+        self.control_transfer(
+            &mut device,
+            SetupPacket::get_descriptor(DESCRIPTOR_DEVICE, 0, 8),
+        )?;
+        let mut descriptor = [0; DEVICE_DESCRIPTOR_SIZE];
+        device.read_data(&mut descriptor[..8]);
+        let partial =
+            DeviceDescriptor::parse(&descriptor[..8]).ok_or(XhciError::InvalidDescriptor)?;
+        let actual_max_packet_size0 = match partial.max_packet_size0 {
+            // USB 3 devices give the maximum packet size as a power of two.
+            9 if partial.usb_version >= 0x0300 => 512,
+            size => u16::from(size),
+        };
+        if actual_max_packet_size0 != max_packet_size0 {
+            device.reset_input_context(context_size, 0b10);
+            device.write_input_context(
+                context_size,
+                CONTROL_ENDPOINT_DCI + 1,
+                1,
+                u32::from(actual_max_packet_size0) << 16,
+            );
+            fence(Ordering::SeqCst);
+            self.command(Trb::new(
+                TRB_EVALUATE_CONTEXT,
+                device.input_context.paddr() as u64,
+                0,
+                u32::from(slot) << 24,
+            ))?;
+        }
+
+        self.control_transfer(
+            &mut device,
+            SetupPacket::get_descriptor(DESCRIPTOR_DEVICE, 0, DEVICE_DESCRIPTOR_SIZE as u16),
+        )?;
+        device.read_data(&mut descriptor);
+        let device_descriptor =
+            DeviceDescriptor::parse(&descriptor).ok_or(XhciError::InvalidDescriptor)?;
+        info!(
+            "USB device {:04x}:{:04x} on port {port}, speed {speed}",
+            device_descriptor.vendor_id, device_descriptor.product_id
+        );
+
+        // Read the configuration header to find its total length, then the whole configuration.
+        self.control_transfer(
+            &mut device,
+            SetupPacket::get_descriptor(
+                DESCRIPTOR_CONFIGURATION,
+                0,
+                CONFIGURATION_DESCRIPTOR_SIZE as u16,
+            ),
+        )?;
+        let mut configuration = [0; REPORT_OFFSET];
+        device.read_data(&mut configuration[..CONFIGURATION_DESCRIPTOR_SIZE]);
+        let total_length = usize::from(
+            configuration_total_length(&configuration).ok_or(XhciError::InvalidDescriptor)?,
+        )
+        .min(configuration.len());
+        self.control_transfer(
+            &mut device,
+            SetupPacket::get_descriptor(DESCRIPTOR_CONFIGURATION, 0, total_length as u16),
+        )?;
+        device.read_data(&mut configuration[..total_length]);
+        let configuration = &configuration[..total_length];
+
+        if let Some(interface) = find_boot_keyboard(configuration) {
+            Ok(Some(PortDevice::Keyboard(
+                self.configure_keyboard(device, &interface)?,
+            )))
+        } else if let Some(interface) = find_mass_storage(configuration) {
+            Ok(Some(PortDevice::Storage(
+                self.configure_storage(device, &interface)?,
+            )))
+        } else {
+            info!("Ignoring unsupported USB device on port {port}");
+            Ok(None)
+        }
+    }
+
+    /// Selects the configuration and boot protocol for the given keyboard interface, and
+    /// configures its interrupt endpoint.
+    fn configure_keyboard(
+        &mut self,
+        mut device: UsbDevice,
+        interface: &BootKeyboardInterface,
+    ) -> Result<Keyboard, XhciError> {
+        self.control_transfer(
+            &mut device,
+            SetupPacket::set_configuration(interface.configuration_value),
+        )?;
+        self.control_transfer(
+            &mut device,
+            SetupPacket::hid_set_boot_protocol(interface.interface),
+        )?;
+        self.control_transfer(&mut device, SetupPacket::hid_set_idle(interface.interface))?;
+
+        // IN endpoints have odd device context indices.
+        let dci = interface.endpoint * 2 + 1;
+        let interval = endpoint_interval(device.speed, interface.interval);
+        let interrupt_ring = Ring::new()?;
+        let context_size = self.context_size;
+        device.reset_input_context(context_size, 1 | 1 << dci);
+        device.write_slot_context(context_size, dci);
+        device.write_endpoint_context(
+            context_size,
+            dci,
+            ENDPOINT_TYPE_INTERRUPT_IN,
+            interface.max_packet_size,
+            interval,
+            interrupt_ring.paddr(),
+        );
+        fence(Ordering::SeqCst);
+        self.command(Trb::new(
+            TRB_CONFIGURE_ENDPOINT,
+            device.input_context.paddr() as u64,
+            0,
+            u32::from(device.slot) << 24,
+        ))?;
+        Ok(Keyboard {
+            device,
+            interrupt_ring,
+            endpoint: dci,
+            report_size: interface.max_packet_size.min(BOOT_REPORT_SIZE as u16),
+            state: BootKeyboard::new(),
+        })
+    }
+
+    /// Selects the configuration for the given mass storage interface, and configures its bulk
+    /// endpoints.
+    fn configure_storage(
+        &mut self,
+        mut device: UsbDevice,
+        interface: &MassStorageInterface,
+    ) -> Result<BulkEndpoints, XhciError> {
+        self.control_transfer(
+            &mut device,
+            SetupPacket::set_configuration(interface.configuration_value),
+        )?;
+
+        // IN endpoints have odd device context indices, and OUT endpoints even.
+        let in_dci = interface.bulk_in.endpoint * 2 + 1;
+        let out_dci = interface.bulk_out.endpoint * 2;
+        let in_ring = Ring::new()?;
+        let out_ring = Ring::new()?;
+        let context_size = self.context_size;
+        device.reset_input_context(context_size, 1 | 1 << in_dci | 1 << out_dci);
+        device.write_slot_context(context_size, in_dci.max(out_dci));
+        device.write_endpoint_context(
+            context_size,
+            in_dci,
+            ENDPOINT_TYPE_BULK_IN,
+            interface.bulk_in.max_packet_size,
+            0,
+            in_ring.paddr(),
+        );
+        device.write_endpoint_context(
+            context_size,
+            out_dci,
+            ENDPOINT_TYPE_BULK_OUT,
+            interface.bulk_out.max_packet_size,
+            0,
+            out_ring.paddr(),
+        );
+        fence(Ordering::SeqCst);
+        self.command(Trb::new(
+            TRB_CONFIGURE_ENDPOINT,
+            device.input_context.paddr() as u64,
+            0,
+            u32::from(device.slot) << 24,
+        ))?;
+        Ok(BulkEndpoints {
+            device,
+            in_ring,
+            out_ring,
+            in_dci,
+            out_dci,
+        })
+    }
+
+    /// Enumerates the devices on all root hub ports, and starts polling any keyboards found.
+    ///
+    /// Returns the endpoints of any mass storage devices found, to be driven separately.
+    fn init_ports(&mut self) -> Vec<BulkEndpoints> {
+        let mut storage = Vec::new();
+        for port in 1..=self.max_ports {
+            let Some(speed) = self.reset_port(port) else {
+                continue;
+            };
+            match self.init_port(port, speed) {
+                Ok(Some(PortDevice::Keyboard(keyboard))) => self.keyboards.push(keyboard),
+                Ok(Some(PortDevice::Storage(endpoints))) => storage.push(endpoints),
+                Ok(None) => {}
+                Err(e) => error!("Error initialising USB device on port {port}: {e}"),
+            }
+        }
+        for keyboard in &mut self.keyboards {
+            keyboard.queue_report_transfer(&self.registers);
+        }
+        storage
+    }
+
+    /// Enables the controller's interrupt, which must already have been configured in the GIC.
+    fn enable_irq(&mut self, intid: IntId) {
+        self.irq = Some(intid);
+        let registers = &self.registers;
+        registers.write32(registers.runtime + IR0_IMAN, IMAN_IE | IMAN_IP);
+        registers.write_operational(
+            OP_USBCMD,
+            registers.read_operational(OP_USBCMD) | USBCMD_INTE,
+        );
+    }
+}
+
+/// An XHCI controller shared between its keyboards, as an input source, and any mass storage
+/// devices attached to it.
+pub type SharedXhci = Arc<SpinMutex<Xhci>>;
+
+impl InputSource for SharedXhci {
+    fn poll(&mut self, input: &mut VecDeque<u8>) {
+        let mut xhci = self.lock();
+        // Any events which aren't keyboard reports are left over from transfers which timed out.
+        while xhci.next_event().is_some() {}
+        input.extend(xhci.pending_input.drain(..));
+        if let Some(intid) = xhci.irq
+            && take_device_irq(intid)
+        {
+            let registers = &xhci.registers;
+            registers.write_operational(OP_USBSTS, USBSTS_EINT);
+            registers.write32(registers.runtime + IR0_IMAN, IMAN_IE | IMAN_IP);
+            unmask_device_irq(intid);
+        }
+    }
+}
+
+/// Returns the value for the interval field of an interrupt endpoint context, given the port
+/// speed and the endpoint descriptor's `bInterval`.
+fn endpoint_interval(speed: u32, b_interval: u8) -> u8 {
+    match speed {
+        // Full and low speed intervals are in frames of 1 ms, but the context uses a power of two
+        // number of 125 µs microframes.
+        SPEED_FULL | SPEED_LOW => (u32::from(b_interval.max(1)) * 8).ilog2().clamp(3, 10) as u8,
+        // High and super speed intervals are already a power of two number of microframes.
+        _ => b_interval.clamp(1, 16) - 1,
+    }
+}
+
+/// Returns whether the given PCI function is an XHCI controller.
+fn is_xhci(info: &DeviceFunctionInfo) -> bool {
+    info.class == PCI_CLASS_SERIAL_BUS
+        && info.subclass == PCI_SUBCLASS_USB
+        && info.prog_if == PCI_PROG_IF_XHCI
+}
+
+/// Finds XHCI controllers on the given PCI root, and adds any keyboards attached to them as console
+/// input sources and any mass storage devices as block devices.
+pub fn find_xhci_devices(pci_root: &mut PciRootComplex, devices: &mut Devices) {
+    let controllers = pci_root
+        .unclaimed_devices()
+        .into_iter()
+        .filter(|(_, info)| is_xhci(info))
+        .map(|(device_function, _)| device_function)
+        .collect::<Vec<_>>();
+    for device_function in controllers {
+        info!("Found XHCI controller at {device_function}");
+        match init_xhci(pci_root, device_function) {
+            Ok((xhci, storage)) => {
+                let controller_id = match devices.register(
+                    DeviceKind::Usb,
+                    "xhci",
+                    format!("PCI {device_function}"),
+                    format!("{} ports", xhci.max_ports),
+                ) {
+                    Ok(id) => id,
+                    Err(e) => {
+                        error!("Error registering XHCI controller at {device_function}: {e}");
+                        continue;
+                    }
+                };
+                pci_root.claim(device_function);
+                register_keyboards(&xhci, controller_id, devices);
+                let xhci = Arc::new(SpinMutex::new(xhci));
+                #[cfg(not(feature = "block"))]
+                let _ = storage;
+                #[cfg(feature = "block")]
+                for endpoints in storage {
+                    let port = endpoints.port();
+                    match UsbStorage::new(xhci.clone(), endpoints) {
+                        Ok(storage) => {
+                            if let Err(e) = devices.add_block(
+                                Box::new(storage),
+                                format!("{controller_id} port {port}"),
+                            ) {
+                                error!("Error adding USB storage on port {port}: {e}");
+                            }
+                        }
+                        Err(e) => error!("Error initialising USB storage on port {port}: {e}"),
+                    }
+                }
+                add_source(Box::new(xhci));
+            }
+            Err(e) => error!("Error initialising XHCI controller at {device_function}: {e}"),
+        }
+    }
+}
+
+/// Adds registry entries for the keyboards attached to the given controller.
+fn register_keyboards(xhci: &Xhci, controller_id: DeviceId, devices: &mut Devices) {
+    for keyboard in &xhci.keyboards {
+        let port = keyboard.device.port;
+        if let Err(e) = devices.register(
+            DeviceKind::Input,
+            "usb-hid",
+            format!("{controller_id} port {port}"),
+            "USB boot keyboard".into(),
+        ) {
+            error!("Error registering USB keyboard on port {port}: {e}");
+        }
+    }
+}
+
+/// Initialises the controller at the given PCI function and the devices attached to it, returning
+/// the endpoints of any mass storage devices found.
+fn init_xhci(
+    pci_root: &mut PciRootComplex,
+    device_function: DeviceFunction,
+) -> Result<(Xhci, Vec<BulkEndpoints>), XhciError> {
+    let Ok([Some(BarInfo::Memory { address, size, .. }), ..]) = pci_root.bars(device_function)
+    else {
+        return Err(XhciError::NoRegisters);
+    };
+    if address == 0 {
+        return Err(XhciError::NoRegisters);
+    }
+    // SAFETY: BAR 0 was allocated and mapped as device memory when the PCI root was initialised,
+    // and this is the only driver for the device.
+    let mut xhci = unsafe { Xhci::new(NonNull::new(address as *mut u8).unwrap(), size as usize)? };
+    let storage = xhci.init_ports();
+    // Only legacy INTx interrupts are supported, not MSI.
+    if let Some(&(intid, trigger)) = pci_root.irqs.get(&device_function) {
+        setup_device_irq(intid, trigger);
+        xhci.enable_irq(intid);
+    }
+    Ok((xhci, storage))
+}
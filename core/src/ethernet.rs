@@ -0,0 +1,175 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Ethernet frame headers and MAC addresses.
+
+use core::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+/// The size of an Ethernet header without a VLAN tag.
+pub const ETHERNET_HEADER_SIZE: usize = 14;
+
+/// The minimum size of an Ethernet frame, excluding the frame check sequence.
+pub const MIN_FRAME_SIZE: usize = 60;
+
+/// The maximum size of an untagged Ethernet frame with a 1500 byte MTU, excluding the frame check
+/// sequence.
+pub const MAX_FRAME_SIZE: usize = 1514;
+
+/// EtherType reserved for local experimental use, for test frames.
+pub const ETHERTYPE_LOCAL_EXPERIMENTAL: u16 = 0x88b5;
+
+/// A 48-bit Ethernet MAC address.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct MacAddress(pub [u8; 6]);
+
+impl MacAddress {
+    /// The broadcast address.
+    pub const BROADCAST: Self = Self([0xff; 6]);
+
+    /// Returns whether this is a multicast (or broadcast) address.
+    pub fn is_multicast(&self) -> bool {
+        self.0[0] & 0x01 != 0
+    }
+
+    /// Returns whether this is all zeroes, which some devices report if they have no address
+    /// configured.
+    pub fn is_zero(&self) -> bool {
+        self.0 == [0; 6]
+    }
+}
+
+impl Display for MacAddress {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{g:02x}")
+    }
+}
+
+impl FromStr for MacAddress {
+    type Err = ParseMacError;
+
+    /// Parses six hexadecimal octets separated by colons, such as `52:54:00:12:34:56`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut octets = [0; 6];
+        let mut parts = s.split(':');
+        for octet in &mut octets {
+            *octet = parts
+                .next()
+                .filter(|part| part.len() == 2 && part.bytes().all(|byte| byte.is_ascii_hexdigit()))
+                .and_then(|part| u8::from_str_radix(part, 16).ok())
+                .ok_or(ParseMacError)?;
+        }
+        if parts.next().is_some() {
+            return Err(ParseMacError);
+        }
+        Ok(Self(octets))
+    }
+}
+
+/// An error parsing a MAC address.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ParseMacError;
+
+impl Display for ParseMacError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Invalid MAC address")
+    }
+}
+
+/// The header at the start of an Ethernet frame.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EthernetHeader {
+    pub destination: MacAddress,
+    pub source: MacAddress,
+    pub ethertype: u16,
+}
+
+impl EthernetHeader {
+    /// Parses the header from the start of the given frame, returning it along with the payload.
+    ///
+    /// Returns `None` if the frame is too short to contain a header.
+    pub fn parse(frame: &[u8]) -> Option<(Self, &[u8])> {
+        let (header, payload) = frame.split_first_chunk::<ETHERNET_HEADER_SIZE>()?;
+        Some((
+            Self {
+                destination: MacAddress(header[0..6].try_into().unwrap()),
+                source: MacAddress(header[6..12].try_into().unwrap()),
+                ethertype: u16::from_be_bytes([header[12], header[13]]),
+            },
+            payload,
+        ))
+    }
+
+    /// Returns the header in wire format.
+    pub fn to_bytes(&self) -> [u8; ETHERNET_HEADER_SIZE] {
+        let mut bytes = [0; ETHERNET_HEADER_SIZE];
+        bytes[0..6].copy_from_slice(&self.destination.0);
+        bytes[6..12].copy_from_slice(&self.source.0);
+        bytes[12..14].copy_from_slice(&self.ethertype.to_be_bytes());
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_mac_address() {
+        assert_eq!(
+            MacAddress([0x52, 0x54, 0x00, 0x12, 0x34, 0x0a]).to_string(),
+            "52:54:00:12:34:0a"
+        );
+        assert!(MacAddress::BROADCAST.is_multicast());
+        assert!(!MacAddress([0x52, 0x54, 0, 0, 0, 1]).is_multicast());
+        assert!(MacAddress::default().is_zero());
+    }
+
+    #[test]
+    fn parse_mac_address() {
+        assert_eq!(
+            "52:54:00:12:34:0A".parse(),
+            Ok(MacAddress([0x52, 0x54, 0x00, 0x12, 0x34, 0x0a]))
+        );
+        assert_eq!("52:54:00:12:34".parse::<MacAddress>(), Err(ParseMacError));
+        assert_eq!(
+            "52:54:00:12:34:56:78".parse::<MacAddress>(),
+            Err(ParseMacError)
+        );
+        assert_eq!("52:54:0:12:34:56".parse::<MacAddress>(), Err(ParseMacError));
+        assert_eq!(
+            "52:54:+0:12:34:56".parse::<MacAddress>(),
+            Err(ParseMacError)
+        );
+        assert_eq!(
+            "52:54:00:12:34:xx".parse::<MacAddress>(),
+            Err(ParseMacError)
+        );
+    }
+
+    #[test]
+    fn parse_header() {
+        let frame = [
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x52, 0x54, 0x00, 0x12, 0x34, 0x56, 0x08, 0x06,
+            0xaa, 0xbb,
+        ];
+        let (header, payload) = EthernetHeader::parse(&frame).unwrap();
+        assert_eq!(header.destination, MacAddress::BROADCAST);
+        assert_eq!(
+            header.source,
+            MacAddress([0x52, 0x54, 0x00, 0x12, 0x34, 0x56])
+        );
+        assert_eq!(header.ethertype, 0x0806);
+        assert_eq!(payload, &[0xaa, 0xbb]);
+        assert_eq!(header.to_bytes(), frame[..ETHERNET_HEADER_SIZE]);
+    }
+
+    #[test]
+    fn parse_short_frame() {
+        assert_eq!(EthernetHeader::parse(&[0; ETHERNET_HEADER_SIZE - 1]), None);
+    }
+}
@@ -0,0 +1,153 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A bus on which changes to devices are published, for subscribers such as the shell to react to.
+//!
+//! Recent events are kept in a bounded queue, and each subscriber keeps its own position in it, so
+//! a subscriber which falls too far behind misses the oldest events rather than blocking
+//! publishers.
+
+use crate::{device_id::DeviceId, executor::WakerSlot};
+use alloc::collections::vec_deque::VecDeque;
+use core::{
+    fmt::{self, Display, Formatter},
+    future::poll_fn,
+    task::{Context, Poll},
+};
+use log::info;
+use spin::mutex::SpinMutex;
+
+/// The maximum number of events kept for subscribers which haven't seen them yet.
+const MAX_QUEUED_EVENTS: usize = 64;
+
+static QUEUE: SpinMutex<EventQueue> = SpinMutex::new(EventQueue {
+    events: VecDeque::new(),
+    first_sequence: 0,
+});
+static EVENT_WAKER: WakerSlot = WakerSlot::new();
+
+/// A change to a device.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DeviceEvent {
+    /// The device was found and added to the registry.
+    Added(DeviceId),
+    /// The number of blocks on a block device changed.
+    CapacityChanged { id: DeviceId, blocks: u64 },
+    /// The size of a console changed.
+    ConsoleResized {
+        id: DeviceId,
+        columns: u16,
+        rows: u16,
+    },
+    /// A network interface's link went up or down.
+    LinkChanged { id: DeviceId, up: bool },
+}
+
+impl Display for DeviceEvent {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Added(id) => write!(f, "{id} added"),
+            Self::CapacityChanged { id, blocks } => {
+                write!(f, "{id} capacity changed to {blocks} blocks")
+            }
+            Self::ConsoleResized { id, columns, rows } => {
+                write!(f, "{id} resized to {columns}x{rows}")
+            }
+            Self::LinkChanged { id, up } => {
+                write!(f, "{id} link {}", if *up { "up" } else { "down" })
+            }
+        }
+    }
+}
+
+/// The recent events.
+struct EventQueue {
+    events: VecDeque<DeviceEvent>,
+    /// The sequence number of the first event in `events`.
+    first_sequence: u64,
+}
+
+impl EventQueue {
+    /// Returns the sequence number which the next event published will have.
+    fn next_sequence(&self) -> u64 {
+        self.first_sequence + self.events.len() as u64
+    }
+}
+
+/// Publishes the given event to all subscribers.
+pub fn publish(event: DeviceEvent) {
+    info!("Device event: {event}");
+    {
+        let mut queue = QUEUE.lock();
+        if queue.events.len() == MAX_QUEUED_EVENTS {
+            queue.events.pop_front();
+            queue.first_sequence += 1;
+        }
+        queue.events.push_back(event);
+    }
+    EVENT_WAKER.wake();
+}
+
+/// A subscriber to device events, which receives every event published after it was created.
+#[derive(Debug)]
+pub struct Subscriber {
+    /// The sequence number of the next event to return.
+    next_sequence: u64,
+    /// The number of events which were dropped from the queue before we received them.
+    missed: u64,
+}
+
+impl Subscriber {
+    /// Creates a new subscriber, which will receive events published from now on.
+    pub fn new() -> Self {
+        Self {
+            next_sequence: QUEUE.lock().next_sequence(),
+            missed: 0,
+        }
+    }
+
+    /// Returns the next event if there is one, without waiting.
+    pub fn try_next(&mut self) -> Option<DeviceEvent> {
+        let queue = QUEUE.lock();
+        if self.next_sequence < queue.first_sequence {
+            self.missed += queue.first_sequence - self.next_sequence;
+            self.next_sequence = queue.first_sequence;
+        }
+        let event = *queue
+            .events
+            .get((self.next_sequence - queue.first_sequence) as usize)?;
+        self.next_sequence += 1;
+        Some(event)
+    }
+
+    /// Returns the next event if there is one, or else registers to be woken when one is
+    /// published.
+    pub fn poll_next(&mut self, context: &mut Context) -> Poll<DeviceEvent> {
+        if let Some(event) = self.try_next() {
+            return Poll::Ready(event);
+        }
+        EVENT_WAKER.register(context.waker());
+        // Check again in case an event was published before the waker was registered.
+        match self.try_next() {
+            Some(event) => Poll::Ready(event),
+            None => Poll::Pending,
+        }
+    }
+
+    /// Completes with the next event.
+    pub async fn next(&mut self) -> DeviceEvent {
+        poll_fn(|context| self.poll_next(context)).await
+    }
+
+    /// Returns the number of events which were dropped before this subscriber received them.
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+}
+
+impl Default for Subscriber {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,104 @@
+// Copyright 2024 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Helpers for the exception level we are running at.
+//!
+//! The exception vector table itself is left to the binary, as it decides how to handle each
+//! exception.
+
+use arm_sysregs::{HcrEl2, read_currentel, read_hcr_el2, write_hcr_el2};
+use core::arch::asm;
+
+/// Returns the current exception level.
+pub fn current_el() -> u8 {
+    read_currentel().el()
+}
+
+/// Checks that we are running at an exception level which we support.
+///
+/// Panics if not, as the rest of the system assumes it is either at EL1 or EL2.
+pub fn check_el() {
+    let el = current_el();
+    assert!(
+        matches!(el, 1 | 2),
+        "Unsupported exception level EL{el}, must be started at EL1 or EL2"
+    );
+}
+
+/// Returns the current value of `HCR_EL2`, or `None` if we are not running at EL2 so can't read
+/// it.
+pub fn hcr_el2() -> Option<HcrEl2> {
+    if current_el() == 2 {
+        Some(read_hcr_el2())
+    } else {
+        None
+    }
+}
+
+/// The E2H bit of `HCR_EL2`, which enables the Virtualization Host Extensions.
+const HCR_EL2_E2H: u64 = 1 << 34;
+
+/// The SRE bit of `ICC_SRE_EL2`, which enables the GICv3 system register interface at EL2.
+const ICC_SRE_EL2_SRE: u64 = 1 << 0;
+/// The Enable bit of `ICC_SRE_EL2`, which allows EL1 to use the GICv3 system register interface.
+const ICC_SRE_EL2_ENABLE: u64 = 1 << 3;
+
+/// The shift of the GIC field of `ID_AA64PFR0_EL1`.
+const ID_AA64PFR0_EL1_GIC_SHIFT: u64 = 24;
+/// The mask of the GIC field of `ID_AA64PFR0_EL1`, after shifting.
+const ID_AA64PFR0_EL1_GIC_MASK: u64 = 0xf;
+
+/// Returns whether we are running at EL2 with the Virtualization Host Extensions enabled, in which
+/// case accesses to EL1 timer registers are redirected to the EL2 timers.
+pub fn vhe_enabled() -> bool {
+    hcr_el2().is_some_and(|hcr| hcr.bits() & HCR_EL2_E2H != 0)
+}
+
+/// Returns whether the core implements the GICv3 system register interface.
+fn gicv3_sysregs_implemented() -> bool {
+    let id_aa64pfr0: u64;
+    // SAFETY: Reading an ID register has no side effects.
+    unsafe {
+        asm!("mrs {}, id_aa64pfr0_el1", out(reg) id_aa64pfr0, options(nomem, nostack));
+    }
+    (id_aa64pfr0 >> ID_AA64PFR0_EL1_GIC_SHIFT) & ID_AA64PFR0_EL1_GIC_MASK != 0
+}
+
+/// Configures the system registers of the current core which depend on the exception level we are
+/// running at.
+///
+/// At EL2 this routes physical IRQs to EL2, enables the GICv3 system register interface at EL2 if
+/// the core has one, and sets the virtual counter offset to 0 so that the virtual counter used for
+/// timekeeping matches the physical counter. At EL1 there is nothing to do, as IRQs not routed to a
+/// higher exception level are taken to EL1, and the rest is up to the hypervisor.
+pub fn init_current_el() {
+    if current_el() != 2 {
+        return;
+    }
+    // SAFETY: We only set the IMO bit, which is safe.
+    unsafe {
+        // Route Physical IRQs to EL2.
+        write_hcr_el2(read_hcr_el2() | HcrEl2::IMO);
+    }
+    if gicv3_sysregs_implemented() {
+        // SAFETY: Enabling the system register interface only changes how the GIC CPU interface
+        // is accessed, and the GIC driver only uses system registers for a GICv3.
+        unsafe {
+            asm!(
+                "mrs {sre}, icc_sre_el2",
+                "orr {sre}, {sre}, {bits}",
+                "msr icc_sre_el2, {sre}",
+                "isb",
+                sre = out(reg) _,
+                bits = in(reg) ICC_SRE_EL2_SRE | ICC_SRE_EL2_ENABLE,
+                options(nomem, nostack),
+            );
+        }
+    }
+    // SAFETY: Every core uses an offset of 0, so this keeps the virtual counter the same on all
+    // cores, including one which lost its offset when it was powered down.
+    unsafe {
+        asm!("msr cntvoff_el2, xzr", "isb", options(nomem, nostack));
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A minimal single-threaded async executor, which sleeps with `wfi` until an interrupt handler
+//! wakes it.
+//!
+//! There are no tasks or task queues: `block_on` runs a single future to completion on the current
+//! CPU, and any concurrency within it comes from combinators such as `select`.
+//! Background work which isn't tied to any future can be done whenever `block_on` is idle, with
+//! `set_idle_hook`.
+
+use crate::idle::wfi;
+use core::{
+    future::poll_fn,
+    pin::pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+};
+use percore::{ExceptionLock, exception_free};
+use spin::mutex::SpinMutex;
+
+/// Set by the waker whenever a future asks to be polled again.
+static WOKEN: AtomicBool = AtomicBool::new(false);
+
+/// The function called to do background work while `block_on` is idle, if any.
+static IDLE_HOOK: SpinMutex<Option<IdleHook>> = SpinMutex::new(None);
+
+/// A function which does a small amount of background work, and returns whether it has more to do
+/// straight away.
+pub type IdleHook = fn() -> bool;
+
+static WAKER_VTABLE: RawWakerVTable = RawWakerVTable::new(clone_waker, wake, wake, drop_waker);
+
+fn clone_waker(_: *const ()) -> RawWaker {
+    RawWaker::new(core::ptr::null(), &WAKER_VTABLE)
+}
+
+fn wake(_: *const ()) {
+    WOKEN.store(true, Ordering::Release);
+}
+
+fn drop_waker(_: *const ()) {}
+
+/// Runs the given future to completion, sleeping until the next interrupt whenever it isn't ready.
+///
+/// The future is polled again after every interrupt, not only when it is woken, so futures may
+/// also poll devices which can't wake them.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    // SAFETY: The vtable functions ignore the data pointer, and are all safe to call from any
+    // thread.
+    let waker = unsafe { Waker::from_raw(clone_waker(core::ptr::null())) };
+    let mut context = Context::from_waker(&waker);
+    loop {
+        WOKEN.store(false, Ordering::Release);
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+        run_idle_hook();
+        // Check whether we were woken with exceptions masked, so that an interrupt between the
+        // check and the `wfi` still wakes us. Its handler then runs once exceptions are unmasked.
+        exception_free(|_| {
+            if !WOKEN.load(Ordering::Acquire) {
+                wfi();
+            }
+        });
+    }
+}
+
+/// Sets the function to be called to do background work whenever `block_on` has nothing else to
+/// do, or stops calling one if `None`.
+pub fn set_idle_hook(hook: Option<IdleHook>) {
+    *IDLE_HOOK.lock() = hook;
+}
+
+/// Calls the idle hook, if any, until it has no more work to do or the future is woken.
+///
+/// Exceptions are left unmasked so that interrupts are still handled promptly, so the hook should
+/// only do a little work each time it is called.
+fn run_idle_hook() {
+    // Copy the hook out so that it isn't called with the lock held.
+    let hook = *IDLE_HOOK.lock();
+    if let Some(hook) = hook {
+        while !WOKEN.load(Ordering::Acquire) && hook() {}
+    }
+}
+
+/// Returns `Pending` once, asking to be polled again straight away.
+///
+/// This lets futures which must busy-poll a device give others a chance to run.
+pub async fn yield_now() {
+    let mut yielded = false;
+    poll_fn(|context| {
+        if yielded {
+            Poll::Ready(())
+        } else {
+            yielded = true;
+            context.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// The output of whichever future passed to `select` completed first.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Either<A, B> {
+    Left(A),
+    Right(B),
+}
+
+/// Polls both futures until one of them completes, then drops the other.
+///
+/// If both are ready at once then `a` wins.
+pub async fn select<A: Future, B: Future>(a: A, b: B) -> Either<A::Output, B::Output> {
+    let mut a = pin!(a);
+    let mut b = pin!(b);
+    poll_fn(|context| {
+        if let Poll::Ready(output) = a.as_mut().poll(context) {
+            Poll::Ready(Either::Left(output))
+        } else if let Poll::Ready(output) = b.as_mut().poll(context) {
+            Poll::Ready(Either::Right(output))
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// A place for a future to register its waker, so that an interrupt handler can wake it.
+pub struct WakerSlot {
+    waker: ExceptionLock<SpinMutex<Option<Waker>>>,
+}
+
+impl Default for WakerSlot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WakerSlot {
+    /// Creates a new slot with no waker registered.
+    pub const fn new() -> Self {
+        Self {
+            waker: ExceptionLock::new(SpinMutex::new(None)),
+        }
+    }
+
+    /// Registers the given waker to be woken by the next call to `wake`, replacing any previous
+    /// one.
+    pub fn register(&self, waker: &Waker) {
+        exception_free(|token| {
+            let mut slot = self.waker.borrow(token).lock();
+            if !slot.as_ref().is_some_and(|old| old.will_wake(waker)) {
+                *slot = Some(waker.clone());
+            }
+        });
+    }
+
+    /// Wakes the registered waker, if any.
+    pub fn wake(&self) {
+        if let Some(waker) = exception_free(|token| self.waker.borrow(token).lock().take()) {
+            waker.wake();
+        }
+    }
+}
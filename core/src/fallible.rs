@@ -0,0 +1,137 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Fallible allocation, so that running out of memory at runtime can be reported as an error
+//! rather than aborting via `handle_alloc_error`.
+//!
+//! These should be used for allocations whose size depends on user input or on devices found at
+//! runtime. Small fixed-size allocations, such as for formatting messages, may still abort if the
+//! heap is completely exhausted.
+
+use alloc::{alloc::alloc, boxed::Box, vec::Vec};
+use core::{
+    alloc::Layout,
+    fmt::{self, Display, Formatter},
+};
+
+/// An allocation failed because there wasn't enough free memory.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct OutOfMemory {
+    /// The size of the allocation which failed, or `usize::MAX` if it was too large to represent.
+    pub bytes: usize,
+}
+
+impl Display for OutOfMemory {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.bytes == usize::MAX {
+            write!(f, "Out of memory")
+        } else {
+            write!(f, "Out of memory allocating {} bytes", self.bytes)
+        }
+    }
+}
+
+impl OutOfMemory {
+    /// Returns the error for failing to allocate `count` values of type `T`.
+    fn for_array<T>(count: usize) -> Self {
+        Self {
+            bytes: count.checked_mul(size_of::<T>()).unwrap_or(usize::MAX),
+        }
+    }
+}
+
+/// Moves the given value into a new box, or returns an error if there isn't enough memory.
+pub fn try_alloc<T>(value: T) -> Result<Box<T>, OutOfMemory> {
+    let layout = Layout::new::<T>();
+    if layout.size() == 0 {
+        // Boxes of zero-sized types don't allocate.
+        return Ok(Box::new(value));
+    }
+    // SAFETY: The layout has a non-zero size.
+    let pointer = unsafe { alloc(layout) }.cast::<T>();
+    if pointer.is_null() {
+        return Err(OutOfMemory {
+            bytes: layout.size(),
+        });
+    }
+    // SAFETY: The pointer was just allocated by the global allocator with the layout of `T`, so
+    // it is valid to write a `T` to it and then for the box to take ownership of it.
+    unsafe {
+        pointer.write(value);
+        Ok(Box::from_raw(pointer))
+    }
+}
+
+/// Returns a vector of `len` copies of `value`, or an error if there isn't enough memory.
+pub fn try_vec<T: Clone>(value: T, len: usize) -> Result<Vec<T>, OutOfMemory> {
+    let mut vec = Vec::new();
+    vec.try_reserve_exact(len)
+        .map_err(|_| OutOfMemory::for_array::<T>(len))?;
+    vec.resize(len, value);
+    Ok(vec)
+}
+
+/// Reserves space for at least `additional` more elements in the given vector, or returns an error
+/// if there isn't enough memory.
+pub fn try_reserve<T>(vec: &mut Vec<T>, additional: usize) -> Result<(), OutOfMemory> {
+    vec.try_reserve(additional)
+        .map_err(|_| OutOfMemory::for_array::<T>(vec.len().saturating_add(additional)))
+}
+
+/// Appends the given value to the vector, or returns an error if there isn't enough memory to grow
+/// it.
+pub fn try_push<T>(vec: &mut Vec<T>, value: T) -> Result<(), OutOfMemory> {
+    try_reserve(vec, 1)?;
+    vec.push(value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocate() {
+        assert_eq!(*try_alloc(42u64).unwrap(), 42);
+        assert_eq!(*try_alloc(()).unwrap(), ());
+        assert_eq!(try_vec(7u8, 3).unwrap(), [7, 7, 7]);
+
+        let mut vec = Vec::new();
+        try_push(&mut vec, 1).unwrap();
+        try_push(&mut vec, 2).unwrap();
+        assert_eq!(vec, [1, 2]);
+    }
+
+    #[test]
+    fn too_large() {
+        assert_eq!(
+            try_vec(0u32, usize::MAX / 2),
+            Err(OutOfMemory { bytes: usize::MAX })
+        );
+        assert_eq!(
+            try_vec(0u8, isize::MAX as usize + 1),
+            Err(OutOfMemory {
+                bytes: isize::MAX as usize + 1
+            })
+        );
+        let mut vec = vec![0u8];
+        assert_eq!(
+            try_reserve(&mut vec, usize::MAX),
+            Err(OutOfMemory { bytes: usize::MAX })
+        );
+        assert_eq!(vec, [0]);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            OutOfMemory { bytes: 4096 }.to_string(),
+            "Out of memory allocating 4096 bytes"
+        );
+        assert_eq!(
+            OutOfMemory { bytes: usize::MAX }.to_string(),
+            "Out of memory"
+        );
+    }
+}
@@ -0,0 +1,777 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Helpers for working with device tree nodes, and typed access to their properties.
+
+use aarch64_paging::paging::MemoryRegion;
+use alloc::vec::Vec;
+use arm_gic::{IntId, Trigger};
+use core::{
+    fmt::{self, Display, Formatter},
+    str,
+};
+use dtoolkit::{
+    Node, Property,
+    fdt::{Fdt, FdtNode},
+    standard::{NodeStandard, Reg},
+};
+use log::warn;
+
+/// The first cell of a GIC interrupt specifier for a shared peripheral interrupt.
+const GIC_SPI: u32 = 0;
+/// The first cell of a GIC interrupt specifier for a private peripheral interrupt.
+const GIC_PPI: u32 = 1;
+
+/// The `#address-cells` of a node which doesn't have the property.
+const DEFAULT_ADDRESS_CELLS: usize = 2;
+/// The `#size-cells` of a node which doesn't have the property.
+const DEFAULT_SIZE_CELLS: usize = 1;
+
+/// Converts a `reg` entry from the device tree to a memory region.
+pub fn fdt_to_pagetable_region(region: &Reg) -> MemoryRegion {
+    let address = region.address::<u64>().unwrap();
+    let size = region.size::<u64>().unwrap();
+    MemoryRegion::new(address as _, (address + size) as usize)
+}
+
+/// Returns whether the given node is compatible with any of the given compatible strings.
+pub fn is_compatible(node: &FdtNode, with: &[&str]) -> bool {
+    if let Some(mut compatible) = node.compatible() {
+        compatible.any(|c| with.contains(&c))
+    } else {
+        false
+    }
+}
+
+/// Returns whether the given node or any of its descendants is compatible with any of the given
+/// compatible strings.
+pub fn has_compatible_node(node: &FdtNode, with: &[&str]) -> bool {
+    is_compatible(node, with)
+        || node
+            .children()
+            .any(|child| has_compatible_node(&child, with))
+}
+
+/// Returns the value of the given property as a list of big-endian cells, or `None` if it is
+/// missing or not a whole number of cells.
+pub fn property_cells(node: &FdtNode, name: &str) -> Option<Vec<u32>> {
+    let property = node.property(name)?;
+    let value = property.value();
+    if !value.len().is_multiple_of(4) {
+        return None;
+    }
+    Some(
+        value
+            .chunks_exact(4)
+            .map(|cell| u32::from_be_bytes(cell.try_into().unwrap()))
+            .collect(),
+    )
+}
+
+/// Returns the single-cell value of the given property, or `None` if it is missing or invalid.
+pub fn property_u32(node: &FdtNode, name: &str) -> Option<u32> {
+    node.property(name)?.as_u32().ok()
+}
+
+/// Returns the value of the given property as a list of 64-bit values, each made of two big-endian
+/// cells, or `None` if it is missing or not a whole number of them.
+pub fn property_u64s(node: &FdtNode, name: &str) -> Option<Vec<u64>> {
+    let cells = property_cells(node, name)?;
+    if !cells.len().is_multiple_of(2) {
+        return None;
+    }
+    Some(
+        cells
+            .chunks_exact(2)
+            .map(|pair| (u64::from(pair[0]) << 32) | u64::from(pair[1]))
+            .collect(),
+    )
+}
+
+/// Returns the strings of the given string list property, such as `compatible`, or `None` if it is
+/// missing or not a list of NUL-terminated UTF-8 strings.
+pub fn property_strings<'a>(node: &FdtNode<'a>, name: &str) -> Option<Vec<&'a str>> {
+    parse_strings(node.property(name)?.value())
+}
+
+/// Splits a string list property value into its strings.
+fn parse_strings(value: &[u8]) -> Option<Vec<&str>> {
+    let value = value.strip_suffix(&[0])?;
+    value
+        .split(|&byte| byte == 0)
+        .map(|string| str::from_utf8(string).ok())
+        .collect()
+}
+
+/// Returns the `#address-cells` and `#size-cells` of the given node, which give the sizes of the
+/// addresses and sizes in its children's `reg` properties and its own `ranges`.
+pub fn child_cells(node: &FdtNode) -> (usize, usize) {
+    (
+        property_u32(node, "#address-cells").map_or(DEFAULT_ADDRESS_CELLS, |cells| cells as usize),
+        property_u32(node, "#size-cells").map_or(DEFAULT_SIZE_CELLS, |cells| cells as usize),
+    )
+}
+
+/// Combines the given big-endian cells into a single value, or returns `None` if there are too many
+/// to fit.
+pub fn cells_to_u128(cells: &[u32]) -> Option<u128> {
+    if cells.len() > 4 {
+        return None;
+    }
+    Some(
+        cells
+            .iter()
+            .fold(0, |value, &cell| (value << 32) | u128::from(cell)),
+    )
+}
+
+/// An entry in a `reg` property: a region in the parent node's address space.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RegEntry {
+    pub address: u64,
+    pub size: u64,
+}
+
+impl Display for RegEntry {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "address {:#x} size {:#x}", self.address, self.size)
+    }
+}
+
+/// Returns the entries of the given node's `reg` property, or `None` if it is missing or invalid.
+pub fn reg_entries(node: &FdtNode) -> Option<Vec<RegEntry>> {
+    node.reg()
+        .ok()??
+        .map(|reg| {
+            Some(RegEntry {
+                address: reg.address::<u64>().ok()?,
+                size: reg.size::<u64>().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// An entry in a `ranges` property, mapping part of a node's address space to its parent's.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RangeEntry {
+    /// The address in the node's address space, which may have up to four cells such as for PCI.
+    pub child_address: u128,
+    pub parent_address: u64,
+    pub size: u64,
+}
+
+impl Display for RangeEntry {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "child {:#x} parent {:#x} size {:#x}",
+            self.child_address, self.parent_address, self.size
+        )
+    }
+}
+
+/// Decodes the cells of a `ranges` property, given the node's `#address-cells` and `#size-cells`
+/// and its parent's `#address-cells`.
+///
+/// Returns `None` if the property isn't a whole number of entries or a value is too large.
+pub fn decode_ranges(
+    cells: &[u32],
+    child_address_cells: usize,
+    parent_address_cells: usize,
+    size_cells: usize,
+) -> Option<Vec<RangeEntry>> {
+    let entry_cells = child_address_cells + parent_address_cells + size_cells;
+    if entry_cells == 0 || !cells.len().is_multiple_of(entry_cells) {
+        return None;
+    }
+    cells
+        .chunks_exact(entry_cells)
+        .map(|entry| {
+            let (child_address, rest) = entry.split_at(child_address_cells);
+            let (parent_address, size) = rest.split_at(parent_address_cells);
+            Some(RangeEntry {
+                child_address: cells_to_u128(child_address)?,
+                parent_address: cells_to_u128(parent_address)?.try_into().ok()?,
+                size: cells_to_u128(size)?.try_into().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// A property value, interpreted according to its contents as `dtc` does when decompiling.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PropertyValue<'a> {
+    /// An empty value, for a boolean property which is true.
+    Empty,
+    /// A list of printable strings.
+    Strings(Vec<&'a str>),
+    /// A list of big-endian cells.
+    Cells(Vec<u32>),
+    /// Anything else.
+    Bytes(&'a [u8]),
+}
+
+impl<'a> PropertyValue<'a> {
+    /// Interprets the given raw property value.
+    pub fn parse(value: &'a [u8]) -> Self {
+        if value.is_empty() {
+            return Self::Empty;
+        }
+        if let Some(strings) = parse_strings(value)
+            && strings.iter().all(|string| {
+                !string.is_empty() && string.chars().all(|c| !c.is_control() && c != '"')
+            })
+        {
+            return Self::Strings(strings);
+        }
+        if value.len().is_multiple_of(4) {
+            Self::Cells(
+                value
+                    .chunks_exact(4)
+                    .map(|cell| u32::from_be_bytes(cell.try_into().unwrap()))
+                    .collect(),
+            )
+        } else {
+            Self::Bytes(value)
+        }
+    }
+}
+
+impl Display for PropertyValue<'_> {
+    /// Formats the value in device tree source syntax.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Empty => Ok(()),
+            Self::Strings(strings) => {
+                for (index, string) in strings.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "\"{string}\"")?;
+                }
+                Ok(())
+            }
+            Self::Cells(cells) => {
+                write!(f, "<")?;
+                for (index, cell) in cells.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{cell:#x}")?;
+                }
+                write!(f, ">")
+            }
+            Self::Bytes(bytes) => {
+                write!(f, "[")?;
+                for (index, byte) in bytes.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{byte:02x}")?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+/// Returns the kernel command line from the `/chosen` node, or `None` if there isn't one or it isn't
+/// valid UTF-8.
+pub fn bootargs<'a>(fdt: &Fdt<'a>) -> Option<&'a str> {
+    let bootargs = fdt.find_node("/chosen")?.property("bootargs")?;
+    let Ok(bootargs) = str::from_utf8(bootargs.value()) else {
+        warn!("bootargs aren't valid UTF-8");
+        return None;
+    };
+    Some(bootargs.trim_end_matches('\0'))
+}
+
+/// Returns the node which the `stdout-path` property of the `/chosen` node refers to, either by
+/// path or by alias, ignoring any options after a `:`.
+pub fn stdout_node<'a>(fdt: &Fdt<'a>) -> Option<FdtNode<'a>> {
+    let stdout_path = string_property(&fdt.find_node("/chosen")?, "stdout-path")?;
+    let path = stdout_path.split(':').next()?;
+    if path.starts_with('/') {
+        fdt.find_node(path)
+    } else {
+        fdt.find_node(string_property(&fdt.find_node("/aliases")?, path)?)
+    }
+}
+
+/// Returns the value of the given single string property, or `None` if it is missing or not valid
+/// UTF-8.
+fn string_property<'a>(node: &FdtNode<'a>, name: &str) -> Option<&'a str> {
+    let value = str::from_utf8(node.property(name)?.value()).ok()?;
+    Some(value.trim_end_matches('\0'))
+}
+
+/// Finds the first node under the given node, or the node itself, which matches the given
+/// predicate.
+pub fn find_matching_node<'a>(
+    node: FdtNode<'a>,
+    predicate: &impl Fn(&FdtNode) -> bool,
+) -> Option<FdtNode<'a>> {
+    if predicate(&node) {
+        return Some(node);
+    }
+    node.children()
+        .find_map(|child| find_matching_node(child, predicate))
+}
+
+/// The MMIO address and interrupt of a simple device such as a UART, from its device tree node.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MmioDevice {
+    /// The address of the first entry in the `reg` property.
+    pub address: u64,
+    /// The first entry in the `interrupts` property, if it is a GIC SPI or PPI.
+    pub interrupt: Option<(IntId, Trigger)>,
+}
+
+impl MmioDevice {
+    /// Reads the address and interrupt of the device which the given node describes, or returns
+    /// `None` if it has no `reg` property.
+    ///
+    /// This doesn't allocate, so can be used before the heap is set up.
+    pub fn from_node(node: &FdtNode) -> Option<Self> {
+        let address = node.reg().ok()??.next()?.address::<u64>().ok()?;
+        let interrupt = node.property("interrupts").and_then(|interrupts| {
+            let mut specifier = [0; 3];
+            let bytes = interrupts.value().get(..size_of_val(&specifier))?;
+            for (cell, bytes) in specifier.iter_mut().zip(bytes.chunks_exact(4)) {
+                *cell = u32::from_be_bytes(bytes.try_into().unwrap());
+            }
+            gic_interrupt(&specifier)
+        });
+        Some(Self { address, interrupt })
+    }
+}
+
+/// Returns whether the given kernel command line contains the given argument on its own, without a
+/// value.
+pub fn has_bootarg_flag(bootargs: &str, name: &str) -> bool {
+    bootargs.split_whitespace().any(|arg| arg == name)
+}
+
+/// Returns the value of the given `name=value` argument in the given kernel command line, if there
+/// is one. If it is given more than once then the last one wins.
+pub fn bootarg_value<'a>(bootargs: &'a str, name: &str) -> Option<&'a str> {
+    bootargs
+        .split_whitespace()
+        .filter_map(|arg| arg.split_once('='))
+        .filter(|(arg_name, _)| *arg_name == name)
+        .map(|(_, value)| value)
+        .last()
+}
+
+/// Finds the node under the given node, or the node itself, with the given phandle.
+pub fn find_phandle<'a>(node: FdtNode<'a>, phandle: u32) -> Option<FdtNode<'a>> {
+    if node.phandle().ok().flatten() == Some(phandle) {
+        return Some(node);
+    }
+    node.children()
+        .find_map(|child| find_phandle(child, phandle))
+}
+
+/// Parses a 3-cell GIC interrupt specifier, as used in `interrupts` properties and interrupt maps.
+///
+/// Returns `None` if the specifier is the wrong length or not an SPI or PPI.
+pub fn gic_interrupt(specifier: &[u32]) -> Option<(IntId, Trigger)> {
+    let &[kind, number, flags] = specifier else {
+        return None;
+    };
+    let intid = match kind {
+        GIC_SPI => IntId::spi(number),
+        GIC_PPI => IntId::ppi(number),
+        _ => return None,
+    };
+    // Bits 0 and 1 are for rising and falling edges, bits 2 and 3 for high and low levels.
+    let trigger = if flags & 0b0011 != 0 {
+        Trigger::Edge
+    } else {
+        Trigger::Level
+    };
+    Some((intid, trigger))
+}
+
+/// Helpers for tests which need a device tree.
+#[cfg(test)]
+pub(crate) mod test_util {
+    use alloc::vec::Vec;
+
+    const FDT_BEGIN_NODE: u32 = 1;
+    const FDT_END_NODE: u32 = 2;
+    const FDT_PROP: u32 = 3;
+    const FDT_END: u32 = 9;
+
+    /// Builds a minimal flattened device tree blob.
+    #[derive(Default)]
+    pub(crate) struct FdtBuilder {
+        structure: Vec<u8>,
+        strings: Vec<u8>,
+    }
+
+    impl FdtBuilder {
+        fn token(&mut self, token: u32) {
+            self.structure.extend_from_slice(&token.to_be_bytes());
+        }
+
+        fn pad(&mut self) {
+            while !self.structure.len().is_multiple_of(4) {
+                self.structure.push(0);
+            }
+        }
+
+        pub(crate) fn begin_node(&mut self, name: &str) -> &mut Self {
+            self.token(FDT_BEGIN_NODE);
+            self.structure.extend_from_slice(name.as_bytes());
+            self.structure.push(0);
+            self.pad();
+            self
+        }
+
+        pub(crate) fn end_node(&mut self) -> &mut Self {
+            self.token(FDT_END_NODE);
+            self
+        }
+
+        pub(crate) fn property(&mut self, name: &str, value: &[u8]) -> &mut Self {
+            let name_offset = self.strings.len() as u32;
+            self.strings.extend_from_slice(name.as_bytes());
+            self.strings.push(0);
+            self.token(FDT_PROP);
+            self.token(value.len() as u32);
+            self.token(name_offset);
+            self.structure.extend_from_slice(value);
+            self.pad();
+            self
+        }
+
+        pub(crate) fn cells(&mut self, name: &str, cells: &[u32]) -> &mut Self {
+            let value = cells
+                .iter()
+                .flat_map(|cell| cell.to_be_bytes())
+                .collect::<Vec<_>>();
+            self.property(name, &value)
+        }
+
+        pub(crate) fn build(mut self) -> Vec<u8> {
+            self.token(FDT_END);
+            const HEADER_SIZE: usize = 40;
+            const MEMORY_RESERVATION_SIZE: usize = 16;
+            let structure_offset = HEADER_SIZE + MEMORY_RESERVATION_SIZE;
+            let strings_offset = structure_offset + self.structure.len();
+            let total_size = strings_offset + self.strings.len();
+            let header = [
+                0xd00d_feed,
+                total_size as u32,
+                structure_offset as u32,
+                strings_offset as u32,
+                HEADER_SIZE as u32,
+                17,
+                16,
+                0,
+                self.strings.len() as u32,
+                self.structure.len() as u32,
+            ];
+            let mut blob = header
+                .iter()
+                .flat_map(|value| value.to_be_bytes())
+                .collect::<Vec<_>>();
+            blob.extend_from_slice(&[0; MEMORY_RESERVATION_SIZE]);
+            blob.extend_from_slice(&self.structure);
+            blob.extend_from_slice(&self.strings);
+            blob
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dtoolkit::fdt::Fdt;
+    use test_util::FdtBuilder;
+
+    fn test_fdt() -> Vec<u8> {
+        let mut builder = FdtBuilder::default();
+        builder
+            .begin_node("")
+            .cells("#address-cells", &[2])
+            .cells("#size-cells", &[2])
+            .begin_node("pl011@9000000")
+            .property("compatible", b"arm,pl011\0arm,primecell\0")
+            .cells("reg", &[0, 0x900_0000, 0, 0x1000])
+            .end_node()
+            .begin_node("memory@40000000")
+            .property("device_type", b"memory\0")
+            .cells("reg", &[0, 0x4000_0000, 0x1, 0])
+            .end_node()
+            .begin_node("intc@8000000")
+            .cells("phandle", &[0x8001])
+            .cells("#interrupt-cells", &[3])
+            .begin_node("its@8080000")
+            .cells("phandle", &[0x8002])
+            .end_node()
+            .end_node()
+            .end_node();
+        builder.build()
+    }
+
+    #[test]
+    fn compatible() {
+        let blob = test_fdt();
+        let fdt = Fdt::new(&blob).unwrap();
+        let uart = fdt.find_node("/pl011@9000000").unwrap();
+        assert!(is_compatible(&uart, &["arm,primecell"]));
+        assert!(is_compatible(&uart, &["ns16550a", "arm,pl011"]));
+        assert!(!is_compatible(&uart, &["ns16550a"]));
+        let memory = fdt.find_node("/memory@40000000").unwrap();
+        assert!(!is_compatible(&memory, &["arm,pl011"]));
+    }
+
+    #[test]
+    fn stdout() {
+        let mut builder = FdtBuilder::default();
+        builder
+            .begin_node("")
+            .cells("#address-cells", &[2])
+            .cells("#size-cells", &[2])
+            .begin_node("chosen")
+            .property("stdout-path", b"/serial@3f8:115200n8\0")
+            .end_node()
+            .begin_node("serial@2f8")
+            .property("compatible", b"ns16550a\0")
+            .cells("reg", &[0, 0x2f8, 0, 8])
+            .end_node()
+            .begin_node("serial@3f8")
+            .property("compatible", b"ns16550a\0")
+            .cells("reg", &[0, 0x3f8, 0, 8])
+            .cells("interrupts", &[0, 0, 1])
+            .end_node()
+            .end_node();
+        let blob = builder.build();
+        let fdt = Fdt::new(&blob).unwrap();
+        let serial = stdout_node(&fdt).unwrap();
+        assert_eq!(serial.name(), "serial@3f8");
+        assert_eq!(
+            MmioDevice::from_node(&serial),
+            Some(MmioDevice {
+                address: 0x3f8,
+                interrupt: Some((IntId::spi(0), Trigger::Edge)),
+            })
+        );
+        let first = find_matching_node(fdt.root(), &|node| is_compatible(node, &["ns16550a"]));
+        assert_eq!(first.unwrap().name(), "serial@2f8");
+    }
+
+    #[test]
+    fn stdout_alias() {
+        let mut builder = FdtBuilder::default();
+        builder
+            .begin_node("")
+            .begin_node("aliases")
+            .property("serial0", b"/pl011@9000000\0")
+            .end_node()
+            .begin_node("chosen")
+            .property("stdout-path", b"serial0\0")
+            .end_node()
+            .begin_node("pl011@9000000")
+            .end_node()
+            .end_node();
+        let blob = builder.build();
+        let fdt = Fdt::new(&blob).unwrap();
+        assert_eq!(stdout_node(&fdt).unwrap().name(), "pl011@9000000");
+    }
+
+    #[test]
+    fn mmio_device_without_interrupts() {
+        let blob = test_fdt();
+        let fdt = Fdt::new(&blob).unwrap();
+        let uart = fdt.find_node("/pl011@9000000").unwrap();
+        assert_eq!(
+            MmioDevice::from_node(&uart),
+            Some(MmioDevice {
+                address: 0x900_0000,
+                interrupt: None,
+            })
+        );
+        assert!(stdout_node(&fdt).is_none());
+        let memory = fdt.find_node("/memory@40000000").unwrap();
+        assert!(find_matching_node(memory, &|node| is_compatible(node, &["arm,pl011"])).is_none());
+    }
+
+    #[test]
+    fn compatible_node() {
+        let blob = test_fdt();
+        let fdt = Fdt::new(&blob).unwrap();
+        assert!(has_compatible_node(&fdt.root(), &["arm,pl011"]));
+        assert!(!has_compatible_node(&fdt.root(), &["ns16550a"]));
+        let memory = fdt.find_node("/memory@40000000").unwrap();
+        assert!(!has_compatible_node(&memory, &["arm,pl011"]));
+    }
+
+    #[test]
+    fn reg_region() {
+        let blob = test_fdt();
+        let fdt = Fdt::new(&blob).unwrap();
+        let uart = fdt.find_node("/pl011@9000000").unwrap();
+        let region = fdt_to_pagetable_region(&uart.reg().unwrap().unwrap().next().unwrap());
+        assert_eq!(region.start().0, 0x900_0000);
+        assert_eq!(region.end().0, 0x900_1000);
+
+        let memory = fdt.find_node("/memory@40000000").unwrap();
+        let region = fdt_to_pagetable_region(&memory.reg().unwrap().unwrap().next().unwrap());
+        assert_eq!(region.start().0, 0x4000_0000);
+        assert_eq!(region.end().0, 0x1_4000_0000);
+    }
+
+    #[test]
+    fn cells() {
+        let blob = test_fdt();
+        let fdt = Fdt::new(&blob).unwrap();
+        let uart = fdt.find_node("/pl011@9000000").unwrap();
+        assert_eq!(
+            property_cells(&uart, "reg"),
+            Some(vec![0, 0x900_0000, 0, 0x1000])
+        );
+        assert_eq!(property_cells(&uart, "interrupts"), None);
+        let memory = fdt.find_node("/memory@40000000").unwrap();
+        assert_eq!(property_cells(&memory, "device_type"), None);
+        assert_eq!(property_u32(&fdt.root(), "#address-cells"), Some(2));
+        assert_eq!(property_u32(&uart, "reg"), None);
+    }
+
+    #[test]
+    fn typed_properties() {
+        let blob = test_fdt();
+        let fdt = Fdt::new(&blob).unwrap();
+        let uart = fdt.find_node("/pl011@9000000").unwrap();
+        assert_eq!(
+            property_strings(&uart, "compatible"),
+            Some(vec!["arm,pl011", "arm,primecell"])
+        );
+        assert_eq!(property_strings(&uart, "reg"), None);
+        assert_eq!(property_u64s(&uart, "reg"), Some(vec![0x900_0000, 0x1000]));
+        assert_eq!(property_u64s(&fdt.root(), "#address-cells"), None);
+        assert_eq!(child_cells(&fdt.root()), (2, 2));
+        assert_eq!(child_cells(&uart), (2, 1));
+        assert_eq!(
+            reg_entries(&uart),
+            Some(vec![RegEntry {
+                address: 0x900_0000,
+                size: 0x1000
+            }])
+        );
+        assert_eq!(reg_entries(&fdt.root()), None);
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(
+            cells_to_u128(&[0x200_0000, 0, 0x1000_0000]),
+            Some(0x200_0000_0000_0000_1000_0000)
+        );
+        assert_eq!(cells_to_u128(&[1; 5]), None);
+        // A PCI memory range, with 3 child address cells, 2 parent address cells and 2 size cells.
+        assert_eq!(
+            decode_ranges(
+                &[0x200_0000, 0, 0x1000_0000, 0, 0x1000_0000, 0, 0x2eff_0000],
+                3,
+                2,
+                2
+            ),
+            Some(vec![RangeEntry {
+                child_address: 0x200_0000_0000_0000_1000_0000,
+                parent_address: 0x1000_0000,
+                size: 0x2eff_0000,
+            }])
+        );
+        assert_eq!(
+            decode_ranges(&[0, 0x4000_0000, 0x1000, 1, 0x2000, 0x1000], 1, 1, 1)
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(decode_ranges(&[1, 2, 3, 4], 1, 1, 1), None);
+        assert_eq!(decode_ranges(&[], 1, 1, 1), Some(vec![]));
+    }
+
+    #[test]
+    fn property_values() {
+        assert_eq!(PropertyValue::parse(b""), PropertyValue::Empty);
+        assert_eq!(
+            PropertyValue::parse(b"arm,pl011\0arm,primecell\0").to_string(),
+            "\"arm,pl011\", \"arm,primecell\""
+        );
+        assert_eq!(
+            PropertyValue::parse(&[0, 0, 0, 2, 0, 0, 0x10, 0]).to_string(),
+            "<0x2 0x1000>"
+        );
+        // Not NUL-terminated, so not a string.
+        assert_eq!(PropertyValue::parse(b"okay").to_string(), "<0x6f6b6179>");
+        assert_eq!(
+            PropertyValue::parse(&[1, 0xab, 0]).to_string(),
+            "[01 ab 00]"
+        );
+        // An empty string isn't printed as one.
+        assert_eq!(PropertyValue::parse(&[0]).to_string(), "[00]");
+    }
+
+    #[test]
+    fn bootarg_flags() {
+        assert!(has_bootarg_flag("semihosting", "semihosting"));
+        assert!(has_bootarg_flag(
+            "console=ttyAMA0  semihosting quiet",
+            "semihosting"
+        ));
+        assert!(!has_bootarg_flag("", "semihosting"));
+        assert!(!has_bootarg_flag("semihosting=1", "semihosting"));
+        assert!(!has_bootarg_flag("nosemihosting", "semihosting"));
+    }
+
+    #[test]
+    fn bootarg_values() {
+        assert_eq!(bootarg_value("", "rc"), None);
+        assert_eq!(bootarg_value("console=ttyAMA0 quiet", "rc"), None);
+        assert_eq!(bootarg_value("rc quiet", "rc"), None);
+        assert_eq!(
+            bootarg_value("nrc=blk0 rc=blk1:0+4096", "rc"),
+            Some("blk1:0+4096")
+        );
+        assert_eq!(
+            bootarg_value("rc=blk0 quiet rc=vsock:2:9000", "rc"),
+            Some("vsock:2:9000")
+        );
+        assert_eq!(bootarg_value("rc= quiet", "rc"), Some(""));
+        assert_eq!(bootarg_value("opts=a=b", "opts"), Some("a=b"));
+    }
+
+    #[test]
+    fn phandles() {
+        let blob = test_fdt();
+        let fdt = Fdt::new(&blob).unwrap();
+        assert_eq!(
+            find_phandle(fdt.root(), 0x8001).unwrap().name(),
+            "intc@8000000"
+        );
+        assert_eq!(
+            find_phandle(fdt.root(), 0x8002).unwrap().name(),
+            "its@8080000"
+        );
+        assert!(find_phandle(fdt.root(), 0x8003).is_none());
+    }
+
+    #[test]
+    fn gic_interrupts() {
+        assert_eq!(
+            gic_interrupt(&[0, 1, 4]),
+            Some((IntId::spi(1), Trigger::Level))
+        );
+        assert_eq!(
+            gic_interrupt(&[1, 13, 0x301]),
+            Some((IntId::ppi(13), Trigger::Edge))
+        );
+        assert_eq!(gic_interrupt(&[2, 1, 4]), None);
+        assert_eq!(gic_interrupt(&[0, 1]), None);
+    }
+}
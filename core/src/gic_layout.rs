@@ -0,0 +1,461 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Checking the regions which the device tree gives for a GIC before creating a driver for it, so
+//! that unusual or broken configurations are reported clearly rather than causing a panic or fault.
+
+use crate::fdt::{
+    RegEntry, find_matching_node, is_compatible, property_u32, property_u64s, reg_entries,
+};
+use alloc::vec::Vec;
+use arm_gic::{
+    gicv2::registers::{Gicc, Gicd as GicV2Gicd},
+    gicv3::registers::{Gicd, GicrSgi},
+};
+use core::fmt::{self, Display, Formatter};
+use dtoolkit::{Node, fdt::FdtNode};
+
+/// The compatible string of a GICv3.
+pub const GICV3_COMPATIBLE: &str = "arm,gic-v3";
+
+/// The compatible string of a GICv3 ITS, which is a child of the GICv3 node.
+pub const GICV3_ITS_COMPATIBLE: &str = "arm,gic-v3-its";
+
+/// The size of the GICv3 distributor registers.
+const GICD_SIZE: u64 = size_of::<Gicd>() as u64;
+
+/// The size of the RD_base and SGI_base frames of each GICv3 redistributor, which is the only
+/// redistributor stride which the driver supports.
+const GICR_SIZE: u64 = size_of::<GicrSgi>() as u64;
+
+/// The size of the GICv2 distributor registers.
+const GICV2_GICD_SIZE: u64 = size_of::<GicV2Gicd>() as u64;
+
+/// The size of the GICv2 CPU interface registers.
+const GICC_SIZE: u64 = size_of::<Gicc>() as u64;
+
+/// A problem with the device tree description of a GIC.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GicLayoutError {
+    /// There is no GICv2 or GICv3 node in the device tree.
+    NotFound,
+    /// The GIC's `reg` property is missing or invalid.
+    InvalidReg,
+    /// The `reg` property has no entry for the distributor.
+    MissingDistributor,
+    /// The distributor region is smaller than its registers.
+    DistributorTooSmall { size: u64, required: u64 },
+    /// The `reg` property of a GICv2 has no entry for the CPU interface.
+    MissingCpuInterface,
+    /// The GICv2 CPU interface region is smaller than its registers.
+    CpuInterfaceTooSmall { size: u64, required: u64 },
+    /// The `reg` property has fewer redistributor regions than `#redistributor-regions` says.
+    MissingRedistributorRegions { expected: usize, found: usize },
+    /// The `redistributor-stride` isn't one which the driver supports.
+    UnsupportedRedistributorStride { stride: u64, supported: u64 },
+    /// The redistributor regions don't have room for a redistributor for every CPU.
+    RedistributorWindowTooSmall {
+        cpu_count: usize,
+        size: u64,
+        required: u64,
+    },
+    /// The ITS node's `reg` property is missing or invalid.
+    InvalidItsReg,
+}
+
+impl Display for GicLayoutError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "No GICv2 or GICv3 found in the device tree"),
+            Self::InvalidReg => write!(f, "GIC reg property is missing or invalid"),
+            Self::MissingDistributor => write!(f, "GIC reg property has no distributor region"),
+            Self::DistributorTooSmall { size, required } => write!(
+                f,
+                "GICD region is {size:#x} bytes, but the distributor needs {required:#x}"
+            ),
+            Self::MissingCpuInterface => {
+                write!(f, "GICv2 reg property has no CPU interface region")
+            }
+            Self::CpuInterfaceTooSmall { size, required } => write!(
+                f,
+                "GICC region is {size:#x} bytes, but the CPU interface needs {required:#x}"
+            ),
+            Self::MissingRedistributorRegions { expected, found } => write!(
+                f,
+                "#redistributor-regions is {expected}, but reg only has {found} after the GICD"
+            ),
+            Self::UnsupportedRedistributorStride { stride, supported } => write!(
+                f,
+                "redistributor-stride {stride:#x} isn't supported, only {supported:#x}"
+            ),
+            Self::RedistributorWindowTooSmall {
+                cpu_count,
+                size,
+                required,
+            } => write!(
+                f,
+                "GICR regions are {size:#x} bytes in total, but {cpu_count} CPUs need {required:#x}"
+            ),
+            Self::InvalidItsReg => write!(f, "GIC ITS reg property is missing or invalid"),
+        }
+    }
+}
+
+/// The redistributor which a CPU uses.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Redistributor {
+    /// The index of the GICR region which the redistributor is in.
+    pub region: usize,
+    /// The address of the redistributor's RD_base frame.
+    pub address: u64,
+}
+
+/// The checked regions of a GICv3.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GicV3Layout {
+    pub distributor: RegEntry,
+    /// The redistributor regions.
+    pub redistributor_regions: Vec<RegEntry>,
+    /// The redistributor used by each CPU which can use one, by CPU index.
+    ///
+    /// The driver is only given the first redistributor's address, so this stops before the first
+    /// CPU whose redistributor doesn't directly follow the previous CPU's, such as in the split
+    /// regions QEMU's virt machine uses for more than 123 CPUs. CPUs from there on can't be used.
+    pub redistributors: Vec<Redistributor>,
+    /// The region of the ITS, if there is one.
+    pub its: Option<RegEntry>,
+    /// Whether any device has its MSIs routed to an interrupt controller.
+    pub msi_requested: bool,
+}
+
+impl GicV3Layout {
+    /// Reads and checks the regions of the GICv3 described by the given node, which must have
+    /// room for a redistributor for each of `cpu_count` CPUs.
+    ///
+    /// Redistributors are assigned to CPUs in order through the regions, as far as they are
+    /// contiguous.
+    ///
+    /// `msi_requested` is recorded so that `missing_its` can report it, but a missing ITS isn't an
+    /// error as nothing uses MSIs yet.
+    pub fn from_node(
+        node: &FdtNode,
+        cpu_count: usize,
+        msi_requested: bool,
+    ) -> Result<Self, GicLayoutError> {
+        let reg = reg_entries(node).ok_or(GicLayoutError::InvalidReg)?;
+        let (&distributor, rest) = reg
+            .split_first()
+            .ok_or(GicLayoutError::MissingDistributor)?;
+        if distributor.size < GICD_SIZE {
+            return Err(GicLayoutError::DistributorTooSmall {
+                size: distributor.size,
+                required: GICD_SIZE,
+            });
+        }
+
+        let region_count = property_u32(node, "#redistributor-regions").unwrap_or(1) as usize;
+        let redistributor_regions = rest
+            .get(..region_count)
+            .filter(|regions| !regions.is_empty())
+            .ok_or(GicLayoutError::MissingRedistributorRegions {
+                expected: region_count.max(1),
+                found: rest.len(),
+            })?
+            .to_vec();
+        if let Some(&stride) = property_u64s(node, "redistributor-stride")
+            .as_deref()
+            .and_then(<[u64]>::first)
+            && stride != GICR_SIZE
+        {
+            return Err(GicLayoutError::UnsupportedRedistributorStride {
+                stride,
+                supported: GICR_SIZE,
+            });
+        }
+        let redistributors = assign_redistributors(&redistributor_regions, cpu_count)?;
+
+        let its = node
+            .children()
+            .find(|child| is_compatible(child, &[GICV3_ITS_COMPATIBLE]))
+            .map(|its| {
+                reg_entries(&its)
+                    .and_then(|reg| reg.first().copied())
+                    .ok_or(GicLayoutError::InvalidItsReg)
+            })
+            .transpose()?;
+
+        Ok(Self {
+            distributor,
+            redistributor_regions,
+            redistributors,
+            its,
+            msi_requested,
+        })
+    }
+
+    /// Returns whether devices have their MSIs routed to an interrupt controller, but there is no
+    /// ITS to deliver them.
+    pub fn missing_its(&self) -> bool {
+        self.msi_requested && self.its.is_none()
+    }
+}
+
+/// Assigns a redistributor to each of `cpu_count` CPUs from the given regions in order, stopping
+/// before the first which isn't contiguous with those before it.
+fn assign_redistributors(
+    regions: &[RegEntry],
+    cpu_count: usize,
+) -> Result<Vec<Redistributor>, GicLayoutError> {
+    let frames = regions.iter().enumerate().flat_map(|(region, entry)| {
+        (0..entry.size / GICR_SIZE).map(move |i| Redistributor {
+            region,
+            address: entry.address + i * GICR_SIZE,
+        })
+    });
+    let mut redistributors: Vec<_> = frames.take(cpu_count).collect();
+    if redistributors.len() < cpu_count {
+        return Err(GicLayoutError::RedistributorWindowTooSmall {
+            cpu_count,
+            size: regions.iter().map(|region| region.size).sum(),
+            required: cpu_count as u64 * GICR_SIZE,
+        });
+    }
+    if let Some(&Redistributor { address: first, .. }) = redistributors.first() {
+        let contiguous = redistributors
+            .iter()
+            .zip(0..)
+            .take_while(|(redistributor, cpu)| redistributor.address == first + cpu * GICR_SIZE)
+            .count();
+        redistributors.truncate(contiguous);
+    }
+    Ok(redistributors)
+}
+
+/// The checked regions of a GICv2.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct GicV2Layout {
+    pub distributor: RegEntry,
+    pub cpu_interface: RegEntry,
+}
+
+impl GicV2Layout {
+    /// Reads and checks the regions of the GICv2 described by the given node.
+    pub fn from_node(node: &FdtNode) -> Result<Self, GicLayoutError> {
+        let reg = reg_entries(node).ok_or(GicLayoutError::InvalidReg)?;
+        let distributor = *reg.first().ok_or(GicLayoutError::MissingDistributor)?;
+        let cpu_interface = *reg.get(1).ok_or(GicLayoutError::MissingCpuInterface)?;
+        if distributor.size < GICV2_GICD_SIZE {
+            return Err(GicLayoutError::DistributorTooSmall {
+                size: distributor.size,
+                required: GICV2_GICD_SIZE,
+            });
+        }
+        if cpu_interface.size < GICC_SIZE {
+            return Err(GicLayoutError::CpuInterfaceTooSmall {
+                size: cpu_interface.size,
+                required: GICC_SIZE,
+            });
+        }
+        Ok(Self {
+            distributor,
+            cpu_interface,
+        })
+    }
+}
+
+/// The checked regions of either a GICv2 or a GICv3.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum GicLayout {
+    V2(GicV2Layout),
+    V3(GicV3Layout),
+}
+
+/// Returns whether any device under the given node, or the node itself, has its MSIs routed to an
+/// interrupt controller with `msi-parent` or `msi-map`.
+pub fn msi_requested(node: FdtNode) -> bool {
+    find_matching_node(node, &|node| {
+        node.property("msi-parent").is_some() || node.property("msi-map").is_some()
+    })
+    .is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fdt::test_util::FdtBuilder;
+    use dtoolkit::fdt::Fdt;
+
+    /// Builds a device tree with a GICv3 with the given `reg` cells and extra properties, with
+    /// an ITS if `its` is true, and a PCI host bridge using MSIs if `msi` is true.
+    fn gicv3_fdt(reg: &[u32], properties: &[(&str, &[u32])], its: bool, msi: bool) -> Vec<u8> {
+        let mut builder = FdtBuilder::default();
+        builder
+            .begin_node("")
+            .cells("#address-cells", &[2])
+            .cells("#size-cells", &[2])
+            .begin_node("intc@8000000")
+            .property("compatible", b"arm,gic-v3\0")
+            .cells("#address-cells", &[2])
+            .cells("#size-cells", &[2])
+            .cells("reg", reg);
+        for (name, cells) in properties {
+            builder.cells(name, cells);
+        }
+        if its {
+            builder
+                .begin_node("its@8080000")
+                .property("compatible", b"arm,gic-v3-its\0")
+                .cells("reg", &[0, 0x808_0000, 0, 0x2_0000])
+                .end_node();
+        }
+        builder.end_node();
+        if msi {
+            builder
+                .begin_node("pcie@10000000")
+                .cells("msi-map", &[0, 0x8002, 0, 0x10000])
+                .end_node();
+        }
+        builder.end_node();
+        builder.build()
+    }
+
+    fn gicv3_layout(blob: &[u8], cpu_count: usize) -> Result<GicV3Layout, GicLayoutError> {
+        let fdt = Fdt::new(blob).unwrap();
+        let node = fdt.find_node("/intc@8000000").unwrap();
+        GicV3Layout::from_node(&node, cpu_count, msi_requested(fdt.root()))
+    }
+
+    #[test]
+    fn single_region() {
+        let blob = gicv3_fdt(
+            &[0, 0x800_0000, 0, 0x1_0000, 0, 0x80a_0000, 0, 0xf6_0000],
+            &[],
+            false,
+            false,
+        );
+        let layout = gicv3_layout(&blob, 2).unwrap();
+        assert_eq!(layout.redistributor_regions.len(), 1);
+        assert_eq!(
+            layout.redistributors,
+            [
+                Redistributor {
+                    region: 0,
+                    address: 0x80a_0000
+                },
+                Redistributor {
+                    region: 0,
+                    address: 0x80c_0000
+                },
+            ]
+        );
+        assert_eq!(layout.its, None);
+    }
+
+    #[test]
+    fn window_too_small() {
+        let blob = gicv3_fdt(
+            &[0, 0x800_0000, 0, 0x1_0000, 0, 0x80a_0000, 0, 0x2_0000],
+            &[],
+            false,
+            false,
+        );
+        assert_eq!(
+            gicv3_layout(&blob, 2),
+            Err(GicLayoutError::RedistributorWindowTooSmall {
+                cpu_count: 2,
+                size: 0x2_0000,
+                required: 0x4_0000,
+            })
+        );
+        let blob = gicv3_fdt(&[0, 0x800_0000, 0, 0x1000], &[], false, false);
+        assert_eq!(
+            gicv3_layout(&blob, 1),
+            Err(GicLayoutError::DistributorTooSmall {
+                size: 0x1000,
+                required: 0x1_0000,
+            })
+        );
+    }
+
+    #[test]
+    fn multiple_regions() {
+        let reg = [
+            0, 0x800_0000, 0, 0x1_0000, 0, 0x80a_0000, 0, 0x2_0000, 0, 0x80c_0000, 0, 0x2_0000,
+        ];
+        let blob = gicv3_fdt(&reg, &[("#redistributor-regions", &[2])], false, false);
+        let layout = gicv3_layout(&blob, 2).unwrap();
+        assert_eq!(layout.redistributor_regions.len(), 2);
+        assert_eq!(layout.redistributors[1].region, 1);
+
+        let reg = [
+            0, 0x800_0000, 0, 0x1_0000, 0, 0x80a_0000, 0, 0x2_0000, 0, 0x900_0000, 0, 0x2_0000,
+        ];
+        let blob = gicv3_fdt(&reg, &[("#redistributor-regions", &[2])], false, false);
+        let layout = gicv3_layout(&blob, 2).unwrap();
+        assert_eq!(
+            layout.redistributors,
+            [Redistributor {
+                region: 0,
+                address: 0x80a_0000
+            }]
+        );
+
+        let blob = gicv3_fdt(
+            &[0, 0x800_0000, 0, 0x1_0000, 0, 0x80a_0000, 0, 0x2_0000],
+            &[("#redistributor-regions", &[2])],
+            false,
+            false,
+        );
+        assert_eq!(
+            gicv3_layout(&blob, 1),
+            Err(GicLayoutError::MissingRedistributorRegions {
+                expected: 2,
+                found: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn stride() {
+        let reg = [0, 0x800_0000, 0, 0x1_0000, 0, 0x80a_0000, 0, 0x10_0000];
+        let blob = gicv3_fdt(
+            &reg,
+            &[("redistributor-stride", &[0, 0x2_0000])],
+            false,
+            false,
+        );
+        assert!(gicv3_layout(&blob, 4).is_ok());
+        let blob = gicv3_fdt(
+            &reg,
+            &[("redistributor-stride", &[0, 0x4_0000])],
+            false,
+            false,
+        );
+        assert_eq!(
+            gicv3_layout(&blob, 4),
+            Err(GicLayoutError::UnsupportedRedistributorStride {
+                stride: 0x4_0000,
+                supported: 0x2_0000,
+            })
+        );
+    }
+
+    #[test]
+    fn its() {
+        let reg = [0, 0x800_0000, 0, 0x1_0000, 0, 0x80a_0000, 0, 0xf6_0000];
+        let blob = gicv3_fdt(&reg, &[], true, true);
+        assert_eq!(
+            gicv3_layout(&blob, 1).unwrap().its,
+            Some(RegEntry {
+                address: 0x808_0000,
+                size: 0x2_0000,
+            })
+        );
+        assert!(!gicv3_layout(&blob, 1).unwrap().missing_its());
+        let blob = gicv3_fdt(&reg, &[], false, true);
+        assert!(gicv3_layout(&blob, 1).unwrap().missing_its());
+        let blob = gicv3_fdt(&reg, &[], false, false);
+        assert!(!gicv3_layout(&blob, 1).unwrap().missing_its());
+    }
+}
@@ -0,0 +1,180 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Translation of USB HID boot protocol keyboard reports into the bytes a terminal would send, with
+//! a US keyboard layout.
+
+/// The size of a boot protocol keyboard input report.
+pub const BOOT_REPORT_SIZE: usize = 8;
+
+const MODIFIER_LEFT_CTRL: u8 = 1 << 0;
+const MODIFIER_LEFT_SHIFT: u8 = 1 << 1;
+const MODIFIER_RIGHT_CTRL: u8 = 1 << 4;
+const MODIFIER_RIGHT_SHIFT: u8 = 1 << 5;
+
+/// Usage ID reported in every key slot when too many keys are pressed at once.
+const USAGE_ERROR_ROLL_OVER: u8 = 0x01;
+const USAGE_A: u8 = 0x04;
+const USAGE_Z: u8 = 0x1d;
+const USAGE_1: u8 = 0x1e;
+const USAGE_0: u8 = 0x27;
+const USAGE_MINUS: u8 = 0x2d;
+const USAGE_SLASH: u8 = 0x38;
+
+const DIGITS: &[u8; 10] = b"1234567890";
+const SHIFTED_DIGITS: &[u8; 10] = b"!@#$%^&*()";
+/// The characters for usages from `USAGE_MINUS` to `USAGE_SLASH`.
+const PUNCTUATION: &[u8; 12] = b"-=[]\\#;'`,./";
+const SHIFTED_PUNCTUATION: &[u8; 12] = b"_+{}|~:\"~<>?";
+
+/// The state of a boot protocol keyboard, to work out which keys have been newly pressed.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BootKeyboard {
+    /// The keys which were pressed in the last report.
+    pressed: [u8; 6],
+}
+
+impl BootKeyboard {
+    pub const fn new() -> Self {
+        Self { pressed: [0; 6] }
+    }
+
+    /// Handles an input report from the keyboard, calling `output` with the bytes for each key
+    /// which has been pressed since the last report.
+    ///
+    /// Keys which are held down don't repeat.
+    pub fn handle_report(&mut self, report: &[u8], mut output: impl FnMut(&[u8])) {
+        let Some(report) = report.first_chunk::<BOOT_REPORT_SIZE>() else {
+            return;
+        };
+        let modifiers = report[0];
+        let keys: [u8; 6] = report[2..].try_into().unwrap();
+        if keys.contains(&USAGE_ERROR_ROLL_OVER) {
+            return;
+        }
+        for &usage in &keys {
+            if usage != 0 && !self.pressed.contains(&usage) {
+                let mut buffer = [0; 1];
+                if let Some(bytes) = translate(usage, modifiers, &mut buffer) {
+                    output(bytes);
+                }
+            }
+        }
+        self.pressed = keys;
+    }
+}
+
+/// Returns the bytes which a terminal would send for the key with the given usage ID and
+/// modifiers, if any, using `buffer` for single characters.
+fn translate(usage: u8, modifiers: u8, buffer: &mut [u8; 1]) -> Option<&[u8]> {
+    let shift = modifiers & (MODIFIER_LEFT_SHIFT | MODIFIER_RIGHT_SHIFT) != 0;
+    let ctrl = modifiers & (MODIFIER_LEFT_CTRL | MODIFIER_RIGHT_CTRL) != 0;
+    let character = match usage {
+        USAGE_A..=USAGE_Z => {
+            let letter = b'a' + (usage - USAGE_A);
+            if ctrl {
+                letter & 0x1f
+            } else if shift {
+                letter.to_ascii_uppercase()
+            } else {
+                letter
+            }
+        }
+        USAGE_1..=USAGE_0 => {
+            let table = if shift { SHIFTED_DIGITS } else { DIGITS };
+            table[usize::from(usage - USAGE_1)]
+        }
+        USAGE_MINUS..=USAGE_SLASH => {
+            let table = if shift {
+                SHIFTED_PUNCTUATION
+            } else {
+                PUNCTUATION
+            };
+            table[usize::from(usage - USAGE_MINUS)]
+        }
+        0x28 => b'\r',
+        0x29 => 0x1b,
+        0x2a => 0x7f,
+        0x2b => b'\t',
+        0x2c => b' ',
+        0x4a => return Some(b"\x1b[H"),
+        0x4c => return Some(b"\x1b[3~"),
+        0x4d => return Some(b"\x1b[F"),
+        0x4f => return Some(b"\x1b[C"),
+        0x50 => return Some(b"\x1b[D"),
+        0x51 => return Some(b"\x1b[B"),
+        0x52 => return Some(b"\x1b[A"),
+        _ => return None,
+    };
+    buffer[0] = character;
+    Some(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the output from handling the given reports in order.
+    fn handle(reports: &[[u8; BOOT_REPORT_SIZE]]) -> Vec<u8> {
+        let mut keyboard = BootKeyboard::new();
+        let mut output = Vec::new();
+        for report in reports {
+            keyboard.handle_report(report, |bytes| output.extend_from_slice(bytes));
+        }
+        output
+    }
+
+    #[test]
+    fn letters_and_digits() {
+        assert_eq!(
+            handle(&[
+                [0, 0, 0x0b, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [MODIFIER_LEFT_SHIFT, 0, 0x0c, 0, 0, 0, 0, 0],
+                [0, 0, 0x1e, 0, 0, 0, 0, 0],
+                [MODIFIER_RIGHT_SHIFT, 0, 0x1f, 0, 0, 0, 0, 0],
+                [0, 0, 0x28, 0, 0, 0, 0, 0],
+            ]),
+            b"hI1@\r"
+        );
+    }
+
+    #[test]
+    fn held_keys_dont_repeat() {
+        assert_eq!(
+            handle(&[
+                [0, 0, 0x04, 0, 0, 0, 0, 0],
+                [0, 0, 0x04, 0x05, 0, 0, 0, 0],
+                [0, 0, 0x05, 0, 0, 0, 0, 0],
+                [0, 0, 0x05, 0x04, 0, 0, 0, 0],
+            ]),
+            b"aba"
+        );
+    }
+
+    #[test]
+    fn control_and_escape_sequences() {
+        assert_eq!(
+            handle(&[
+                [MODIFIER_LEFT_CTRL, 0, 0x07, 0, 0, 0, 0, 0],
+                [0, 0, 0x52, 0, 0, 0, 0, 0],
+                [0, 0, 0x2a, 0, 0, 0, 0, 0],
+                [MODIFIER_LEFT_SHIFT, 0, 0x38, 0, 0, 0, 0, 0],
+            ]),
+            b"\x04\x1b[A\x7f?"
+        );
+    }
+
+    #[test]
+    fn roll_over_and_short_reports() {
+        let mut keyboard = BootKeyboard::new();
+        let mut output = Vec::new();
+        keyboard.handle_report(&[0, 0, 1, 1, 1, 1, 1, 1], |bytes| {
+            output.extend_from_slice(bytes)
+        });
+        keyboard.handle_report(&[0, 0, 0x04], |bytes| output.extend_from_slice(bytes));
+        assert!(output.is_empty());
+        assert_eq!(keyboard, BootKeyboard::new());
+    }
+}
@@ -0,0 +1,74 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! ICMP echo messages, as sent by `ping`.
+
+use crate::ipv4::checksum;
+use alloc::vec::Vec;
+
+/// The size of the header of an ICMP echo message, before its data.
+pub const ICMP_ECHO_HEADER_SIZE: usize = 8;
+
+/// The ICMP message type of an echo reply.
+const TYPE_ECHO_REPLY: u8 = 0;
+/// The ICMP message type of an echo request.
+const TYPE_ECHO_REQUEST: u8 = 8;
+
+/// Returns the reply to the given ICMP message, if it is a valid echo request.
+///
+/// The reply has the same identifier, sequence number and data as the request.
+pub fn echo_reply(request: &[u8]) -> Option<Vec<u8>> {
+    if request.len() < ICMP_ECHO_HEADER_SIZE
+        || request[0] != TYPE_ECHO_REQUEST
+        || request[1] != 0
+        || checksum(request) != 0
+    {
+        return None;
+    }
+    let mut reply = request.to_vec();
+    reply[0] = TYPE_ECHO_REPLY;
+    reply[2..4].fill(0);
+    let checksum = checksum(&reply);
+    reply[2..4].copy_from_slice(&checksum.to_be_bytes());
+    Some(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns an echo request with the given data and a correct checksum.
+    fn request(data: &[u8]) -> Vec<u8> {
+        let mut request = vec![TYPE_ECHO_REQUEST, 0, 0, 0, 0x12, 0x34, 0, 7];
+        request.extend_from_slice(data);
+        let checksum = checksum(&request);
+        request[2..4].copy_from_slice(&checksum.to_be_bytes());
+        request
+    }
+
+    #[test]
+    fn reply_to_request() {
+        let reply = echo_reply(&request(b"ping!")).unwrap();
+        assert_eq!(reply[0], TYPE_ECHO_REPLY);
+        assert_eq!(reply[1], 0);
+        assert_eq!(reply[4..], [0x12, 0x34, 0, 7, b'p', b'i', b'n', b'g', b'!']);
+        assert_eq!(checksum(&reply), 0);
+    }
+
+    #[test]
+    fn ignore_invalid() {
+        let mut corrupted = request(&[1, 2, 3]);
+        corrupted[8] ^= 1;
+        assert_eq!(echo_reply(&corrupted), None);
+
+        let mut reply = request(&[]);
+        reply[0] = TYPE_ECHO_REPLY;
+        reply[2..4].fill(0);
+        let checksum = checksum(&reply);
+        reply[2..4].copy_from_slice(&checksum.to_be_bytes());
+        assert_eq!(echo_reply(&reply), None);
+
+        assert_eq!(echo_reply(&request(&[])[..4]), None);
+    }
+}
@@ -0,0 +1,74 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Waiting for interrupts, while accounting for how long each core spends doing so.
+
+use crate::{
+    cpus::{cpu_count, try_current_cpu_index},
+    drivers::generic_timer::ticks_to_duration,
+    timer::counter,
+};
+use alloc::boxed::Box;
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use spin::Lazy;
+
+/// The time each core has spent in `wfi`, by CPU index.
+///
+/// Like the work queues, every core can read every other core's counters.
+static RESIDENCY: Lazy<Box<[Residency]>> =
+    Lazy::new(|| (0..cpu_count()).map(|_| Residency::default()).collect());
+
+#[derive(Debug, Default)]
+struct Residency {
+    /// The total number of counter ticks spent waiting.
+    ticks: AtomicU64,
+    /// The number of times `wfi` has returned.
+    wakeups: AtomicU64,
+}
+
+/// A snapshot of the time a core has spent waiting for interrupts since it was first used.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct IdleStats {
+    /// The total number of counter ticks spent in `wfi`.
+    pub ticks: u64,
+    /// The number of times the core has been woken from `wfi`.
+    pub wakeups: u64,
+}
+
+impl IdleStats {
+    /// Returns the total time spent in `wfi`.
+    pub fn duration(&self) -> Duration {
+        ticks_to_duration(self.ticks)
+    }
+}
+
+/// Waits for an interrupt, and adds the time spent waiting to the current core's residency.
+///
+/// Callers normally mask exceptions around this, so that an interrupt which arrives just before it
+/// still wakes the core. The time spent running the handler for that interrupt is then not counted
+/// either.
+pub fn wfi() {
+    let start = counter();
+    arm_gic::wfi();
+    let waited = counter().saturating_sub(start);
+    // The FDT is needed to know which core we are on, so we can't account for waits before it is
+    // set.
+    if let Some(residency) = try_current_cpu_index().and_then(|cpu| RESIDENCY.get(cpu)) {
+        residency.ticks.fetch_add(waited, Ordering::Relaxed);
+        residency.wakeups.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Returns the time the given core has spent waiting for interrupts, or `None` if there is no such
+/// core.
+pub fn idle_stats(cpu: usize) -> Option<IdleStats> {
+    let residency = RESIDENCY.get(cpu)?;
+    Some(IdleStats {
+        ticks: residency.ticks.load(Ordering::Relaxed),
+        wakeups: residency.wakeups.load(Ordering::Relaxed),
+    })
+}
@@ -0,0 +1,63 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Input from devices other than the console UART, such as USB keyboards, which is read by the
+//! console along with the UART's input.
+
+use alloc::{boxed::Box, collections::vec_deque::VecDeque, vec::Vec};
+use spin::mutex::SpinMutex;
+
+/// A device which provides console input.
+pub trait InputSource: Send {
+    /// Processes any input from the device, appending the bytes which a terminal would have sent
+    /// for it to `input`.
+    ///
+    /// This is called from the console's read loop, so must not block.
+    fn poll(&mut self, input: &mut VecDeque<u8>);
+}
+
+/// All the input sources which have been added, and input received from them which hasn't yet
+/// been read.
+static INPUT: SpinMutex<Input> = SpinMutex::new(Input {
+    sources: Vec::new(),
+    pending: VecDeque::new(),
+});
+
+struct Input {
+    sources: Vec<Box<dyn InputSource>>,
+    pending: VecDeque<u8>,
+}
+
+impl Input {
+    fn poll(&mut self) {
+        for source in &mut self.sources {
+            source.poll(&mut self.pending);
+        }
+    }
+}
+
+/// Adds a device to be polled for console input.
+pub fn add_source(source: Box<dyn InputSource>) {
+    INPUT.lock().sources.push(source);
+}
+
+/// Polls all input sources, and returns whether there is any input ready to read.
+pub fn ready() -> bool {
+    let mut input = INPUT.lock();
+    input.poll();
+    !input.pending.is_empty()
+}
+
+/// Polls all input sources, and reads as much pending input as fits into the given buffer.
+///
+/// Returns the number of bytes read, which may be 0 if there is no input.
+pub fn read(buf: &mut [u8]) -> usize {
+    let mut input = INPUT.lock();
+    input.poll();
+    let count = buf.len().min(input.pending.len());
+    for (dest, byte) in buf.iter_mut().zip(input.pending.drain(..count)) {
+        *dest = byte;
+    }
+    count
+}
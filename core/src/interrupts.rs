@@ -0,0 +1,562 @@
+// Copyright 2025 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Setting up the GIC, and dispatching interrupts to handlers registered for them.
+
+use crate::{
+    cpus::{PerCoreState, current_cpu_index, limit_cpu_count, new_per_core_state_with_default},
+    exceptions::init_current_el,
+    executor::block_on,
+    gic_layout::{
+        GICV3_COMPATIBLE, GicLayout, GicLayoutError, GicV2Layout, GicV3Layout, msi_requested,
+    },
+};
+use alloc::{
+    collections::{btree_map::BTreeMap, btree_set::BTreeSet},
+    vec::Vec,
+};
+use arm_gic::{
+    IntId, InterruptGroup, Trigger, UniqueMmioPointer,
+    gicv2::{
+        self, GicV2,
+        registers::{Gicc, Gicd as GicV2Gicd},
+    },
+    gicv3::{self, GicCpuInterface, GicV3, SgiTarget, SgiTargetGroup},
+};
+use core::{
+    future::poll_fn,
+    ptr::NonNull,
+    task::{Poll, Waker},
+};
+use dtoolkit::{Node, fdt::Fdt, standard::NodeStandard};
+use log::{debug, info, trace, warn};
+use percore::{ExceptionLock, exception_free};
+use spin::{Once, mutex::SpinMutex};
+
+type IrqHandler = &'static (dyn Fn(IntId) + Sync);
+
+static SHARED_IRQ_HANDLERS: ExceptionLock<SpinMutex<BTreeMap<IntId, IrqHandler>>> =
+    ExceptionLock::new(SpinMutex::new(BTreeMap::new()));
+static PRIVATE_IRQ_HANDLERS: PerCoreState<BTreeMap<IntId, IrqHandler>> =
+    new_per_core_state_with_default();
+
+/// The number of times each interrupt has been handled, on any core.
+static IRQ_COUNTS: ExceptionLock<SpinMutex<BTreeMap<IntId, u64>>> =
+    ExceptionLock::new(SpinMutex::new(BTreeMap::new()));
+
+/// Device interrupts which have been set up with `setup_device_irq`.
+static DEVICE_IRQS: ExceptionLock<SpinMutex<BTreeSet<IntId>>> =
+    ExceptionLock::new(SpinMutex::new(BTreeSet::new()));
+/// Device interrupts which have been raised since their driver last waited for one.
+static PENDING_DEVICE_IRQS: ExceptionLock<SpinMutex<BTreeSet<IntId>>> =
+    ExceptionLock::new(SpinMutex::new(BTreeSet::new()));
+/// Wakers for futures waiting for device interrupts, keyed by interrupt ID.
+static DEVICE_IRQ_WAKERS: ExceptionLock<SpinMutex<BTreeMap<IntId, Waker>>> =
+    ExceptionLock::new(SpinMutex::new(BTreeMap::new()));
+
+/// Compatible strings for GICv2 and compatible interrupt controllers.
+pub const GICV2_COMPATIBLE: [&str; 3] = ["arm,gic-400", "arm,cortex-a15-gic", "arm,cortex-a9-gic"];
+
+static GIC: Once<SpinMutex<Gic>> = Once::new();
+
+/// The regions of the GIC, as checked when it was found.
+static GIC_LAYOUT: Once<GicLayout> = Once::new();
+
+/// A driver for either a GICv2 or a GICv3.
+#[derive(Debug)]
+pub enum Gic {
+    V2(GicV2<'static>),
+    V3(GicV3<'static>),
+}
+
+/// An error from either the GICv2 or GICv3 driver.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GicError {
+    V2(gicv2::Error),
+    V3(gicv3::GicError),
+}
+
+impl From<gicv2::Error> for GicError {
+    fn from(e: gicv2::Error) -> Self {
+        Self::V2(e)
+    }
+}
+
+impl From<gicv3::GicError> for GicError {
+    fn from(e: gicv3::GicError) -> Self {
+        Self::V3(e)
+    }
+}
+
+impl Gic {
+    /// Initialises the GIC, and the CPU interface for the given core.
+    fn setup(&mut self, cpu: usize) {
+        match self {
+            Self::V2(gic) => gic.setup(),
+            Self::V3(gic) => gic.setup(cpu),
+        }
+    }
+
+    /// Initialises the CPU interface for the given core, which must be the current core.
+    fn init_cpu(&mut self, cpu: usize) {
+        match self {
+            // The GICv2 CPU interface and the distributor registers for private interrupts are
+            // banked per core, so running the setup again initialises them for this core.
+            Self::V2(gic) => gic.setup(),
+            Self::V3(gic) => {
+                gic.init_cpu(cpu);
+                GicCpuInterface::enable_group1(true);
+            }
+        }
+    }
+
+    /// Enables or disables the interrupt with the given ID.
+    ///
+    /// If it is an SGI or PPI then the CPU core on which to enable it must also be specified;
+    /// otherwise this is ignored and may be `None`. For a GICv2 this must be the current core.
+    pub fn enable_interrupt(
+        &mut self,
+        intid: IntId,
+        cpu: Option<usize>,
+        enable: bool,
+    ) -> Result<(), GicError> {
+        match self {
+            Self::V2(gic) => {
+                check_gicv2_cpu(intid, cpu);
+                gic.enable_interrupt(intid, enable)?;
+            }
+            Self::V3(gic) => gic.enable_interrupt(intid, cpu, enable)?,
+        }
+        Ok(())
+    }
+
+    /// Sets the priority of the interrupt with the given ID.
+    ///
+    /// If it is an SGI or PPI then the CPU core must also be specified, as for `enable_interrupt`.
+    pub fn set_interrupt_priority(
+        &mut self,
+        intid: IntId,
+        cpu: Option<usize>,
+        priority: u8,
+    ) -> Result<(), GicError> {
+        match self {
+            Self::V2(gic) => {
+                check_gicv2_cpu(intid, cpu);
+                // TODO: The GICv2 driver writes the whole GICD_IPRIORITYR register, so this also
+                // resets the priorities of the other three interrupts sharing it.
+                gic.set_interrupt_priority(intid, priority);
+            }
+            Self::V3(gic) => gic.set_interrupt_priority(intid, cpu, priority)?,
+        }
+        Ok(())
+    }
+
+    /// Configures the trigger type for the interrupt with the given ID.
+    ///
+    /// If it is an SGI or PPI then the CPU core must also be specified, as for `enable_interrupt`.
+    pub fn set_trigger(
+        &mut self,
+        intid: IntId,
+        cpu: Option<usize>,
+        trigger: Trigger,
+    ) -> Result<(), GicError> {
+        match self {
+            Self::V2(gic) => {
+                check_gicv2_cpu(intid, cpu);
+                gic.set_trigger(intid, trigger);
+            }
+            Self::V3(gic) => gic.set_trigger(intid, cpu, trigger)?,
+        }
+        Ok(())
+    }
+}
+
+/// Checks that the given CPU is valid for configuring the given interrupt on a GICv2, which can
+/// only access the private interrupts of the current core.
+fn check_gicv2_cpu(intid: IntId, cpu: Option<usize>) {
+    assert!(
+        !intid.is_private() || cpu.is_none_or(|cpu| cpu == current_cpu_index()),
+        "GICv2 can only configure {intid:?} for the current CPU",
+    );
+}
+
+/// Calls the given function with the GIC driver.
+///
+/// IRQs are masked while the GIC is locked, as the IRQ handler may also need to lock it.
+///
+/// Panics if `init_gic` has not yet been called.
+pub fn with_gic<R>(f: impl FnOnce(&mut Gic) -> R) -> R {
+    exception_free(|_| f(&mut GIC.get().unwrap().lock()))
+}
+
+/// Returns the regions of the GIC, as checked when it was found, or `None` if `init_gic` has not
+/// yet been called.
+pub fn gic_layout() -> Option<&'static GicLayout> {
+    GIC_LAYOUT.get()
+}
+
+// From the non-secure world, the GICv2 CPU interface registers which arm-gic uses for Group 0
+// (GICC_IAR and GICC_EOIR) handle Group 1 interrupts, so these use `InterruptGroup::Group0` for a
+// GICv2 but `InterruptGroup::Group1` for a GICv3.
+
+/// Sets the priority mask for the current CPU core.
+///
+/// Only interrupts with a higher priority (numerically lower) will be signalled.
+pub fn set_priority_mask(min_priority: u8) {
+    with_gic(|gic| match gic {
+        Gic::V2(gic) => gic.set_priority_mask(min_priority),
+        Gic::V3(_) => GicCpuInterface::set_priority_mask(min_priority),
+    });
+}
+
+/// Gets the ID of the highest priority signalled interrupt, and acknowledges it.
+fn get_and_acknowledge_interrupt() -> Option<IntId> {
+    with_gic(|gic| match gic {
+        Gic::V2(gic) => gic.get_and_acknowledge_interrupt(InterruptGroup::Group0),
+        Gic::V3(_) => GicCpuInterface::get_and_acknowledge_interrupt(InterruptGroup::Group1),
+    })
+}
+
+/// Informs the GIC that the current CPU core has finished handling the given interrupt.
+pub fn end_interrupt(intid: IntId) {
+    with_gic(|gic| match gic {
+        Gic::V2(gic) => gic.end_interrupt(intid, InterruptGroup::Group0),
+        Gic::V3(_) => GicCpuInterface::end_interrupt(intid, InterruptGroup::Group1),
+    });
+}
+
+/// Sends the given software-generated interrupt to all CPU cores except the current one.
+pub fn send_sgi_to_all(intid: IntId) {
+    with_gic(|gic| match gic {
+        Gic::V2(gic) => gic.send_sgi(intid, gicv2::SgiTarget::All),
+        Gic::V3(_) => {
+            GicCpuInterface::send_sgi(intid, SgiTarget::All, SgiTargetGroup::CurrentGroup1).unwrap()
+        }
+    });
+}
+
+/// Sets the IRQ handler for the given interrupt ID to the given function, on all cores.
+///
+/// Returns the handler that was previously set, if any.
+pub fn set_shared_irq_handler(intid: IntId, handler: IrqHandler) -> Option<IrqHandler> {
+    trace!("Setting shared IRQ handler for {intid:?}");
+    exception_free(|token| {
+        assert!(
+            !PRIVATE_IRQ_HANDLERS
+                .get()
+                .borrow(token)
+                .borrow()
+                .contains_key(&intid),
+            "Private IRQ handler already exists for {intid:?}",
+        );
+        SHARED_IRQ_HANDLERS
+            .borrow(token)
+            .lock()
+            .insert(intid, handler)
+    })
+}
+
+/// Removes the shared IRQ handler for the given interrupt ID.
+///
+/// Returns the handler that was previously set, if any.
+pub fn remove_shared_irq_handler(intid: IntId) -> Option<IrqHandler> {
+    trace!("Removing shared IRQ handler for {intid:?}");
+    exception_free(|token| SHARED_IRQ_HANDLERS.borrow(token).lock().remove(&intid))
+}
+
+/// Sets the IRQ handler for the given interrupt ID to the given function, on the current core only.
+///
+/// Returns the handler that was previously set, if any.
+pub fn set_private_irq_handler(intid: IntId, handler: IrqHandler) -> Option<IrqHandler> {
+    trace!("Setting private IRQ handler for {intid:?}");
+    exception_free(|token| {
+        assert!(
+            !SHARED_IRQ_HANDLERS
+                .borrow(token)
+                .lock()
+                .contains_key(&intid),
+            "Private IRQ handler already exists for {intid:?}",
+        );
+        PRIVATE_IRQ_HANDLERS
+            .get()
+            .borrow_mut(token)
+            .insert(intid, handler)
+    })
+}
+
+/// Removes the private IRQ handler for the given interrupt ID.
+///
+/// Returns the handler that was previously set, if any.
+pub fn remove_private_irq_handler(intid: IntId) -> Option<IrqHandler> {
+    trace!("Removing private IRQ handler for {intid:?}");
+    exception_free(|token| PRIVATE_IRQ_HANDLERS.get().borrow_mut(token).remove(&intid))
+}
+
+/// Handles a level-triggered interrupt from a device whose driver waits for it with
+/// `wait_for_device_irq`.
+///
+/// The device keeps the interrupt asserted until the driver acknowledges it, so we mask it in the
+/// GIC until then.
+fn handle_device_irq(intid: IntId) {
+    with_gic(|gic| gic.enable_interrupt(intid, None, false)).unwrap();
+    exception_free(|token| PENDING_DEVICE_IRQS.borrow(token).lock().insert(intid));
+    if let Some(waker) =
+        exception_free(|token| DEVICE_IRQ_WAKERS.borrow(token).lock().remove(&intid))
+    {
+        waker.wake();
+    }
+    end_interrupt(intid);
+}
+
+/// Configures and enables the given device interrupt, to be waited for with `wait_for_device_irq`.
+///
+/// Several PCI devices may share the same interrupt, in which case this is called once for each.
+pub fn setup_device_irq(intid: IntId, trigger: Trigger) {
+    set_shared_irq_handler(intid, &handle_device_irq);
+    exception_free(|token| DEVICE_IRQS.borrow(token).lock().insert(intid));
+    with_gic(|gic| {
+        gic.set_interrupt_priority(intid, None, 0x80).unwrap();
+        gic.set_trigger(intid, None, trigger).unwrap();
+        gic.enable_interrupt(intid, None, true).unwrap();
+    });
+}
+
+/// Waits until the given device interrupt has been raised since the last call.
+///
+/// The interrupt is left masked, so the caller must acknowledge it in the device and then call
+/// `unmask_device_irq`.
+pub fn wait_for_device_irq(intid: IntId) {
+    block_on(device_irq(intid));
+}
+
+/// Completes once the given device interrupt has been raised since the last call.
+///
+/// As with `wait_for_device_irq`, the interrupt is left masked, so the caller must acknowledge it
+/// in the device and then call `unmask_device_irq`.
+pub async fn device_irq(intid: IntId) {
+    poll_fn(|context| {
+        if take_device_irq(intid) {
+            return Poll::Ready(());
+        }
+        exception_free(|token| {
+            DEVICE_IRQ_WAKERS
+                .borrow(token)
+                .lock()
+                .insert(intid, context.waker().clone())
+        });
+        // Check again in case the interrupt arrived before the waker was registered.
+        if take_device_irq(intid) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// Returns whether the given device interrupt has been raised since the last call, without
+/// waiting.
+///
+/// If so, the interrupt is left masked, so the caller must acknowledge it in the device and then
+/// call `unmask_device_irq`.
+pub fn take_device_irq(intid: IntId) -> bool {
+    exception_free(|token| PENDING_DEVICE_IRQS.borrow(token).lock().remove(&intid))
+}
+
+/// Unmasks the given device interrupt once its driver has acknowledged it in the device.
+pub fn unmask_device_irq(intid: IntId) {
+    with_gic(|gic| gic.enable_interrupt(intid, None, true)).unwrap();
+}
+
+/// Masks all device interrupts set up with `setup_device_irq`, such as before suspending the
+/// system.
+pub fn mask_device_irqs() {
+    let intids = exception_free(|token| DEVICE_IRQS.borrow(token).lock().clone());
+    with_gic(|gic| {
+        for intid in intids {
+            gic.enable_interrupt(intid, None, false).unwrap();
+        }
+    });
+}
+
+/// Unmasks the device interrupts masked by `mask_device_irqs`, except those which have been raised
+/// but not yet acknowledged by their driver.
+pub fn restore_device_irqs() {
+    let intids = exception_free(|token| {
+        let pending = PENDING_DEVICE_IRQS.borrow(token).lock();
+        DEVICE_IRQS
+            .borrow(token)
+            .lock()
+            .difference(&pending)
+            .copied()
+            .collect::<Vec<_>>()
+    });
+    with_gic(|gic| {
+        for intid in intids {
+            gic.enable_interrupt(intid, None, true).unwrap();
+        }
+    });
+}
+
+/// Asks the GIC what interrupt is pending and then calls the appropriate handler.
+///
+/// This should be called when there is an irq_current exception.
+///
+/// Panics if there is no no pending interrupt, or no registered handler for the pending interrupt.
+pub fn handle_irq() {
+    let intid = get_and_acknowledge_interrupt().expect("No pending interrupt");
+    trace!("IRQ: {intid:?}");
+    exception_free(|token| {
+        *IRQ_COUNTS.borrow(token).lock().entry(intid).or_default() += 1;
+        if let Some(handler) = PRIVATE_IRQ_HANDLERS
+            .get()
+            .borrow(token)
+            .borrow()
+            .get(&intid)
+        {
+            handler(intid);
+        } else if let Some(handler) = SHARED_IRQ_HANDLERS.borrow(token).lock().get(&intid) {
+            handler(intid);
+        } else {
+            panic!("Unexpected IRQ {:?} with no handler", intid);
+        }
+    });
+}
+
+/// Returns the number of times each interrupt has been handled since boot, on any core.
+pub fn irq_counts() -> BTreeMap<IntId, u64> {
+    exception_free(|token| IRQ_COUNTS.borrow(token).lock().clone())
+}
+
+/// Finds a GICv3 or GICv2 in the given device tree, checks its regions and constructs a driver for
+/// it.
+///
+/// A GICv3 is preferred if both are present.
+///
+/// # Safety
+///
+/// This must only be called once, to avoid creating multiple drivers with aliases to the same GIC.
+/// The given FDT must accurately reflect the platform, and the GIC device must already be mapped
+/// in the pagetable and not used anywhere else.
+unsafe fn make_gic(fdt: &Fdt) -> Result<(Gic, GicLayout), GicLayoutError> {
+    // SAFETY: Our caller promised that the device tree is accurate and we are only called once.
+    unsafe {
+        make_gicv3(fdt)
+            .or_else(|| make_gicv2(fdt))
+            .unwrap_or(Err(GicLayoutError::NotFound))
+    }
+}
+
+/// Finds a GICv3 in the given device tree, checks its regions and constructs a driver for it.
+///
+/// Returns `None` if there is no GICv3.
+///
+/// # Safety
+///
+/// Same as for `make_gic`.
+unsafe fn make_gicv3(fdt: &Fdt) -> Option<Result<(Gic, GicLayout), GicLayoutError>> {
+    let cpu_count = fdt.cpus().unwrap().cpus().count();
+
+    let node = fdt.root().find_compatible(GICV3_COMPATIBLE).next()?;
+    info!("Found GIC FDT node {}", node.name());
+    let layout = match GicV3Layout::from_node(&node, cpu_count, msi_requested(fdt.root())) {
+        Ok(layout) => layout,
+        Err(e) => return Some(Err(e)),
+    };
+    info!("  GICD: {}", layout.distributor);
+    for region in &layout.redistributor_regions {
+        info!("  GICR: {region}");
+    }
+    if let Some(its) = &layout.its {
+        info!("  ITS: {its}");
+    }
+    if layout.missing_its() {
+        warn!("Devices use MSIs, but the GICv3 has no ITS to deliver them");
+    }
+    let usable_cpus = layout.redistributors.len();
+    if usable_cpus < cpu_count {
+        warn!(
+            "Only the first {usable_cpus} of {cpu_count} CPUs have contiguous redistributors, so \
+             the rest won't be used"
+        );
+        limit_cpu_count(usable_cpus);
+    }
+    let gicd = NonNull::new(layout.distributor.address as _).unwrap();
+    let gicr = NonNull::new(layout.redistributors[0].address as _).unwrap();
+    debug!("GICD: {gicd:?} GICR: {gicr:?} cpu_count {usable_cpus}");
+    // SAFETY: Our caller promised that the device tree is accurate and we are only called once, and
+    // the layout has been checked to have a redistributor for each usable CPU following the first.
+    let gic = unsafe { GicV3::new(UniqueMmioPointer::new(gicd), gicr, usable_cpus, false) };
+
+    Some(Ok((Gic::V3(gic), GicLayout::V3(layout))))
+}
+
+/// Finds a GICv2 in the given device tree, checks its regions and constructs a driver for it.
+///
+/// Returns `None` if there is no GICv2.
+///
+/// # Safety
+///
+/// Same as for `make_gic`.
+unsafe fn make_gicv2(fdt: &Fdt) -> Option<Result<(Gic, GicLayout), GicLayoutError>> {
+    let node = GICV2_COMPATIBLE
+        .iter()
+        .find_map(|compatible| fdt.root().find_compatible(compatible).next())?;
+    info!("Found GICv2 FDT node {}", node.name());
+    let layout = match GicV2Layout::from_node(&node) {
+        Ok(layout) => layout,
+        Err(e) => return Some(Err(e)),
+    };
+    info!("  GICD: {}", layout.distributor);
+    info!("  GICC: {}", layout.cpu_interface);
+    let gicd = layout.distributor.address as *mut GicV2Gicd;
+    let gicc = layout.cpu_interface.address as *mut Gicc;
+    // SAFETY: Our caller promised that the device tree is accurate and we are only called once.
+    let gic = unsafe { GicV2::new(gicd, gicc) };
+
+    Some(Ok((Gic::V2(gic), GicLayout::V2(layout))))
+}
+
+/// Finds a GICv3 or GICv2 in the device tree, creates a driver for it, initialises it ready to start
+/// handling interrupts, and stores it for later access.
+///
+/// `platform_setup` is called once the GIC is initialised, to configure any interrupts which the
+/// platform uses such as for its console UART.
+///
+/// Panics with a description of the problem if there is no GIC, or the device tree gives regions
+/// for it which the driver can't use.
+///
+/// # Safety
+///
+/// The given FDT must accurately reflect the platform, and the GIC device must already be mapped
+/// in the pagetable and not used anywhere else.
+pub unsafe fn init_gic(fdt: &Fdt, platform_setup: impl FnOnce(&mut Gic)) {
+    init_current_el();
+
+    GIC.call_once(|| {
+        // SAFETY: Our caller promised that the FDT is accurate, and the call_once ensures that this
+        // isn't called more than once.
+        let (mut gic, layout) = unsafe { make_gic(fdt) }.unwrap_or_else(|e| panic!("{e}"));
+        GIC_LAYOUT.call_once(|| layout);
+
+        debug!("gic.setup...");
+        gic.setup(0);
+        debug!("Platform GIC setup");
+        platform_setup(&mut gic);
+
+        SpinMutex::new(gic)
+    });
+}
+
+/// Initialises the GIC on a secondary CPU core which has just come online, or on any core which
+/// has lost its GIC CPU interface state, such as after resuming from system suspend.
+///
+/// This will panic if `init_gic` has not already been called on the primary CPU core.
+pub fn secondary_init_gic() {
+    init_current_el();
+
+    let cpu = current_cpu_index();
+    with_gic(|gic| gic.init_cpu(cpu));
+    set_priority_mask(0xff);
+}
@@ -0,0 +1,364 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! IPv4 addresses, packet headers and interface configuration, and the internet checksum.
+
+use core::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+/// The EtherType of an IPv4 packet.
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+
+/// The size of an IPv4 header without options.
+pub const IPV4_HEADER_SIZE: usize = 20;
+
+/// The IP protocol number of ICMP.
+pub const PROTOCOL_ICMP: u8 = 1;
+
+/// The IP protocol number of TCP.
+pub const PROTOCOL_TCP: u8 = 6;
+
+/// The IP protocol number of UDP.
+pub const PROTOCOL_UDP: u8 = 17;
+
+/// The time to live used for packets we send.
+pub const DEFAULT_TTL: u8 = 64;
+
+/// The don't fragment flag, in the same 16 bits as the fragment offset.
+const FLAG_DONT_FRAGMENT: u16 = 0x4000;
+/// The more fragments flag, in the same 16 bits as the fragment offset.
+const FLAG_MORE_FRAGMENTS: u16 = 0x2000;
+/// The mask of the fragment offset.
+const FRAGMENT_OFFSET_MASK: u16 = 0x1fff;
+
+/// An IPv4 address.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Ipv4Address(pub [u8; 4]);
+
+impl Ipv4Address {
+    /// The unspecified address, 0.0.0.0.
+    pub const UNSPECIFIED: Self = Self([0; 4]);
+
+    /// Returns whether this and `other` are on the same subnet with the given prefix length.
+    pub fn same_subnet(self, other: Self, prefix_length: u8) -> bool {
+        let mask = prefix_mask(prefix_length);
+        u32::from(self) & mask == u32::from(other) & mask
+    }
+}
+
+impl From<Ipv4Address> for u32 {
+    fn from(address: Ipv4Address) -> Self {
+        u32::from_be_bytes(address.0)
+    }
+}
+
+impl From<u32> for Ipv4Address {
+    fn from(address: u32) -> Self {
+        Self(address.to_be_bytes())
+    }
+}
+
+impl Display for Ipv4Address {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let [a, b, c, d] = self.0;
+        write!(f, "{a}.{b}.{c}.{d}")
+    }
+}
+
+impl FromStr for Ipv4Address {
+    type Err = ParseIpError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut octets = [0; 4];
+        let mut parts = s.split('.');
+        for octet in &mut octets {
+            *octet = parts
+                .next()
+                .filter(|part| !part.starts_with('+'))
+                .and_then(|part| part.parse().ok())
+                .ok_or(ParseIpError)?;
+        }
+        if parts.next().is_some() {
+            return Err(ParseIpError);
+        }
+        Ok(Self(octets))
+    }
+}
+
+/// An error parsing an IPv4 address or interface configuration.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ParseIpError;
+
+impl Display for ParseIpError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Invalid IPv4 address")
+    }
+}
+
+/// Returns the netmask for the given prefix length, as a big-endian integer.
+fn prefix_mask(prefix_length: u8) -> u32 {
+    u32::MAX
+        .checked_shl(32 - u32::from(prefix_length.min(32)))
+        .unwrap_or(0)
+}
+
+/// The static IPv4 configuration of a network interface.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct IpConfig {
+    /// The interface's own address.
+    pub address: Ipv4Address,
+    /// The length of the subnet prefix, e.g. 24 for a netmask of 255.255.255.0.
+    pub prefix_length: u8,
+    /// The router to send packets for other subnets to, if there is one.
+    pub gateway: Option<Ipv4Address>,
+}
+
+impl IpConfig {
+    /// Parses an address and prefix length in CIDR notation, such as `10.0.2.15/24`, with an
+    /// optional gateway address.
+    pub fn parse(cidr: &str, gateway: Option<&str>) -> Result<Self, ParseIpError> {
+        let (address, prefix_length) = cidr.split_once('/').ok_or(ParseIpError)?;
+        let prefix_length = prefix_length
+            .parse()
+            .ok()
+            .filter(|&length| length <= 32)
+            .ok_or(ParseIpError)?;
+        Ok(Self {
+            address: address.parse()?,
+            prefix_length,
+            gateway: gateway.map(str::parse).transpose()?,
+        })
+    }
+
+    /// Returns the netmask corresponding to the prefix length.
+    pub fn netmask(&self) -> Ipv4Address {
+        prefix_mask(self.prefix_length).into()
+    }
+
+    /// Returns the address to send packets for the given destination to on the local link: the
+    /// destination itself if it is on our subnet, or else the gateway, if there is one.
+    pub fn next_hop(&self, destination: Ipv4Address) -> Option<Ipv4Address> {
+        if destination.same_subnet(self.address, self.prefix_length) {
+            Some(destination)
+        } else {
+            self.gateway
+        }
+    }
+}
+
+impl Display for IpConfig {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix_length)?;
+        if let Some(gateway) = self.gateway {
+            write!(f, " via {gateway}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Accumulates the ones' complement sum used by the IPv4 and TCP checksums.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Checksum {
+    sum: u64,
+}
+
+impl Checksum {
+    /// Adds the given bytes to the sum, as big-endian 16-bit words.
+    ///
+    /// If there are an odd number of bytes then the last is padded with a zero byte, so all but
+    /// the last call must pass an even number.
+    pub fn add_bytes(&mut self, bytes: &[u8]) {
+        let mut words = bytes.chunks_exact(2);
+        for word in &mut words {
+            self.sum += u64::from(u16::from_be_bytes([word[0], word[1]]));
+        }
+        if let [last] = words.remainder() {
+            self.sum += u64::from(*last) << 8;
+        }
+    }
+
+    /// Adds the given 16-bit word to the sum.
+    pub fn add_u16(&mut self, word: u16) {
+        self.sum += u64::from(word);
+    }
+
+    /// Folds the sum into 16 bits and returns its complement.
+    pub fn finish(self) -> u16 {
+        let mut sum = self.sum;
+        while sum > 0xffff {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        !(sum as u16)
+    }
+}
+
+/// Returns the internet checksum of the given bytes. This is zero for data which includes a
+/// correct checksum.
+pub fn checksum(bytes: &[u8]) -> u16 {
+    let mut checksum = Checksum::default();
+    checksum.add_bytes(bytes);
+    checksum.finish()
+}
+
+/// The fields of an IPv4 header which we use.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Ipv4Header {
+    pub source: Ipv4Address,
+    pub destination: Ipv4Address,
+    pub protocol: u8,
+    pub ttl: u8,
+    pub identification: u16,
+    /// The length of the payload following the header, in bytes.
+    pub payload_length: u16,
+}
+
+impl Ipv4Header {
+    /// Parses the header from the start of the given packet, returning it along with the payload,
+    /// without any padding after it.
+    ///
+    /// Returns `None` if the packet is truncated, isn't IPv4, has an incorrect header checksum or
+    /// is a fragment, as fragments aren't reassembled.
+    pub fn parse(packet: &[u8]) -> Option<(Self, &[u8])> {
+        let first = *packet.first()?;
+        let header_length = usize::from(first & 0x0f) * 4;
+        if first >> 4 != 4 || header_length < IPV4_HEADER_SIZE || packet.len() < header_length {
+            return None;
+        }
+        let header = &packet[..header_length];
+        if checksum(header) != 0 {
+            return None;
+        }
+        let total_length = usize::from(u16::from_be_bytes([header[2], header[3]]));
+        if total_length < header_length || total_length > packet.len() {
+            return None;
+        }
+        let fragment = u16::from_be_bytes([header[6], header[7]]);
+        if fragment & (FLAG_MORE_FRAGMENTS | FRAGMENT_OFFSET_MASK) != 0 {
+            return None;
+        }
+        Some((
+            Self {
+                source: Ipv4Address(header[12..16].try_into().unwrap()),
+                destination: Ipv4Address(header[16..20].try_into().unwrap()),
+                protocol: header[9],
+                ttl: header[8],
+                identification: u16::from_be_bytes([header[4], header[5]]),
+                payload_length: (total_length - header_length) as u16,
+            },
+            &packet[header_length..total_length],
+        ))
+    }
+
+    /// Returns the header in wire format, without options and with the don't fragment flag set.
+    pub fn to_bytes(&self) -> [u8; IPV4_HEADER_SIZE] {
+        let mut bytes = [0; IPV4_HEADER_SIZE];
+        bytes[0] = 0x45;
+        let total_length = IPV4_HEADER_SIZE as u16 + self.payload_length;
+        bytes[2..4].copy_from_slice(&total_length.to_be_bytes());
+        bytes[4..6].copy_from_slice(&self.identification.to_be_bytes());
+        bytes[6..8].copy_from_slice(&FLAG_DONT_FRAGMENT.to_be_bytes());
+        bytes[8] = self.ttl;
+        bytes[9] = self.protocol;
+        bytes[12..16].copy_from_slice(&self.source.0);
+        bytes[16..20].copy_from_slice(&self.destination.0);
+        let checksum = checksum(&bytes);
+        bytes[10..12].copy_from_slice(&checksum.to_be_bytes());
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_format_address() {
+        let address: Ipv4Address = "10.0.2.15".parse().unwrap();
+        assert_eq!(address, Ipv4Address([10, 0, 2, 15]));
+        assert_eq!(address.to_string(), "10.0.2.15");
+        assert_eq!("10.0.2".parse::<Ipv4Address>(), Err(ParseIpError));
+        assert_eq!("10.0.2.15.1".parse::<Ipv4Address>(), Err(ParseIpError));
+        assert_eq!("10.0.2.256".parse::<Ipv4Address>(), Err(ParseIpError));
+        assert_eq!("10.0.+2.1".parse::<Ipv4Address>(), Err(ParseIpError));
+    }
+
+    #[test]
+    fn config_next_hop() {
+        let config = IpConfig::parse("10.0.2.15/24", Some("10.0.2.2")).unwrap();
+        assert_eq!(config.netmask(), Ipv4Address([255, 255, 255, 0]));
+        assert_eq!(config.to_string(), "10.0.2.15/24 via 10.0.2.2");
+        assert_eq!(
+            config.next_hop(Ipv4Address([10, 0, 2, 100])),
+            Some(Ipv4Address([10, 0, 2, 100]))
+        );
+        assert_eq!(
+            config.next_hop(Ipv4Address([8, 8, 8, 8])),
+            Some(Ipv4Address([10, 0, 2, 2]))
+        );
+
+        let config = IpConfig::parse("192.168.1.1/0", None).unwrap();
+        assert_eq!(config.netmask(), Ipv4Address::UNSPECIFIED);
+        assert_eq!(
+            config.next_hop(Ipv4Address([8, 8, 8, 8])),
+            Some(Ipv4Address([8, 8, 8, 8]))
+        );
+        assert_eq!(
+            IpConfig::parse("10.0.0.1/8", None)
+                .unwrap()
+                .next_hop(Ipv4Address([11, 0, 0, 1])),
+            None
+        );
+        assert_eq!(IpConfig::parse("10.0.0.1", None), Err(ParseIpError));
+        assert_eq!(IpConfig::parse("10.0.0.1/33", None), Err(ParseIpError));
+    }
+
+    #[test]
+    fn checksum_example() {
+        // The example from RFC 1071.
+        assert_eq!(
+            checksum(&[0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7]),
+            !0xddf2
+        );
+        assert_eq!(checksum(&[0x01]), !0x0100);
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let header = Ipv4Header {
+            source: Ipv4Address([10, 0, 2, 15]),
+            destination: Ipv4Address([10, 0, 2, 2]),
+            protocol: PROTOCOL_TCP,
+            ttl: DEFAULT_TTL,
+            identification: 0x1234,
+            payload_length: 3,
+        };
+        let mut packet = header.to_bytes().to_vec();
+        packet.extend_from_slice(&[1, 2, 3, 0, 0]);
+        assert_eq!(Ipv4Header::parse(&packet), Some((header, &[1, 2, 3][..])));
+
+        // Corrupt the checksum.
+        packet[10] ^= 1;
+        assert_eq!(Ipv4Header::parse(&packet), None);
+    }
+
+    #[test]
+    fn reject_fragments() {
+        let header = Ipv4Header {
+            source: Ipv4Address([10, 0, 2, 2]),
+            destination: Ipv4Address([10, 0, 2, 15]),
+            protocol: PROTOCOL_TCP,
+            ttl: DEFAULT_TTL,
+            identification: 1,
+            payload_length: 0,
+        };
+        let mut packet = header.to_bytes();
+        packet[6] |= (FLAG_MORE_FRAGMENTS >> 8) as u8;
+        packet[10..12].fill(0);
+        let checksum = checksum(&packet);
+        packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+        assert_eq!(Ipv4Header::parse(&packet), None);
+    }
+}
@@ -0,0 +1,137 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Reusable infrastructure for aarch64 bare-metal programs running under a VMM such as QEMU or
+//! crosvm, at EL1 or EL2.
+//!
+//! This provides the subsystems which osdemo is built on, so that other programs can use them
+//! without forking the demo. A program using it is expected to provide its own entry point,
+//! exception vector table, panic handler, global allocator, initial page table and console UART
+//! driver, and then to initialise things in this order:
+//!
+//! 1. Set [`FDT`] to the device tree passed by the bootloader.
+//! 2. Give the DMA pool some memory with [`dma::init`]. If the host can only access memory which
+//!    has been shared with it, also give the bounce buffer pool some shared memory with
+//!    [`dma::init_bounce_pool`].
+//! 3. Create a [`pagetable::IdMap`], map memory and devices in it, activate it and store it in
+//!    [`pagetable::PAGETABLE`].
+//! 4. Initialise the GIC with [`interrupts::init_gic`].
+//! 5. Create a [`devices::Devices`], and find devices with [`virtio::find_virtio_mmio_devices`],
+//!    [`pci::find_pci_roots`] and [`devices::find_pci_devices`].
+//!
+//! The modules which only contain platform-independent logic, such as parsing registers and
+//! descriptors, are also built for other targets so that they can be unit tested on the host.
+//!
+//! # Features
+//!
+//! - `block` (enabled by default): discover block devices, both VirtIO and, with `drivers`, AHCI,
+//!   NVMe, SD cards and USB mass storage.
+//! - `drivers` (enabled by default): drivers for devices other than VirtIO devices and the console
+//!   UART, namely AHCI, e1000, NVMe, SDHCI and xHCI with USB keyboards and mass storage.
+//! - `net` (enabled by default): discover network interfaces, both VirtIO and, with `drivers`,
+//!   e1000. [`tcpip`] provides a minimal IPv4 and TCP stack over them.
+//! - `smp` (enabled by default): start secondary CPU cores, with [`secondary_entry`], and run work
+//!   on them with [`workqueue`].
+//! - `higher-half`: additionally map normal memory in the upper VA range, and access DMA buffers
+//!   through it.
+
+#![cfg_attr(not(test), no_std)]
+#![deny(clippy::undocumented_unsafe_blocks)]
+#![deny(unsafe_op_in_unsafe_fn)]
+
+extern crate alloc;
+
+pub mod arp;
+pub mod block_cache;
+pub mod block_overlay;
+pub mod device_id;
+pub mod device_owners;
+pub mod ethernet;
+pub mod fallible;
+pub mod fdt;
+pub mod gic_layout;
+pub mod hid_keyboard;
+pub mod icmp;
+pub mod ipv4;
+pub mod pcap;
+pub mod pci_bridge;
+pub mod pci_config;
+pub mod pci_interrupt_map;
+pub mod pci_range;
+pub mod scsi;
+pub mod sd;
+pub mod tcp;
+pub mod tlsf;
+pub mod tracked_heap;
+pub mod uart16550_config;
+pub mod udp;
+pub mod usb;
+
+#[cfg(target_os = "none")]
+pub mod block;
+#[cfg(target_os = "none")]
+pub mod console;
+#[cfg(target_os = "none")]
+pub mod cpus;
+#[cfg(target_os = "none")]
+pub mod devices;
+#[cfg(target_os = "none")]
+pub mod dma;
+#[cfg(target_os = "none")]
+pub mod drivers;
+#[cfg(target_os = "none")]
+pub mod events;
+#[cfg(target_os = "none")]
+pub mod exceptions;
+#[cfg(target_os = "none")]
+pub mod executor;
+#[cfg(target_os = "none")]
+pub mod idle;
+#[cfg(target_os = "none")]
+pub mod input;
+#[cfg(target_os = "none")]
+pub mod interrupts;
+#[cfg(target_os = "none")]
+pub mod net;
+#[cfg(target_os = "none")]
+pub mod pagetable;
+#[cfg(target_os = "none")]
+pub mod pci;
+#[cfg(target_os = "none")]
+pub mod pmu;
+#[cfg(target_os = "none")]
+pub mod power;
+#[cfg(target_os = "none")]
+pub mod psci;
+#[cfg(target_os = "none")]
+pub mod rtc;
+#[cfg(all(target_os = "none", feature = "smp"))]
+pub mod secondary_entry;
+#[cfg(target_os = "none")]
+pub mod semihosting;
+#[cfg(target_os = "none")]
+pub mod shutdown;
+#[cfg(target_os = "none")]
+pub mod system_suspend;
+#[cfg(target_os = "none")]
+pub mod tcpip;
+#[cfg(target_os = "none")]
+pub mod timer;
+#[cfg(target_os = "none")]
+pub mod virtio;
+#[cfg(target_os = "none")]
+pub mod virtio_balloon;
+#[cfg(all(target_os = "none", feature = "smp"))]
+pub mod workqueue;
+
+#[cfg(target_os = "none")]
+use dtoolkit::fdt::Fdt;
+#[cfg(target_os = "none")]
+use spin::Once;
+
+/// The device tree passed by the bootloader.
+///
+/// This must be set before using [`cpus`] or [`psci`], or starting secondary cores.
+#[cfg(target_os = "none")]
+pub static FDT: Once<Fdt<'static>> = Once::new();
@@ -0,0 +1,355 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A common interface to network interfaces, whatever their driver.
+
+#[cfg(feature = "drivers")]
+use crate::drivers::e1000::E1000Error;
+use crate::{
+    drivers::generic_timer::Instant, ethernet::MacAddress, fallible::OutOfMemory,
+    pcap::CaptureBuffer,
+};
+use alloc::boxed::Box;
+use core::fmt::{self, Display, Formatter};
+use spin::mutex::SpinMutex;
+
+/// The capture in progress, if any.
+static CAPTURE: SpinMutex<Option<Capture>> = SpinMutex::new(None);
+
+/// An Ethernet network interface.
+pub trait NetworkInterface {
+    /// Returns a short name for the kind of device, such as `virtio` or `e1000`.
+    fn kind(&self) -> &'static str;
+
+    /// Returns the interface's MAC address.
+    fn mac_address(&self) -> MacAddress;
+
+    /// Sends the given Ethernet frame, excluding the frame check sequence.
+    fn send(&mut self, frame: &[u8]) -> Result<(), NetError>;
+
+    /// Receives a frame into the given buffer if one is available, returning its length.
+    ///
+    /// Returns `Ok(None)` without blocking if no frame has been received.
+    fn receive(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, NetError>;
+
+    /// Waits for the device to raise an interrupt, then acknowledges it. May return early.
+    ///
+    /// Returns immediately if we don't know the device's interrupt, so callers must still poll the
+    /// device.
+    fn wait_for_irq(&mut self);
+
+    /// Returns whether the link is up, or `None` if the driver doesn't know.
+    fn link_up(&self) -> Option<bool> {
+        None
+    }
+
+    /// Returns the link's speed and duplex, or `None` if the driver doesn't know.
+    fn link_speed(&self) -> Option<LinkSpeed> {
+        None
+    }
+
+    /// Changes the MAC address which the interface sends from and accepts frames for.
+    fn set_mac_address(&mut self, _mac_address: MacAddress) -> Result<(), NetError> {
+        Err(NetError::Unsupported)
+    }
+
+    /// Brings the link up or down, if the driver can control it.
+    fn set_link_up(&mut self, _up: bool) -> Result<(), NetError> {
+        Err(NetError::Unsupported)
+    }
+
+    /// Returns whether the interface is administratively enabled.
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    /// Administratively enables or disables the interface. Frames aren't sent or received while it
+    /// is disabled.
+    fn set_enabled(&mut self, _enabled: bool) -> Result<(), NetError> {
+        Err(NetError::Unsupported)
+    }
+
+    /// Returns how many frames have been sent and received, or `None` if they aren't counted.
+    fn stats(&self) -> Option<InterfaceStats> {
+        None
+    }
+}
+
+/// How many frames and bytes an interface has sent and received.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct InterfaceStats {
+    pub rx_frames: u64,
+    pub rx_bytes: u64,
+    pub tx_frames: u64,
+    pub tx_bytes: u64,
+}
+
+impl Display for InterfaceStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "RX {} frames {} bytes, TX {} frames {} bytes",
+            self.rx_frames, self.rx_bytes, self.tx_frames, self.tx_bytes
+        )
+    }
+}
+
+/// The speed and duplex of a network link.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LinkSpeed {
+    /// The speed in megabits per second.
+    pub mbps: u32,
+    pub full_duplex: bool,
+}
+
+impl Display for LinkSpeed {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} Mb/s {} duplex",
+            self.mbps,
+            if self.full_duplex { "full" } else { "half" }
+        )
+    }
+}
+
+/// An error from a network interface driver.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NetError {
+    Virtio(virtio_drivers::Error),
+    #[cfg(feature = "drivers")]
+    E1000(E1000Error),
+    /// The frame is larger than the driver can send.
+    FrameTooLarge(usize),
+    /// The frame received is larger than the buffer provided for it.
+    BufferTooSmall {
+        frame: usize,
+        buffer: usize,
+    },
+    /// The interface has been administratively disabled.
+    Disabled,
+    /// The driver doesn't support the operation.
+    Unsupported,
+}
+
+impl Display for NetError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Virtio(e) => write!(f, "VirtIO error: {e}"),
+            #[cfg(feature = "drivers")]
+            Self::E1000(e) => write!(f, "e1000 error: {e}"),
+            Self::FrameTooLarge(size) => write!(f, "Frame of {size} bytes is too large to send"),
+            Self::BufferTooSmall { frame, buffer } => write!(
+                f,
+                "Received frame of {frame} bytes doesn't fit in {buffer} byte buffer"
+            ),
+            Self::Disabled => write!(f, "Interface is disabled"),
+            Self::Unsupported => write!(f, "Not supported by the driver"),
+        }
+    }
+}
+
+impl From<virtio_drivers::Error> for NetError {
+    fn from(e: virtio_drivers::Error) -> Self {
+        Self::Virtio(e)
+    }
+}
+
+#[cfg(feature = "drivers")]
+impl From<E1000Error> for NetError {
+    fn from(e: E1000Error) -> Self {
+        Self::E1000(e)
+    }
+}
+
+/// A capture of the frames sent and received on one network interface.
+struct Capture {
+    /// The index of the interface being captured.
+    interface: usize,
+    /// When the capture started, by the counter.
+    start: Instant,
+    /// When the capture started, in microseconds since the Unix epoch.
+    start_time_us: u64,
+    buffer: CaptureBuffer,
+}
+
+/// The state of a capture in progress.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CaptureStatus {
+    /// The index of the interface being captured.
+    pub interface: usize,
+    /// The number of frames recorded so far.
+    pub frames: usize,
+    /// The number of frames which didn't fit in the buffer.
+    pub dropped: usize,
+    /// The size of the pcap file so far.
+    pub bytes: usize,
+}
+
+/// An error starting a capture.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CaptureError {
+    /// A capture of the given interface is already in progress.
+    AlreadyCapturing(usize),
+    /// There wasn't enough memory for the capture buffer.
+    OutOfMemory(OutOfMemory),
+}
+
+impl Display for CaptureError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::AlreadyCapturing(interface) => {
+                write!(f, "Already capturing on net{interface}")
+            }
+            Self::OutOfMemory(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// Starts recording the frames sent and received on the given interface into a pcap buffer of at
+/// most `max_size` bytes, with each frame truncated to `snaplen` bytes.
+///
+/// `start_time_us` is the current time in microseconds since the Unix epoch, which the frames'
+/// timestamps are relative to.
+pub fn start_capture(
+    interface: usize,
+    max_size: usize,
+    snaplen: u32,
+    start_time_us: u64,
+) -> Result<(), CaptureError> {
+    let mut capture = CAPTURE.lock();
+    if let Some(capture) = &*capture {
+        return Err(CaptureError::AlreadyCapturing(capture.interface));
+    }
+    let buffer = CaptureBuffer::new(max_size, snaplen).map_err(CaptureError::OutOfMemory)?;
+    *capture = Some(Capture {
+        interface,
+        start: Instant::now(),
+        start_time_us,
+        buffer,
+    });
+    Ok(())
+}
+
+/// Stops the capture in progress, and returns the frames it recorded.
+pub fn stop_capture() -> Option<CaptureBuffer> {
+    CAPTURE.lock().take().map(|capture| capture.buffer)
+}
+
+/// Returns the state of the capture in progress, if any.
+pub fn capture_status() -> Option<CaptureStatus> {
+    CAPTURE.lock().as_ref().map(|capture| CaptureStatus {
+        interface: capture.interface,
+        frames: capture.buffer.frames(),
+        dropped: capture.buffer.dropped(),
+        bytes: capture.buffer.data().len(),
+    })
+}
+
+/// Records the given frame if the given interface is being captured.
+fn capture_frame(interface: usize, frame: &[u8]) {
+    if let Some(capture) = CAPTURE.lock().as_mut()
+        && capture.interface == interface
+    {
+        let elapsed = capture.start.elapsed().as_micros() as u64;
+        capture
+            .buffer
+            .record(capture.start_time_us + elapsed, frame);
+    }
+}
+
+/// Wraps a network interface driver with the state which the network layer keeps for every
+/// interface: whether it has been administratively disabled, whether its frames are being captured,
+/// and how many have been sent and received.
+pub struct ManagedInterface {
+    index: usize,
+    enabled: bool,
+    stats: InterfaceStats,
+    inner: Box<dyn NetworkInterface>,
+}
+
+impl ManagedInterface {
+    /// Wraps the given interface, which has the given index in `Devices::net`.
+    pub fn new(index: usize, inner: Box<dyn NetworkInterface>) -> Self {
+        Self {
+            index,
+            enabled: true,
+            stats: InterfaceStats::default(),
+            inner,
+        }
+    }
+}
+
+impl NetworkInterface for ManagedInterface {
+    fn kind(&self) -> &'static str {
+        self.inner.kind()
+    }
+
+    fn mac_address(&self) -> MacAddress {
+        self.inner.mac_address()
+    }
+
+    fn send(&mut self, frame: &[u8]) -> Result<(), NetError> {
+        if !self.enabled {
+            return Err(NetError::Disabled);
+        }
+        self.inner.send(frame)?;
+        self.stats.tx_frames += 1;
+        self.stats.tx_bytes += frame.len() as u64;
+        capture_frame(self.index, frame);
+        Ok(())
+    }
+
+    fn receive(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, NetError> {
+        let length = self.inner.receive(buffer)?;
+        if !self.enabled {
+            // Keep taking frames from the driver so that its buffers don't fill up, but drop them.
+            return Ok(None);
+        }
+        if let Some(length) = length {
+            self.stats.rx_frames += 1;
+            self.stats.rx_bytes += length as u64;
+            capture_frame(self.index, &buffer[..length]);
+        }
+        Ok(length)
+    }
+
+    fn wait_for_irq(&mut self) {
+        self.inner.wait_for_irq();
+    }
+
+    fn link_up(&self) -> Option<bool> {
+        self.inner.link_up()
+    }
+
+    fn link_speed(&self) -> Option<LinkSpeed> {
+        self.inner.link_speed()
+    }
+
+    fn set_mac_address(&mut self, mac_address: MacAddress) -> Result<(), NetError> {
+        self.inner.set_mac_address(mac_address)
+    }
+
+    fn set_link_up(&mut self, up: bool) -> Result<(), NetError> {
+        self.inner.set_link_up(up)
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Also brings the link up or down if the driver supports it, so that the peer notices.
+    fn set_enabled(&mut self, enabled: bool) -> Result<(), NetError> {
+        match self.inner.set_link_up(enabled) {
+            Ok(()) | Err(NetError::Unsupported) => {}
+            Err(e) => return Err(e),
+        }
+        self.enabled = enabled;
+        Ok(())
+    }
+
+    fn stats(&self) -> Option<InterfaceStats> {
+        Some(self.stats)
+    }
+}
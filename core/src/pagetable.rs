@@ -0,0 +1,591 @@
+// Copyright 2024 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Identity-mapped page tables for EL1 or EL2, with an optional higher-half alias of normal memory.
+//!
+//! The only exception to the identity mapping is user data mapped with `IdMap::map_user_data`, for
+//! memory which user programs allocate at run time.
+
+use crate::{
+    exceptions::current_el,
+    tracked_heap::{HeapStats, TrackedHeap},
+};
+use aarch64_paging::{
+    MapError, Mapping,
+    descriptor::{
+        El1Attributes, El23Attributes, PagingAttributes, PhysicalAddress, VirtualAddress,
+    },
+    paging::{
+        Constraints, El1And0, El2, MemoryRegion, PAGE_SIZE, PageTable, Translation,
+        TranslationRegime, VaRange,
+    },
+};
+use alloc::sync::Arc;
+use core::{
+    alloc::Layout,
+    arch::asm,
+    marker::PhantomData,
+    ptr::{self, NonNull},
+};
+#[cfg(feature = "higher-half")]
+use log::warn;
+use spin::{Once, mutex::SpinMutex};
+
+const ASID: usize = 0;
+const ROOT_LEVEL: usize = 1;
+
+/// The offset at which normal memory is additionally mapped in the upper VA range (`TTBR1_EL1`),
+/// when the `higher-half` feature is enabled.
+///
+/// With a root level of 1 and 4 KiB pages the upper range covers the top 512 GiB of the address
+/// space.
+#[cfg(feature = "higher-half")]
+pub const HIGHER_HALF_OFFSET: usize = 0xffff_ff80_0000_0000;
+
+/// The fields of `TCR_EL1` which control translation table walks for the upper VA range: T1SZ, A1,
+/// EPD1, IRGN1, ORGN1, SH1 and TG1.
+#[cfg(feature = "higher-half")]
+const TCR_EL1_TTBR1_MASK: u64 = 0xffff_0000;
+
+/// The values of the `TCR_EL1` fields in `TCR_EL1_TTBR1_MASK` for `HIGHER_HALF_OFFSET`.
+///
+/// T1SZ is 25 for a 39-bit (512 GiB) range, walks are enabled by leaving EPD1 clear, the tables are
+/// in inner shareable write-back cacheable memory like those for `TTBR0_EL1`, and TG1 is 4 KiB.
+#[cfg(feature = "higher-half")]
+const TCR_EL1_TTBR1: u64 = (25 << 16) | (0b01 << 24) | (0b01 << 26) | (0b11 << 28) | (0b10 << 30);
+
+pub const EL1_DEVICE_ATTRIBUTES: El1Attributes = El1Attributes::VALID
+    .union(El1Attributes::ATTRIBUTE_INDEX_0)
+    .union(El1Attributes::ACCESSED)
+    .union(El1Attributes::UXN);
+pub const EL1_MEMORY_ATTRIBUTES: El1Attributes = El1Attributes::VALID
+    .union(El1Attributes::ATTRIBUTE_INDEX_1)
+    .union(El1Attributes::INNER_SHAREABLE)
+    .union(El1Attributes::ACCESSED)
+    .union(El1Attributes::NON_GLOBAL);
+const EL1_USER_CODE_ATTRIBUTES: El1Attributes = EL1_MEMORY_ATTRIBUTES
+    .union(El1Attributes::USER)
+    .union(El1Attributes::READ_ONLY)
+    .union(El1Attributes::PXN);
+const EL1_USER_DATA_ATTRIBUTES: El1Attributes = EL1_MEMORY_ATTRIBUTES
+    .union(El1Attributes::USER)
+    .union(El1Attributes::UXN)
+    .union(El1Attributes::PXN);
+const EL2_DEVICE_ATTRIBUTES: El23Attributes = El23Attributes::VALID
+    .union(El23Attributes::ATTRIBUTE_INDEX_0)
+    .union(El23Attributes::ACCESSED)
+    .union(El23Attributes::XN);
+const EL2_MEMORY_ATTRIBUTES: El23Attributes = El23Attributes::VALID
+    .union(El23Attributes::ATTRIBUTE_INDEX_1)
+    .union(El23Attributes::INNER_SHAREABLE)
+    .union(El23Attributes::ACCESSED)
+    .union(El23Attributes::NON_GLOBAL);
+
+/// The active page table, once it has been activated on the primary core.
+pub static PAGETABLE: Once<SpinMutex<IdMap>> = Once::new();
+
+/// An allocator for page table pages, which may be shared between several mappings.
+type PageAllocator = Arc<SpinMutex<TrackedHeap<32>>>;
+
+#[derive(Debug)]
+pub struct IdTranslation<A: PagingAttributes> {
+    page_allocator: PageAllocator,
+    _attributes: PhantomData<A>,
+}
+
+impl<A: PagingAttributes> IdTranslation<A> {
+    fn new(page_allocator: PageAllocator) -> Self {
+        Self {
+            page_allocator,
+            _attributes: PhantomData,
+        }
+    }
+
+    fn virtual_to_physical(va: VirtualAddress) -> PhysicalAddress {
+        PhysicalAddress(virt_to_phys(va.0))
+    }
+}
+
+/// Returns the physical address corresponding to the given virtual address.
+///
+/// This handles addresses in both the identity-mapped lower range and, if enabled, the higher-half
+/// alias of normal memory.
+pub fn virt_to_phys(va: usize) -> usize {
+    #[cfg(feature = "higher-half")]
+    if va >= HIGHER_HALF_OFFSET {
+        return va - HIGHER_HALF_OFFSET;
+    }
+    va
+}
+
+/// Returns the virtual address through which the kernel should access the given physical address
+/// of normal memory.
+///
+/// This is the higher-half alias if the `higher-half` feature is enabled and the upper range has
+/// been activated, otherwise the identity mapping.
+pub fn phys_to_virt(pa: usize) -> usize {
+    #[cfg(feature = "higher-half")]
+    if PAGETABLE.get().is_some_and(IdMap::has_upper_range) {
+        return pa + HIGHER_HALF_OFFSET;
+    }
+    pa
+}
+
+impl<A: PagingAttributes> Translation<A> for IdTranslation<A> {
+    fn allocate_table(&mut self) -> (NonNull<PageTable<A>>, PhysicalAddress) {
+        let layout = Layout::new::<PageTable<A>>();
+        let pointer = self
+            .page_allocator
+            .lock()
+            .alloc(layout)
+            .expect("Failed to allocate page for pagetable");
+        // SAFETY: The allocator has just given us a new allocation so it must be valid and
+        // unaliased.
+        unsafe {
+            ptr::write_bytes(pointer.as_ptr(), 0, layout.size());
+        }
+        let table = pointer.cast();
+
+        // Page tables are always allocated from identity-mapped memory, so they can be accessed
+        // before the upper range is activated.
+        (
+            table,
+            PhysicalAddress(virt_to_phys(table.as_ptr() as usize)),
+        )
+    }
+
+    unsafe fn deallocate_table(&mut self, page_table: NonNull<PageTable<A>>) {
+        let layout = Layout::new::<PageTable<A>>();
+        // SAFETY: Our caller promises that the page table was allocated by `allocate_table` and not
+        // yet deallocated, and it won't be used after this.
+        unsafe {
+            self.page_allocator
+                .lock()
+                .dealloc(page_table.cast(), layout);
+        }
+    }
+
+    fn physical_to_virtual(&self, pa: PhysicalAddress) -> NonNull<PageTable<A>> {
+        // Always use the identity mapping for page tables, as above.
+        NonNull::new(pa.0 as *mut PageTable<A>).expect("Got physical address 0 for pagetable")
+    }
+}
+
+// SAFETY: An `&IdTranslation` only allows looking up the mapping from a physical to virtual
+// address, which is safe to do from any context.
+unsafe impl<A: PagingAttributes> Sync for IdTranslation<A> {}
+
+/// Manages a page table using identity mapping, at either EL1 or EL2.
+///
+/// At EL1 with the `higher-half` feature enabled, normal memory is additionally mapped at
+/// `HIGHER_HALF_OFFSET` in the upper VA range.
+#[derive(Debug)]
+pub enum IdMap {
+    El1 {
+        mapping: Mapping<IdTranslation<El1Attributes>, El1And0>,
+        #[cfg(feature = "higher-half")]
+        upper: Mapping<IdTranslation<El1Attributes>, El1And0>,
+        page_allocator: PageAllocator,
+    },
+    El2 {
+        mapping: Mapping<IdTranslation<El23Attributes>, El2>,
+        page_allocator: PageAllocator,
+    },
+}
+
+impl IdMap {
+    /// Creates a new `IdMap` using the given page allocator.
+    pub fn new(page_allocator: TrackedHeap<32>) -> Self {
+        let page_allocator = Arc::new(SpinMutex::new(page_allocator));
+        if current_el() == 2 {
+            #[cfg(feature = "higher-half")]
+            warn!("Higher-half mapping is not supported at EL2, using identity mapping only");
+            Self::El2 {
+                mapping: Mapping::new(IdTranslation::new(page_allocator.clone()), ROOT_LEVEL, El2),
+                page_allocator,
+            }
+        } else {
+            Self::El1 {
+                mapping: Mapping::with_asid_and_va_range(
+                    IdTranslation::new(page_allocator.clone()),
+                    ASID,
+                    ROOT_LEVEL,
+                    El1And0,
+                    VaRange::Lower,
+                ),
+                #[cfg(feature = "higher-half")]
+                upper: Mapping::with_asid_and_va_range(
+                    IdTranslation::new(page_allocator.clone()),
+                    ASID,
+                    ROOT_LEVEL,
+                    El1And0,
+                    VaRange::Upper,
+                ),
+                page_allocator,
+            }
+        }
+    }
+
+    /// Returns whether normal memory is also mapped in the upper VA range.
+    pub fn has_upper_range(&self) -> bool {
+        match self {
+            #[cfg(feature = "higher-half")]
+            IdMap::El1 { upper, .. } => upper.active(),
+            _ => false,
+        }
+    }
+
+    /// Returns the current and peak usage of the allocator for page table pages.
+    pub fn page_allocator_stats(&self) -> HeapStats {
+        match self {
+            IdMap::El1 { page_allocator, .. } | IdMap::El2 { page_allocator, .. } => {
+                page_allocator.lock().stats()
+            }
+        }
+    }
+
+    /// Takes the memory left free in the allocator for page table pages, passing each block of it
+    /// to the given function so that it can be used for something else.
+    ///
+    /// This should only be called once all mappings have been made, as any later change which needs
+    /// a new page table will fail. Returns the number of bytes taken.
+    pub fn reclaim_page_allocator(&mut self, reclaim: impl FnMut(NonNull<u8>, usize)) -> usize {
+        match self {
+            IdMap::El1 { page_allocator, .. } | IdMap::El2 { page_allocator, .. } => {
+                page_allocator.lock().reclaim_free(reclaim)
+            }
+        }
+    }
+
+    /// Returns the size in bytes of the virtual address space which can be mapped in this page
+    /// table.
+    pub fn size(&self) -> usize {
+        match self {
+            IdMap::El1 { mapping, .. } => mapping.size(),
+            IdMap::El2 { mapping, .. } => mapping.size(),
+        }
+    }
+
+    /// Identity-maps the given range of pages as normal memory.
+    ///
+    /// If the `higher-half` feature is enabled then the range is also mapped at
+    /// `HIGHER_HALF_OFFSET` in the upper VA range.
+    pub fn map_memory(&mut self, range: &MemoryRegion) -> Result<(), MapError> {
+        match self {
+            #[cfg(feature = "higher-half")]
+            IdMap::El1 { mapping, upper, .. } => {
+                let pa = IdTranslation::<El1Attributes>::virtual_to_physical(range.start());
+                mapping.map_range(range, pa, EL1_MEMORY_ATTRIBUTES, Constraints::empty())?;
+                let upper_range = MemoryRegion::new(
+                    range.start().0 + HIGHER_HALF_OFFSET,
+                    range.end().0 + HIGHER_HALF_OFFSET,
+                );
+                upper.map_range(
+                    &upper_range,
+                    pa,
+                    EL1_MEMORY_ATTRIBUTES,
+                    Constraints::empty(),
+                )
+            }
+            #[cfg(not(feature = "higher-half"))]
+            IdMap::El1 { mapping, .. } => {
+                let pa = IdTranslation::<El1Attributes>::virtual_to_physical(range.start());
+                mapping.map_range(range, pa, EL1_MEMORY_ATTRIBUTES, Constraints::empty())
+            }
+            IdMap::El2 { mapping, .. } => {
+                let pa = IdTranslation::<El23Attributes>::virtual_to_physical(range.start());
+                mapping.map_range(range, pa, EL2_MEMORY_ATTRIBUTES, Constraints::empty())
+            }
+        }
+    }
+
+    /// Identity-maps the given range of pages as device memory.
+    pub fn map_device(&mut self, range: &MemoryRegion) -> Result<(), MapError> {
+        match self {
+            IdMap::El1 { mapping, .. } => {
+                let pa = IdTranslation::<El1Attributes>::virtual_to_physical(range.start());
+                mapping.map_range(range, pa, EL1_DEVICE_ATTRIBUTES, Constraints::empty())
+            }
+            IdMap::El2 { mapping, .. } => {
+                let pa = IdTranslation::<El23Attributes>::virtual_to_physical(range.start());
+                mapping.map_range(range, pa, EL2_DEVICE_ATTRIBUTES, Constraints::empty())
+            }
+        }
+    }
+
+    /// Identity-maps the given range of pages as normal memory accessible from EL0.
+    ///
+    /// If `executable` is true then the pages will be read-only and executable from EL0, otherwise
+    /// they will be writable but not executable. This is only supported at EL1, as there is no EL0
+    /// in the EL2 translation regime.
+    pub fn map_user(&mut self, range: &MemoryRegion, executable: bool) -> Result<(), MapError> {
+        match self {
+            IdMap::El1 { mapping, .. } => {
+                let pa = IdTranslation::<El1Attributes>::virtual_to_physical(range.start());
+                let attributes = if executable {
+                    EL1_USER_CODE_ATTRIBUTES
+                } else {
+                    EL1_USER_DATA_ATTRIBUTES
+                };
+                mapping.map_range(range, pa, attributes, Constraints::empty())
+            }
+            IdMap::El2 { .. } => panic!("EL0 mappings are not supported at EL2"),
+        }
+    }
+
+    /// Makes the given range of pages, which must have been mapped with `map_user` as not
+    /// executable, read-only or writable again, from both EL0 and EL1.
+    ///
+    /// Changing permissions doesn't need break-before-make, so this may be used on the active page
+    /// table. Any stale TLB entries are invalidated on all cores.
+    pub fn set_user_data_read_only(
+        &mut self,
+        range: &MemoryRegion,
+        read_only: bool,
+    ) -> Result<(), MapError> {
+        match self {
+            IdMap::El1 { mapping, .. } => {
+                let attributes = if read_only {
+                    EL1_USER_DATA_ATTRIBUTES.union(El1Attributes::READ_ONLY)
+                } else {
+                    EL1_USER_DATA_ATTRIBUTES
+                };
+                mapping.modify_range(range, &|region, descriptor| {
+                    if descriptor.is_table() {
+                        Ok(())
+                    } else {
+                        let pa =
+                            IdTranslation::<El1Attributes>::virtual_to_physical(region.start());
+                        descriptor.set(pa, attributes)
+                    }
+                })?;
+                invalidate_el1_tlb();
+                Ok(())
+            }
+            IdMap::El2 { .. } => panic!("EL0 mappings are not supported at EL2"),
+        }
+    }
+
+    /// Allocates the subtables needed to map the given range of pages at page granularity, leaving
+    /// the pages unmapped, so that they can be mapped later with `map_user_data` without
+    /// allocating.
+    ///
+    /// This should be called before the page allocator is reclaimed. It is only supported at EL1.
+    pub fn reserve_user_range(&mut self, range: &MemoryRegion) -> Result<(), MapError> {
+        match self {
+            IdMap::El1 { mapping, .. } => mapping.map_range(
+                range,
+                PhysicalAddress(0),
+                El1Attributes::empty(),
+                Constraints::NO_BLOCK_MAPPINGS,
+            ),
+            IdMap::El2 { .. } => panic!("EL0 mappings are not supported at EL2"),
+        }
+    }
+
+    /// Maps the given range of pages, which must be unmapped and reserved with
+    /// `reserve_user_range`, to the given physical address as writable normal memory accessible
+    /// from EL0. Unlike the other mappings, this isn't an identity mapping.
+    ///
+    /// Mapping unmapped pages doesn't need break-before-make, so this may be used on the active
+    /// page table.
+    pub fn map_user_data(&mut self, range: &MemoryRegion, pa: usize) -> Result<(), MapError> {
+        match self {
+            IdMap::El1 { mapping, .. } => {
+                mapping.map_range(
+                    range,
+                    PhysicalAddress(pa),
+                    EL1_USER_DATA_ATTRIBUTES,
+                    Constraints::NO_BLOCK_MAPPINGS,
+                )?;
+                invalidate_el1_tlb();
+                Ok(())
+            }
+            IdMap::El2 { .. } => panic!("EL0 mappings are not supported at EL2"),
+        }
+    }
+
+    /// Unmaps the given range of pages mapped with `map_user_data`, leaving the subtables in place
+    /// so that they can be mapped again.
+    ///
+    /// This may be used on the active page table. Any stale TLB entries are invalidated on all
+    /// cores.
+    pub fn unmap_user_data(&mut self, range: &MemoryRegion) -> Result<(), MapError> {
+        match self {
+            IdMap::El1 { mapping, .. } => {
+                mapping.modify_range(range, &|_, descriptor| {
+                    if descriptor.is_table() {
+                        Ok(())
+                    } else {
+                        descriptor.set(PhysicalAddress(0), El1Attributes::empty())
+                    }
+                })?;
+                invalidate_el1_tlb();
+                Ok(())
+            }
+            IdMap::El2 { .. } => panic!("EL0 mappings are not supported at EL2"),
+        }
+    }
+
+    /// Removes any mappings of the given range of pages from the identity mapping, freeing any
+    /// subtables which become empty.
+    ///
+    /// Any higher-half alias of the range is left mapped. This doesn't follow break-before-make, so
+    /// should only be used on a page table which hasn't been activated.
+    pub fn unmap(&mut self, range: &MemoryRegion) -> Result<(), MapError> {
+        match self {
+            IdMap::El1 { mapping, .. } => unmap_range(mapping, range),
+            IdMap::El2 { mapping, .. } => unmap_range(mapping, range),
+        }
+    }
+
+    /// Returns the physical address to which the given virtual address in the lower range is
+    /// mapped, or `None` if it is unmapped.
+    pub fn translate(&self, va: usize) -> Option<usize> {
+        match self {
+            IdMap::El1 { mapping, .. } => translate(mapping, va),
+            IdMap::El2 { mapping, .. } => translate(mapping, va),
+        }
+    }
+
+    /// Activates the page table by setting `TTBR0_EL1` (and `TTBR1_EL1` for the higher half, if
+    /// enabled) to point to it.
+    ///
+    /// For the higher half, this also configures `TCR_EL1` to enable walks of the upper VA range.
+    ///
+    /// Panics if the `IdMap` has already been activated.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the page table doesn't unmap any memory which the program is
+    /// using. The page table must not be dropped as long as its mappings are required, as it will
+    /// automatically be deactivated when it is dropped.
+    pub unsafe fn activate(&self) {
+        // SAFETY: The caller has ensured that the page table doesn't unmap any memory and is held
+        // for long enough. Mappings are unique because it uses identity mapping, so it won't
+        // introduce any aliases.
+        unsafe {
+            match self {
+                IdMap::El1 {
+                    mapping,
+                    #[cfg(feature = "higher-half")]
+                    upper,
+                    ..
+                } => {
+                    mapping.activate();
+                    #[cfg(feature = "higher-half")]
+                    {
+                        upper.activate();
+                        enable_upper_range_walks();
+                    }
+                }
+                IdMap::El2 { mapping, .. } => {
+                    mapping.activate();
+                }
+            }
+        }
+    }
+
+    /// Activates the page table on a secondary CPU core by setting `TTBR0_EL1` to point to it.
+    ///
+    /// Panics if `IdMap` has not already been activated on the primary core.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the page table doesn't unmap any memory which the program is
+    /// using, and that it isn't dropped as long as it is active on the secondary core.
+    pub unsafe fn activate_secondary(&self) {
+        match self {
+            IdMap::El1 { mapping, .. } => {
+                assert!(mapping.active());
+            }
+            IdMap::El2 { mapping, .. } => {
+                assert!(mapping.active());
+            }
+        }
+        // SAFETY: Our caller promised that the page table doesn't unmapping anything which the
+        // program needs, and that it won't be dropped while it is active.
+        unsafe {
+            self.activate();
+        }
+    }
+}
+
+/// Configures `TCR_EL1` for walks of the upper VA range from `TTBR1_EL1`, which whatever set up the
+/// MMU before us may have left disabled or with a different size or granule.
+///
+/// This must be called after `TTBR1_EL1` points to the upper range's page table, so that no walks
+/// are made from whatever it pointed to before.
+#[cfg(feature = "higher-half")]
+fn enable_upper_range_walks() {
+    // SAFETY: Only the fields for the upper VA range are changed, and `TTBR1_EL1` already points to
+    // a valid page table for it. Nothing was mapped in the upper range before, so there are no TLB
+    // entries for it which could be wrong.
+    unsafe {
+        asm!(
+            "mrs {tcr}, tcr_el1",
+            "bic {tcr}, {tcr}, {mask}",
+            "orr {tcr}, {tcr}, {value}",
+            "msr tcr_el1, {tcr}",
+            "isb",
+            tcr = out(reg) _,
+            mask = in(reg) TCR_EL1_TTBR1_MASK,
+            value = in(reg) TCR_EL1_TTBR1,
+            options(nostack)
+        );
+    }
+}
+
+/// Invalidates all EL1&0 TLB entries on all cores in the inner shareable domain, so that changes to
+/// the active page table take effect.
+fn invalidate_el1_tlb() {
+    // SAFETY: Invalidating TLB entries only makes the MMU walk the page table again.
+    unsafe {
+        asm!(
+            "dsb ishst",
+            "tlbi vmalle1is",
+            "dsb ish",
+            "isb",
+            options(nostack)
+        );
+    }
+}
+
+/// Clears every block and page descriptor covering the given range, then frees empty subtables.
+fn unmap_range<R: TranslationRegime>(
+    mapping: &mut Mapping<IdTranslation<R::Attributes>, R>,
+    range: &MemoryRegion,
+) -> Result<(), MapError> {
+    mapping.modify_range(range, &|_, descriptor| {
+        if descriptor.is_table() {
+            Ok(())
+        } else {
+            descriptor.set(PhysicalAddress(0), R::Attributes::default())
+        }
+    })?;
+    mapping.compact_subtables();
+    Ok(())
+}
+
+/// Walks the given mapping to find the physical address for the given virtual address.
+fn translate<R: TranslationRegime>(
+    mapping: &Mapping<IdTranslation<R::Attributes>, R>,
+    va: usize,
+) -> Option<usize> {
+    let mut pa = None;
+    mapping
+        .walk_range(
+            &MemoryRegion::new(va, va + 1),
+            &mut |_, descriptor, level| {
+                if descriptor.is_valid() {
+                    // Each level above the leaf level covers 512 times as much as the one below.
+                    let block_size = PAGE_SIZE << (9 * (3 - level));
+                    pa = Some(descriptor.output_address().0 + va % block_size);
+                }
+                Ok(())
+            },
+        )
+        .ok()?;
+    pa
+}
@@ -0,0 +1,180 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Recording Ethernet frames in the classic libpcap file format, which Wireshark and tcpdump can
+//! read.
+
+use crate::fallible::{OutOfMemory, try_reserve};
+use alloc::vec::Vec;
+
+/// The magic number at the start of a pcap file with microsecond timestamps.
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+
+/// The version of the file format.
+const PCAP_VERSION_MAJOR: u16 = 2;
+const PCAP_VERSION_MINOR: u16 = 4;
+
+/// The link-layer header type for Ethernet frames.
+const LINKTYPE_ETHERNET: u32 = 1;
+
+/// The size of the header at the start of a pcap file.
+pub const FILE_HEADER_SIZE: usize = 24;
+
+/// The size of the header before each frame in a pcap file.
+pub const RECORD_HEADER_SIZE: usize = 16;
+
+/// Returns the header for a pcap file of Ethernet frames, each truncated to at most `snaplen`
+/// bytes.
+pub fn file_header(snaplen: u32) -> [u8; FILE_HEADER_SIZE] {
+    let mut header = [0; FILE_HEADER_SIZE];
+    header[0..4].copy_from_slice(&PCAP_MAGIC.to_le_bytes());
+    header[4..6].copy_from_slice(&PCAP_VERSION_MAJOR.to_le_bytes());
+    header[6..8].copy_from_slice(&PCAP_VERSION_MINOR.to_le_bytes());
+    // The timezone offset and timestamp accuracy are always 0.
+    header[16..20].copy_from_slice(&snaplen.to_le_bytes());
+    header[20..24].copy_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+    header
+}
+
+/// The header before each frame in a pcap file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RecordHeader {
+    /// When the frame was sent or received, in microseconds since the Unix epoch.
+    pub timestamp_us: u64,
+    /// The number of bytes of the frame which follow the header.
+    pub captured_length: u32,
+    /// The length of the frame before it was truncated.
+    pub original_length: u32,
+}
+
+impl RecordHeader {
+    /// Returns the header in pcap's little-endian format.
+    pub fn to_bytes(&self) -> [u8; RECORD_HEADER_SIZE] {
+        let seconds = (self.timestamp_us / 1_000_000) as u32;
+        let microseconds = (self.timestamp_us % 1_000_000) as u32;
+        let mut bytes = [0; RECORD_HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&seconds.to_le_bytes());
+        bytes[4..8].copy_from_slice(&microseconds.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.captured_length.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.original_length.to_le_bytes());
+        bytes
+    }
+}
+
+/// A complete pcap file being built in memory, with a fixed maximum size.
+///
+/// The space is reserved up front, so that recording a frame never allocates.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CaptureBuffer {
+    data: Vec<u8>,
+    max_size: usize,
+    snaplen: u32,
+    frames: usize,
+    dropped: usize,
+}
+
+impl CaptureBuffer {
+    /// Creates a buffer holding at most `max_size` bytes including the file header, for frames
+    /// truncated to at most `snaplen` bytes.
+    ///
+    /// Returns an error if there isn't enough memory to reserve the space.
+    pub fn new(max_size: usize, snaplen: u32) -> Result<Self, OutOfMemory> {
+        let mut data = Vec::new();
+        let max_size = max_size.max(FILE_HEADER_SIZE);
+        try_reserve(&mut data, max_size)?;
+        data.extend_from_slice(&file_header(snaplen));
+        Ok(Self {
+            data,
+            max_size,
+            snaplen,
+            frames: 0,
+            dropped: 0,
+        })
+    }
+
+    /// Records the given frame, truncated to the snapshot length, if there is space for it.
+    ///
+    /// Returns whether it was recorded. Frames which don't fit are counted as dropped.
+    pub fn record(&mut self, timestamp_us: u64, frame: &[u8]) -> bool {
+        let captured = &frame[..frame.len().min(self.snaplen as usize)];
+        if self.data.len() + RECORD_HEADER_SIZE + captured.len() > self.max_size {
+            self.dropped += 1;
+            return false;
+        }
+        let header = RecordHeader {
+            timestamp_us,
+            captured_length: captured.len() as u32,
+            original_length: frame.len() as u32,
+        };
+        self.data.extend_from_slice(&header.to_bytes());
+        self.data.extend_from_slice(captured);
+        self.frames += 1;
+        true
+    }
+
+    /// Returns the pcap file recorded so far.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the number of frames recorded.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Returns the number of frames which didn't fit in the buffer.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header() {
+        assert_eq!(
+            file_header(65535),
+            [
+                0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 1, 0,
+                0, 0
+            ]
+        );
+        assert_eq!(
+            RecordHeader {
+                timestamp_us: 1_700_000_000_250_000,
+                captured_length: 60,
+                original_length: 1514,
+            }
+            .to_bytes(),
+            [
+                0x00, 0xf1, 0x53, 0x65, 0x90, 0xd0, 0x03, 0x00, 60, 0, 0, 0, 0xea, 0x05, 0, 0
+            ]
+        );
+    }
+
+    #[test]
+    fn record_frames() {
+        let mut buffer =
+            CaptureBuffer::new(FILE_HEADER_SIZE + 2 * RECORD_HEADER_SIZE + 12, 8).unwrap();
+        assert_eq!(buffer.data().len(), FILE_HEADER_SIZE);
+
+        // Truncated to the snapshot length.
+        assert!(buffer.record(1, &[1; 10]));
+        assert!(buffer.record(2, &[2; 4]));
+        // No space left.
+        assert!(!buffer.record(3, &[3; 1]));
+        assert_eq!(buffer.frames(), 2);
+        assert_eq!(buffer.dropped(), 1);
+
+        let records = &buffer.data()[FILE_HEADER_SIZE..];
+        assert_eq!(records.len(), 2 * RECORD_HEADER_SIZE + 12);
+        assert_eq!(records[8..12], 8u32.to_le_bytes());
+        assert_eq!(records[12..16], 10u32.to_le_bytes());
+        assert_eq!(records[RECORD_HEADER_SIZE..][..8], [1; 8]);
+        assert_eq!(records[RECORD_HEADER_SIZE + 8..][4..8], 2u32.to_le_bytes());
+        assert_eq!(records[2 * RECORD_HEADER_SIZE + 8..], [2; 4]);
+    }
+}
@@ -0,0 +1,901 @@
+// Copyright 2024 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Finding PCI root complexes in the device tree, and allocating BARs and interrupts for their
+//! functions.
+
+use crate::{
+    fdt::{RegEntry, find_phandle, gic_interrupt, property_cells, property_u32, reg_entries},
+    pagetable::IdMap,
+    pci_bridge::{
+        BRIDGE_BAR_COUNT, BUS_NUMBERS_OFFSET, DISABLED_IO_WINDOW, IO_WINDOW_OFFSET,
+        MEMORY_WINDOW_OFFSET, PREFETCHABLE_BASE_UPPER_OFFSET, PREFETCHABLE_LIMIT_UPPER_OFFSET,
+        PREFETCHABLE_WINDOW_OFFSET, bridge_window_size, bus_numbers_register,
+        memory_window_register, swizzle_interrupt_pin,
+    },
+    pci_config::{CONFIG_SPACE_SIZE, CapabilityDescription},
+    pci_interrupt_map::PciInterruptMap,
+    pci_range::{PciRange, PciRangeType, parse_bus_range},
+};
+use alloc::{
+    collections::{btree_map::BTreeMap, btree_set::BTreeSet},
+    vec::Vec,
+};
+use arm_gic::{IntId, Trigger};
+use buddy_system_allocator::FrameAllocator;
+use core::{
+    alloc::Layout,
+    array,
+    cmp::Reverse,
+    fmt::{self, Debug, Display, Formatter},
+    mem::{self, align_of, size_of},
+    ops::{Deref, DerefMut, Range, RangeInclusive},
+    ptr::NonNull,
+};
+use dtoolkit::{
+    Node,
+    fdt::{Fdt, FdtNode},
+    standard::NodeStandard,
+};
+use log::{error, info, warn};
+use virtio_drivers::transport::pci::bus::{
+    BarInfo, Cam, CapabilityInfo, Command, ConfigurationAccess, DeviceFunction, DeviceFunctionInfo,
+    HeaderType, MemoryBarType, MmioCam, PciError, PciRoot,
+};
+
+pub const PCI_COMPATIBLE: &str = "pci-host-cam-generic";
+pub const PCIE_COMPATIBLE: &str = "pci-host-ecam-generic";
+
+/// The offset of the register containing the header type in the PCI configuration header.
+const HEADER_TYPE_REGISTER_OFFSET: u8 = 0x0c;
+
+/// The offset of the interrupt line and interrupt pin registers in the PCI configuration header.
+const INTERRUPT_REGISTER_OFFSET: u8 = 0x3c;
+
+/// The legacy INTx interrupts of the devices on a PCI root, and how they are triggered.
+pub type PciIrqs = BTreeMap<DeviceFunction, (IntId, Trigger)>;
+
+/// An initialised PCI root complex.
+pub struct PciRootComplex {
+    root: PciRoot<MmioCam<'static>>,
+    /// Used only to read configuration space, without going through `root`.
+    read_only_cam: MmioCam<'static>,
+    /// The first bus number of the root.
+    root_bus: u8,
+    /// The numbers of all buses found on the root, including those behind bridges.
+    buses: Vec<u8>,
+    /// The bridge to each bus behind a bridge, by secondary bus number.
+    bridges: BTreeMap<u8, DeviceFunction>,
+    /// The allocator for BARs on each bus.
+    allocators: BTreeMap<u8, PciBarAllocator>,
+    interrupt_map: Option<PciInterruptMap>,
+    /// The root's I/O space ranges, to translate I/O BAR addresses to CPU physical addresses.
+    io_ranges: Vec<PciRange>,
+    /// Devices whose BARs have been allocated.
+    initialised: BTreeSet<DeviceFunction>,
+    /// Devices which a driver has claimed.
+    claimed: BTreeSet<DeviceFunction>,
+    /// The legacy interrupts of devices on the root.
+    pub irqs: PciIrqs,
+}
+
+impl PciRootComplex {
+    /// Returns information about all the BARs of the given device function.
+    ///
+    /// Unlike `PciRoot::bars`, this only looks at the two BARs of PCI-to-PCI bridges, as the rest of
+    /// their header is used for other registers.
+    pub fn device_bars(
+        &mut self,
+        device_function: DeviceFunction,
+    ) -> Result<[Option<BarInfo>; 6], PciError> {
+        let header_type = HeaderType::from(
+            (self.read_config(device_function, HEADER_TYPE_REGISTER_OFFSET) >> 16) as u8 & 0x7f,
+        );
+        let bar_count = if header_type == HeaderType::PciPciBridge {
+            BRIDGE_BAR_COUNT
+        } else {
+            6
+        };
+        device_bars(&mut self.root, device_function, bar_count)
+    }
+
+    /// Returns an accessor for the given I/O BAR of the given device function.
+    ///
+    /// Returns `None` if the BAR isn't an I/O BAR, or hasn't been allocated and enabled.
+    pub fn io_bar(&mut self, device_function: DeviceFunction, bar_index: usize) -> Option<IoBar> {
+        let (_, command) = self.get_status_command(device_function);
+        if !command.contains(Command::IO_SPACE) {
+            return None;
+        }
+        let Some(BarInfo::IO { address, size }) =
+            *self.device_bars(device_function).ok()?.get(bar_index)?
+        else {
+            return None;
+        };
+        if address == 0 || size == 0 {
+            return None;
+        }
+        let cpu_physical = self
+            .io_ranges
+            .iter()
+            .find_map(|range| range.bus_to_cpu(address as usize, size as usize))?;
+        Some(IoBar {
+            base: NonNull::new(cpu_physical as *mut u8)?,
+            size: size as usize,
+        })
+    }
+
+    /// Returns all device functions on all buses of the root.
+    pub fn enumerate_devices(&self) -> Vec<(DeviceFunction, DeviceFunctionInfo)> {
+        self.buses
+            .iter()
+            .flat_map(|&bus| self.root.enumerate_bus(bus))
+            .collect()
+    }
+
+    /// Returns the devices which have been initialised but not yet claimed by a driver.
+    pub fn unclaimed_devices(&self) -> Vec<(DeviceFunction, DeviceFunctionInfo)> {
+        self.enumerate_devices()
+            .into_iter()
+            .filter(|(device_function, _)| {
+                self.initialised.contains(device_function)
+                    && !self.claimed.contains(device_function)
+            })
+            .collect()
+    }
+
+    /// Marks the given device as claimed by a driver, so that it won't be offered to other drivers.
+    pub fn claim(&mut self, device_function: DeviceFunction) {
+        self.claimed.insert(device_function);
+    }
+
+    /// Returns whether a driver has claimed the given device.
+    pub fn is_claimed(&self, device_function: DeviceFunction) -> bool {
+        self.claimed.contains(&device_function)
+    }
+
+    /// Initialises any devices which have appeared on the root's buses since it was last scanned,
+    /// allocating their BARs from what is left of the root's ranges, and returns them.
+    ///
+    /// Bridges which weren't present when the root was first initialised are ignored, as no bus
+    /// numbers or memory window were reserved for them.
+    pub fn rescan(&mut self) -> Vec<(DeviceFunction, DeviceFunctionInfo)> {
+        let mut found = Vec::new();
+        for (device_function, info) in self.enumerate_devices() {
+            if self.initialised.contains(&device_function) {
+                continue;
+            }
+            if info.header_type == HeaderType::PciPciBridge
+                && !self
+                    .bridges
+                    .values()
+                    .any(|bridge| *bridge == device_function)
+            {
+                warn!("Ignoring new bridge {device_function}");
+                continue;
+            }
+            info!("Initialising bars for {device_function} {info}");
+            let bars = match self.device_bars(device_function) {
+                Ok(bars) => bars,
+                Err(e) => {
+                    error!("Error reading BARs of {device_function}: {e}");
+                    continue;
+                }
+            };
+            let allocator = self.allocators.get_mut(&device_function.bus).unwrap();
+            if let Err(e) = allocate_bars(&mut self.root, allocator, device_function, &bars) {
+                error!("Error initialising {device_function}: {e}");
+                continue;
+            }
+            self.initialised.insert(device_function);
+            self.find_interrupt(device_function);
+            found.push((device_function, info));
+        }
+        found
+    }
+
+    /// Finds the legacy interrupt of the given device, if it has one, and adds it to `irqs`.
+    fn find_interrupt(&mut self, device_function: DeviceFunction) {
+        let pin = (self.read_config(device_function, INTERRUPT_REGISTER_OFFSET) >> 8) as u8;
+        if pin == 0 {
+            return;
+        }
+        if let Some((intid, trigger)) = self.map_interrupt(device_function, pin) {
+            info!(
+                "{device_function} INT{} is {intid:?}",
+                char::from(b'A' + pin - 1)
+            );
+            self.irqs.insert(device_function, (intid, trigger));
+        } else {
+            warn!("No interrupt mapping for {device_function} pin {pin}");
+        }
+    }
+
+    /// Maps the given interrupt pin of the given device function to a GIC interrupt, swizzling it
+    /// through any bridges between the device and the root bus.
+    fn map_interrupt(
+        &self,
+        mut device_function: DeviceFunction,
+        mut pin: u8,
+    ) -> Option<(IntId, Trigger)> {
+        while device_function.bus != self.root_bus {
+            pin = swizzle_interrupt_pin(device_function.device, pin);
+            device_function = *self.bridges.get(&device_function.bus)?;
+        }
+        self.interrupt_map
+            .as_ref()?
+            .lookup(
+                device_function.bus,
+                device_function.device,
+                device_function.function,
+                pin,
+            )
+            .and_then(|(_, specifier)| gic_interrupt(specifier))
+    }
+
+    /// Reads the 32-bit word at the given offset in the configuration space of the given device
+    /// function.
+    pub fn read_config(&self, device_function: DeviceFunction, offset: u8) -> u32 {
+        self.read_only_cam.read_word(device_function, offset)
+    }
+
+    /// Reads the whole conventional configuration space of the given device function.
+    pub fn config_space(&self, device_function: DeviceFunction) -> [u8; CONFIG_SPACE_SIZE] {
+        let mut config_space = [0; CONFIG_SPACE_SIZE];
+        for (offset, word) in config_space.chunks_exact_mut(4).enumerate() {
+            word.copy_from_slice(
+                &self
+                    .read_config(device_function, (offset * 4) as u8)
+                    .to_le_bytes(),
+            );
+        }
+        config_space
+    }
+
+    /// Returns the capabilities of the given device function, along with descriptions of them.
+    pub fn describe_capabilities(
+        &self,
+        device_function: DeviceFunction,
+    ) -> impl Iterator<Item = (CapabilityInfo, CapabilityDescription)> {
+        self.capabilities(device_function).map(move |capability| {
+            let words = array::from_fn(|i| {
+                // Capabilities may be too close to the end of configuration space for all the
+                // words to be present.
+                u8::try_from(usize::from(capability.offset) + i * 4)
+                    .map_or(0, |offset| self.read_config(device_function, offset))
+            });
+            (capability, CapabilityDescription { words })
+        })
+    }
+}
+
+impl Deref for PciRootComplex {
+    type Target = PciRoot<MmioCam<'static>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.root
+    }
+}
+
+impl DerefMut for PciRootComplex {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.root
+    }
+}
+
+/// A PCI I/O BAR, accessed through the memory-mapped I/O window of its root.
+#[derive(Debug)]
+pub struct IoBar {
+    base: NonNull<u8>,
+    size: usize,
+}
+
+impl IoBar {
+    /// Returns the size of the BAR in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns a pointer to a register of type `T` at the given offset in the BAR.
+    ///
+    /// Panics if the register isn't entirely within the BAR or isn't aligned.
+    fn register<T>(&self, offset: usize) -> *mut T {
+        assert!(offset + size_of::<T>() <= self.size);
+        assert!(offset.is_multiple_of(align_of::<T>()));
+        // SAFETY: We just checked that the offset is within the BAR.
+        unsafe { self.base.add(offset).cast().as_ptr() }
+    }
+
+    /// Reads an 8, 16 or 32-bit register at the given offset in the BAR.
+    pub fn read<T: IoValue>(&self, offset: usize) -> T {
+        // SAFETY: The I/O window was mapped as device memory when the PCI root was initialised,
+        // and `register` checks that the offset is within the BAR. Reading a device's registers
+        // may have side effects on the device, but not on our memory.
+        unsafe { self.register::<T>(offset).read_volatile() }
+    }
+}
+
+/// A type which can be read from an I/O BAR in a single access.
+pub trait IoValue: Copy {}
+
+impl IoValue for u8 {}
+impl IoValue for u16 {}
+impl IoValue for u32 {}
+
+#[derive(Debug)]
+pub struct PciRootInfo {
+    cam: Cam,
+    /// The address which bus 0 would be at. If the bus range doesn't start at 0 then this will be
+    /// before the start of the region, but only buses within the range are ever accessed.
+    mmio_base: *mut u8,
+    bus_range: RangeInclusive<u8>,
+    ranges: Vec<PciRange>,
+    interrupt_map: Option<PciInterruptMap>,
+}
+
+/// A device function found while enumerating a PCI root.
+struct FoundDevice {
+    device_function: DeviceFunction,
+    /// The bus behind the device, if it is a PCI-to-PCI bridge.
+    secondary_bus: Option<u8>,
+    bars: [Option<BarInfo>; 6],
+}
+
+impl PciRootInfo {
+    fn for_fdt_node(fdt: &Fdt, pci_node: FdtNode, cam: Cam, bar_range_limit: usize) -> Self {
+        let RegEntry { address, size } = reg_entries(&pci_node)
+            .and_then(|reg| reg.first().copied())
+            .expect("Invalid PCI root reg property");
+        info!("Reg: {:#x}-{:#x}", address, address + size);
+        let bus_range = property_cells(&pci_node, "bus-range")
+            .map(|cells| parse_bus_range(&cells).expect("Invalid PCI bus-range"))
+            .unwrap_or(0..=u8::MAX);
+        let bus_size = u64::from(cam.size()) / 256;
+        let bus_count = u64::from(*bus_range.end() - *bus_range.start()) + 1;
+        info!("Buses {:#04x}-{:#04x}", bus_range.start(), bus_range.end());
+        assert!(size >= bus_count * bus_size);
+
+        let mut ranges = Vec::new();
+        for range in pci_node.ranges().unwrap().unwrap() {
+            let range = PciRange::from(range);
+            info!("PCI range {range}");
+            let cpu_physical = range.cpu_physical;
+            if let Some(range) = range.limit_to(bar_range_limit) {
+                ranges.push(range);
+            } else {
+                warn!(
+                    "Ignoring range outside page table size ({cpu_physical:#x} >= {bar_range_limit:#x}).",
+                );
+            }
+        }
+
+        let interrupt_map = property_cells(&pci_node, "interrupt-map").and_then(|map| {
+            let mask = property_cells(&pci_node, "interrupt-map-mask");
+            let interrupt_map = PciInterruptMap::parse(&map, mask.as_deref(), |phandle| {
+                let parent = find_phandle(fdt.root(), phandle)?;
+                Some((
+                    property_u32(&parent, "#address-cells").unwrap_or(0) as usize,
+                    property_u32(&parent, "#interrupt-cells")? as usize,
+                ))
+            });
+            if interrupt_map.is_none() {
+                warn!("Ignoring invalid PCI interrupt-map");
+            }
+            interrupt_map
+        });
+
+        Self {
+            cam,
+            mmio_base: (address as *mut u8)
+                .wrapping_sub((u64::from(*bus_range.start()) * bus_size) as usize),
+            bus_range,
+            ranges,
+            interrupt_map,
+        }
+    }
+
+    /// Maps all the BAR ranges for this PCI root in the given IdMap.
+    pub fn map_ranges(&self, idmap: &mut IdMap) {
+        for range in &self.ranges {
+            if range.is_mapped() {
+                let memory_region = range.memory_region();
+                info!("Mappping {memory_region}");
+                idmap.map_device(&memory_region).unwrap();
+            }
+        }
+    }
+
+    /// Initialises and returns the PCI root represented by the given FDT node.
+    ///
+    /// Assigns bus numbers to all PCI-to-PCI bridges, and allocates bridge windows and BAR ranges
+    /// for all devices on the root.
+    ///
+    /// # Safety
+    ///
+    /// This must only be called once per PCI root, to avoid creating aliases to the MMIO space. The
+    /// root info must refer to a valid MMIO region which has already been mapped appropriately.
+    pub unsafe fn init_pci(mut self) -> PciRootComplex {
+        // Each of the CAMs below is dropped before the next is created, so they never alias. The
+        // caller promises that the pointer is to a valid MMIO region.
+
+        // Find all devices, numbering buses as we find bridges.
+        let mut devices = Vec::new();
+        {
+            // SAFETY: See above.
+            let mut cam = unsafe { self.cam() };
+            let mut next_bus = u16::from(*self.bus_range.start()) + 1;
+            self.scan_bus(
+                &mut cam,
+                *self.bus_range.start(),
+                &mut next_bus,
+                &mut devices,
+            );
+        }
+
+        // Size the BARs of all devices.
+        {
+            // SAFETY: See above.
+            let mut pci_root = PciRoot::new(unsafe { self.cam() });
+            for device in &mut devices {
+                let bar_count = if device.secondary_bus.is_some() {
+                    BRIDGE_BAR_COUNT
+                } else {
+                    6
+                };
+                device.bars =
+                    device_bars(&mut pci_root, device.device_function, bar_count).unwrap();
+            }
+        }
+
+        // Allocate and program bridge windows, with a separate allocator for each bus behind a
+        // bridge.
+        let io_ranges = self
+            .ranges
+            .iter()
+            .filter(|range| range.flags.range_type() == PciRangeType::IoSpace)
+            .cloned()
+            .collect();
+        let mut allocators = BTreeMap::new();
+        allocators.insert(
+            *self.bus_range.start(),
+            PciBarAllocator::new(mem::take(&mut self.ranges)),
+        );
+        let mut windows = Vec::new();
+        allocate_bridge_windows(
+            &devices,
+            *self.bus_range.start(),
+            &mut allocators,
+            &mut windows,
+        );
+        {
+            // SAFETY: See above.
+            let mut cam = unsafe { self.cam() };
+            for (device_function, window) in windows {
+                info!("Bridge {device_function} memory window {window:#x?}");
+                cam.write_word(device_function, IO_WINDOW_OFFSET, DISABLED_IO_WINDOW);
+                cam.write_word(
+                    device_function,
+                    MEMORY_WINDOW_OFFSET,
+                    memory_window_register(&window),
+                );
+                cam.write_word(
+                    device_function,
+                    PREFETCHABLE_WINDOW_OFFSET,
+                    memory_window_register(&(0..0)),
+                );
+                cam.write_word(device_function, PREFETCHABLE_BASE_UPPER_OFFSET, 0);
+                cam.write_word(device_function, PREFETCHABLE_LIMIT_UPPER_OFFSET, 0);
+            }
+        }
+
+        // SAFETY: See above.
+        let cam = unsafe { self.cam() };
+        // SAFETY: `PciRootComplex` only uses this for reads, which don't have side effects.
+        let read_only_cam = unsafe { cam.unsafe_clone() };
+        let root_bus = *self.bus_range.start();
+        let bridges = devices
+            .iter()
+            .filter_map(|device| Some((device.secondary_bus?, device.device_function)))
+            .collect::<BTreeMap<_, _>>();
+        let mut pci_root = PciRootComplex {
+            root: PciRoot::new(cam),
+            read_only_cam,
+            root_bus,
+            buses: [root_bus]
+                .into_iter()
+                .chain(bridges.keys().copied())
+                .collect(),
+            bridges,
+            allocators,
+            interrupt_map: self.interrupt_map.take(),
+            io_ranges,
+            initialised: BTreeSet::new(),
+            claimed: BTreeSet::new(),
+            irqs: PciIrqs::new(),
+        };
+        pci_root.rescan();
+        pci_root
+    }
+
+    /// Creates a new CAM for the root.
+    ///
+    /// # Safety
+    ///
+    /// The root info must refer to a valid MMIO region which has already been mapped
+    /// appropriately, and there must be no other CAM for the same root while the returned one
+    /// exists.
+    unsafe fn cam(&self) -> MmioCam<'static> {
+        // SAFETY: The caller promises that the pointer is to a valid MMIO region, and that there is
+        // no alias.
+        unsafe { MmioCam::new(self.mmio_base, self.cam) }
+    }
+
+    /// Finds all devices on the given bus, and recursively on buses behind any bridges on it,
+    /// assigning bus numbers to bridges as they are found.
+    fn scan_bus(
+        &self,
+        cam: &mut MmioCam,
+        bus: u8,
+        next_bus: &mut u16,
+        devices: &mut Vec<FoundDevice>,
+    ) {
+        // SAFETY: The clone is only used to enumerate devices, which only reads read-only fields.
+        let found = PciRoot::new(unsafe { cam.unsafe_clone() })
+            .enumerate_bus(bus)
+            .collect::<Vec<_>>();
+        for (device_function, info) in found {
+            let is_bridge = info.header_type == HeaderType::PciPciBridge;
+            let index = devices.len();
+            devices.push(FoundDevice {
+                device_function,
+                secondary_bus: None,
+                bars: Default::default(),
+            });
+            if !is_bridge {
+                continue;
+            }
+            let Ok(secondary_bus) = u8::try_from(*next_bus) else {
+                warn!("No bus numbers left for bridge {device_function}");
+                continue;
+            };
+            if secondary_bus > *self.bus_range.end() {
+                warn!("No bus numbers left for bridge {device_function}");
+                continue;
+            }
+            *next_bus += 1;
+            info!("Bridge {device_function} to bus {secondary_bus:#04x}");
+            // Set the subordinate bus to the end of the range while scanning behind the bridge, so
+            // that configuration transactions for any bridges found further down get forwarded.
+            set_bus_numbers(cam, device_function, secondary_bus, *self.bus_range.end());
+            devices[index].secondary_bus = Some(secondary_bus);
+            self.scan_bus(cam, secondary_bus, next_bus, devices);
+            set_bus_numbers(cam, device_function, secondary_bus, (*next_bus - 1) as u8);
+        }
+    }
+}
+
+/// Sets the bus numbers of the given bridge, with its own bus as the primary bus.
+fn set_bus_numbers(
+    cam: &mut MmioCam,
+    device_function: DeviceFunction,
+    secondary: u8,
+    subordinate: u8,
+) {
+    let existing = cam.read_word(device_function, BUS_NUMBERS_OFFSET);
+    cam.write_word(
+        device_function,
+        BUS_NUMBERS_OFFSET,
+        bus_numbers_register(existing, device_function.bus, secondary, subordinate),
+    );
+}
+
+/// Returns the memory space needed behind the bridge to the given bus, for all the BARs and bridge
+/// windows of devices on it.
+fn bus_window_size(devices: &[FoundDevice], bus: u8) -> u64 {
+    bridge_window_size(
+        devices
+            .iter()
+            .filter(|device| device.device_function.bus == bus)
+            .flat_map(|device| {
+                device
+                    .bars
+                    .iter()
+                    .flatten()
+                    .filter_map(|bar| match bar {
+                        BarInfo::Memory { size, .. } => Some(*size),
+                        BarInfo::IO { .. } => None,
+                    })
+                    .chain(
+                        device
+                            .secondary_bus
+                            .map(|secondary_bus| bus_window_size(devices, secondary_bus)),
+                    )
+            }),
+    )
+}
+
+/// Allocates memory windows for all bridges on the given bus from the bus's allocator, recursing
+/// to buses behind them, and adds an allocator for each bus behind a bridge.
+///
+/// All the BARs behind a bridge are allocated from its non-prefetchable window, which is always in
+/// the 32-bit address space. The prefetchable window is not used.
+fn allocate_bridge_windows(
+    devices: &[FoundDevice],
+    bus: u8,
+    allocators: &mut BTreeMap<u8, PciBarAllocator>,
+    windows: &mut Vec<(DeviceFunction, Range<u64>)>,
+) {
+    // Allocate the largest windows first, so that smaller allocations don't fragment the space.
+    let mut bridges = devices
+        .iter()
+        .filter(|device| device.device_function.bus == bus)
+        .filter_map(|device| {
+            let secondary_bus = device.secondary_bus?;
+            Some((
+                device.device_function,
+                secondary_bus,
+                bus_window_size(devices, secondary_bus),
+            ))
+        })
+        .collect::<Vec<_>>();
+    bridges.sort_by_key(|&(_, _, size)| Reverse(size));
+    for (device_function, secondary_bus, size) in bridges {
+        let window = if size == 0 {
+            0..0
+        } else {
+            let layout = Layout::from_size_align(size as usize, size as usize).unwrap();
+            let start = u64::from(
+                allocators
+                    .get_mut(&bus)
+                    .unwrap()
+                    .allocate32(layout)
+                    .expect("Failed to allocate PCI bridge window"),
+            );
+            start..start + size
+        };
+        allocators.insert(
+            secondary_bus,
+            PciBarAllocator::for_window(window.start, window.end - window.start),
+        );
+        windows.push((device_function, window));
+        allocate_bridge_windows(devices, secondary_bus, allocators, windows);
+    }
+}
+
+/// Finds all PCI and PCIE roots.
+///
+/// BAR ranges higher than the given address limit will be ignored.
+pub fn find_pci_roots(fdt: &Fdt, bar_range_limit: usize) -> Vec<PciRootInfo> {
+    let mut pci_roots = Vec::new();
+    let fdt_root = fdt.root();
+    for pci_node in fdt_root.find_compatible(PCI_COMPATIBLE) {
+        info!("PCI node: {}", pci_node.name());
+        pci_roots.push(PciRootInfo::for_fdt_node(
+            fdt,
+            pci_node,
+            Cam::MmioCam,
+            bar_range_limit,
+        ))
+    }
+    for pcie_node in fdt_root.find_compatible(PCIE_COMPATIBLE) {
+        info!("PCIE node: {}", pcie_node.name());
+        pci_roots.push(PciRootInfo::for_fdt_node(
+            fdt,
+            pcie_node,
+            Cam::Ecam,
+            bar_range_limit,
+        ))
+    }
+    pci_roots
+}
+
+/// The lowest bus address to allocate I/O BARs from. This avoids legacy ISA ports, and address 0
+/// which some devices treat as unassigned.
+const IO_ALLOCATION_START: usize = 0x1000;
+
+/// Allocator for PCI BARs.
+struct PciBarAllocator {
+    memory32: FrameAllocator<32>,
+    memory64: FrameAllocator<64>,
+    prefetchable_memory64: FrameAllocator<64>,
+    /// I/O space, by bus address.
+    io: FrameAllocator<32>,
+}
+
+impl PciBarAllocator {
+    /// Creates an allocator for BARs behind a bridge with the given 32-bit memory window.
+    fn for_window(start: u64, size: u64) -> Self {
+        let mut memory32 = FrameAllocator::new();
+        if size > 0 {
+            memory32.add_frame(start as usize, (start + size) as usize);
+        }
+        Self {
+            memory32,
+            memory64: FrameAllocator::new(),
+            prefetchable_memory64: FrameAllocator::new(),
+            io: FrameAllocator::new(),
+        }
+    }
+
+    fn new(ranges: Vec<PciRange>) -> Self {
+        let mut memory32 = FrameAllocator::new();
+        let mut memory64 = FrameAllocator::new();
+        let mut prefetchable_memory64 = FrameAllocator::new();
+        let mut io = FrameAllocator::new();
+        for range in ranges {
+            match range.flags.range_type() {
+                PciRangeType::Memory32 => {
+                    assert_eq!(range.cpu_physical, range.bus_address);
+                    if range.flags.prefetchable() {
+                        warn!("32-bit PCI range was marked as prefetchable");
+                    }
+                    memory32.add_frame(range.cpu_physical, range.cpu_physical + range.size);
+                }
+                PciRangeType::Memory64 => {
+                    assert_eq!(range.cpu_physical, range.bus_address);
+                    if range.bus_address + range.size < u32::MAX.try_into().unwrap() {
+                        // If a 64-bit range is entirely within 32-bit address space then treat it
+                        // as a 32-bit range. This is necessary for crosvm, which doesn't correctly
+                        // provide any 32-bit ranges.
+                        memory32.add_frame(range.cpu_physical, range.cpu_physical + range.size);
+                    } else if range.flags.prefetchable() {
+                        prefetchable_memory64
+                            .add_frame(range.cpu_physical, range.cpu_physical + range.size);
+                    } else {
+                        memory64.add_frame(range.cpu_physical, range.cpu_physical + range.size);
+                    }
+                }
+                PciRangeType::IoSpace => {
+                    let start = range.bus_address.max(IO_ALLOCATION_START);
+                    let end = range.bus_address + range.size;
+                    if start < end {
+                        io.add_frame(start, end);
+                    }
+                }
+                PciRangeType::ConfigurationSpace => {}
+            }
+        }
+        Self {
+            memory32,
+            memory64,
+            prefetchable_memory64,
+            io,
+        }
+    }
+
+    fn allocate32(&mut self, layout: Layout) -> Option<u32> {
+        Some(self.memory32.alloc_aligned(layout)?.try_into().unwrap())
+    }
+
+    fn allocate_io(&mut self, layout: Layout) -> Option<u32> {
+        Some(self.io.alloc_aligned(layout)?.try_into().unwrap())
+    }
+
+    fn allocate64(&mut self, layout: Layout, prefetchable: bool) -> Option<u64> {
+        if prefetchable && let Some(allocation) = self.prefetchable_memory64.alloc_aligned(layout) {
+            return Some(allocation.try_into().unwrap());
+        }
+        // If prefetchable allocation fails then fall back to non-prefetchable.
+
+        if let Some(allocation) = self.memory64.alloc_aligned(layout) {
+            Some(allocation.try_into().unwrap())
+        } else {
+            // Fall back to 32-bit pool if the 64-bit pool fails.
+            self.allocate32(layout).map(Into::into)
+        }
+    }
+}
+
+/// Returns information about the first `bar_count` BARs of the given device function.
+fn device_bars(
+    pci_root: &mut PciRoot<MmioCam>,
+    device_function: DeviceFunction,
+    bar_count: u8,
+) -> Result<[Option<BarInfo>; 6], PciError> {
+    let mut bars = <[Option<BarInfo>; 6]>::default();
+    let mut bar_index = 0;
+    while bar_index < bar_count {
+        let info = pci_root.bar_info(device_function, bar_index)?;
+        let takes_two_entries = info.as_ref().is_some_and(BarInfo::takes_two_entries);
+        bars[usize::from(bar_index)] = info;
+        bar_index += if takes_two_entries { 2 } else { 1 };
+    }
+    Ok(bars)
+}
+
+/// An error allocating a BAR.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BarAllocationError {
+    /// There wasn't enough space left in the PCI root's ranges to allocate the BAR.
+    NoSpace { bar_index: u8, size: u64 },
+    /// The BAR must be below 1 MiB, which isn't supported.
+    Below1MiB { bar_index: u8 },
+}
+
+impl Display for BarAllocationError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::NoSpace { bar_index, size } => {
+                write!(f, "Failed to allocate {size:#x} bytes for BAR {bar_index}")
+            }
+            Self::Below1MiB { bar_index } => {
+                write!(
+                    f,
+                    "BAR {bar_index} must be below 1 MiB, which isn't supported"
+                )
+            }
+        }
+    }
+}
+
+/// Allocates the given bars of the given PCI device function, and enables the device to use them.
+///
+/// If allocation fails then the device is left disabled.
+fn allocate_bars(
+    pci_root: &mut PciRoot<MmioCam>,
+    allocator: &mut PciBarAllocator,
+    device_function: DeviceFunction,
+    bars: &[Option<BarInfo>; 6],
+) -> Result<(), BarAllocationError> {
+    let mut io_space = true;
+    for (bar_index, info) in bars.iter().enumerate() {
+        let Some(info) = info else { continue };
+        let bar_index = bar_index as u8;
+        info!("BAR {bar_index}: {info}");
+        match *info {
+            BarInfo::Memory {
+                address_type,
+                prefetchable,
+                address: _,
+                size,
+            } => {
+                if size > 0 {
+                    let layout = Layout::from_size_align(size as usize, size as usize).unwrap();
+                    let error = BarAllocationError::NoSpace { bar_index, size };
+                    match address_type {
+                        MemoryBarType::Width32 => {
+                            if prefetchable {
+                                warn!("  32-bit BAR should not be marked prefetchable.");
+                            }
+                            let allocation = allocator.allocate32(layout).ok_or(error)?;
+                            info!("  allocated {allocation:#0x}");
+                            pci_root.set_bar_32(device_function, bar_index, allocation);
+                        }
+                        MemoryBarType::Width64 => {
+                            let allocation =
+                                allocator.allocate64(layout, prefetchable).ok_or(error)?;
+                            info!("  allocated {allocation:#0x}");
+                            pci_root.set_bar_64(device_function, bar_index, allocation);
+                        }
+                        MemoryBarType::Below1MiB => {
+                            return Err(BarAllocationError::Below1MiB { bar_index });
+                        }
+                    }
+                }
+            }
+            BarInfo::IO { address: _, size } => {
+                if size > 0 {
+                    let layout = Layout::from_size_align(size as usize, size as usize).unwrap();
+                    if let Some(allocation) = allocator.allocate_io(layout) {
+                        info!("  allocated IO {allocation:#0x}");
+                        pci_root.set_bar_32(device_function, bar_index, allocation);
+                    } else {
+                        // Many devices with I/O BARs also have memory BARs which can be used
+                        // instead, so don't fail entirely.
+                        warn!("  No I/O space for BAR, leaving I/O space disabled.");
+                        io_space = false;
+                    }
+                }
+            }
+        }
+    }
+
+    // Enable the device to use its BARs, or for a bridge to forward transactions.
+    let mut command = Command::MEMORY_SPACE | Command::BUS_MASTER;
+    if io_space {
+        command |= Command::IO_SPACE;
+    }
+    pci_root.set_command(device_function, command);
+    Ok(())
+}
@@ -0,0 +1,119 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Configuration of PCI-to-PCI bridges.
+
+use core::ops::Range;
+
+/// The number of BARs in a type 1 (PCI-to-PCI bridge) configuration header.
+pub const BRIDGE_BAR_COUNT: u8 = 2;
+
+/// The offset of the primary, secondary and subordinate bus number registers.
+pub const BUS_NUMBERS_OFFSET: u8 = 0x18;
+/// The offset of the I/O base and limit registers.
+pub const IO_WINDOW_OFFSET: u8 = 0x1c;
+/// The offset of the memory base and limit registers.
+pub const MEMORY_WINDOW_OFFSET: u8 = 0x20;
+/// The offset of the prefetchable memory base and limit registers.
+pub const PREFETCHABLE_WINDOW_OFFSET: u8 = 0x24;
+/// The offset of the upper 32 bits of the prefetchable memory base.
+pub const PREFETCHABLE_BASE_UPPER_OFFSET: u8 = 0x28;
+/// The offset of the upper 32 bits of the prefetchable memory limit.
+pub const PREFETCHABLE_LIMIT_UPPER_OFFSET: u8 = 0x2c;
+
+/// Value for the I/O base and limit registers which disables the I/O window, by setting the base
+/// above the limit.
+pub const DISABLED_IO_WINDOW: u32 = 0x0000_00f0;
+
+/// Memory windows have a granularity of 1 MiB.
+pub const MEMORY_WINDOW_GRANULE: u64 = 1 << 20;
+
+/// Returns the size of memory window needed by a bridge to fit BARs or child bridge windows of the
+/// given sizes, or 0 if no window is needed.
+///
+/// All the sizes must be powers of two, as they are for BARs, so they can be allocated from the
+/// window in descending order of size without any gaps. The window is also a power of two, so it
+/// can be allocated from a buddy allocator.
+pub fn bridge_window_size(sizes: impl IntoIterator<Item = u64>) -> u64 {
+    let total: u64 = sizes.into_iter().sum();
+    if total == 0 {
+        0
+    } else {
+        total.max(MEMORY_WINDOW_GRANULE).next_power_of_two()
+    }
+}
+
+/// Returns the new value for the bus number register, given its existing value.
+///
+/// The secondary latency timer in the top byte is preserved.
+pub fn bus_numbers_register(existing: u32, primary: u8, secondary: u8, subordinate: u8) -> u32 {
+    existing & 0xff00_0000
+        | u32::from(subordinate) << 16
+        | u32::from(secondary) << 8
+        | u32::from(primary)
+}
+
+/// Returns the value for the memory base and limit registers for the given window.
+///
+/// An empty window disables the bridge from forwarding memory transactions. The window must be
+/// aligned to `MEMORY_WINDOW_GRANULE` and within the 32-bit address space.
+pub fn memory_window_register(window: &Range<u64>) -> u32 {
+    if window.is_empty() {
+        // A base above the limit disables the window.
+        return 0x0000_fff0;
+    }
+    let base = (window.start >> 16) as u32 & 0xfff0;
+    let limit = ((window.end - 1) >> 16) as u32 & 0xfff0;
+    limit << 16 | base
+}
+
+/// Returns the interrupt pin on the upstream side of a bridge which the given pin of the given
+/// device behind it is routed to.
+///
+/// This is the standard swizzle from the PCI-to-PCI bridge specification. Pins are numbered from 1
+/// for INTA to 4 for INTD.
+pub fn swizzle_interrupt_pin(device: u8, pin: u8) -> u8 {
+    (pin - 1 + device) % 4 + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_size() {
+        assert_eq!(bridge_window_size([]), 0);
+        assert_eq!(bridge_window_size([0, 0]), 0);
+        assert_eq!(bridge_window_size([0x1000]), MEMORY_WINDOW_GRANULE);
+        assert_eq!(bridge_window_size([0x10_0000, 0x4000]), 0x20_0000);
+        assert_eq!(bridge_window_size([0x40_0000, 0x40_0000]), 0x80_0000);
+    }
+
+    #[test]
+    fn bus_numbers() {
+        assert_eq!(bus_numbers_register(0, 0, 1, 0xff), 0x00ff_0100);
+        assert_eq!(bus_numbers_register(0x40ff_ffff, 2, 3, 5), 0x4005_0302);
+    }
+
+    #[test]
+    fn memory_window() {
+        assert_eq!(memory_window_register(&(0..0)), 0x0000_fff0);
+        assert_eq!(
+            memory_window_register(&(0x1010_0000..0x1030_0000)),
+            0x1020_1010
+        );
+        assert_eq!(
+            memory_window_register(&(0x1000_0000..0x1010_0000)),
+            0x1000_1000
+        );
+    }
+
+    #[test]
+    fn swizzle() {
+        assert_eq!(swizzle_interrupt_pin(0, 1), 1);
+        assert_eq!(swizzle_interrupt_pin(1, 1), 2);
+        assert_eq!(swizzle_interrupt_pin(3, 2), 1);
+        assert_eq!(swizzle_interrupt_pin(5, 4), 1);
+    }
+}
@@ -0,0 +1,269 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Helpers for inspecting PCI configuration space and BARs.
+
+use core::fmt::{self, Display, Formatter};
+use virtio_drivers::transport::pci::bus::DeviceFunction;
+
+/// The size of the conventional PCI configuration space of a device function.
+pub const CONFIG_SPACE_SIZE: usize = 256;
+
+/// The number of 32-bit words of a capability which `CapabilityDescription` decodes.
+pub const CAPABILITY_WORDS: usize = 4;
+
+/// Capability ID for Message Signalled Interrupts.
+pub const CAPABILITY_MSI: u8 = 0x05;
+/// Capability ID for vendor-specific capabilities, such as those used by VirtIO.
+pub const CAPABILITY_VENDOR_SPECIFIC: u8 = 0x09;
+/// Capability ID for PCI Express.
+pub const CAPABILITY_PCI_EXPRESS: u8 = 0x10;
+/// Capability ID for MSI-X.
+pub const CAPABILITY_MSIX: u8 = 0x11;
+
+/// The number of bytes shown on each line of a `HexDump`.
+const HEX_DUMP_LINE_LENGTH: usize = 16;
+
+/// Parses a bus, device and function in the same `bb:dd.f` hexadecimal form that
+/// `DeviceFunction` is displayed in.
+pub fn parse_device_function(s: &str) -> Option<DeviceFunction> {
+    let (bus, rest) = s.split_once(':')?;
+    let (device, function) = rest.split_once('.')?;
+    let device_function = DeviceFunction {
+        bus: u8::from_str_radix(bus, 16).ok()?,
+        device: u8::from_str_radix(device, 16).ok()?,
+        function: u8::from_str_radix(function, 16).ok()?,
+    };
+    device_function.valid().then_some(device_function)
+}
+
+/// Returns a human-readable name for the given PCI capability ID.
+pub fn capability_name(id: u8) -> &'static str {
+    match id {
+        0x01 => "Power Management",
+        0x03 => "VPD",
+        CAPABILITY_MSI => "MSI",
+        CAPABILITY_VENDOR_SPECIFIC => "Vendor Specific",
+        0x0d => "PCI Bridge Subsystem Vendor ID",
+        CAPABILITY_PCI_EXPRESS => "PCI Express",
+        CAPABILITY_MSIX => "MSI-X",
+        0x12 => "SATA",
+        0x13 => "Advanced Features",
+        _ => "Unknown",
+    }
+}
+
+/// Returns a human-readable name for the `cfg_type` of a VirtIO vendor-specific capability.
+fn virtio_cfg_type_name(cfg_type: u8) -> &'static str {
+    match cfg_type {
+        1 => "common",
+        2 => "notify",
+        3 => "ISR",
+        4 => "device",
+        5 => "PCI configuration access",
+        8 => "shared memory",
+        _ => "unknown",
+    }
+}
+
+/// Returns a human-readable name for the device/port type field of a PCI Express capability.
+fn pcie_port_type_name(port_type: u32) -> &'static str {
+    match port_type {
+        0b0000 => "endpoint",
+        0b0001 => "legacy endpoint",
+        0b1001 => "root complex integrated endpoint",
+        0b1010 => "root complex event collector",
+        0b0100 => "root port",
+        0b0101 => "upstream switch port",
+        0b0110 => "downstream switch port",
+        0b0111 => "PCIe to PCI bridge",
+        0b1000 => "PCI to PCIe bridge",
+        _ => "unknown",
+    }
+}
+
+/// Formats the fields of common PCI capabilities, from the first few words of the capability.
+///
+/// VirtIO capabilities are decoded for vendor-specific capabilities, as those are the only ones
+/// we expect to see.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CapabilityDescription {
+    /// The first words of the capability, starting with the one containing its ID.
+    pub words: [u32; CAPABILITY_WORDS],
+}
+
+impl Display for CapabilityDescription {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let [header, word1, word2, word3] = self.words;
+        let id = header as u8;
+        let control = header >> 16;
+        write!(f, "{}", capability_name(id))?;
+        match id {
+            CAPABILITY_MSI => {
+                write!(
+                    f,
+                    ": {} vectors, {}-bit{}",
+                    1 << ((control >> 1) & 0b111),
+                    if control & (1 << 7) != 0 { 64 } else { 32 },
+                    if control & 1 != 0 { ", enabled" } else { "" },
+                )
+            }
+            CAPABILITY_MSIX => {
+                write!(
+                    f,
+                    ": {} vectors, table BAR {} offset {:#x}, PBA BAR {} offset {:#x}{}{}",
+                    (control & 0x7ff) + 1,
+                    word1 & 0b111,
+                    word1 & !0b111,
+                    word2 & 0b111,
+                    word2 & !0b111,
+                    if control & (1 << 15) != 0 {
+                        ", enabled"
+                    } else {
+                        ""
+                    },
+                    if control & (1 << 14) != 0 {
+                        ", masked"
+                    } else {
+                        ""
+                    },
+                )
+            }
+            CAPABILITY_PCI_EXPRESS => {
+                write!(
+                    f,
+                    ": version {}, {}",
+                    control & 0xf,
+                    pcie_port_type_name((control >> 4) & 0xf),
+                )
+            }
+            CAPABILITY_VENDOR_SPECIFIC => {
+                let cfg_type = (header >> 24) as u8;
+                write!(
+                    f,
+                    ": VirtIO {} configuration, BAR {} offset {:#x} length {:#x}",
+                    virtio_cfg_type_name(cfg_type),
+                    word1 as u8,
+                    word2,
+                    word3,
+                )
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Formats a buffer as lines of hexadecimal bytes followed by their printable ASCII characters.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct HexDump<'a> {
+    /// The address or offset to show for the first byte.
+    pub address: usize,
+    pub data: &'a [u8],
+}
+
+impl Display for HexDump<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (i, line) in self.data.chunks(HEX_DUMP_LINE_LENGTH).enumerate() {
+            write!(f, "{:08x}:", self.address + i * HEX_DUMP_LINE_LENGTH)?;
+            for byte in line {
+                write!(f, " {byte:02x}")?;
+            }
+            for _ in line.len()..HEX_DUMP_LINE_LENGTH {
+                write!(f, "   ")?;
+            }
+            write!(f, "  |")?;
+            for &byte in line {
+                let c = if byte.is_ascii_graphic() || byte == b' ' {
+                    char::from(byte)
+                } else {
+                    '.'
+                };
+                write!(f, "{c}")?;
+            }
+            writeln!(f, "|")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bdf() {
+        assert_eq!(
+            parse_device_function("00:1f.7"),
+            Some(DeviceFunction {
+                bus: 0,
+                device: 0x1f,
+                function: 7
+            })
+        );
+        assert_eq!(
+            parse_device_function("a:2.0"),
+            Some(DeviceFunction {
+                bus: 0xa,
+                device: 2,
+                function: 0
+            })
+        );
+        assert_eq!(parse_device_function("00:20.0"), None);
+        assert_eq!(parse_device_function("00:01.8"), None);
+        assert_eq!(parse_device_function("100:01.0"), None);
+        assert_eq!(parse_device_function("00.01:0"), None);
+        assert_eq!(parse_device_function("0001"), None);
+    }
+
+    #[test]
+    fn capability_names() {
+        assert_eq!(capability_name(0x11), "MSI-X");
+        assert_eq!(capability_name(0xff), "Unknown");
+    }
+
+    #[test]
+    fn describe_capabilities() {
+        let describe = |words| CapabilityDescription { words }.to_string();
+        assert_eq!(
+            describe([0x0081_0005, 0, 0, 0]),
+            "MSI: 1 vectors, 64-bit, enabled"
+        );
+        assert_eq!(describe([0x0006_0005, 0, 0, 0]), "MSI: 8 vectors, 32-bit");
+        assert_eq!(
+            describe([0x8007_0011, 0x0000_0001, 0x0000_0801, 0]),
+            "MSI-X: 8 vectors, table BAR 1 offset 0x0, PBA BAR 1 offset 0x800, enabled"
+        );
+        assert_eq!(
+            describe([0x0042_0010, 0, 0, 0]),
+            "PCI Express: version 2, root port"
+        );
+        assert_eq!(
+            describe([0x0110_0009, 0x0000_0004, 0x3000, 0x1000]),
+            "Vendor Specific: VirtIO common configuration, BAR 4 offset 0x3000 length 0x1000"
+        );
+        assert_eq!(describe([0x0000_0001, 0, 0, 0]), "Power Management");
+    }
+
+    #[test]
+    fn hex_dump() {
+        let data = b"0123456789abcdef\x00\x01xyz";
+        assert_eq!(
+            HexDump {
+                address: 0x1000,
+                data
+            }
+            .to_string(),
+            "00001000: 30 31 32 33 34 35 36 37 38 39 61 62 63 64 65 66  |0123456789abcdef|\n\
+             00001010: 00 01 78 79 7a                                   |..xyz|\n"
+        );
+        assert_eq!(
+            HexDump {
+                address: 0,
+                data: &[]
+            }
+            .to_string(),
+            ""
+        );
+    }
+}
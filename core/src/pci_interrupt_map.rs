@@ -0,0 +1,151 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Parsing of the `interrupt-map` of a PCI host bridge, to find the legacy INTx interrupts of
+//! devices.
+
+use alloc::vec::Vec;
+
+/// The number of cells in a PCI unit address.
+const PCI_ADDRESS_CELLS: usize = 3;
+/// The number of cells in a PCI interrupt specifier, i.e. the interrupt pin.
+const PCI_INTERRUPT_CELLS: usize = 1;
+/// The number of cells in the child part of an interrupt map entry or its mask.
+const CHILD_CELLS: usize = PCI_ADDRESS_CELLS + PCI_INTERRUPT_CELLS;
+
+/// A single entry of an interrupt map.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct InterruptMapEntry {
+    /// The PCI unit address and interrupt pin to match.
+    child: [u32; CHILD_CELLS],
+    /// The phandle of the interrupt controller.
+    parent: u32,
+    /// The interrupt specifier for the interrupt controller.
+    parent_specifier: Vec<u32>,
+}
+
+/// A parsed PCI `interrupt-map`, along with its `interrupt-map-mask`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PciInterruptMap {
+    mask: [u32; CHILD_CELLS],
+    entries: Vec<InterruptMapEntry>,
+}
+
+impl PciInterruptMap {
+    /// Parses the given `interrupt-map` and optional `interrupt-map-mask` property values.
+    ///
+    /// `parent_cells` is called with the phandle of each interrupt controller in the map, and
+    /// should return its `#address-cells` and `#interrupt-cells`.
+    ///
+    /// Returns `None` if the map is truncated, or refers to an unknown interrupt controller.
+    pub fn parse(
+        map: &[u32],
+        mask: Option<&[u32]>,
+        parent_cells: impl Fn(u32) -> Option<(usize, usize)>,
+    ) -> Option<Self> {
+        let mask = match mask {
+            Some(mask) => mask.try_into().ok()?,
+            None => [u32::MAX; CHILD_CELLS],
+        };
+        let mut entries = Vec::new();
+        let mut rest = map;
+        while !rest.is_empty() {
+            let (child, after_child) = rest.split_at_checked(CHILD_CELLS)?;
+            let (&parent, after_parent) = after_child.split_first()?;
+            let (address_cells, interrupt_cells) = parent_cells(parent)?;
+            let (_, after_address) = after_parent.split_at_checked(address_cells)?;
+            let (parent_specifier, after_specifier) =
+                after_address.split_at_checked(interrupt_cells)?;
+            entries.push(InterruptMapEntry {
+                child: child.try_into().unwrap(),
+                parent,
+                parent_specifier: parent_specifier.to_vec(),
+            });
+            rest = after_specifier;
+        }
+        Some(Self { mask, entries })
+    }
+
+    /// Returns the phandle of the interrupt controller and the interrupt specifier for the given
+    /// interrupt pin (1 for INTA to 4 for INTD) of the given device function, if it is mapped.
+    pub fn lookup(&self, bus: u8, device: u8, function: u8, pin: u8) -> Option<(u32, &[u32])> {
+        let address_high =
+            (u32::from(bus) << 16) | (u32::from(device) << 11) | (u32::from(function) << 8);
+        let child = [address_high, 0, 0, pin.into()];
+        self.entries
+            .iter()
+            .find(|entry| {
+                entry
+                    .child
+                    .iter()
+                    .zip(&child)
+                    .zip(&self.mask)
+                    .all(|((entry_cell, cell), mask)| entry_cell & mask == cell & mask)
+            })
+            .map(|entry| (entry.parent, entry.parent_specifier.as_slice()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIC: u32 = 0x8001;
+
+    fn gic_cells(phandle: u32) -> Option<(usize, usize)> {
+        (phandle == GIC).then_some((2, 3))
+    }
+
+    /// An interrupt map like the one QEMU's virt machine generates, with INTx swizzled across the
+    /// first two device slots.
+    fn qemu_map() -> Vec<u32> {
+        let mut map = Vec::new();
+        for device in 0..2 {
+            for pin in 1..=4 {
+                let spi = 3 + (device + pin - 1) % 4;
+                map.extend_from_slice(&[device << 11, 0, 0, pin, GIC, 0, 0, 0, spi, 4]);
+            }
+        }
+        map
+    }
+
+    #[test]
+    fn lookup_with_mask() {
+        let mask = [0x1800, 0, 0, 7];
+        let map = PciInterruptMap::parse(&qemu_map(), Some(&mask), gic_cells).unwrap();
+        assert_eq!(map.lookup(0, 0, 0, 1), Some((GIC, [0, 3, 4].as_slice())));
+        assert_eq!(map.lookup(0, 1, 0, 1), Some((GIC, [0, 4, 4].as_slice())));
+        assert_eq!(map.lookup(0, 1, 0, 4), Some((GIC, [0, 3, 4].as_slice())));
+        // Only the low two bits of the device number are in the mask, and the function isn't.
+        assert_eq!(map.lookup(0, 4, 3, 2), Some((GIC, [0, 4, 4].as_slice())));
+        assert_eq!(map.lookup(0, 2, 0, 1), None);
+        assert_eq!(map.lookup(0, 0, 0, 0), None);
+    }
+
+    #[test]
+    fn lookup_without_mask() {
+        let map = PciInterruptMap::parse(&qemu_map(), None, gic_cells).unwrap();
+        assert_eq!(map.lookup(0, 1, 0, 2), Some((GIC, [0, 5, 4].as_slice())));
+        assert_eq!(map.lookup(0, 1, 1, 2), None);
+    }
+
+    #[test]
+    fn invalid() {
+        let mut map = qemu_map();
+        map.pop();
+        assert_eq!(PciInterruptMap::parse(&map, None, gic_cells), None);
+        assert_eq!(PciInterruptMap::parse(&qemu_map(), None, |_| None), None);
+        assert_eq!(
+            PciInterruptMap::parse(&qemu_map(), Some(&[0x1800, 0, 0]), gic_cells),
+            None
+        );
+        assert_eq!(
+            PciInterruptMap::parse(&[], None, gic_cells),
+            Some(PciInterruptMap {
+                mask: [u32::MAX; CHILD_CELLS],
+                entries: Vec::new()
+            })
+        );
+    }
+}
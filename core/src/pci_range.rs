@@ -0,0 +1,267 @@
+// Copyright 2024 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Decoding of PCI root ranges from the device tree.
+
+use aarch64_paging::paging::MemoryRegion;
+use core::{
+    cmp::min,
+    fmt::{self, Display, Formatter},
+    ops::RangeInclusive,
+};
+use dtoolkit::standard::Range;
+
+/// A PCI root range, from which BARs can be allocated.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PciRange {
+    pub cpu_physical: usize,
+    pub bus_address: usize,
+    pub size: usize,
+    pub flags: PciMemoryFlags,
+}
+
+impl PciRange {
+    /// Returns the region of CPU physical address space covered by the range.
+    pub fn memory_region(&self) -> MemoryRegion {
+        MemoryRegion::new(self.cpu_physical, self.cpu_physical + self.size)
+    }
+
+    /// Returns whether the range is for 32-bit or 64-bit memory space, rather than IO or
+    /// configuration space.
+    pub fn is_memory(&self) -> bool {
+        matches!(
+            self.flags.range_type(),
+            PciRangeType::Memory32 | PciRangeType::Memory64
+        )
+    }
+
+    /// Returns whether the range is for memory or I/O space, both of which are memory-mapped by
+    /// the CPU, rather than configuration space.
+    pub fn is_mapped(&self) -> bool {
+        self.is_memory() || self.flags.range_type() == PciRangeType::IoSpace
+    }
+
+    /// Translates the given region of bus addresses to the CPU physical address it is mapped at.
+    ///
+    /// Returns `None` if the region isn't entirely within the range.
+    pub fn bus_to_cpu(&self, bus_address: usize, size: usize) -> Option<usize> {
+        let offset = bus_address.checked_sub(self.bus_address)?;
+        (offset.checked_add(size)? <= self.size).then_some(self.cpu_physical + offset)
+    }
+
+    /// Limits a memory or I/O range to CPU physical addresses below the given limit.
+    ///
+    /// Returns `None` if the range starts at or above the limit, or the range trimmed down to end
+    /// at the limit otherwise. Configuration space ranges are returned unchanged.
+    pub fn limit_to(mut self, limit: usize) -> Option<Self> {
+        if !self.is_mapped() {
+            return Some(self);
+        }
+        if self.cpu_physical >= limit {
+            return None;
+        }
+        self.size = min(self.size, limit - self.cpu_physical);
+        Some(self)
+    }
+}
+
+impl From<Range<'_>> for PciRange {
+    fn from(range: Range) -> Self {
+        let child_bus_address = range.child_bus_address::<u128>().unwrap();
+        Self {
+            cpu_physical: range.parent_bus_address::<u64>().unwrap() as usize,
+            bus_address: child_bus_address as usize,
+            size: range.length::<u64>().unwrap() as usize,
+            flags: PciMemoryFlags((child_bus_address >> 64) as u32),
+        }
+    }
+}
+
+impl Display for PciRange {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "PCI range CPU physical {:#0x}, bus_address {:#0x}, size {:#0x}, flags {}",
+            self.cpu_physical, self.bus_address, self.size, self.flags,
+        )
+    }
+}
+
+/// Encodes memory flags of a PCI range
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PciMemoryFlags(pub u32);
+
+impl PciMemoryFlags {
+    /// Returns whether this PCI range is relocatable.
+    pub fn relocatable(self) -> bool {
+        self.0 & 0x8000_0000 == 0
+    }
+
+    /// Returns whether this PCI range is prefetchable.
+    pub fn prefetchable(self) -> bool {
+        self.0 & 0x4000_0000 != 0
+    }
+
+    /// Returns the type of this PCI range.
+    pub fn range_type(self) -> PciRangeType {
+        PciRangeType::from((self.0 & 0x0300_0000) >> 24)
+    }
+}
+
+impl Display for PciMemoryFlags {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:#x} ({} {:?})",
+            self.0,
+            if self.prefetchable() {
+                "prefetchable"
+            } else {
+                "non-prefetchable"
+            },
+            self.range_type(),
+        )
+    }
+}
+
+/// Parses the cells of a PCI root's `bus-range` property.
+///
+/// Returns `None` if the property is malformed.
+pub fn parse_bus_range(cells: &[u32]) -> Option<RangeInclusive<u8>> {
+    let &[start, end] = cells else {
+        return None;
+    };
+    let (start, end) = (u8::try_from(start).ok()?, u8::try_from(end).ok()?);
+    (start <= end).then_some(start..=end)
+}
+
+/// Type of a PCI range
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PciRangeType {
+    /// Range represents the PCI configuration space
+    ConfigurationSpace,
+    /// Range is on IO space
+    IoSpace,
+    /// Range is on 32-bit MMIO space
+    Memory32,
+    /// Range is on 64-bit MMIO space
+    Memory64,
+}
+
+impl From<u32> for PciRangeType {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => Self::ConfigurationSpace,
+            1 => Self::IoSpace,
+            2 => Self::Memory32,
+            3 => Self::Memory64,
+            _ => panic!("Tried to convert invalid range type {}", value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(cpu_physical: usize, size: usize, flags: u32) -> PciRange {
+        PciRange {
+            cpu_physical,
+            bus_address: cpu_physical,
+            size,
+            flags: PciMemoryFlags(flags),
+        }
+    }
+
+    #[test]
+    fn flags() {
+        let flags = PciMemoryFlags(0x4300_0000);
+        assert!(flags.prefetchable());
+        assert!(flags.relocatable());
+        assert_eq!(flags.range_type(), PciRangeType::Memory64);
+
+        let flags = PciMemoryFlags(0x8100_0000);
+        assert!(!flags.prefetchable());
+        assert!(!flags.relocatable());
+        assert_eq!(flags.range_type(), PciRangeType::IoSpace);
+
+        assert_eq!(
+            PciMemoryFlags(0x0200_0000).range_type(),
+            PciRangeType::Memory32
+        );
+        assert_eq!(
+            PciMemoryFlags(0).range_type(),
+            PciRangeType::ConfigurationSpace
+        );
+    }
+
+    #[test]
+    fn limit_memory_range() {
+        // Entirely below the limit.
+        assert_eq!(
+            range(0x1000_0000, 0x1000, 0x0200_0000).limit_to(0x2000_0000),
+            Some(range(0x1000_0000, 0x1000, 0x0200_0000))
+        );
+        // Straddling the limit.
+        assert_eq!(
+            range(0x1000_0000, 0x2000_0000, 0x0300_0000).limit_to(0x2000_0000),
+            Some(range(0x1000_0000, 0x1000_0000, 0x0300_0000))
+        );
+        // Starting at the limit.
+        assert_eq!(
+            range(0x2000_0000, 0x1000, 0x0200_0000).limit_to(0x2000_0000),
+            None
+        );
+    }
+
+    #[test]
+    fn bus_range() {
+        assert_eq!(parse_bus_range(&[0, 0xff]), Some(0..=0xff));
+        assert_eq!(parse_bus_range(&[0x10, 0x1f]), Some(0x10..=0x1f));
+        assert_eq!(parse_bus_range(&[0x10, 0x0f]), None);
+        assert_eq!(parse_bus_range(&[0, 0x100]), None);
+        assert_eq!(parse_bus_range(&[0]), None);
+    }
+
+    #[test]
+    fn limit_io_range() {
+        assert_eq!(
+            range(0x3eff_0000, 0x1_0000, 0x0100_0000).limit_to(0x4000_0000),
+            Some(range(0x3eff_0000, 0x1_0000, 0x0100_0000))
+        );
+        assert_eq!(
+            range(0x3eff_0000, 0x1_0000, 0x0100_0000).limit_to(0x1000),
+            None
+        );
+    }
+
+    #[test]
+    fn limit_configuration_range() {
+        assert_eq!(
+            range(0x3eff_0000, 0x1_0000, 0).limit_to(0x1000),
+            Some(range(0x3eff_0000, 0x1_0000, 0))
+        );
+    }
+
+    #[test]
+    fn bus_to_cpu() {
+        let io = PciRange {
+            cpu_physical: 0x3eff_0000,
+            bus_address: 0,
+            size: 0x1_0000,
+            flags: PciMemoryFlags(0x0100_0000),
+        };
+        assert_eq!(io.bus_to_cpu(0x1000, 0x20), Some(0x3eff_1000));
+        assert_eq!(io.bus_to_cpu(0xffe0, 0x20), Some(0x3eff_ffe0));
+        assert_eq!(io.bus_to_cpu(0xfff0, 0x20), None);
+        assert_eq!(range(0x1000_0000, 0x1000, 0).bus_to_cpu(0x100, 4), None);
+    }
+
+    #[test]
+    fn memory_region() {
+        let region = range(0x1000_0000, 0x2000, 0x0200_0000).memory_region();
+        assert_eq!(region.start().0, 0x1000_0000);
+        assert_eq!(region.end().0, 0x1000_2000);
+    }
+}
@@ -0,0 +1,194 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Counting CPU cycles and retired instructions with the ARMv8 Performance Monitors Extension.
+//!
+//! The cycle counter `PMCCNTR_EL0` is used for cycles, and event counters 0 and 1 are chained to
+//! count retired instructions in 64 bits. Counting is enabled on the current core whenever a
+//! measurement is started, and counts everything the core does at any exception level, including
+//! handling interrupts.
+
+use core::{
+    arch::asm,
+    fmt::{self, Display, Formatter},
+};
+
+/// The `PMCR_EL0.E` bit, which enables the counters.
+const PMCR_E: u64 = 1 << 0;
+/// The `PMCR_EL0.LC` bit, which makes the cycle counter overflow at 64 bits rather than 32.
+const PMCR_LC: u64 = 1 << 6;
+/// The shift of the `PMCR_EL0.N` field, the number of event counters.
+const PMCR_N_SHIFT: u64 = 11;
+
+/// The event filter bit which enables counting at EL2. With the other filter bits clear, EL0 and
+/// EL1 are counted too.
+const FILTER_NSH: u64 = 1 << 27;
+
+/// The architectural event for instructions architecturally executed.
+const EVENT_INST_RETIRED: u64 = 0x08;
+/// The architectural event which counts overflows of the preceding even-numbered counter, to chain
+/// two 32-bit counters into a 64-bit one.
+const EVENT_CHAIN: u64 = 0x1e;
+
+/// The bit in `PMCNTENSET_EL0` for the cycle counter.
+const CYCLE_COUNTER_ENABLE: u64 = 1 << 31;
+
+/// The values of the counters at a point in time, or the difference between two points.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PerfCounts {
+    /// The number of CPU cycles.
+    pub cycles: u64,
+    /// The number of instructions retired.
+    pub instructions: u64,
+}
+
+impl PerfCounts {
+    /// Returns the counts between `earlier` and `self`.
+    pub fn since(&self, earlier: &Self) -> Self {
+        Self {
+            cycles: self.cycles.wrapping_sub(earlier.cycles),
+            instructions: self.instructions.wrapping_sub(earlier.instructions),
+        }
+    }
+
+    /// Returns the average number of instructions per cycle, in hundredths.
+    pub fn ipc_hundredths(&self) -> u64 {
+        (u128::from(self.instructions) * 100)
+            .checked_div(u128::from(self.cycles))
+            .unwrap_or(0) as u64
+    }
+}
+
+impl Display for PerfCounts {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let ipc = self.ipc_hundredths();
+        write!(
+            f,
+            "{} cycles, {} instructions, {}.{:02} IPC",
+            self.cycles,
+            self.instructions,
+            ipc / 100,
+            ipc % 100
+        )
+    }
+}
+
+/// Returns whether the current core implements the PMU, with at least the two event counters
+/// needed to count instructions.
+pub fn is_available() -> bool {
+    let dfr0: u64;
+    // SAFETY: Reading ID_AA64DFR0_EL1 has no side effects.
+    unsafe {
+        asm!("mrs {}, id_aa64dfr0_el1", out(reg) dfr0, options(nomem, nostack));
+    }
+    // PMUVer is 0 if there is no PMU, or 0xf if it is IMPLEMENTATION DEFINED rather than PMUv3.
+    let pmu_version = (dfr0 >> 8) & 0xf;
+    pmu_version != 0 && pmu_version != 0xf && event_counter_count() >= 2
+}
+
+/// Returns the number of event counters the current core has, not counting the cycle counter.
+///
+/// This must only be called if the core has a PMU.
+pub fn event_counter_count() -> u8 {
+    ((read_pmcr() >> PMCR_N_SHIFT) & 0x1f) as u8
+}
+
+/// Enables the cycle counter and the instruction counter on the current core.
+///
+/// This leaves the counters' values alone, so measurements already in progress aren't disturbed.
+///
+/// # Panics
+///
+/// Panics if the core doesn't have a PMU, as reported by `is_available`.
+pub fn enable() {
+    assert!(is_available(), "No PMU available");
+    // SAFETY: Configuring the PMU counters doesn't affect memory, and nothing else uses them.
+    unsafe {
+        asm!(
+            "msr pmccfiltr_el0, {filter}",
+            "msr pmevtyper0_el0, {instructions}",
+            "msr pmevtyper1_el0, {chain}",
+            "msr pmcntenset_el0, {enable}",
+            "isb",
+            filter = in(reg) FILTER_NSH,
+            instructions = in(reg) FILTER_NSH | EVENT_INST_RETIRED,
+            chain = in(reg) FILTER_NSH | EVENT_CHAIN,
+            enable = in(reg) CYCLE_COUNTER_ENABLE | 0b11,
+            options(nomem, nostack),
+        );
+    }
+    write_pmcr(read_pmcr() | PMCR_E | PMCR_LC);
+}
+
+/// Returns the current values of the counters on the current core.
+pub fn read() -> PerfCounts {
+    PerfCounts {
+        cycles: read_cycles(),
+        instructions: read_instructions(),
+    }
+}
+
+/// Returns the current value of the cycle counter on the current core.
+pub fn read_cycles() -> u64 {
+    let cycles: u64;
+    // SAFETY: Reading the cycle counter has no side effects.
+    unsafe {
+        asm!("isb", "mrs {}, pmccntr_el0", out(reg) cycles, options(nomem, nostack));
+    }
+    cycles
+}
+
+/// Runs the given function on the current core, and returns its result along with the cycles and
+/// instructions it took.
+///
+/// # Panics
+///
+/// Panics if the core doesn't have a PMU, as reported by `is_available`.
+pub fn measure<T>(f: impl FnOnce() -> T) -> (T, PerfCounts) {
+    enable();
+    let start = read();
+    let result = f();
+    let counts = read().since(&start);
+    (result, counts)
+}
+
+/// Reads the chained pair of event counters which count instructions, as a single 64-bit value.
+fn read_instructions() -> u64 {
+    loop {
+        let (high, low, high_again): (u64, u64, u64);
+        // SAFETY: Reading the event counters has no side effects.
+        unsafe {
+            asm!(
+                "isb",
+                "mrs {high}, pmevcntr1_el0",
+                "mrs {low}, pmevcntr0_el0",
+                "mrs {high_again}, pmevcntr1_el0",
+                high = out(reg) high,
+                low = out(reg) low,
+                high_again = out(reg) high_again,
+                options(nomem, nostack),
+            );
+        }
+        // If the low half overflowed between reading the high halves, try again.
+        if high == high_again {
+            return ((high & 0xffff_ffff) << 32) | (low & 0xffff_ffff);
+        }
+    }
+}
+
+fn read_pmcr() -> u64 {
+    let value;
+    // SAFETY: Reading PMCR_EL0 has no side effects.
+    unsafe {
+        asm!("mrs {}, pmcr_el0", out(reg) value, options(nomem, nostack));
+    }
+    value
+}
+
+fn write_pmcr(value: u64) {
+    // SAFETY: Writing PMCR_EL0 only affects the PMU counters, which nothing else uses.
+    unsafe {
+        asm!("msr pmcr_el0, {}", "isb", in(reg) value, options(nomem, nostack));
+    }
+}
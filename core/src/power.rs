@@ -0,0 +1,82 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Sampling of whatever power or energy telemetry the platform provides.
+
+use core::fmt::{self, Display, Formatter};
+
+/// A source of energy telemetry.
+pub trait EnergyMeter {
+    /// Returns a human-readable name for the meter.
+    fn name(&self) -> &'static str;
+
+    /// Returns the total energy consumed so far in microjoules, or `None` if it can't currently be
+    /// read.
+    ///
+    /// The absolute value is not meaningful, only the difference between two readings.
+    fn energy_uj(&mut self) -> Option<u64>;
+}
+
+/// An energy meter for platforms without any telemetry, such as QEMU and crosvm, which is the only
+/// one there is so far.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct NoEnergyMeter;
+
+impl EnergyMeter for NoEnergyMeter {
+    fn name(&self) -> &'static str {
+        "none"
+    }
+
+    fn energy_uj(&mut self) -> Option<u64> {
+        None
+    }
+}
+
+/// The energy used while running some code.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EnergyReport {
+    /// The name of the meter which was used.
+    pub meter: &'static str,
+    /// The energy used in microjoules, if it could be measured.
+    pub energy_uj: Option<u64>,
+}
+
+impl Display for EnergyReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if let Some(energy_uj) = self.energy_uj {
+            write!(f, "{energy_uj} µJ (from {})", self.meter)
+        } else {
+            write!(f, "energy unknown (meter {})", self.meter)
+        }
+    }
+}
+
+/// An energy reading taken at the start of some operation, to measure the energy it uses.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EnergySample {
+    start_uj: Option<u64>,
+}
+
+impl EnergySample {
+    /// Takes an initial reading from the given meter.
+    pub fn start(meter: &mut dyn EnergyMeter) -> Self {
+        Self {
+            start_uj: meter.energy_uj(),
+        }
+    }
+
+    /// Takes a final reading from the given meter, and returns the energy used since `start`.
+    ///
+    /// The same meter must be used as for `start`.
+    pub fn finish(self, meter: &mut dyn EnergyMeter) -> EnergyReport {
+        let energy_uj = match (self.start_uj, meter.energy_uj()) {
+            (Some(start), Some(end)) => Some(end.wrapping_sub(start)),
+            _ => None,
+        };
+        EnergyReport {
+            meter: meter.name(),
+            energy_uj,
+        }
+    }
+}
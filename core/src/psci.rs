@@ -0,0 +1,95 @@
+// Copyright 2024 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Choosing the conduit for PSCI calls, suspending the current core, and powering off or resetting
+//! the system.
+
+use crate::FDT;
+use dtoolkit::{Node, Property};
+use log::error;
+use smccc::{
+    Hvc, Smc,
+    psci::{self, cpu_suspend, system_off, system_reset},
+};
+
+/// The bit of a CPU_SUSPEND power state parameter in the original format which marks it as a
+/// powerdown state rather than a standby or retention state.
+pub const POWER_STATE_TYPE_POWERDOWN: u32 = 1 << 16;
+
+/// A CPU_SUSPEND power state supported by a platform.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SuspendState {
+    /// A short name for the state, used to choose it.
+    pub name: &'static str,
+    /// The power state parameter to pass to CPU_SUSPEND, in the original format.
+    pub power_state: u32,
+    /// A human-readable description of the state.
+    pub description: &'static str,
+}
+
+/// Returns whether to use SMC calls for PSCI rather than HVCs, according to the device tree.
+///
+/// Defaults to HVCs if `FDT` hasn't been set or doesn't say.
+pub fn smc_for_psci() -> bool {
+    let Some(fdt) = FDT.get() else {
+        return false;
+    };
+    let Some(psci_node) = fdt.find_node("/psci") else {
+        return false;
+    };
+    let Some(method) = psci_node.property("method") else {
+        return false;
+    };
+    method.value() == b"smc\0"
+}
+
+/// Suspends the current core with PSCI CPU_SUSPEND in the given standby or retention power state,
+/// until it is woken by an interrupt.
+///
+/// The interrupt wakes the core even if it is masked, in which case its handler runs once it is
+/// unmasked. Powerdown states aren't supported, as the core would then resume at an entry point
+/// with its context lost rather than returning from the call.
+pub fn suspend_current_cpu(power_state: u32) -> Result<(), psci::Error> {
+    if power_state & POWER_STATE_TYPE_POWERDOWN != 0 {
+        return Err(psci::Error::InvalidParameters);
+    }
+    // The entry point and context ID are only used for powerdown states.
+    if smc_for_psci() {
+        cpu_suspend::<Smc>(power_state, 0, 0)
+    } else {
+        cpu_suspend::<Hvc>(power_state, 0, 0)
+    }
+}
+
+/// Powers off the system via PSCI.
+pub fn power_off() -> ! {
+    let result = if smc_for_psci() {
+        system_off::<Smc>()
+    } else {
+        system_off::<Hvc>()
+    };
+    if let Err(e) = result {
+        error!("PSCI_SYSTEM_OFF failed: {e}");
+    } else {
+        error!("PSCI_SYSTEM_OFF returned unexpectedly");
+    }
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
+
+/// Resets the system via PSCI.
+pub fn reset() -> ! {
+    let result = if smc_for_psci() {
+        system_reset::<Smc>()
+    } else {
+        system_reset::<Hvc>()
+    };
+    if let Err(e) = result {
+        error!("PSCI_SYSTEM_RESET failed: {e}");
+    } else {
+        error!("PSCI_SYSTEM_RESET returned unexpectedly");
+    }
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
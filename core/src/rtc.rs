@@ -0,0 +1,80 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A common interface to real-time clocks, so that RTCs other than the PL031 can be supported.
+
+use arm_pl031::Rtc as Pl031;
+use chrono::{DateTime, Utc};
+use core::fmt::{self, Display, Formatter};
+
+/// A real-time clock with an alarm.
+pub trait RtcDevice {
+    /// Returns the name of the RTC's driver.
+    fn name(&self) -> &'static str;
+
+    /// Returns the current time.
+    fn get_time(&self) -> DateTime<Utc>;
+
+    /// Sets the current time.
+    fn set_time(&mut self, time: DateTime<Utc>) -> Result<(), RtcError>;
+
+    /// Sets the time at which the alarm interrupt will be raised, if it is enabled.
+    fn set_match(&mut self, time: DateTime<Utc>) -> Result<(), RtcError>;
+
+    /// Enables or disables the alarm interrupt.
+    fn enable_interrupt(&mut self, enable: bool);
+
+    /// Clears a pending alarm interrupt.
+    fn clear_interrupt(&mut self);
+}
+
+/// An error setting the time or alarm of an RTC.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RtcError {
+    /// The RTC can't represent the given time.
+    OutOfRange(DateTime<Utc>),
+}
+
+impl Display for RtcError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::OutOfRange(time) => write!(f, "{time} is out of range for the RTC"),
+        }
+    }
+}
+
+/// Converts the given time to the 32-bit count of seconds since the Unix epoch used by the PL031.
+fn pl031_timestamp(time: DateTime<Utc>) -> Result<u32, RtcError> {
+    time.timestamp()
+        .try_into()
+        .map_err(|_| RtcError::OutOfRange(time))
+}
+
+impl RtcDevice for Pl031 {
+    fn name(&self) -> &'static str {
+        "pl031"
+    }
+
+    fn get_time(&self) -> DateTime<Utc> {
+        Pl031::get_time(self)
+    }
+
+    fn set_time(&mut self, time: DateTime<Utc>) -> Result<(), RtcError> {
+        self.set_unix_timestamp(pl031_timestamp(time)?);
+        Ok(())
+    }
+
+    fn set_match(&mut self, time: DateTime<Utc>) -> Result<(), RtcError> {
+        self.set_match_timestamp(pl031_timestamp(time)?);
+        Ok(())
+    }
+
+    fn enable_interrupt(&mut self, enable: bool) {
+        Pl031::enable_interrupt(self, enable);
+    }
+
+    fn clear_interrupt(&mut self) {
+        Pl031::clear_interrupt(self);
+    }
+}
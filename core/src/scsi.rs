@@ -0,0 +1,370 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! SCSI commands for block devices, and the wrappers used to send them over the USB mass storage
+//! bulk-only transport.
+
+use core::str;
+
+/// The size of a command block wrapper.
+pub const CBW_SIZE: usize = 31;
+/// The size of a command status wrapper.
+pub const CSW_SIZE: usize = 13;
+
+const CBW_SIGNATURE: u32 = 0x4342_5355;
+const CSW_SIGNATURE: u32 = 0x5342_5355;
+/// Command block wrapper flag for commands which transfer data from the device to the host.
+const CBW_FLAG_DATA_IN: u8 = 0x80;
+
+const OPERATION_TEST_UNIT_READY: u8 = 0x00;
+const OPERATION_REQUEST_SENSE: u8 = 0x03;
+const OPERATION_INQUIRY: u8 = 0x12;
+const OPERATION_MODE_SENSE_6: u8 = 0x1a;
+const OPERATION_READ_CAPACITY_10: u8 = 0x25;
+const OPERATION_READ_10: u8 = 0x28;
+const OPERATION_WRITE_10: u8 = 0x2a;
+
+/// The size of standard inquiry data, up to the end of the product revision.
+pub const INQUIRY_DATA_SIZE: usize = 36;
+/// The size of fixed format sense data, up to the end of the additional sense code qualifier.
+pub const SENSE_DATA_SIZE: usize = 18;
+/// The size of READ CAPACITY (10) parameter data.
+pub const READ_CAPACITY_10_DATA_SIZE: usize = 8;
+/// The size of the mode parameter header returned by MODE SENSE (6).
+pub const MODE_SENSE_6_HEADER_SIZE: usize = 4;
+/// The page code to request all mode pages.
+const MODE_PAGE_ALL: u8 = 0x3f;
+/// The write protect bit in the device-specific parameter of a mode parameter header.
+const MODE_WRITE_PROTECT: u8 = 0x80;
+
+/// A SCSI command descriptor block.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Command {
+    bytes: [u8; 16],
+    length: u8,
+}
+
+impl Command {
+    fn new(command: &[u8]) -> Self {
+        let mut bytes = [0; 16];
+        bytes[..command.len()].copy_from_slice(command);
+        Self {
+            bytes,
+            length: command.len() as u8,
+        }
+    }
+
+    pub fn test_unit_ready() -> Self {
+        Self::new(&[OPERATION_TEST_UNIT_READY, 0, 0, 0, 0, 0])
+    }
+
+    pub fn request_sense() -> Self {
+        Self::new(&[OPERATION_REQUEST_SENSE, 0, 0, 0, SENSE_DATA_SIZE as u8, 0])
+    }
+
+    pub fn inquiry() -> Self {
+        Self::new(&[OPERATION_INQUIRY, 0, 0, 0, INQUIRY_DATA_SIZE as u8, 0])
+    }
+
+    /// Returns a MODE SENSE (6) command which only asks for the mode parameter header.
+    pub fn mode_sense_6() -> Self {
+        Self::new(&[
+            OPERATION_MODE_SENSE_6,
+            0,
+            MODE_PAGE_ALL,
+            0,
+            MODE_SENSE_6_HEADER_SIZE as u8,
+            0,
+        ])
+    }
+
+    pub fn read_capacity_10() -> Self {
+        Self::new(&[OPERATION_READ_CAPACITY_10, 0, 0, 0, 0, 0, 0, 0, 0, 0])
+    }
+
+    pub fn read_10(block: u32, count: u16) -> Self {
+        Self::transfer_10(OPERATION_READ_10, block, count)
+    }
+
+    pub fn write_10(block: u32, count: u16) -> Self {
+        Self::transfer_10(OPERATION_WRITE_10, block, count)
+    }
+
+    fn transfer_10(operation: u8, block: u32, count: u16) -> Self {
+        let [b0, b1, b2, b3] = block.to_be_bytes();
+        let [c0, c1] = count.to_be_bytes();
+        Self::new(&[operation, 0, b0, b1, b2, b3, 0, c0, c1, 0])
+    }
+
+    /// Returns the command descriptor block.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.length.into()]
+    }
+}
+
+/// The wrapper sent on the bulk OUT endpoint at the start of each bulk-only transport command.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CommandBlockWrapper {
+    /// A value to match the command to its status.
+    pub tag: u32,
+    /// The number of bytes expected in the data stage.
+    pub data_length: u32,
+    /// Whether the data stage, if any, is from the device to the host.
+    pub data_in: bool,
+    pub lun: u8,
+    pub command: Command,
+}
+
+impl CommandBlockWrapper {
+    pub fn to_bytes(&self) -> [u8; CBW_SIZE] {
+        let mut bytes = [0; CBW_SIZE];
+        bytes[0..4].copy_from_slice(&CBW_SIGNATURE.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.tag.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.data_length.to_le_bytes());
+        bytes[12] = if self.data_in { CBW_FLAG_DATA_IN } else { 0 };
+        bytes[13] = self.lun;
+        bytes[14] = self.command.length;
+        bytes[15..].copy_from_slice(&self.command.bytes);
+        bytes
+    }
+}
+
+/// The status of a bulk-only transport command.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CommandStatus {
+    Passed,
+    Failed,
+    /// The device didn't follow the transport protocol, and must be reset.
+    PhaseError,
+}
+
+/// The wrapper received on the bulk IN endpoint at the end of each bulk-only transport command.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CommandStatusWrapper {
+    /// The tag from the corresponding command block wrapper.
+    pub tag: u32,
+    /// The difference between the data length expected and the amount actually processed.
+    pub residue: u32,
+    pub status: CommandStatus,
+}
+
+impl CommandStatusWrapper {
+    /// Parses a command status wrapper, returning `None` if it isn't valid.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.first_chunk::<CSW_SIZE>()?;
+        let read_u32 =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        if read_u32(0) != CSW_SIGNATURE {
+            return None;
+        }
+        let status = match bytes[12] {
+            0 => CommandStatus::Passed,
+            1 => CommandStatus::Failed,
+            2 => CommandStatus::PhaseError,
+            _ => return None,
+        };
+        Some(Self {
+            tag: read_u32(4),
+            residue: read_u32(8),
+            status,
+        })
+    }
+}
+
+/// The fields we use from standard inquiry data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct InquiryData<'a> {
+    pub peripheral_device_type: u8,
+    pub removable: bool,
+    pub vendor: &'a str,
+    pub product: &'a str,
+    pub revision: &'a str,
+}
+
+impl<'a> InquiryData<'a> {
+    /// Parses standard inquiry data, trimming the padding from its strings.
+    pub fn parse(bytes: &'a [u8]) -> Option<Self> {
+        if bytes.len() < INQUIRY_DATA_SIZE {
+            return None;
+        }
+        let string = |range: core::ops::Range<usize>| {
+            str::from_utf8(&bytes[range])
+                .ok()
+                .map(|s| s.trim_end_matches([' ', '\0']))
+        };
+        Some(Self {
+            peripheral_device_type: bytes[0] & 0x1f,
+            removable: bytes[1] & 0x80 != 0,
+            vendor: string(8..16)?,
+            product: string(16..32)?,
+            revision: string(32..36)?,
+        })
+    }
+}
+
+/// The fields we use from fixed format sense data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SenseData {
+    pub key: u8,
+    pub additional_code: u8,
+    pub additional_qualifier: u8,
+}
+
+impl SenseData {
+    /// The sense key reported after the device has been reset or its medium changed.
+    pub const KEY_UNIT_ATTENTION: u8 = 0x06;
+
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        // Only the fixed format, for current or deferred errors, is supported.
+        if bytes.len() < 14 || !matches!(bytes[0] & 0x7f, 0x70 | 0x71) {
+            return None;
+        }
+        Some(Self {
+            key: bytes[2] & 0x0f,
+            additional_code: bytes[12],
+            additional_qualifier: bytes[13],
+        })
+    }
+}
+
+/// The result of READ CAPACITY (10).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Capacity {
+    /// The address of the last block, or 0xffffffff if there are too many to report.
+    pub last_block: u32,
+    pub block_size: u32,
+}
+
+impl Capacity {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.first_chunk::<READ_CAPACITY_10_DATA_SIZE>()?;
+        Some(Self {
+            last_block: u32::from_be_bytes(bytes[0..4].try_into().unwrap()),
+            block_size: u32::from_be_bytes(bytes[4..8].try_into().unwrap()),
+        })
+    }
+
+    /// Returns the number of blocks, or `None` if there are too many for READ CAPACITY (10).
+    pub fn blocks(&self) -> Option<u64> {
+        (self.last_block != u32::MAX).then(|| u64::from(self.last_block) + 1)
+    }
+}
+
+/// Returns whether the given mode parameter header from MODE SENSE (6) reports that the medium is
+/// write protected.
+pub fn mode_sense_6_write_protected(header: &[u8]) -> Option<bool> {
+    let header = header.first_chunk::<MODE_SENSE_6_HEADER_SIZE>()?;
+    Some(header[2] & MODE_WRITE_PROTECT != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_block_wrapper() {
+        let cbw = CommandBlockWrapper {
+            tag: 0x1234_5678,
+            data_length: 512,
+            data_in: true,
+            lun: 0,
+            command: Command::read_10(0x0102_0304, 1),
+        };
+        assert_eq!(
+            cbw.to_bytes(),
+            [
+                0x55, 0x53, 0x42, 0x43, 0x78, 0x56, 0x34, 0x12, 0x00, 0x02, 0x00, 0x00, 0x80, 0x00,
+                0x0a, 0x28, 0x00, 0x01, 0x02, 0x03, 0x04, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00,
+            ]
+        );
+        assert_eq!(Command::inquiry().as_bytes(), [0x12, 0, 0, 0, 36, 0]);
+        assert_eq!(
+            Command::write_10(2, 0x100).as_bytes()[..],
+            [0x2a, 0, 0, 0, 0, 2, 0, 1, 0, 0]
+        );
+    }
+
+    #[test]
+    fn command_status_wrapper() {
+        let bytes = [
+            0x55, 0x53, 0x42, 0x53, 0x78, 0x56, 0x34, 0x12, 0x00, 0x02, 0x00, 0x00, 0x01,
+        ];
+        assert_eq!(
+            CommandStatusWrapper::parse(&bytes),
+            Some(CommandStatusWrapper {
+                tag: 0x1234_5678,
+                residue: 512,
+                status: CommandStatus::Failed,
+            })
+        );
+        assert_eq!(CommandStatusWrapper::parse(&bytes[..12]), None);
+        let mut bad_signature = bytes;
+        bad_signature[3] = 0x43;
+        assert_eq!(CommandStatusWrapper::parse(&bad_signature), None);
+        let mut bad_status = bytes;
+        bad_status[12] = 3;
+        assert_eq!(CommandStatusWrapper::parse(&bad_status), None);
+    }
+
+    #[test]
+    fn inquiry_data() {
+        let mut bytes = [0; INQUIRY_DATA_SIZE];
+        bytes[1] = 0x80;
+        bytes[8..16].copy_from_slice(b"QEMU    ");
+        bytes[16..32].copy_from_slice(b"QEMU HARDDISK   ");
+        bytes[32..36].copy_from_slice(b"2.5+");
+        assert_eq!(
+            InquiryData::parse(&bytes),
+            Some(InquiryData {
+                peripheral_device_type: 0,
+                removable: true,
+                vendor: "QEMU",
+                product: "QEMU HARDDISK",
+                revision: "2.5+",
+            })
+        );
+        assert_eq!(InquiryData::parse(&bytes[..35]), None);
+    }
+
+    #[test]
+    fn sense_data() {
+        let mut bytes = [0; SENSE_DATA_SIZE];
+        bytes[0] = 0x70;
+        bytes[2] = SenseData::KEY_UNIT_ATTENTION;
+        bytes[12] = 0x29;
+        assert_eq!(
+            SenseData::parse(&bytes),
+            Some(SenseData {
+                key: SenseData::KEY_UNIT_ATTENTION,
+                additional_code: 0x29,
+                additional_qualifier: 0,
+            })
+        );
+        bytes[0] = 0x72;
+        assert_eq!(SenseData::parse(&bytes), None);
+    }
+
+    #[test]
+    fn capacity() {
+        let capacity = Capacity::parse(&[0, 0, 0x07, 0xff, 0, 0, 0x02, 0]).unwrap();
+        assert_eq!(
+            capacity,
+            Capacity {
+                last_block: 0x7ff,
+                block_size: 512
+            }
+        );
+        assert_eq!(capacity.blocks(), Some(0x800));
+        assert_eq!(Capacity::parse(&[0xff; 8]).unwrap().blocks(), None);
+        assert_eq!(Capacity::parse(&[0; 7]), None);
+    }
+
+    #[test]
+    fn write_protect() {
+        assert_eq!(mode_sense_6_write_protected(&[3, 0, 0x80, 0]), Some(true));
+        assert_eq!(mode_sense_6_write_protected(&[3, 0, 0, 0]), Some(false));
+        assert_eq!(mode_sense_6_write_protected(&[3, 0, 0]), None);
+    }
+}
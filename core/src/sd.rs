@@ -0,0 +1,244 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Registers of SD and MMC (eMMC) cards, and clock calculations for SD host controllers.
+
+use alloc::string::String;
+
+/// The block size used for all data transfers.
+pub const BLOCK_SIZE: usize = 512;
+/// The size of an MMC extended card-specific data register.
+pub const EXT_CSD_SIZE: usize = 512;
+
+/// Bit of the operation conditions register which is set once the card has finished powering up.
+pub const OCR_READY: u32 = 1 << 31;
+/// Bit of the operation conditions register for high capacity cards, which are addressed in blocks
+/// rather than bytes. Also set by the host to say that it supports them.
+pub const OCR_HIGH_CAPACITY: u32 = 1 << 30;
+/// The voltage window from 2.7V to 3.6V, in the operation conditions register.
+pub const OCR_VOLTAGE_WINDOW: u32 = 0x00ff_8000;
+/// The argument to SEND_IF_COND: 2.7-3.6V, with a check pattern which the card echoes back.
+pub const IF_COND_ARGUMENT: u32 = 0x1aa;
+
+/// The frequency to use while identifying cards.
+pub const IDENTIFICATION_CLOCK_HZ: u32 = 400_000;
+/// The frequency to use once cards have been identified, which all SD and MMC cards support.
+pub const DEFAULT_CLOCK_HZ: u32 = 25_000_000;
+
+/// Whether a card speaks the SD or MMC protocol.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CardKind {
+    Sd,
+    Mmc,
+}
+
+/// Converts the response registers of an SD host controller for a 136-bit response to the 128-bit
+/// register which the card sent.
+///
+/// Controllers don't store the CRC in the lowest byte, so the rest is shifted down by 8 bits.
+pub fn r2_from_response(response: [u32; 4]) -> u128 {
+    let value = response
+        .iter()
+        .rev()
+        .fold(0u128, |value, &word| (value << 32) | u128::from(word));
+    value << 8
+}
+
+/// Returns bits `high` to `low` inclusive of the given register.
+fn bits(register: u128, high: u32, low: u32) -> u32 {
+    ((register >> low) & ((1 << (high - low + 1)) - 1)) as u32
+}
+
+/// The fields we use from a card identification register.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Cid {
+    pub manufacturer_id: u8,
+    pub product_name: String,
+    /// The product revision, as a binary-coded decimal major and minor version.
+    pub revision: u8,
+    pub serial: u32,
+}
+
+impl Cid {
+    /// Parses the given card identification register, which has a different layout for SD and MMC.
+    pub fn parse(cid: u128, kind: CardKind) -> Self {
+        let (name_low, revision, serial) = match kind {
+            CardKind::Sd => (64, 56, 24),
+            CardKind::Mmc => (56, 48, 16),
+        };
+        let product_name = (name_low..104)
+            .step_by(8)
+            .rev()
+            .map(|low| bits(cid, low + 7, low) as u8)
+            .filter(|&byte| byte != 0)
+            .map(char::from)
+            .collect::<String>();
+        Self {
+            manufacturer_id: bits(cid, 127, 120) as u8,
+            product_name: product_name.trim_end().into(),
+            revision: bits(cid, revision + 7, revision) as u8,
+            serial: bits(cid, serial + 31, serial),
+        }
+    }
+}
+
+/// The capacity described by a card-specific data register.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CsdCapacity {
+    /// The card has the given number of 512-byte blocks.
+    Blocks(u64),
+    /// The card is an MMC device over 2 GiB, whose capacity must be read from the extended CSD.
+    ExtendedCsd,
+    /// The register has a structure version which we don't support.
+    UnsupportedStructure(u8),
+}
+
+/// Returns the capacity described by the given card-specific data register.
+pub fn csd_capacity(csd: u128, kind: CardKind) -> CsdCapacity {
+    let structure = bits(csd, 127, 126) as u8;
+    match (kind, structure) {
+        // SD version 2.0 high capacity, where the size is in units of 512 KiB.
+        (CardKind::Sd, 1) => CsdCapacity::Blocks((u64::from(bits(csd, 69, 48)) + 1) * 1024),
+        (CardKind::Sd, 0) | (CardKind::Mmc, _) => {
+            let size = bits(csd, 73, 62);
+            if kind == CardKind::Mmc && size == 0xfff {
+                return CsdCapacity::ExtendedCsd;
+            }
+            let multiplier = bits(csd, 49, 47);
+            let read_block_length = bits(csd, 83, 80);
+            let bytes = (u64::from(size) + 1) << (multiplier + 2 + read_block_length);
+            CsdCapacity::Blocks(bytes / BLOCK_SIZE as u64)
+        }
+        (CardKind::Sd, _) => CsdCapacity::UnsupportedStructure(structure),
+    }
+}
+
+/// Returns the number of 512-byte sectors from the given MMC extended card-specific data register.
+///
+/// # Panics
+///
+/// Panics if `ext_csd` is shorter than `EXT_CSD_SIZE`.
+pub fn ext_csd_sectors(ext_csd: &[u8]) -> u64 {
+    u32::from_le_bytes(ext_csd[212..216].try_into().unwrap()).into()
+}
+
+/// Returns the smallest SD clock divisor for an SD host controller which gives a frequency no more
+/// than `target_hz`, given the controller's base clock.
+///
+/// The resulting frequency is `base_hz / (2 * divisor)`, or `base_hz` if the divisor is 0. Version
+/// 3.00 controllers support any 10-bit divisor, while earlier ones only support powers of two up
+/// to 128.
+pub fn clock_divisor(base_hz: u32, target_hz: u32, ten_bit: bool) -> u16 {
+    if target_hz >= base_hz {
+        return 0;
+    }
+    let divisor = base_hz.div_ceil(2 * target_hz);
+    if ten_bit {
+        divisor.min(0x3ff) as u16
+    } else {
+        divisor.next_power_of_two().min(0x80) as u16
+    }
+}
+
+/// Returns the frequency resulting from the given base clock and divisor.
+pub fn divided_clock(base_hz: u32, divisor: u16) -> u32 {
+    if divisor == 0 {
+        base_hz
+    } else {
+        base_hz / (2 * u32::from(divisor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn r2_response() {
+        assert_eq!(
+            r2_from_response([0x1234_5678, 0x9abc_def0, 0, 0x00aa_0000]),
+            0x00aa_0000_0000_0000_9abc_def0_1234_5678 << 8
+        );
+    }
+
+    #[test]
+    fn sd_cid() {
+        // The CID of QEMU's emulated SD card.
+        let cid = u128::from_be_bytes([
+            0xaa, b'X', b'Y', b'Q', b'E', b'M', b'U', b'!', 0x01, 0xde, 0xad, 0xbe, 0xef, 0x00,
+            0x6a, 0x01,
+        ]);
+        assert_eq!(
+            Cid::parse(cid, CardKind::Sd),
+            Cid {
+                manufacturer_id: 0xaa,
+                product_name: "QEMU!".into(),
+                revision: 0x01,
+                serial: 0xdead_beef,
+            }
+        );
+    }
+
+    #[test]
+    fn mmc_cid() {
+        let cid = u128::from_be_bytes([
+            0x15, 0x01, 0x00, b'8', b'G', b'M', b'E', b'4', b'R', 0x30, 0x12, 0x34, 0x56, 0x78,
+            0x91, 0x01,
+        ]);
+        assert_eq!(
+            Cid::parse(cid, CardKind::Mmc),
+            Cid {
+                manufacturer_id: 0x15,
+                product_name: "8GME4R".into(),
+                revision: 0x30,
+                serial: 0x1234_5678,
+            }
+        );
+    }
+
+    #[test]
+    fn csd() {
+        // SD version 2.0 with 2048 units of 512 KiB.
+        let csd = (1 << 126) | (2047 << 48);
+        assert_eq!(
+            csd_capacity(csd, CardKind::Sd),
+            CsdCapacity::Blocks(1 << 21)
+        );
+
+        // SD version 1.0 with 512-byte blocks and a multiplier of 512.
+        let csd = (9 << 80) | (2047 << 62) | (7 << 47);
+        assert_eq!(
+            csd_capacity(csd, CardKind::Sd),
+            CsdCapacity::Blocks(1 << 20)
+        );
+
+        // MMC larger than 2 GiB.
+        let csd = (3 << 126) | (9 << 80) | (0xfff << 62) | (7 << 47);
+        assert_eq!(csd_capacity(csd, CardKind::Mmc), CsdCapacity::ExtendedCsd);
+        assert_eq!(
+            csd_capacity(2 << 126, CardKind::Sd),
+            CsdCapacity::UnsupportedStructure(2)
+        );
+
+        let mut ext_csd = [0; EXT_CSD_SIZE];
+        ext_csd[212..216].copy_from_slice(&[0x00, 0x00, 0xe9, 0x00]);
+        assert_eq!(ext_csd_sectors(&ext_csd), 0x00e9_0000);
+    }
+
+    #[test]
+    fn clock() {
+        // 52 MHz base clock, as on QEMU.
+        assert_eq!(clock_divisor(52_000_000, IDENTIFICATION_CLOCK_HZ, true), 65);
+        assert_eq!(divided_clock(52_000_000, 65), 400_000);
+        assert_eq!(clock_divisor(52_000_000, DEFAULT_CLOCK_HZ, true), 2);
+        assert_eq!(
+            clock_divisor(52_000_000, IDENTIFICATION_CLOCK_HZ, false),
+            128
+        );
+        assert_eq!(clock_divisor(52_000_000, DEFAULT_CLOCK_HZ, false), 2);
+        assert_eq!(clock_divisor(25_000_000, DEFAULT_CLOCK_HZ, false), 0);
+        // Slower than the largest divisor allows.
+        assert_eq!(clock_divisor(200_000_000, 1, true), 0x3ff);
+    }
+}
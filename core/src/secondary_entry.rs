@@ -2,12 +2,17 @@
 // This project is dual-licensed under Apache 2.0 and MIT terms.
 // See LICENSE-APACHE and LICENSE-MIT for details.
 
-use crate::{interrupts::secondary_init_gic, pagetable::PAGETABLE, smc_for_psci};
+//! Starting secondary CPU cores, each with its own stack.
+
+use crate::{interrupts::secondary_init_gic, pagetable::PAGETABLE, psci::smc_for_psci};
 use aarch64_rt::{Stack, start_core};
 use alloc::{boxed::Box, collections::btree_map::BTreeMap};
 use core::ops::DerefMut;
 use log::debug;
-use smccc::{Hvc, Smc, psci};
+use smccc::{
+    Hvc, Smc,
+    psci::{self, AffinityState, LowestAffinityLevel},
+};
 use spin::mutex::SpinMutex;
 
 /// The number of pages to allocate for each secondary core stack.
@@ -52,7 +57,7 @@ pub fn start_core_with_stack(
 ) -> Result<(), psci::Error> {
     let stack = get_secondary_stack(mpidr);
 
-    // SAFETY: We allocate a unique stack per MPIDR, and never deallocate it.
+    // SAFETY: We allocate a unique stack per MPIDR, and only deallocate it once the core is off.
     unsafe {
         if smc_for_psci() {
             start_core::<Smc, _, SECONDARY_STACK_PAGE_COUNT>(mpidr, stack, move || {
@@ -68,11 +73,29 @@ pub fn start_core_with_stack(
     }
 }
 
+/// Frees the stack allocated for the core with the given MPIDR, if it is off. A new stack will be
+/// allocated if it is started again.
+///
+/// Returns `Ok(false)` without freeing anything if the core isn't off, as it may still be using
+/// its stack.
+pub fn free_secondary_stack(mpidr: u64) -> Result<bool, psci::Error> {
+    let state = if smc_for_psci() {
+        psci::affinity_info::<Smc>(mpidr, LowestAffinityLevel::All)
+    } else {
+        psci::affinity_info::<Hvc>(mpidr, LowestAffinityLevel::All)
+    }?;
+    if state != AffinityState::Off {
+        return Ok(false);
+    }
+    SECONDARY_STACKS.lock().remove(&mpidr);
+    Ok(true)
+}
+
 fn secondary_init() {
     // SAFETY: All relevant memory was mapped before the pagetable was activated on the primary
-    // core.
+    // core, and `PAGETABLE` is never dropped.
     unsafe {
-        PAGETABLE.get().unwrap().activate_secondary();
+        PAGETABLE.get().unwrap().lock().activate_secondary();
     }
     debug!("Page table activated on secondary CPU.");
     secondary_init_gic();
@@ -0,0 +1,262 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Arm semihosting calls, which a VMM or debugger handles on behalf of the program.
+//!
+//! QEMU only handles these if it is run with `-semihosting`; otherwise the `HLT` instruction used
+//! to make them causes an exception. crosvm doesn't support semihosting at all. As there is no way
+//! to tell whether semihosting is enabled without trying it, the [`BOOTARG`] kernel command line
+//! argument is used to say so, and [`enabled`] checks for it.
+//!
+//! Besides exiting, semihosting gives access to files on the host and to the host's console,
+//! which QEMU connects to its standard output.
+
+use crate::{
+    FDT,
+    fdt::{bootargs, has_bootarg_flag},
+};
+use core::{
+    arch::asm,
+    fmt::{self, Display, Formatter},
+};
+use embedded_io::{ErrorKind, ErrorType, Read, Write};
+
+/// The kernel command line argument which says that the VMM was started with semihosting enabled.
+pub const BOOTARG: &str = "semihosting";
+
+/// The semihosting operation numbers.
+const SYS_OPEN: u64 = 0x01;
+const SYS_CLOSE: u64 = 0x02;
+const SYS_WRITE: u64 = 0x05;
+const SYS_READ: u64 = 0x06;
+const SYS_FLEN: u64 = 0x0c;
+const SYS_ERRNO: u64 = 0x13;
+const SYS_EXIT: u64 = 0x18;
+
+/// The `SYS_OPEN` modes, which correspond to the `fopen` modes `"rb"` and `"wb"`.
+const OPEN_READ_BINARY: u64 = 1;
+const OPEN_WRITE_BINARY: u64 = 5;
+
+/// The special file name which `SYS_OPEN` opens the host's console for.
+const CONSOLE_PATH: &str = ":tt";
+
+/// The `SYS_EXIT` reason for an application which exited normally, `ADP_Stopped_ApplicationExit`.
+const ADP_STOPPED_APPLICATION_EXIT: u64 = 0x20026;
+
+/// The longest path which can be passed to [`HostFile::open`], in bytes.
+pub const MAX_PATH_LEN: usize = 255;
+
+/// An error from a semihosting call.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SemihostingError {
+    /// The path is longer than [`MAX_PATH_LEN`] or contains a NUL byte.
+    InvalidPath,
+    /// The host reported an error, with the given `errno` value.
+    Host(u64),
+}
+
+impl Display for SemihostingError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidPath => write!(f, "Invalid host path"),
+            Self::Host(errno) => write!(f, "Host error {errno}"),
+        }
+    }
+}
+
+impl embedded_io::Error for SemihostingError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::InvalidPath => ErrorKind::InvalidInput,
+            Self::Host(_) => ErrorKind::Other,
+        }
+    }
+}
+
+/// Returns whether the kernel command line says that semihosting is enabled.
+///
+/// This always returns false before [`FDT`] is set.
+pub fn enabled() -> bool {
+    FDT.get()
+        .and_then(bootargs)
+        .is_some_and(|bootargs| has_bootarg_flag(bootargs, BOOTARG))
+}
+
+/// Makes the given semihosting call with the given parameter, and returns its result.
+///
+/// # Safety
+///
+/// Semihosting must be enabled, and the parameter must be valid for the given operation: where it
+/// points to a parameter block, the block and any buffers it points to must be valid for the
+/// duration of the call, and writable where the operation writes to them.
+unsafe fn call(operation: u64, parameter: u64) -> u64 {
+    let result;
+    // SAFETY: Our caller promises that the parameter is valid for the operation, and semihosting
+    // calls don't otherwise access our memory.
+    unsafe {
+        asm!(
+            "hlt #0xf000",
+            inout("x0") operation => result,
+            in("x1") parameter,
+            options(nostack),
+        );
+    }
+    result
+}
+
+/// Returns the `errno` value of the last semihosting call which failed.
+fn errno() -> SemihostingError {
+    // SAFETY: SYS_ERRNO takes no parameters. The caller has already made a semihosting call, so
+    // semihosting must be enabled.
+    SemihostingError::Host(unsafe { call(SYS_ERRNO, 0) })
+}
+
+/// A file on the host, opened with semihosting. It is closed when dropped.
+#[derive(Debug)]
+pub struct HostFile {
+    handle: u64,
+}
+
+impl HostFile {
+    /// Opens the file at the given path on the host for reading.
+    ///
+    /// This must only be called when semihosting is enabled.
+    pub fn open(path: &str) -> Result<Self, SemihostingError> {
+        Self::open_with_mode(path, OPEN_READ_BINARY)
+    }
+
+    /// Creates or truncates the file at the given path on the host, and opens it for writing.
+    ///
+    /// This must only be called when semihosting is enabled.
+    pub fn create(path: &str) -> Result<Self, SemihostingError> {
+        Self::open_with_mode(path, OPEN_WRITE_BINARY)
+    }
+
+    fn open_with_mode(path: &str, mode: u64) -> Result<Self, SemihostingError> {
+        if path.len() > MAX_PATH_LEN || path.contains('\0') {
+            return Err(SemihostingError::InvalidPath);
+        }
+        // The path must be NUL terminated, even though its length is also passed.
+        let mut path_buffer = [0; MAX_PATH_LEN + 1];
+        path_buffer[..path.len()].copy_from_slice(path.as_bytes());
+        let parameters: [u64; 3] = [path_buffer.as_ptr() as u64, mode, path.len() as u64];
+        // SAFETY: SYS_OPEN only reads the parameter block and the path it points to, which are
+        // valid for the duration of the call.
+        let handle = unsafe { call(SYS_OPEN, parameters.as_ptr() as u64) };
+        if handle as i64 == -1 {
+            return Err(errno());
+        }
+        Ok(Self { handle })
+    }
+
+    /// Returns the length of the file in bytes.
+    pub fn len(&self) -> Result<u64, SemihostingError> {
+        let parameters: [u64; 1] = [self.handle];
+        // SAFETY: SYS_FLEN only reads the parameter block, which is valid for the duration of the
+        // call.
+        let len = unsafe { call(SYS_FLEN, parameters.as_ptr() as u64) };
+        if len as i64 == -1 {
+            return Err(errno());
+        }
+        Ok(len)
+    }
+
+    /// Returns whether the file is empty.
+    pub fn is_empty(&self) -> Result<bool, SemihostingError> {
+        Ok(self.len()? == 0)
+    }
+}
+
+impl Drop for HostFile {
+    fn drop(&mut self) {
+        let parameters: [u64; 1] = [self.handle];
+        // SAFETY: SYS_CLOSE only reads the parameter block, which is valid for the duration of the
+        // call. There's nothing useful to do if it fails.
+        unsafe {
+            call(SYS_CLOSE, parameters.as_ptr() as u64);
+        }
+    }
+}
+
+impl ErrorType for HostFile {
+    type Error = SemihostingError;
+}
+
+impl Read for HostFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let parameters: [u64; 3] = [self.handle, buf.as_mut_ptr() as u64, buf.len() as u64];
+        // SAFETY: SYS_READ reads the parameter block and writes to the buffer it points to, which
+        // are valid for the duration of the call.
+        let not_read = unsafe { call(SYS_READ, parameters.as_ptr() as u64) };
+        // SYS_READ returns the number of bytes which weren't read, or the whole length at the end
+        // of the file. It doesn't distinguish errors from the end of the file.
+        Ok(buf.len().saturating_sub(not_read as usize))
+    }
+}
+
+impl Write for HostFile {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let parameters: [u64; 3] = [self.handle, buf.as_ptr() as u64, buf.len() as u64];
+        // SAFETY: SYS_WRITE only reads the parameter block and the buffer it points to, which are
+        // valid for the duration of the call.
+        let not_written = unsafe { call(SYS_WRITE, parameters.as_ptr() as u64) } as usize;
+        if not_written >= buf.len() {
+            return Err(errno());
+        }
+        Ok(buf.len() - not_written)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// The host's console, written to with semihosting.
+///
+/// This doesn't need any devices to be mapped, so it can be used early in boot, or when the UART
+/// isn't working.
+#[derive(Debug)]
+pub struct HostConsole {
+    file: HostFile,
+}
+
+impl HostConsole {
+    /// Opens the host's console.
+    ///
+    /// This must only be called when semihosting is enabled.
+    pub fn open() -> Result<Self, SemihostingError> {
+        Ok(Self {
+            file: HostFile::create(CONSOLE_PATH)?,
+        })
+    }
+}
+
+impl ErrorType for HostConsole {
+    type Error = SemihostingError;
+}
+
+impl Write for HostConsole {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Exits the VMM with the given exit status.
+///
+/// This must only be called when semihosting is enabled.
+pub fn exit(status: u32) -> ! {
+    let parameters: [u64; 2] = [ADP_STOPPED_APPLICATION_EXIT, status.into()];
+    // SAFETY: SYS_EXIT only reads the parameter block, which is valid for the duration of the call.
+    unsafe {
+        call(SYS_EXIT, parameters.as_ptr() as u64);
+    }
+    panic!("Semihosting SYS_EXIT returned");
+}
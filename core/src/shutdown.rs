@@ -0,0 +1,186 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Orderly shutdown: requests from the platform to power off, such as from a power button, and
+//! hooks which subsystems register to tear themselves down before powering off.
+//!
+//! The power button is found from a `gpio-keys` node in the device tree with a `KEY_POWER` key on a
+//! PL061 GPIO controller, which is how QEMU's virt machine describes the button it presses for
+//! `system_powerdown`. crosvm doesn't currently give aarch64 guests a way to request a shutdown,
+//! so there a request is only seen if its device tree describes a button in the same way.
+
+use crate::{
+    devices::Devices,
+    drivers::pl061::Pl061,
+    fdt::{find_phandle, gic_interrupt, is_compatible, property_cells, property_u32},
+    interrupts::{end_interrupt, remove_shared_irq_handler, set_shared_irq_handler, with_gic},
+};
+use alloc::vec::Vec;
+use arm_gic::IntId;
+use core::{
+    mem::take,
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use dtoolkit::{Node, fdt::Fdt, standard::NodeStandard};
+use log::{info, warn};
+use percore::{ExceptionLock, exception_free};
+use spin::mutex::SpinMutex;
+
+/// The compatible string of the node whose children are keys connected to GPIO lines.
+const GPIO_KEYS_COMPATIBLE: &str = "gpio-keys";
+
+/// The compatible string of the PL061 GPIO controller.
+const PL061_COMPATIBLE: &str = "arm,pl061";
+
+/// The Linux input event code of the power key.
+const KEY_POWER: u32 = 116;
+
+/// A function which tears down part of the system before powering off.
+pub type ShutdownHook = fn(&mut Devices);
+
+/// Whether the platform has asked us to shut down.
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// The registered shutdown hooks and their names, in the order they were added.
+static HOOKS: SpinMutex<Vec<(&'static str, ShutdownHook)>> = SpinMutex::new(Vec::new());
+
+/// The power button, once it has been found.
+static POWER_BUTTON: ExceptionLock<SpinMutex<Option<PowerButton>>> =
+    ExceptionLock::new(SpinMutex::new(None));
+
+/// A power button connected to a GPIO line.
+struct PowerButton {
+    gpio: Pl061,
+    line: u8,
+    intid: IntId,
+}
+
+/// Asks for the system to be shut down, as if the power button had been pressed.
+pub fn request() {
+    if !REQUESTED.swap(true, Ordering::SeqCst) {
+        info!("Shutdown requested");
+    }
+}
+
+/// Returns whether a shutdown has been requested.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Adds a hook to be run by `run_hooks` before powering off.
+///
+/// Hooks are run in the reverse of the order they were added, so that subsystems are torn down in
+/// the reverse of the order they were set up.
+pub fn add_hook(name: &'static str, hook: ShutdownHook) {
+    HOOKS.lock().push((name, hook));
+}
+
+/// Runs and removes all the registered shutdown hooks, most recently added first.
+pub fn run_hooks(devices: &mut Devices) {
+    let hooks = take(&mut *HOOKS.lock());
+    for (name, hook) in hooks.into_iter().rev() {
+        info!("Shutting down {name}");
+        hook(devices);
+    }
+}
+
+/// Finds a power button in the given device tree, and enables its interrupt to request a shutdown
+/// when it is pressed.
+///
+/// Returns whether a power button was found.
+///
+/// # Safety
+///
+/// Any PL061 GPIO controller which the power button is connected to must be mapped as device
+/// memory, and not be used by anything else.
+pub unsafe fn init_power_button(fdt: &Fdt) -> bool {
+    let Some(key) = fdt
+        .root()
+        .find_compatible(GPIO_KEYS_COMPATIBLE)
+        .flat_map(|keys| keys.children())
+        .find(|key| property_u32(key, "linux,code") == Some(KEY_POWER))
+    else {
+        return false;
+    };
+    let Some(&[phandle, line, _flags]) = property_cells(&key, "gpios").as_deref() else {
+        warn!("Power key {} has an unsupported gpios property", key.name());
+        return false;
+    };
+    let Some(controller) = find_phandle(fdt.root(), phandle) else {
+        warn!("Power key {} GPIO controller not found", key.name());
+        return false;
+    };
+    if !is_compatible(&controller, &[PL061_COMPATIBLE]) || line >= 8 {
+        warn!(
+            "Power key {} is on unsupported GPIO controller {} line {line}",
+            key.name(),
+            controller.name()
+        );
+        return false;
+    }
+    let Some(region) = controller
+        .reg()
+        .ok()
+        .flatten()
+        .and_then(|mut reg| reg.next())
+    else {
+        warn!("GPIO controller {} has no registers", controller.name());
+        return false;
+    };
+    let Some((intid, trigger)) =
+        property_cells(&controller, "interrupts").and_then(|cells| gic_interrupt(&cells))
+    else {
+        warn!(
+            "GPIO controller {} has no usable interrupt",
+            controller.name()
+        );
+        return false;
+    };
+    let address = region.address::<u64>().unwrap() as usize;
+    // SAFETY: Our caller promised that the controller is mapped and not used elsewhere.
+    let mut gpio = unsafe { Pl061::new(NonNull::new(address as *mut u8).unwrap()) };
+    let line = line as u8;
+    gpio.enable_rising_edge_interrupt(line);
+    exception_free(|token| {
+        *POWER_BUTTON.borrow(token).lock() = Some(PowerButton { gpio, line, intid });
+    });
+    set_shared_irq_handler(intid, &handle_power_button_irq);
+    with_gic(|gic| {
+        gic.set_interrupt_priority(intid, None, 0x80).unwrap();
+        gic.set_trigger(intid, None, trigger).unwrap();
+        gic.enable_interrupt(intid, None, true).unwrap();
+    });
+    add_hook("power button", remove_power_button);
+    info!(
+        "Power button on {} line {line} at {address:#x}, {intid:?}",
+        controller.name()
+    );
+    true
+}
+
+/// Handles an interrupt from the GPIO controller which the power button is connected to.
+fn handle_power_button_irq(intid: IntId) {
+    let pressed = exception_free(|token| {
+        POWER_BUTTON
+            .borrow(token)
+            .lock()
+            .as_mut()
+            .is_some_and(|button| button.gpio.take_interrupts() & (1 << button.line) != 0)
+    });
+    if pressed {
+        request();
+    }
+    end_interrupt(intid);
+}
+
+/// Disables the power button's interrupt and removes its handler.
+fn remove_power_button(_devices: &mut Devices) {
+    let Some(mut button) = exception_free(|token| POWER_BUTTON.borrow(token).lock().take()) else {
+        return;
+    };
+    with_gic(|gic| gic.enable_interrupt(button.intid, None, false)).unwrap();
+    button.gpio.disable_interrupt(button.line);
+    remove_shared_irq_handler(button.intid);
+}
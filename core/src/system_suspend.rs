@@ -0,0 +1,232 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Suspending the whole system with PSCI SYSTEM_SUSPEND, and resuming where it left off.
+
+use crate::{exceptions::current_el, interrupts::secondary_init_gic, psci::smc_for_psci};
+use core::{
+    arch::naked_asm,
+    fmt::{self, Display, Formatter},
+};
+use percore::exception_free;
+use smccc::{
+    Hvc, Smc,
+    psci::{self, system_suspend},
+};
+use spin::mutex::SpinMutex;
+
+/// The value returned by `save_context_and_suspend` when the system has been suspended and resumed
+/// at `resume_entry`. PSCI return codes are never positive, so this can't be confused with one.
+const RESUMED: u64 = 1;
+
+/// The value returned by `call_system_suspend` when SYSTEM_SUSPEND returned an error, which is
+/// left in `SUSPEND_ERROR`.
+const FAILED: u64 = 2;
+
+/// The error returned by the last SYSTEM_SUSPEND call, if it failed.
+static SUSPEND_ERROR: SpinMutex<Option<psci::Error>> = SpinMutex::new(None);
+
+/// The state of the current core which is lost while the system is suspended, to restore on
+/// resume.
+///
+/// This is aligned to a cache line so that `save_context_and_suspend` can clean it with a fixed
+/// number of cache maintenance instructions.
+#[derive(Debug, Default)]
+#[repr(C, align(64))]
+struct SuspendContext {
+    /// x19 to x30.
+    registers: [u64; 12],
+    sp: u64,
+    mair_el1: u64,
+    tcr_el1: u64,
+    ttbr0_el1: u64,
+    ttbr1_el1: u64,
+    vbar_el1: u64,
+    cpacr_el1: u64,
+    sctlr_el1: u64,
+}
+
+/// How a successful call to `suspend_system` ended.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SuspendOutcome {
+    /// The system was suspended, and the current core resumed at the entry point with its state
+    /// restored.
+    Resumed,
+    /// SYSTEM_SUSPEND returned successfully without the current core losing its state, as a
+    /// hypervisor may do if it implements it as a standby state.
+    Returned,
+}
+
+/// An error suspending the system.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SystemSuspendError {
+    /// Suspending isn't supported at the given exception level.
+    UnsupportedEl(u8),
+    /// The SYSTEM_SUSPEND call failed, such as because other cores are still on.
+    Psci(psci::Error),
+}
+
+impl Display for SystemSuspendError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::UnsupportedEl(el) => write!(f, "System suspend isn't supported at EL{el}"),
+            Self::Psci(e) => write!(f, "SYSTEM_SUSPEND failed: {e}"),
+        }
+    }
+}
+
+/// Suspends the whole system with PSCI SYSTEM_SUSPEND until a wakeup event such as an interrupt,
+/// then restores the state of the current core and returns.
+///
+/// All other cores must be off, and the caller should quiesce devices first with
+/// `Devices::quiesce`. The GIC CPU interface is reinitialised on resume in case it lost its
+/// state, but the distributor is assumed to keep its state, as it does under a VMM. Any interrupt
+/// which woke the system is handled once this returns.
+///
+/// This is only supported at EL1, and the code and stack of the current core must be
+/// identity-mapped, as the firmware resumes with the MMU off.
+pub fn suspend_system() -> Result<SuspendOutcome, SystemSuspendError> {
+    let el = current_el();
+    if el != 1 {
+        return Err(SystemSuspendError::UnsupportedEl(el));
+    }
+    let mut context = SuspendContext::default();
+    // Mask exceptions so that nothing runs between saving the context and suspending, and so that
+    // the wakeup interrupt is only handled once the GIC has been reinitialised.
+    let result = exception_free(|_| {
+        // SAFETY: `resume_entry` only restores the state saved in `context`, which lives until
+        // this returns, and `call_system_suspend` only makes the SYSTEM_SUSPEND call.
+        unsafe { save_context_and_suspend(&mut context, call_system_suspend) }
+    });
+    match result {
+        RESUMED => {
+            secondary_init_gic();
+            Ok(SuspendOutcome::Resumed)
+        }
+        FAILED => Err(SystemSuspendError::Psci(
+            SUSPEND_ERROR.lock().take().unwrap(),
+        )),
+        _ => Ok(SuspendOutcome::Returned),
+    }
+}
+
+/// Calls PSCI SYSTEM_SUSPEND with `resume_entry` as the entry point and the given context.
+///
+/// Returns 0 if it returns successfully, or `FAILED` if it returns an error.
+extern "C" fn call_system_suspend(context: *mut SuspendContext) -> u64 {
+    let entry = resume_entry as usize as u64;
+    let result = if smc_for_psci() {
+        system_suspend::<Smc>(entry, context as u64)
+    } else {
+        system_suspend::<Hvc>(entry, context as u64)
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            *SUSPEND_ERROR.lock() = Some(e);
+            FAILED
+        }
+    }
+}
+
+/// Saves the callee-saved registers and the system registers needed to resume to `context`, and
+/// then tail-calls `suspend` with it.
+///
+/// Returns whatever `suspend` returns if the system isn't suspended, or `RESUMED` once it has been
+/// suspended and resumed at `resume_entry`.
+///
+/// # Safety
+///
+/// `suspend` must not return to `resume_entry` with a context other than `context`, and `context`
+/// must remain valid until the system has resumed.
+#[unsafe(naked)]
+unsafe extern "C" fn save_context_and_suspend(
+    context: *mut SuspendContext,
+    suspend: extern "C" fn(*mut SuspendContext) -> u64,
+) -> u64 {
+    naked_asm!(
+        "stp x19, x20, [x0, #0]",
+        "stp x21, x22, [x0, #16]",
+        "stp x23, x24, [x0, #32]",
+        "stp x25, x26, [x0, #48]",
+        "stp x27, x28, [x0, #64]",
+        "stp x29, x30, [x0, #80]",
+        "mov x9, sp",
+        "str x9, [x0, #96]",
+        "mrs x9, mair_el1",
+        "str x9, [x0, #104]",
+        "mrs x9, tcr_el1",
+        "str x9, [x0, #112]",
+        "mrs x9, ttbr0_el1",
+        "str x9, [x0, #120]",
+        "mrs x9, ttbr1_el1",
+        "str x9, [x0, #128]",
+        "mrs x9, vbar_el1",
+        "str x9, [x0, #136]",
+        "mrs x9, cpacr_el1",
+        "str x9, [x0, #144]",
+        "mrs x9, sctlr_el1",
+        "str x9, [x0, #152]",
+        // `resume_entry` reads the context with the MMU and caches off, so clean it to the point
+        // of coherency.
+        "dc cvac, x0",
+        "add x9, x0, #64",
+        "dc cvac, x9",
+        "add x9, x0, #128",
+        "dc cvac, x9",
+        "dsb sy",
+        // Tail-call, so that `suspend` returns straight to our caller if the system isn't
+        // suspended.
+        "br x1",
+    )
+}
+
+/// The entry point at which the firmware resumes the system, with the MMU and caches off and `x0`
+/// pointing to the context saved by `save_context_and_suspend`.
+///
+/// Restores the context, making `save_context_and_suspend` return `RESUMED`.
+///
+/// # Safety
+///
+/// This must only be used as the entry point for SYSTEM_SUSPEND, with the context saved by
+/// `save_context_and_suspend`.
+#[unsafe(naked)]
+unsafe extern "C" fn resume_entry(context: *const SuspendContext) -> ! {
+    naked_asm!(
+        "ldr x9, [x0, #104]",
+        "msr mair_el1, x9",
+        "ldr x9, [x0, #112]",
+        "msr tcr_el1, x9",
+        "ldr x9, [x0, #120]",
+        "msr ttbr0_el1, x9",
+        "ldr x9, [x0, #128]",
+        "msr ttbr1_el1, x9",
+        "ldr x9, [x0, #136]",
+        "msr vbar_el1, x9",
+        "ldr x9, [x0, #144]",
+        "msr cpacr_el1, x9",
+        "isb",
+        // Discard any stale TLB entries and instructions from before the system was suspended.
+        "tlbi vmalle1",
+        "ic iallu",
+        "dsb nsh",
+        "isb",
+        // Turn the MMU and caches back on. The code is identity-mapped, so execution carries on
+        // from the next instruction.
+        "ldr x9, [x0, #152]",
+        "msr sctlr_el1, x9",
+        "isb",
+        "ldp x19, x20, [x0, #0]",
+        "ldp x21, x22, [x0, #16]",
+        "ldp x23, x24, [x0, #32]",
+        "ldp x25, x26, [x0, #48]",
+        "ldp x27, x28, [x0, #64]",
+        "ldp x29, x30, [x0, #80]",
+        "ldr x9, [x0, #96]",
+        "mov sp, x9",
+        "mov x0, #{resumed}",
+        "ret",
+        resumed = const RESUMED,
+    )
+}
@@ -0,0 +1,224 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! TCP segment headers and checksums.
+
+use crate::ipv4::{Checksum, Ipv4Address, PROTOCOL_TCP};
+use alloc::vec::Vec;
+use core::{
+    fmt::{self, Debug, Formatter},
+    ops::BitOr,
+};
+
+/// The size of a TCP header without options.
+pub const TCP_HEADER_SIZE: usize = 20;
+
+/// The control bits of a TCP segment.
+#[derive(Clone, Copy, Default, Eq, PartialEq)]
+pub struct TcpFlags(pub u8);
+
+impl TcpFlags {
+    pub const FIN: Self = Self(0x01);
+    pub const SYN: Self = Self(0x02);
+    pub const RST: Self = Self(0x04);
+    pub const PSH: Self = Self(0x08);
+    pub const ACK: Self = Self(0x10);
+
+    /// Returns whether all the flags in `other` are set.
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for TcpFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl Debug for TcpFlags {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let names = [
+            (Self::FIN, "FIN"),
+            (Self::SYN, "SYN"),
+            (Self::RST, "RST"),
+            (Self::PSH, "PSH"),
+            (Self::ACK, "ACK"),
+        ];
+        let mut first = true;
+        for (flag, name) in names {
+            if self.contains(flag) {
+                if !first {
+                    write!(f, "|")?;
+                }
+                write!(f, "{name}")?;
+                first = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The fields of a TCP header which we use. Options are skipped when parsing, and never sent.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TcpHeader {
+    pub source_port: u16,
+    pub destination_port: u16,
+    pub sequence: u32,
+    pub acknowledgement: u32,
+    pub flags: TcpFlags,
+    pub window: u16,
+}
+
+impl TcpHeader {
+    /// Parses the header from the start of the given segment, which was sent from `source` to
+    /// `destination`, and returns it along with the payload.
+    ///
+    /// Returns `None` if the segment is truncated or its checksum is incorrect.
+    pub fn parse(
+        segment: &[u8],
+        source: Ipv4Address,
+        destination: Ipv4Address,
+    ) -> Option<(Self, &[u8])> {
+        let header = segment.first_chunk::<TCP_HEADER_SIZE>()?;
+        let header_length = usize::from(header[12] >> 4) * 4;
+        if header_length < TCP_HEADER_SIZE || segment.len() < header_length {
+            return None;
+        }
+        let mut checksum = pseudo_header_checksum(source, destination, segment.len());
+        checksum.add_bytes(segment);
+        if checksum.finish() != 0 {
+            return None;
+        }
+        Some((
+            Self {
+                source_port: u16::from_be_bytes([header[0], header[1]]),
+                destination_port: u16::from_be_bytes([header[2], header[3]]),
+                sequence: u32::from_be_bytes(header[4..8].try_into().unwrap()),
+                acknowledgement: u32::from_be_bytes(header[8..12].try_into().unwrap()),
+                flags: TcpFlags(header[13] & 0x3f),
+                window: u16::from_be_bytes([header[14], header[15]]),
+            },
+            &segment[header_length..],
+        ))
+    }
+
+    /// Returns a segment with this header and the given payload, sent from `source` to
+    /// `destination`, with its checksum filled in.
+    pub fn to_segment(
+        &self,
+        source: Ipv4Address,
+        destination: Ipv4Address,
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let mut segment = Vec::with_capacity(TCP_HEADER_SIZE + payload.len());
+        segment.extend_from_slice(&self.source_port.to_be_bytes());
+        segment.extend_from_slice(&self.destination_port.to_be_bytes());
+        segment.extend_from_slice(&self.sequence.to_be_bytes());
+        segment.extend_from_slice(&self.acknowledgement.to_be_bytes());
+        segment.push(((TCP_HEADER_SIZE / 4) as u8) << 4);
+        segment.push(self.flags.0);
+        segment.extend_from_slice(&self.window.to_be_bytes());
+        // The checksum and urgent pointer.
+        segment.extend_from_slice(&[0; 4]);
+        segment.extend_from_slice(payload);
+        let mut checksum = pseudo_header_checksum(source, destination, segment.len());
+        checksum.add_bytes(&segment);
+        let checksum = checksum.finish();
+        segment[16..18].copy_from_slice(&checksum.to_be_bytes());
+        segment
+    }
+}
+
+/// Returns the checksum of the IPv4 pseudo-header for a TCP segment of the given length.
+fn pseudo_header_checksum(
+    source: Ipv4Address,
+    destination: Ipv4Address,
+    segment_length: usize,
+) -> Checksum {
+    let mut checksum = Checksum::default();
+    checksum.add_bytes(&source.0);
+    checksum.add_bytes(&destination.0);
+    checksum.add_u16(PROTOCOL_TCP.into());
+    checksum.add_u16(segment_length as u16);
+    checksum
+}
+
+/// Returns whether sequence number `a` is before `b`, allowing for wrapping.
+pub fn sequence_before(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT: Ipv4Address = Ipv4Address([10, 0, 2, 15]);
+    const SERVER: Ipv4Address = Ipv4Address([10, 0, 2, 2]);
+
+    #[test]
+    fn segment_round_trip() {
+        let header = TcpHeader {
+            source_port: 49152,
+            destination_port: 23,
+            sequence: 0x12345678,
+            acknowledgement: 0x9abcdef0,
+            flags: TcpFlags::PSH | TcpFlags::ACK,
+            window: 4096,
+        };
+        let segment = header.to_segment(CLIENT, SERVER, b"hello");
+        assert_eq!(segment.len(), TCP_HEADER_SIZE + 5);
+        assert_eq!(
+            TcpHeader::parse(&segment, CLIENT, SERVER),
+            Some((header, &b"hello"[..]))
+        );
+        // The checksum covers the addresses in the pseudo-header.
+        assert_eq!(
+            TcpHeader::parse(&segment, Ipv4Address([10, 0, 2, 3]), SERVER),
+            None
+        );
+    }
+
+    #[test]
+    fn skip_options() {
+        let header = TcpHeader {
+            source_port: 23,
+            destination_port: 49152,
+            sequence: 1,
+            acknowledgement: 2,
+            flags: TcpFlags::SYN | TcpFlags::ACK,
+            window: 1024,
+        };
+        let mut segment = header.to_segment(SERVER, CLIENT, &[]);
+        // Add a maximum segment size option, and fix up the data offset and checksum.
+        segment.extend_from_slice(&[2, 4, 0x05, 0xb4]);
+        segment[12] = 6 << 4;
+        segment[16..18].fill(0);
+        let mut checksum = pseudo_header_checksum(SERVER, CLIENT, segment.len());
+        checksum.add_bytes(&segment);
+        let checksum = checksum.finish();
+        segment[16..18].copy_from_slice(&checksum.to_be_bytes());
+
+        assert_eq!(
+            TcpHeader::parse(&segment, SERVER, CLIENT),
+            Some((header, &[][..]))
+        );
+    }
+
+    #[test]
+    fn format_flags() {
+        assert_eq!(format!("{:?}", TcpFlags::SYN | TcpFlags::ACK), "SYN|ACK");
+        assert_eq!(format!("{:?}", TcpFlags::default()), "");
+    }
+
+    #[test]
+    fn sequence_wrapping() {
+        assert!(sequence_before(1, 2));
+        assert!(!sequence_before(2, 2));
+        assert!(sequence_before(u32::MAX, 0));
+        assert!(!sequence_before(0, u32::MAX));
+    }
+}
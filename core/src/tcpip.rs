@@ -0,0 +1,785 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A minimal IPv4 stack over a network interface, with ARP, UDP and enough TCP for simple
+//! connections to and from peers, one at a time.
+//!
+//! Everything is done by polling the interface from the caller's thread, so frames are only
+//! received while a call is in progress. TCP segments are sent one at a time and each is
+//! retransmitted until it is acknowledged, without congestion control.
+//!
+//! By default ARP requests for our address and ICMP echo requests to it are answered whenever
+//! frames are received, so that the host can ping us. This can be turned off with
+//! `set_responder_enabled`.
+
+use crate::{
+    arp::{ArpOperation, ArpPacket, ETHERTYPE_ARP},
+    drivers::generic_timer::Instant,
+    ethernet::{ETHERNET_HEADER_SIZE, EthernetHeader, MAX_FRAME_SIZE, MIN_FRAME_SIZE, MacAddress},
+    icmp::echo_reply,
+    ipv4::{
+        DEFAULT_TTL, ETHERTYPE_IPV4, IPV4_HEADER_SIZE, IpConfig, Ipv4Address, Ipv4Header,
+        PROTOCOL_ICMP, PROTOCOL_TCP, PROTOCOL_UDP,
+    },
+    net::{NetError, NetworkInterface},
+    tcp::{TcpFlags, TcpHeader, sequence_before},
+    udp::{UDP_HEADER_SIZE, UdpHeader},
+};
+use alloc::{
+    collections::{btree_map::BTreeMap, vec_deque::VecDeque},
+    vec::Vec,
+};
+use core::{
+    fmt::{self, Display, Formatter},
+    hint::spin_loop,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+/// How long to wait for a reply to an ARP request before sending it again.
+const ARP_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How many ARP requests to send before giving up on resolving an address.
+const ARP_ATTEMPTS: u32 = 3;
+
+/// How long to wait for a TCP segment to be acknowledged before sending it again.
+const RETRANSMIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How many times to send a TCP data segment before giving up on the connection.
+const MAX_TRANSMISSIONS: u32 = 5;
+
+/// How long to wait for our FIN to be acknowledged when closing a connection.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The largest TCP payload we send in a segment. We don't parse the peer's maximum segment size
+/// option, so this is the default which every host must accept.
+const MAX_SEGMENT_SIZE: usize = 536;
+
+/// The most received data to buffer for a connection before the caller reads it.
+const RECEIVE_BUFFER_SIZE: usize = 8192;
+
+/// The largest UDP payload which fits in a single frame, as we don't fragment packets.
+pub const MAX_UDP_PAYLOAD: usize =
+    MAX_FRAME_SIZE - ETHERNET_HEADER_SIZE - IPV4_HEADER_SIZE - UDP_HEADER_SIZE;
+
+/// Whether ARP requests and ICMP echo requests for our address are answered.
+static RESPONDER_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Sets whether ARP requests and ICMP echo requests for our address are answered.
+///
+/// Addresses are still learnt from ARP packets while this is disabled, but peers which don't
+/// already know our MAC address won't be able to reach us.
+pub fn set_responder_enabled(enabled: bool) {
+    RESPONDER_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether ARP requests and ICMP echo requests for our address are answered.
+pub fn responder_enabled() -> bool {
+    RESPONDER_ENABLED.load(Ordering::Relaxed)
+}
+
+/// An error from the IP stack.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TcpIpError {
+    /// The network interface returned an error.
+    Net(NetError),
+    /// The destination isn't on our subnet and we have no gateway.
+    NoRoute(Ipv4Address),
+    /// Nothing replied to our ARP requests for the given address.
+    ArpTimeout(Ipv4Address),
+    /// The peer didn't accept the connection in time.
+    ConnectTimeout,
+    /// The peer refused the connection.
+    ConnectionRefused,
+    /// The peer reset the connection.
+    ConnectionReset,
+    /// The peer didn't acknowledge data we sent, despite retransmissions.
+    Timeout,
+    /// The connection has already been closed.
+    Closed,
+    /// A datagram was too large to send in a single frame.
+    MessageTooLong(usize),
+}
+
+impl Display for TcpIpError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Net(e) => write!(f, "{e}"),
+            Self::NoRoute(address) => write!(f, "No route to {address}"),
+            Self::ArpTimeout(address) => write!(f, "No ARP reply from {address}"),
+            Self::ConnectTimeout => write!(f, "Connection timed out"),
+            Self::ConnectionRefused => write!(f, "Connection refused"),
+            Self::ConnectionReset => write!(f, "Connection reset by peer"),
+            Self::Timeout => write!(f, "Peer stopped acknowledging data"),
+            Self::Closed => write!(f, "Connection closed"),
+            Self::MessageTooLong(length) => write!(
+                f,
+                "Message of {length} bytes too long, at most {MAX_UDP_PAYLOAD} bytes fit in a frame"
+            ),
+        }
+    }
+}
+
+impl From<NetError> for TcpIpError {
+    fn from(e: NetError) -> Self {
+        Self::Net(e)
+    }
+}
+
+/// The addresses learnt from ARP packets, which can be kept between uses of a stack on the same
+/// interface.
+pub type ArpCache = BTreeMap<Ipv4Address, MacAddress>;
+
+/// An IPv4 packet addressed to us.
+struct Packet {
+    header: Ipv4Header,
+    payload: Vec<u8>,
+}
+
+/// What happened to a frame received by the stack.
+enum Received {
+    /// No frame was available.
+    Nothing,
+    /// A frame was received and either handled by the stack itself or dropped.
+    Handled,
+    /// An IPv4 packet addressed to us was received, for the caller to handle.
+    Packet(Packet),
+}
+
+/// An IPv4 stack using a network interface with a static configuration.
+pub struct IpStack<'a> {
+    interface: &'a mut dyn NetworkInterface,
+    mac_address: MacAddress,
+    config: IpConfig,
+    arp_cache: ArpCache,
+    next_identification: u16,
+}
+
+impl<'a> IpStack<'a> {
+    /// Creates a stack sending and receiving on the given interface with the given address.
+    pub fn new(interface: &'a mut dyn NetworkInterface, config: IpConfig) -> Self {
+        Self::with_arp_cache(interface, config, ArpCache::new())
+    }
+
+    /// Creates a stack as with `new`, starting with addresses learnt by a previous stack on the
+    /// same interface so that they needn't be resolved again.
+    pub fn with_arp_cache(
+        interface: &'a mut dyn NetworkInterface,
+        config: IpConfig,
+        arp_cache: ArpCache,
+    ) -> Self {
+        let mac_address = interface.mac_address();
+        Self {
+            interface,
+            mac_address,
+            config,
+            arp_cache,
+            next_identification: 0,
+        }
+    }
+
+    /// Returns the addresses learnt by the stack, to pass to the next stack on the same interface.
+    pub fn into_arp_cache(self) -> ArpCache {
+        self.arp_cache
+    }
+
+    /// Returns the stack's IP configuration.
+    pub fn config(&self) -> IpConfig {
+        self.config
+    }
+
+    /// Sends an Ethernet frame with the given header fields and payload, padded to the minimum
+    /// frame size.
+    fn send_frame(
+        &mut self,
+        destination: MacAddress,
+        ethertype: u16,
+        payload: &[&[u8]],
+    ) -> Result<(), TcpIpError> {
+        let header = EthernetHeader {
+            destination,
+            source: self.mac_address,
+            ethertype,
+        };
+        let mut frame = Vec::with_capacity(MAX_FRAME_SIZE);
+        frame.extend_from_slice(&header.to_bytes());
+        for part in payload {
+            frame.extend_from_slice(part);
+        }
+        if frame.len() < MIN_FRAME_SIZE {
+            frame.resize(MIN_FRAME_SIZE, 0);
+        }
+        self.interface.send(&frame)?;
+        Ok(())
+    }
+
+    /// Handles all frames which have already been received, answering ARP and ICMP echo requests
+    /// if the responder is enabled and dropping everything else.
+    ///
+    /// This is for when nothing else is using the interface, so that the host can still reach us.
+    /// Returns the number of frames handled.
+    pub fn respond(&mut self) -> Result<usize, TcpIpError> {
+        let mut frames = 0;
+        while !matches!(self.poll_frame()?, Received::Nothing) {
+            frames += 1;
+        }
+        Ok(frames)
+    }
+
+    /// Receives a frame if one is available, and handles it if it is ARP or an ICMP echo request.
+    ///
+    /// Returns the frame's packet if it is some other IPv4 packet addressed to us.
+    fn poll(&mut self) -> Result<Option<Packet>, TcpIpError> {
+        match self.poll_frame()? {
+            Received::Packet(packet) => Ok(Some(packet)),
+            Received::Nothing | Received::Handled => Ok(None),
+        }
+    }
+
+    /// Receives a frame if one is available, and handles it if it is ARP or an ICMP echo request.
+    fn poll_frame(&mut self) -> Result<Received, TcpIpError> {
+        let mut frame = [0; MAX_FRAME_SIZE];
+        let Some(length) = self.interface.receive(&mut frame)? else {
+            return Ok(Received::Nothing);
+        };
+        let Some((header, payload)) = EthernetHeader::parse(&frame[..length]) else {
+            return Ok(Received::Handled);
+        };
+        match header.ethertype {
+            ETHERTYPE_ARP => {
+                if let Some(arp) = ArpPacket::parse(payload) {
+                    self.handle_arp(arp)?;
+                }
+                Ok(Received::Handled)
+            }
+            ETHERTYPE_IPV4 => {
+                let Some((ip_header, payload)) = Ipv4Header::parse(payload)
+                    .filter(|(ip_header, _)| ip_header.destination == self.config.address)
+                else {
+                    return Ok(Received::Handled);
+                };
+                if ip_header.protocol == PROTOCOL_ICMP {
+                    self.handle_icmp(header.source, ip_header, payload)?;
+                    return Ok(Received::Handled);
+                }
+                Ok(Received::Packet(Packet {
+                    header: ip_header,
+                    payload: payload.to_vec(),
+                }))
+            }
+            _ => Ok(Received::Handled),
+        }
+    }
+
+    /// Replies to an ICMP echo request, if the responder is enabled.
+    ///
+    /// The reply is sent straight back to the MAC address the request came from, so that
+    /// answering doesn't need an ARP lookup.
+    fn handle_icmp(
+        &mut self,
+        source_mac: MacAddress,
+        header: Ipv4Header,
+        message: &[u8],
+    ) -> Result<(), TcpIpError> {
+        if !responder_enabled() {
+            return Ok(());
+        }
+        let Some(reply) = echo_reply(message) else {
+            return Ok(());
+        };
+        let reply_header = Ipv4Header {
+            source: self.config.address,
+            destination: header.source,
+            protocol: PROTOCOL_ICMP,
+            ttl: DEFAULT_TTL,
+            identification: self.next_identification,
+            payload_length: reply.len() as u16,
+        };
+        self.next_identification = self.next_identification.wrapping_add(1);
+        self.send_frame(
+            source_mac,
+            ETHERTYPE_IPV4,
+            &[&reply_header.to_bytes(), &reply],
+        )
+    }
+
+    /// Learns the sender's address from an ARP packet, and replies if it is a request for ours and
+    /// the responder is enabled.
+    fn handle_arp(&mut self, arp: ArpPacket) -> Result<(), TcpIpError> {
+        if arp.sender_ip != Ipv4Address::UNSPECIFIED {
+            self.arp_cache.insert(arp.sender_ip, arp.sender_mac);
+        }
+        if arp.operation == ArpOperation::Request
+            && arp.target_ip == self.config.address
+            && responder_enabled()
+        {
+            let reply = ArpPacket {
+                operation: ArpOperation::Reply,
+                sender_mac: self.mac_address,
+                sender_ip: self.config.address,
+                target_mac: arp.sender_mac,
+                target_ip: arp.sender_ip,
+            };
+            self.send_frame(arp.sender_mac, ETHERTYPE_ARP, &[&reply.to_bytes()])?;
+        }
+        Ok(())
+    }
+
+    /// Returns the MAC address to send packets for the given destination to, sending ARP requests
+    /// for it if it isn't already known.
+    ///
+    /// Any IPv4 packets received while waiting for a reply are dropped.
+    fn resolve(&mut self, destination: Ipv4Address) -> Result<MacAddress, TcpIpError> {
+        let next_hop = self
+            .config
+            .next_hop(destination)
+            .ok_or(TcpIpError::NoRoute(destination))?;
+        for _ in 0..ARP_ATTEMPTS {
+            if let Some(&mac_address) = self.arp_cache.get(&next_hop) {
+                return Ok(mac_address);
+            }
+            let request = ArpPacket {
+                operation: ArpOperation::Request,
+                sender_mac: self.mac_address,
+                sender_ip: self.config.address,
+                target_mac: MacAddress::default(),
+                target_ip: next_hop,
+            };
+            self.send_frame(MacAddress::BROADCAST, ETHERTYPE_ARP, &[&request.to_bytes()])?;
+            let deadline = Instant::now() + ARP_RETRY_INTERVAL;
+            while Instant::now() < deadline && !self.arp_cache.contains_key(&next_hop) {
+                if self.poll()?.is_none() {
+                    spin_loop();
+                }
+            }
+        }
+        self.arp_cache
+            .get(&next_hop)
+            .copied()
+            .ok_or(TcpIpError::ArpTimeout(next_hop))
+    }
+
+    /// Sends an IPv4 packet with the given protocol and payload.
+    fn send_ipv4(
+        &mut self,
+        destination: Ipv4Address,
+        protocol: u8,
+        payload: &[u8],
+    ) -> Result<(), TcpIpError> {
+        let mac_address = self.resolve(destination)?;
+        let header = Ipv4Header {
+            source: self.config.address,
+            destination,
+            protocol,
+            ttl: DEFAULT_TTL,
+            identification: self.next_identification,
+            payload_length: payload.len() as u16,
+        };
+        self.next_identification = self.next_identification.wrapping_add(1);
+        self.send_frame(mac_address, ETHERTYPE_IPV4, &[&header.to_bytes(), payload])
+    }
+}
+
+/// A UDP datagram received by a `UdpSocket`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UdpDatagram {
+    pub source: Ipv4Address,
+    pub source_port: u16,
+    pub payload: Vec<u8>,
+}
+
+/// A UDP socket bound to a local port.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UdpSocket {
+    local_port: u16,
+}
+
+impl UdpSocket {
+    /// Creates a socket which sends from and receives on the given local port.
+    pub fn bind(local_port: u16) -> Self {
+        Self { local_port }
+    }
+
+    /// Returns the local port which the socket is bound to.
+    pub fn local_port(&self) -> u16 {
+        self.local_port
+    }
+
+    /// Sends the given data as a single datagram to the given address and port.
+    pub fn send_to(
+        &self,
+        stack: &mut IpStack,
+        destination: Ipv4Address,
+        port: u16,
+        data: &[u8],
+    ) -> Result<(), TcpIpError> {
+        if data.len() > MAX_UDP_PAYLOAD {
+            return Err(TcpIpError::MessageTooLong(data.len()));
+        }
+        let header = UdpHeader {
+            source_port: self.local_port,
+            destination_port: port,
+        };
+        let datagram = header.to_datagram(stack.config.address, destination, data);
+        stack.send_ipv4(destination, PROTOCOL_UDP, &datagram)
+    }
+
+    /// Returns the next datagram received for this socket, if there is one, discarding anything
+    /// else received before it.
+    pub fn recv_from(&self, stack: &mut IpStack) -> Result<Option<UdpDatagram>, TcpIpError> {
+        while let Some(packet) = stack.poll()? {
+            if packet.header.protocol != PROTOCOL_UDP {
+                continue;
+            }
+            let Some((header, payload)) =
+                UdpHeader::parse(&packet.payload, packet.header.source, stack.config.address)
+            else {
+                continue;
+            };
+            if header.destination_port == self.local_port {
+                return Ok(Some(UdpDatagram {
+                    source: packet.header.source,
+                    source_port: header.source_port,
+                    payload: payload.to_vec(),
+                }));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// The state of a TCP connection once it has been established.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum TcpState {
+    /// Both sides may send data.
+    Established,
+    /// The peer has sent a FIN, so it won't send any more data.
+    CloseWait,
+    /// We have sent a FIN, or the connection was reset.
+    Closed,
+}
+
+/// A TCP port on which connections from peers are accepted.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TcpListener {
+    local_port: u16,
+}
+
+impl TcpListener {
+    /// Creates a listener which accepts connections to the given local port.
+    pub fn bind(local_port: u16) -> Self {
+        Self { local_port }
+    }
+
+    /// Returns the local port which the listener accepts connections to.
+    pub fn local_port(&self) -> u16 {
+        self.local_port
+    }
+
+    /// Accepts a connection if a peer has asked for one, discarding anything else received before
+    /// the request.
+    ///
+    /// Returns `None` if no request has been received. Otherwise waits for the peer to complete the
+    /// handshake, retransmitting our reply if necessary.
+    pub fn accept(&self, stack: &mut IpStack) -> Result<Option<TcpConnection>, TcpIpError> {
+        while let Some(packet) = stack.poll()? {
+            if packet.header.protocol != PROTOCOL_TCP {
+                continue;
+            }
+            let Some((header, _)) =
+                TcpHeader::parse(&packet.payload, packet.header.source, stack.config.address)
+            else {
+                continue;
+            };
+            if header.destination_port != self.local_port
+                || !header.flags.contains(TcpFlags::SYN)
+                || header.flags.contains(TcpFlags::ACK)
+            {
+                continue;
+            }
+            let initial_sequence = initial_sequence();
+            let mut connection = TcpConnection {
+                remote: packet.header.source,
+                remote_port: header.source_port,
+                local_port: self.local_port,
+                state: TcpState::Established,
+                send_unacknowledged: initial_sequence,
+                send_next: initial_sequence.wrapping_add(1),
+                receive_next: header.sequence.wrapping_add(1),
+                received: VecDeque::new(),
+            };
+            return match connection.send_until_acknowledged(
+                stack,
+                initial_sequence,
+                TcpFlags::SYN | TcpFlags::ACK,
+                &[],
+            ) {
+                Ok(()) => Ok(Some(connection)),
+                Err(TcpIpError::Timeout) => Err(TcpIpError::ConnectTimeout),
+                Err(e) => Err(e),
+            };
+        }
+        Ok(None)
+    }
+}
+
+/// Returns an initial sequence number for a new connection.
+///
+/// This uses the counter, so that it is different for each connection.
+fn initial_sequence() -> u32 {
+    Instant::now().ticks() as u32
+}
+
+/// A TCP connection to a peer, initiated by either side.
+pub struct TcpConnection {
+    remote: Ipv4Address,
+    remote_port: u16,
+    local_port: u16,
+    state: TcpState,
+    /// The sequence number of the oldest byte we have sent which hasn't been acknowledged.
+    send_unacknowledged: u32,
+    /// The sequence number of the next byte we will send.
+    send_next: u32,
+    /// The sequence number of the next byte we expect from the peer.
+    receive_next: u32,
+    /// Data received from the peer which hasn't been read yet.
+    received: VecDeque<u8>,
+}
+
+impl TcpConnection {
+    /// Connects from the given local port to the given remote address and port, waiting up to the
+    /// given timeout for the peer to accept.
+    pub fn connect(
+        stack: &mut IpStack,
+        remote: Ipv4Address,
+        remote_port: u16,
+        local_port: u16,
+        timeout: Duration,
+    ) -> Result<Self, TcpIpError> {
+        let initial_sequence = initial_sequence();
+        let mut connection = Self {
+            remote,
+            remote_port,
+            local_port,
+            state: TcpState::Closed,
+            send_unacknowledged: initial_sequence,
+            send_next: initial_sequence.wrapping_add(1),
+            receive_next: 0,
+            received: VecDeque::new(),
+        };
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            connection.send_segment(stack, initial_sequence, TcpFlags::SYN, &[])?;
+            let retransmit_at = (Instant::now() + RETRANSMIT_TIMEOUT).min(deadline);
+            while Instant::now() < retransmit_at {
+                let Some((header, _)) = connection.next_segment(stack)? else {
+                    spin_loop();
+                    continue;
+                };
+                if header.flags.contains(TcpFlags::ACK)
+                    && header.acknowledgement == connection.send_next
+                {
+                    if header.flags.contains(TcpFlags::RST) {
+                        return Err(TcpIpError::ConnectionRefused);
+                    }
+                    if header.flags.contains(TcpFlags::SYN) {
+                        connection.send_unacknowledged = connection.send_next;
+                        connection.receive_next = header.sequence.wrapping_add(1);
+                        connection.state = TcpState::Established;
+                        connection.send_ack(stack)?;
+                        return Ok(connection);
+                    }
+                }
+            }
+        }
+        Err(TcpIpError::ConnectTimeout)
+    }
+
+    /// Sends all the given data, waiting for each segment to be acknowledged.
+    ///
+    /// Data received in the meantime is buffered to be read with `recv`.
+    pub fn send(&mut self, stack: &mut IpStack, data: &[u8]) -> Result<(), TcpIpError> {
+        for chunk in data.chunks(MAX_SEGMENT_SIZE) {
+            if self.state == TcpState::Closed {
+                return Err(TcpIpError::Closed);
+            }
+            let sequence = self.send_next;
+            self.send_next = self.send_next.wrapping_add(chunk.len() as u32);
+            self.send_until_acknowledged(stack, sequence, TcpFlags::PSH | TcpFlags::ACK, chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Handles any segments which have been received, buffering their data to be read with `recv`.
+    pub fn poll(&mut self, stack: &mut IpStack) -> Result<(), TcpIpError> {
+        while let Some((header, payload)) = self.next_segment(stack)? {
+            self.handle_segment(stack, header, &payload)?;
+        }
+        Ok(())
+    }
+
+    /// Reads received data into the given buffer, returning how many bytes were read.
+    pub fn recv(&mut self, buf: &mut [u8]) -> usize {
+        let length = buf.len().min(self.received.len());
+        for (byte, received) in buf.iter_mut().zip(self.received.drain(..length)) {
+            *byte = received;
+        }
+        length
+    }
+
+    /// Returns the peer's address and port.
+    pub fn remote(&self) -> (Ipv4Address, u16) {
+        (self.remote, self.remote_port)
+    }
+
+    /// Returns whether the peer has closed its side of the connection or reset it, so it won't
+    /// send any more data.
+    pub fn peer_closed(&self) -> bool {
+        self.state != TcpState::Established
+    }
+
+    /// Closes our side of the connection, waiting briefly for the peer to acknowledge it.
+    pub fn close(mut self, stack: &mut IpStack) -> Result<(), TcpIpError> {
+        if self.state == TcpState::Closed {
+            return Ok(());
+        }
+        let sequence = self.send_next;
+        self.send_next = self.send_next.wrapping_add(1);
+        self.send_segment(stack, sequence, TcpFlags::FIN | TcpFlags::ACK, &[])?;
+        self.state = TcpState::Closed;
+        let deadline = Instant::now() + CLOSE_TIMEOUT;
+        while Instant::now() < deadline && self.send_unacknowledged != self.send_next {
+            match self.next_segment(stack)? {
+                Some((header, payload)) => self.handle_segment(stack, header, &payload)?,
+                None => spin_loop(),
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a segment with the given sequence number, flags and payload, and then waits for it to
+    /// be acknowledged, retransmitting it if necessary.
+    fn send_until_acknowledged(
+        &mut self,
+        stack: &mut IpStack,
+        sequence: u32,
+        flags: TcpFlags,
+        payload: &[u8],
+    ) -> Result<(), TcpIpError> {
+        for _ in 0..MAX_TRANSMISSIONS {
+            self.send_segment(stack, sequence, flags, payload)?;
+            let retransmit_at = Instant::now() + RETRANSMIT_TIMEOUT;
+            while Instant::now() < retransmit_at {
+                match self.next_segment(stack)? {
+                    Some((header, payload)) => self.handle_segment(stack, header, &payload)?,
+                    None => spin_loop(),
+                }
+                if self.send_unacknowledged == self.send_next {
+                    return Ok(());
+                }
+            }
+        }
+        Err(TcpIpError::Timeout)
+    }
+
+    /// Handles a segment received for this connection once it is established.
+    fn handle_segment(
+        &mut self,
+        stack: &mut IpStack,
+        header: TcpHeader,
+        payload: &[u8],
+    ) -> Result<(), TcpIpError> {
+        if header.flags.contains(TcpFlags::RST) {
+            self.state = TcpState::Closed;
+            return Err(TcpIpError::ConnectionReset);
+        }
+        if header.flags.contains(TcpFlags::ACK)
+            && sequence_before(self.send_unacknowledged, header.acknowledgement)
+            && !sequence_before(self.send_next, header.acknowledgement)
+        {
+            self.send_unacknowledged = header.acknowledgement;
+        }
+        if header.sequence != self.receive_next {
+            // Out of order or retransmitted, so acknowledge what we have so far and wait for the
+            // peer to send the rest again.
+            if !payload.is_empty() || header.flags.contains(TcpFlags::FIN) {
+                self.send_ack(stack)?;
+            }
+            return Ok(());
+        }
+        let mut acknowledge = false;
+        if !payload.is_empty() && self.received.len() + payload.len() <= RECEIVE_BUFFER_SIZE {
+            self.received.extend(payload);
+            self.receive_next = self.receive_next.wrapping_add(payload.len() as u32);
+            acknowledge = true;
+        }
+        if header.flags.contains(TcpFlags::FIN)
+            && self.receive_next == header.sequence.wrapping_add(payload.len() as u32)
+        {
+            self.receive_next = self.receive_next.wrapping_add(1);
+            if self.state == TcpState::Established {
+                self.state = TcpState::CloseWait;
+            }
+            acknowledge = true;
+        }
+        if acknowledge {
+            self.send_ack(stack)?;
+        }
+        Ok(())
+    }
+
+    /// Receives the next segment for this connection, if there is one, discarding anything else
+    /// received before it.
+    fn next_segment(
+        &mut self,
+        stack: &mut IpStack,
+    ) -> Result<Option<(TcpHeader, Vec<u8>)>, TcpIpError> {
+        while let Some(packet) = stack.poll()? {
+            if packet.header.protocol != PROTOCOL_TCP || packet.header.source != self.remote {
+                continue;
+            }
+            let Some((header, payload)) =
+                TcpHeader::parse(&packet.payload, packet.header.source, stack.config.address)
+            else {
+                continue;
+            };
+            if header.source_port == self.remote_port && header.destination_port == self.local_port
+            {
+                return Ok(Some((header, payload.to_vec())));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Acknowledges everything received so far.
+    fn send_ack(&mut self, stack: &mut IpStack) -> Result<(), TcpIpError> {
+        self.send_segment(stack, self.send_next, TcpFlags::ACK, &[])
+    }
+
+    /// Sends a segment with the given sequence number, flags and payload, acknowledging everything
+    /// received so far unless it is a SYN.
+    fn send_segment(
+        &mut self,
+        stack: &mut IpStack,
+        sequence: u32,
+        flags: TcpFlags,
+        payload: &[u8],
+    ) -> Result<(), TcpIpError> {
+        let window = RECEIVE_BUFFER_SIZE.saturating_sub(self.received.len()) as u16;
+        let header = TcpHeader {
+            source_port: self.local_port,
+            destination_port: self.remote_port,
+            sequence,
+            acknowledgement: if flags.contains(TcpFlags::ACK) {
+                self.receive_next
+            } else {
+                0
+            },
+            flags,
+            window,
+        };
+        let segment = header.to_segment(stack.config.address, self.remote, payload);
+        stack.send_ipv4(self.remote, PROTOCOL_TCP, &segment)
+    }
+}
@@ -0,0 +1,50 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Reading the ARM generic timer's virtual counter.
+
+use core::{arch::asm, hint::spin_loop};
+
+/// Returns the current value of the virtual counter.
+pub fn counter() -> u64 {
+    let value;
+    // SAFETY: Reading the virtual counter has no side effects.
+    unsafe {
+        asm!("isb", "mrs {}, cntvct_el0", out(reg) value, options(nomem, nostack));
+    }
+    value
+}
+
+/// Returns the frequency of the virtual counter in Hz.
+pub fn counter_frequency() -> u64 {
+    let value;
+    // SAFETY: Reading the counter frequency has no side effects.
+    unsafe {
+        asm!("mrs {}, cntfrq_el0", out(reg) value, options(nomem, nostack));
+    }
+    value
+}
+
+/// Returns the number of microseconds since the virtual counter started.
+pub fn uptime_us() -> u64 {
+    (u128::from(counter()) * 1_000_000 / u128::from(counter_frequency())) as u64
+}
+
+/// Returns the number of counter ticks in the given number of milliseconds.
+pub fn ms_to_ticks(ms: u64) -> u64 {
+    counter_frequency() * ms / 1000
+}
+
+/// Calls `condition` until it returns true or `timeout_ms` milliseconds have passed, and returns
+/// whether it returned true.
+pub fn wait_until(timeout_ms: u64, mut condition: impl FnMut() -> bool) -> bool {
+    let deadline = counter() + ms_to_ticks(timeout_ms);
+    while !condition() {
+        if counter() >= deadline {
+            return false;
+        }
+        spin_loop();
+    }
+    true
+}
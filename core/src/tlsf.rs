@@ -0,0 +1,542 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A two-level segregated fit (TLSF) allocator, whose allocation and free times are bounded no
+//! matter how fragmented the heap is.
+//!
+//! Free blocks are kept in lists by size class: a first level of powers of two, each split into
+//! `SL_COUNT` equal second-level classes, with bitmaps of which lists are non-empty. Finding a free
+//! block which is big enough then takes a couple of bit scans rather than a search, and freed
+//! blocks are merged with their free neighbours straight away.
+
+use crate::tracked_heap::HeapStats;
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ops::Deref,
+    ptr::{NonNull, null_mut},
+};
+use spin::mutex::SpinMutex;
+
+/// The log2 of the alignment of every block, and of the granularity of block sizes.
+const ALIGN_LOG: u32 = 4;
+/// The alignment of every block, and of the granularity of block sizes.
+const ALIGN: usize = 1 << ALIGN_LOG;
+
+/// The size of the header before each block's data.
+const HEADER_SIZE: usize = ALIGN;
+
+/// The smallest block, which is big enough for a header and the free list links.
+const MIN_BLOCK_SIZE: usize = 2 * ALIGN;
+
+/// The log2 of the number of second-level size classes in each first-level class.
+const SL_LOG: u32 = 4;
+/// The number of second-level size classes in each first-level class.
+const SL_COUNT: usize = 1 << SL_LOG;
+
+/// The log2 of the size below which blocks are all in first-level class 0, in `SL_COUNT` classes
+/// of `ALIGN` bytes each.
+const FL_SHIFT: u32 = SL_LOG + ALIGN_LOG;
+/// The size below which blocks are all in first-level class 0.
+const SMALL_BLOCK_SIZE: usize = 1 << FL_SHIFT;
+
+/// The number of first-level size classes.
+const FL_COUNT: usize = (usize::BITS - FL_SHIFT + 1) as usize;
+
+/// The flag in `Block::size` which is set if the block is free.
+const FREE: usize = 1;
+
+/// The header of a block, followed by its data.
+///
+/// The free list links are only used while the block is free, and are part of its data otherwise.
+#[repr(C)]
+struct Block {
+    /// The size of the block including its header, with the `FREE` flag.
+    size: usize,
+    /// The block immediately before this one in memory, or null if this is the first in its region.
+    prev_phys: *mut Block,
+    next_free: *mut Block,
+    prev_free: *mut Block,
+}
+
+/// A TLSF heap, which records its peak usage and how much has been allocated and freed.
+pub struct Tlsf {
+    /// A bit for each first-level class with any free blocks.
+    fl_bitmap: usize,
+    /// A bit for each second-level class with any free blocks, for each first-level class.
+    sl_bitmaps: [u32; FL_COUNT],
+    /// The first free block in each size class, or null if there are none.
+    free_lists: [[*mut Block; SL_COUNT]; FL_COUNT],
+    total_bytes: usize,
+    used_bytes: usize,
+    peak_bytes: usize,
+    allocated_bytes: u64,
+    freed_bytes: u64,
+}
+
+// SAFETY: The heap owns the memory the raw pointers point to, and `&mut self` is needed to use them.
+unsafe impl Send for Tlsf {}
+
+impl Default for Tlsf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tlsf {
+    /// Creates an empty heap.
+    pub const fn new() -> Self {
+        Self {
+            fl_bitmap: 0,
+            sl_bitmaps: [0; FL_COUNT],
+            free_lists: [[null_mut(); SL_COUNT]; FL_COUNT],
+            total_bytes: 0,
+            used_bytes: 0,
+            peak_bytes: 0,
+            allocated_bytes: 0,
+            freed_bytes: 0,
+        }
+    }
+
+    /// Adds the given range of memory to the heap.
+    ///
+    /// Ranges too small to hold a block are ignored.
+    ///
+    /// # Safety
+    ///
+    /// The range must be valid and unused by anything else for as long as the heap exists.
+    pub unsafe fn init(&mut self, start: usize, size: usize) {
+        let end = (start + size) & !(ALIGN - 1);
+        let start = start.next_multiple_of(ALIGN);
+        // Leave room for a sentinel header at the end, so that the last block is never merged past
+        // the end of the region.
+        let Some(block_size) = end.checked_sub(start + HEADER_SIZE) else {
+            return;
+        };
+        if block_size < MIN_BLOCK_SIZE {
+            return;
+        }
+        let block = start as *mut Block;
+        let sentinel = (end - HEADER_SIZE) as *mut Block;
+        // SAFETY: Our caller promises that the range is valid, and both headers are within it.
+        unsafe {
+            (*block).size = block_size | FREE;
+            (*block).prev_phys = null_mut();
+            (*sentinel).size = 0;
+            (*sentinel).prev_phys = block;
+            self.insert(block);
+        }
+        self.total_bytes += block_size;
+    }
+
+    /// Allocates memory with the given layout, or returns `None` if there isn't enough free.
+    pub fn alloc(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        let align = layout.align().max(ALIGN);
+        let size = layout
+            .size()
+            .checked_next_multiple_of(ALIGN)?
+            .checked_add(HEADER_SIZE)?
+            .max(MIN_BLOCK_SIZE);
+        // Leave room to split off a free block before the aligned data if necessary.
+        let search_size = if align > ALIGN {
+            size.checked_add(align + MIN_BLOCK_SIZE)?
+        } else {
+            size
+        };
+        let (fl, sl) = mapping_search(search_size)?;
+        let (fl, sl) = self.find_suitable(fl, sl)?;
+        let mut block = self.free_lists[fl][sl];
+
+        // SAFETY: Every block in the free lists is a valid free block in the heap, and is at least
+        // `search_size` bytes because of the size class it is in.
+        unsafe {
+            self.remove(block);
+            let data = block as usize + HEADER_SIZE;
+            if !data.is_multiple_of(align) {
+                let mut aligned = data.next_multiple_of(align);
+                if aligned - data < MIN_BLOCK_SIZE {
+                    aligned = (data + MIN_BLOCK_SIZE).next_multiple_of(align);
+                }
+                let front = block;
+                block = split(front, aligned - data).unwrap();
+                self.insert(front);
+            }
+            if let Some(rest) = split(block, size) {
+                self.insert(rest);
+            }
+            (*block).size &= !FREE;
+            self.used_bytes += block_size(block);
+        }
+        self.peak_bytes = self.peak_bytes.max(self.used_bytes);
+        self.allocated_bytes += layout.size() as u64;
+        NonNull::new((block as usize + HEADER_SIZE) as *mut u8)
+    }
+
+    /// Frees memory previously allocated with the given layout.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must have been returned by `alloc` on this heap with the same layout, and not
+    /// yet freed.
+    pub unsafe fn dealloc(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        let mut block = (ptr.as_ptr() as usize - HEADER_SIZE) as *mut Block;
+        // SAFETY: Our caller promises that the pointer came from `alloc`, so is just after a valid
+        // block header, and its neighbours' headers are valid too.
+        unsafe {
+            self.used_bytes -= block_size(block);
+            (*block).size |= FREE;
+            let prev = (*block).prev_phys;
+            if !prev.is_null() && is_free(prev) {
+                self.remove(prev);
+                (*prev).size += block_size(block);
+                block = prev;
+                (*next_phys(block)).prev_phys = block;
+            }
+            let next = next_phys(block);
+            if is_free(next) {
+                self.remove(next);
+                (*block).size += block_size(next);
+                (*next_phys(block)).prev_phys = block;
+            }
+            self.insert(block);
+        }
+        self.freed_bytes += layout.size() as u64;
+    }
+
+    /// Returns the heap's current, peak and cumulative usage.
+    ///
+    /// Block headers count as used.
+    pub fn stats(&self) -> HeapStats {
+        HeapStats {
+            total_bytes: self.total_bytes,
+            used_bytes: self.used_bytes,
+            peak_bytes: self.peak_bytes,
+            allocated_bytes: self.allocated_bytes,
+            freed_bytes: self.freed_bytes,
+        }
+    }
+
+    /// Resets the peak usage to the current usage.
+    pub fn reset_peak(&mut self) {
+        self.peak_bytes = self.used_bytes;
+    }
+
+    /// Returns the first-level and second-level class of the smallest non-empty free list whose
+    /// blocks are all at least as big as those of the given class.
+    fn find_suitable(&self, fl: usize, sl: usize) -> Option<(usize, usize)> {
+        if fl >= FL_COUNT {
+            return None;
+        }
+        let mut sl_map = self.sl_bitmaps[fl] & (u32::MAX << sl);
+        let mut fl = fl;
+        if sl_map == 0 {
+            let fl_map = self.fl_bitmap & usize::MAX.checked_shl(fl as u32 + 1).unwrap_or(0);
+            if fl_map == 0 {
+                return None;
+            }
+            fl = fl_map.trailing_zeros() as usize;
+            sl_map = self.sl_bitmaps[fl];
+        }
+        Some((fl, sl_map.trailing_zeros() as usize))
+    }
+
+    /// Adds the given free block to the front of the free list for its size.
+    ///
+    /// # Safety
+    ///
+    /// The block must be a valid free block in the heap, and not already in a free list.
+    unsafe fn insert(&mut self, block: *mut Block) {
+        // SAFETY: Our caller promises that the block is valid, and the free list only contains
+        // valid blocks.
+        unsafe {
+            let (fl, sl) = mapping(block_size(block));
+            let head = self.free_lists[fl][sl];
+            (*block).next_free = head;
+            (*block).prev_free = null_mut();
+            if !head.is_null() {
+                (*head).prev_free = block;
+            }
+            self.free_lists[fl][sl] = block;
+            self.sl_bitmaps[fl] |= 1 << sl;
+            self.fl_bitmap |= 1 << fl;
+        }
+    }
+
+    /// Removes the given free block from the free list for its size.
+    ///
+    /// # Safety
+    ///
+    /// The block must be a valid free block in the heap, and in the free list for its size.
+    unsafe fn remove(&mut self, block: *mut Block) {
+        // SAFETY: Our caller promises that the block is valid and in a free list, whose other
+        // blocks are also valid.
+        unsafe {
+            let (fl, sl) = mapping(block_size(block));
+            let next = (*block).next_free;
+            let prev = (*block).prev_free;
+            if !next.is_null() {
+                (*next).prev_free = prev;
+            }
+            if prev.is_null() {
+                self.free_lists[fl][sl] = next;
+                if next.is_null() {
+                    self.sl_bitmaps[fl] &= !(1 << sl);
+                    if self.sl_bitmaps[fl] == 0 {
+                        self.fl_bitmap &= !(1 << fl);
+                    }
+                }
+            } else {
+                (*prev).next_free = next;
+            }
+        }
+    }
+}
+
+/// A `Tlsf` behind a lock, which can be used as a global allocator.
+#[derive(Default)]
+pub struct LockedTlsf(SpinMutex<Tlsf>);
+
+impl LockedTlsf {
+    /// Creates an empty heap.
+    pub const fn new() -> Self {
+        Self(SpinMutex::new(Tlsf::new()))
+    }
+}
+
+impl Deref for LockedTlsf {
+    type Target = SpinMutex<Tlsf>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+// SAFETY: `Tlsf` returns valid, unaliased allocations with the requested layout, and the lock
+// ensures that it is only used by one thread at a time.
+unsafe impl GlobalAlloc for LockedTlsf {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.0
+            .lock()
+            .alloc(layout)
+            .map_or(null_mut(), NonNull::as_ptr)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: Our caller promises that the pointer was returned by `alloc` with the same
+        // layout, so it isn't null.
+        unsafe { self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout) }
+    }
+}
+
+/// Returns the first-level and second-level class of a free block of the given size.
+fn mapping(size: usize) -> (usize, usize) {
+    if size < SMALL_BLOCK_SIZE {
+        (0, size >> ALIGN_LOG)
+    } else {
+        let fl = size.ilog2();
+        let sl = (size >> (fl - SL_LOG)) ^ SL_COUNT;
+        ((fl - FL_SHIFT + 1) as usize, sl)
+    }
+}
+
+/// Returns the first size class whose blocks are all at least the given size, or `None` if there
+/// can't be one.
+fn mapping_search(size: usize) -> Option<(usize, usize)> {
+    if size < SMALL_BLOCK_SIZE {
+        Some(mapping(size))
+    } else {
+        let round_up = (1 << (size.ilog2() - SL_LOG)) - 1;
+        Some(mapping(size.checked_add(round_up)?))
+    }
+}
+
+/// Returns the size of the given block, including its header.
+///
+/// # Safety
+///
+/// The block header must be valid.
+unsafe fn block_size(block: *const Block) -> usize {
+    // SAFETY: Our caller promises that the header is valid.
+    unsafe { (*block).size & !FREE }
+}
+
+/// Returns whether the given block is free.
+///
+/// # Safety
+///
+/// The block header must be valid.
+unsafe fn is_free(block: *const Block) -> bool {
+    // SAFETY: Our caller promises that the header is valid.
+    unsafe { (*block).size & FREE != 0 }
+}
+
+/// Returns the block immediately after the given one in memory.
+///
+/// # Safety
+///
+/// The block header must be valid, so that there is another header after it.
+unsafe fn next_phys(block: *mut Block) -> *mut Block {
+    // SAFETY: Our caller promises that the header is valid, so its size is within the region.
+    unsafe { block.byte_add(block_size(block)) }
+}
+
+/// Splits the given free or allocated block so that it is `size` bytes, and returns the rest as a
+/// new free block, if the rest is big enough to be a block.
+///
+/// The new block isn't added to a free list.
+///
+/// # Safety
+///
+/// The block must be valid and not in a free list, and `size` a multiple of `ALIGN` no more than
+/// its size.
+unsafe fn split(block: *mut Block, size: usize) -> Option<*mut Block> {
+    // SAFETY: Our caller promises that the block is valid and at least `size` bytes, so the rest is
+    // within it.
+    unsafe {
+        let rest_size = block_size(block) - size;
+        if rest_size < MIN_BLOCK_SIZE {
+            return None;
+        }
+        let rest = block.byte_add(size);
+        (*rest).size = rest_size | FREE;
+        (*rest).prev_phys = block;
+        (*next_phys(rest)).prev_phys = rest;
+        (*block).size = size | ((*block).size & FREE);
+        Some(rest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{vec, vec::Vec};
+
+    /// Returns a heap over the given memory.
+    fn heap(memory: &mut [u64]) -> Tlsf {
+        let mut heap = Tlsf::new();
+        // SAFETY: The memory is valid and outlives the heap in each test.
+        unsafe { heap.init(memory.as_mut_ptr() as usize, memory.len() * 8) };
+        heap
+    }
+
+    /// Returns the sizes of all the heap's free blocks.
+    fn free_block_sizes(heap: &Tlsf) -> Vec<usize> {
+        let mut sizes = Vec::new();
+        for mut block in heap.free_lists.iter().flatten().copied() {
+            while !block.is_null() {
+                // SAFETY: Every block in the free lists is a valid free block.
+                unsafe {
+                    sizes.push(block_size(block));
+                    block = (*block).next_free;
+                }
+            }
+        }
+        sizes
+    }
+
+    #[test]
+    fn size_classes() {
+        assert_eq!(mapping(32), (0, 2));
+        assert_eq!(mapping(255), (0, 15));
+        assert_eq!(mapping(256), (1, 0));
+        assert_eq!(mapping(511), (1, 15));
+        assert_eq!(mapping(512), (2, 0));
+        assert_eq!(mapping(544), (2, 1));
+        assert_eq!(mapping(usize::MAX), (FL_COUNT - 1, SL_COUNT - 1));
+
+        // Searching rounds up to a class whose blocks are all big enough.
+        assert_eq!(mapping_search(512), Some((2, 0)));
+        assert_eq!(mapping_search(513), Some((2, 1)));
+        assert_eq!(mapping_search(usize::MAX), None);
+    }
+
+    #[test]
+    fn alloc_and_merge() {
+        let mut memory = vec![0u64; 512];
+        let mut heap = heap(&mut memory);
+        let total = heap.stats().total_bytes;
+        assert_eq!(total, 4096 - HEADER_SIZE);
+
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        let blocks: Vec<_> = (0..8).map(|_| heap.alloc(layout).unwrap()).collect();
+        assert_eq!(heap.stats().used_bytes, 8 * 128);
+        assert_eq!(heap.stats().allocated_bytes, 800);
+
+        // Free every other block, then the rest, so that blocks are merged in both directions.
+        for block in blocks
+            .iter()
+            .step_by(2)
+            .chain(blocks.iter().skip(1).step_by(2))
+        {
+            // SAFETY: The block was allocated from this heap with this layout.
+            unsafe { heap.dealloc(*block, layout) };
+        }
+        let stats = heap.stats();
+        assert_eq!(stats.used_bytes, 0);
+        assert_eq!(stats.peak_bytes, 8 * 128);
+        assert_eq!(stats.freed_bytes, 800);
+
+        // Everything was merged back into one block.
+        assert_eq!(free_block_sizes(&heap), [total]);
+    }
+
+    #[test]
+    fn alignment() {
+        let mut memory = vec![0u64; 2048];
+        let mut heap = heap(&mut memory);
+        for align in [16, 64, 256, 4096] {
+            let layout = Layout::from_size_align(24, align).unwrap();
+            let block = heap.alloc(layout).unwrap();
+            assert!((block.as_ptr() as usize).is_multiple_of(align));
+            // SAFETY: The block was allocated from this heap with this layout.
+            unsafe { heap.dealloc(block, layout) };
+            assert_eq!(heap.stats().used_bytes, 0);
+            assert_eq!(free_block_sizes(&heap), [heap.stats().total_bytes]);
+        }
+        assert_eq!(
+            heap.alloc(Layout::from_size_align(1 << 20, 8).unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn random_alloc_and_free() {
+        let mut memory = vec![0u64; 8192];
+        let mut heap = heap(&mut memory);
+        let mut state = 1u64;
+        let mut random = |bound: u64| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) % bound
+        };
+        let mut live: Vec<(NonNull<u8>, Layout, u8)> = Vec::new();
+        for i in 0..2000 {
+            if live.is_empty() || random(3) != 0 {
+                let layout =
+                    Layout::from_size_align(random(500) as usize + 1, 1 << random(7)).unwrap();
+                if let Some(block) = heap.alloc(layout) {
+                    assert!((block.as_ptr() as usize).is_multiple_of(layout.align()));
+                    let fill = i as u8;
+                    // SAFETY: The block is at least `layout.size()` bytes and not used elsewhere.
+                    unsafe { block.as_ptr().write_bytes(fill, layout.size()) };
+                    live.push((block, layout, fill));
+                }
+            } else {
+                let (block, layout, fill) = live.swap_remove(random(live.len() as u64) as usize);
+                // SAFETY: The block is at least `layout.size()` bytes and was filled when allocated.
+                let contents =
+                    unsafe { core::slice::from_raw_parts(block.as_ptr(), layout.size()) };
+                assert!(contents.iter().all(|&byte| byte == fill));
+                // SAFETY: The block was allocated from this heap with this layout.
+                unsafe { heap.dealloc(block, layout) };
+            }
+        }
+        for (block, layout, _) in live {
+            // SAFETY: The block was allocated from this heap with this layout.
+            unsafe { heap.dealloc(block, layout) };
+        }
+        assert_eq!(heap.stats().used_bytes, 0);
+        assert_eq!(free_block_sizes(&heap), [heap.stats().total_bytes]);
+    }
+}
@@ -0,0 +1,256 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Wrappers around the buddy system allocator which also record peak and cumulative usage.
+
+use buddy_system_allocator::Heap;
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    fmt::{self, Display, Formatter},
+    ops::Deref,
+    ptr::{NonNull, null_mut},
+};
+use spin::mutex::SpinMutex;
+
+/// Statistics about a heap's usage, in bytes.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct HeapStats {
+    /// The total size of the memory added to the heap.
+    pub total_bytes: usize,
+    /// The number of bytes currently allocated, including rounding up to the allocator's block
+    /// sizes.
+    pub used_bytes: usize,
+    /// The most bytes which have been allocated at once.
+    pub peak_bytes: usize,
+    /// The total size of every allocation ever made, as requested rather than rounded up.
+    pub allocated_bytes: u64,
+    /// The total size of every allocation ever freed, as requested rather than rounded up.
+    pub freed_bytes: u64,
+}
+
+impl HeapStats {
+    /// Returns the number of bytes which aren't allocated.
+    pub fn free_bytes(&self) -> usize {
+        self.total_bytes.saturating_sub(self.used_bytes)
+    }
+}
+
+impl Display for HeapStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} total, {} used, {} free, {} peak",
+            self.total_bytes,
+            self.used_bytes,
+            self.free_bytes(),
+            self.peak_bytes
+        )
+    }
+}
+
+/// A buddy system heap which records its peak usage, and how much has been allocated and freed.
+#[derive(Debug, Default)]
+pub struct TrackedHeap<const ORDER: usize> {
+    heap: Heap<ORDER>,
+    peak_bytes: usize,
+    allocated_bytes: u64,
+    freed_bytes: u64,
+    /// Bytes taken out of the heap by `reclaim_free`, which no longer count as part of it.
+    reclaimed_bytes: usize,
+}
+
+impl<const ORDER: usize> TrackedHeap<ORDER> {
+    /// Creates an empty heap.
+    pub const fn new() -> Self {
+        Self {
+            heap: Heap::new(),
+            peak_bytes: 0,
+            allocated_bytes: 0,
+            freed_bytes: 0,
+            reclaimed_bytes: 0,
+        }
+    }
+
+    /// Adds the given range of memory to the heap.
+    ///
+    /// # Safety
+    ///
+    /// The range must be valid and unused by anything else for as long as the heap exists.
+    pub unsafe fn init(&mut self, start: usize, size: usize) {
+        // SAFETY: Our caller promises that the range is valid.
+        unsafe { self.heap.init(start, size) }
+    }
+
+    /// Allocates memory with the given layout, or returns `None` if there isn't enough free.
+    pub fn alloc(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        let allocation = self.heap.alloc(layout).ok()?;
+        self.peak_bytes = self.peak_bytes.max(self.used_bytes());
+        self.allocated_bytes += layout.size() as u64;
+        Some(allocation)
+    }
+
+    /// Frees memory previously allocated with the given layout.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must have been returned by `alloc` on this heap with the same layout, and not
+    /// yet freed.
+    pub unsafe fn dealloc(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        // SAFETY: Our caller promises that the allocation came from this heap.
+        unsafe { self.heap.dealloc(ptr, layout) }
+        self.freed_bytes += layout.size() as u64;
+    }
+
+    /// Returns the heap's current, peak and cumulative usage.
+    pub fn stats(&self) -> HeapStats {
+        HeapStats {
+            total_bytes: self.heap.stats_total_bytes() - self.reclaimed_bytes,
+            used_bytes: self.used_bytes(),
+            peak_bytes: self.peak_bytes,
+            allocated_bytes: self.allocated_bytes,
+            freed_bytes: self.freed_bytes,
+        }
+    }
+
+    /// Resets the peak usage to the current usage.
+    pub fn reset_peak(&mut self) {
+        self.peak_bytes = self.used_bytes();
+    }
+
+    /// Takes all the memory left free in the heap, in the largest blocks possible, and passes each
+    /// block to the given function so that it can be used for something else.
+    ///
+    /// The blocks are never given back, and no longer count towards the heap's total or used bytes.
+    /// Returns the number of bytes taken.
+    pub fn reclaim_free(&mut self, mut reclaim: impl FnMut(NonNull<u8>, usize)) -> usize {
+        let mut reclaimed = 0;
+        for order in (size_of::<usize>().trailing_zeros()..ORDER as u32).rev() {
+            let size = 1 << order;
+            let layout = Layout::from_size_align(size, size).unwrap();
+            while let Ok(block) = self.heap.alloc(layout) {
+                reclaim(block, size);
+                reclaimed += size;
+            }
+        }
+        self.reclaimed_bytes += reclaimed;
+        reclaimed
+    }
+
+    /// Returns the number of bytes currently allocated, not counting reclaimed blocks.
+    fn used_bytes(&self) -> usize {
+        self.heap.stats_alloc_actual() - self.reclaimed_bytes
+    }
+}
+
+/// A `TrackedHeap` behind a lock, which can be used as a global allocator.
+#[derive(Debug, Default)]
+pub struct LockedTrackedHeap<const ORDER: usize>(SpinMutex<TrackedHeap<ORDER>>);
+
+impl<const ORDER: usize> LockedTrackedHeap<ORDER> {
+    /// Creates an empty heap.
+    pub const fn new() -> Self {
+        Self(SpinMutex::new(TrackedHeap::new()))
+    }
+}
+
+impl<const ORDER: usize> Deref for LockedTrackedHeap<ORDER> {
+    type Target = SpinMutex<TrackedHeap<ORDER>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+// SAFETY: The buddy system allocator returns valid, unaliased allocations with the requested
+// layout, and the lock ensures that it is only used by one thread at a time.
+unsafe impl<const ORDER: usize> GlobalAlloc for LockedTrackedHeap<ORDER> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.0
+            .lock()
+            .alloc(layout)
+            .map_or(null_mut(), NonNull::as_ptr)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: Our caller promises that the pointer was returned by `alloc` with the same
+        // layout, so it isn't null.
+        unsafe { self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peak_usage() {
+        let mut memory = vec![0u64; 512];
+        let mut heap = TrackedHeap::<32>::new();
+        // SAFETY: The memory is valid and outlives the heap.
+        unsafe { heap.init(memory.as_mut_ptr() as usize, memory.len() * 8) };
+        assert_eq!(heap.stats().total_bytes, 4096);
+        assert_eq!(heap.stats().peak_bytes, 0);
+
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        let a = heap.alloc(layout).unwrap();
+        let b = heap.alloc(layout).unwrap();
+        assert_eq!(
+            heap.stats(),
+            HeapStats {
+                total_bytes: 4096,
+                used_bytes: 256,
+                peak_bytes: 256,
+                allocated_bytes: 200,
+                freed_bytes: 0,
+            }
+        );
+
+        // SAFETY: Both allocations came from this heap with this layout.
+        unsafe {
+            heap.dealloc(a, layout);
+            heap.dealloc(b, layout);
+        }
+        let stats = heap.stats();
+        assert_eq!(stats.used_bytes, 0);
+        assert_eq!(stats.peak_bytes, 256);
+        assert_eq!(stats.allocated_bytes, 200);
+        assert_eq!(stats.freed_bytes, 200);
+        assert_eq!(stats.free_bytes(), 4096);
+        assert_eq!(stats.to_string(), "4096 total, 0 used, 4096 free, 256 peak");
+
+        heap.reset_peak();
+        assert_eq!(heap.stats().peak_bytes, 0);
+    }
+
+    #[test]
+    fn reclaim_free() {
+        let mut memory = vec![0u64; 512];
+        let mut heap = TrackedHeap::<32>::new();
+        // SAFETY: The memory is valid and outlives the heap.
+        unsafe { heap.init(memory.as_mut_ptr() as usize, memory.len() * 8) };
+        let layout = Layout::from_size_align(1024, 8).unwrap();
+        let allocation = heap.alloc(layout).unwrap();
+
+        let mut blocks = Vec::new();
+        let reclaimed =
+            heap.reclaim_free(|block, size| blocks.push((block.as_ptr() as usize, size)));
+        assert_eq!(reclaimed, 3072);
+        blocks.sort();
+        let start = memory.as_ptr() as usize;
+        let allocation = allocation.as_ptr() as usize - start;
+        let mut covered = vec![(allocation, 1024)];
+        covered.extend(blocks.iter().map(|&(block, size)| (block - start, size)));
+        covered.sort();
+        assert_eq!(covered.iter().map(|(_, size)| size).sum::<usize>(), 4096);
+        for pair in covered.windows(2) {
+            assert_eq!(pair[0].0 + pair[0].1, pair[1].0);
+        }
+
+        let stats = heap.stats();
+        assert_eq!(stats.total_bytes, 1024);
+        assert_eq!(stats.used_bytes, 1024);
+        assert_eq!(stats.free_bytes(), 0);
+        assert_eq!(heap.alloc(Layout::from_size_align(8, 8).unwrap()), None);
+    }
+}
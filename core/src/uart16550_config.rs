@@ -0,0 +1,136 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! The register layout and line settings of a 16550-compatible UART, as described by its device
+//! tree node.
+
+use crate::fdt::property_u32;
+use dtoolkit::fdt::FdtNode;
+
+/// The baud rate to use if the device tree doesn't give one.
+pub const DEFAULT_BAUD_RATE: u32 = 115_200;
+
+/// The width of each access to the UART's registers.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RegisterWidth {
+    /// Registers are accessed as single bytes.
+    U8,
+    /// Registers are accessed as 32-bit words, of which only the low byte is used.
+    U32,
+}
+
+/// How to access a 16550-compatible UART, and what speed to run it at.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Uart16550Config {
+    /// The log2 of the distance in bytes between consecutive registers, from `reg-shift`.
+    pub reg_shift: u32,
+    /// The width of each register access, from `reg-io-width`.
+    pub reg_width: RegisterWidth,
+    /// The frequency of the UART's input clock in Hz, from `clock-frequency`, if known.
+    pub clock_frequency: Option<u32>,
+    /// The baud rate to use, from `current-speed`.
+    pub baud_rate: u32,
+}
+
+impl Uart16550Config {
+    /// The layout of a PC-style UART with byte-wide registers at consecutive addresses, with an
+    /// unknown clock.
+    pub const DEFAULT: Self = Self {
+        reg_shift: 0,
+        reg_width: RegisterWidth::U8,
+        clock_frequency: None,
+        baud_rate: DEFAULT_BAUD_RATE,
+    };
+
+    /// Reads the configuration from the given FDT node, using the defaults for any properties
+    /// which are missing, or `None` if `reg-io-width` is one which isn't supported.
+    ///
+    /// This doesn't allocate, so can be used before the heap is set up.
+    pub fn from_node(node: &FdtNode) -> Option<Self> {
+        let reg_width = match property_u32(node, "reg-io-width") {
+            None | Some(1) => RegisterWidth::U8,
+            Some(4) => RegisterWidth::U32,
+            Some(_) => return None,
+        };
+        Some(Self {
+            reg_shift: property_u32(node, "reg-shift").unwrap_or(0),
+            reg_width,
+            clock_frequency: property_u32(node, "clock-frequency").filter(|&clock| clock != 0),
+            baud_rate: property_u32(node, "current-speed")
+                .filter(|&baud| baud != 0)
+                .unwrap_or(DEFAULT_BAUD_RATE),
+        })
+    }
+
+    /// Returns the offset in bytes of the register with the given index.
+    pub fn register_offset(&self, register: usize) -> usize {
+        register << self.reg_shift
+    }
+
+    /// Returns the value to program into the divisor latch for the configured baud rate, rounded
+    /// to the nearest, or `None` if the clock frequency isn't known or the baud rate can't be
+    /// reached with it.
+    pub fn divisor(&self) -> Option<u16> {
+        let clock = u64::from(self.clock_frequency?);
+        let scaled_baud = u64::from(self.baud_rate) * 16;
+        let divisor = (clock + scaled_baud / 2) / scaled_baud;
+        u16::try_from(divisor).ok().filter(|&divisor| divisor != 0)
+    }
+}
+
+impl Default for Uart16550Config {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fdt::test_util::FdtBuilder;
+    use dtoolkit::fdt::Fdt;
+
+    fn config_for(properties: &[(&str, u32)]) -> Option<Uart16550Config> {
+        let mut builder = FdtBuilder::default();
+        builder.begin_node("").begin_node("serial@1000");
+        builder.property("compatible", b"ns16550a\0");
+        for (name, value) in properties {
+            builder.cells(name, &[*value]);
+        }
+        builder.end_node().end_node();
+        let blob = builder.build();
+        let fdt = Fdt::new(&blob).unwrap();
+        Uart16550Config::from_node(&fdt.find_node("/serial@1000").unwrap())
+    }
+
+    #[test]
+    fn defaults() {
+        assert_eq!(config_for(&[]), Some(Uart16550Config::DEFAULT));
+        assert_eq!(Uart16550Config::DEFAULT.divisor(), None);
+        assert_eq!(Uart16550Config::DEFAULT.register_offset(5), 5);
+    }
+
+    #[test]
+    fn word_registers() {
+        let config = config_for(&[("reg-shift", 2), ("reg-io-width", 4)]).unwrap();
+        assert_eq!(config.reg_width, RegisterWidth::U32);
+        assert_eq!(config.register_offset(5), 20);
+    }
+
+    #[test]
+    fn unsupported_width() {
+        assert_eq!(config_for(&[("reg-io-width", 2)]), None);
+    }
+
+    #[test]
+    fn divisor() {
+        let config = config_for(&[("clock-frequency", 1_843_200)]).unwrap();
+        assert_eq!(config.divisor(), Some(1));
+        let config = config_for(&[("clock-frequency", 24_000_000), ("current-speed", 9600)]);
+        // 24 MHz / (16 * 9600) is 156.25.
+        assert_eq!(config.unwrap().divisor(), Some(156));
+        let config = config_for(&[("clock-frequency", 1_000), ("current-speed", 115_200)]);
+        assert_eq!(config.unwrap().divisor(), None);
+    }
+}
@@ -0,0 +1,148 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! UDP datagram headers and checksums.
+
+use crate::ipv4::{Checksum, Ipv4Address, PROTOCOL_UDP};
+use alloc::vec::Vec;
+
+/// The size of a UDP header.
+pub const UDP_HEADER_SIZE: usize = 8;
+
+/// The fields of a UDP header which we use.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UdpHeader {
+    pub source_port: u16,
+    pub destination_port: u16,
+}
+
+impl UdpHeader {
+    /// Parses the header from the start of the given datagram, which was sent from `source` to
+    /// `destination`, and returns it along with the payload.
+    ///
+    /// Returns `None` if the datagram is truncated or its checksum is incorrect. A checksum of 0
+    /// means that the sender didn't calculate one, so isn't checked.
+    pub fn parse(
+        datagram: &[u8],
+        source: Ipv4Address,
+        destination: Ipv4Address,
+    ) -> Option<(Self, &[u8])> {
+        let header = datagram.first_chunk::<UDP_HEADER_SIZE>()?;
+        let length = usize::from(u16::from_be_bytes([header[4], header[5]]));
+        if length < UDP_HEADER_SIZE || length > datagram.len() {
+            return None;
+        }
+        let datagram = &datagram[..length];
+        if header[6..8] != [0, 0] {
+            let mut checksum = pseudo_header_checksum(source, destination, length);
+            checksum.add_bytes(datagram);
+            if checksum.finish() != 0 {
+                return None;
+            }
+        }
+        Some((
+            Self {
+                source_port: u16::from_be_bytes([header[0], header[1]]),
+                destination_port: u16::from_be_bytes([header[2], header[3]]),
+            },
+            &datagram[UDP_HEADER_SIZE..],
+        ))
+    }
+
+    /// Returns a datagram with this header and the given payload, sent from `source` to
+    /// `destination`, with its checksum filled in.
+    pub fn to_datagram(
+        &self,
+        source: Ipv4Address,
+        destination: Ipv4Address,
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let length = UDP_HEADER_SIZE + payload.len();
+        let mut datagram = Vec::with_capacity(length);
+        datagram.extend_from_slice(&self.source_port.to_be_bytes());
+        datagram.extend_from_slice(&self.destination_port.to_be_bytes());
+        datagram.extend_from_slice(&(length as u16).to_be_bytes());
+        datagram.extend_from_slice(&[0; 2]);
+        datagram.extend_from_slice(payload);
+        let mut checksum = pseudo_header_checksum(source, destination, length);
+        checksum.add_bytes(&datagram);
+        // A calculated checksum of 0 is sent as all ones, as 0 means there is no checksum.
+        let checksum = match checksum.finish() {
+            0 => 0xffff,
+            checksum => checksum,
+        };
+        datagram[6..8].copy_from_slice(&checksum.to_be_bytes());
+        datagram
+    }
+}
+
+/// Returns the checksum of the IPv4 pseudo-header for a UDP datagram of the given length.
+fn pseudo_header_checksum(
+    source: Ipv4Address,
+    destination: Ipv4Address,
+    datagram_length: usize,
+) -> Checksum {
+    let mut checksum = Checksum::default();
+    checksum.add_bytes(&source.0);
+    checksum.add_bytes(&destination.0);
+    checksum.add_u16(PROTOCOL_UDP.into());
+    checksum.add_u16(datagram_length as u16);
+    checksum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT: Ipv4Address = Ipv4Address([10, 0, 2, 15]);
+    const SERVER: Ipv4Address = Ipv4Address([10, 0, 2, 2]);
+
+    #[test]
+    fn datagram_round_trip() {
+        let header = UdpHeader {
+            source_port: 49152,
+            destination_port: 7,
+        };
+        let mut datagram = header.to_datagram(CLIENT, SERVER, b"hello");
+        assert_eq!(datagram.len(), UDP_HEADER_SIZE + 5);
+        assert_eq!(datagram[4..6], [0, 13]);
+        // Trailing padding is ignored.
+        datagram.extend_from_slice(&[0; 3]);
+        assert_eq!(
+            UdpHeader::parse(&datagram, CLIENT, SERVER),
+            Some((header, &b"hello"[..]))
+        );
+        // The checksum covers the addresses in the pseudo-header.
+        assert_eq!(
+            UdpHeader::parse(&datagram, Ipv4Address([10, 0, 2, 3]), SERVER),
+            None
+        );
+    }
+
+    #[test]
+    fn no_checksum() {
+        let mut datagram = UdpHeader {
+            source_port: 53,
+            destination_port: 1024,
+        }
+        .to_datagram(SERVER, CLIENT, &[1, 2, 3]);
+        datagram[6..8].fill(0);
+        datagram[8] = 42;
+        assert_eq!(
+            UdpHeader::parse(&datagram, SERVER, CLIENT).map(|(_, payload)| payload),
+            Some(&[42, 2, 3][..])
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let datagram = UdpHeader {
+            source_port: 1,
+            destination_port: 2,
+        }
+        .to_datagram(CLIENT, SERVER, &[0; 10]);
+        assert_eq!(UdpHeader::parse(&datagram[..12], CLIENT, SERVER), None);
+        assert_eq!(UdpHeader::parse(&datagram[..4], CLIENT, SERVER), None);
+    }
+}
@@ -0,0 +1,413 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! USB standard descriptors and requests, and HID boot protocol requests.
+
+pub const DESCRIPTOR_DEVICE: u8 = 1;
+pub const DESCRIPTOR_CONFIGURATION: u8 = 2;
+pub const DESCRIPTOR_INTERFACE: u8 = 4;
+pub const DESCRIPTOR_ENDPOINT: u8 = 5;
+
+/// The size of a device descriptor.
+pub const DEVICE_DESCRIPTOR_SIZE: usize = 18;
+/// The size of the configuration descriptor at the start of a configuration.
+pub const CONFIGURATION_DESCRIPTOR_SIZE: usize = 9;
+
+const REQUEST_GET_DESCRIPTOR: u8 = 0x06;
+const REQUEST_SET_CONFIGURATION: u8 = 0x09;
+const HID_REQUEST_SET_IDLE: u8 = 0x0a;
+const HID_REQUEST_SET_PROTOCOL: u8 = 0x0b;
+
+/// Request type bit for transfers from the device to the host.
+const REQUEST_TYPE_DEVICE_TO_HOST: u8 = 0x80;
+/// Request type for class-specific requests to an interface.
+const REQUEST_TYPE_CLASS_INTERFACE: u8 = 0x21;
+
+pub const CLASS_HID: u8 = 0x03;
+pub const CLASS_MASS_STORAGE: u8 = 0x08;
+pub const HID_SUBCLASS_BOOT: u8 = 0x01;
+pub const HID_PROTOCOL_KEYBOARD: u8 = 0x01;
+/// Mass storage subclass for devices using the SCSI transparent command set.
+pub const MASS_STORAGE_SUBCLASS_SCSI: u8 = 0x06;
+/// Mass storage protocol for the bulk-only transport.
+pub const MASS_STORAGE_PROTOCOL_BULK_ONLY: u8 = 0x50;
+/// Value for the HID SET_PROTOCOL request to select the boot protocol.
+const HID_BOOT_PROTOCOL: u16 = 0;
+
+/// Endpoint address bit for IN endpoints.
+const ENDPOINT_DIRECTION_IN: u8 = 0x80;
+/// Endpoint transfer type for bulk endpoints.
+const ENDPOINT_TYPE_BULK: u8 = 0x02;
+/// Endpoint transfer type for interrupt endpoints.
+const ENDPOINT_TYPE_INTERRUPT: u8 = 0x03;
+
+/// The setup packet at the start of a control transfer.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SetupPacket {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    pub length: u16,
+}
+
+impl SetupPacket {
+    /// Returns a GET_DESCRIPTOR request for the given descriptor.
+    pub fn get_descriptor(descriptor_type: u8, index: u8, length: u16) -> Self {
+        Self {
+            request_type: REQUEST_TYPE_DEVICE_TO_HOST,
+            request: REQUEST_GET_DESCRIPTOR,
+            value: u16::from(descriptor_type) << 8 | u16::from(index),
+            index: 0,
+            length,
+        }
+    }
+
+    /// Returns a SET_CONFIGURATION request for the configuration with the given value.
+    pub fn set_configuration(configuration_value: u8) -> Self {
+        Self {
+            request: REQUEST_SET_CONFIGURATION,
+            value: configuration_value.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns a HID SET_PROTOCOL request to select the boot protocol on the given interface.
+    pub fn hid_set_boot_protocol(interface: u8) -> Self {
+        Self {
+            request_type: REQUEST_TYPE_CLASS_INTERFACE,
+            request: HID_REQUEST_SET_PROTOCOL,
+            value: HID_BOOT_PROTOCOL,
+            index: interface.into(),
+            length: 0,
+        }
+    }
+
+    /// Returns a HID SET_IDLE request so that the given interface only sends reports when they
+    /// change.
+    pub fn hid_set_idle(interface: u8) -> Self {
+        Self {
+            request_type: REQUEST_TYPE_CLASS_INTERFACE,
+            request: HID_REQUEST_SET_IDLE,
+            value: 0,
+            index: interface.into(),
+            length: 0,
+        }
+    }
+
+    /// Returns whether the data stage of the transfer, if any, is from the device to the host.
+    pub fn is_device_to_host(&self) -> bool {
+        self.request_type & REQUEST_TYPE_DEVICE_TO_HOST != 0
+    }
+
+    /// Returns the packet in wire format, as a little-endian 64-bit value.
+    pub fn to_u64(&self) -> u64 {
+        u64::from(self.request_type)
+            | u64::from(self.request) << 8
+            | u64::from(self.value) << 16
+            | u64::from(self.index) << 32
+            | u64::from(self.length) << 48
+    }
+}
+
+/// The fields we use from a device descriptor.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DeviceDescriptor {
+    pub usb_version: u16,
+    pub max_packet_size0: u8,
+    pub vendor_id: u16,
+    pub product_id: u16,
+}
+
+impl DeviceDescriptor {
+    /// Parses the fields we use from the given device descriptor, which may be truncated to its
+    /// first 8 bytes in which case the vendor and product IDs are 0.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 8 || bytes[1] != DESCRIPTOR_DEVICE {
+            return None;
+        }
+        let read_u16 = |offset: usize| {
+            bytes
+                .get(offset..offset + 2)
+                .map_or(0, |b| u16::from_le_bytes([b[0], b[1]]))
+        };
+        Some(Self {
+            usb_version: read_u16(2),
+            max_packet_size0: bytes[7],
+            vendor_id: read_u16(8),
+            product_id: read_u16(10),
+        })
+    }
+}
+
+/// Returns the total length of the configuration from the given configuration descriptor.
+pub fn configuration_total_length(bytes: &[u8]) -> Option<u16> {
+    if bytes.len() < 4 || bytes[1] != DESCRIPTOR_CONFIGURATION {
+        return None;
+    }
+    Some(u16::from_le_bytes([bytes[2], bytes[3]]))
+}
+
+/// A HID boot protocol keyboard interface, and its interrupt IN endpoint.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BootKeyboardInterface {
+    /// The value to select the configuration containing the interface.
+    pub configuration_value: u8,
+    pub interface: u8,
+    /// The endpoint number, without the direction bit.
+    pub endpoint: u8,
+    pub max_packet_size: u16,
+    /// The polling interval, in the units of the device's speed.
+    pub interval: u8,
+}
+
+/// Returns an iterator over the descriptors in the given configuration, stopping at the first
+/// invalid one.
+fn descriptors(configuration: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut remaining = configuration;
+    core::iter::from_fn(move || {
+        let length = usize::from(*remaining.first()?);
+        if length < 2 || length > remaining.len() {
+            return None;
+        }
+        let (descriptor, rest) = remaining.split_at(length);
+        remaining = rest;
+        Some(descriptor)
+    })
+}
+
+/// Finds the first HID boot keyboard interface with an interrupt IN endpoint in the given
+/// configuration, including all of its interface and endpoint descriptors.
+pub fn find_boot_keyboard(configuration: &[u8]) -> Option<BootKeyboardInterface> {
+    let mut configuration_value = None;
+    let mut keyboard_interface = None;
+    for descriptor in descriptors(configuration) {
+        match descriptor[1] {
+            DESCRIPTOR_CONFIGURATION if descriptor.len() >= CONFIGURATION_DESCRIPTOR_SIZE => {
+                configuration_value = Some(descriptor[5]);
+            }
+            DESCRIPTOR_INTERFACE if descriptor.len() >= 9 => {
+                keyboard_interface = (descriptor[5] == CLASS_HID
+                    && descriptor[6] == HID_SUBCLASS_BOOT
+                    && descriptor[7] == HID_PROTOCOL_KEYBOARD)
+                    .then_some(descriptor[2]);
+            }
+            DESCRIPTOR_ENDPOINT if descriptor.len() >= 7 => {
+                if let Some(interface) = keyboard_interface
+                    && descriptor[2] & ENDPOINT_DIRECTION_IN != 0
+                    && descriptor[3] & 0x03 == ENDPOINT_TYPE_INTERRUPT
+                {
+                    return Some(BootKeyboardInterface {
+                        configuration_value: configuration_value?,
+                        interface,
+                        endpoint: descriptor[2] & 0x0f,
+                        max_packet_size: endpoint_max_packet_size(descriptor),
+                        interval: descriptor[6],
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// A bulk endpoint.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BulkEndpoint {
+    /// The endpoint number, without the direction bit.
+    pub endpoint: u8,
+    pub max_packet_size: u16,
+}
+
+/// A mass storage interface using the SCSI command set over the bulk-only transport, and its
+/// bulk endpoints.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MassStorageInterface {
+    /// The value to select the configuration containing the interface.
+    pub configuration_value: u8,
+    pub interface: u8,
+    pub bulk_in: BulkEndpoint,
+    pub bulk_out: BulkEndpoint,
+}
+
+/// Finds the first bulk-only SCSI mass storage interface with both bulk endpoints in the given
+/// configuration, including all of its interface and endpoint descriptors.
+pub fn find_mass_storage(configuration: &[u8]) -> Option<MassStorageInterface> {
+    let mut configuration_value = None;
+    let mut storage_interface = None;
+    let mut bulk_in = None;
+    let mut bulk_out = None;
+    for descriptor in descriptors(configuration) {
+        match descriptor[1] {
+            DESCRIPTOR_CONFIGURATION if descriptor.len() >= CONFIGURATION_DESCRIPTOR_SIZE => {
+                configuration_value = Some(descriptor[5]);
+            }
+            DESCRIPTOR_INTERFACE if descriptor.len() >= 9 => {
+                storage_interface = (descriptor[5] == CLASS_MASS_STORAGE
+                    && descriptor[6] == MASS_STORAGE_SUBCLASS_SCSI
+                    && descriptor[7] == MASS_STORAGE_PROTOCOL_BULK_ONLY)
+                    .then_some(descriptor[2]);
+                bulk_in = None;
+                bulk_out = None;
+            }
+            DESCRIPTOR_ENDPOINT if descriptor.len() >= 7 => {
+                if storage_interface.is_some() && descriptor[3] & 0x03 == ENDPOINT_TYPE_BULK {
+                    let endpoint = BulkEndpoint {
+                        endpoint: descriptor[2] & 0x0f,
+                        max_packet_size: endpoint_max_packet_size(descriptor),
+                    };
+                    if descriptor[2] & ENDPOINT_DIRECTION_IN != 0 {
+                        bulk_in.get_or_insert(endpoint);
+                    } else {
+                        bulk_out.get_or_insert(endpoint);
+                    }
+                }
+                if let (Some(interface), Some(bulk_in), Some(bulk_out)) =
+                    (storage_interface, bulk_in, bulk_out)
+                {
+                    return Some(MassStorageInterface {
+                        configuration_value: configuration_value?,
+                        interface,
+                        bulk_in,
+                        bulk_out,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Returns the maximum packet size from the given endpoint descriptor, without the bits for
+/// additional transactions per microframe.
+fn endpoint_max_packet_size(descriptor: &[u8]) -> u16 {
+    u16::from_le_bytes([descriptor[4], descriptor[5]]) & 0x07ff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The configuration of QEMU's `usb-kbd`.
+    const QEMU_KEYBOARD_CONFIGURATION: [u8; 34] = [
+        // Configuration descriptor.
+        0x09, 0x02, 0x22, 0x00, 0x01, 0x01, 0x06, 0xa0, 0x32, //
+        // Interface descriptor.
+        0x09, 0x04, 0x00, 0x00, 0x01, 0x03, 0x01, 0x01, 0x07, //
+        // HID descriptor.
+        0x09, 0x21, 0x11, 0x01, 0x00, 0x01, 0x22, 0x3f, 0x00, //
+        // Endpoint descriptor.
+        0x07, 0x05, 0x81, 0x03, 0x08, 0x00, 0x07,
+    ];
+
+    #[test]
+    fn setup_packet() {
+        let packet = SetupPacket::get_descriptor(DESCRIPTOR_DEVICE, 0, 18);
+        assert!(packet.is_device_to_host());
+        assert_eq!(packet.to_u64(), 0x0012_0000_0100_0680);
+        let packet = SetupPacket::set_configuration(1);
+        assert!(!packet.is_device_to_host());
+        assert_eq!(packet.to_u64(), 0x0000_0000_0001_0900);
+        assert_eq!(
+            SetupPacket::hid_set_boot_protocol(2).to_u64(),
+            0x0000_0002_0000_0b21
+        );
+    }
+
+    #[test]
+    fn device_descriptor() {
+        let bytes = [
+            0x12, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x40, 0x27, 0x06, 0x01, 0x00, 0x00, 0x00,
+            0x01, 0x04, 0x0b, 0x01,
+        ];
+        assert_eq!(
+            DeviceDescriptor::parse(&bytes),
+            Some(DeviceDescriptor {
+                usb_version: 0x0200,
+                max_packet_size0: 64,
+                vendor_id: 0x0627,
+                product_id: 0x0001,
+            })
+        );
+        assert_eq!(
+            DeviceDescriptor::parse(&bytes[..8])
+                .unwrap()
+                .max_packet_size0,
+            64
+        );
+        assert_eq!(DeviceDescriptor::parse(&bytes[..7]), None);
+        assert_eq!(DeviceDescriptor::parse(&QEMU_KEYBOARD_CONFIGURATION), None);
+    }
+
+    #[test]
+    fn total_length() {
+        assert_eq!(
+            configuration_total_length(&QEMU_KEYBOARD_CONFIGURATION),
+            Some(34)
+        );
+        assert_eq!(configuration_total_length(&[0x09, 0x01, 0x22, 0x00]), None);
+    }
+
+    #[test]
+    fn boot_keyboard() {
+        assert_eq!(
+            find_boot_keyboard(&QEMU_KEYBOARD_CONFIGURATION),
+            Some(BootKeyboardInterface {
+                configuration_value: 1,
+                interface: 0,
+                endpoint: 1,
+                max_packet_size: 8,
+                interval: 7,
+            })
+        );
+    }
+
+    /// The configuration of QEMU's `usb-storage`.
+    const QEMU_STORAGE_CONFIGURATION: [u8; 32] = [
+        // Configuration descriptor.
+        0x09, 0x02, 0x20, 0x00, 0x01, 0x01, 0x00, 0xc0, 0x01, //
+        // Interface descriptor.
+        0x09, 0x04, 0x00, 0x00, 0x02, 0x08, 0x06, 0x50, 0x00, //
+        // Endpoint descriptors.
+        0x07, 0x05, 0x81, 0x02, 0x40, 0x00, 0x00, //
+        0x07, 0x05, 0x02, 0x02, 0x40, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn mass_storage() {
+        assert_eq!(
+            find_mass_storage(&QEMU_STORAGE_CONFIGURATION),
+            Some(MassStorageInterface {
+                configuration_value: 1,
+                interface: 0,
+                bulk_in: BulkEndpoint {
+                    endpoint: 1,
+                    max_packet_size: 64
+                },
+                bulk_out: BulkEndpoint {
+                    endpoint: 2,
+                    max_packet_size: 64
+                },
+            })
+        );
+        assert_eq!(find_mass_storage(&QEMU_KEYBOARD_CONFIGURATION), None);
+        assert_eq!(find_boot_keyboard(&QEMU_STORAGE_CONFIGURATION), None);
+        // Missing the bulk OUT endpoint.
+        assert_eq!(find_mass_storage(&QEMU_STORAGE_CONFIGURATION[..25]), None);
+    }
+
+    #[test]
+    fn not_keyboard() {
+        // A mouse has boot protocol 2.
+        let mut configuration = QEMU_KEYBOARD_CONFIGURATION;
+        configuration[16] = 0x02;
+        assert_eq!(find_boot_keyboard(&configuration), None);
+        // Truncated before the endpoint.
+        assert_eq!(find_boot_keyboard(&QEMU_KEYBOARD_CONFIGURATION[..30]), None);
+        // Invalid zero length descriptor.
+        assert_eq!(find_boot_keyboard(&[0, 2, 0, 0]), None);
+    }
+}
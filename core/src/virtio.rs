@@ -0,0 +1,728 @@
+// Copyright 2024 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! The HAL for VirtIO drivers, and finding and setting up VirtIO devices over MMIO and PCI.
+
+use crate::{
+    block::{BlockDevice, BlockError},
+    devices::Devices,
+    dma::{self, NO_ADDRESS_LIMIT},
+    ethernet::MacAddress,
+    executor::{block_on, yield_now},
+    fdt::{gic_interrupt, is_compatible, property_cells},
+    interrupts::{device_irq, setup_device_irq, take_device_irq, unmask_device_irq},
+    net::{NetError, NetworkInterface},
+    pagetable::{phys_to_virt, virt_to_phys},
+    pci::PciRootComplex,
+    virtio_balloon::VirtioBalloon,
+};
+use alloc::{boxed::Box, collections::btree_set::BTreeSet, format, string::String};
+use arm_gic::{IntId, Trigger};
+use core::{
+    hint::spin_loop,
+    mem::size_of,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+};
+use dtoolkit::{Node, fdt::Fdt};
+use log::{debug, error, info, warn};
+use spin::mutex::SpinMutex;
+use virtio_drivers::{
+    BufferDirection, Hal, PAGE_SIZE, PhysAddr,
+    device::{
+        blk::{BlkReq, BlkResp, SECTOR_SIZE, VirtIOBlk},
+        console::VirtIOConsole,
+        net::VirtIONet,
+        socket::{VirtIOSocket, VsockConnectionManager, VsockEvent},
+    },
+    transport::{
+        DeviceType, DeviceTypeError, SomeTransport, Transport,
+        mmio::{MmioError, MmioTransport, VirtIOHeader},
+        pci::{PciTransport, virtio_device_type},
+    },
+};
+
+const VIRTIO_MMIO_COMPATIBLE: &str = "virtio,mmio";
+
+/// The number of buffers in each VirtIO network device queue.
+const NET_QUEUE_SIZE: usize = 16;
+/// The size of each VirtIO network device buffer, big enough for a full Ethernet frame and the
+/// VirtIO header.
+const NET_BUFFER_SIZE: usize = 2048;
+
+/// The maximum number of requests which `read_sectors_async` and `write_sectors_async` keep
+/// outstanding at once.
+const MAX_OUTSTANDING_BLK_REQUESTS: usize = 8;
+/// The size of each request made by `read_sectors_async` and `write_sectors_async`.
+pub const ASYNC_BLK_REQUEST_SIZE: usize = 8 * SECTOR_SIZE;
+
+type VirtioNet = VirtIONet<VirtioHal, SomeTransport<'static>, NET_QUEUE_SIZE>;
+pub type VirtioBlk = VirtIOBlk<VirtioHal, SomeTransport<'static>>;
+
+/// The addresses of VirtIO MMIO devices which we have created transports for, so that we don't
+/// create another when rescanning.
+static MMIO_TRANSPORTS: SpinMutex<BTreeSet<usize>> = SpinMutex::new(BTreeSet::new());
+
+/// A VirtIO device driver, along with the interrupt which the device raises, if known.
+pub struct VirtioDevice<D> {
+    device: D,
+    irq: Option<IntId>,
+}
+
+impl<D: AckInterrupt> VirtioDevice<D> {
+    /// Waits for the device to raise an interrupt, then acknowledges it. May return early.
+    ///
+    /// Returns immediately if we don't know the device's interrupt, so callers must still poll the
+    /// device.
+    pub fn wait_for_irq(&mut self) {
+        block_on(self.next_irq());
+    }
+
+    /// Completes once the device has raised an interrupt, and acknowledges it. May complete early.
+    ///
+    /// Completes immediately if we don't know the device's interrupt, so callers must still poll
+    /// the device.
+    pub async fn next_irq(&mut self) {
+        let Some(intid) = self.irq else {
+            return;
+        };
+        device_irq(intid).await;
+        self.device.ack_interrupt();
+        unmask_device_irq(intid);
+    }
+
+    /// Acknowledges the device's interrupt if it has been raised since it was last acknowledged,
+    /// without waiting for it.
+    ///
+    /// This lets a caller which polls several devices at once keep their interrupts waking the CPU.
+    pub fn ack_pending_irq(&mut self) {
+        if let Some(intid) = self.irq
+            && take_device_irq(intid)
+        {
+            self.device.ack_interrupt();
+            unmask_device_irq(intid);
+        }
+    }
+}
+
+impl VirtioDevice<VirtioBlk> {
+    /// Reads one or more blocks into the given buffer, waiting for the device's interrupt rather
+    /// than busy-polling if possible.
+    pub fn read_blocks_irq(&mut self, block_id: usize, buf: &mut [u8]) -> virtio_drivers::Result {
+        let mut request = BlkReq::default();
+        let mut response = BlkResp::default();
+        // SAFETY: We don't access the request, buffer or response again until the request has
+        // completed.
+        let token = unsafe {
+            self.device
+                .read_blocks_nb(block_id, &mut request, buf, &mut response)?
+        };
+        while self.device.peek_used() != Some(token) {
+            self.wait_for_irq();
+        }
+        // SAFETY: These are the same buffers as we passed to `read_blocks_nb`.
+        unsafe {
+            self.device
+                .complete_read_blocks(token, &request, buf, &mut response)
+        }
+    }
+}
+
+impl VirtioDevice<VirtioBlk> {
+    /// Reads one or more blocks into the given buffer, split into several requests which are kept
+    /// outstanding at once, waiting for the device's interrupt between completions if possible.
+    pub async fn read_sectors_async(
+        &mut self,
+        block_id: usize,
+        buf: &mut [u8],
+    ) -> virtio_drivers::Result {
+        let requests = buf
+            .chunks_mut(ASYNC_BLK_REQUEST_SIZE)
+            .map(RequestBuffer::Read);
+        self.run_requests(block_id, requests).await
+    }
+
+    /// Writes the given buffer to one or more blocks, split into several requests which are kept
+    /// outstanding at once, waiting for the device's interrupt between completions if possible.
+    pub async fn write_sectors_async(
+        &mut self,
+        block_id: usize,
+        buf: &[u8],
+    ) -> virtio_drivers::Result {
+        let requests = buf.chunks(ASYNC_BLK_REQUEST_SIZE).map(RequestBuffer::Write);
+        self.run_requests(block_id, requests).await
+    }
+
+    /// Submits the given requests for consecutive blocks starting at `block_id`, keeping as many
+    /// outstanding as possible, until they have all completed.
+    async fn run_requests<'b>(
+        &mut self,
+        mut block_id: usize,
+        mut requests: impl Iterator<Item = RequestBuffer<'b>>,
+    ) -> virtio_drivers::Result {
+        let mut batch = RequestBatch::new(self);
+        let mut next = requests.next();
+        loop {
+            while let Some(buffer) = next.take() {
+                let sectors = buffer.len() / SECTOR_SIZE;
+                if let Some(buffer) = batch.submit(block_id, buffer)? {
+                    // There's no room for more requests until some complete.
+                    next = Some(buffer);
+                    break;
+                }
+                block_id += sectors;
+                next = requests.next();
+            }
+            if next.is_none() && batch.is_empty() {
+                return Ok(());
+            }
+            if !batch.complete_next()? {
+                batch.wait().await;
+            }
+        }
+    }
+}
+
+/// The buffer for a VirtIO block request.
+enum RequestBuffer<'a> {
+    Read(&'a mut [u8]),
+    Write(&'a [u8]),
+}
+
+impl RequestBuffer<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Self::Read(buf) => buf.len(),
+            Self::Write(buf) => buf.len(),
+        }
+    }
+}
+
+/// A request which has been submitted to a VirtIO block device but not yet completed.
+struct InFlightRequest<'a> {
+    token: u16,
+    request: BlkReq,
+    response: BlkResp,
+    buffer: RequestBuffer<'a>,
+}
+
+/// The outstanding requests to a VirtIO block device.
+///
+/// Requests are boxed so that they don't move while the device may access them. If the batch is
+/// dropped while some are still outstanding, such as because the future using it was cancelled, it
+/// waits for them to complete so that the device doesn't access their buffers after they are freed.
+struct RequestBatch<'a, 'b> {
+    device: &'a mut VirtioDevice<VirtioBlk>,
+    requests: [Option<Box<InFlightRequest<'b>>>; MAX_OUTSTANDING_BLK_REQUESTS],
+}
+
+impl<'a, 'b> RequestBatch<'a, 'b> {
+    fn new(device: &'a mut VirtioDevice<VirtioBlk>) -> Self {
+        Self {
+            device,
+            requests: [const { None }; MAX_OUTSTANDING_BLK_REQUESTS],
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.requests.iter().all(Option::is_none)
+    }
+
+    /// Submits a request for the given buffer, unless the batch or the device's queue is full, in
+    /// which case the buffer is returned.
+    fn submit(
+        &mut self,
+        block_id: usize,
+        buffer: RequestBuffer<'b>,
+    ) -> virtio_drivers::Result<Option<RequestBuffer<'b>>> {
+        let Some(slot) = self.requests.iter_mut().find(|slot| slot.is_none()) else {
+            return Ok(Some(buffer));
+        };
+        let in_flight = slot.insert(Box::new(InFlightRequest {
+            token: 0,
+            request: BlkReq::default(),
+            response: BlkResp::default(),
+            buffer,
+        }));
+        // SAFETY: The request, buffer and response are kept in the batch until the request
+        // completes, and aren't accessed until then.
+        let result = unsafe {
+            match &mut in_flight.buffer {
+                RequestBuffer::Read(buf) => self.device.device.read_blocks_nb(
+                    block_id,
+                    &mut in_flight.request,
+                    buf,
+                    &mut in_flight.response,
+                ),
+                RequestBuffer::Write(buf) => self.device.device.write_blocks_nb(
+                    block_id,
+                    &mut in_flight.request,
+                    buf,
+                    &mut in_flight.response,
+                ),
+            }
+        };
+        match result {
+            Ok(token) => {
+                in_flight.token = token;
+                Ok(None)
+            }
+            Err(virtio_drivers::Error::QueueFull) => Ok(slot.take().map(|request| request.buffer)),
+            Err(e) => {
+                *slot = None;
+                Err(e)
+            }
+        }
+    }
+
+    /// Completes the next request which the device has finished with, if any.
+    ///
+    /// Returns whether a request was completed.
+    fn complete_next(&mut self) -> virtio_drivers::Result<bool> {
+        let Some(token) = self.device.device.peek_used() else {
+            return Ok(false);
+        };
+        let mut in_flight = self
+            .requests
+            .iter_mut()
+            .find_map(|slot| slot.take_if(|request| request.token == token))
+            .expect("VirtIO block device completed a request which we didn't submit");
+        let in_flight = &mut *in_flight;
+        // SAFETY: These are the same buffers as we passed when submitting the request.
+        unsafe {
+            match &mut in_flight.buffer {
+                RequestBuffer::Read(buf) => self.device.device.complete_read_blocks(
+                    token,
+                    &in_flight.request,
+                    buf,
+                    &mut in_flight.response,
+                ),
+                RequestBuffer::Write(buf) => self.device.device.complete_write_blocks(
+                    token,
+                    &in_flight.request,
+                    buf,
+                    &mut in_flight.response,
+                ),
+            }
+        }?;
+        Ok(true)
+    }
+
+    /// Waits for the device's interrupt, or just yields if we don't know it.
+    async fn wait(&mut self) {
+        if self.device.irq.is_some() {
+            self.device.next_irq().await;
+        } else {
+            yield_now().await;
+        }
+    }
+}
+
+impl Drop for RequestBatch<'_, '_> {
+    fn drop(&mut self) {
+        while !self.is_empty() {
+            match self.complete_next() {
+                Ok(true) => {}
+                Ok(false) => spin_loop(),
+                // The request has still been removed from the queue, but there's nobody left to
+                // report the error to.
+                Err(e) => warn!("Error completing abandoned VirtIO block request: {e}"),
+            }
+        }
+    }
+}
+
+impl BlockDevice for VirtioDevice<VirtioBlk> {
+    fn kind(&self) -> &'static str {
+        "virtio"
+    }
+
+    fn id(&mut self) -> Result<String, BlockError> {
+        let mut id = [0; 20];
+        let id_len = self.device.device_id(&mut id)?;
+        Ok(String::from_utf8_lossy(&id[..id_len]).into())
+    }
+
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn capacity(&self) -> u64 {
+        self.device.capacity()
+    }
+
+    fn readonly(&self) -> bool {
+        self.device.readonly()
+    }
+
+    fn read_blocks(&mut self, block_id: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        Ok(self.read_blocks_irq(block_id as usize, buf)?)
+    }
+
+    fn write_blocks(&mut self, block_id: u64, buf: &[u8]) -> Result<(), BlockError> {
+        Ok(self.device.write_blocks(block_id as usize, buf)?)
+    }
+
+    fn as_virtio_blk(&mut self) -> Option<&mut VirtioDevice<VirtioBlk>> {
+        Some(self)
+    }
+}
+
+impl NetworkInterface for VirtioDevice<VirtioNet> {
+    fn kind(&self) -> &'static str {
+        "virtio"
+    }
+
+    fn mac_address(&self) -> MacAddress {
+        MacAddress(self.device.mac_address())
+    }
+
+    fn send(&mut self, frame: &[u8]) -> Result<(), NetError> {
+        if frame.len() > NET_BUFFER_SIZE {
+            return Err(NetError::FrameTooLarge(frame.len()));
+        }
+        let mut tx = self.device.new_tx_buffer(frame.len());
+        tx.packet_mut().copy_from_slice(frame);
+        Ok(self.device.send(tx)?)
+    }
+
+    fn receive(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, NetError> {
+        let rx = match self.device.receive() {
+            Ok(rx) => rx,
+            Err(virtio_drivers::Error::NotReady) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let frame = rx.packet();
+        let result = if let Some(buffer) = buffer.get_mut(..frame.len()) {
+            buffer.copy_from_slice(frame);
+            Ok(Some(frame.len()))
+        } else {
+            Err(NetError::BufferTooSmall {
+                frame: frame.len(),
+                buffer: buffer.len(),
+            })
+        };
+        self.device.recycle_rx_buffer(rx)?;
+        result
+    }
+
+    fn wait_for_irq(&mut self) {
+        VirtioDevice::wait_for_irq(self);
+    }
+}
+
+impl<D> Deref for VirtioDevice<D> {
+    type Target = D;
+
+    fn deref(&self) -> &D {
+        &self.device
+    }
+}
+
+impl<D> DerefMut for VirtioDevice<D> {
+    fn deref_mut(&mut self) -> &mut D {
+        &mut self.device
+    }
+}
+
+/// Trait for VirtIO device drivers which can acknowledge an interrupt from their device.
+pub trait AckInterrupt {
+    /// Acknowledges any pending interrupt from the device, and processes any used buffers.
+    fn ack_interrupt(&mut self);
+}
+
+/// Completes with the next event from the given vsock device.
+///
+/// The vsock driver can't yet acknowledge the device's interrupt, so rather than sleeping until
+/// it is raised this polls the device and then asks to be polled again straight away.
+pub async fn next_vsock_event(
+    vsock: &mut VsockConnectionManager<VirtioHal, SomeTransport<'static>>,
+) -> virtio_drivers::Result<VsockEvent> {
+    loop {
+        if let Some(event) = vsock.poll()? {
+            return Ok(event);
+        }
+        yield_now().await;
+    }
+}
+
+impl AckInterrupt for VirtioBlk {
+    fn ack_interrupt(&mut self) {
+        self.ack_interrupt();
+    }
+}
+
+impl AckInterrupt for VirtIOConsole<VirtioHal, SomeTransport<'static>> {
+    fn ack_interrupt(&mut self) {
+        if let Err(e) = self.ack_interrupt() {
+            warn!("Error acknowledging VirtIO console interrupt: {e}");
+        }
+    }
+}
+
+impl AckInterrupt for VirtioNet {
+    fn ack_interrupt(&mut self) {
+        self.ack_interrupt();
+    }
+}
+
+/// Finds VirtIO MMIO devices in the given device tree, and adds drivers for any which don't already
+/// have one.
+///
+/// This may be called again later to pick up devices which weren't present the first time.
+///
+/// # Safety
+///
+/// Any VirtIO MMIO devices in the given device tree must exist and be mapped appropriately, and
+/// must not be constructed anywhere else.
+pub unsafe fn find_virtio_mmio_devices(fdt: &Fdt, devices: &mut Devices) {
+    for node in fdt.root().children() {
+        let node_name = node.name();
+        if is_compatible(&node, &[VIRTIO_MMIO_COMPATIBLE]) {
+            debug!("Found VirtIO MMIO device {}", node_name);
+            if let Some(region) = node.reg().unwrap().unwrap().next() {
+                let region_size = region.size::<u64>().unwrap() as usize;
+                if region_size < size_of::<VirtIOHeader>() {
+                    error!(
+                        "VirtIO MMIO device {} region smaller than VirtIO header size ({} < {})",
+                        node_name,
+                        region_size,
+                        size_of::<VirtIOHeader>()
+                    );
+                } else if MMIO_TRANSPORTS
+                    .lock()
+                    .contains(&(region.address::<u64>().unwrap() as usize))
+                {
+                    debug!("VirtIO MMIO device {node_name} already has a driver");
+                } else {
+                    let header =
+                        NonNull::new(region.address::<u64>().unwrap() as *mut VirtIOHeader)
+                            .unwrap();
+                    // SAFETY: The caller promised that the device tree is correct, VirtIO MMIO
+                    // devices are mapped, and no aliases are constructed to the MMIO region.
+                    match unsafe { MmioTransport::new(header, region_size) } {
+                        Err(MmioError::InvalidDeviceID(DeviceTypeError::InvalidDeviceType(0))) => {
+                            debug!("Ignoring VirtIO device with zero device ID.");
+                        }
+                        Err(e) => {
+                            error!("Error creating VirtIO transport: {e}");
+                        }
+                        Ok(mut transport) => {
+                            MMIO_TRANSPORTS.lock().insert(header.as_ptr() as usize);
+                            info!(
+                                "Detected virtio MMIO device with device type {:?}, vendor ID {:#x}, version {:?}, features {:#018x}",
+                                transport.device_type(),
+                                transport.vendor_id(),
+                                transport.version(),
+                                transport.read_device_features(),
+                            );
+                            let irq = property_cells(&node, "interrupts")
+                                .and_then(|interrupts| gic_interrupt(&interrupts));
+                            if irq.is_none() {
+                                warn!("VirtIO MMIO device {node_name} has no usable interrupt");
+                            }
+                            init_virtio_device(
+                                transport.into(),
+                                irq,
+                                format!("MMIO {:#x}", header.as_ptr() as usize),
+                                devices,
+                            );
+                        }
+                    }
+                }
+            } else {
+                error!("VirtIO MMIO device {} missing region", node_name);
+            }
+        }
+    }
+}
+
+fn init_virtio_device(
+    transport: SomeTransport<'static>,
+    irq: Option<(IntId, Trigger)>,
+    location: String,
+    devices: &mut Devices,
+) {
+    // Only enable the interrupt for devices whose driver can acknowledge it.
+    let irq = irq.and_then(|(intid, trigger)| {
+        if matches!(
+            transport.device_type(),
+            DeviceType::Block | DeviceType::Console | DeviceType::Network
+        ) {
+            setup_device_irq(intid, trigger);
+            Some(intid)
+        } else {
+            None
+        }
+    });
+    let added = match transport.device_type() {
+        #[cfg(feature = "block")]
+        DeviceType::Block => devices.add_block(
+            Box::new(VirtioDevice {
+                device: VirtIOBlk::new(transport).unwrap(),
+                irq,
+            }),
+            location,
+        ),
+        #[cfg(feature = "net")]
+        DeviceType::Network => match VirtIONet::new(transport, NET_BUFFER_SIZE) {
+            Ok(device) => devices.add_net(Box::new(VirtioDevice { device, irq }), location),
+            Err(e) => {
+                error!("Error initialising VirtIO network device: {e}");
+                return;
+            }
+        },
+        DeviceType::Console => devices.add_console(
+            VirtioDevice {
+                device: VirtIOConsole::new(transport).unwrap(),
+                irq,
+            },
+            location,
+        ),
+        DeviceType::MemoryBallooning => match VirtioBalloon::new(transport) {
+            Ok(balloon) => devices.add_balloon(balloon, location),
+            Err(e) => {
+                error!("Error initialising VirtIO balloon device: {e}");
+                return;
+            }
+        },
+        DeviceType::Socket => {
+            // TODO: Use the interrupt once the vsock driver can acknowledge it.
+            devices.add_vsock(
+                VsockConnectionManager::new(VirtIOSocket::new(transport).unwrap()),
+                location,
+            )
+        }
+        t => {
+            warn!("Ignoring unsupported VirtIO device type {t:?}");
+            return;
+        }
+    };
+    if let Err(e) = added {
+        error!("Error adding VirtIO device: {e}");
+    }
+}
+
+pub fn find_virtio_pci_devices(pci_root: &mut PciRootComplex, devices: &mut Devices) {
+    info!("Looking for VirtIO devices on PCI bus");
+    for (device_function, info) in pci_root.unclaimed_devices() {
+        if let Some(virtio_type) = virtio_device_type(&info) {
+            info!("  VirtIO {virtio_type:?} {info} at {device_function}");
+            let mut transport =
+                PciTransport::new::<VirtioHal, _>(pci_root, device_function).unwrap();
+            pci_root.claim(device_function);
+            info!(
+                "Detected virtio PCI device with device type {:?}, features {:#018x}, status {:?}",
+                transport.device_type(),
+                transport.read_device_features(),
+                transport.get_status(),
+            );
+            init_virtio_device(
+                transport.into(),
+                pci_root.irqs.get(&device_function).copied(),
+                format!("PCI {device_function}"),
+                devices,
+            );
+        }
+    }
+}
+
+/// VirtIO HAL implementation, allocating DMA buffers from the DMA pool below the given address
+/// limit.
+///
+/// Other buffers are passed to the device directly, unless the bounce buffer pool has been
+/// initialised, in which case they are copied to and from bounce buffers allocated from it.
+#[derive(Debug)]
+pub struct VirtioHal<const ADDRESS_LIMIT: usize = NO_ADDRESS_LIMIT>;
+
+// SAFETY: dma_alloc and mmio_phys_to_virt always return appropriate pointers based on their
+// parameters.
+unsafe impl<const ADDRESS_LIMIT: usize> Hal for VirtioHal<ADDRESS_LIMIT> {
+    fn dma_alloc(pages: usize, _direction: BufferDirection) -> (PhysAddr, NonNull<u8>) {
+        assert_ne!(pages, 0);
+        let paddr = match dma::alloc_pages(pages, ADDRESS_LIMIT) {
+            Ok(paddr) => paddr,
+            Err(e) => {
+                if let Some(stats) = dma::stats() {
+                    error!("{stats}");
+                }
+                // The HAL has no way to report the error to the driver.
+                panic!("DMA allocation failed: {e}");
+            }
+        };
+        // Access the buffer through the kernel's preferred alias, which may be in the higher half.
+        let vaddr = NonNull::new(phys_to_virt(paddr) as *mut u8).unwrap();
+        // SAFETY: The pool has just given us these pages so they must be valid and unaliased.
+        unsafe {
+            vaddr.write_bytes(0, pages * PAGE_SIZE);
+        }
+        (paddr as _, vaddr)
+    }
+
+    unsafe fn dma_dealloc(paddr: PhysAddr, _vaddr: NonNull<u8>, pages: usize) -> i32 {
+        // The caller promises that the pages were allocated by `dma_alloc` above with the same
+        // size.
+        dma::dealloc_pages(paddr as usize, pages);
+        0
+    }
+
+    unsafe fn mmio_phys_to_virt(paddr: PhysAddr, _size: usize) -> NonNull<u8> {
+        // Devices are only ever identity-mapped.
+        NonNull::new(paddr as _).unwrap()
+    }
+
+    unsafe fn share(buffer: NonNull<[u8]>, direction: BufferDirection) -> PhysAddr {
+        let vaddr = buffer.as_ptr() as *mut u8 as usize;
+        if !dma::bounce_buffers_enabled() {
+            // Nothing to do, as the host already has access to all memory.
+            return virt_to_phys(vaddr) as _;
+        }
+
+        let paddr = match dma::alloc_bounce(buffer.len()) {
+            Ok(paddr) => paddr,
+            Err(e) => {
+                if let Some(stats) = dma::bounce_stats() {
+                    error!("Bounce buffer pool: {stats}");
+                }
+                // The HAL has no way to report the error to the driver.
+                panic!("Bounce buffer allocation failed: {e}");
+            }
+        };
+        assert!(
+            paddr + buffer.len() <= ADDRESS_LIMIT,
+            "Bounce buffer at {paddr:#x} is above the device's address limit {ADDRESS_LIMIT:#x}"
+        );
+        let bounce = phys_to_virt(paddr) as *mut u8;
+        // SAFETY: The caller promises that the buffer is valid for reads, and the bounce buffer has
+        // just been allocated with the same size so nothing else is using it.
+        unsafe {
+            if direction == BufferDirection::DeviceToDriver {
+                // Don't let the host see whatever was in the pool before.
+                bounce.write_bytes(0, buffer.len());
+            } else {
+                bounce.copy_from_nonoverlapping(buffer.as_ptr() as *const u8, buffer.len());
+            }
+        }
+        paddr as _
+    }
+
+    unsafe fn unshare(paddr: PhysAddr, buffer: NonNull<[u8]>, direction: BufferDirection) {
+        if !dma::bounce_buffers_enabled() {
+            // Nothing to do, as the host already has access to all memory and we didn't copy the
+            // buffer anywhere else.
+            return;
+        }
+
+        let paddr = paddr as usize;
+        if direction != BufferDirection::DriverToDevice {
+            // SAFETY: The caller promises that the buffer is valid for writes and that `paddr` was
+            // returned by `share` for it, so the bounce buffer is the same size.
+            unsafe {
+                (buffer.as_ptr() as *mut u8)
+                    .copy_from_nonoverlapping(phys_to_virt(paddr) as *const u8, buffer.len());
+            }
+        }
+        dma::dealloc_bounce(paddr, buffer.len());
+    }
+}
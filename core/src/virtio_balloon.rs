@@ -0,0 +1,534 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A driver for the VirtIO memory balloon, which gives pages of the heap back to the host and
+//! reclaims them, and reports memory statistics to it.
+//!
+//! `virtio-drivers` doesn't have a balloon driver, so this drives the transport directly, with a
+//! minimal split virtqueue for each of the device's queues. Only one request is outstanding on a
+//! queue at once, and requests are completed by polling rather than waiting for an interrupt.
+
+use crate::{
+    dma::{self, DmaError, NO_ADDRESS_LIMIT},
+    drivers::generic_timer::Instant,
+    fallible::{OutOfMemory, try_reserve},
+    pagetable::{phys_to_virt, virt_to_phys},
+};
+use alloc::{
+    alloc::{alloc, dealloc},
+    vec::Vec,
+};
+use core::{
+    alloc::Layout,
+    fmt::{self, Display, Formatter},
+    hint::spin_loop,
+    ptr::NonNull,
+    sync::atomic::{Ordering, fence},
+    time::Duration,
+};
+use virtio_drivers::{
+    PAGE_SIZE, PhysAddr,
+    transport::{DeviceStatus, SomeTransport, Transport},
+};
+
+/// The size of the pages which the balloon deals in, regardless of the guest's page size.
+pub const BALLOON_PAGE_SIZE: usize = 4096;
+
+/// The shift to convert a physical address to a balloon page frame number.
+const PFN_SHIFT: usize = 12;
+
+/// The device must be told before pages are reclaimed from the balloon.
+const FEATURE_MUST_TELL_HOST: u64 = 1 << 0;
+/// The device has a queue for the driver to report memory statistics on.
+const FEATURE_STATS_VQ: u64 = 1 << 1;
+/// The device follows the VirtIO 1.0 specification rather than the legacy interface.
+const FEATURE_VERSION_1: u64 = 1 << 32;
+
+/// The features which we can use, if the device offers them.
+const SUPPORTED_FEATURES: u64 = FEATURE_MUST_TELL_HOST | FEATURE_STATS_VQ | FEATURE_VERSION_1;
+
+const INFLATE_QUEUE: u16 = 0;
+const DEFLATE_QUEUE: u16 = 1;
+const STATS_QUEUE: u16 = 2;
+
+/// The number of descriptors in each queue. Only one is ever used at a time.
+const QUEUE_SIZE: u16 = 4;
+
+/// The most page frame numbers to send in a single inflate or deflate request.
+const MAX_PFNS_PER_REQUEST: usize = 256;
+
+/// The offset of the statistics within the shared buffer, after the page frame numbers.
+const STATS_OFFSET: usize = MAX_PFNS_PER_REQUEST * size_of::<u32>();
+
+/// The size of each statistic reported to the device: a 16-bit tag and a 64-bit value, packed.
+const STAT_SIZE: usize = 10;
+
+/// How long to wait for the device to process an inflate or deflate request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The offsets of fields in the device's configuration space.
+const CONFIG_NUM_PAGES: usize = 0;
+const CONFIG_ACTUAL: usize = 4;
+
+/// A memory statistic which can be reported to the host.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BalloonStat {
+    /// The amount of memory which isn't being used for anything, in bytes.
+    FreeMemory(u64),
+    /// The total amount of memory available to the guest, in bytes.
+    TotalMemory(u64),
+    /// An estimate of how much memory is available for new allocations, in bytes.
+    AvailableMemory(u64),
+}
+
+impl BalloonStat {
+    /// Returns the statistic in the device's format.
+    fn to_bytes(self) -> [u8; STAT_SIZE] {
+        let (tag, value): (u16, u64) = match self {
+            Self::FreeMemory(bytes) => (4, bytes),
+            Self::TotalMemory(bytes) => (5, bytes),
+            Self::AvailableMemory(bytes) => (6, bytes),
+        };
+        let mut bytes = [0; STAT_SIZE];
+        bytes[0..2].copy_from_slice(&tag.to_le_bytes());
+        bytes[2..10].copy_from_slice(&value.to_le_bytes());
+        bytes
+    }
+}
+
+/// An error from the balloon driver.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BalloonError {
+    /// The device didn't accept the features we asked for.
+    FeaturesRejected,
+    /// One of the device's queues isn't available.
+    QueueUnavailable(u16),
+    /// The device didn't complete a request in time.
+    Timeout,
+    /// Allocating memory for the driver's queues or buffer failed.
+    Dma(DmaError),
+    /// Allocating memory to track the balloon's pages failed.
+    OutOfMemory(OutOfMemory),
+    /// Accessing the device's configuration space failed.
+    Virtio(virtio_drivers::Error),
+}
+
+impl Display for BalloonError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::FeaturesRejected => write!(f, "Device rejected features"),
+            Self::QueueUnavailable(queue) => write!(f, "Queue {queue} not available"),
+            Self::Timeout => write!(f, "Device didn't complete request in time"),
+            Self::Dma(e) => write!(f, "{e}"),
+            Self::OutOfMemory(e) => write!(f, "{e}"),
+            Self::Virtio(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl From<DmaError> for BalloonError {
+    fn from(e: DmaError) -> Self {
+        Self::Dma(e)
+    }
+}
+
+impl From<OutOfMemory> for BalloonError {
+    fn from(e: OutOfMemory) -> Self {
+        Self::OutOfMemory(e)
+    }
+}
+
+impl From<virtio_drivers::Error> for BalloonError {
+    fn from(e: virtio_drivers::Error) -> Self {
+        Self::Virtio(e)
+    }
+}
+
+/// Pages from the DMA pool, which are freed when dropped.
+struct DmaPages {
+    paddr: usize,
+    pages: usize,
+}
+
+impl DmaPages {
+    /// Allocates the given number of zeroed pages.
+    fn new(pages: usize) -> Result<Self, DmaError> {
+        let paddr = dma::alloc_pages(pages, NO_ADDRESS_LIMIT)?;
+        let allocation = Self { paddr, pages };
+        // SAFETY: The pool has just given us these pages so they are valid and unaliased.
+        unsafe { allocation.pointer(0).write_bytes(0, pages * PAGE_SIZE) };
+        Ok(allocation)
+    }
+
+    /// Returns a pointer to the given offset within the pages, through the kernel's preferred
+    /// alias.
+    fn pointer(&self, offset: usize) -> *mut u8 {
+        assert!(offset < self.pages * PAGE_SIZE);
+        (phys_to_virt(self.paddr) + offset) as *mut u8
+    }
+}
+
+impl Drop for DmaPages {
+    fn drop(&mut self) {
+        dma::dealloc_pages(self.paddr, self.pages);
+    }
+}
+
+/// A split virtqueue with a single buffer outstanding at a time.
+///
+/// The rings are laid out as the legacy interface requires, with the used ring on the page after
+/// the descriptor table and available ring, which also works for modern devices.
+struct Queue {
+    index: u16,
+    memory: DmaPages,
+    /// The index of the next entry we will add to the available ring.
+    next_available: u16,
+    /// The index of the next entry we expect the device to add to the used ring.
+    next_used: u16,
+}
+
+impl Queue {
+    const DESCRIPTORS_OFFSET: usize = 0;
+    const AVAILABLE_OFFSET: usize = 16 * QUEUE_SIZE as usize;
+    const USED_OFFSET: usize = PAGE_SIZE;
+
+    /// Allocates the queue's rings and tells the transport about them.
+    fn new(transport: &mut SomeTransport<'static>, index: u16) -> Result<Self, BalloonError> {
+        if transport.max_queue_size(index) < u32::from(QUEUE_SIZE) {
+            return Err(BalloonError::QueueUnavailable(index));
+        }
+        let memory = DmaPages::new(2)?;
+        transport.queue_set(
+            index,
+            QUEUE_SIZE.into(),
+            (memory.paddr + Self::DESCRIPTORS_OFFSET) as PhysAddr,
+            (memory.paddr + Self::AVAILABLE_OFFSET) as PhysAddr,
+            (memory.paddr + Self::USED_OFFSET) as PhysAddr,
+        );
+        Ok(Self {
+            index,
+            memory,
+            next_available: 0,
+            next_used: 0,
+        })
+    }
+
+    /// Adds the given device-readable buffer to the queue and notifies the device.
+    ///
+    /// The previous buffer must have been used.
+    fn add(&mut self, transport: &mut SomeTransport<'static>, paddr: usize, length: usize) {
+        let descriptor = self.memory.pointer(Self::DESCRIPTORS_OFFSET);
+        let slot = usize::from(self.next_available % QUEUE_SIZE);
+        // SAFETY: The descriptor table and available ring are within our DMA pages, and the
+        // device isn't using the descriptor as its previous buffer has been used.
+        unsafe {
+            descriptor
+                .cast::<u64>()
+                .write_volatile((paddr as u64).to_le());
+            descriptor
+                .add(8)
+                .cast::<u32>()
+                .write_volatile((length as u32).to_le());
+            // No flags, as the buffer is device-readable and there is no next descriptor.
+            descriptor.add(12).cast::<u32>().write_volatile(0);
+            let available = self.memory.pointer(Self::AVAILABLE_OFFSET);
+            available.add(4 + 2 * slot).cast::<u16>().write_volatile(0);
+            // The device must see the descriptor and ring entry before the new index.
+            fence(Ordering::SeqCst);
+            self.next_available = self.next_available.wrapping_add(1);
+            available
+                .add(2)
+                .cast::<u16>()
+                .write_volatile(self.next_available.to_le());
+        }
+        fence(Ordering::SeqCst);
+        transport.notify(self.index);
+    }
+
+    /// Returns whether the device has used the outstanding buffer, and if so consumes the used
+    /// ring entry.
+    fn take_used(&mut self) -> bool {
+        // SAFETY: The used ring is within our DMA pages.
+        let used_index = u16::from_le(unsafe {
+            self.memory
+                .pointer(Self::USED_OFFSET + 2)
+                .cast::<u16>()
+                .read_volatile()
+        });
+        if used_index == self.next_used {
+            return false;
+        }
+        // Make sure any data the device wrote is read after the index.
+        fence(Ordering::SeqCst);
+        self.next_used = self.next_used.wrapping_add(1);
+        true
+    }
+
+    /// Waits for the device to use the outstanding buffer.
+    fn wait_used(&mut self) -> Result<(), BalloonError> {
+        let deadline = Instant::now() + REQUEST_TIMEOUT;
+        while !self.take_used() {
+            if Instant::now() >= deadline {
+                return Err(BalloonError::Timeout);
+            }
+            spin_loop();
+        }
+        Ok(())
+    }
+}
+
+/// A VirtIO memory balloon device.
+pub struct VirtioBalloon {
+    transport: SomeTransport<'static>,
+    inflate_queue: Queue,
+    deflate_queue: Queue,
+    stats_queue: Option<Queue>,
+    /// A page shared with the device, for the page frame numbers of requests and the statistics.
+    buffer: DmaPages,
+    /// Whether the device must be told before pages are taken back out of the balloon.
+    must_tell_host: bool,
+    /// The heap pages which have been given to the device.
+    pages: Vec<NonNull<u8>>,
+    /// The host's target size when `target_change` last returned it.
+    last_target: Option<usize>,
+}
+
+// SAFETY: The pages in the balloon aren't accessed by anything, and the driver's other state is
+// only accessed through `&mut self`, so it can be moved between CPUs.
+unsafe impl Send for VirtioBalloon {}
+
+impl VirtioBalloon {
+    /// Initialises the device and its queues.
+    ///
+    /// If the device supports statistics, an empty set is given to it straight away, so that it
+    /// can ask for real ones with `poll_stats`.
+    pub fn new(mut transport: SomeTransport<'static>) -> Result<Self, BalloonError> {
+        transport.set_status(DeviceStatus::empty());
+        transport.set_status(DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER);
+        let features = transport.read_device_features() & SUPPORTED_FEATURES;
+        transport.write_driver_features(features);
+        transport.set_status(
+            DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER | DeviceStatus::FEATURES_OK,
+        );
+        if !transport.get_status().contains(DeviceStatus::FEATURES_OK) {
+            transport.set_status(DeviceStatus::FAILED);
+            return Err(BalloonError::FeaturesRejected);
+        }
+        if transport.requires_legacy_layout() {
+            transport.set_guest_page_size(PAGE_SIZE as u32);
+        }
+
+        let inflate_queue = Queue::new(&mut transport, INFLATE_QUEUE)?;
+        let deflate_queue = Queue::new(&mut transport, DEFLATE_QUEUE)?;
+        let stats_queue = if features & FEATURE_STATS_VQ != 0 {
+            Some(Queue::new(&mut transport, STATS_QUEUE)?)
+        } else {
+            None
+        };
+        let buffer = DmaPages::new(1)?;
+        transport.finish_init();
+
+        let mut balloon = Self {
+            transport,
+            inflate_queue,
+            deflate_queue,
+            stats_queue,
+            buffer,
+            must_tell_host: features & FEATURE_MUST_TELL_HOST != 0,
+            pages: Vec::new(),
+            last_target: None,
+        };
+        balloon.write_actual()?;
+        balloon.send_stats(&[]);
+        Ok(balloon)
+    }
+
+    /// Returns the number of pages the host would like the balloon to contain.
+    pub fn target_pages(&self) -> Result<usize, BalloonError> {
+        Ok(u32::from_le(self.transport.read_config_space::<u32>(CONFIG_NUM_PAGES)?) as usize)
+    }
+
+    /// Returns the host's target size if it has changed since this was last called, so that the
+    /// caller can follow the host's requests without undoing its own resizes.
+    pub fn target_change(&mut self) -> Result<Option<usize>, BalloonError> {
+        let target = self.target_pages()?;
+        if self.last_target == Some(target) {
+            return Ok(None);
+        }
+        self.last_target = Some(target);
+        Ok(Some(target))
+    }
+
+    /// Returns the number of pages currently in the balloon.
+    pub fn pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Returns whether the device accepts memory statistics.
+    pub fn has_stats(&self) -> bool {
+        self.stats_queue.is_some()
+    }
+
+    /// Tells the device how many pages are in the balloon.
+    fn write_actual(&mut self) -> Result<(), BalloonError> {
+        let actual = self.pages.len() as u32;
+        self.transport
+            .write_config_space(CONFIG_ACTUAL, actual.to_le())?;
+        Ok(())
+    }
+
+    /// Writes the given page frame numbers to the shared buffer, and sends them on the given
+    /// queue.
+    fn send_pfns(&mut self, deflate: bool, pages: &[NonNull<u8>]) -> Result<(), BalloonError> {
+        let pfns = self.buffer.pointer(0).cast::<u32>();
+        for (i, page) in pages.iter().enumerate() {
+            let pfn = (virt_to_phys(page.as_ptr() as usize) >> PFN_SHIFT) as u32;
+            // SAFETY: There are at most `MAX_PFNS_PER_REQUEST` pages, which fit before the
+            // statistics in the buffer, and the device isn't using this part of it.
+            unsafe { pfns.add(i).write_volatile(pfn.to_le()) };
+        }
+        let queue = if deflate {
+            &mut self.deflate_queue
+        } else {
+            &mut self.inflate_queue
+        };
+        queue.add(
+            &mut self.transport,
+            self.buffer.paddr,
+            pages.len() * size_of::<u32>(),
+        );
+        queue.wait_used()
+    }
+
+    /// Takes up to `count` pages from the heap and gives them to the host.
+    ///
+    /// Stops early without an error if the heap runs out of pages. Returns the number of pages
+    /// added to the balloon.
+    pub fn inflate(&mut self, count: usize) -> Result<usize, BalloonError> {
+        let layout = balloon_page_layout();
+        let mut added = 0;
+        while added < count {
+            let batch = (count - added).min(MAX_PFNS_PER_REQUEST);
+            try_reserve(&mut self.pages, batch)?;
+            let start = self.pages.len();
+            for _ in 0..batch {
+                // SAFETY: The layout has a non-zero size.
+                let Some(page) = NonNull::new(unsafe { alloc(layout) }) else {
+                    break;
+                };
+                self.pages.push(page);
+            }
+            let new_pages = self.pages.len() - start;
+            if new_pages == 0 {
+                break;
+            }
+            let pages = self.pages[start..].to_vec();
+            if let Err(e) = self.send_pfns(false, &pages) {
+                // The device may not have taken them, so give them back to the heap.
+                for page in self.pages.drain(start..) {
+                    // SAFETY: The page was allocated above with the same layout.
+                    unsafe { dealloc(page.as_ptr(), layout) };
+                }
+                return Err(e);
+            }
+            added += new_pages;
+            if new_pages < batch {
+                break;
+            }
+        }
+        self.write_actual()?;
+        Ok(added)
+    }
+
+    /// Takes up to `count` pages back from the host and returns them to the heap.
+    ///
+    /// Returns the number of pages removed from the balloon.
+    pub fn deflate(&mut self, count: usize) -> Result<usize, BalloonError> {
+        let layout = balloon_page_layout();
+        let mut removed = 0;
+        while removed < count && !self.pages.is_empty() {
+            let batch = (count - removed)
+                .min(MAX_PFNS_PER_REQUEST)
+                .min(self.pages.len());
+            let start = self.pages.len() - batch;
+            let pages = self.pages[start..].to_vec();
+            let result = self.send_pfns(true, &pages);
+            if self.must_tell_host {
+                // We can't use the pages unless the device has acknowledged the request.
+                result?;
+            }
+            for page in self.pages.drain(start..) {
+                // SAFETY: The page was allocated by `inflate` with the same layout, and the
+                // device has been told that we are taking it back.
+                unsafe { dealloc(page.as_ptr(), layout) };
+            }
+            removed += batch;
+            result?;
+        }
+        self.write_actual()?;
+        Ok(removed)
+    }
+
+    /// Inflates or deflates the balloon to contain the given number of pages, or as close to it as
+    /// the heap allows.
+    pub fn resize(&mut self, target_pages: usize) -> Result<(), BalloonError> {
+        let pages = self.pages.len();
+        if target_pages > pages {
+            self.inflate(target_pages - pages)?;
+        } else if target_pages < pages {
+            self.deflate(pages - target_pages)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the given statistics to the shared buffer and gives it to the device.
+    fn send_stats(&mut self, stats: &[BalloonStat]) {
+        let Some(queue) = &mut self.stats_queue else {
+            return;
+        };
+        let stats_buffer = self.buffer.pointer(STATS_OFFSET);
+        let max_stats = (PAGE_SIZE - STATS_OFFSET) / STAT_SIZE;
+        let stats = &stats[..stats.len().min(max_stats)];
+        for (i, stat) in stats.iter().enumerate() {
+            for (j, byte) in stat.to_bytes().into_iter().enumerate() {
+                // SAFETY: The statistics fit in the buffer after the page frame numbers, and the
+                // device isn't using the statistics buffer as it has been used or never sent.
+                unsafe { stats_buffer.add(i * STAT_SIZE + j).write_volatile(byte) };
+            }
+        }
+        queue.add(
+            &mut self.transport,
+            self.buffer.paddr + STATS_OFFSET,
+            stats.len() * STAT_SIZE,
+        );
+    }
+
+    /// Gives the device fresh statistics if it has asked for them by using the previous ones.
+    ///
+    /// Returns whether it had asked.
+    pub fn poll_stats(&mut self, stats: &[BalloonStat]) -> bool {
+        if !self
+            .stats_queue
+            .as_mut()
+            .is_some_and(|queue| queue.take_used())
+        {
+            return false;
+        }
+        self.send_stats(stats);
+        true
+    }
+}
+
+impl Drop for VirtioBalloon {
+    fn drop(&mut self) {
+        // Reset the device so it stops using our queues before they are freed. The pages in the
+        // balloon are leaked, as the host may not have given them back.
+        self.transport.set_status(DeviceStatus::empty());
+    }
+}
+
+/// Returns the layout of a page given to the balloon.
+fn balloon_page_layout() -> Layout {
+    Layout::from_size_align(BALLOON_PAGE_SIZE, BALLOON_PAGE_SIZE).unwrap()
+}
@@ -0,0 +1,194 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A queue of work for each CPU core, which any core can add to, kicking the target core with an
+//! SGI so that it runs the work from its idle loop.
+
+use crate::{
+    cpus::{cpu_count, current_cpu_index},
+    idle::wfi,
+    interrupts::{end_interrupt, send_sgi_to_all, set_private_irq_handler, with_gic},
+    timer::wait_until,
+};
+use alloc::{boxed::Box, collections::vec_deque::VecDeque, sync::Arc};
+use arm_gic::IntId;
+use core::fmt::{self, Display, Formatter};
+use percore::{ExceptionLock, exception_free};
+use spin::{Lazy, mutex::SpinMutex};
+
+/// The SGI used to tell a core that there is work in its queue.
+pub const WORK_SGI: IntId = IntId::sgi(14);
+
+/// A unit of work to run on a particular core.
+type Work = Box<dyn FnOnce() + Send>;
+
+/// The work queued for a single core.
+struct Queue {
+    /// Whether more work can be added. This is cleared by `stop_accepting_work`.
+    accepting: bool,
+    work: VecDeque<Work>,
+}
+
+/// The queue of work for each core, by CPU index.
+///
+/// Unlike a `PerCoreState`, every core can access every other core's queue, so work can be added
+/// to a queue from any core.
+static QUEUES: Lazy<Box<[ExceptionLock<SpinMutex<Queue>>]>> = Lazy::new(|| {
+    (0..cpu_count())
+        .map(|_| {
+            ExceptionLock::new(SpinMutex::new(Queue {
+                accepting: true,
+                work: VecDeque::new(),
+            }))
+        })
+        .collect()
+});
+
+/// An error queueing work.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WorkError {
+    /// There is no CPU core with the given index.
+    NoSuchCpu(usize),
+    /// The CPU core with the given index is stopping, so won't run any more work.
+    NotAccepting(usize),
+}
+
+impl Display for WorkError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::NoSuchCpu(cpu) => write!(f, "No CPU {cpu}"),
+            Self::NotAccepting(cpu) => write!(f, "CPU {cpu} isn't accepting work"),
+        }
+    }
+}
+
+/// Enables the work SGI on the current core, so that it is woken when work is queued for it.
+///
+/// This should be called by each core which runs `idle` or `run_pending_work`, before enabling
+/// IRQs.
+pub fn init_current_cpu() {
+    let cpu = current_cpu_index();
+    exception_free(|token| QUEUES[cpu].borrow(token).lock().accepting = true);
+    with_gic(|gic| {
+        gic.enable_interrupt(WORK_SGI, Some(cpu), true).unwrap();
+        gic.set_interrupt_priority(WORK_SGI, Some(cpu), 0x80)
+            .unwrap();
+    });
+    set_private_irq_handler(WORK_SGI, &work_sgi_handler);
+}
+
+/// Stops any more work being queued for the current core, so that once `run_pending_work` has
+/// emptied its queue nothing queued for it will be left unrun.
+///
+/// `init_current_cpu` starts accepting work again.
+pub fn stop_accepting_work() {
+    let queue = &QUEUES[current_cpu_index()];
+    exception_free(|token| queue.borrow(token).lock().accepting = false);
+}
+
+/// The handler for the work SGI. There is nothing to do but acknowledge it, as the interrupt only
+/// serves to wake the core from `wfi` so that it runs its queue from its idle loop.
+fn work_sgi_handler(intid: IntId) {
+    end_interrupt(intid);
+}
+
+/// Adds the given work to the queue for the given core, and kicks it with an SGI.
+///
+/// The work runs the next time the core runs its queue, from `idle` or `run_pending_work`. Work
+/// queued for a core which never does so will never run. Returns an error without queueing the
+/// work if the core has called `stop_accepting_work`.
+pub fn queue_work(cpu: usize, work: impl FnOnce() + Send + 'static) -> Result<(), WorkError> {
+    let queue = QUEUES.get(cpu).ok_or(WorkError::NoSuchCpu(cpu))?;
+    exception_free(|token| {
+        let mut queue = queue.borrow(token).lock();
+        if !queue.accepting {
+            return Err(WorkError::NotAccepting(cpu));
+        }
+        queue.work.push_back(Box::new(work));
+        Ok(())
+    })?;
+    if cpu != current_cpu_index() {
+        // There's no way to target a single core yet, but other cores will see that their queues
+        // are empty and go back to sleep.
+        send_sgi_to_all(WORK_SGI);
+    }
+    Ok(())
+}
+
+/// Runs the given function on the given core, returning a handle which can be used to get its
+/// result once it has finished.
+///
+/// If `cpu` is the current core then the function is run immediately.
+pub fn run_on_cpu<R: Send + 'static>(
+    cpu: usize,
+    f: impl FnOnce() -> R + Send + 'static,
+) -> Result<WorkHandle<R>, WorkError> {
+    let handle = WorkHandle {
+        result: Arc::new(SpinMutex::new(None)),
+    };
+    if cpu == current_cpu_index() {
+        *handle.result.lock() = Some(f());
+    } else {
+        let result = handle.result.clone();
+        queue_work(cpu, move || {
+            let value = f();
+            *result.lock() = Some(value);
+        })?;
+    }
+    Ok(handle)
+}
+
+/// A handle to the result of a function passed to `run_on_cpu`.
+pub struct WorkHandle<R> {
+    result: Arc<SpinMutex<Option<R>>>,
+}
+
+impl<R> WorkHandle<R> {
+    /// Returns the result of the function if it has finished, or `None` if it hasn't yet.
+    ///
+    /// The result can only be taken once.
+    pub fn try_take(&self) -> Option<R> {
+        self.result.lock().take()
+    }
+
+    /// Waits up to the given number of milliseconds for the function to finish, and returns its
+    /// result if it did.
+    pub fn wait(self, timeout_ms: u64) -> Option<R> {
+        let mut result = None;
+        wait_until(timeout_ms, || {
+            result = self.try_take();
+            result.is_some()
+        });
+        result
+    }
+}
+
+/// Runs all the work in the current core's queue, including any which is added while doing so.
+///
+/// Returns the number of items of work which were run.
+pub fn run_pending_work() -> usize {
+    let queue = &QUEUES[current_cpu_index()];
+    let mut count = 0;
+    // Take each item out before running it, so that the lock isn't held while it runs.
+    while let Some(work) = exception_free(|token| queue.borrow(token).lock().work.pop_front()) {
+        work();
+        count += 1;
+    }
+    count
+}
+
+/// Runs any work in the current core's queue, and then sleeps until the next interrupt.
+///
+/// This is intended to be called in a loop by an otherwise idle core, with IRQs enabled.
+pub fn idle() {
+    run_pending_work();
+    let queue = &QUEUES[current_cpu_index()];
+    // Check the queue with exceptions masked, so that work queued between the check and the `wfi`
+    // still wakes us.
+    exception_free(|token| {
+        if queue.borrow(token).lock().work.is_empty() {
+            wfi();
+        }
+    });
+}
@@ -0,0 +1,148 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Tracing of heap allocations above a size threshold and of all DMA pool allocations, recorded in
+//! the log ring buffer along with the code which made them.
+//!
+//! Records are only added to the ring buffer, where `dmesg` shows them, as writing every allocation
+//! to the console would be too slow and could deadlock if the console itself allocates. The caller
+//! is the first function in the backtrace outside the allocators, which needs the symbol table to
+//! be embedded in the image; without it, the allocator's own caller is shown instead.
+
+use crate::{
+    backtrace::{CodeAddress, current_frame_pointer, first_caller},
+    logger,
+};
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+use osdemo_core::dma::{self, DmaTraceEvent};
+
+/// Prefixes of the names of functions which are part of allocating memory from the heap, so are
+/// skipped when looking for the code which asked for it.
+const HEAP_INTERNAL_PREFIXES: [&str; 9] = [
+    "__rust_",
+    "__rdl_",
+    "alloc::",
+    "<alloc::",
+    "core::",
+    "<core::",
+    "osdemo::alloc_trace::",
+    "<osdemo::alloc_trace::",
+    "<osdemo::debug_allocator::",
+];
+
+/// Prefixes of the names of functions which are part of allocating memory from the DMA pool, so are
+/// skipped when looking for the code which asked for it.
+const DMA_INTERNAL_PREFIXES: [&str; 6] = [
+    "osdemo::alloc_trace::",
+    "osdemo_core::dma::",
+    "<osdemo_core::dma::",
+    "<osdemo_core::virtio::VirtioHal",
+    "virtio_drivers::hal::",
+    "<virtio_drivers::hal::",
+];
+
+/// Whether allocations are currently being traced.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The smallest heap allocation which is traced, in bytes.
+static MIN_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// Starts tracing heap allocations and frees of at least `min_size` bytes, and all DMA pool
+/// allocations and frees.
+pub fn enable(min_size: usize) {
+    MIN_SIZE.store(min_size, Ordering::Relaxed);
+    ENABLED.store(true, Ordering::Relaxed);
+    dma::set_trace_hook(Some(trace_dma));
+}
+
+/// Stops tracing allocations.
+pub fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+    dma::set_trace_hook(None);
+}
+
+/// Returns the smallest heap allocation being traced, or `None` if tracing is disabled.
+pub fn min_size() -> Option<usize> {
+    ENABLED
+        .load(Ordering::Relaxed)
+        .then(|| MIN_SIZE.load(Ordering::Relaxed))
+}
+
+/// Wraps another allocator to record allocations and frees in the log ring buffer while tracing is
+/// enabled.
+pub struct TracingAllocator<A: 'static> {
+    inner: &'static A,
+}
+
+impl<A: GlobalAlloc> TracingAllocator<A> {
+    /// Creates a new tracing allocator which allocates blocks from `inner`.
+    pub const fn new(inner: &'static A) -> Self {
+        Self { inner }
+    }
+}
+
+// SAFETY: All allocations are made by the inner allocator, which we trust to implement
+// `GlobalAlloc` correctly.
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TracingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // SAFETY: Our caller promises that the layout has a non-zero size.
+        let ptr = unsafe { self.inner.alloc(layout) };
+        if should_trace(layout) {
+            trace_heap("alloc", ptr, layout);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if should_trace(layout) {
+            trace_heap("free", ptr, layout);
+        }
+        // SAFETY: Our caller promises that the pointer was allocated by this allocator, and so by
+        // the inner allocator, with the same layout.
+        unsafe { self.inner.dealloc(ptr, layout) }
+    }
+}
+
+/// Returns whether a heap allocation or free with the given layout should be traced.
+fn should_trace(layout: Layout) -> bool {
+    ENABLED.load(Ordering::Relaxed) && layout.size() >= MIN_SIZE.load(Ordering::Relaxed)
+}
+
+/// Records a heap allocation or free of the given block.
+#[inline(never)]
+fn trace_heap(operation: &str, ptr: *mut u8, layout: Layout) {
+    let caller = find_caller(&HEAP_INTERNAL_PREFIXES);
+    logger::trace(format_args!(
+        "{operation} heap {} bytes at {ptr:?} from {caller}",
+        layout.size()
+    ));
+}
+
+/// Records an allocation or free in the DMA pool.
+#[inline(never)]
+fn trace_dma(event: DmaTraceEvent) {
+    let caller = find_caller(&DMA_INTERNAL_PREFIXES);
+    let (operation, paddr, pages) = match event {
+        DmaTraceEvent::Alloc { paddr, pages } => ("alloc", paddr, pages),
+        DmaTraceEvent::Dealloc { paddr, pages } => ("free", paddr, pages),
+    };
+    logger::trace(format_args!(
+        "{operation} dma {pages} pages at {paddr:#x} from {caller}"
+    ));
+}
+
+/// Returns the first caller in the current backtrace which isn't in a function whose name starts
+/// with one of the given prefixes.
+#[inline(always)]
+fn find_caller(internal_prefixes: &[&str]) -> CodeAddress {
+    let address = first_caller(current_frame_pointer(), |name| {
+        internal_prefixes
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+    });
+    CodeAddress(address.unwrap_or(0))
+}
@@ -3,5 +3,39 @@
 // See LICENSE-APACHE and LICENSE-MIT for details.
 
 mod alarm;
+mod balloon;
+mod bench;
+#[cfg(feature = "block")]
+mod blk;
+mod command;
+mod coredump;
+mod cp;
 mod cpus;
+pub mod crashdump;
+mod dashboard;
+mod dmesg;
+mod events;
+mod flood;
+mod hostcat;
+mod hwreport;
+mod line_editor;
+#[cfg(feature = "net")]
+mod net;
+mod pcidump;
+mod perf;
+mod process;
+mod prompt;
+mod random;
+mod rc;
+mod rusage;
+mod scrub;
+pub mod selftest;
+mod session;
+mod settings;
 pub mod shell;
+mod stopwatch;
+mod suspend;
+mod timezone;
+mod top;
+mod trace;
+mod vconsole;
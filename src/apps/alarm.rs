@@ -3,69 +3,98 @@
 // See LICENSE-APACHE and LICENSE-MIT for details.
 
 use crate::{
-    interrupts::{GIC, remove_shared_irq_handler, set_shared_irq_handler},
+    apps::{
+        command::{Args, CommandError, Context, ErrorContext},
+        timezone,
+    },
     platform::{Platform, PlatformImpl},
 };
-use arm_gic::{IntId, InterruptGroup, Trigger, gicv3::GicCpuInterface};
-use arm_pl031::Rtc;
-use chrono::Duration;
-use core::sync::atomic::{AtomicBool, Ordering};
+use arm_gic::{IntId, Trigger};
+use chrono::{DateTime, Duration, Utc};
+use core::sync::atomic::{AtomicBool, AtomicI64, Ordering};
 use embedded_io::Write;
 use log::info;
+use osdemo_core::{
+    interrupts::{end_interrupt, remove_shared_irq_handler, set_shared_irq_handler, with_gic},
+    rtc::RtcDevice,
+};
 
 /// The RTC alarm IRQ has fired, and we have not yet cleared the interrupt.
 static ALARM_FIRED: AtomicBool = AtomicBool::new(false);
 
+/// The time the alarm set by `set_alarm` is due, in seconds since the Unix epoch, or 0 if there is
+/// no alarm pending.
+static PENDING_ALARM: AtomicI64 = AtomicI64::new(0);
+
 /// Configures the RTC IRQ.
 pub fn irq_setup() {
-    let mut gic = GIC.get().unwrap().lock();
-
-    set_shared_irq_handler(PlatformImpl::RTC_IRQ, &irq_handle);
-    gic.set_interrupt_priority(PlatformImpl::RTC_IRQ, None, 0x80)
-        .unwrap();
-    gic.set_trigger(PlatformImpl::RTC_IRQ, None, Trigger::Level)
-        .unwrap();
-    gic.enable_interrupt(PlatformImpl::RTC_IRQ, None, true)
-        .unwrap();
+    set_shared_irq_handler(PlatformImpl::rtc_irq(), &irq_handle);
+    with_gic(|gic| {
+        gic.set_interrupt_priority(PlatformImpl::rtc_irq(), None, 0x80)
+            .unwrap();
+        gic.set_trigger(PlatformImpl::rtc_irq(), None, Trigger::Level)
+            .unwrap();
+        gic.enable_interrupt(PlatformImpl::rtc_irq(), None, true)
+            .unwrap();
+    });
 }
 
 /// Removes our RTC IRQ handler.
 pub fn irq_remove() {
-    remove_shared_irq_handler(PlatformImpl::RTC_IRQ);
+    remove_shared_irq_handler(PlatformImpl::rtc_irq());
 }
 
 /// Handles an RTC IRQ.
 fn irq_handle(_intid: IntId) {
     info!("RTC alarm");
+    PENDING_ALARM.store(0, Ordering::SeqCst);
     ALARM_FIRED.store(true, Ordering::SeqCst);
 }
 
+/// Returns whether the alarm IRQ has fired since it was last finished with `irq_finish`.
+pub fn fired() -> bool {
+    ALARM_FIRED.load(Ordering::SeqCst)
+}
+
 /// Finishes handling the alarm IRQ, ready to set another alarm in future.
-pub fn irq_finish(rtc: &mut Rtc) {
+pub fn irq_finish(rtc: &mut dyn RtcDevice) {
     if ALARM_FIRED.swap(false, Ordering::SeqCst) {
         rtc.clear_interrupt();
-        GicCpuInterface::end_interrupt(PlatformImpl::RTC_IRQ, InterruptGroup::Group1);
+        end_interrupt(PlatformImpl::rtc_irq());
         info!("Alarm fired, clearing");
     }
 }
 
-/// Sets an alarm for 5 seconds in the future.
-pub fn alarm<'a>(console: &mut impl Write, mut args: impl Iterator<Item = &'a str>, rtc: &mut Rtc) {
+/// Returns when the alarm set by `set_alarm` is due, if it hasn't fired yet.
+pub fn pending() -> Option<DateTime<Utc>> {
+    match PENDING_ALARM.load(Ordering::SeqCst) {
+        0 => None,
+        timestamp => DateTime::from_timestamp(timestamp, 0),
+    }
+}
+
+/// Sets an alarm for the given time, replacing any previous alarm.
+pub fn set_alarm(rtc: &mut dyn RtcDevice, alarm_time: DateTime<Utc>) -> Result<(), CommandError> {
     irq_finish(rtc);
+    rtc.set_match(alarm_time).context("setting alarm")?;
+    PENDING_ALARM.store(alarm_time.timestamp(), Ordering::SeqCst);
+    rtc.enable_interrupt(true);
+    Ok(())
+}
 
-    let Some(delay) = args.next() else {
-        writeln!(console, "Usage:").unwrap();
-        writeln!(console, "  alarm <delay>").unwrap();
-        return;
-    };
-    let Ok(delay) = delay.parse() else {
-        writeln!(console, "Invalid delay time").unwrap();
-        return;
-    };
+/// Sets an alarm for the given number of seconds in the future.
+pub fn alarm(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let delay = args.required("delay")?;
+    args.finish()?;
 
-    let timestamp = rtc.get_time();
-    let alarm_time = timestamp + Duration::seconds(delay);
-    rtc.set_match(alarm_time).unwrap();
-    rtc.enable_interrupt(true);
-    writeln!(console, "Set alarm for {alarm_time}").unwrap();
+    let rtc = &mut *context.devices.rtc;
+    let alarm_time = rtc.get_time() + Duration::seconds(delay);
+    set_alarm(rtc, alarm_time)?;
+    writeln!(
+        context.console,
+        "Set alarm for {}",
+        timezone::local_time(alarm_time)
+    )
+    .unwrap();
+    Ok(())
 }
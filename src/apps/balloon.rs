@@ -0,0 +1,240 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Cooperatively returning unused heap memory to the host with a memory balloon, and the policy for
+//! doing so.
+
+use crate::{
+    apps::command::{Args, CommandError, Context, ErrorContext},
+    heap_usage,
+};
+use aarch64_paging::paging::PAGE_SIZE;
+use core::sync::atomic::{AtomicU64, Ordering};
+use embedded_io::Write;
+use log::{info, warn};
+use osdemo::balloon_policy::{Balloon, BalloonAction, BalloonPolicy, MemoryUsage};
+use osdemo_core::{
+    devices::Devices,
+    dma,
+    timer::uptime_us,
+    virtio_balloon::{BALLOON_PAGE_SIZE, BalloonStat, VirtioBalloon},
+};
+use spin::mutex::SpinMutex;
+
+/// How much of the heap to always leave free, however large the balloon is asked to be.
+const HEAP_RESERVE_BYTES: usize = 1024 * 1024;
+
+/// How long to wait between steps of the balloon policy.
+///
+/// The next step actually happens the next time the balloon is polled after this, which may be
+/// later if nothing wakes the shell.
+const POLICY_INTERVAL_US: u64 = 1_000_000;
+
+/// The current balloon policy, as configured with `balloon policy`.
+static POLICY: SpinMutex<BalloonPolicy> = SpinMutex::new(BalloonPolicy::DEFAULT);
+
+/// The uptime when the balloon policy last took a step.
+static LAST_POLICY_STEP_US: AtomicU64 = AtomicU64::new(0);
+
+/// A balloon which is resized without leaving less than `HEAP_RESERVE_BYTES` of the heap free.
+struct HeapBalloon<'a>(&'a mut VirtioBalloon);
+
+impl Balloon for HeapBalloon<'_> {
+    type Error = CommandError;
+
+    fn pages(&self) -> usize {
+        self.0.pages()
+    }
+
+    fn resize(&mut self, target_pages: usize) -> Result<(), CommandError> {
+        resize(self.0, target_pages)
+    }
+}
+
+/// Returns the combined usage of the heap and the DMA pool.
+fn memory_usage() -> MemoryUsage {
+    let mut usage = heap_usage();
+    if let Some(stats) = dma::stats() {
+        usage.total_bytes += stats.total_pages * PAGE_SIZE;
+        usage.used_bytes += stats.allocated_pages * PAGE_SIZE;
+    }
+    usage
+}
+
+/// Returns the statistics to report to the host.
+fn stats() -> [BalloonStat; 3] {
+    let usage = heap_usage();
+    let free_bytes = usage.total_bytes.saturating_sub(usage.used_bytes) as u64;
+    [
+        BalloonStat::TotalMemory(usage.total_bytes as u64),
+        BalloonStat::FreeMemory(free_bytes),
+        BalloonStat::AvailableMemory(free_bytes),
+    ]
+}
+
+/// Resizes the balloon towards the given number of pages, without leaving less than
+/// `HEAP_RESERVE_BYTES` of the heap free.
+fn resize(balloon: &mut VirtioBalloon, target_pages: usize) -> Result<(), CommandError> {
+    let usage = heap_usage();
+    let spare_pages = usage
+        .total_bytes
+        .saturating_sub(usage.used_bytes)
+        .saturating_sub(HEAP_RESERVE_BYTES)
+        / BALLOON_PAGE_SIZE;
+    let target_pages = target_pages.min(balloon.pages() + spare_pages);
+    balloon
+        .resize(target_pages)
+        .context("resizing memory balloon")?;
+    Ok(())
+}
+
+/// Gives each balloon fresh statistics if it has asked for them, follows any change to the size the
+/// host would like it to be, and resizes it according to the policy when a step is due.
+pub fn poll(devices: &mut Devices) {
+    let policy = *POLICY.lock();
+    let now = uptime_us();
+    // A policy which never inflates the balloon would only undo resizing with `balloon <MiB>`.
+    let step_due = policy.max_pages > 0
+        && now >= LAST_POLICY_STEP_US.load(Ordering::Relaxed) + POLICY_INTERVAL_US;
+    if step_due {
+        LAST_POLICY_STEP_US.store(now, Ordering::Relaxed);
+    }
+    for (index, balloon) in devices.balloon.iter_mut().enumerate() {
+        balloon.poll_stats(&stats());
+        match balloon.target_change() {
+            Ok(Some(target_pages)) => {
+                info!("Host asked for balloon{index} to be {target_pages} pages");
+                if let Err(e) = resize(balloon, target_pages) {
+                    warn!("{e}");
+                }
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Error reading balloon{index} target: {e}"),
+        }
+        if step_due {
+            step_policy(&policy, index, balloon);
+        }
+    }
+}
+
+/// Resizes the given balloon by one step of the policy, unless the host has asked for it to be a
+/// particular size.
+fn step_policy(policy: &BalloonPolicy, index: usize, balloon: &mut VirtioBalloon) {
+    let host_target_pages = match balloon.target_pages() {
+        Ok(target_pages) => target_pages,
+        Err(e) => {
+            warn!("Error reading balloon{index} target: {e}");
+            return;
+        }
+    };
+    match policy.step(
+        &memory_usage(),
+        host_target_pages,
+        &mut HeapBalloon(balloon),
+    ) {
+        Ok(BalloonAction::None) => {}
+        Ok(action) => info!(
+            "Balloon policy: {action}, balloon{index} now {} pages",
+            balloon.pages()
+        ),
+        Err(e) => warn!("{e}"),
+    }
+}
+
+/// Shows the balloon's size, resizes it, or shows or configures the policy.
+pub fn balloon(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let Context {
+        console, devices, ..
+    } = context;
+    match args.next() {
+        None => {
+            if devices.balloon.is_empty() {
+                writeln!(console, "No balloon device").unwrap();
+            }
+            for (index, balloon) in devices.balloon.iter().enumerate() {
+                let target = balloon.target_pages().context("reading balloon target")?;
+                writeln!(
+                    console,
+                    "balloon{index}: {} pages ({} KiB), host target {target} pages",
+                    balloon.pages(),
+                    balloon.pages() * BALLOON_PAGE_SIZE / 1024
+                )
+                .unwrap();
+            }
+            return Ok(());
+        }
+        Some("policy") => {}
+        Some(size) => {
+            let mib: usize = size.parse().map_err(|_| CommandError::InvalidArgument {
+                name: "MiB",
+                value: size.into(),
+            })?;
+            args.finish()?;
+            let balloon = devices
+                .balloon
+                .first_mut()
+                .ok_or(CommandError::NoSuchDevice {
+                    kind: "balloon",
+                    index: 0,
+                })?;
+            let target_pages = mib * (1024 * 1024 / BALLOON_PAGE_SIZE);
+            resize(balloon, target_pages)?;
+            if balloon.pages() < target_pages {
+                writeln!(
+                    console,
+                    "Only inflated to {} pages, to leave enough of the heap free",
+                    balloon.pages()
+                )
+                .unwrap();
+            }
+            return Ok(());
+        }
+    }
+    let mut policy = *POLICY.lock();
+    match args.next() {
+        None => {}
+        Some("set") => {
+            policy.min_pages = args.required("min_pages")?;
+            policy.max_pages = args.required("max_pages")?;
+        }
+        Some("thresholds") => {
+            policy.deflate_below_percent = args.required("deflate_below_percent")?;
+            policy.inflate_above_percent = args.required("inflate_above_percent")?;
+        }
+        Some("step") => {
+            policy.step_pages = args.required("step_pages")?;
+        }
+        Some("reset") => {
+            policy = BalloonPolicy::DEFAULT;
+        }
+        Some(_) => return Err(CommandError::Usage),
+    }
+    args.finish()?;
+    if !policy.is_valid() {
+        return Err(
+            "Invalid policy: need min <= max, deflate < inflate <= 100 and step > 0.".into(),
+        );
+    }
+    *POLICY.lock() = policy;
+
+    let usage = memory_usage();
+    writeln!(console, "Policy: {policy}").unwrap();
+    writeln!(console, "Memory: {usage}").unwrap();
+    match devices.balloon.first() {
+        Some(balloon) => writeln!(
+            console,
+            "Balloon has {} pages, next policy step: {} (unless the host sets a target)",
+            balloon.pages(),
+            policy.decide(&usage, balloon.pages())
+        )
+        .unwrap(),
+        None => writeln!(
+            console,
+            "No balloon device, would {}",
+            policy.decide(&usage, 0)
+        )
+        .unwrap(),
+    }
+    Ok(())
+}
@@ -0,0 +1,618 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Benchmarks reporting results in a common structured format, so they can be saved and compared.
+//!
+//! Saved results are kept in the settings store along with the other settings, so they survive a
+//! reboot if there is a writable block device.
+
+#[cfg(feature = "block")]
+use crate::apps::command::ErrorContext;
+use crate::apps::{
+    command::{Args, CommandError, Context},
+    settings,
+};
+use alloc::{boxed::Box, format, string::String, vec, vec::Vec};
+use core::{
+    alloc::Layout,
+    fmt::{self, Display, Formatter},
+    hint::black_box,
+    ptr::NonNull,
+    time::Duration,
+};
+use embedded_io::Write;
+use osdemo::{
+    prng::Prng,
+    settings::{SavedBenchResults, SavedMetric},
+};
+#[cfg(feature = "block")]
+use osdemo_core::{
+    block::BlockDevice, device_id::DeviceKind, executor::block_on, virtio::ASYNC_BLK_REQUEST_SIZE,
+};
+use osdemo_core::{
+    devices::Devices,
+    drivers::generic_timer::{self, Instant},
+    fallible::try_vec,
+    power::{EnergyReport, EnergySample},
+    timer::{counter, counter_frequency},
+    tlsf::Tlsf,
+    tracked_heap::TrackedHeap,
+};
+use spin::mutex::SpinMutex;
+#[cfg(feature = "block")]
+use virtio_drivers::device::blk::SECTOR_SIZE;
+
+/// The number of blocks to read from each device in the disk benchmark.
+const DISK_BENCH_BLOCKS: u64 = 256;
+
+/// The number of blocks read by `blkbench` unless otherwise specified.
+#[cfg(feature = "block")]
+const BLKBENCH_DEFAULT_BLOCKS: usize = 1024;
+
+/// The number of iterations of the CPU benchmark loop.
+const CPU_BENCH_ITERATIONS: u64 = 10_000_000;
+
+/// The size of the buffer copied by the memory benchmark.
+const MEMORY_BENCH_SIZE: usize = 64 * 1024;
+
+/// The number of times the memory benchmark copies its buffer.
+const MEMORY_BENCH_ITERATIONS: usize = 64;
+
+/// The size of the scratch memory which each allocator in the allocation benchmark manages.
+const ALLOC_BENCH_HEAP_SIZE: usize = 32 * 1024;
+
+/// The number of allocations and frees made with each allocator in the allocation benchmark.
+const ALLOC_BENCH_OPERATIONS: usize = 10_000;
+
+/// The most allocations which the allocation benchmark keeps live at once.
+const ALLOC_BENCH_MAX_LIVE: usize = 16;
+
+/// The largest allocation made by the allocation benchmark, in bytes.
+const ALLOC_BENCH_MAX_SIZE: u64 = 1024;
+
+/// The number of times the IRQ benchmark sleeps.
+const IRQ_BENCH_ITERATIONS: usize = 100;
+
+/// How long the IRQ benchmark sleeps for each time.
+const IRQ_BENCH_SLEEP: Duration = Duration::from_micros(100);
+
+/// The seed for the allocation benchmark's sizes and order of operations, so that every allocator
+/// sees the same sequence.
+const ALLOC_BENCH_SEED: u64 = 0x5eed;
+
+/// A single measurement from a benchmark.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Metric {
+    pub name: String,
+    pub value: u64,
+    pub unit: &'static str,
+}
+
+/// The results of running a single benchmark.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BenchResult {
+    pub benchmark: &'static str,
+    pub metrics: Vec<Metric>,
+    pub energy: Option<EnergyReport>,
+}
+
+impl Display for BenchResult {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for metric in &self.metrics {
+            writeln!(
+                f,
+                "bench {}.{} {} {}",
+                self.benchmark, metric.name, metric.value, metric.unit
+            )?;
+        }
+        if let Some(energy) = &self.energy {
+            writeln!(f, "bench {}.energy {energy}", self.benchmark)?;
+        }
+        Ok(())
+    }
+}
+
+/// A benchmark which can be run by the `bench` command.
+struct Benchmark {
+    name: &'static str,
+    description: &'static str,
+    run: fn(&mut Devices) -> Result<Vec<Metric>, &'static str>,
+}
+
+const BENCHMARKS: [Benchmark; 5] = [
+    Benchmark {
+        name: "alloc",
+        description: "Heap allocation and free latency, buddy versus TLSF",
+        run: bench_alloc,
+    },
+    Benchmark {
+        name: "cpu",
+        description: "Integer arithmetic loop",
+        run: bench_cpu,
+    },
+    Benchmark {
+        name: "disk",
+        description: "Sequential reads from each block device",
+        run: bench_disk,
+    },
+    Benchmark {
+        name: "irq",
+        description: "Timer interrupt latency when waking from sleep",
+        run: bench_irq,
+    },
+    Benchmark {
+        name: "memory",
+        description: "Memory copy bandwidth",
+        run: bench_memory,
+    },
+];
+
+/// Results from the most recent `bench run`.
+static LAST_RESULTS: SpinMutex<Vec<BenchResult>> = SpinMutex::new(Vec::new());
+
+/// Results saved with `bench save`, in the order they were saved.
+static SAVED_RESULTS: SpinMutex<Vec<SavedBenchResults>> = SpinMutex::new(Vec::new());
+
+/// Returns the results saved with `bench save`, to be kept in the settings store.
+pub fn saved_results() -> Vec<SavedBenchResults> {
+    SAVED_RESULTS.lock().clone()
+}
+
+/// Replaces the saved results with those restored from the settings store.
+pub fn restore_saved_results(results: Vec<SavedBenchResults>) {
+    *SAVED_RESULTS.lock() = results;
+}
+
+pub fn bench(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let console = &mut *context.console;
+    match args.required_str("subcommand")? {
+        "list" => {
+            args.finish()?;
+            for benchmark in &BENCHMARKS {
+                writeln!(console, "{} - {}", benchmark.name, benchmark.description).unwrap();
+            }
+        }
+        "run" => {
+            let name = args.next().unwrap_or("all");
+            args.finish()?;
+            run(console, name, context.devices)?;
+        }
+        "alloc" => {
+            args.finish()?;
+            run(console, "alloc", context.devices)?;
+        }
+        "show" => {
+            args.finish()?;
+            for result in LAST_RESULTS.lock().iter() {
+                write!(console, "{result}").unwrap();
+            }
+        }
+        "save" => {
+            let name = args.required_str("name")?;
+            args.finish()?;
+            let metrics = saved_metrics(&LAST_RESULTS.lock());
+            if metrics.is_empty() {
+                return Err("No results to save, use `bench run` first.".into());
+            }
+            let previous = saved_results();
+            {
+                let mut saved = SAVED_RESULTS.lock();
+                saved.retain(|results| results.name != name);
+                saved.push(SavedBenchResults {
+                    name: name.into(),
+                    metrics,
+                });
+            }
+            if !settings::has_store(context.devices) {
+                writeln!(
+                    console,
+                    "Saved results as {name} in memory only, as there is no writable block device"
+                )
+                .unwrap();
+            } else if let Err(e) = settings::save(context.devices, context.sessions.uart_editor()) {
+                restore_saved_results(previous);
+                return Err(e);
+            } else {
+                writeln!(console, "Saved results as {name}").unwrap();
+            }
+        }
+        "compare" => {
+            let a = args.required_str("name")?;
+            let b = args.required_str("name")?;
+            args.finish()?;
+            compare(console, a, b)?;
+        }
+        _ => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+/// Reads from a VirtIO block device first with one request at a time, then with several outstanding
+/// at once, and prints the throughput of each.
+#[cfg(feature = "block")]
+pub fn blkbench(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let Context {
+        console, devices, ..
+    } = context;
+    let index = args.next_device(DeviceKind::Block, devices.block.len())?;
+    let blocks = args.optional("blocks")?.unwrap_or(BLKBENCH_DEFAULT_BLOCKS);
+    args.finish()?;
+    // Bypass the cache, so that the results reflect the device.
+    let device = devices.block[index]
+        .uncached()
+        .as_virtio_blk()
+        .ok_or("Only VirtIO block devices support asynchronous requests")?;
+    let blocks = blocks.min(device.capacity() as usize);
+    if blocks == 0 {
+        return Err("Block device is empty".into());
+    }
+    let mut buffer = try_vec(0, blocks * SECTOR_SIZE)?;
+
+    // Use the same request size for both, so that only the number outstanding differs.
+    let mut result = Ok(());
+    let sync_ns = time_ns(|| {
+        for (i, chunk) in buffer.chunks_mut(ASYNC_BLK_REQUEST_SIZE).enumerate() {
+            let block_id = i * ASYNC_BLK_REQUEST_SIZE / SECTOR_SIZE;
+            result = device.read_blocks(block_id as u64, chunk);
+            if result.is_err() {
+                break;
+            }
+        }
+    });
+    result.context("reading synchronously")?;
+    let mut result = Ok(());
+    let async_ns = time_ns(|| result = block_on(device.read_sectors_async(0, &mut buffer)));
+    result.context("reading asynchronously")?;
+
+    let bytes = buffer.len() as u64;
+    let mut metrics = Vec::new();
+    for (name, elapsed_ns) in [("sync", sync_ns), ("async", async_ns)] {
+        metrics.push(Metric {
+            name: format!("{name}.time"),
+            value: elapsed_ns / 1000,
+            unit: "us",
+        });
+        metrics.push(Metric {
+            name: format!("{name}.throughput"),
+            value: bytes * 1_000_000_000 / 1024 / elapsed_ns.max(1),
+            unit: "KiB/s",
+        });
+    }
+    let result = BenchResult {
+        benchmark: "blkbench",
+        metrics,
+        energy: None,
+    };
+    write!(console, "{result}").unwrap();
+    Ok(())
+}
+
+/// Runs the given benchmark, or all of them.
+fn run(
+    console: &mut (impl Write + ?Sized),
+    name: &str,
+    devices: &mut Devices,
+) -> Result<(), CommandError> {
+    let mut results = Vec::new();
+    for benchmark in BENCHMARKS
+        .iter()
+        .filter(|benchmark| name == "all" || benchmark.name == name)
+    {
+        writeln!(console, "Running {}...", benchmark.name).unwrap();
+        let energy_sample = EnergySample::start(devices.energy_meter.as_mut());
+        let metrics = (benchmark.run)(devices);
+        let energy = energy_sample.finish(devices.energy_meter.as_mut());
+        match metrics {
+            Ok(metrics) => {
+                let result = BenchResult {
+                    benchmark: benchmark.name,
+                    metrics,
+                    energy: Some(energy),
+                };
+                write!(console, "{result}").unwrap();
+                results.push(result);
+            }
+            Err(e) => {
+                writeln!(console, "{} failed: {e}", benchmark.name).unwrap();
+            }
+        }
+    }
+    if results.is_empty() {
+        return Err("No benchmarks run.".into());
+    }
+    *LAST_RESULTS.lock() = results;
+    Ok(())
+}
+
+/// Converts the given results to the form in which they are saved.
+fn saved_metrics(results: &[BenchResult]) -> Vec<SavedMetric> {
+    results
+        .iter()
+        .flat_map(|result| {
+            result.metrics.iter().map(|metric| SavedMetric {
+                benchmark: result.benchmark.into(),
+                name: metric.name.clone(),
+                value: metric.value,
+                unit: metric.unit.into(),
+            })
+        })
+        .collect()
+}
+
+/// Compares two sets of saved results, printing the relative change in each metric.
+fn compare(console: &mut (impl Write + ?Sized), a: &str, b: &str) -> Result<(), CommandError> {
+    let saved = SAVED_RESULTS.lock();
+    let find = |name| saved.iter().find(|results| results.name == name);
+    let (Some(results_a), Some(results_b)) = (find(a), find(b)) else {
+        return Err("Saved results not found.".into());
+    };
+    for metric_a in &results_a.metrics {
+        let Some(metric_b) = results_b
+            .metrics
+            .iter()
+            .find(|metric| metric.benchmark == metric_a.benchmark && metric.name == metric_a.name)
+        else {
+            continue;
+        };
+        let change = if metric_a.value == 0 {
+            0
+        } else {
+            (metric_b.value as i64 - metric_a.value as i64) * 100 / metric_a.value as i64
+        };
+        writeln!(
+            console,
+            "bench {}.{} {} -> {} {} ({change:+}%)",
+            metric_a.benchmark, metric_a.name, metric_a.value, metric_b.value, metric_a.unit
+        )
+        .unwrap();
+    }
+    Ok(())
+}
+
+/// Runs the given function, and returns how long it took in nanoseconds.
+fn time_ns(f: impl FnOnce()) -> u64 {
+    let start = counter();
+    f();
+    ticks_to_ns(counter() - start)
+}
+
+/// Converts a number of counter ticks to nanoseconds.
+fn ticks_to_ns(ticks: u64) -> u64 {
+    (u128::from(ticks) * 1_000_000_000 / u128::from(counter_frequency())) as u64
+}
+
+fn bench_cpu(_devices: &mut Devices) -> Result<Vec<Metric>, &'static str> {
+    let elapsed_ns = time_ns(|| {
+        let mut value = 0u64;
+        for i in 0..CPU_BENCH_ITERATIONS {
+            value = black_box(value.wrapping_mul(31).wrapping_add(i));
+        }
+    });
+    Ok(vec![
+        Metric {
+            name: "time".into(),
+            value: elapsed_ns / 1000,
+            unit: "us",
+        },
+        Metric {
+            name: "iterations_per_us".into(),
+            value: CPU_BENCH_ITERATIONS * 1000 / elapsed_ns.max(1),
+            unit: "iter/us",
+        },
+    ])
+}
+
+fn bench_disk(devices: &mut Devices) -> Result<Vec<Metric>, &'static str> {
+    if devices.block.is_empty() {
+        return Err("No block device found");
+    }
+    let mut metrics = Vec::new();
+    for (index, block) in devices.block.iter_mut().enumerate() {
+        // Bypass the cache, so that the results reflect the backend.
+        let block = block.uncached();
+        let blocks = DISK_BENCH_BLOCKS.min(block.capacity());
+        if blocks == 0 {
+            continue;
+        }
+        let mut buffer = vec![0; block.block_size()];
+        let mut result = Ok(());
+        let elapsed_ns = time_ns(|| {
+            for block_id in 0..blocks {
+                result = block.read_blocks(block_id, &mut buffer);
+                if result.is_err() {
+                    break;
+                }
+            }
+        });
+        result.map_err(|_| "Error reading from block device")?;
+        // Prefix metrics with the device kind and index, so that different backends can be compared.
+        let prefix = format!("{}{index}", block.kind());
+        let bytes = blocks * buffer.len() as u64;
+        metrics.push(Metric {
+            name: format!("{prefix}.time"),
+            value: elapsed_ns / 1000,
+            unit: "us",
+        });
+        metrics.push(Metric {
+            name: format!("{prefix}.throughput"),
+            value: bytes * 1_000_000_000 / 1024 / elapsed_ns.max(1),
+            unit: "KiB/s",
+        });
+    }
+    if metrics.is_empty() {
+        return Err("All block devices are empty");
+    }
+    Ok(metrics)
+}
+
+fn bench_memory(_devices: &mut Devices) -> Result<Vec<Metric>, &'static str> {
+    let source = try_vec(0x55u8, MEMORY_BENCH_SIZE).map_err(|_| "Out of memory")?;
+    let mut destination = try_vec(0u8, MEMORY_BENCH_SIZE).map_err(|_| "Out of memory")?;
+    let elapsed_ns = time_ns(|| {
+        for _ in 0..MEMORY_BENCH_ITERATIONS {
+            destination.copy_from_slice(black_box(&source));
+            black_box(&mut destination);
+        }
+    });
+    let bytes = (MEMORY_BENCH_SIZE * MEMORY_BENCH_ITERATIONS) as u64;
+    Ok(vec![
+        Metric {
+            name: "time".into(),
+            value: elapsed_ns / 1000,
+            unit: "us",
+        },
+        Metric {
+            name: "bandwidth".into(),
+            value: bytes * 1_000_000_000 / (1024 * 1024) / elapsed_ns.max(1),
+            unit: "MiB/s",
+        },
+    ])
+}
+
+/// Sleeps repeatedly on the timer interrupt, and reports the distribution of how long after each
+/// deadline the core was woken.
+fn bench_irq(_devices: &mut Devices) -> Result<Vec<Metric>, &'static str> {
+    let mut latencies = try_vec(0, IRQ_BENCH_ITERATIONS).map_err(|_| "Out of memory")?;
+    for latency in &mut latencies {
+        let deadline = Instant::now() + IRQ_BENCH_SLEEP;
+        generic_timer::sleep(IRQ_BENCH_SLEEP);
+        *latency = Instant::now().ticks().saturating_sub(deadline.ticks());
+    }
+    latencies.sort_unstable();
+    let percentile = |percent: usize| latencies[(latencies.len() - 1) * percent / 100];
+    Ok([
+        ("latency_p50", percentile(50)),
+        ("latency_p99", percentile(99)),
+        ("latency_max", percentile(100)),
+    ]
+    .into_iter()
+    .map(|(name, ticks)| Metric {
+        name: name.into(),
+        value: ticks_to_ns(ticks),
+        unit: "ns",
+    })
+    .collect())
+}
+
+/// Runs the same sequence of allocations and frees with the buddy allocator used for the heap by
+/// default and with the TLSF allocator, each managing its own scratch memory, and reports the
+/// distribution of how long each operation took.
+fn bench_alloc(_devices: &mut Devices) -> Result<Vec<Metric>, &'static str> {
+    let mut memory = try_vec(0u8, ALLOC_BENCH_HEAP_SIZE).map_err(|_| "Out of memory")?;
+    let mut metrics = Vec::new();
+    let buddy = alloc_latencies(&mut TrackedHeap::<32>::new(), &mut memory)?;
+    let tlsf = alloc_latencies(&mut *Box::new(Tlsf::new()), &mut memory)?;
+    for (allocator, (alloc_ticks, free_ticks)) in [("buddy", buddy), ("tlsf", tlsf)] {
+        for (operation, mut ticks) in [("alloc", alloc_ticks), ("free", free_ticks)] {
+            ticks.sort_unstable();
+            let percentile = |percent: usize| ticks[(ticks.len() - 1) * percent / 100];
+            for (statistic, value) in [
+                ("p50", percentile(50)),
+                ("p99", percentile(99)),
+                ("max", percentile(100)),
+            ] {
+                metrics.push(Metric {
+                    name: format!("{allocator}.{operation}_{statistic}"),
+                    value: ticks_to_ns(value),
+                    unit: "ns",
+                });
+            }
+        }
+    }
+    Ok(metrics)
+}
+
+/// An allocator which can be compared by the allocation benchmark.
+trait BenchAllocator {
+    /// Adds the given memory to the allocator.
+    ///
+    /// # Safety
+    ///
+    /// The range must be valid and unused by anything else for as long as the allocator is used.
+    unsafe fn init(&mut self, start: usize, size: usize);
+
+    fn alloc(&mut self, layout: Layout) -> Option<NonNull<u8>>;
+
+    /// # Safety
+    ///
+    /// The pointer must have been returned by `alloc` on this allocator with the same layout, and
+    /// not yet freed.
+    unsafe fn dealloc(&mut self, ptr: NonNull<u8>, layout: Layout);
+}
+
+impl<const ORDER: usize> BenchAllocator for TrackedHeap<ORDER> {
+    unsafe fn init(&mut self, start: usize, size: usize) {
+        // SAFETY: Our caller promises that the range is valid and unused.
+        unsafe { TrackedHeap::init(self, start, size) }
+    }
+
+    fn alloc(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        TrackedHeap::alloc(self, layout)
+    }
+
+    unsafe fn dealloc(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        // SAFETY: Our caller promises that the pointer came from `alloc` with the same layout.
+        unsafe { TrackedHeap::dealloc(self, ptr, layout) }
+    }
+}
+
+impl BenchAllocator for Tlsf {
+    unsafe fn init(&mut self, start: usize, size: usize) {
+        // SAFETY: Our caller promises that the range is valid and unused.
+        unsafe { Tlsf::init(self, start, size) }
+    }
+
+    fn alloc(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        Tlsf::alloc(self, layout)
+    }
+
+    unsafe fn dealloc(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        // SAFETY: Our caller promises that the pointer came from `alloc` with the same layout.
+        unsafe { Tlsf::dealloc(self, ptr, layout) }
+    }
+}
+
+/// Gives the given empty allocator the given memory, makes a pseudorandom sequence of allocations
+/// and frees with it, and returns how long each allocation and each free took, in counter ticks.
+///
+/// Everything allocated is freed again before returning, so the memory can be reused.
+fn alloc_latencies(
+    allocator: &mut impl BenchAllocator,
+    memory: &mut [u8],
+) -> Result<(Vec<u64>, Vec<u64>), &'static str> {
+    // SAFETY: The memory is borrowed mutably for as long as the allocator is used here, and
+    // everything allocated from it is freed before returning.
+    unsafe { allocator.init(memory.as_mut_ptr() as usize, memory.len()) };
+    let mut prng = Prng::new(ALLOC_BENCH_SEED);
+    let mut live: Vec<(NonNull<u8>, Layout)> = Vec::with_capacity(ALLOC_BENCH_MAX_LIVE);
+    let mut alloc_ticks = Vec::with_capacity(ALLOC_BENCH_OPERATIONS);
+    let mut free_ticks = Vec::with_capacity(ALLOC_BENCH_OPERATIONS);
+    let mut result = Ok(());
+    for _ in 0..ALLOC_BENCH_OPERATIONS {
+        if live.is_empty() || (live.len() < ALLOC_BENCH_MAX_LIVE && prng.below(2) == 0) {
+            let size = prng.below(ALLOC_BENCH_MAX_SIZE) as usize + 1;
+            let layout = Layout::from_size_align(size, 8).unwrap();
+            let start = counter();
+            let ptr = allocator.alloc(layout);
+            alloc_ticks.push(counter() - start);
+            let Some(ptr) = ptr else {
+                result = Err("Scratch heap exhausted");
+                break;
+            };
+            live.push((ptr, layout));
+        } else {
+            let (ptr, layout) = live.swap_remove(prng.below(live.len() as u64) as usize);
+            let start = counter();
+            // SAFETY: The pointer was allocated from this allocator with this layout, and was
+            // removed from `live` so won't be freed again.
+            unsafe { allocator.dealloc(ptr, layout) };
+            free_ticks.push(counter() - start);
+        }
+    }
+    for (ptr, layout) in live {
+        // SAFETY: The pointer was allocated from this allocator with this layout, and not yet freed.
+        unsafe { allocator.dealloc(ptr, layout) };
+    }
+    result.map(|()| (alloc_ticks, free_ticks))
+}
@@ -0,0 +1,146 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Commands to inspect, configure and flush the block device caches, and to protect block devices
+//! from writes.
+
+use crate::apps::command::{Args, CommandError, Context, ErrorContext};
+use alloc::{format, string::String};
+use embedded_io::Write;
+use osdemo_core::{
+    block::BlockDevice,
+    device_id::{DeviceId, DeviceKind},
+};
+
+pub fn blkstat(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let Context {
+        console, devices, ..
+    } = context;
+    let index = args.optional_device(DeviceKind::Block, devices.block.len())?;
+    args.finish()?;
+    if devices.block.is_empty() {
+        writeln!(console, "No block devices").unwrap();
+    }
+    for (number, device) in devices.block.iter().enumerate() {
+        if index.is_some_and(|index| index != number) {
+            continue;
+        }
+        let cache = device.cache();
+        writeln!(
+            console,
+            "{}: {}/{} blocks of {} bytes cached, {} dirty, {}",
+            DeviceId {
+                kind: DeviceKind::Block,
+                number
+            },
+            cache.len(),
+            cache.capacity(),
+            device.block_size(),
+            cache.dirty_blocks().len(),
+            if device.write_back() {
+                "write-back"
+            } else {
+                "write-through"
+            }
+        )
+        .unwrap();
+        writeln!(console, "  {}", cache.stats()).unwrap();
+        writeln!(console, "  {}", device.io_stats()).unwrap();
+        if let Some(overlay) = device.overlay() {
+            writeln!(console, "  overlay with {} blocks written", overlay.len()).unwrap();
+        } else if device.write_protected() {
+            writeln!(console, "  write-protected").unwrap();
+        }
+    }
+    Ok(())
+}
+
+pub fn blk(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let Context {
+        console, devices, ..
+    } = context;
+    let subcommand = args.required_str("subcommand")?;
+    let index = args.next_device(DeviceKind::Block, devices.block.len())?;
+    let id = DeviceId {
+        kind: DeviceKind::Block,
+        number: index,
+    };
+    let device = &mut devices.block[index];
+    match subcommand {
+        "ro" => {
+            let write_protected = match args.next() {
+                None => !device.write_protected(),
+                Some("on") => true,
+                Some("off") => false,
+                Some(_) => return Err(CommandError::Usage),
+            };
+            args.finish()?;
+            device
+                .set_write_protected(write_protected)
+                .context("writing back dirty blocks")?;
+            writeln!(
+                console,
+                "{id} is now {}",
+                if write_protected {
+                    "write-protected"
+                } else {
+                    "writable"
+                }
+            )
+            .unwrap();
+        }
+        "overlay" => match args.next() {
+            None | Some("on") => {
+                args.finish()?;
+                device
+                    .enable_overlay()
+                    .context("writing back dirty blocks")?;
+                writeln!(console, "Writes to {id} now go to an overlay in memory").unwrap();
+            }
+            Some("discard") => {
+                args.finish()?;
+                let overlay = device
+                    .discard_overlay()
+                    .ok_or_else(|| CommandError::Failed(format!("{id} has no overlay")))?;
+                writeln!(
+                    console,
+                    "Discarded {} blocks written to {id}",
+                    overlay.len()
+                )
+                .unwrap();
+            }
+            Some(_) => return Err(CommandError::Usage),
+        },
+        _ => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+pub fn blkcache(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let Context { devices, .. } = context;
+    let index = args.next_device(DeviceKind::Block, devices.block.len())?;
+    let blocks = args.required("blocks")?;
+    let write_back = match args.optional::<String>("mode")?.as_deref() {
+        None | Some("writethrough") => false,
+        Some("writeback") => true,
+        Some(_) => return Err(CommandError::Usage),
+    };
+    args.finish()?;
+    devices.block[index]
+        .configure(blocks, write_back)
+        .context("writing back dirty blocks")
+}
+
+pub fn sync(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let Context { devices, .. } = context;
+    let index = args.optional_device(DeviceKind::Block, devices.block.len())?;
+    args.finish()?;
+    for (number, device) in devices.block.iter_mut().enumerate() {
+        if index.is_some_and(|index| index != number) {
+            continue;
+        }
+        device.sync().context(format_args!("syncing blk{number}"))?;
+    }
+    Ok(())
+}
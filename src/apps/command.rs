@@ -0,0 +1,111 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Framework for shell commands.
+
+use crate::{apps::session::SessionManager, console::Console, platform::ConsoleImpl};
+use alloc::string::String;
+use core::{
+    future::poll_fn,
+    task::{self, Poll},
+};
+use dtoolkit::fdt::Fdt;
+use embedded_io::{ErrorType, Write};
+pub use osdemo::args::{Args, CommandError, ErrorContext};
+use osdemo_core::{devices::Devices, pci::PciRootComplex};
+
+/// The error type of the console UART, which all terminals use.
+pub type ConsoleError = <ConsoleImpl as ErrorType>::Error;
+
+/// Somewhere a shell session reads commands from and writes their output to.
+///
+/// All terminals use the console UART's error type, so that commands can use any of them through
+/// `dyn Terminal`.
+pub trait Terminal: Write<Error = ConsoleError> {
+    /// Reads some bytes of input for a command if any are available, or else registers to be woken
+    /// when there may be some.
+    fn poll_read(
+        &mut self,
+        context: &mut task::Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, ConsoleError>>;
+}
+
+impl dyn Terminal + '_ {
+    /// Reads some bytes of input for a command, completing once at least one byte is available.
+    pub async fn read_async(&mut self, buf: &mut [u8]) -> Result<usize, ConsoleError> {
+        poll_fn(|context| self.poll_read(context, buf)).await
+    }
+}
+
+impl Terminal for Console<ConsoleImpl> {
+    fn poll_read(
+        &mut self,
+        context: &mut task::Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, ConsoleError>> {
+        Console::poll_read(self, context, buf)
+    }
+}
+
+/// Everything a command may need access to.
+pub struct Context<'a> {
+    /// The terminal of the session which the command was entered in.
+    pub console: &'a mut dyn Terminal,
+    pub pci_roots: &'a mut [PciRootComplex],
+    pub devices: &'a mut Devices,
+    pub fdt: &'a Fdt<'static>,
+    /// The format of the shell prompt, as described by `prompt::write_prompt`.
+    pub prompt: String,
+    /// Set by a command to ask the shell to exit with the given status once it returns.
+    pub exit: Option<u32>,
+    /// Whether to report the resources used by each command after it runs, as set by
+    /// `set RUSAGE=1`.
+    pub rusage: bool,
+    /// The shell sessions on all terminals.
+    pub sessions: &'a mut SessionManager,
+}
+
+/// A shell command.
+pub trait Command: Sync {
+    /// The name used to invoke the command.
+    fn name(&self) -> &'static str;
+
+    /// A one-line description of what the command does, for `help`.
+    fn summary(&self) -> &'static str;
+
+    /// A description of the arguments the command takes, such as `<cpu_index> <arg>`.
+    fn usage(&self) -> &'static str {
+        ""
+    }
+
+    /// Runs the command with the given arguments.
+    fn run(&self, context: &mut Context, args: Args) -> Result<(), CommandError>;
+}
+
+/// A command implemented by a plain function.
+pub struct FnCommand {
+    pub name: &'static str,
+    pub summary: &'static str,
+    pub usage: &'static str,
+    pub run: fn(&mut Context, Args) -> Result<(), CommandError>,
+}
+
+impl Command for FnCommand {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn summary(&self) -> &'static str {
+        self.summary
+    }
+
+    fn usage(&self) -> &'static str {
+        self.usage
+    }
+
+    fn run(&self, context: &mut Context, args: Args) -> Result<(), CommandError> {
+        (self.run)(context, args)
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Streaming memory to the host over vsock, for offline analysis.
+
+use crate::apps::{
+    command::{Args, CommandError, Context},
+    cp::{Sink, VsockStream},
+};
+use alloc::{format, vec::Vec};
+use core::ptr;
+use dtoolkit::standard::NodeStandard;
+use embedded_io::Write;
+use osdemo::coredump::{Adler32, Region, file_header, trailer};
+use osdemo_core::{devices::Devices, fallible::try_vec};
+use virtio_drivers::device::socket::VsockAddr;
+
+/// The local port used for the vsock connection.
+const COREDUMP_PORT: u32 = 46;
+
+/// The most bytes read from memory and sent at once.
+const CHUNK_SIZE: usize = 4096;
+
+unsafe extern "C" {
+    /// The start of the image, defined by the `aarch64-rt` linker script.
+    static text_begin: u8;
+    /// The end of the zero-initialised data, including the heap, defined by the `aarch64-rt` linker
+    /// script.
+    static bss_end: u8;
+}
+
+/// Streams the given regions of memory, or the whole image including the heap if none are given,
+/// to a vsock peer in the format described in `osdemo::coredump`.
+pub fn coredump(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let cid = args.next_cid()?;
+    let port = args.next_port()?;
+    let mut regions = args
+        .map(|region| {
+            Region::parse(region).ok_or_else(|| CommandError::InvalidArgument {
+                name: "region",
+                value: region.into(),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let Context {
+        console,
+        devices,
+        fdt,
+        ..
+    } = context;
+
+    if regions.is_empty() {
+        let start = &raw const text_begin as u64;
+        let end = &raw const bss_end as u64;
+        regions.push(Region {
+            address: start,
+            length: end - start,
+        });
+    }
+    // Only allow normal memory, as reading unmapped addresses or device registers could fault or
+    // have side effects.
+    let memory = fdt
+        .memory()
+        .ok()
+        .and_then(|memory| memory.reg().ok().flatten())
+        .map(|reg| {
+            reg.filter_map(|reg| {
+                Some(Region {
+                    address: reg.address::<u64>().ok()?,
+                    length: reg.size::<u64>().ok()?,
+                })
+            })
+            .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    if let Some(region) = regions
+        .iter()
+        .find(|region| !memory.iter().any(|memory| region.is_within(memory)))
+    {
+        return Err(CommandError::Failed(format!(
+            "Region {:#x}+{:#x} isn't within a memory node of the device tree",
+            region.address, region.length
+        )));
+    }
+
+    let total: u64 = regions.iter().map(|region| region.length).sum();
+    writeln!(
+        console,
+        "Dumping {} regions, {total} bytes, to {cid}:{port}...",
+        regions.len()
+    )
+    .unwrap();
+    let mut stream = VsockStream::connect(devices, VsockAddr { cid, port }, COREDUMP_PORT)?;
+    let result = send_regions(&mut stream, devices, &regions);
+    // Close the connection even if sending failed, so the peer isn't left waiting.
+    let closed = stream.close(devices);
+    let checksum = result?;
+    closed?;
+    writeln!(console, "Done, Adler-32 checksum {checksum:#010x}").unwrap();
+    Ok(())
+}
+
+/// Sends the file header, the given regions and the trailer to the given sink, and returns the
+/// checksum of the regions' contents.
+fn send_regions(
+    sink: &mut dyn Sink,
+    devices: &mut Devices,
+    regions: &[Region],
+) -> Result<u32, CommandError> {
+    sink.write(devices, &file_header(regions.len() as u32))?;
+    let mut checksum = Adler32::default();
+    let mut buffer = try_vec(0, CHUNK_SIZE)?;
+    for region in regions {
+        sink.write(devices, &region.header())?;
+        let mut address = region.address;
+        let end = region.end().unwrap();
+        while address < end {
+            let length = (end - address).min(CHUNK_SIZE as u64) as usize;
+            let chunk = &mut buffer[..length];
+            for (i, byte) in chunk.iter_mut().enumerate() {
+                // SAFETY: The region is within normal memory from the device tree, which is all
+                // identity-mapped. Other code may be writing to it concurrently, so read it with
+                // volatile reads rather than through a reference.
+                *byte = unsafe { ptr::read_volatile((address as usize + i) as *const u8) };
+            }
+            checksum.update(chunk);
+            sink.write(devices, chunk)?;
+            address += length as u64;
+        }
+    }
+    let checksum = checksum.finish();
+    sink.write(devices, &trailer(checksum))?;
+    Ok(checksum)
+}
@@ -0,0 +1,491 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Copying data between block device ranges and vsock connections, with progress reporting.
+
+use crate::apps::command::{Args, CommandError, Context, ErrorContext};
+use alloc::{boxed::Box, format, vec::Vec};
+use core::time::Duration;
+use embedded_io::Write;
+use osdemo::endpoint::Endpoint;
+use osdemo_core::{
+    block::BlockDevice,
+    device_id::{DeviceId, DeviceKind},
+    device_owners::DeviceClaim,
+    devices::Devices,
+    drivers::generic_timer::timeout,
+    executor::block_on,
+    fallible::try_vec,
+    virtio::next_vsock_event,
+};
+use virtio_drivers::{
+    Error as VirtioError,
+    device::socket::{SocketError, VsockAddr, VsockEventType},
+};
+
+/// The most bytes copied at once.
+const CHUNK_SIZE: usize = 4096;
+
+/// How many bytes to copy between progress reports.
+const PROGRESS_INTERVAL: u64 = 64 * 1024;
+
+/// The local port used for a vsock source.
+const VSOCK_SOURCE_PORT: u32 = 44;
+
+/// The local port used for a vsock destination.
+const VSOCK_DESTINATION_PORT: u32 = 45;
+
+/// How long to wait for a vsock peer to connect, send data or accept more, before giving up.
+const VSOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The vsock device which streams use.
+pub(super) const VSOCK0: DeviceId = DeviceId {
+    kind: DeviceKind::Vsock,
+    number: 0,
+};
+
+/// The owner of the vsock device while a stream is open.
+const VSOCK_STREAM_OWNER: &str = "vsock stream";
+
+/// Somewhere to copy data from.
+trait Source {
+    /// Returns the total number of bytes which will be read, if known in advance.
+    fn len(&self) -> Option<u64>;
+
+    /// Reads up to `buf.len()` bytes into `buf`, returning how many were read, or 0 once there are
+    /// no more.
+    fn read(&mut self, devices: &mut Devices, buf: &mut [u8]) -> Result<usize, CommandError>;
+
+    /// Cleans up once everything has been read, or copying failed.
+    fn close(&mut self, _devices: &mut Devices) {}
+}
+
+/// Somewhere to copy data to.
+pub(super) trait Sink {
+    /// Writes all of the given data.
+    fn write(&mut self, devices: &mut Devices, data: &[u8]) -> Result<(), CommandError>;
+
+    /// Finishes writing, once all the data has been written or copying failed.
+    fn close(&mut self, _devices: &mut Devices) -> Result<(), CommandError> {
+        Ok(())
+    }
+}
+
+/// Copies from one endpoint to another: a block device range such as `blk0:4096+512`, or a vsock
+/// connection such as `vsock:2:9000`.
+pub fn cp(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let source = endpoint(args.required_str("source")?, "source")?;
+    let destination = endpoint(args.required_str("destination")?, "destination")?;
+    args.finish()?;
+    let Context {
+        console, devices, ..
+    } = context;
+
+    let mut source = open_source(devices, source)?;
+    let mut sink = match open_sink(devices, destination) {
+        Ok(sink) => sink,
+        Err(e) => {
+            source.close(devices);
+            return Err(e);
+        }
+    };
+    let total = source.len();
+    let mut last_report = 0;
+    let result = copy(devices, &mut *source, &mut *sink, |copied| {
+        if copied - last_report >= PROGRESS_INTERVAL {
+            last_report = copied;
+            write_progress(&mut **console, copied, total);
+        }
+    });
+    source.close(devices);
+    let closed = sink.close(devices);
+    let copied = result?;
+    closed?;
+    write_progress(&mut **console, copied, total);
+    writeln!(console).unwrap();
+    Ok(())
+}
+
+/// Parses the given endpoint argument.
+pub(super) fn endpoint<'a>(
+    value: &'a str,
+    name: &'static str,
+) -> Result<Endpoint<'a>, CommandError> {
+    let endpoint = Endpoint::parse(value).context(format_args!("parsing {name}"))?;
+    if let Endpoint::Path(path) = endpoint {
+        return Err(CommandError::Failed(format!(
+            "Can't copy {path}, as there are no filesystems to find it in"
+        )));
+    }
+    Ok(endpoint)
+}
+
+/// Writes a progress report over the current line.
+fn write_progress(console: &mut (impl Write + ?Sized), copied: u64, total: Option<u64>) {
+    match total {
+        Some(total) if total > 0 => write!(
+            console,
+            "\r{copied} / {total} bytes ({}%)",
+            copied * 100 / total
+        ),
+        _ => write!(console, "\r{copied} bytes"),
+    }
+    .unwrap();
+}
+
+/// Copies everything from `source` to `sink` in chunks, calling `progress` with the number of bytes
+/// copied so far after each, and returns the total.
+fn copy(
+    devices: &mut Devices,
+    source: &mut dyn Source,
+    sink: &mut dyn Sink,
+    mut progress: impl FnMut(u64),
+) -> Result<u64, CommandError> {
+    let mut buffer = try_vec(0, CHUNK_SIZE)?;
+    let mut copied = 0;
+    loop {
+        let length = source.read(devices, &mut buffer)?;
+        if length == 0 {
+            return Ok(copied);
+        }
+        sink.write(devices, &buffer[..length])?;
+        copied += length as u64;
+        progress(copied);
+    }
+}
+
+/// Reads everything from the given block device range or vsock peer, up to `max_size` bytes. Any
+/// more is ignored.
+pub(super) fn read_endpoint(
+    devices: &mut Devices,
+    endpoint: Endpoint,
+    max_size: usize,
+) -> Result<Vec<u8>, CommandError> {
+    let mut source = open_source(devices, endpoint)?;
+    let mut data = try_vec(0, max_size)?;
+    let mut length = 0;
+    let result = loop {
+        if length == max_size {
+            break Ok(());
+        }
+        match source.read(devices, &mut data[length..]) {
+            Ok(0) => break Ok(()),
+            Ok(read) => length += read,
+            Err(e) => break Err(e),
+        }
+    };
+    source.close(devices);
+    result?;
+    data.truncate(length);
+    Ok(data)
+}
+
+fn open_source(devices: &mut Devices, endpoint: Endpoint) -> Result<Box<dyn Source>, CommandError> {
+    match endpoint {
+        Endpoint::Block {
+            device,
+            offset,
+            length,
+        } => Ok(Box::new(BlockRange::open(devices, device, offset, length)?)),
+        Endpoint::Vsock { cid, port } => Ok(Box::new(VsockStream::connect(
+            devices,
+            VsockAddr { cid, port },
+            VSOCK_SOURCE_PORT,
+        )?)),
+        Endpoint::Path(_) => unreachable!(),
+    }
+}
+
+pub(super) fn open_sink(
+    devices: &mut Devices,
+    endpoint: Endpoint,
+) -> Result<Box<dyn Sink>, CommandError> {
+    match endpoint {
+        Endpoint::Block {
+            device,
+            offset,
+            length,
+        } => {
+            let range = BlockRange::open(devices, device, offset, length)?;
+            if devices.block[device].readonly() {
+                return Err(CommandError::Failed(format!("blk{device} is read-only")));
+            }
+            Ok(Box::new(range))
+        }
+        Endpoint::Vsock { cid, port } => Ok(Box::new(VsockStream::connect(
+            devices,
+            VsockAddr { cid, port },
+            VSOCK_DESTINATION_PORT,
+        )?)),
+        Endpoint::Path(_) => unreachable!(),
+    }
+}
+
+/// A range of bytes on a block device, read or written sequentially.
+struct BlockRange {
+    device: usize,
+    block_size: u64,
+    /// The byte offset of the next read or write.
+    position: u64,
+    /// The byte offset of the end of the range.
+    end: u64,
+}
+
+impl BlockRange {
+    /// Checks that the given range fits on the given device.
+    fn open(
+        devices: &Devices,
+        device: usize,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<Self, CommandError> {
+        let block = devices
+            .block
+            .get(device)
+            .ok_or(CommandError::NoSuchDevice {
+                kind: "block",
+                index: device,
+            })?;
+        let block_size = block.block_size() as u64;
+        let capacity = block.capacity() * block_size;
+        let end = match length {
+            Some(length) => offset.checked_add(length),
+            None => Some(capacity),
+        };
+        match end {
+            Some(end) if offset <= end && end <= capacity => Ok(Self {
+                device,
+                block_size,
+                position: offset,
+                end,
+            }),
+            _ => Err(CommandError::Failed(format!(
+                "Range is past the end of blk{device}, which has {capacity} bytes"
+            ))),
+        }
+    }
+}
+
+impl Source for BlockRange {
+    fn len(&self) -> Option<u64> {
+        Some(self.end - self.position)
+    }
+
+    fn read(&mut self, devices: &mut Devices, buf: &mut [u8]) -> Result<usize, CommandError> {
+        let length = (buf.len() as u64).min(self.end - self.position) as usize;
+        if length == 0 {
+            return Ok(0);
+        }
+        // Read whole blocks covering the range, and copy out the part we want.
+        let first_block = self.position / self.block_size;
+        let within = (self.position % self.block_size) as usize;
+        let blocks = (within + length).div_ceil(self.block_size as usize);
+        let mut blocks_buffer = try_vec(0, blocks * self.block_size as usize)?;
+        devices.block[self.device]
+            .read_blocks(first_block, &mut blocks_buffer)
+            .context(format_args!("reading blk{}", self.device))?;
+        buf[..length].copy_from_slice(&blocks_buffer[within..within + length]);
+        self.position += length as u64;
+        Ok(length)
+    }
+}
+
+impl Sink for BlockRange {
+    fn write(&mut self, devices: &mut Devices, mut data: &[u8]) -> Result<(), CommandError> {
+        if data.len() as u64 > self.end - self.position {
+            return Err(CommandError::Failed(format!(
+                "Source is longer than the destination range on blk{}",
+                self.device
+            )));
+        }
+        let device = &mut devices.block[self.device];
+        let block_size = self.block_size as usize;
+        while !data.is_empty() {
+            let block = self.position / self.block_size;
+            let within = (self.position % self.block_size) as usize;
+            let length = if within == 0 && data.len() >= block_size {
+                // Write as many whole blocks as possible at once.
+                let length = data.len() - data.len() % block_size;
+                device
+                    .write_blocks(block, &data[..length])
+                    .context(format_args!("writing blk{}", self.device))?;
+                length
+            } else {
+                // Read, modify and write back a partial block.
+                let length = data.len().min(block_size - within);
+                let mut block_buffer = try_vec(0, block_size)?;
+                device
+                    .read_blocks(block, &mut block_buffer)
+                    .context(format_args!("reading blk{}", self.device))?;
+                block_buffer[within..within + length].copy_from_slice(&data[..length]);
+                device
+                    .write_blocks(block, &block_buffer)
+                    .context(format_args!("writing blk{}", self.device))?;
+                length
+            };
+            data = &data[length..];
+            self.position += length as u64;
+        }
+        Ok(())
+    }
+}
+
+/// A stream connection to a vsock peer on the first vsock device.
+pub(super) struct VsockStream {
+    peer: VsockAddr,
+    local_port: u32,
+    /// Whether the peer has closed the connection.
+    closed: bool,
+    /// The claim on the vsock device, until the stream is closed.
+    claim: Option<DeviceClaim>,
+}
+
+impl VsockStream {
+    /// Connects to the given peer and waits for it to accept the connection.
+    pub(super) fn connect(
+        devices: &mut Devices,
+        peer: VsockAddr,
+        local_port: u32,
+    ) -> Result<Self, CommandError> {
+        let vsock = devices
+            .vsock
+            .first_mut()
+            .ok_or(CommandError::NoSuchDevice {
+                kind: "vsock",
+                index: 0,
+            })?;
+        let claim = devices.owners.claim(VSOCK0, VSOCK_STREAM_OWNER)?;
+        if let Err(e) = vsock.connect(peer, local_port) {
+            devices.owners.release(claim);
+            return Err(e).context("connecting");
+        }
+        let mut stream = Self {
+            peer,
+            local_port,
+            closed: false,
+            claim: Some(claim),
+        };
+        match stream.next_event(devices) {
+            Ok(VsockEventType::Connected) => Ok(stream),
+            Ok(event) => {
+                stream.close_connection(devices);
+                Err(CommandError::Failed(format!(
+                    "Connecting to {}:{} failed: {event:?}",
+                    peer.cid, peer.port
+                )))
+            }
+            Err(e) => {
+                stream.close_connection(devices);
+                Err(e)
+            }
+        }
+    }
+
+    /// Waits for the next event for this connection, ignoring any for others.
+    fn next_event(&mut self, devices: &mut Devices) -> Result<VsockEventType, CommandError> {
+        let vsock = devices.vsock.first_mut().unwrap();
+        let (peer, local_port) = (self.peer, self.local_port);
+        let event_type = block_on(timeout(VSOCK_TIMEOUT, async {
+            loop {
+                let event = next_vsock_event(vsock).await?;
+                if event.source == peer && event.destination.port == local_port {
+                    return Ok::<_, VirtioError>(event.event_type);
+                }
+            }
+        }))
+        .map_err(|_| {
+            CommandError::Failed(format!(
+                "vsock peer {}:{} didn't respond within {} s",
+                peer.cid,
+                peer.port,
+                VSOCK_TIMEOUT.as_secs()
+            ))
+        })?
+        .context("polling vsock")?;
+        if matches!(event_type, VsockEventType::Disconnected { .. }) {
+            self.closed = true;
+        }
+        Ok(event_type)
+    }
+
+    /// Closes the connection if the peer hasn't already, and releases the vsock device.
+    fn close_connection(&mut self, devices: &mut Devices) {
+        if !self.closed
+            && let Some(vsock) = devices.vsock.first_mut()
+        {
+            let _ = vsock.force_close(self.peer, self.local_port);
+            self.closed = true;
+        }
+        self.release(devices);
+    }
+
+    /// Releases the claim on the vsock device, if it hasn't been already.
+    fn release(&mut self, devices: &mut Devices) {
+        if let Some(claim) = self.claim.take() {
+            devices.owners.release(claim);
+        }
+    }
+}
+
+impl Source for VsockStream {
+    fn len(&self) -> Option<u64> {
+        None
+    }
+
+    fn read(&mut self, devices: &mut Devices, buf: &mut [u8]) -> Result<usize, CommandError> {
+        loop {
+            let vsock = devices.vsock.first_mut().unwrap();
+            if vsock
+                .recv_buffer_available_bytes(self.peer, self.local_port)
+                .unwrap_or(0)
+                > 0
+            {
+                return vsock
+                    .recv(self.peer, self.local_port, buf)
+                    .context("receiving");
+            }
+            if self.closed {
+                return Ok(0);
+            }
+            self.next_event(devices)?;
+        }
+    }
+
+    fn close(&mut self, devices: &mut Devices) {
+        self.close_connection(devices);
+    }
+}
+
+impl Sink for VsockStream {
+    fn write(&mut self, devices: &mut Devices, data: &[u8]) -> Result<(), CommandError> {
+        loop {
+            if self.closed {
+                return Err("vsock peer closed the connection".into());
+            }
+            let vsock = devices.vsock.first_mut().unwrap();
+            match vsock.send(self.peer, self.local_port, data) {
+                Ok(()) => return Ok(()),
+                Err(VirtioError::SocketDeviceError(SocketError::InsufficientBufferSpaceInPeer)) => {
+                    // Wait for the peer to give us more credit.
+                    self.next_event(devices)?;
+                }
+                Err(e) => return Err(e).context("sending"),
+            }
+        }
+    }
+
+    fn close(&mut self, devices: &mut Devices) -> Result<(), CommandError> {
+        let result = if self.closed {
+            Ok(())
+        } else {
+            let vsock = devices.vsock.first_mut().unwrap();
+            self.closed = true;
+            vsock
+                .shutdown(self.peer, self.local_port)
+                .context("shutting down")
+        };
+        self.release(devices);
+        result
+    }
+}
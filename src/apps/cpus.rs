@@ -2,92 +2,222 @@
 // This project is dual-licensed under Apache 2.0 and MIT terms.
 // See LICENSE-APACHE and LICENSE-MIT for details.
 
-use crate::{
-    cpus::{MPIDR_AFFINITY_MASK, current_cpu_index},
-    interrupts::{GIC, remove_private_irq_handler, set_private_irq_handler},
-    secondary_entry::start_core_with_stack,
-    smc_for_psci,
-};
-use arm_gic::{
-    IntId,
-    gicv3::{GicCpuInterface, SgiTarget, SgiTargetGroup},
-    irq_enable, wfi,
-};
+use crate::apps::command::{Args, CommandError, Context, ErrorContext};
+#[cfg(feature = "smp")]
+use alloc::{format, string::ToString};
+#[cfg(feature = "smp")]
+use arm_gic::{IntId, irq_disable, irq_enable};
 use arm_sysregs::{MpidrEl1, read_mpidr_el1};
+#[cfg(feature = "smp")]
+use core::{
+    sync::atomic::{AtomicU8, AtomicU64, Ordering},
+    time::Duration,
+};
 use dtoolkit::ToCellInt;
+#[cfg(feature = "smp")]
 use dtoolkit::fdt::Fdt;
 use embedded_io::Write;
+#[cfg(feature = "smp")]
 use log::{error, info};
+use osdemo_core::{cpus::MPIDR_AFFINITY_MASK, psci::smc_for_psci};
+#[cfg(feature = "smp")]
+use osdemo_core::{
+    cpus::{cpu_count, current_cpu_index},
+    drivers::generic_timer::{Instant, busy_wait, sleep},
+    interrupts::{
+        end_interrupt, remove_private_irq_handler, send_sgi_to_all, set_private_irq_handler,
+        with_gic,
+    },
+    secondary_entry::{free_secondary_stack, start_core_with_stack},
+    timer::{counter, counter_frequency, wait_until},
+    workqueue::{self, WORK_SGI, queue_work, run_on_cpu},
+};
+#[cfg(feature = "smp")]
+use smccc::psci::AffinityState;
 use smccc::{
     Hvc, Smc,
-    psci::{self, AffinityState, LowestAffinityLevel},
+    psci::{self, LowestAffinityLevel},
 };
 
-pub fn start_cpu<'a>(console: &mut impl Write, fdt: &Fdt, mut args: impl Iterator<Item = &'a str>) {
-    let Some(cpu_index) = args.next() else {
-        writeln!(console, "Usage:").unwrap();
-        writeln!(console, "  start_cpu <cpu_index> <arg>").unwrap();
-        return;
-    };
-    let Ok(cpu_index) = cpu_index.parse() else {
-        writeln!(console, "Invalid cpu_index").unwrap();
-        return;
-    };
-    let Some(arg) = args.next() else {
-        writeln!(console, "Usage:").unwrap();
-        writeln!(console, "  start_cpu <cpu_index> <arg>").unwrap();
-        return;
-    };
-    let Ok(arg) = arg.parse() else {
-        writeln!(console, "Invalid arg").unwrap();
-        return;
-    };
-
-    let Some(cpu) = fdt.cpus().unwrap().cpus().nth(cpu_index) else {
-        writeln!(console, "cpu_index out of bounds").unwrap();
-        return;
-    };
-
-    let id = cpu.ids().unwrap().next().unwrap().to_int::<u64>().unwrap();
+/// The SGI sent to secondary cores to ask them to stop.
+#[cfg(feature = "smp")]
+const STOP_SGI: u32 = 15;
+
+/// How long to wait for a secondary core to turn off after asking it to stop.
+#[cfg(feature = "smp")]
+const STOP_TIMEOUT_MS: u64 = 1000;
+
+/// How long to wait for a secondary core to run work sent to it by `ipi`.
+#[cfg(feature = "smp")]
+const IPI_TIMEOUT_MS: u64 = 1000;
+
+/// The period over which idle injection forces a core to be idle for the requested share of time.
+#[cfg(feature = "smp")]
+const IDLE_INJECT_PERIOD: Duration = Duration::from_millis(10);
+
+/// The value of `IDLE_INJECT_PERCENT` for a core which isn't running the idle injection loop.
+#[cfg(feature = "smp")]
+const NOT_INJECTING: u8 = u8::MAX;
+
+/// A bit for each secondary core started by `start_cpu` which has been asked to stop, by CPU index.
+#[cfg(feature = "smp")]
+static STOP_REQUESTED: AtomicU64 = AtomicU64::new(0);
+
+/// A bit for each secondary core started by `start_cpu` which is running work from its queue, by
+/// CPU index.
+#[cfg(feature = "smp")]
+static WORKERS: AtomicU64 = AtomicU64::new(0);
+
+/// The percentage of each period that each core is forced to be idle for, by CPU index, or
+/// `NOT_INJECTING` if it isn't running the idle injection loop.
+///
+/// Setting this to 0 asks the loop to stop, after which it sets it back to `NOT_INJECTING`.
+#[cfg(feature = "smp")]
+static IDLE_INJECT_PERCENT: [AtomicU8; u64::BITS as usize] =
+    [const { AtomicU8::new(NOT_INJECTING) }; u64::BITS as usize];
+
+#[cfg(feature = "smp")]
+pub fn start_cpu(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let cpu_index = args.required("cpu_index")?;
+    let arg = args.required("arg")?;
+    args.finish()?;
+    let console = &mut *context.console;
+
+    let id = cpu_id(context.fdt, cpu_index)?;
     writeln!(console, "CPU {cpu_index}: ID {id:#012x}").unwrap();
-    let state = if smc_for_psci() {
-        psci::affinity_info::<Smc>(id, LowestAffinityLevel::All)
-    } else {
-        psci::affinity_info::<Hvc>(id, LowestAffinityLevel::All)
-    }
-    .unwrap();
+    let state = affinity_state(id).context("getting CPU affinity state")?;
     if state == AffinityState::Off {
         let result = start_core_with_stack(id, move || secondary_entry(arg));
         writeln!(console, " => {result:?}").unwrap();
     } else {
         writeln!(console, " already {state:?}").unwrap();
     }
+    Ok(())
 }
 
+#[cfg(feature = "smp")]
 fn secondary_entry(arg: u64) {
     let cpu = current_cpu_index();
     info!("Secondary CPU {cpu} started with arg {arg}");
-    {
-        let mut gic = GIC.get().unwrap().lock();
+    with_gic(|gic| {
         for i in 0..IntId::SGI_COUNT {
             let sgi = IntId::sgi(i);
             gic.enable_interrupt(sgi, Some(cpu), true).unwrap();
             gic.set_interrupt_priority(sgi, Some(cpu), 0x80).unwrap();
         }
-    }
+    });
     for sgi in 0..IntId::SGI_COUNT {
-        set_private_irq_handler(IntId::sgi(sgi), &secondary_irq_handler);
+        if IntId::sgi(sgi) != WORK_SGI {
+            set_private_irq_handler(IntId::sgi(sgi), &secondary_irq_handler);
+        }
     }
+    workqueue::init_current_cpu();
     irq_enable();
+    WORKERS.fetch_or(1 << cpu, Ordering::SeqCst);
 
-    info!("Waiting for interrupt...");
-    wfi();
-    info!("Finished waiting");
+    info!("Running queued work until stop_cpu...");
+    while STOP_REQUESTED.load(Ordering::SeqCst) & (1 << cpu) == 0 {
+        workqueue::idle();
+    }
+    info!("Secondary CPU {cpu} stopping");
 
+    WORKERS.fetch_and(!(1 << cpu), Ordering::SeqCst);
+    workqueue::stop_accepting_work();
+    // Anything queued before we stopped accepting work should still run.
+    workqueue::run_pending_work();
+    irq_disable();
+    with_gic(|gic| {
+        for i in 0..IntId::SGI_COUNT {
+            gic.enable_interrupt(IntId::sgi(i), Some(cpu), false)
+                .unwrap();
+        }
+    });
     for sgi in 0..IntId::SGI_COUNT {
         remove_private_irq_handler(IntId::sgi(sgi));
     }
+    // Nothing logged with IRQs masked from here on will be written out by this core.
+    log::logger().flush();
+    STOP_REQUESTED.fetch_and(!(1 << cpu), Ordering::SeqCst);
+
+    power_off_current_cpu();
+}
+
+/// Returns a bitmask of the secondary cores started by `start_cpu` which are running work queued
+/// for them with `run_on_cpu`, by CPU index.
+#[cfg(feature = "smp")]
+pub fn worker_cpus() -> u64 {
+    WORKERS.load(Ordering::SeqCst)
+}
 
+/// Asks a secondary core started by `start_cpu` to tear down and turn itself off, waits for it to
+/// do so, and then frees its stack.
+#[cfg(feature = "smp")]
+pub fn stop_cpu(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let cpu_index = args.required("cpu_index")?;
+    args.finish()?;
+    if cpu_index == current_cpu_index() {
+        return Err("Can't stop the current CPU.".into());
+    }
+    let id = cpu_id(context.fdt, cpu_index)?;
+    if cpu_index >= u64::BITS as usize {
+        return Err(CommandError::Failed(format!(
+            "Only the first {} CPUs can be stopped",
+            u64::BITS
+        )));
+    }
+    let state = affinity_state(id).context("getting CPU affinity state")?;
+    if state != AffinityState::On {
+        return Err(CommandError::Failed(format!(
+            "CPU {cpu_index} is {state:?}"
+        )));
+    }
+
+    STOP_REQUESTED.fetch_or(1 << cpu_index, Ordering::SeqCst);
+    send_sgi_to_all(IntId::sgi(STOP_SGI));
+    if !wait_until(STOP_TIMEOUT_MS, || {
+        affinity_state(id).is_ok_and(|state| state == AffinityState::Off)
+    }) {
+        // It may not have been started by `start_cpu`, so don't leave the request for a later core.
+        STOP_REQUESTED.fetch_and(!(1 << cpu_index), Ordering::SeqCst);
+        return Err(CommandError::Failed(format!(
+            "CPU {cpu_index} didn't turn off within {STOP_TIMEOUT_MS} ms"
+        )));
+    }
+    free_secondary_stack(id).context("freeing stack")?;
+    writeln!(context.console, "CPU {cpu_index} stopped").unwrap();
+    Ok(())
+}
+
+/// Returns the MPIDR affinity value of the CPU with the given index in the FDT, if it is one of
+/// the CPUs which can be used.
+#[cfg(feature = "smp")]
+fn cpu_id(fdt: &Fdt, cpu_index: usize) -> Result<u64, CommandError> {
+    let cpu = fdt
+        .cpus()
+        .unwrap()
+        .cpus()
+        .take(cpu_count())
+        .nth(cpu_index)
+        .ok_or(CommandError::NoSuchDevice {
+            kind: "CPU",
+            index: cpu_index,
+        })?;
+    Ok(cpu.ids().unwrap().next().unwrap().to_int::<u64>().unwrap())
+}
+
+/// Returns the PSCI affinity state of the CPU with the given MPIDR affinity value.
+#[cfg(feature = "smp")]
+fn affinity_state(id: u64) -> Result<AffinityState, psci::Error> {
+    if smc_for_psci() {
+        psci::affinity_info::<Smc>(id, LowestAffinityLevel::All)
+    } else {
+        psci::affinity_info::<Hvc>(id, LowestAffinityLevel::All)
+    }
+}
+
+#[cfg(feature = "smp")]
+/// Turns off the current CPU core with PSCI_CPU_OFF.
+pub fn power_off_current_cpu() -> ! {
     if smc_for_psci() {
         psci::cpu_off::<Smc>()
     } else {
@@ -99,14 +229,20 @@ fn secondary_entry(arg: u64) {
     loop {}
 }
 
+#[cfg(feature = "smp")]
 fn secondary_irq_handler(intid: IntId) {
     info!(
         "Secondary CPU {} IRQ handler {intid:?}",
         current_cpu_index()
     );
+    // The core keeps running after the SGI, so it must be acknowledged or no other interrupt of
+    // the same priority would be signalled, including STOP_SGI and WORK_SGI.
+    end_interrupt(intid);
 }
 
-pub fn cpus(console: &mut impl Write, fdt: &Fdt) {
+pub fn cpus(context: &mut Context, args: Args) -> Result<(), CommandError> {
+    args.finish()?;
+    let Context { console, fdt, .. } = context;
     let smc_for_psci = smc_for_psci();
 
     writeln!(
@@ -117,7 +253,7 @@ pub fn cpus(console: &mut impl Write, fdt: &Fdt) {
         } else {
             psci::version::<Hvc>()
         }
-        .unwrap()
+        .context("getting PSCI version")?
     )
     .unwrap();
 
@@ -139,7 +275,7 @@ pub fn cpus(console: &mut impl Write, fdt: &Fdt) {
         } else {
             psci::affinity_info::<Hvc>(current_cpu, LowestAffinityLevel::All)
         }
-        .unwrap(),
+        .context("getting CPU affinity state")?,
     )
     .unwrap();
 
@@ -150,46 +286,168 @@ pub fn cpus(console: &mut impl Write, fdt: &Fdt) {
             writeln!(
                 console,
                 "  affinity state {:?} {:?} {:?} {:?}",
-                psci::affinity_info::<Smc>(id, LowestAffinityLevel::All).unwrap(),
-                psci::affinity_info::<Smc>(id, LowestAffinityLevel::Aff0Ignored).unwrap(),
-                psci::affinity_info::<Smc>(id, LowestAffinityLevel::Aff0Aff1Ignored).unwrap(),
-                psci::affinity_info::<Smc>(id, LowestAffinityLevel::Aff0Aff1Aff2Ignored).unwrap(),
+                psci::affinity_info::<Smc>(id, LowestAffinityLevel::All)
+                    .context("getting CPU affinity state")?,
+                psci::affinity_info::<Smc>(id, LowestAffinityLevel::Aff0Ignored)
+                    .context("getting CPU affinity state")?,
+                psci::affinity_info::<Smc>(id, LowestAffinityLevel::Aff0Aff1Ignored)
+                    .context("getting CPU affinity state")?,
+                psci::affinity_info::<Smc>(id, LowestAffinityLevel::Aff0Aff1Aff2Ignored)
+                    .context("getting CPU affinity state")?,
             )
         } else {
             writeln!(
                 console,
                 "  affinity state {:?} {:?} {:?} {:?}",
-                psci::affinity_info::<Hvc>(id, LowestAffinityLevel::All).unwrap(),
-                psci::affinity_info::<Hvc>(id, LowestAffinityLevel::Aff0Ignored).unwrap(),
-                psci::affinity_info::<Hvc>(id, LowestAffinityLevel::Aff0Aff1Ignored).unwrap(),
-                psci::affinity_info::<Hvc>(id, LowestAffinityLevel::Aff0Aff1Aff2Ignored).unwrap(),
+                psci::affinity_info::<Hvc>(id, LowestAffinityLevel::All)
+                    .context("getting CPU affinity state")?,
+                psci::affinity_info::<Hvc>(id, LowestAffinityLevel::Aff0Ignored)
+                    .context("getting CPU affinity state")?,
+                psci::affinity_info::<Hvc>(id, LowestAffinityLevel::Aff0Aff1Ignored)
+                    .context("getting CPU affinity state")?,
+                psci::affinity_info::<Hvc>(id, LowestAffinityLevel::Aff0Aff1Aff2Ignored)
+                    .context("getting CPU affinity state")?,
             )
         }
         .unwrap();
     }
+    Ok(())
 }
 
-pub fn sgi<'a>(console: &mut impl Write, mut args: impl Iterator<Item = &'a str>) {
-    let Some(id) = args.next() else {
-        writeln!(console, "Usage:").unwrap();
-        writeln!(console, "  sgi <id>").unwrap();
-        return;
-    };
-    let Ok(id) = id.parse() else {
-        writeln!(console, "Invalid id").unwrap();
-        return;
-    };
+/// Runs a function on a secondary core started by `start_cpu`, and reports how long it took for
+/// the core to pick it up after being kicked with an SGI.
+#[cfg(feature = "smp")]
+pub fn ipi(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let cpu_index: usize = args.required("cpu_index")?;
+    args.finish()?;
+    if cpu_index == current_cpu_index() {
+        return Err("Can't send an IPI to the current CPU.".into());
+    }
+    if cpu_index >= u64::BITS as usize || worker_cpus() & (1 << cpu_index) == 0 {
+        return Err(CommandError::Failed(format!(
+            "CPU {cpu_index} wasn't started by start_cpu"
+        )));
+    }
+
+    let sent = counter();
+    let handle =
+        run_on_cpu(cpu_index, || (current_cpu_index(), counter())).context("queueing work")?;
+    let (ran_on, ran_at) = handle.wait(IPI_TIMEOUT_MS).ok_or_else(|| {
+        CommandError::Failed(format!(
+            "CPU {cpu_index} didn't run the work within {IPI_TIMEOUT_MS} ms"
+        ))
+    })?;
+    let latency_us =
+        u128::from(ran_at.saturating_sub(sent)) * 1_000_000 / u128::from(counter_frequency());
+    writeln!(
+        context.console,
+        "Work ran on CPU {ran_on} {latency_us} us after being queued"
+    )
+    .unwrap();
+    Ok(())
+}
+
+/// Starts, changes or stops forcing a secondary core started by `start_cpu` to be idle for the
+/// given percentage of the time.
+#[cfg(feature = "smp")]
+pub fn idle_inject(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let cpu_index: usize = args.required("cpu_index")?;
+    let percent: u8 = args.required("percent")?;
+    args.finish()?;
+    if percent > 100 {
+        return Err(CommandError::InvalidArgument {
+            name: "percent",
+            value: percent.to_string(),
+        });
+    }
+    if cpu_index == current_cpu_index() {
+        return Err("Can't inject idle time on the current CPU.".into());
+    }
+    if cpu_index >= u64::BITS as usize || worker_cpus() & (1 << cpu_index) == 0 {
+        return Err(CommandError::Failed(format!(
+            "CPU {cpu_index} wasn't started by start_cpu"
+        )));
+    }
+
+    let state = &IDLE_INJECT_PERCENT[cpu_index];
+    let previous = state.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |current| {
+        (current != NOT_INJECTING || percent != 0).then_some(percent)
+    });
+    match previous {
+        Err(_) => {
+            writeln!(context.console, "CPU {cpu_index} isn't injecting idle time").unwrap();
+        }
+        Ok(NOT_INJECTING) => {
+            queue_work(cpu_index, move || inject_idle(cpu_index))
+                .inspect_err(|_| state.store(NOT_INJECTING, Ordering::SeqCst))
+                .context("queueing work")?;
+            writeln!(
+                context.console,
+                "Injecting {percent}% idle time on CPU {cpu_index}"
+            )
+            .unwrap();
+        }
+        Ok(_) if percent == 0 => {
+            writeln!(
+                context.console,
+                "Stopping idle injection on CPU {cpu_index}"
+            )
+            .unwrap();
+        }
+        Ok(_) => {
+            writeln!(
+                context.console,
+                "Changed idle injection on CPU {cpu_index} to {percent}%"
+            )
+            .unwrap();
+        }
+    }
+    Ok(())
+}
+
+/// Repeatedly sleeps for the requested share of each `IDLE_INJECT_PERIOD`, and runs queued work
+/// and then spins for the rest of it, until idle injection is stopped or the core is asked to stop.
+///
+/// This runs as work on the core's queue, so work queued for it in the meantime only runs during
+/// the busy part of each period. As nothing is preempted, a long-running item of work delays the
+/// next idle part until it finishes.
+#[cfg(feature = "smp")]
+fn inject_idle(cpu: usize) {
+    let state = &IDLE_INJECT_PERCENT[cpu];
+    info!("Starting idle injection on CPU {cpu}");
+    loop {
+        let percent = state.load(Ordering::SeqCst);
+        if percent == 0 || STOP_REQUESTED.load(Ordering::SeqCst) & (1 << cpu) != 0 {
+            // Only stop if the percentage hasn't been changed again in the meantime.
+            if state
+                .compare_exchange(percent, NOT_INJECTING, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                break;
+            }
+            continue;
+        }
+        let period_end = Instant::now() + IDLE_INJECT_PERIOD;
+        sleep(IDLE_INJECT_PERIOD * u32::from(percent) / 100);
+        workqueue::run_pending_work();
+        busy_wait(period_end.duration_since(Instant::now()));
+    }
+    info!("Stopped idle injection on CPU {cpu}");
+}
+
+#[cfg(feature = "smp")]
+pub fn sgi(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let id = args.required("id")?;
+    args.finish()?;
     if id >= IntId::SGI_COUNT {
-        writeln!(
-            console,
+        return Err(CommandError::Failed(format!(
             "Invalid SGI, must be less than {}",
             IntId::SGI_COUNT
-        )
-        .unwrap();
-        return;
+        )));
     }
 
     let intid = IntId::sgi(id);
-    writeln!(console, "Sending {intid:?} to all CPUs").unwrap();
-    GicCpuInterface::send_sgi(intid, SgiTarget::All, SgiTargetGroup::CurrentGroup1).unwrap();
+    writeln!(context.console, "Sending {intid:?} to all CPUs").unwrap();
+    send_sgi_to_all(intid);
+    Ok(())
 }
@@ -0,0 +1,335 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Writing a crash report on panic, in the format described in `osdemo::crashdump`, to a reserved
+//! range of a block device or to a vsock peer, and reading it back after a reboot.
+//!
+//! Where to write reports is given by the `crashdump` kernel command line argument, such as
+//! `crashdump=blk0:1048576+32768`, or set with `crashdump target`. Block device ranges must be
+//! whole blocks, and are written directly to the device, bypassing the cache.
+
+use crate::{
+    apps::{
+        command::{Args, CommandError, Context, ErrorContext},
+        cp::{Sink, VsockStream, read_endpoint},
+    },
+    backtrace::stack_top,
+    exceptions::{esr, far},
+    logger,
+};
+use alloc::format;
+use core::{
+    arch::asm,
+    panic::PanicInfo,
+    ptr::{self, null_mut},
+    sync::atomic::{AtomicPtr, Ordering},
+};
+use dtoolkit::fdt::Fdt;
+use embedded_io::Write;
+use log::{error, info, warn};
+use osdemo::{
+    crashdump::{
+        BOOTARG, CrashReport, Encoder, REGISTER_COUNT, REGISTER_NAMES, SECTION_LOG,
+        SECTION_MESSAGE, SECTION_REGISTERS, SECTION_STACK,
+    },
+    endpoint::Endpoint,
+};
+use osdemo_core::{
+    block::BlockDevice,
+    devices::Devices,
+    fdt::{bootarg_value, bootargs},
+    pci_config::HexDump,
+};
+use spin::mutex::SpinMutex;
+use virtio_drivers::device::socket::VsockAddr;
+
+/// The largest report which will be written, in bytes. This is enough for the whole log buffer.
+const REPORT_SIZE: usize = 32 * 1024;
+
+/// The most bytes of the stack to include in a report.
+const STACK_SIZE: usize = 4096;
+
+/// The local port used for the vsock connection.
+const CRASHDUMP_PORT: u32 = 47;
+
+/// The index of the frame pointer in `REGISTER_NAMES`.
+const FP_INDEX: usize = 10;
+/// The index of the stack pointer in `REGISTER_NAMES`.
+const SP_INDEX: usize = 12;
+
+/// Where to write crash reports, if anywhere. This is never a path.
+static TARGET: SpinMutex<Option<Endpoint<'static>>> = SpinMutex::new(None);
+
+/// The devices to write crash reports with, while the shell is running.
+static DEVICES: AtomicPtr<Devices> = AtomicPtr::new(null_mut());
+
+/// The buffer which reports are encoded in, as the heap may not be usable during a panic.
+static REPORT_BUFFER: SpinMutex<[u8; REPORT_SIZE]> = SpinMutex::new([0; REPORT_SIZE]);
+
+/// Sets where to write crash reports from the `crashdump` argument in the kernel command line, if
+/// there is one, and lets them be written with the given devices until `remove` is called.
+pub fn init(fdt: &Fdt, devices: &mut Devices) {
+    DEVICES.store(devices, Ordering::SeqCst);
+    let Some(value) = bootargs(fdt).and_then(|args| bootarg_value(args, BOOTARG)) else {
+        return;
+    };
+    match Endpoint::parse(value)
+        .context("parsing destination")
+        .and_then(|endpoint| check_target(devices, endpoint))
+    {
+        Ok(target) => {
+            *TARGET.lock() = Some(target);
+            info!("Writing crash reports to {target}");
+        }
+        Err(e) => warn!("Ignoring invalid {BOOTARG} {value:?}: {e}"),
+    }
+}
+
+/// Stops writing crash reports, as the devices are no longer available.
+pub fn remove() {
+    DEVICES.store(null_mut(), Ordering::SeqCst);
+}
+
+/// Returns the values of the registers named in `REGISTER_NAMES`.
+///
+/// This is inlined so that the callee-saved registers are those of the caller.
+#[inline(always)]
+pub fn capture_registers() -> [u64; REGISTER_COUNT] {
+    let mut registers = [0; REGISTER_COUNT];
+    // SAFETY: This only writes the first 13 elements of `registers`, which is large enough.
+    unsafe {
+        asm!(
+            "stp x19, x20, [{registers}]",
+            "stp x21, x22, [{registers}, #16]",
+            "stp x23, x24, [{registers}, #32]",
+            "stp x25, x26, [{registers}, #48]",
+            "stp x27, x28, [{registers}, #64]",
+            "stp x29, x30, [{registers}, #80]",
+            "mov {sp}, sp",
+            "str {sp}, [{registers}, #96]",
+            registers = in(reg) registers.as_mut_ptr(),
+            sp = out(reg) _,
+            options(nostack, preserves_flags),
+        );
+    }
+    registers[13] = esr();
+    registers[14] = far();
+    registers
+}
+
+/// Writes a crash report for the given panic, with the given registers from `capture_registers`,
+/// if a target has been set.
+///
+/// Any error is logged. This never allocates, except to write to a vsock peer.
+pub fn save_on_panic(info: &PanicInfo, registers: &[u64; REGISTER_COUNT]) {
+    // Don't wait for locks, in case the panic happened while they were held.
+    let Some(target) = TARGET.try_lock().and_then(|target| *target) else {
+        return;
+    };
+    let devices = DEVICES.load(Ordering::SeqCst);
+    if devices.is_null() {
+        return;
+    }
+    let Some(mut report_buffer) = REPORT_BUFFER.try_lock() else {
+        return;
+    };
+    let buffer = match target {
+        Endpoint::Block {
+            length: Some(length),
+            ..
+        } => &mut report_buffer[..length as usize],
+        _ => &mut report_buffer[..],
+    };
+    let length = encode_report(buffer, info, registers);
+    // SAFETY: `DEVICES` is only set while the shell is running, so it points to valid devices. The
+    // code which panicked may have held a mutable reference to them, but it will never run again,
+    // as the panic handler powers off once this returns.
+    let devices = unsafe { &mut *devices };
+    match write_report(devices, target, buffer, length) {
+        Ok(()) => error!("Wrote crash report to {target}"),
+        Err(e) => error!("Error writing crash report to {target}: {e}"),
+    }
+}
+
+/// Shows or sets where crash reports are written, shows the last report, or panics to test them.
+pub fn crashdump(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    match args.next() {
+        None => match *TARGET.lock() {
+            Some(target) => writeln!(context.console, "Writing crash reports to {target}").unwrap(),
+            None => writeln!(context.console, "Not writing crash reports").unwrap(),
+        },
+        Some("target") => {
+            let destination = args.required_str("destination")?;
+            args.finish()?;
+            if destination == "off" {
+                *TARGET.lock() = None;
+            } else {
+                let endpoint = Endpoint::parse(destination).context("parsing destination")?;
+                *TARGET.lock() = Some(check_target(context.devices, endpoint)?);
+            }
+        }
+        Some("show") => {
+            let source = args.next();
+            args.finish()?;
+            let source = match source {
+                Some(source) => Endpoint::parse(source).context("parsing source")?,
+                None => (*TARGET.lock()).ok_or("No crash report target set")?,
+            };
+            let Endpoint::Block { .. } = source else {
+                return Err("Crash reports can only be read back from block devices".into());
+            };
+            let data = read_endpoint(context.devices, source, REPORT_SIZE)?;
+            let report = CrashReport::decode(&data).context("reading crash report")?;
+            show_report(context.console, &report);
+        }
+        Some("panic") => {
+            args.finish()?;
+            panic!("Deliberate panic from crashdump");
+        }
+        Some(_) => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+/// Checks that crash reports can be written to the given endpoint, and returns it with the length
+/// of a block device range filled in and limited to `REPORT_SIZE`.
+fn check_target(devices: &Devices, endpoint: Endpoint) -> Result<Endpoint<'static>, CommandError> {
+    match endpoint {
+        Endpoint::Block {
+            device,
+            offset,
+            length,
+        } => {
+            let block = devices
+                .block
+                .get(device)
+                .ok_or(CommandError::NoSuchDevice {
+                    kind: "block",
+                    index: device,
+                })?;
+            if block.readonly() {
+                return Err(CommandError::Failed(format!("blk{device} is read-only")));
+            }
+            let block_size = block.block_size() as u64;
+            let capacity = block.capacity() * block_size;
+            let length = length
+                .unwrap_or(capacity.saturating_sub(offset))
+                .min(REPORT_SIZE as u64);
+            if !offset.is_multiple_of(block_size) || !length.is_multiple_of(block_size) {
+                return Err(CommandError::Failed(format!(
+                    "Range must be whole {block_size} byte blocks"
+                )));
+            }
+            if length < block_size || offset + length > capacity {
+                return Err(CommandError::Failed(format!(
+                    "Range doesn't fit on blk{device}"
+                )));
+            }
+            Ok(Endpoint::Block {
+                device,
+                offset,
+                length: Some(length),
+            })
+        }
+        Endpoint::Vsock { cid, port } => Ok(Endpoint::Vsock { cid, port }),
+        Endpoint::Path(path) => Err(CommandError::Failed(format!(
+            "Can't write to {path}, as there are no filesystems"
+        ))),
+    }
+}
+
+/// Encodes a crash report into the given buffer, and returns its length.
+fn encode_report(buffer: &mut [u8], info: &PanicInfo, registers: &[u64; REGISTER_COUNT]) -> usize {
+    let mut encoder = Encoder::new(buffer);
+    encoder.text_section(SECTION_MESSAGE, format_args!("{info}"));
+
+    let mut register_bytes = [0; REGISTER_COUNT * 8];
+    for (bytes, register) in register_bytes.chunks_exact_mut(8).zip(registers) {
+        bytes.copy_from_slice(&register.to_le_bytes());
+    }
+    encoder.bytes_section(SECTION_REGISTERS, &register_bytes);
+
+    let sp = registers[SP_INDEX] as usize;
+    let stack_length = stack_top(registers[FP_INDEX] as usize)
+        .map_or(0, |top| top.saturating_sub(sp))
+        .min(STACK_SIZE);
+    encoder.section(SECTION_STACK, |space| {
+        let Some((address, space)) = space.split_first_chunk_mut() else {
+            return 0;
+        };
+        *address = (sp as u64).to_le_bytes();
+        let length = stack_length.min(space.len());
+        // SAFETY: The stack between the stack pointer and the outermost frame record is mapped and
+        // in use, and `space` is a separate static buffer so can't overlap it.
+        unsafe {
+            ptr::copy_nonoverlapping(sp as *const u8, space.as_mut_ptr(), length);
+        }
+        8 + length
+    });
+
+    encoder.section(SECTION_LOG, logger::copy_recent);
+    encoder.finish().len()
+}
+
+/// Writes the first `length` bytes of the given buffer to the given target. A block device range is
+/// written in whole blocks, so the rest of the final block is zeroed.
+fn write_report(
+    devices: &mut Devices,
+    target: Endpoint,
+    buffer: &mut [u8],
+    length: usize,
+) -> Result<(), CommandError> {
+    match target {
+        Endpoint::Block { device, offset, .. } => {
+            let block = &mut devices.block[device];
+            let block_size = block.block_size();
+            let padded_length = length.next_multiple_of(block_size);
+            buffer[length..padded_length].fill(0);
+            block
+                .uncached()
+                .write_blocks(offset / block_size as u64, &buffer[..padded_length])
+                .context(format_args!("writing blk{device}"))
+        }
+        Endpoint::Vsock { cid, port } => {
+            let mut stream =
+                VsockStream::connect(devices, VsockAddr { cid, port }, CRASHDUMP_PORT)?;
+            let result = stream.write(devices, &buffer[..length]);
+            stream.close(devices)?;
+            result
+        }
+        Endpoint::Path(_) => unreachable!(),
+    }
+}
+
+/// Prints the given crash report.
+fn show_report(console: &mut (impl Write + ?Sized), report: &CrashReport) {
+    if let Some(message) = report.message {
+        writeln!(console, "{message}").unwrap();
+    }
+    if let Some(registers) = report.registers {
+        writeln!(console, "Registers:").unwrap();
+        for (index, (name, value)) in REGISTER_NAMES.iter().zip(registers).enumerate() {
+            write!(console, "  {name:<3} {value:#018x}").unwrap();
+            if index % 3 == 2 {
+                writeln!(console).unwrap();
+            }
+        }
+    }
+    if let Some((address, stack)) = report.stack {
+        writeln!(console, "Stack:").unwrap();
+        write!(
+            console,
+            "{}",
+            HexDump {
+                address: address as usize,
+                data: stack,
+            }
+        )
+        .unwrap();
+    }
+    if !report.log.is_empty() {
+        writeln!(console, "Recent log:").unwrap();
+        console.write_all(report.log).unwrap();
+    }
+}
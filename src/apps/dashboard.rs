@@ -0,0 +1,243 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A full-screen dashboard of CPU utilisation, memory usage, interrupt rates and block and network
+//! throughput, redrawn in place at a fixed interval.
+
+use crate::{
+    apps::{
+        command::{Args, CommandError, Context, Terminal},
+        stopwatch::wait_or_stop,
+    },
+    heap_stats,
+};
+use alloc::{collections::BTreeMap, format, string::ToString, vec::Vec};
+use arm_gic::IntId;
+use core::{fmt::Arguments, time::Duration};
+use embedded_io::Write;
+use osdemo::terminal::{
+    Bar, Bytes, CLEAR_SCREEN, CLEAR_TO_END_OF_LINE, CLEAR_TO_END_OF_SCREEN, CURSOR_HOME,
+    HIDE_CURSOR, SHOW_CURSOR, per_second,
+};
+use osdemo_core::{
+    block::IoStats,
+    cpus::cpu_count,
+    devices::Devices,
+    dma,
+    drivers::generic_timer::Instant,
+    executor::block_on,
+    idle::{IdleStats, idle_stats},
+    interrupts::irq_counts,
+    net::InterfaceStats,
+    pagetable::PAGETABLE,
+    timer::uptime_us,
+};
+
+/// How often the display is refreshed if no interval is given, in milliseconds.
+const DEFAULT_INTERVAL_MS: u64 = 1000;
+
+/// The shortest refresh interval allowed, in milliseconds.
+const MIN_INTERVAL_MS: u64 = 10;
+
+/// The width of the bars inside their brackets, in characters.
+const BAR_WIDTH: usize = 30;
+
+/// The counters shown by the dashboard at a point in time.
+struct Sample {
+    time: Instant,
+    cpus: Vec<IdleStats>,
+    irqs: BTreeMap<IntId, u64>,
+    block: Vec<IoStats>,
+    net: Vec<Option<InterfaceStats>>,
+}
+
+impl Sample {
+    /// Reads the current value of every counter.
+    fn new(devices: &Devices) -> Self {
+        Self {
+            time: Instant::now(),
+            cpus: (0..cpu_count())
+                .map(|cpu| idle_stats(cpu).unwrap_or_default())
+                .collect(),
+            irqs: irq_counts(),
+            block: devices
+                .block
+                .iter()
+                .map(|device| device.io_stats())
+                .collect(),
+            net: devices
+                .net
+                .iter()
+                .map(|interface| interface.stats())
+                .collect(),
+        }
+    }
+}
+
+/// Shows a full-screen dashboard which is redrawn every interval, until a stop key is pressed.
+pub fn dashboard(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let interval_ms = args.optional("interval_ms")?.unwrap_or(DEFAULT_INTERVAL_MS);
+    args.finish()?;
+    if interval_ms < MIN_INTERVAL_MS {
+        return Err(CommandError::InvalidArgument {
+            name: "interval_ms",
+            value: interval_ms.to_string(),
+        });
+    }
+    let interval = Duration::from_millis(interval_ms);
+    let console = &mut *context.console;
+    let devices = &*context.devices;
+    write!(console, "{HIDE_CURSOR}{CLEAR_SCREEN}{CURSOR_HOME}").unwrap();
+    writeln!(console, "Collecting statistics...").unwrap();
+
+    let result = block_on(async {
+        let mut previous = Sample::new(devices);
+        loop {
+            if wait_or_stop(console, previous.time + interval).await? {
+                return Ok(());
+            }
+            let current = Sample::new(devices);
+            write!(console, "{CURSOR_HOME}").unwrap();
+            draw(console, &previous, &current);
+            write!(console, "{CLEAR_TO_END_OF_SCREEN}").unwrap();
+            previous = current;
+        }
+    });
+    write!(console, "{SHOW_CURSOR}").unwrap();
+    result
+}
+
+/// Draws every section of the dashboard, with rates over the time between the two samples.
+fn draw(console: &mut dyn Terminal, previous: &Sample, current: &Sample) {
+    let elapsed_ticks = current.time.ticks() - previous.time.ticks();
+    let elapsed_us = current.time.duration_since(previous.time).as_micros() as u64;
+    let uptime = Duration::from_micros(uptime_us());
+    line(
+        console,
+        format_args!(
+            "Uptime {}.{:03} s, press q or Ctrl-C to stop.",
+            uptime.as_secs(),
+            uptime.subsec_millis()
+        ),
+    );
+
+    line(console, format_args!(""));
+    line(console, format_args!("CPU  busy"));
+    for (cpu, (before, after)) in previous.cpus.iter().zip(&current.cpus).enumerate() {
+        let idle_ticks = (after.ticks - before.ticks).min(elapsed_ticks);
+        let busy_ticks = elapsed_ticks - idle_ticks;
+        let bar = Bar {
+            value: busy_ticks,
+            max: elapsed_ticks,
+            width: BAR_WIDTH,
+        };
+        let busy_percent = (u128::from(busy_ticks) * 100)
+            .checked_div(u128::from(elapsed_ticks))
+            .unwrap_or(0);
+        line(console, format_args!("{cpu:>3} {bar} {busy_percent:>3}%"));
+    }
+
+    line(console, format_args!(""));
+    line(console, format_args!("Memory"));
+    let allocators = [
+        ("Heap", Some(heap_stats())),
+        (
+            "Page tables",
+            PAGETABLE
+                .get()
+                .map(|idmap| idmap.lock().page_allocator_stats()),
+        ),
+        ("Bounce pool", dma::bounce_stats()),
+    ];
+    for (name, stats) in allocators {
+        let Some(stats) = stats else {
+            continue;
+        };
+        let bar = Bar {
+            value: stats.used_bytes as u64,
+            max: stats.total_bytes as u64,
+            width: BAR_WIDTH,
+        };
+        line(
+            console,
+            format_args!(
+                "  {name:<12} {bar} {} of {}",
+                Bytes(stats.used_bytes as u64),
+                Bytes(stats.total_bytes as u64)
+            ),
+        );
+    }
+    if let Some(stats) = dma::stats() {
+        let bar = Bar {
+            value: stats.allocated_pages as u64,
+            max: stats.total_pages as u64,
+            width: BAR_WIDTH,
+        };
+        line(
+            console,
+            format_args!(
+                "  {:<12} {bar} {} of {} pages",
+                "DMA pool", stats.allocated_pages, stats.total_pages
+            ),
+        );
+    }
+
+    line(console, format_args!(""));
+    line(console, format_args!("IRQs/s"));
+    for (intid, &count) in &current.irqs {
+        let before = previous.irqs.get(intid).copied().unwrap_or(0);
+        let rate = per_second(count - before, elapsed_us);
+        line(
+            console,
+            format_args!("  {:<20} {rate:>8}", format!("{intid:?}")),
+        );
+    }
+
+    if !current.block.is_empty() {
+        line(console, format_args!(""));
+        line(console, format_args!("Block        read/s      write/s"));
+    }
+    for (index, (before, after)) in previous.block.iter().zip(&current.block).enumerate() {
+        let read = per_second(after.bytes_read - before.bytes_read, elapsed_us);
+        let written = per_second(after.bytes_written - before.bytes_written, elapsed_us);
+        line(
+            console,
+            format_args!(
+                "  blk{index:<4} {:>12} {:>12}",
+                Bytes(read).to_string(),
+                Bytes(written).to_string()
+            ),
+        );
+    }
+
+    if !current.net.is_empty() {
+        line(console, format_args!(""));
+        line(
+            console,
+            format_args!("Network   RX frames/s         RX/s  TX frames/s         TX/s"),
+        );
+    }
+    for (index, (before, after)) in previous.net.iter().zip(&current.net).enumerate() {
+        let (Some(before), Some(after)) = (before, after) else {
+            line(console, format_args!("  net{index:<4} no statistics"));
+            continue;
+        };
+        let rate = |after: u64, before: u64| per_second(after - before, elapsed_us);
+        line(
+            console,
+            format_args!(
+                "  net{index:<4} {:>11} {:>12} {:>12} {:>12}",
+                rate(after.rx_frames, before.rx_frames),
+                Bytes(rate(after.rx_bytes, before.rx_bytes)).to_string(),
+                rate(after.tx_frames, before.tx_frames),
+                Bytes(rate(after.tx_bytes, before.tx_bytes)).to_string()
+            ),
+        );
+    }
+}
+
+/// Writes a line of the dashboard, clearing anything left over from the previous frame after it.
+fn line(console: &mut dyn Terminal, text: Arguments) {
+    writeln!(console, "{text}{CLEAR_TO_END_OF_LINE}").unwrap();
+}
@@ -0,0 +1,38 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Commands to show recent log records and change which are logged.
+
+use crate::{
+    apps::command::{Args, CommandError, Context},
+    logger,
+};
+use alloc::string::String;
+use embedded_io::Write;
+use log::LevelFilter;
+use osdemo::log_buffer::line_enabled;
+
+/// Prints the recent log records, optionally only those at or above the given level.
+pub fn dmesg(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let filter = args.optional("level")?.unwrap_or(LevelFilter::Trace);
+    args.finish()?;
+    let recent = logger::recent();
+    for line in String::from_utf8_lossy(&recent).lines() {
+        if line_enabled(line, filter) {
+            writeln!(context.console, "{line}").unwrap();
+        }
+    }
+    Ok(())
+}
+
+/// Prints or changes the maximum level of records which are logged.
+pub fn loglevel(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let level = args.optional::<LevelFilter>("level")?;
+    args.finish()?;
+    match level {
+        Some(level) => log::set_max_level(level),
+        None => writeln!(context.console, "{}", log::max_level()).unwrap(),
+    }
+    Ok(())
+}
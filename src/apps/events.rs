@@ -0,0 +1,51 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A command to watch device events as they are published.
+
+use crate::apps::command::{Args, CommandError, Context, ErrorContext};
+use core::future::poll_fn;
+use embedded_io::Write;
+use osdemo_core::{
+    events::Subscriber,
+    executor::{Either, block_on, select},
+};
+
+/// Prints device events as they happen, until a key is pressed or the given number of events have
+/// been printed.
+pub fn events(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let count = args.optional::<usize>("count")?;
+    args.finish()?;
+    let Context {
+        console, devices, ..
+    } = context;
+    let mut subscriber = Subscriber::new();
+    writeln!(console, "Watching device events, press any key to stop.").unwrap();
+
+    let mut printed = 0;
+    block_on(async {
+        while count.is_none_or(|count| printed < count) {
+            let mut buffer = [0];
+            let next_event = poll_fn(|task_context| {
+                devices.poll_changes();
+                subscriber.poll_next(task_context)
+            });
+            match select(console.read_async(&mut buffer), next_event).await {
+                Either::Left(result) => {
+                    result.context("reading from console")?;
+                    break;
+                }
+                Either::Right(event) => {
+                    writeln!(console, "{event}").unwrap();
+                    printed += 1;
+                }
+            }
+        }
+        Ok::<_, CommandError>(())
+    })?;
+    if subscriber.missed() > 0 {
+        writeln!(console, "Missed {} events.", subscriber.missed()).unwrap();
+    }
+    Ok(())
+}
@@ -0,0 +1,112 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Generating sustained output, to load test the console and the logger.
+
+use crate::apps::command::{Args, CommandError, Context};
+use alloc::{string::String, vec};
+use core::{fmt::Write as _, time::Duration};
+use embedded_io::Write;
+use log::info;
+use osdemo_core::{
+    drivers::generic_timer::{Instant, sleep_until},
+    executor::block_on,
+};
+
+/// The number of bytes of payload in each line, unless otherwise specified.
+const DEFAULT_PAYLOAD_BYTES: usize = 64;
+
+/// How many seconds to generate output for, unless otherwise specified.
+const DEFAULT_SECONDS: u64 = 5;
+
+/// Where generated lines are written.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Target {
+    /// Directly to the session's terminal.
+    Console,
+    /// Through the logger, so they also go to the ring buffer and contend for the shared console.
+    Log,
+}
+
+/// Writes numbered lines of the given size to the console or the logger at the given rate for the
+/// given time, and then reports the throughput achieved.
+///
+/// Each line starts with its sequence number, so that any which are lost can be spotted.
+pub fn flood(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let mut next = args.next();
+    let target = match next {
+        Some("log") => {
+            next = args.next();
+            Target::Log
+        }
+        Some("console") => {
+            next = args.next();
+            Target::Console
+        }
+        _ => Target::Console,
+    };
+    // Zero means as fast as possible.
+    let lines_per_second: u64 = match next {
+        Some(value) => value.parse().map_err(|_| CommandError::InvalidArgument {
+            name: "lines/s",
+            value: value.into(),
+        })?,
+        None => 0,
+    };
+    let payload_bytes = args.optional("bytes")?.unwrap_or(DEFAULT_PAYLOAD_BYTES);
+    let seconds = args.optional("seconds")?.unwrap_or(DEFAULT_SECONDS);
+    args.finish()?;
+
+    let console = &mut *context.console;
+    let payload = String::from_utf8(vec![b'y'; payload_bytes]).unwrap();
+    let rate = (lines_per_second > 0).then_some(lines_per_second);
+    let start = Instant::now();
+    let end = start + Duration::from_secs(seconds);
+    let mut line = String::new();
+    let mut lines = 0u64;
+    let mut bytes = 0u64;
+    let mut late_lines = 0u64;
+    loop {
+        let now = Instant::now();
+        if now >= end {
+            break;
+        }
+        if let Some(rate) = rate {
+            let due = start + Duration::from_nanos(lines * 1_000_000_000 / rate);
+            if due > now {
+                block_on(sleep_until(due.min(end)));
+                continue;
+            } else if now.duration_since(due) > Duration::from_nanos(1_000_000_000 / rate) {
+                late_lines += 1;
+            }
+        }
+        line.clear();
+        write!(line, "{lines:08} {payload}").unwrap();
+        match target {
+            Target::Console => writeln!(console, "{line}").unwrap(),
+            Target::Log => info!("{line}"),
+        }
+        lines += 1;
+        bytes += line.len() as u64 + 1;
+    }
+
+    let elapsed_us = start.elapsed().as_micros().max(1) as u64;
+    writeln!(
+        console,
+        "Wrote {lines} lines ({bytes} bytes) in {} ms: {} lines/s, {} KiB/s",
+        elapsed_us / 1000,
+        lines * 1_000_000 / elapsed_us,
+        bytes * 1_000_000 / 1024 / elapsed_us
+    )
+    .unwrap();
+    if late_lines > 0 {
+        writeln!(
+            console,
+            "{late_lines} lines were written more than one interval late, so the requested rate \
+             couldn't be sustained"
+        )
+        .unwrap();
+    }
+    Ok(())
+}
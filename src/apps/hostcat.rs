@@ -0,0 +1,40 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Reading files on the host with semihosting, for getting test inputs into the VM without a block
+//! device or network.
+
+use crate::apps::command::{Args, CommandError, Context, ErrorContext};
+use alloc::format;
+use embedded_io::{Read, Write};
+use osdemo_core::semihosting::{self, HostFile};
+
+/// The size of the chunks the file is read in.
+const CHUNK_SIZE: usize = 512;
+
+/// Prints the file at the given path on the host.
+///
+/// Relative paths are relative to the directory QEMU was started in.
+pub fn hostcat(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let path = args.required_str("path")?;
+    args.finish()?;
+    if !semihosting::enabled() {
+        return Err(CommandError::Failed(format!(
+            "Semihosting isn't enabled; run QEMU with `-semihosting -append {}`",
+            semihosting::BOOTARG
+        )));
+    }
+    let mut file = HostFile::open(path).context(format_args!("opening {path}"))?;
+    let mut buffer = [0; CHUNK_SIZE];
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .context(format_args!("reading {path}"))?;
+        if read == 0 {
+            break;
+        }
+        context.console.write_all(&buffer[..read]).unwrap();
+    }
+    Ok(())
+}
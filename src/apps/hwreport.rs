@@ -0,0 +1,148 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Sending the hardware inventory described in `osdemo::hw_report` to a vsock peer once device
+//! discovery has finished, so that host test harnesses can check what the guest saw.
+//!
+//! Where to send it is given by the `hwreport` kernel command line argument, such as
+//! `hwreport=vsock:2:9002`.
+
+use crate::apps::{
+    command::{Args, CommandError, Context, ErrorContext},
+    cp::{Sink, VsockStream},
+};
+use alloc::{format, string::ToString, vec::Vec};
+use dtoolkit::{ToCellInt, fdt::Fdt, standard::NodeStandard};
+use embedded_io::Write;
+use log::{info, warn};
+use osdemo::{
+    endpoint::Endpoint,
+    hw_report::{BOOTARG, Cpu, Device, HwReport, MemoryRange, PciFunction},
+};
+use osdemo_core::{
+    devices::Devices,
+    fdt::{bootarg_value, bootargs},
+    pci::PciRootComplex,
+};
+use virtio_drivers::device::socket::VsockAddr;
+
+/// The local port used for the vsock connection.
+const HWREPORT_PORT: u32 = 48;
+
+/// Sends the hardware report to the destination given by the `hwreport` argument in the kernel
+/// command line, if there is one.
+///
+/// Any error is logged, as it shouldn't stop the system from booting.
+pub fn send_at_boot(fdt: &Fdt, pci_roots: &[PciRootComplex], devices: &mut Devices) {
+    let Some(value) = bootargs(fdt).and_then(|args| bootarg_value(args, BOOTARG)) else {
+        return;
+    };
+    let result = parse_destination(value).and_then(|peer| {
+        let report = collect(fdt, pci_roots, devices).to_string();
+        send(devices, peer, report.as_bytes())
+    });
+    match result {
+        Ok(()) => info!("Sent hardware report to {value}"),
+        Err(e) => warn!("Failed to send hardware report to {BOOTARG} {value:?}: {e}"),
+    }
+}
+
+/// Prints the hardware report, or sends it to the given vsock destination.
+pub fn hwreport(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let destination = args.next();
+    args.finish()?;
+    let report = collect(context.fdt, context.pci_roots, context.devices).to_string();
+    match destination {
+        None => writeln!(context.console, "{report}").unwrap(),
+        Some(destination) => {
+            let peer = parse_destination(destination)?;
+            send(context.devices, peer, report.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Parses a destination for the report, which must be a vsock endpoint such as `vsock:2:9002`.
+fn parse_destination(value: &str) -> Result<VsockAddr, CommandError> {
+    match Endpoint::parse(value).context("parsing destination")? {
+        Endpoint::Vsock { cid, port } => Ok(VsockAddr { cid, port }),
+        _ => Err(CommandError::Failed(format!(
+            "Can only send the hardware report to vsock, not {value}"
+        ))),
+    }
+}
+
+/// Sends the given report to the given vsock peer, and closes the connection.
+fn send(devices: &mut Devices, peer: VsockAddr, report: &[u8]) -> Result<(), CommandError> {
+    let mut stream = VsockStream::connect(devices, peer, HWREPORT_PORT)?;
+    let result = stream.write(devices, report);
+    // Close the connection even if sending failed, so the peer isn't left waiting.
+    let closed = stream.close(devices);
+    result?;
+    closed
+}
+
+/// Collects the CPUs and memory from the device tree, the PCI functions on all roots, and the
+/// device registry into a report.
+fn collect<'a>(fdt: &Fdt, pci_roots: &[PciRootComplex], devices: &'a Devices) -> HwReport<'a> {
+    let cpus = fdt
+        .cpus()
+        .unwrap()
+        .cpus()
+        .enumerate()
+        .map(|(index, cpu)| Cpu {
+            index,
+            mpidr: cpu.ids().unwrap().next().unwrap().to_int::<u64>().unwrap(),
+        })
+        .collect();
+    let memory = fdt
+        .memory()
+        .ok()
+        .and_then(|memory| memory.reg().ok().flatten())
+        .map(|reg| {
+            reg.filter_map(|reg| {
+                Some(MemoryRange {
+                    address: reg.address::<u64>().ok()?,
+                    size: reg.size::<u64>().ok()?,
+                })
+            })
+            .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let pci = pci_roots
+        .iter()
+        .enumerate()
+        .flat_map(|(root, pci_root)| {
+            pci_root
+                .enumerate_devices()
+                .into_iter()
+                .map(move |(device_function, info)| PciFunction {
+                    root,
+                    bus: device_function.bus,
+                    device: device_function.device,
+                    function: device_function.function,
+                    vendor_id: info.vendor_id,
+                    device_id: info.device_id,
+                    class: info.class,
+                    subclass: info.subclass,
+                })
+        })
+        .collect();
+    let devices = devices
+        .registry()
+        .iter()
+        .map(|info| Device {
+            id: info.id,
+            driver: info.driver,
+            location: &info.location,
+            description: &info.description,
+        })
+        .collect::<Vec<_>>();
+    HwReport {
+        cpus,
+        memory,
+        pci,
+        devices,
+    }
+}
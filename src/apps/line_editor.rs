@@ -0,0 +1,189 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+use alloc::collections::VecDeque;
+use arrayvec::ArrayVec;
+use core::mem::take;
+use embedded_io::Write;
+use osdemo::terminal::{Key, KeyDecoder};
+
+/// The maximum length of a line which can be entered.
+pub const MAX_LINE_LENGTH: usize = 128;
+
+/// The maximum number of previous lines to keep in the history.
+const MAX_HISTORY_LENGTH: usize = 32;
+
+pub type Line = ArrayVec<u8, MAX_LINE_LENGTH>;
+
+/// Something entered in a line editor.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LineEvent {
+    /// A line was entered.
+    Line(Line),
+    /// Ctrl-D was pressed on an empty line.
+    Eof,
+}
+
+/// Reads lines from a terminal, with support for editing and history.
+///
+/// Input is pushed in a byte at a time, so that several editors can be fed from different
+/// terminals at once.
+#[derive(Debug, Default)]
+pub struct LineEditor {
+    decoder: KeyDecoder,
+    /// Previously entered lines, oldest first.
+    history: VecDeque<Line>,
+    /// The line being edited.
+    line: Line,
+    /// The position of the cursor within `line`.
+    cursor: usize,
+    /// The index into the history of the line being shown, if any.
+    history_index: Option<usize>,
+    /// The line being edited before we started navigating the history.
+    draft: Line,
+}
+
+impl LineEditor {
+    /// Creates a new line editor with an empty history.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the given line to the end of the history, unless it is empty or the same as the most
+    /// recent entry.
+    pub fn add_history(&mut self, line: &[u8]) {
+        if line.is_empty()
+            || self
+                .history
+                .back()
+                .is_some_and(|last| last.as_slice() == line)
+        {
+            return;
+        }
+        if self.history.len() == MAX_HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        let mut entry = Line::new();
+        entry
+            .try_extend_from_slice(&line[..line.len().min(MAX_LINE_LENGTH)])
+            .unwrap();
+        self.history.push_back(entry);
+    }
+
+    /// Returns the lines in the history, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &[u8]> {
+        self.history.iter().map(Line::as_slice)
+    }
+
+    /// Handles the given byte of input, echoing to the given console and allowing the line to be
+    /// edited.
+    ///
+    /// Returns the line once it is entered, or `Eof` if Ctrl-D is pressed on an empty line.
+    pub fn push(&mut self, byte: u8, console: &mut (impl Write + ?Sized)) -> Option<LineEvent> {
+        let key = self.decoder.push(byte)?;
+        let Self {
+            history,
+            line,
+            cursor,
+            history_index,
+            draft,
+            ..
+        } = self;
+        match key {
+            Key::Enter => {
+                console.write_all(b"\r\n").unwrap();
+                let line = take(line);
+                *cursor = 0;
+                *history_index = None;
+                self.add_history(&line);
+                return Some(LineEvent::Line(line));
+            }
+            Key::Eof if line.is_empty() => {
+                console.write_all(b"\r\n").unwrap();
+                return Some(LineEvent::Eof);
+            }
+            Key::Eof => {}
+            Key::Char(c) => {
+                if line.try_insert(*cursor, c).is_ok() {
+                    console.write_all(&line[*cursor..]).unwrap();
+                    *cursor += 1;
+                    move_left(console, line.len() - *cursor);
+                }
+            }
+            Key::Backspace => {
+                if *cursor > 0 {
+                    *cursor -= 1;
+                    line.remove(*cursor);
+                    move_left(console, 1);
+                    redraw_from_cursor(console, line, *cursor);
+                }
+            }
+            Key::Delete => {
+                if *cursor < line.len() {
+                    line.remove(*cursor);
+                    redraw_from_cursor(console, line, *cursor);
+                }
+            }
+            Key::Left => {
+                if *cursor > 0 {
+                    *cursor -= 1;
+                    move_left(console, 1);
+                }
+            }
+            Key::Right => {
+                if *cursor < line.len() {
+                    console.write_all(&[line[*cursor]]).unwrap();
+                    *cursor += 1;
+                }
+            }
+            Key::Home => {
+                move_left(console, *cursor);
+                *cursor = 0;
+            }
+            Key::End => {
+                console.write_all(&line[*cursor..]).unwrap();
+                *cursor = line.len();
+            }
+            Key::Up | Key::Down => {
+                let new_index = match (key, *history_index) {
+                    (Key::Up, None) if !history.is_empty() => {
+                        *draft = line.clone();
+                        Some(history.len() - 1)
+                    }
+                    (Key::Up, Some(index)) if index > 0 => Some(index - 1),
+                    (Key::Down, Some(index)) if index + 1 < history.len() => Some(index + 1),
+                    (Key::Down, Some(_)) => None,
+                    // Nothing further in this direction.
+                    _ => return None,
+                };
+                *history_index = new_index;
+                move_left(console, *cursor);
+                *line = match new_index {
+                    Some(index) => history[index].clone(),
+                    None => draft.clone(),
+                };
+                *cursor = line.len();
+                console.write_all(line).unwrap();
+                // Clear the rest of the old line.
+                console.write_all(b"\x1b[K").unwrap();
+            }
+        }
+        None
+    }
+}
+
+/// Moves the terminal cursor left by the given number of characters.
+fn move_left(console: &mut (impl Write + ?Sized), count: usize) {
+    if count > 0 {
+        write!(console, "\x1b[{count}D").unwrap();
+    }
+}
+
+/// Redraws the line from the cursor position to the end, clearing anything after it, and then
+/// moves the cursor back to where it was.
+fn redraw_from_cursor(console: &mut (impl Write + ?Sized), line: &[u8], cursor: usize) {
+    console.write_all(&line[cursor..]).unwrap();
+    console.write_all(b"\x1b[K").unwrap();
+    move_left(console, line.len() - cursor);
+}
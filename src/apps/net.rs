@@ -0,0 +1,703 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Configuring network interfaces, sending and capturing raw Ethernet frames to check their
+//! drivers, recording pcap captures, answering pings, UDP datagrams and TCP connections.
+//!
+//! The shell can also be offered on a TCP port, either raw or with telnet option negotiation. The
+//! session manager starts a session for each connection accepted, one at a time, using the
+//! functions here to send to and receive from it.
+
+use crate::apps::{
+    command::{Args, CommandError, Context, ErrorContext, Terminal},
+    cp::{endpoint, open_sink},
+    shell::DISCONNECT_KEY,
+};
+use alloc::{
+    collections::btree_map::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{mem::take, time::Duration};
+use embedded_io::Write;
+use log::{info, warn};
+use osdemo::telnet::{INITIAL_NEGOTIATION, TelnetDecoder, TelnetEvent, encode};
+use osdemo_core::{
+    device_id::DeviceKind,
+    devices::Devices,
+    drivers::generic_timer::{Instant, timeout},
+    ethernet::{
+        ETHERNET_HEADER_SIZE, ETHERTYPE_LOCAL_EXPERIMENTAL, EthernetHeader, MAX_FRAME_SIZE,
+        MIN_FRAME_SIZE, MacAddress,
+    },
+    executor::block_on,
+    ipv4::{IpConfig, Ipv4Address},
+    net::{NetworkInterface, capture_status, start_capture, stop_capture},
+    tcpip::{
+        ArpCache, IpStack, TcpConnection, TcpIpError, TcpListener, UdpSocket, responder_enabled,
+        set_responder_enabled,
+    },
+};
+use spin::mutex::SpinMutex;
+
+/// How long `nc` waits for the peer to accept a connection.
+const NC_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long `nc` and `udp recv` wait for console input before polling the network again.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The first local port used for outgoing connections, the start of the dynamic port range.
+const FIRST_EPHEMERAL_PORT: u16 = 49152;
+
+/// The maximum size of the pcap file recorded by `pcap start`, including its header.
+const CAPTURE_BUFFER_SIZE: usize = 32 * 1024;
+
+/// The IP configuration of each network interface which has one, by interface index.
+static IP_CONFIGS: SpinMutex<BTreeMap<usize, IpConfig>> = SpinMutex::new(BTreeMap::new());
+
+/// The TCP port which the shell is offered on, if any.
+static TCP_CONSOLE: SpinMutex<Option<TcpConsole>> = SpinMutex::new(None);
+
+/// A TCP port on which connections are accepted as shell sessions.
+struct TcpConsole {
+    /// The index of the network interface to accept connections on.
+    index: usize,
+    config: IpConfig,
+    listener: TcpListener,
+    /// Whether to negotiate telnet options and use telnet's encoding, rather than passing bytes
+    /// through unchanged.
+    telnet: bool,
+    /// Addresses learnt by the stack, kept as a new stack is made each time the interface is polled.
+    arp_cache: ArpCache,
+    /// The connection currently accepted, if any.
+    connection: Option<TcpConsoleConnection>,
+}
+
+/// A connection to the shell's TCP port.
+struct TcpConsoleConnection {
+    connection: TcpConnection,
+    decoder: TelnetDecoder,
+    /// Input received and decoded, which hasn't been read by the session manager yet.
+    input: Vec<u8>,
+}
+
+impl TcpConsole {
+    /// Runs the given function with an IP stack on the console's interface, keeping the addresses
+    /// it learns.
+    fn with_stack<T>(
+        &mut self,
+        devices: &mut Devices,
+        f: impl FnOnce(&mut Self, &mut IpStack) -> Result<T, TcpIpError>,
+    ) -> Result<T, TcpIpError> {
+        let interface = &mut *devices.net[self.index];
+        let mut stack = IpStack::with_arp_cache(interface, self.config, take(&mut self.arp_cache));
+        let result = f(self, &mut stack);
+        self.arp_cache = stack.into_arp_cache();
+        result
+    }
+
+    /// Returns the current connection, if it is from the given peer.
+    fn connection_from(&mut self, peer: (Ipv4Address, u16)) -> Option<&mut TcpConsoleConnection> {
+        self.connection
+            .as_mut()
+            .filter(|current| current.connection.remote() == peer)
+    }
+}
+
+pub fn net(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let Context {
+        console, devices, ..
+    } = context;
+    let index = args.next_device(DeviceKind::Network, devices.net.len())?;
+    let interface = &mut devices.net[index];
+    match args.required_str("subcommand")? {
+        "ip" => {
+            let Some(cidr) = args.next() else {
+                match IP_CONFIGS.lock().get(&index) {
+                    Some(config) => writeln!(console, "{config}").unwrap(),
+                    None => writeln!(console, "No IP address configured").unwrap(),
+                }
+                return Ok(());
+            };
+            let gateway = args.next();
+            args.finish()?;
+            let config = IpConfig::parse(cidr, gateway).map_err(|e| {
+                CommandError::Failed(format!("{e}, expected <address>/<prefix> [<gateway>]"))
+            })?;
+            IP_CONFIGS.lock().insert(index, config);
+        }
+        "send" => {
+            let text = args.required_str("text")?;
+            args.finish()?;
+            // Broadcast a test frame, padded to the minimum frame size.
+            let header = EthernetHeader {
+                destination: MacAddress::BROADCAST,
+                source: interface.mac_address(),
+                ethertype: ETHERTYPE_LOCAL_EXPERIMENTAL,
+            };
+            let length = (ETHERNET_HEADER_SIZE + text.len()).max(MIN_FRAME_SIZE);
+            if length > MAX_FRAME_SIZE {
+                return Err("Text too long for a single frame".into());
+            }
+            let mut frame = [0; MAX_FRAME_SIZE];
+            frame[..ETHERNET_HEADER_SIZE].copy_from_slice(&header.to_bytes());
+            frame[ETHERNET_HEADER_SIZE..][..text.len()].copy_from_slice(text.as_bytes());
+            interface.send(&frame[..length]).context("sending frame")?;
+            writeln!(console, "Sent {length} byte frame").unwrap();
+        }
+        "capture" => {
+            let count = args.optional("count")?.unwrap_or(1);
+            args.finish()?;
+            writeln!(console, "Capturing {count} frames...").unwrap();
+            let mut frame = [0; MAX_FRAME_SIZE];
+            let mut captured = 0;
+            while captured < count {
+                let length = match interface.receive(&mut frame) {
+                    Ok(Some(length)) => length,
+                    Ok(None) => {
+                        interface.wait_for_irq();
+                        continue;
+                    }
+                    Err(e) => {
+                        writeln!(console, "Error receiving frame: {e}").unwrap();
+                        continue;
+                    }
+                };
+                captured += 1;
+                if let Some((header, _)) = EthernetHeader::parse(&frame[..length]) {
+                    writeln!(
+                        console,
+                        "{} > {}, ethertype {:#06x}, length {}",
+                        header.source, header.destination, header.ethertype, length
+                    )
+                    .unwrap();
+                } else {
+                    writeln!(console, "Runt frame, length {length}").unwrap();
+                }
+            }
+        }
+        _ => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+/// Answers ARP and ICMP echo requests received on each enabled network interface with an IP
+/// address, if the responder is enabled, and drops any other frames received.
+///
+/// Returns whether there are any such interfaces, which need to be polled again.
+pub fn respond(devices: &mut Devices) -> bool {
+    if !responder_enabled() {
+        return false;
+    }
+    let tcp_console_index = TCP_CONSOLE.lock().as_ref().map(|console| console.index);
+    let mut polled = false;
+    for (&index, &config) in IP_CONFIGS.lock().iter() {
+        let Some(interface) = devices.net.get_mut(index) else {
+            continue;
+        };
+        // The TCP console answers requests itself while it polls its interface.
+        if !interface.enabled() || tcp_console_index == Some(index) {
+            continue;
+        }
+        polled = true;
+        if let Err(e) = IpStack::new(&mut **interface, config).respond() {
+            warn!("Error answering requests on net{index}: {e}");
+        }
+    }
+    polled
+}
+
+/// Shows or changes whether ARP and ICMP echo requests for our IP addresses are answered.
+pub fn responder(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let state = args.next();
+    args.finish()?;
+    match state {
+        None => writeln!(
+            context.console,
+            "Responder {}",
+            if responder_enabled() { "on" } else { "off" }
+        )
+        .unwrap(),
+        Some("on") => set_responder_enabled(true),
+        Some("off") => set_responder_enabled(false),
+        Some(_) => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+/// Shows the state of network interfaces, or changes an interface's MAC address or brings it up
+/// or down.
+pub fn ifconfig(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let Context {
+        console, devices, ..
+    } = context;
+    let Some(index) = args.optional_device(DeviceKind::Network, devices.net.len())? else {
+        for (index, interface) in devices.net.iter().enumerate() {
+            show_interface(&mut **console, index, &**interface);
+        }
+        return Ok(());
+    };
+    let interface = &mut devices.net[index];
+    match args.next() {
+        None => show_interface(&mut **console, index, &**interface),
+        Some("hw") => {
+            let mac_address: MacAddress = args.required("mac")?;
+            args.finish()?;
+            if mac_address.is_multicast() || mac_address.is_zero() {
+                return Err(CommandError::InvalidArgument {
+                    name: "mac",
+                    value: mac_address.to_string(),
+                });
+            }
+            interface
+                .set_mac_address(mac_address)
+                .context("setting MAC address")?;
+        }
+        Some(state @ ("up" | "down")) => {
+            args.finish()?;
+            interface
+                .set_enabled(state == "up")
+                .context(format_args!("bringing net{index} {state}"))?;
+        }
+        Some(_) => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+/// Prints a summary of the given interface's state and configuration.
+fn show_interface(console: &mut dyn Terminal, index: usize, interface: &dyn NetworkInterface) {
+    let link = match interface.link_up() {
+        Some(true) => "up",
+        Some(false) => "down",
+        None => "unknown",
+    };
+    write!(
+        console,
+        "net{index}: {} {}, {}, link {link}",
+        interface.kind(),
+        interface.mac_address(),
+        if interface.enabled() {
+            "enabled"
+        } else {
+            "disabled"
+        },
+    )
+    .unwrap();
+    if let Some(speed) = interface.link_speed() {
+        write!(console, " {speed}").unwrap();
+    }
+    writeln!(console).unwrap();
+    if let Some(config) = IP_CONFIGS.lock().get(&index) {
+        writeln!(console, "  {config}").unwrap();
+    }
+    if let Some(stats) = interface.stats() {
+        writeln!(console, "  {stats}").unwrap();
+    }
+}
+
+/// Starts or stops recording the frames sent and received on a network interface, and writes the
+/// recording to a block device range or vsock peer as a pcap file.
+pub fn pcap(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let Context {
+        console, devices, ..
+    } = context;
+    match args.next().unwrap_or("status") {
+        "start" => {
+            let index = args.next_device(DeviceKind::Network, devices.net.len())?;
+            let snaplen = args.optional("snaplen")?.unwrap_or(MAX_FRAME_SIZE as u32);
+            args.finish()?;
+            let start_time_us = devices.rtc.get_time().timestamp_micros() as u64;
+            start_capture(index, CAPTURE_BUFFER_SIZE, snaplen, start_time_us)
+                .context("starting capture")?;
+            writeln!(console, "Capturing on net{index}").unwrap();
+        }
+        "stop" => {
+            let destination = args
+                .next()
+                .map(|destination| endpoint(destination, "destination"))
+                .transpose()?;
+            args.finish()?;
+            if capture_status().is_none() {
+                return Err("No capture in progress".into());
+            }
+            // Open the destination before stopping, so the capture isn't lost if it fails.
+            let mut sink = destination
+                .map(|destination| open_sink(devices, destination))
+                .transpose()?;
+            let buffer = stop_capture().unwrap();
+            writeln!(
+                console,
+                "Captured {} frames, {} dropped",
+                buffer.frames(),
+                buffer.dropped()
+            )
+            .unwrap();
+            if let Some(sink) = &mut sink {
+                let result = sink.write(devices, buffer.data());
+                let closed = sink.close(devices);
+                result?;
+                closed?;
+                writeln!(console, "Wrote {} bytes", buffer.data().len()).unwrap();
+            }
+        }
+        "status" => {
+            args.finish()?;
+            match capture_status() {
+                Some(status) => writeln!(
+                    console,
+                    "Capturing on net{}: {} frames, {} dropped, {} bytes",
+                    status.interface, status.frames, status.dropped, status.bytes
+                )
+                .unwrap(),
+                None => writeln!(console, "No capture in progress").unwrap(),
+            }
+        }
+        _ => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+/// Returns the index and IP configuration of the first network interface with an IP address.
+fn configured_interface(devices: &Devices) -> Result<(usize, IpConfig), CommandError> {
+    IP_CONFIGS
+        .lock()
+        .iter()
+        .map(|(&index, &config)| (index, config))
+        .find(|&(index, _)| index < devices.net.len())
+        .ok_or_else(|| {
+            "No network interface has an IP address, set one with net <interface> ip".into()
+        })
+}
+
+/// Returns a local port for an outgoing connection or datagram.
+///
+/// This varies so that a new connection isn't confused with an old one.
+fn ephemeral_port() -> u16 {
+    FIRST_EPHEMERAL_PORT + (Instant::now().ticks() % 16384) as u16
+}
+
+/// Sends a UDP datagram, or prints the datagrams received on a port until Ctrl-] is pressed,
+/// using the first network interface with an IP address.
+pub fn udp(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let subcommand = args.required_str("subcommand")?;
+    let Context {
+        console, devices, ..
+    } = context;
+    match subcommand {
+        "send" => {
+            let address: Ipv4Address = args.required("address")?;
+            let port = args.required("port")?;
+            let text = args.collect::<Vec<_>>().join(" ");
+            let (index, config) = configured_interface(devices)?;
+            let mut stack = IpStack::new(&mut *devices.net[index], config);
+            UdpSocket::bind(ephemeral_port())
+                .send_to(&mut stack, address, port, text.as_bytes())
+                .context("sending datagram")?;
+            writeln!(console, "Sent {} bytes to {address}:{port}", text.len()).unwrap();
+            Ok(())
+        }
+        "recv" => {
+            let port = args.required("port")?;
+            let count = args.optional("count")?;
+            args.finish()?;
+            let (index, config) = configured_interface(devices)?;
+            let mut stack = IpStack::new(&mut *devices.net[index], config);
+            let socket = UdpSocket::bind(port);
+            writeln!(
+                console,
+                "Listening on {}:{port}. Press Ctrl-] to stop.",
+                config.address
+            )
+            .unwrap();
+            let mut received = 0;
+            block_on(async {
+                while count.is_none_or(|count| received < count) {
+                    let mut buffer = [0; 64];
+                    if let Ok(bytes_read) =
+                        timeout(POLL_INTERVAL, console.read_async(&mut buffer)).await
+                    {
+                        let bytes_read = bytes_read.context("reading from console")?;
+                        if buffer[..bytes_read].contains(&DISCONNECT_KEY) {
+                            break;
+                        }
+                    }
+                    while let Some(datagram) =
+                        socket.recv_from(&mut stack).context("receiving datagram")?
+                    {
+                        writeln!(
+                            console,
+                            "{}:{}: {}",
+                            datagram.source,
+                            datagram.source_port,
+                            String::from_utf8_lossy(&datagram.payload).trim_end()
+                        )
+                        .unwrap();
+                        received += 1;
+                    }
+                }
+                Ok::<_, CommandError>(())
+            })?;
+            writeln!(console, "Received {received} datagrams").unwrap();
+            Ok(())
+        }
+        _ => Err(CommandError::Usage),
+    }
+}
+
+/// Connects to a TCP port using the first network interface with an IP address, and then passes
+/// data between it and the console until either side closes the connection.
+pub fn nc(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let address: Ipv4Address = args.required("address")?;
+    let port = args.required("port")?;
+    args.finish()?;
+    let Context {
+        console, devices, ..
+    } = context;
+    let (index, config) = configured_interface(devices)?;
+    let mut stack = IpStack::new(&mut *devices.net[index], config);
+    let local_port = ephemeral_port();
+
+    writeln!(console, "Connecting to {address}:{port} from net{index}...").unwrap();
+    let mut connection =
+        TcpConnection::connect(&mut stack, address, port, local_port, NC_CONNECT_TIMEOUT)
+            .context("connecting")?;
+    writeln!(console, "Connected. Press Ctrl-] to disconnect.").unwrap();
+
+    let result = block_on(async {
+        loop {
+            let mut buffer = [0; 64];
+            if let Ok(bytes_read) = timeout(POLL_INTERVAL, console.read_async(&mut buffer)).await {
+                let bytes_read = bytes_read.context("reading from console")?;
+                let input = &buffer[..bytes_read];
+                let escape = input.iter().position(|&byte| byte == DISCONNECT_KEY);
+                connection
+                    .send(&mut stack, &input[..escape.unwrap_or(input.len())])
+                    .context("sending")?;
+                if escape.is_some() {
+                    writeln!(console, "\r\nDisconnecting.").unwrap();
+                    return Ok(());
+                }
+            }
+            connection.poll(&mut stack).context("receiving")?;
+            let mut received = [0; 256];
+            loop {
+                let length = connection.recv(&mut received);
+                if length == 0 {
+                    break;
+                }
+                console.write_all(&received[..length]).unwrap();
+            }
+            if connection.peer_closed() {
+                writeln!(console, "Connection closed by peer.").unwrap();
+                return Ok(());
+            }
+        }
+    });
+    // Close our side too, even if something went wrong.
+    let closed = connection.close(&mut stack).context("closing connection");
+    result.and(closed)
+}
+
+/// Accepts a connection to the shell's TCP port if there isn't one already, or receives input from
+/// the current connection and closes it once the peer has closed its side and all the input has
+/// been read.
+///
+/// Returns the peer's address and port if a new connection was accepted.
+pub fn poll_tcp_console(devices: &mut Devices) -> Option<(Ipv4Address, u16)> {
+    let mut guard = TCP_CONSOLE.lock();
+    let console = guard.as_mut()?;
+    if !devices.net[console.index].enabled() {
+        return None;
+    }
+    let result = console.with_stack(devices, |console, stack| {
+        let Some(current) = &mut console.connection else {
+            let Some(connection) = console.listener.accept(stack)? else {
+                return Ok(None);
+            };
+            let (address, port) = connection.remote();
+            let mut current = TcpConsoleConnection {
+                connection,
+                decoder: TelnetDecoder::new(),
+                input: Vec::new(),
+            };
+            if console.telnet {
+                current.connection.send(stack, &INITIAL_NEGOTIATION)?;
+            }
+            console.connection = Some(current);
+            return Ok(Some((address, port)));
+        };
+        current.connection.poll(stack)?;
+        let mut buffer = [0; 256];
+        loop {
+            let length = current.connection.recv(&mut buffer);
+            if length == 0 {
+                break;
+            }
+            for &byte in &buffer[..length] {
+                if !console.telnet {
+                    current.input.push(byte);
+                    continue;
+                }
+                match current.decoder.push(byte) {
+                    Some(TelnetEvent::Data(byte)) => current.input.push(byte),
+                    Some(TelnetEvent::Reply(reply)) => current.connection.send(stack, &reply)?,
+                    None => {}
+                }
+            }
+        }
+        if current.connection.peer_closed() && current.input.is_empty() {
+            let current = console.connection.take().unwrap();
+            current.connection.close(stack)?;
+        }
+        Ok(None)
+    });
+    result.unwrap_or_else(|e| {
+        warn!("Error polling TCP console: {e}");
+        // The connection can't be used any more, so drop it.
+        console.connection = None;
+        None
+    })
+}
+
+/// Returns whether the shell is offered on a TCP port, so its interface needs to be polled.
+pub fn tcp_console_listening() -> bool {
+    TCP_CONSOLE.lock().is_some()
+}
+
+/// Reads input received from the connection from the given peer to the shell's TCP port into the
+/// given buffer, returning how many bytes were read.
+///
+/// Returns `None` if there is no longer a connection from the peer.
+pub fn tcp_console_recv(peer: (Ipv4Address, u16), buf: &mut [u8]) -> Option<usize> {
+    let mut guard = TCP_CONSOLE.lock();
+    let current = guard.as_mut()?.connection_from(peer)?;
+    let length = buf.len().min(current.input.len());
+    for (byte, input) in buf.iter_mut().zip(current.input.drain(..length)) {
+        *byte = input;
+    }
+    Some(length)
+}
+
+/// Sends the given output to the connection from the given peer to the shell's TCP port, if it is
+/// still connected, logging any error.
+pub fn tcp_console_send(devices: &mut Devices, peer: (Ipv4Address, u16), data: &[u8]) {
+    let mut guard = TCP_CONSOLE.lock();
+    let Some(console) = guard.as_mut() else {
+        return;
+    };
+    let result = console.with_stack(devices, |console, stack| {
+        let Some(current) = console.connection_from(peer) else {
+            return Ok(());
+        };
+        if console.telnet {
+            let mut encoded = Vec::with_capacity(data.len());
+            encode(data, &mut encoded);
+            current.connection.send(stack, &encoded)
+        } else {
+            current.connection.send(stack, data)
+        }
+    });
+    if let Err(e) = result {
+        warn!(
+            "Error sending to TCP console connection from {}:{}: {e}",
+            peer.0, peer.1
+        );
+    }
+}
+
+/// Closes the connection from the given peer to the shell's TCP port, if it is still connected,
+/// so that another can be accepted.
+pub fn close_tcp_console(devices: &mut Devices, peer: (Ipv4Address, u16)) {
+    let mut guard = TCP_CONSOLE.lock();
+    let Some(console) = guard.as_mut() else {
+        return;
+    };
+    if console.connection_from(peer).is_none() {
+        return;
+    }
+    let current = console.connection.take().unwrap();
+    let result = console.with_stack(devices, |_, stack| current.connection.close(stack));
+    if let Err(e) = result {
+        warn!(
+            "Error closing TCP console connection from {}:{}: {e}",
+            peer.0, peer.1
+        );
+    }
+}
+
+/// Shows or changes the TCP port which shell sessions are accepted on, using the first network
+/// interface with an IP address.
+pub fn tcpconsole(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let Context {
+        console, devices, ..
+    } = context;
+    let Some(port) = args.next() else {
+        match &*TCP_CONSOLE.lock() {
+            Some(tcp_console) => {
+                write!(
+                    console,
+                    "Accepting {} sessions on {}:{}",
+                    if tcp_console.telnet { "telnet" } else { "raw" },
+                    tcp_console.config.address,
+                    tcp_console.listener.local_port()
+                )
+                .unwrap();
+                match &tcp_console.connection {
+                    Some(current) => {
+                        let (address, port) = current.connection.remote();
+                        writeln!(console, ", connected from {address}:{port}").unwrap();
+                    }
+                    None => writeln!(console).unwrap(),
+                }
+            }
+            None => writeln!(console, "Not accepting sessions over TCP").unwrap(),
+        }
+        return Ok(());
+    };
+    if port == "off" {
+        args.finish()?;
+        // Any current session is ended by the session manager once the connection is gone.
+        let Some(mut tcp_console) = TCP_CONSOLE.lock().take() else {
+            return Ok(());
+        };
+        if let Some(current) = tcp_console.connection.take() {
+            tcp_console
+                .with_stack(devices, |_, stack| current.connection.close(stack))
+                .context("closing connection")?;
+        }
+        return Ok(());
+    }
+    let port = port.parse().map_err(|_| CommandError::InvalidArgument {
+        name: "port",
+        value: port.to_string(),
+    })?;
+    let telnet = match args.next() {
+        None | Some("raw") => false,
+        Some("telnet") => true,
+        Some(_) => return Err(CommandError::Usage),
+    };
+    args.finish()?;
+    if TCP_CONSOLE.lock().is_some() {
+        return Err("Already accepting sessions over TCP, turn it off first".into());
+    }
+    let (index, config) = configured_interface(devices)?;
+    *TCP_CONSOLE.lock() = Some(TcpConsole {
+        index,
+        config,
+        listener: TcpListener::bind(port),
+        telnet,
+        arp_cache: ArpCache::new(),
+        connection: None,
+    });
+    info!("Accepting shell sessions on TCP port {port} of net{index}");
+    writeln!(
+        console,
+        "Accepting {} sessions on {}:{port}",
+        if telnet { "telnet" } else { "raw" },
+        config.address
+    )
+    .unwrap();
+    Ok(())
+}
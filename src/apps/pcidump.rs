@@ -0,0 +1,175 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Inspection of arbitrary PCI devices, such as host devices passed through to the VM.
+
+use crate::apps::command::{Args, CommandError, Context, ErrorContext};
+use alloc::{format, vec::Vec};
+use core::ptr;
+use embedded_io::Write;
+use osdemo_core::pci_config::HexDump;
+use virtio_drivers::transport::pci::{
+    bus::{BarInfo, Command},
+    virtio_device_type,
+};
+
+/// The number of bytes of a BAR to dump if no length is given.
+const DEFAULT_BAR_DUMP_LENGTH: u64 = 256;
+
+pub fn pcidump(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let device_function = args.next_device_function()?;
+    let bar_dump = match args.next() {
+        None => None,
+        Some("bar") => Some((
+            args.required::<usize>("index")?,
+            args.optional::<u64>("offset")?.unwrap_or(0),
+            args.optional::<u64>("length")?
+                .unwrap_or(DEFAULT_BAR_DUMP_LENGTH),
+        )),
+        Some(_) => return Err(CommandError::Usage),
+    };
+    args.finish()?;
+    let Context {
+        console, pci_roots, ..
+    } = context;
+
+    let Some(pci_root) = pci_roots
+        .iter_mut()
+        .find(|pci_root| pci_root.read_config(device_function, 0) as u16 != 0xffff)
+    else {
+        return Err(CommandError::Failed(format!(
+            "No PCI device at {device_function}"
+        )));
+    };
+    let Some((_, info)) = pci_root
+        .enumerate_bus(device_function.bus)
+        .find(|(found, _)| *found == device_function)
+    else {
+        return Err(CommandError::Failed(format!(
+            "No PCI device at {device_function}"
+        )));
+    };
+    let bars = pci_root
+        .device_bars(device_function)
+        .context("reading BARs")?;
+
+    if let Some((index, offset, length)) = bar_dump {
+        let Some(Some(bar)) = bars.get(index) else {
+            return Err(CommandError::Failed(format!(
+                "{device_function} has no BAR {index}"
+            )));
+        };
+        if let BarInfo::IO { .. } = bar {
+            let io_bar = pci_root
+                .io_bar(device_function, index)
+                .ok_or("I/O BAR is not enabled.")?;
+            let (offset, length) = check_dump_range(offset, length, io_bar.size() as u64)?;
+            let data = (offset..offset + length)
+                .step_by(4)
+                .flat_map(|word_offset| io_bar.read::<u32>(word_offset as usize).to_le_bytes())
+                .collect::<Vec<_>>();
+            write!(
+                console,
+                "{}",
+                HexDump {
+                    address: offset as usize,
+                    data: &data,
+                }
+            )
+            .unwrap();
+            return Ok(());
+        }
+        let (_, command) = pci_root.get_status_command(device_function);
+        return dump_bar(console, bar, command, offset, length);
+    }
+
+    writeln!(console, "{info} at {device_function}").unwrap();
+    if let Some(virtio_type) = virtio_device_type(&info) {
+        writeln!(console, "VirtIO {virtio_type:?}").unwrap();
+    }
+    let (status, command) = pci_root.get_status_command(device_function);
+    writeln!(console, "Status {status:?} command {command:?}").unwrap();
+    if let Some((intid, trigger)) = pci_root.irqs.get(&device_function) {
+        writeln!(console, "Interrupt {intid:?} {trigger:?}").unwrap();
+    }
+
+    writeln!(console, "Configuration space:").unwrap();
+    write!(
+        console,
+        "{}",
+        HexDump {
+            address: 0,
+            data: &pci_root.config_space(device_function),
+        }
+    )
+    .unwrap();
+
+    writeln!(console, "BARs:").unwrap();
+    for (bar_index, bar) in bars.iter().enumerate() {
+        if let Some(bar) = bar {
+            writeln!(console, "  {bar_index}: {bar}").unwrap();
+        }
+    }
+
+    writeln!(console, "Capabilities:").unwrap();
+    for (capability, description) in pci_root.describe_capabilities(device_function) {
+        writeln!(console, "  {:#04x}: {description}", capability.offset).unwrap();
+    }
+    Ok(())
+}
+
+/// Checks that the given offset and length are word-aligned and start within a BAR of the given
+/// size, and returns them with the length trimmed to the end of the BAR.
+fn check_dump_range(offset: u64, length: u64, size: u64) -> Result<(u64, u64), CommandError> {
+    if offset % 4 != 0 || length % 4 != 0 {
+        return Err("Offset and length must be multiples of 4.".into());
+    }
+    if offset >= size {
+        return Err(CommandError::Failed(format!(
+            "Offset is beyond the end of the BAR ({size:#x} bytes)."
+        )));
+    }
+    Ok((offset, length.min(size - offset)))
+}
+
+/// Dumps part of the given memory BAR.
+///
+/// BARs are already identity-mapped as device memory when the PCI root is initialised, so this
+/// only needs to read from them. Reads are done a 32-bit word at a time, as many devices don't
+/// support other access sizes.
+fn dump_bar(
+    console: &mut impl Write,
+    bar: &BarInfo,
+    command: Command,
+    offset: u64,
+    length: u64,
+) -> Result<(), CommandError> {
+    let BarInfo::Memory { address, size, .. } = *bar else {
+        return Err("Only memory BARs can be dumped.".into());
+    };
+    if !command.contains(Command::MEMORY_SPACE) || address == 0 {
+        return Err("BAR is not enabled.".into());
+    }
+    let (offset, length) = check_dump_range(offset, length, size)?;
+    let start = address + offset;
+    let data = (start..start + length)
+        .step_by(4)
+        .flat_map(|word_address| {
+            // SAFETY: The BAR was mapped as device memory when the PCI root was initialised, and we
+            // checked that the address is within it and aligned. Reading a device's registers
+            // may have side effects on the device, but not on our memory.
+            unsafe { ptr::read_volatile(word_address as *const u32) }.to_le_bytes()
+        })
+        .collect::<Vec<_>>();
+    write!(
+        console,
+        "{}",
+        HexDump {
+            address: start as usize,
+            data: &data,
+        }
+    )
+    .unwrap();
+    Ok(())
+}
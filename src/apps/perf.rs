@@ -0,0 +1,30 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Measuring the CPU cycles and instructions which a shell command takes, with the PMU.
+
+use crate::apps::{
+    command::{Args, CommandError, Context},
+    shell::run_command,
+};
+use alloc::vec::Vec;
+use embedded_io::Write;
+use osdemo_core::pmu;
+
+/// Runs the given command line, and then prints the cycles, instructions and instructions per cycle
+/// it took on this core.
+///
+/// Time the core spent waiting for interrupts, and handling them, is included.
+pub fn perf(context: &mut Context, args: Args) -> Result<(), CommandError> {
+    let line = args.collect::<Vec<_>>().join(" ");
+    if line.is_empty() {
+        return Err(CommandError::MissingArgument("command"));
+    }
+    if !pmu::is_available() {
+        return Err("This CPU has no PMU, or too few event counters".into());
+    }
+    let (_status, counts) = pmu::measure(|| run_command(context, &line));
+    writeln!(context.console, "{counts}").unwrap();
+    Ok(())
+}
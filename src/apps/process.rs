@@ -0,0 +1,177 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Commands for running the embedded user programs at EL0, listing their processes and killing
+//! them.
+
+#[cfg(feature = "smp")]
+use crate::apps::cpus::worker_cpus;
+use crate::{
+    apps::command::{Args, CommandError, Context, ErrorContext},
+    user::{self, Pid, UserProgram},
+};
+use alloc::{string::ToString, vec::Vec};
+use embedded_io::Write;
+#[cfg(feature = "smp")]
+use log::{info, warn};
+#[cfg(feature = "smp")]
+use osdemo_core::workqueue::queue_work;
+
+/// Runs one of the embedded user programs, either in the foreground until it exits, or with a
+/// trailing `&` on a secondary core started by `start_cpu`.
+pub fn exec(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let name = args.required_str("program")?;
+    let program = UserProgram::from_name(name).ok_or_else(|| CommandError::InvalidArgument {
+        name: "program",
+        value: name.to_string(),
+    })?;
+    let mut rest: Vec<&str> = args.collect();
+    let background = rest.last() == Some(&"&");
+    if background {
+        rest.pop();
+    }
+    let argument = match rest.as_slice() {
+        [] => program.default_argument(),
+        [argument] => argument
+            .parse()
+            .map_err(|_| CommandError::InvalidArgument {
+                name: "argument",
+                value: argument.to_string(),
+            })?,
+        _ => return Err(CommandError::TooManyArguments),
+    };
+    if background {
+        run_in_background(context.console, program, argument)
+    } else {
+        run_in_foreground(context.console, program, argument)
+    }
+}
+
+pub fn runuser(context: &mut Context, args: Args) -> Result<(), CommandError> {
+    args.finish()?;
+    run_in_foreground(context.console, UserProgram::Hello, 0)
+}
+
+/// Lists the user processes which haven't yet exited, with the CPU time each has used if `-l` is
+/// given.
+pub fn ps(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let long = match args.next() {
+        None => false,
+        Some("-l") => true,
+        Some(value) => {
+            return Err(CommandError::InvalidArgument {
+                name: "flag",
+                value: value.to_string(),
+            });
+        }
+    };
+    args.finish()?;
+    let console = &mut *context.console;
+    if long {
+        write!(console, "    USER(s)   KERNEL(s)  ").unwrap();
+    }
+    writeln!(console, "  PID   PPID  CPU  PROGRAM  ARGUMENT").unwrap();
+    for process in user::processes() {
+        if long {
+            write!(
+                console,
+                "{:>11.6} {:>11.6}  ",
+                process.times.user().as_secs_f64(),
+                process.times.kernel().as_secs_f64()
+            )
+            .unwrap();
+        }
+        let parent = process
+            .parent
+            .map_or_else(|| "-".to_string(), |pid| pid.to_string());
+        let cpu = process
+            .cpu
+            .map_or_else(|| "-".to_string(), |cpu| cpu.to_string());
+        writeln!(
+            console,
+            "{:>5}  {parent:>5}  {cpu:>3}  {:<7}  {}",
+            process.pid,
+            process.program.name(),
+            process.argument
+        )
+        .unwrap();
+    }
+    Ok(())
+}
+
+/// Kills a user process and its descendants, which are stopped the next time they make a syscall
+/// or are interrupted.
+pub fn kill(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let pid: Pid = args.required("pid")?;
+    args.finish()?;
+    user::kill(pid).context("killing process")?;
+    writeln!(context.console, "Killed process {pid}").unwrap();
+    Ok(())
+}
+
+/// Runs the given user program on this core, and prints its exit status and the CPU time it used.
+fn run_in_foreground(
+    console: &mut (impl Write + ?Sized),
+    program: UserProgram,
+    argument: u64,
+) -> Result<(), CommandError> {
+    let pid = user::create_process(program, argument).context("creating process")?;
+    writeln!(
+        console,
+        "Running user program {} as process {pid}...",
+        program.name()
+    )
+    .unwrap();
+    let exit = user::run_process(pid).context("running process")?;
+    writeln!(
+        console,
+        "User program exited with status {} after {}",
+        exit.status, exit.times
+    )
+    .unwrap();
+    Ok(())
+}
+
+/// Starts the given user program on a secondary core, and logs its exit status once it exits.
+#[cfg(feature = "smp")]
+fn run_in_background(
+    console: &mut (impl Write + ?Sized),
+    program: UserProgram,
+    argument: u64,
+) -> Result<(), CommandError> {
+    let workers = worker_cpus();
+    if workers == 0 {
+        return Err("Start a secondary core with start_cpu first".into());
+    }
+    let cpu = workers.trailing_zeros() as usize;
+    let pid = user::create_process(program, argument).context("creating process")?;
+    queue_work(cpu, move || match user::run_process(pid) {
+        Ok(exit) => info!(
+            "Process {pid} exited with status {} after {}",
+            exit.status, exit.times
+        ),
+        Err(e) => warn!("Running process {pid} failed: {e}"),
+    })
+    .inspect_err(|_| {
+        // The process will never be run, so remove it rather than leaving it to block others.
+        let _ = user::kill(pid);
+    })
+    .context("queueing process")?;
+    writeln!(
+        console,
+        "Started user program {} as process {pid} on CPU {cpu}",
+        program.name()
+    )
+    .unwrap();
+    Ok(())
+}
+
+#[cfg(not(feature = "smp"))]
+fn run_in_background(
+    _console: &mut (impl Write + ?Sized),
+    _program: UserProgram,
+    _argument: u64,
+) -> Result<(), CommandError> {
+    Err("Running programs in the background needs the smp feature".into())
+}
@@ -0,0 +1,62 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A configurable shell prompt.
+
+use crate::apps::{
+    command::{Args, CommandError, Context},
+    timezone,
+};
+use alloc::vec::Vec;
+use embedded_io::Write;
+use osdemo_core::{cpus::current_cpu_index, rtc::RtcDevice};
+
+/// The prompt format used until it is changed with the `prompt` command.
+pub const DEFAULT_PROMPT: &str = "$";
+
+/// Writes the prompt for the given format to the console, followed by a space.
+///
+/// The format may contain the following placeholders:
+///
+/// - `%t`: the current time from the RTC, with the configured UTC offset.
+/// - `%c`: the index of the CPU the shell is running on.
+/// - `%?`: the exit status of the previous command.
+/// - `%%`: a literal `%`.
+pub fn write_prompt(
+    console: &mut (impl Write + ?Sized),
+    format: &str,
+    rtc: &dyn RtcDevice,
+    status: u8,
+) {
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            write!(console, "{c}").unwrap();
+            continue;
+        }
+        match chars.next() {
+            Some('t') => {
+                write!(console, "{}", timezone::local_time(rtc.get_time()).time()).unwrap()
+            }
+            Some('c') => write!(console, "{}", current_cpu_index()).unwrap(),
+            Some('?') => write!(console, "{status}").unwrap(),
+            Some('%') => write!(console, "%").unwrap(),
+            // Unrecognised placeholders are written as they are.
+            Some(other) => write!(console, "%{other}").unwrap(),
+            None => write!(console, "%").unwrap(),
+        }
+    }
+    write!(console, " ").unwrap();
+}
+
+/// Prints or changes the shell prompt format.
+pub fn prompt(context: &mut Context, args: Args) -> Result<(), CommandError> {
+    let parts = args.collect::<Vec<_>>();
+    match parts.as_slice() {
+        [] => writeln!(context.console, "{}", context.prompt).unwrap(),
+        ["reset"] => context.prompt = DEFAULT_PROMPT.into(),
+        parts => context.prompt = parts.join(" "),
+    }
+    Ok(())
+}
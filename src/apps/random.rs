@@ -0,0 +1,62 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Seeding the pseudorandom number generators used by tests, so that failing runs can be
+//! reproduced.
+
+use crate::apps::command::{Args, CommandError, Context};
+use embedded_io::Write;
+use log::info;
+use osdemo::prng::{Prng, mix, parse_seed};
+use osdemo_core::drivers::generic_timer::Instant;
+use spin::mutex::SpinMutex;
+
+/// The seed for the next run of a test which uses random data, or `None` if it hasn't been seeded
+/// yet.
+static SEED: SpinMutex<Option<u64>> = SpinMutex::new(None);
+
+/// Returns a seed derived from the counter, which varies with how long it took to get here.
+fn entropy_seed() -> u64 {
+    mix(Instant::now().ticks())
+}
+
+/// Returns a generator for one run of a test, and logs the seed it was created from.
+///
+/// The seed for the following run is then derived from this one, so running `srand` with the
+/// logged seed and then the same test again reproduces the run exactly.
+pub fn test_rng(test: &str) -> Prng {
+    let seed = {
+        let mut next_seed = SEED.lock();
+        let seed = next_seed.unwrap_or_else(entropy_seed);
+        *next_seed = Some(mix(seed));
+        seed
+    };
+    info!("{test}: random seed {seed:#x}");
+    Prng::new(seed)
+}
+
+/// Sets the seed for the next test run, or shows it if none is given.
+pub fn srand(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let seed = args.next();
+    args.finish()?;
+    match seed {
+        Some("random") => {
+            let seed = entropy_seed();
+            *SEED.lock() = Some(seed);
+            writeln!(context.console, "Seed set to {seed:#x}").unwrap();
+        }
+        Some(seed) => {
+            let seed = parse_seed(seed).ok_or_else(|| CommandError::InvalidArgument {
+                name: "seed",
+                value: seed.into(),
+            })?;
+            *SEED.lock() = Some(seed);
+        }
+        None => {
+            let seed = *SEED.lock().get_or_insert_with(entropy_seed);
+            writeln!(context.console, "Next seed: {seed:#x}").unwrap();
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,69 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Running scripts of shell commands, such as the boot script given by the `rc` kernel command line
+//! argument, which is run once devices have been found and before the first prompt.
+//!
+//! Scripts are read from a block device range such as `blk0:0+4096`, or from a vsock peer. There
+//! are no filesystems to read `/etc/rc` from.
+//!
+//! An `exit` command in the boot script powers off without showing a prompt, such as at the end of
+//! a soak test.
+
+use crate::apps::{
+    command::{Args, CommandError, Context, ErrorContext},
+    cp::read_endpoint,
+    shell::run_script,
+};
+use alloc::format;
+use embedded_io::Write;
+use log::{info, warn};
+use osdemo::{
+    endpoint::Endpoint,
+    rc::{BOOTARG, script_text},
+};
+use osdemo_core::fdt::{bootarg_value, bootargs};
+
+/// The largest script which will be read, in bytes.
+const MAX_SCRIPT_SIZE: usize = 16 * 1024;
+
+/// Runs the boot script given on the kernel command line, if there is one, logging any error
+/// reading it.
+pub fn run_boot_script(context: &mut Context) {
+    let Some(source) = bootargs(context.fdt).and_then(|args| bootarg_value(args, BOOTARG)) else {
+        return;
+    };
+    info!("Running boot script from {source}");
+    if let Err(e) = run_source(context, source) {
+        warn!("Error running boot script from {BOOTARG}={source}: {e}");
+    }
+}
+
+/// Runs the commands in a script read from a block device range or vsock peer, or the boot script
+/// given on the kernel command line.
+pub fn rc(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let source = args.next();
+    args.finish()?;
+    let source = match source {
+        Some(source) => source,
+        None => bootargs(context.fdt)
+            .and_then(|args| bootarg_value(args, BOOTARG))
+            .ok_or("No boot script given on the kernel command line")?,
+    };
+    run_source(context, source)
+}
+
+/// Reads the script from the given source and runs its commands.
+fn run_source(context: &mut Context, source: &str) -> Result<(), CommandError> {
+    let endpoint = Endpoint::parse(source).context("parsing script source")?;
+    if let Endpoint::Path(path) = endpoint {
+        return Err(CommandError::Failed(format!(
+            "Can't read {path}, as there are no filesystems to find it in"
+        )));
+    }
+    let script = read_endpoint(context.devices, endpoint, MAX_SCRIPT_SIZE)?;
+    let script = script_text(&script).map_err(|_| "Script isn't valid UTF-8")?;
+    run_script(context, script);
+    Ok(())
+}
@@ -0,0 +1,123 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Measuring the resources used while a shell command runs: heap allocated and freed, bytes written
+//! to the console and interrupts handled.
+//!
+//! This is reported after a command when it is prefixed with `-v`, or after every command once
+//! `set RUSAGE=1` has been run in the session.
+
+use crate::{
+    apps::command::{ConsoleError, Context, Terminal},
+    heap_stats,
+};
+use core::{
+    fmt::{self, Display, Formatter},
+    mem::take,
+    task::{self, Poll},
+};
+use embedded_io::{ErrorType, Write};
+use osdemo_core::interrupts::irq_counts;
+
+/// The resources used while running a command.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ResourceUsage {
+    /// The total size of heap allocations made.
+    pub allocated_bytes: u64,
+    /// The total size of heap allocations freed.
+    pub freed_bytes: u64,
+    /// The number of bytes the command wrote to its terminal.
+    pub written_bytes: u64,
+    /// The number of interrupts handled, on any core.
+    pub irqs: u64,
+}
+
+impl Display for ResourceUsage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Heap {} bytes allocated, {} bytes freed; console {} bytes written; {} IRQs handled",
+            self.allocated_bytes, self.freed_bytes, self.written_bytes, self.irqs
+        )
+    }
+}
+
+/// Runs the given function with a context whose terminal counts what is written to it, and returns
+/// its result along with the resources used while it ran.
+pub fn measure<T>(context: &mut Context, f: impl FnOnce(&mut Context) -> T) -> (T, ResourceUsage) {
+    let mut console = CountingTerminal {
+        inner: &mut *context.console,
+        written_bytes: 0,
+    };
+    let mut inner = Context {
+        console: &mut console,
+        pci_roots: &mut *context.pci_roots,
+        devices: &mut *context.devices,
+        fdt: context.fdt,
+        prompt: take(&mut context.prompt),
+        exit: context.exit,
+        rusage: context.rusage,
+        sessions: &mut *context.sessions,
+    };
+
+    let heap_before = heap_stats();
+    let irqs_before = irq_total();
+    let result = f(&mut inner);
+    let heap_after = heap_stats();
+    let irqs_after = irq_total();
+
+    let Context {
+        prompt,
+        exit,
+        rusage,
+        ..
+    } = inner;
+    context.prompt = prompt;
+    context.exit = exit;
+    context.rusage = rusage;
+    let usage = ResourceUsage {
+        allocated_bytes: heap_after.allocated_bytes - heap_before.allocated_bytes,
+        freed_bytes: heap_after.freed_bytes - heap_before.freed_bytes,
+        written_bytes: console.written_bytes,
+        irqs: irqs_after - irqs_before,
+    };
+    (result, usage)
+}
+
+/// Returns the total number of interrupts handled since boot.
+fn irq_total() -> u64 {
+    irq_counts().values().sum()
+}
+
+/// A terminal which passes everything through to another, counting the bytes written.
+struct CountingTerminal<'a> {
+    inner: &'a mut dyn Terminal,
+    written_bytes: u64,
+}
+
+impl ErrorType for CountingTerminal<'_> {
+    type Error = ConsoleError;
+}
+
+impl Write for CountingTerminal<'_> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let written = self.inner.write(buf)?;
+        self.written_bytes += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush()
+    }
+}
+
+impl Terminal for CountingTerminal<'_> {
+    fn poll_read(
+        &mut self,
+        context: &mut task::Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, ConsoleError>> {
+        self.inner.poll_read(context, buf)
+    }
+}
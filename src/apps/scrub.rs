@@ -0,0 +1,131 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Scrubbing free pages of the DMA pool in the background while the shell is idle, and reporting
+//! memory usage along with what the scrubber has found.
+
+use crate::{
+    apps::command::{Args, CommandError, Context},
+    heap_usage,
+};
+use embedded_io::Write;
+use log::warn;
+use osdemo::scrub::{ScrubMode, ScrubStats};
+use osdemo_core::{
+    dma,
+    executor::{IdleHook, set_idle_hook},
+    timer::uptime_us,
+};
+use spin::mutex::SpinMutex;
+
+/// How long to wait after finishing a pass over the free pages before starting another.
+///
+/// The next pass actually starts the next time the shell is idle after this, which may be later if
+/// nothing wakes it.
+const PASS_INTERVAL_US: u64 = 10_000_000;
+
+/// The state of the scrubber.
+static SCRUBBER: SpinMutex<Scrubber> = SpinMutex::new(Scrubber {
+    mode: ScrubMode::Off,
+    stats: ScrubStats {
+        passes: 0,
+        pages_scrubbed: 0,
+        pages_checked: 0,
+        corrupted_pages: 0,
+        bit_flips: 0,
+    },
+    next_page: 0,
+    last_pass_end_us: None,
+});
+
+/// What the scrubber is doing, and how far it has got.
+struct Scrubber {
+    mode: ScrubMode,
+    stats: ScrubStats,
+    /// The index in the DMA pool of the page to look for a free page from next.
+    next_page: usize,
+    /// The uptime when the last complete pass finished, if there has been one.
+    last_pass_end_us: Option<u64>,
+}
+
+/// Scrubs the next free page, if the scrubber is enabled and a pass is due.
+///
+/// Returns whether there are more pages to scrub in the current pass. This is the executor's idle
+/// hook while the scrubber is enabled.
+fn scrub_next_page() -> bool {
+    let mut scrubber = SCRUBBER.lock();
+    let Scrubber {
+        mode,
+        stats,
+        next_page,
+        last_pass_end_us,
+    } = &mut *scrubber;
+    if !mode.is_enabled() {
+        return false;
+    }
+    if *next_page == 0 && last_pass_end_us.is_some_and(|end| uptime_us() < end + PASS_INTERVAL_US) {
+        return false;
+    }
+    let mut flips = 0;
+    let mut flipped_paddr = 0;
+    let scrubbed = dma::scrub_free_page(*next_page, |paddr, page, was_scrubbed| {
+        flips = stats.scrub_page(*mode, page, was_scrubbed);
+        flipped_paddr = paddr;
+    });
+    if flips > 0 {
+        warn!("Found {flips} bits set in zeroed free page at {flipped_paddr:#x}");
+    }
+    if let Some(index) = scrubbed {
+        *next_page = index + 1;
+        true
+    } else {
+        stats.passes += 1;
+        *next_page = 0;
+        *last_pass_end_us = Some(uptime_us());
+        false
+    }
+}
+
+/// Shows the scrubber's mode and what it has found, or changes the mode.
+pub fn scrub(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let Some(mode) = args.next() else {
+        let scrubber = SCRUBBER.lock();
+        writeln!(
+            context.console,
+            "Scrubber {}: {}",
+            scrubber.mode, scrubber.stats
+        )
+        .unwrap();
+        return Ok(());
+    };
+    let mode = ScrubMode::parse(mode).ok_or_else(|| CommandError::InvalidArgument {
+        name: "mode",
+        value: mode.into(),
+    })?;
+    args.finish()?;
+    {
+        let mut scrubber = SCRUBBER.lock();
+        scrubber.mode = mode;
+        // Start a new pass straight away.
+        scrubber.next_page = 0;
+        scrubber.last_pass_end_us = None;
+    }
+    set_idle_hook(mode.is_enabled().then_some(scrub_next_page as IdleHook));
+    Ok(())
+}
+
+/// Prints the usage of the heap and DMA pool, and what the scrubber has found.
+pub fn meminfo(context: &mut Context, args: Args) -> Result<(), CommandError> {
+    args.finish()?;
+    let console = &mut *context.console;
+    writeln!(console, "Heap: {}", heap_usage()).unwrap();
+    if let Some(stats) = dma::stats() {
+        writeln!(console, "{stats}").unwrap();
+    } else {
+        writeln!(console, "DMA pool not initialised.").unwrap();
+    }
+    let scrubber = SCRUBBER.lock();
+    writeln!(console, "Scrubber {}: {}", scrubber.mode, scrubber.stats).unwrap();
+    Ok(())
+}
@@ -0,0 +1,700 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A battery of checks exercising each subsystem, so the demo can be used as a smoke test for VMM
+//! changes.
+
+#[cfg(feature = "smp")]
+use crate::apps::cpus::{power_off_current_cpu, worker_cpus};
+use crate::{
+    apps::{
+        alarm,
+        command::{Args, CommandError, Context},
+        cp::VSOCK0,
+        random::test_rng,
+    },
+    heap_usage,
+    user::{FAULT_EXIT_STATUS, ProcessError, UserProgram, run_user_program_captured},
+};
+use aarch64_paging::paging::{MemoryRegion, PAGE_SIZE};
+use alloc::{
+    alloc::{alloc_zeroed, dealloc},
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+#[cfg(feature = "smp")]
+use arm_gic::{IntId, irq_enable};
+use chrono::Duration;
+#[cfg(feature = "smp")]
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::{
+    alloc::Layout,
+    fmt::{self, Display, Formatter},
+    hint::spin_loop,
+};
+#[cfg(feature = "smp")]
+use dtoolkit::ToCellInt;
+use dtoolkit::fdt::Fdt;
+use embedded_io::Write;
+#[cfg(feature = "smp")]
+use osdemo_core::{
+    cpus::{cpu_count, current_cpu_index},
+    idle::wfi,
+    interrupts::{remove_private_irq_handler, send_sgi_to_all, set_private_irq_handler, with_gic},
+    psci::smc_for_psci,
+    secondary_entry::start_core_with_stack,
+    workqueue::{WorkHandle, run_on_cpu},
+};
+use osdemo_core::{
+    devices::Devices,
+    fallible::{try_push, try_reserve, try_vec},
+    pagetable::IdMap,
+    timer::{counter, counter_frequency, ms_to_ticks, wait_until},
+    tracked_heap::TrackedHeap,
+    virtio::VirtioHal,
+};
+#[cfg(feature = "smp")]
+use smccc::{
+    Hvc, Smc,
+    psci::{self, AffinityState, LowestAffinityLevel},
+};
+use virtio_drivers::{
+    device::socket::{
+        DisconnectReason, VMADDR_CID_HOST, VsockAddr, VsockConnectionManager, VsockEventType,
+    },
+    transport::SomeTransport,
+};
+
+/// The number of allocations made by the heap stress test.
+const HEAP_STRESS_ALLOCATIONS: usize = 512;
+
+/// The largest allocation made by the heap stress test.
+const HEAP_STRESS_MAX_SIZE: usize = 8192;
+
+/// The size of each allocation made by the allocation failure test while exhausting the heap.
+const ALLOC_STRESS_CHUNK_SIZE: usize = 1024;
+
+/// The number of pages given to the scratch page table for its tables.
+const SCRATCH_PAGETABLE_PAGES: usize = 16;
+
+/// The address at which the page table test maps its scratch region.
+const SCRATCH_REGION_START: usize = 0x8000_0000;
+
+/// The SGI sent to secondary cores by the SGI test.
+#[cfg(feature = "smp")]
+const SELFTEST_SGI: u32 = 7;
+
+/// How long to wait for secondary cores to start or receive an SGI, in milliseconds.
+#[cfg(feature = "smp")]
+const SGI_TIMEOUT_MS: u64 = 1000;
+
+/// How long to wait for a test running on another core to finish, in milliseconds.
+#[cfg(feature = "smp")]
+const REMOTE_TEST_TIMEOUT_MS: u64 = 5000;
+
+/// How long to wait for the RTC alarm, in milliseconds. The alarm is set for one second after a
+/// tick of the RTC.
+const ALARM_TIMEOUT_MS: u64 = 3000;
+
+/// The host port to which the vsock test connects, unless otherwise specified. Something on the
+/// host must echo back whatever is sent to it, e.g. `socat VSOCK-LISTEN:1234,fork EXEC:cat`.
+const DEFAULT_VSOCK_ECHO_PORT: u32 = 1234;
+
+/// The local port used by the vsock test.
+const VSOCK_LOCAL_PORT: u32 = 43;
+
+/// How long to wait for the vsock echo server, in milliseconds.
+const VSOCK_TIMEOUT_MS: u64 = 2000;
+
+/// The data sent to the vsock echo server.
+const VSOCK_PAYLOAD: &[u8] = b"osdemo selftest";
+
+/// Bitmask of secondary cores which are ready to receive the SGI, by CPU index.
+#[cfg(feature = "smp")]
+static SGI_READY: AtomicU64 = AtomicU64::new(0);
+
+/// Bitmask of secondary cores which have received the SGI, by CPU index.
+#[cfg(feature = "smp")]
+static SGI_RECEIVED: AtomicU64 = AtomicU64::new(0);
+
+/// The result of a single self-test.
+pub enum Outcome {
+    /// The test passed, with some optional details such as measurements.
+    Pass(String),
+    /// The test failed for the given reason.
+    Fail(String),
+    /// The test couldn't be run for the given reason, e.g. because a device is missing.
+    Skip(String),
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let (status, details) = match self {
+            Self::Pass(details) => ("PASS", details),
+            Self::Fail(reason) => ("FAIL", reason),
+            Self::Skip(reason) => ("SKIP", reason),
+        };
+        if details.is_empty() {
+            write!(f, "{status}")
+        } else {
+            write!(f, "{status} ({details})")
+        }
+    }
+}
+
+/// A test which may still be running on another core.
+enum PendingTest {
+    /// The test has already been run on this core.
+    Done(Outcome),
+    /// The test was queued to run on the given secondary core.
+    #[cfg(feature = "smp")]
+    Remote(usize, WorkHandle<Outcome>),
+}
+
+impl PendingTest {
+    /// Queues the given test to run on a secondary core started by `start_cpu` if there is one, or
+    /// else runs it on this core.
+    fn start(test: fn() -> Outcome) -> Self {
+        #[cfg(feature = "smp")]
+        {
+            let workers = worker_cpus();
+            if workers != 0 {
+                let cpu = workers.trailing_zeros() as usize;
+                if let Ok(handle) = run_on_cpu(cpu, test) {
+                    return Self::Remote(cpu, handle);
+                }
+            }
+        }
+        Self::Done(test())
+    }
+
+    /// Waits for the test to finish, and returns its outcome.
+    fn finish(self) -> Outcome {
+        match self {
+            Self::Done(outcome) => outcome,
+            #[cfg(feature = "smp")]
+            Self::Remote(cpu, handle) => handle.wait(REMOTE_TEST_TIMEOUT_MS).unwrap_or_else(|| {
+                Outcome::Fail(format!(
+                    "didn't finish on CPU {cpu} within {REMOTE_TEST_TIMEOUT_MS} ms"
+                ))
+            }),
+        }
+    }
+}
+
+/// Counts of the outcomes of the tests run so far.
+#[derive(Default)]
+struct Summary {
+    passed: usize,
+    failed: usize,
+    skipped: usize,
+}
+
+impl Summary {
+    /// Prints the outcome of the given test, and counts it.
+    fn record(&mut self, console: &mut (impl Write + ?Sized), name: &str, outcome: Outcome) {
+        match outcome {
+            Outcome::Pass(_) => self.passed += 1,
+            Outcome::Fail(_) => self.failed += 1,
+            Outcome::Skip(_) => self.skipped += 1,
+        }
+        writeln!(console, "selftest {name}: {outcome}").unwrap();
+    }
+}
+
+pub fn selftest(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let vsock_port = args
+        .optional("vsock_port")?
+        .unwrap_or(DEFAULT_VSOCK_ECHO_PORT);
+    args.finish()?;
+    let Context {
+        console,
+        devices,
+        fdt,
+        ..
+    } = context;
+
+    let mut summary = Summary::default();
+    // The heap test checks that nothing else is allocating while it runs.
+    summary.record(console, "heap", test_heap());
+    summary.record(console, "alloc_stress", test_alloc_stress());
+    // The page table test doesn't use any devices, so it can run on another core while this one
+    // waits for the RTC alarm.
+    let pagetable = PendingTest::start(test_pagetable);
+    let rtc_alarm = test_rtc_alarm(devices);
+    summary.record(console, "pagetable", pagetable.finish());
+    summary.record(console, "rtc_alarm", rtc_alarm);
+    summary.record(console, "sgi", test_sgi(fdt));
+    summary.record(console, "user_programs", test_user_programs());
+    summary.record(console, "virtio_blk", test_virtio_blk(devices));
+    summary.record(console, "vsock", test_vsock(devices, vsock_port));
+    writeln!(
+        console,
+        "selftest summary: {} passed, {} failed, {} skipped",
+        summary.passed, summary.failed, summary.skipped
+    )
+    .unwrap();
+
+    if summary.failed == 0 {
+        Ok(())
+    } else {
+        Err(CommandError::Failed(format!(
+            "{} self-tests failed",
+            summary.failed
+        )))
+    }
+}
+
+/// Returns the number of microseconds in the given number of counter ticks.
+fn ticks_to_us(ticks: u64) -> u64 {
+    (u128::from(ticks) * 1_000_000 / u128::from(counter_frequency())) as u64
+}
+
+/// Makes and frees allocations of random sizes, checking that they don't overlap and that all
+/// memory is freed afterwards.
+pub fn test_heap() -> Outcome {
+    let mut rng = test_rng("selftest heap");
+    let used_before = heap_usage().used_bytes;
+    let mut allocations: Vec<(u8, Vec<u8>)> = Vec::new();
+    if allocations.try_reserve(HEAP_STRESS_ALLOCATIONS).is_err() {
+        return Outcome::Fail("couldn't allocate list of allocations".into());
+    }
+    for i in 0..HEAP_STRESS_ALLOCATIONS {
+        let size = 1 + rng.below(HEAP_STRESS_MAX_SIZE as u64) as usize;
+        let tag = i as u8;
+        let mut allocation = Vec::new();
+        if allocation.try_reserve_exact(size).is_err() {
+            return Outcome::Fail(format!("allocation {i} of {size} bytes failed"));
+        }
+        allocation.resize(size, tag);
+        allocations.push((tag, allocation));
+        // Free some allocations along the way, so later ones can reuse the space.
+        if i % 3 == 2 {
+            allocations.swap_remove(rng.below(allocations.len() as u64) as usize);
+        }
+    }
+    let count = allocations.len();
+    if let Some((tag, _)) = allocations
+        .iter()
+        .find(|(tag, allocation)| allocation.iter().any(|byte| byte != tag))
+    {
+        return Outcome::Fail(format!("allocation with tag {tag} was corrupted"));
+    }
+    drop(allocations);
+
+    let used_after = heap_usage().used_bytes;
+    if used_after != used_before {
+        return Outcome::Fail(format!(
+            "{used_before} bytes used before, {used_after} bytes after"
+        ));
+    }
+    Outcome::Pass(format!(
+        "{HEAP_STRESS_ALLOCATIONS} allocations, {count} live at the end"
+    ))
+}
+
+/// Allocates fallibly until the heap is exhausted, checking that the failure is reported as an error
+/// rather than aborting, and that the memory can be allocated again once it has all been freed.
+pub fn test_alloc_stress() -> Outcome {
+    let usage_before = heap_usage();
+    if try_vec(0u8, usage_before.total_bytes + 1).is_ok() {
+        return Outcome::Fail("allocation larger than the heap succeeded".into());
+    }
+
+    let mut allocations = Vec::new();
+    if try_reserve(
+        &mut allocations,
+        usage_before.total_bytes / ALLOC_STRESS_CHUNK_SIZE,
+    )
+    .is_err()
+    {
+        return Outcome::Fail("couldn't allocate list of allocations".into());
+    }
+    // Nothing in this loop may allocate infallibly, as the heap is about to run out.
+    let error = loop {
+        let allocation = match try_vec(0xa5u8, ALLOC_STRESS_CHUNK_SIZE) {
+            Ok(allocation) => allocation,
+            Err(e) => break e,
+        };
+        if let Err(e) = try_push(&mut allocations, allocation) {
+            break e;
+        }
+    };
+    let count = allocations.len();
+    let corrupted = allocations
+        .iter()
+        .any(|allocation| allocation.iter().any(|&byte| byte != 0xa5));
+    drop(allocations);
+
+    if corrupted {
+        return Outcome::Fail("allocation made before running out of memory was corrupted".into());
+    }
+    let used_after = heap_usage().used_bytes;
+    if used_after != usage_before.used_bytes {
+        return Outcome::Fail(format!(
+            "{} bytes used before, {used_after} bytes after",
+            usage_before.used_bytes
+        ));
+    }
+    if try_vec(0u8, count / 2 * ALLOC_STRESS_CHUNK_SIZE).is_err() {
+        return Outcome::Fail("couldn't allocate again after recovering".into());
+    }
+    Outcome::Pass(format!(
+        "{count} allocations of {ALLOC_STRESS_CHUNK_SIZE} bytes before \"{error}\""
+    ))
+}
+
+/// Maps and unmaps regions in a scratch page table which is never activated, checking the
+/// resulting translations.
+pub fn test_pagetable() -> Outcome {
+    let layout = Layout::from_size_align(SCRATCH_PAGETABLE_PAGES * PAGE_SIZE, PAGE_SIZE).unwrap();
+    // SAFETY: The layout has a non-zero size.
+    let pages = unsafe { alloc_zeroed(layout) };
+    if pages.is_null() {
+        return Outcome::Fail("couldn't allocate pages for scratch page table".into());
+    }
+    let mut page_allocator = TrackedHeap::new();
+    // SAFETY: We have just allocated the pages, and only free them after the scratch page table
+    // which uses them has been dropped.
+    unsafe {
+        page_allocator.init(pages as usize, layout.size());
+    }
+    let outcome = check_scratch_mappings(IdMap::new(page_allocator));
+    // SAFETY: The pages were allocated above with the same layout, and the page table using them
+    // has been dropped.
+    unsafe {
+        dealloc(pages, layout);
+    }
+    outcome
+}
+
+fn check_scratch_mappings(mut idmap: IdMap) -> Outcome {
+    let start = SCRATCH_REGION_START;
+    let page = |index: usize| start + index * PAGE_SIZE;
+    if let Err(e) = idmap.map_memory(&MemoryRegion::new(start, page(3))) {
+        return Outcome::Fail(format!("mapping memory failed: {e}"));
+    }
+    if let Err(e) = idmap.map_device(&MemoryRegion::new(page(4), page(5))) {
+        return Outcome::Fail(format!("mapping device failed: {e}"));
+    }
+    for address in [start, page(1) + 0x123, page(4) + 8] {
+        let pa = idmap.translate(address);
+        if pa != Some(address) {
+            return Outcome::Fail(format!("{address:#x} translated to {pa:x?}"));
+        }
+    }
+    if let Some(pa) = idmap.translate(page(3)) {
+        return Outcome::Fail(format!("unmapped {:#x} translated to {pa:#x}", page(3)));
+    }
+
+    if let Err(e) = idmap.unmap(&MemoryRegion::new(page(1), page(2))) {
+        return Outcome::Fail(format!("unmapping failed: {e}"));
+    }
+    let expected = [Some(start), None, Some(page(2)), None, Some(page(4))];
+    for (index, expected) in expected.into_iter().enumerate() {
+        let pa = idmap.translate(page(index));
+        if pa != expected {
+            return Outcome::Fail(format!(
+                "after unmap {:#x} translated to {pa:x?}",
+                page(index)
+            ));
+        }
+    }
+
+    if let Err(e) = idmap.unmap(&MemoryRegion::new(start, page(5))) {
+        return Outcome::Fail(format!("unmapping failed: {e}"));
+    }
+    if let Some(address) = (0..5)
+        .map(page)
+        .find(|&address| idmap.translate(address).is_some())
+    {
+        return Outcome::Fail(format!("{address:#x} still mapped after unmapping all"));
+    }
+    Outcome::Pass(String::new())
+}
+
+/// Sets an RTC alarm for one second after the next tick, and measures how late the alarm IRQ is.
+fn test_rtc_alarm(devices: &mut Devices) -> Outcome {
+    let rtc = &mut *devices.rtc;
+    alarm::irq_finish(rtc);
+
+    let previous = rtc.get_time();
+    let mut now = previous;
+    if !wait_until(ALARM_TIMEOUT_MS, || {
+        now = rtc.get_time();
+        now != previous
+    }) {
+        return Outcome::Fail("RTC isn't ticking".into());
+    }
+    let start = counter();
+    if let Err(e) = rtc.set_match(now + Duration::seconds(1)) {
+        return Outcome::Fail(format!("setting alarm failed: {e}"));
+    }
+    rtc.enable_interrupt(true);
+    let fired = wait_until(ALARM_TIMEOUT_MS, alarm::fired);
+    let elapsed_us = ticks_to_us(counter() - start);
+    alarm::irq_finish(rtc);
+
+    if fired {
+        Outcome::Pass(format!(
+            "latency {} us",
+            elapsed_us.saturating_sub(1_000_000)
+        ))
+    } else {
+        Outcome::Fail(format!("alarm didn't fire within {ALARM_TIMEOUT_MS} ms"))
+    }
+}
+
+/// Starts all secondary cores which are off, sends an SGI to all of them, and checks that each
+/// receives it.
+#[cfg(feature = "smp")]
+fn test_sgi(fdt: &Fdt) -> Outcome {
+    let current_cpu = current_cpu_index();
+    SGI_READY.store(0, Ordering::SeqCst);
+    SGI_RECEIVED.store(0, Ordering::SeqCst);
+
+    let mut started = 0u64;
+    for (index, cpu) in fdt.cpus().unwrap().cpus().take(cpu_count()).enumerate() {
+        if index == current_cpu || index >= u64::BITS as usize {
+            continue;
+        }
+        let id = cpu.ids().unwrap().next().unwrap().to_int::<u64>().unwrap();
+        let state = if smc_for_psci() {
+            psci::affinity_info::<Smc>(id, LowestAffinityLevel::All)
+        } else {
+            psci::affinity_info::<Hvc>(id, LowestAffinityLevel::All)
+        };
+        match state {
+            Ok(AffinityState::Off) => {}
+            // Cores which are already running aren't waiting for our SGI.
+            Ok(_) => continue,
+            Err(e) => return Outcome::Fail(format!("getting CPU {index} state failed: {e}")),
+        }
+        if let Err(e) = start_core_with_stack(id, sgi_secondary_entry) {
+            return Outcome::Fail(format!("starting CPU {index} failed: {e}"));
+        }
+        started |= 1 << index;
+    }
+    if started == 0 {
+        return Outcome::Skip("no secondary cores are off".into());
+    }
+
+    if !wait_until(SGI_TIMEOUT_MS, || {
+        SGI_READY.load(Ordering::SeqCst) == started
+    }) {
+        return Outcome::Fail(format!(
+            "cores {started:#x} started but only {:#x} ready",
+            SGI_READY.load(Ordering::SeqCst)
+        ));
+    }
+    send_sgi_to_all(IntId::sgi(SELFTEST_SGI));
+    if !wait_until(SGI_TIMEOUT_MS, || {
+        SGI_RECEIVED.load(Ordering::SeqCst) == started
+    }) {
+        return Outcome::Fail(format!(
+            "SGI sent to cores {started:#x} but only received by {:#x}",
+            SGI_RECEIVED.load(Ordering::SeqCst)
+        ));
+    }
+    Outcome::Pass(format!("{} cores", started.count_ones()))
+}
+
+/// Entry point for secondary cores started by the SGI test. Waits for the SGI, then turns the core
+/// off again.
+#[cfg(feature = "smp")]
+fn sgi_secondary_entry() {
+    let cpu = current_cpu_index();
+    let sgi = IntId::sgi(SELFTEST_SGI);
+    with_gic(|gic| {
+        gic.enable_interrupt(sgi, Some(cpu), true).unwrap();
+        gic.set_interrupt_priority(sgi, Some(cpu), 0x80).unwrap();
+    });
+    set_private_irq_handler(sgi, &sgi_received);
+    irq_enable();
+    SGI_READY.fetch_or(1 << cpu, Ordering::SeqCst);
+
+    while SGI_RECEIVED.load(Ordering::SeqCst) & (1 << cpu) == 0 {
+        wfi();
+    }
+    remove_private_irq_handler(sgi);
+    power_off_current_cpu();
+}
+
+#[cfg(feature = "smp")]
+fn sgi_received(_intid: IntId) {
+    SGI_RECEIVED.fetch_or(1 << current_cpu_index(), Ordering::SeqCst);
+}
+
+/// Reports the SGI test as skipped, as secondary cores can't be started without the `smp` feature.
+#[cfg(not(feature = "smp"))]
+fn test_sgi(_fdt: &Fdt) -> Outcome {
+    Outcome::Skip("built without smp support".into())
+}
+
+/// Writes random data to the last block of the first writable VirtIO block device, reads it back,
+/// and then restores the original contents.
+fn test_virtio_blk(devices: &mut Devices) -> Outcome {
+    let Some(device) = devices
+        .block
+        .iter_mut()
+        .map(|device| device.uncached())
+        .find_map(|device| {
+            (!device.readonly() && device.as_virtio_blk().is_some()).then_some(device)
+        })
+    else {
+        return Outcome::Skip("no writable VirtIO block device".into());
+    };
+    if device.capacity() == 0 {
+        return Outcome::Skip("VirtIO block device is empty".into());
+    }
+    let block_id = device.capacity() - 1;
+    let block_size = device.block_size();
+
+    let mut original = vec![0; block_size];
+    if let Err(e) = device.read_blocks(block_id, &mut original) {
+        return Outcome::Fail(format!("reading block {block_id} failed: {e}"));
+    }
+    let mut pattern = vec![0; block_size];
+    test_rng("selftest virtio_blk").fill_bytes(&mut pattern);
+    let mut read_back = vec![0; block_size];
+    let result = device
+        .write_blocks(block_id, &pattern)
+        .and_then(|()| device.read_blocks(block_id, &mut read_back));
+    let restored = device.write_blocks(block_id, &original);
+
+    match (result, restored) {
+        (Err(e), _) => Outcome::Fail(format!("round trip of block {block_id} failed: {e}")),
+        (Ok(()), Err(e)) => Outcome::Fail(format!("restoring block {block_id} failed: {e}")),
+        (Ok(()), Ok(())) if read_back != pattern => {
+            Outcome::Fail(format!("block {block_id} read back differently"))
+        }
+        (Ok(()), Ok(())) => Outcome::Pass(format!("block {block_id}")),
+    }
+}
+
+/// Runs each of the embedded user programs at EL0, and checks their output and exit status.
+fn test_user_programs() -> Outcome {
+    let cases: [(UserProgram, u64, &[u8], u64); 6] = [
+        (UserProgram::Hello, 0, b"Hello from EL0!\n", 42),
+        (UserProgram::Yes, 3, b"y\ny\ny\n", 0),
+        (UserProgram::Fault, 0, b"", FAULT_EXIT_STATUS),
+        (UserProgram::Fork, 0, b"child\nparent\n", 0),
+        (UserProgram::Mmap, 0, b"mapped\n", 0),
+        (UserProgram::Futex, 0, b"timed out\n", 0),
+    ];
+    for (program, argument, expected_output, expected_status) in cases {
+        let name = program.name();
+        let (status, output) = match run_user_program_captured(program, argument) {
+            Ok(result) => result,
+            Err(e @ (ProcessError::UnsupportedEl | ProcessError::Busy(_))) => {
+                return Outcome::Skip(e.to_string());
+            }
+            Err(e) => return Outcome::Fail(format!("running {name} failed: {e}")),
+        };
+        if output != expected_output {
+            return Outcome::Fail(format!(
+                "{name} wrote {:?}, expected {:?}",
+                String::from_utf8_lossy(&output),
+                String::from_utf8_lossy(expected_output)
+            ));
+        }
+        if status != expected_status {
+            return Outcome::Fail(format!(
+                "{name} exited with status {status}, expected {expected_status}"
+            ));
+        }
+    }
+    Outcome::Pass(format!("{} programs", cases.len()))
+}
+
+/// Connects to an echo server on the host, sends it some data and checks that the same data comes
+/// back.
+fn test_vsock(devices: &mut Devices, port: u32) -> Outcome {
+    let Some(vsock) = devices.vsock.first_mut() else {
+        return Outcome::Skip("no vsock device".into());
+    };
+    let claim = match devices.owners.claim(VSOCK0, "selftest") {
+        Ok(claim) => claim,
+        Err(e) => return Outcome::Skip(e.to_string()),
+    };
+    let outcome = test_vsock_echo(vsock, port);
+    devices.owners.release(claim);
+    outcome
+}
+
+/// Runs the vsock echo test on the given vsock device.
+fn test_vsock_echo(
+    vsock: &mut VsockConnectionManager<VirtioHal, SomeTransport<'static>>,
+    port: u32,
+) -> Outcome {
+    let peer = VsockAddr {
+        cid: VMADDR_CID_HOST,
+        port,
+    };
+    if let Err(e) = vsock.connect(peer, VSOCK_LOCAL_PORT) {
+        return Outcome::Fail(format!("connecting failed: {e}"));
+    }
+
+    let deadline = counter() + ms_to_ticks(VSOCK_TIMEOUT_MS);
+    let mut received = Vec::new();
+    let outcome = loop {
+        if counter() >= deadline {
+            break Outcome::Fail(format!(
+                "timed out after receiving {} bytes",
+                received.len()
+            ));
+        }
+        let event = match vsock.poll() {
+            Ok(Some(event)) => event,
+            Ok(None) => {
+                spin_loop();
+                continue;
+            }
+            Err(e) => break Outcome::Fail(format!("polling failed: {e}")),
+        };
+        if event.destination.port != VSOCK_LOCAL_PORT || event.source != peer {
+            continue;
+        }
+        match event.event_type {
+            VsockEventType::Connected => {
+                if let Err(e) = vsock.send(peer, VSOCK_LOCAL_PORT, VSOCK_PAYLOAD) {
+                    break Outcome::Fail(format!("sending failed: {e}"));
+                }
+            }
+            VsockEventType::Disconnected {
+                reason: DisconnectReason::Reset,
+            } if received.is_empty() => {
+                // Nothing was listening, so don't count this as a failure of the guest.
+                return Outcome::Skip(format!("no echo server on host port {port}"));
+            }
+            VsockEventType::Disconnected { .. } => {
+                return Outcome::Fail(format!(
+                    "disconnected after receiving {} bytes",
+                    received.len()
+                ));
+            }
+            VsockEventType::Received { .. } => {
+                let mut buffer = [0; VSOCK_PAYLOAD.len()];
+                let bytes_read = match vsock.recv(peer, VSOCK_LOCAL_PORT, &mut buffer) {
+                    Ok(bytes_read) => bytes_read,
+                    Err(e) => break Outcome::Fail(format!("receiving failed: {e}")),
+                };
+                received.extend_from_slice(&buffer[..bytes_read]);
+                if received.len() >= VSOCK_PAYLOAD.len() {
+                    break if received == VSOCK_PAYLOAD {
+                        Outcome::Pass(format!("{} bytes echoed", received.len()))
+                    } else {
+                        Outcome::Fail("echoed data didn't match".into())
+                    };
+                }
+            }
+            _ => {}
+        }
+    };
+    // The connection may already have been closed by the peer, in which case this fails harmlessly.
+    let _ = vsock.force_close(peer, VSOCK_LOCAL_PORT);
+    outcome
+}
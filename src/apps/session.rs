@@ -0,0 +1,804 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A session manager, which runs an independent shell on each terminal: the console UART, each
+//! VirtIO console, each connection to a vsock port which is being listened on, and the connection
+//! to the TCP port which the shell is offered on with `tcpconsole`, if any.
+//!
+//! All sessions are polled together by a single future, so a line entered on any terminal is run
+//! as soon as no other command is running. Each session has its own line editor history, prompt
+//! and exit status. Commands run in sessions other than the UART's can't write to their terminal
+//! directly, as the device is also available to the command, so their output is buffered and sent
+//! once the command finishes.
+//!
+//! A session on a vsock connection is detached rather than ended if the connection drops, or if
+//! `sessions detach` is run in it. It keeps its state and a scrollback buffer of recent output, and
+//! can be reattached from another vsock session with `sessions attach`.
+//!
+//! The session manager owns the vsock device while it is accepting sessions or any are connected,
+//! as it would otherwise lose events to commands using the device, and they to it.
+
+#[cfg(feature = "net")]
+use crate::apps::net;
+use crate::{
+    apps::{
+        balloon,
+        command::{Args, CommandError, ConsoleError, Context, Terminal},
+        cp::VSOCK0,
+        line_editor::{LineEditor, LineEvent},
+        prompt::{DEFAULT_PROMPT, write_prompt},
+        rc::run_boot_script,
+        settings,
+        shell::run_command,
+    },
+    console::Console,
+    platform::ConsoleImpl,
+};
+use alloc::{format, string::String, vec, vec::Vec};
+use core::{
+    fmt::{self, Display, Formatter},
+    future::poll_fn,
+    mem::take,
+    str,
+    task::{self, Poll},
+};
+use dtoolkit::fdt::Fdt;
+use embedded_io::{ErrorType, Write};
+use log::{info, warn};
+use osdemo::log_buffer::LogBuffer;
+#[cfg(feature = "net")]
+use osdemo_core::ipv4::Ipv4Address;
+use osdemo_core::{
+    device_id::{DeviceId, DeviceKind},
+    device_owners::DeviceClaim,
+    devices::Devices,
+    events::{DeviceEvent, Subscriber},
+    executor::block_on,
+    pci::PciRootComplex,
+    shutdown,
+};
+use virtio_drivers::device::socket::{VsockAddr, VsockEventType};
+
+/// The exit status of the previous command, before any command has been run.
+const INITIAL_STATUS: u8 = 0;
+
+/// The owner of the vsock device while sessions use it.
+const SESSIONS_OWNER: &str = "sessions";
+
+/// The amount of recent output kept for each session, to replay when it is reattached, in bytes.
+const SCROLLBACK_SIZE: usize = 4096;
+
+/// The terminal which a session is on.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SessionTerminal {
+    /// The console UART.
+    Uart,
+    /// The VirtIO console with the given index.
+    VirtioConsole(usize),
+    /// A connection from the given peer to the given local vsock port.
+    Vsock { peer: VsockAddr, port: u32 },
+    /// A connection to the shell's TCP port from the given peer address and port.
+    #[cfg(feature = "net")]
+    Tcp { peer: Ipv4Address, port: u16 },
+    /// No terminal, as the session's vsock connection was closed.
+    Detached,
+}
+
+impl Display for SessionTerminal {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Uart => write!(f, "uart"),
+            Self::VirtioConsole(number) => write!(
+                f,
+                "{}",
+                DeviceId {
+                    kind: DeviceKind::Console,
+                    number: *number
+                }
+            ),
+            Self::Vsock { peer, .. } => write!(f, "vsock {}:{}", peer.cid, peer.port),
+            #[cfg(feature = "net")]
+            Self::Tcp { peer, port } => write!(f, "tcp {peer}:{port}"),
+            Self::Detached => write!(f, "detached"),
+        }
+    }
+}
+
+/// A shell running on a terminal.
+struct Session {
+    terminal: SessionTerminal,
+    editor: LineEditor,
+    /// The format of the shell prompt, as described by `prompt::write_prompt`.
+    prompt: String,
+    /// The exit status of the previous command.
+    status: u8,
+    /// Whether to report the resources used by each command, as set by `set RUSAGE=1`.
+    rusage: bool,
+    /// Output waiting to be sent to the terminal. This is always empty for the UART, which is
+    /// written to directly.
+    output: OutputBuffer,
+    /// Recent output sent to the terminal, to replay when the session is reattached.
+    scrollback: LogBuffer<SCROLLBACK_SIZE>,
+}
+
+impl Session {
+    fn new(terminal: SessionTerminal) -> Self {
+        Self {
+            terminal,
+            editor: LineEditor::new(),
+            prompt: DEFAULT_PROMPT.into(),
+            status: INITIAL_STATUS,
+            rusage: false,
+            output: OutputBuffer::default(),
+            scrollback: LogBuffer::new(),
+        }
+    }
+}
+
+/// A terminal which buffers output to be sent later, and has no input for commands.
+#[derive(Debug, Default)]
+struct OutputBuffer {
+    data: Vec<u8>,
+}
+
+impl ErrorType for OutputBuffer {
+    type Error = ConsoleError;
+}
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl Terminal for OutputBuffer {
+    fn poll_read(
+        &mut self,
+        _context: &mut task::Context,
+        _buf: &mut [u8],
+    ) -> Poll<Result<usize, ConsoleError>> {
+        // Input is only read between commands, by the session manager.
+        Poll::Pending
+    }
+}
+
+/// The shell sessions on all terminals.
+pub struct SessionManager {
+    /// The sessions, starting with the UART's.
+    sessions: Vec<Session>,
+    /// The vsock port on which connections are accepted as new sessions, if any.
+    vsock_port: Option<u32>,
+    /// The claim on the vsock device, while sessions are accepted on it or use it.
+    vsock_claim: Option<DeviceClaim>,
+    /// The index of the session which the current command is running in.
+    current: usize,
+    /// A change to make to the current session once the current command finishes.
+    pending: Option<PendingChange>,
+    /// Device events, to start sessions on new consoles.
+    device_events: Subscriber,
+}
+
+/// A change to the current session requested by a command, to be made once it finishes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum PendingChange {
+    /// Move the current session's connection to the detached session with the given index.
+    Attach(usize),
+    /// Detach the current session from its connection.
+    Detach,
+}
+
+impl SessionManager {
+    fn new() -> Self {
+        Self {
+            sessions: vec![Session::new(SessionTerminal::Uart)],
+            vsock_port: None,
+            vsock_claim: None,
+            current: 0,
+            pending: None,
+            device_events: Subscriber::new(),
+        }
+    }
+
+    /// Starts a new session on the given terminal, and sends it a greeting and the prompt.
+    fn start(&mut self, terminal: SessionTerminal, devices: &mut Devices) {
+        info!("Starting session on {terminal}");
+        let mut session = Session::new(terminal);
+        writeln!(session.output, "osdemo session on {terminal}").unwrap();
+        write_prompt(
+            &mut session.output,
+            &session.prompt,
+            &*devices.rtc,
+            session.status,
+        );
+        send_output(&mut session, devices);
+        self.sessions.push(session);
+    }
+
+    /// Starts a session on each VirtIO console which doesn't have one yet.
+    fn start_virtio_console_sessions(&mut self, devices: &mut Devices) {
+        for index in 0..devices.console.len() {
+            let terminal = SessionTerminal::VirtioConsole(index);
+            if !self
+                .sessions
+                .iter()
+                .any(|session| session.terminal == terminal)
+            {
+                self.start(terminal, devices);
+            }
+        }
+    }
+
+    /// Ends the session with the given index, closing its connection if it has one.
+    ///
+    /// The UART session can't be ended, and sessions on VirtIO consoles are restarted afresh.
+    fn end(&mut self, index: usize, devices: &mut Devices) {
+        let session = self.sessions.remove(index);
+        info!("Ending session on {}", session.terminal);
+        match session.terminal {
+            SessionTerminal::Uart => unreachable!("The UART session can't be ended"),
+            SessionTerminal::VirtioConsole(_) => self.start(session.terminal, devices),
+            SessionTerminal::Vsock { peer, port } => close_vsock(peer, port, devices),
+            #[cfg(feature = "net")]
+            SessionTerminal::Tcp { peer, port } => net::close_tcp_console(devices, (peer, port)),
+            SessionTerminal::Detached => {}
+        }
+    }
+
+    /// Closes the vsock connection of the session with the given index, but keeps the session so
+    /// that it can be reattached later.
+    fn detach(&mut self, index: usize, devices: &mut Devices) {
+        let session = &mut self.sessions[index];
+        let SessionTerminal::Vsock { peer, port } = session.terminal else {
+            return;
+        };
+        info!("Detaching session {index} from {}", session.terminal);
+        writeln!(session.output, "Detached from session {index}.").unwrap();
+        send_output(session, devices);
+        session.terminal = SessionTerminal::Detached;
+        close_vsock(peer, port, devices);
+    }
+
+    /// Moves the vsock connection of the session with the given index to the detached session with
+    /// index `target`, replaying its scrollback, and ends the first session.
+    fn attach(&mut self, index: usize, target: usize, devices: &mut Devices) {
+        let terminal = self.sessions[index].terminal;
+        info!("Attaching session {target} to {terminal}");
+        let session = &mut self.sessions[target];
+        session.terminal = terminal;
+        // Send the scrollback directly, so it isn't added to itself.
+        let scrollback = session.scrollback.contents();
+        session.output.data.extend_from_slice(b"\x1b[2J\x1b[H");
+        session.output.data.extend_from_slice(&scrollback);
+        send_to_terminal(terminal, &session.output.data, devices);
+        session.output.data.clear();
+        self.sessions.remove(index);
+    }
+
+    /// Releases the vsock device if sessions are no longer accepted on it and none are connected
+    /// through it, so that commands can use it.
+    fn release_unused_vsock(&mut self, devices: &mut Devices) {
+        let in_use = self.vsock_port.is_some()
+            || self
+                .sessions
+                .iter()
+                .any(|session| matches!(session.terminal, SessionTerminal::Vsock { .. }));
+        if !in_use && let Some(claim) = self.vsock_claim.take() {
+            devices.owners.release(claim);
+        }
+    }
+
+    /// Handles any input on all sessions, until a line is entered or Ctrl-D is pressed on an empty
+    /// line in one of them.
+    ///
+    /// Returns the index of the session along with what was entered.
+    fn poll_input(
+        &mut self,
+        context: &mut task::Context,
+        console: &mut Console<ConsoleImpl>,
+        devices: &mut Devices,
+    ) -> Poll<(usize, LineEvent)> {
+        // Write out anything logged by interrupt handlers since we were last polled.
+        log::logger().flush();
+        devices.poll_changes();
+        // Sessions may have been ended or disconnected since we were last polled.
+        self.release_unused_vsock(devices);
+        while let Some(event) = self.device_events.try_next() {
+            // Start sessions on consoles found since, e.g. by `rescan`.
+            if let DeviceEvent::Added(id) = event
+                && id.kind == DeviceKind::Console
+            {
+                self.start_virtio_console_sessions(devices);
+            }
+        }
+        // The balloon's interrupt isn't used, so host requests are only seen when something else
+        // wakes us.
+        balloon::poll(devices);
+        if let Some(port) = self.vsock_port {
+            self.poll_vsock_connections(port, devices);
+            // The vsock driver can't yet acknowledge the device's interrupt, so ask to be polled
+            // again straight away rather than waiting for one.
+            context.waker().wake_by_ref();
+        }
+        // Network interfaces aren't polled for their interrupts here either, so the same applies.
+        #[cfg(feature = "net")]
+        {
+            if let Some((peer, port)) = net::poll_tcp_console(devices) {
+                self.start(SessionTerminal::Tcp { peer, port }, devices);
+            }
+            if net::respond(devices) || net::tcp_console_listening() {
+                context.waker().wake_by_ref();
+            }
+        }
+        for (index, session) in self.sessions.iter_mut().enumerate() {
+            let event = match session.terminal {
+                SessionTerminal::Uart => poll_uart(session, context, console),
+                SessionTerminal::VirtioConsole(number) => {
+                    poll_virtio_console(session, number, devices)
+                }
+                SessionTerminal::Vsock { peer, port } => poll_vsock(session, peer, port, devices),
+                #[cfg(feature = "net")]
+                SessionTerminal::Tcp { peer, port } => poll_tcp(session, peer, port),
+                SessionTerminal::Detached => None,
+            };
+            send_output(session, devices);
+            if let Some(event) = event {
+                return Poll::Ready((index, event));
+            }
+        }
+        Poll::Pending
+    }
+
+    /// Starts and ends sessions for connections to the given vsock port.
+    fn poll_vsock_connections(&mut self, port: u32, devices: &mut Devices) {
+        loop {
+            let Some(vsock) = devices.vsock.first_mut() else {
+                return;
+            };
+            let event = match vsock.poll() {
+                Ok(Some(event)) => event,
+                Ok(None) => return,
+                Err(e) => {
+                    warn!("Error polling vsock: {e}");
+                    return;
+                }
+            };
+            // Data received is buffered by the connection manager, and read by `poll_vsock`.
+            if event.destination.port != port {
+                continue;
+            }
+            let terminal = SessionTerminal::Vsock {
+                peer: event.source,
+                port,
+            };
+            match event.event_type {
+                VsockEventType::ConnectionRequest => self.start(terminal, devices),
+                VsockEventType::Disconnected { .. } => {
+                    info!("Session on {terminal} disconnected, detaching");
+                    for session in &mut self.sessions {
+                        if session.terminal == terminal {
+                            session.terminal = SessionTerminal::Detached;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Runs the given line as a command in the session with the given index, and then sends it the
+    /// output and the next prompt.
+    ///
+    /// Returns the exit status if the command asked the shell to exit.
+    fn run_line(
+        &mut self,
+        index: usize,
+        line: &str,
+        console: &mut Console<ConsoleImpl>,
+        pci_roots: &mut [PciRootComplex],
+        devices: &mut Devices,
+        fdt: &Fdt<'static>,
+    ) -> Option<u32> {
+        let session = &mut self.sessions[index];
+        let uart = session.terminal == SessionTerminal::Uart;
+        let prompt = take(&mut session.prompt);
+        let rusage = session.rusage;
+        let mut output = take(&mut session.output);
+        self.current = index;
+        let mut context = Context {
+            console: if uart { &mut *console } else { &mut output },
+            pci_roots,
+            devices: &mut *devices,
+            fdt,
+            prompt,
+            exit: None,
+            rusage,
+            sessions: self,
+        };
+        let status = run_command(&mut context, line);
+        let Context {
+            prompt,
+            exit,
+            rusage,
+            ..
+        } = context;
+
+        let session = &mut self.sessions[index];
+        session.prompt = prompt;
+        session.rusage = rusage;
+        session.status = status;
+        session.output = output;
+        if exit.is_none() {
+            if uart {
+                write_prompt(console, &session.prompt, &*devices.rtc, status);
+            } else {
+                write_prompt(&mut session.output, &session.prompt, &*devices.rtc, status);
+            }
+            send_output(session, devices);
+        }
+        exit
+    }
+
+    /// Returns the line editor of the UART session, whose history is kept across reboots.
+    pub(super) fn uart_editor(&mut self) -> &mut LineEditor {
+        &mut self.sessions[0].editor
+    }
+
+    /// Runs the boot script, if any, in the UART session.
+    ///
+    /// Returns the exit status if the script asked the shell to exit.
+    fn run_boot_script(
+        &mut self,
+        console: &mut Console<ConsoleImpl>,
+        pci_roots: &mut [PciRootComplex],
+        devices: &mut Devices,
+        fdt: &Fdt<'static>,
+    ) -> Option<u32> {
+        let prompt = take(&mut self.sessions[0].prompt);
+        let mut context = Context {
+            console,
+            pci_roots,
+            devices,
+            fdt,
+            prompt,
+            exit: None,
+            rusage: self.sessions[0].rusage,
+            sessions: self,
+        };
+        run_boot_script(&mut context);
+        let Context {
+            prompt,
+            exit,
+            rusage,
+            ..
+        } = context;
+        self.sessions[0].prompt = prompt;
+        self.sessions[0].rusage = rusage;
+        exit
+    }
+
+    /// Runs sessions until the UART session exits or a shutdown is requested, restoring saved
+    /// settings first and saving them afterwards.
+    ///
+    /// Returns the status given to `exit` in the UART session, or 0 if it ended some other way.
+    pub fn run(
+        console: &mut Console<ConsoleImpl>,
+        pci_roots: &mut [PciRootComplex],
+        devices: &mut Devices,
+        fdt: &Fdt<'static>,
+    ) -> u32 {
+        let mut manager = Self::new();
+        settings::restore(devices, manager.uart_editor());
+        let status = manager.run_sessions(console, pci_roots, devices, fdt);
+        settings::save_on_exit(devices, manager.uart_editor());
+        manager.close_connections(devices);
+        status
+    }
+
+    /// Closes the connections of all sessions other than the UART session, before powering off.
+    fn close_connections(&mut self, devices: &mut Devices) {
+        for session in self.sessions.drain(1..) {
+            match session.terminal {
+                SessionTerminal::Vsock { peer, port } => close_vsock(peer, port, devices),
+                #[cfg(feature = "net")]
+                SessionTerminal::Tcp { peer, port } => {
+                    net::close_tcp_console(devices, (peer, port))
+                }
+                SessionTerminal::Uart
+                | SessionTerminal::VirtioConsole(_)
+                | SessionTerminal::Detached => {}
+            }
+        }
+    }
+
+    /// Runs the boot script and then sessions, until the UART session exits or a shutdown is
+    /// requested, and returns the exit status.
+    fn run_sessions(
+        &mut self,
+        console: &mut Console<ConsoleImpl>,
+        pci_roots: &mut [PciRootComplex],
+        devices: &mut Devices,
+        fdt: &Fdt<'static>,
+    ) -> u32 {
+        if let Some(status) = self.run_boot_script(console, pci_roots, devices, fdt) {
+            return status;
+        }
+        let prompt = &self.sessions[0].prompt;
+        write_prompt(console, prompt, &*devices.rtc, INITIAL_STATUS);
+        self.start_virtio_console_sessions(devices);
+        loop {
+            let Some((index, event)) = block_on(poll_fn(|context| {
+                if shutdown::requested() {
+                    return Poll::Ready(None);
+                }
+                self.poll_input(context, console, devices).map(Some)
+            })) else {
+                writeln!(console, "Shutting down.").unwrap();
+                return 0;
+            };
+            let uart = self.sessions[index].terminal == SessionTerminal::Uart;
+            let line = match event {
+                LineEvent::Line(line) => line,
+                LineEvent::Eof if uart => return 0,
+                LineEvent::Eof => {
+                    self.end(index, devices);
+                    continue;
+                }
+            };
+            let Ok(line) = str::from_utf8(&line) else {
+                if uart {
+                    writeln!(console, "Invalid UTF-8").unwrap();
+                } else {
+                    let session = &mut self.sessions[index];
+                    writeln!(session.output, "Invalid UTF-8").unwrap();
+                    send_output(session, devices);
+                }
+                continue;
+            };
+            if let Some(status) = self.run_line(index, line, console, pci_roots, devices, fdt) {
+                if uart {
+                    return status;
+                }
+                self.end(index, devices);
+                continue;
+            }
+            match self.pending.take() {
+                Some(PendingChange::Attach(target)) => self.attach(index, target, devices),
+                Some(PendingChange::Detach) => self.detach(index, devices),
+                None => {}
+            }
+        }
+    }
+}
+
+/// Handles any input available on the console UART.
+fn poll_uart(
+    session: &mut Session,
+    context: &mut task::Context,
+    console: &mut Console<ConsoleImpl>,
+) -> Option<LineEvent> {
+    let mut byte = [0];
+    // Read a byte at a time, so that anything after the end of a line is left for later.
+    while let Poll::Ready(result) = console.poll_read(context, &mut byte) {
+        result.unwrap();
+        if let Some(event) = session.editor.push(byte[0], console) {
+            return Some(event);
+        }
+    }
+    None
+}
+
+/// Handles any input available on the VirtIO console with the given index.
+fn poll_virtio_console(
+    session: &mut Session,
+    number: usize,
+    devices: &mut Devices,
+) -> Option<LineEvent> {
+    let device = devices.console.get_mut(number)?;
+    device.ack_pending_irq();
+    loop {
+        match device.recv(true) {
+            Ok(Some(byte)) => {
+                if let Some(event) = session.editor.push(byte, &mut session.output) {
+                    return Some(event);
+                }
+            }
+            Ok(None) => return None,
+            Err(e) => {
+                warn!("Error reading from VirtIO console {number}: {e}");
+                return None;
+            }
+        }
+    }
+}
+
+/// Handles any input available on the connection from the given vsock peer.
+fn poll_vsock(
+    session: &mut Session,
+    peer: VsockAddr,
+    port: u32,
+    devices: &mut Devices,
+) -> Option<LineEvent> {
+    let vsock = devices.vsock.first_mut()?;
+    loop {
+        let mut byte = [0];
+        match vsock.recv(peer, port, &mut byte) {
+            Ok(0) => return None,
+            Ok(_) => {
+                if let Some(event) = session.editor.push(byte[0], &mut session.output) {
+                    return Some(event);
+                }
+            }
+            Err(e) => {
+                warn!("Error reading from vsock connection from {peer:?}: {e}");
+                return None;
+            }
+        }
+    }
+}
+
+/// Handles any input available on the connection from the given peer to the shell's TCP port.
+///
+/// Returns `LineEvent::Eof` once the connection has been closed, so that the session is ended.
+#[cfg(feature = "net")]
+fn poll_tcp(session: &mut Session, peer: Ipv4Address, port: u16) -> Option<LineEvent> {
+    loop {
+        let mut byte = [0];
+        match net::tcp_console_recv((peer, port), &mut byte) {
+            None => return Some(LineEvent::Eof),
+            Some(0) => return None,
+            Some(_) => {
+                if let Some(event) = session.editor.push(byte[0], &mut session.output) {
+                    return Some(event);
+                }
+            }
+        }
+    }
+}
+
+/// Sends any buffered output to the session's terminal, and adds it to the scrollback.
+fn send_output(session: &mut Session, devices: &mut Devices) {
+    if session.output.data.is_empty() {
+        return;
+    }
+    session.scrollback.push(&session.output.data);
+    send_to_terminal(session.terminal, &session.output.data, devices);
+    session.output.data.clear();
+}
+
+/// Sends the given data to the given terminal, logging any error.
+///
+/// Nothing is sent to the UART, which is written to directly, or to a detached session.
+fn send_to_terminal(terminal: SessionTerminal, data: &[u8], devices: &mut Devices) {
+    let result = match terminal {
+        SessionTerminal::Uart | SessionTerminal::Detached => Ok(()),
+        SessionTerminal::VirtioConsole(number) => match devices.console.get_mut(number) {
+            Some(device) => device.send_bytes(data),
+            None => Ok(()),
+        },
+        SessionTerminal::Vsock { peer, port } => match devices.vsock.first_mut() {
+            Some(vsock) => vsock.send(peer, port, data),
+            None => Ok(()),
+        },
+        // Errors are logged by the TCP console itself.
+        #[cfg(feature = "net")]
+        SessionTerminal::Tcp { peer, port } => {
+            net::tcp_console_send(devices, (peer, port), data);
+            Ok(())
+        }
+    };
+    if let Err(e) = result {
+        warn!("Error sending output to {terminal}: {e}");
+    }
+}
+
+/// Closes the vsock connection from the given peer to the given local port, logging any error.
+fn close_vsock(peer: VsockAddr, port: u32, devices: &mut Devices) {
+    if let Some(vsock) = devices.vsock.first_mut()
+        && let Err(e) = vsock.shutdown(peer, port)
+    {
+        warn!("Error closing vsock connection from {peer:?}: {e}");
+    }
+}
+
+/// Lists the shell sessions, starts or stops accepting sessions on a vsock port, or detaches or
+/// reattaches vsock sessions.
+pub fn sessions(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    match args.next() {
+        None => {
+            args.finish()?;
+            let Context {
+                console, sessions, ..
+            } = context;
+            for (index, session) in sessions.sessions.iter().enumerate() {
+                writeln!(
+                    console,
+                    "{index}: {}, prompt {:?}, status {}",
+                    session.terminal, session.prompt, session.status
+                )
+                .unwrap();
+            }
+            if let Some(port) = sessions.vsock_port {
+                writeln!(console, "Accepting sessions on vsock port {port}").unwrap();
+            }
+        }
+        Some("listen") => {
+            let port = args.next_port()?;
+            args.finish()?;
+            let vsock = context
+                .devices
+                .vsock
+                .first_mut()
+                .ok_or(CommandError::NoSuchDevice {
+                    kind: "vsock",
+                    index: 0,
+                })?;
+            if context.sessions.vsock_claim.is_none() {
+                let claim = context.devices.owners.claim(VSOCK0, SESSIONS_OWNER)?;
+                context.sessions.vsock_claim = Some(claim);
+            }
+            if let Some(old_port) = context.sessions.vsock_port {
+                vsock.unlisten(old_port);
+            }
+            vsock.listen(port);
+            context.sessions.vsock_port = Some(port);
+            writeln!(context.console, "Accepting sessions on vsock port {port}").unwrap();
+        }
+        Some("unlisten") => {
+            args.finish()?;
+            let port = context
+                .sessions
+                .vsock_port
+                .take()
+                .ok_or("Not accepting sessions on vsock")?;
+            if let Some(vsock) = context.devices.vsock.first_mut() {
+                vsock.unlisten(port);
+            }
+            context.sessions.release_unused_vsock(context.devices);
+            writeln!(
+                context.console,
+                "Stopped accepting sessions on vsock port {port}"
+            )
+            .unwrap();
+        }
+        Some("attach") => {
+            let target = args.required("index")?;
+            args.finish()?;
+            let sessions = &mut context.sessions;
+            check_vsock_session(sessions)?;
+            let Some(session) = sessions.sessions.get(target) else {
+                return Err(CommandError::NoSuchDevice {
+                    kind: "session",
+                    index: target,
+                });
+            };
+            if session.terminal != SessionTerminal::Detached {
+                return Err(CommandError::Failed(format!(
+                    "Session {target} is not detached"
+                )));
+            }
+            sessions.pending = Some(PendingChange::Attach(target));
+        }
+        Some("detach") => {
+            args.finish()?;
+            check_vsock_session(context.sessions)?;
+            context.sessions.pending = Some(PendingChange::Detach);
+        }
+        Some(_) => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+/// Checks that the current command is running in a session on a vsock connection, which can be
+/// detached and moved to other sessions.
+fn check_vsock_session(sessions: &SessionManager) -> Result<(), CommandError> {
+    match sessions.sessions[sessions.current].terminal {
+        SessionTerminal::Vsock { .. } => Ok(()),
+        _ => Err("Only vsock sessions can be detached or attached.".into()),
+    }
+}
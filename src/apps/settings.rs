@@ -0,0 +1,241 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Keeping the UART session's shell history, the log level, a pending RTC alarm and saved benchmark
+//! results across reboots, in the first few kilobytes of the first writable block device.
+//!
+//! Settings are restored at boot and saved when the UART session exits, or on demand with
+//! `settings save`. The region is only written if it already holds settings or is all zeros, so
+//! that a disk with other data on it isn't overwritten.
+
+use crate::apps::{
+    alarm, bench,
+    command::{Args, CommandError, Context, ErrorContext},
+    cp::{open_sink, read_endpoint},
+    line_editor::LineEditor,
+    timezone,
+};
+use alloc::{format, string::ToString};
+use chrono::{DateTime, Utc};
+use embedded_io::Write;
+use log::{LevelFilter, info, warn};
+use osdemo::{
+    endpoint::Endpoint,
+    settings::{Settings, SettingsError},
+};
+use osdemo_core::{block::BlockDevice, devices::Devices, fallible::try_vec};
+
+/// The size of the region at the start of the block device which settings are kept in, in bytes.
+const STORE_SIZE: usize = 4096;
+
+/// Restores the settings saved on the first writable block device, if any, adding the saved history
+/// to the given line editor.
+///
+/// Errors are logged rather than returned, as there may be no settings to restore.
+pub fn restore(devices: &mut Devices, editor: &mut LineEditor) {
+    let Some(device) = store_device(devices) else {
+        return;
+    };
+    match read_settings(devices, device) {
+        Ok(Ok(settings)) => {
+            info!("Restoring settings from blk{device}");
+            apply(devices, editor, settings);
+        }
+        Ok(Err(SettingsError::NotFound)) => {}
+        Ok(Err(e)) => warn!("Not restoring settings from blk{device}: {e}"),
+        Err(e) => warn!("Error restoring settings: {e}"),
+    }
+}
+
+/// Saves the current settings to the first writable block device, logging any error.
+pub fn save_on_exit(devices: &mut Devices, editor: &LineEditor) {
+    if store_device(devices).is_none() {
+        return;
+    }
+    if let Err(e) = save(devices, editor) {
+        warn!("Error saving settings: {e}");
+    }
+}
+
+/// Shows the saved settings, or saves, reloads or clears them.
+pub fn settings(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let action = args.next();
+    args.finish()?;
+    let Context {
+        console,
+        devices,
+        sessions,
+        ..
+    } = context;
+    match action {
+        None => {
+            let device = required_store_device(devices)?;
+            let settings = read_settings(devices, device)?
+                .context(format_args!("reading settings from blk{device}"))?;
+            writeln!(console, "Settings on blk{device}:").unwrap();
+            match &settings.log_level {
+                Some(log_level) => writeln!(console, "  Log level: {log_level}").unwrap(),
+                None => writeln!(console, "  Log level: not saved").unwrap(),
+            }
+            match alarm_time(&settings) {
+                Some(alarm) => {
+                    writeln!(console, "  Alarm: {}", timezone::local_time(alarm)).unwrap()
+                }
+                None => writeln!(console, "  Alarm: none").unwrap(),
+            }
+            writeln!(
+                console,
+                "  Benchmark results: {} sets",
+                settings.bench_results.len()
+            )
+            .unwrap();
+            for results in &settings.bench_results {
+                writeln!(console, "    {}", results.name).unwrap();
+            }
+            writeln!(console, "  History: {} lines", settings.history.len()).unwrap();
+            for line in &settings.history {
+                writeln!(console, "    {}", line.escape_ascii()).unwrap();
+            }
+        }
+        Some("save") => {
+            save(devices, sessions.uart_editor())?;
+            writeln!(console, "Saved settings").unwrap();
+        }
+        Some("load") => {
+            let device = required_store_device(devices)?;
+            let settings = read_settings(devices, device)?
+                .context(format_args!("reading settings from blk{device}"))?;
+            apply(devices, sessions.uart_editor(), settings);
+            writeln!(console, "Loaded settings from blk{device}").unwrap();
+        }
+        Some("clear") => {
+            let device = required_store_device(devices)?;
+            if let Err(SettingsError::NotFound) = read_settings(devices, device)? {
+                return Err("No settings saved".into());
+            }
+            write_store(devices, device, &[])?;
+            writeln!(console, "Cleared settings from blk{device}").unwrap();
+        }
+        Some(_) => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+/// Returns whether there is a block device to keep settings on.
+pub fn has_store(devices: &Devices) -> bool {
+    store_device(devices).is_some()
+}
+
+/// Returns the index of the block device which settings are kept on, if there is one.
+fn store_device(devices: &Devices) -> Option<usize> {
+    devices.block.iter().position(|device| !device.readonly())
+}
+
+/// Returns the index of the block device which settings are kept on, or an error if there is none.
+fn required_store_device(devices: &Devices) -> Result<usize, CommandError> {
+    store_device(devices).ok_or_else(|| "No writable block device to keep settings on".into())
+}
+
+/// Reads and parses the settings region of the given block device.
+fn read_settings(
+    devices: &mut Devices,
+    device: usize,
+) -> Result<Result<Settings, SettingsError>, CommandError> {
+    let store = read_endpoint(devices, store_endpoint(device), STORE_SIZE)?;
+    Ok(Settings::decode(&store))
+}
+
+/// Applies the given settings, adding the saved history to the given line editor, replacing the
+/// saved benchmark results and setting the saved alarm again if it is still in the future.
+fn apply(devices: &mut Devices, editor: &mut LineEditor, settings: Settings) {
+    if let Some(log_level) = &settings.log_level {
+        match log_level.parse::<LevelFilter>() {
+            Ok(level) => log::set_max_level(level),
+            Err(_) => warn!("Ignoring invalid saved log level {log_level}"),
+        }
+    }
+    for line in &settings.history {
+        editor.add_history(line);
+    }
+    bench::restore_saved_results(settings.bench_results.clone());
+    let Some(alarm_time) = alarm_time(&settings) else {
+        return;
+    };
+    let rtc = &mut *devices.rtc;
+    if alarm_time <= rtc.get_time() {
+        warn!(
+            "Missed alarm due at {} while powered off",
+            timezone::local_time(alarm_time)
+        );
+    } else if let Err(e) = alarm::set_alarm(rtc, alarm_time) {
+        warn!("Error restoring alarm: {e}");
+    } else {
+        info!("Restored alarm for {}", timezone::local_time(alarm_time));
+    }
+}
+
+/// Returns the time of the saved alarm, if there is one.
+fn alarm_time(settings: &Settings) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(settings.alarm?.try_into().ok()?, 0)
+}
+
+/// Saves the current log level, pending alarm, saved benchmark results and the given line editor's
+/// history.
+///
+/// The oldest history is left out if it doesn't all fit, and an error is returned if the rest
+/// still doesn't fit.
+pub fn save(devices: &mut Devices, editor: &LineEditor) -> Result<(), CommandError> {
+    let device = required_store_device(devices)?;
+    let mut settings = Settings {
+        log_level: Some(log::max_level().to_string()),
+        history: editor.history().map(<[u8]>::to_vec).collect(),
+        alarm: alarm::pending().and_then(|alarm| alarm.timestamp().try_into().ok()),
+        bench_results: bench::saved_results(),
+    };
+    let mut store = settings.encode();
+    while store.len() > STORE_SIZE && !settings.history.is_empty() {
+        settings.history.remove(0);
+        store = settings.encode();
+    }
+    if store.len() > STORE_SIZE {
+        return Err(CommandError::Failed(format!(
+            "Settings are {} bytes, but only {STORE_SIZE} bytes are available",
+            store.len()
+        )));
+    }
+
+    // Don't overwrite anything other than settings.
+    let existing = read_endpoint(devices, store_endpoint(device), STORE_SIZE)?;
+    if Settings::decode(&existing) == Err(SettingsError::NotFound)
+        && existing.iter().any(|&byte| byte != 0)
+    {
+        return Err(CommandError::Failed(format!(
+            "Not saving settings, as the start of blk{device} is in use for something else"
+        )));
+    }
+    write_store(devices, device, &store)
+}
+
+/// Writes the given store to the settings region of the given block device, followed by zeros, and
+/// syncs it to the device.
+fn write_store(devices: &mut Devices, device: usize, store: &[u8]) -> Result<(), CommandError> {
+    let mut region = try_vec(0, STORE_SIZE)?;
+    region[..store.len()].copy_from_slice(store);
+    let mut sink = open_sink(devices, store_endpoint(device))?;
+    let result = sink.write(devices, &region);
+    sink.close(devices)?;
+    result?;
+    devices.block[device]
+        .sync()
+        .context(format_args!("syncing blk{device}"))
+}
+
+/// Returns the settings region of the given block device.
+fn store_endpoint(device: usize) -> Endpoint<'static> {
+    Endpoint::Block {
+        device,
+        offset: 0,
+        length: Some(STORE_SIZE as u64),
+    }
+}
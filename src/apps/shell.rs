@@ -2,173 +2,1053 @@
 // This project is dual-licensed under Apache 2.0 and MIT terms.
 // See LICENSE-APACHE and LICENSE-MIT for details.
 
+#[cfg(feature = "block")]
+use crate::apps::blk;
+#[cfg(feature = "net")]
+use crate::apps::net;
 use crate::{
     apps::{
-        alarm,
-        cpus::{cpus, sgi, start_cpu},
+        alarm, balloon, bench,
+        command::{Args, Command, CommandError, Context, ErrorContext, FnCommand},
+        coredump,
+        cp::{self, VSOCK0},
+        cpus, crashdump, dashboard, dmesg, events, flood, hostcat, hwreport,
+        line_editor::Line,
+        pcidump, perf, process, prompt, random, rc, rusage, scrub, selftest,
+        session::{self, SessionManager},
+        settings, stopwatch, suspend, timezone, top, trace, vconsole,
     },
-    devices::Devices,
+    console::Console,
+    heap_stats,
+    platform::ConsoleImpl,
+    reclaimed_boot_memory,
+};
+use alloc::{format, string::ToString};
+use arm_gic::irq_enable;
+use arm_sysregs::HcrEl2;
+use core::{str, time::Duration};
+use dtoolkit::{Node, Property, fdt::Fdt};
+use embedded_io::Write;
+use log::{info, warn};
+use osdemo::{args::split_command, date_time::parse_date_time};
+use osdemo_core::{
+    device_id::{DeviceId, DeviceKind},
+    devices::{DeviceInfo, Devices, find_pci_devices},
+    dma,
+    drivers::generic_timer::{self, TimedOut},
+    exceptions::{current_el, hcr_el2, vhe_enabled},
+    executor::{Either, block_on, select},
+    fdt::{PropertyValue, child_cells, decode_ranges, property_cells, reg_entries},
+    gic_layout::GicLayout,
+    interrupts::{gic_layout, set_priority_mask},
+    pagetable::PAGETABLE,
+    pci::PciRootComplex,
+    pci_config::HexDump,
+    shutdown,
+    virtio::{find_virtio_mmio_devices, next_vsock_event},
 };
-use arm_gic::{gicv3::GicCpuInterface, irq_enable};
-use arm_pl031::Rtc;
-use arrayvec::ArrayVec;
-use core::str;
-use dtoolkit::fdt::Fdt;
-use embedded_io::{Read, ReadReady, Write};
-use log::info;
 use virtio_drivers::{
-    Hal,
-    device::socket::{DisconnectReason, VsockAddr, VsockConnectionManager, VsockEventType},
-    transport::{
-        Transport,
-        pci::{
-            bus::{MmioCam, PciRoot},
-            virtio_device_type,
-        },
-    },
+    Error as VirtioError,
+    device::socket::{DisconnectReason, VsockAddr, VsockEventType},
+    transport::pci::virtio_device_type,
 };
 
+/// The end of transmission character, sent by Ctrl-D.
 const EOF: u8 = 0x04;
 
+/// The group separator character, sent by Ctrl-], which `vcat` and `nc` treat as a request to
+/// disconnect.
+pub const DISCONNECT_KEY: u8 = 0x1d;
+
+/// How long `vcat` waits for the peer to accept a connection, unless otherwise specified.
+const DEFAULT_VSOCK_CONNECT_TIMEOUT_S: u64 = 10;
+
+/// The script run by the `script` command.
+const DEMO_SCRIPT: &str = include_str!("../../scripts/demo.txt");
+
+/// The exit status of a command which succeeded.
+const STATUS_SUCCESS: u8 = 0;
+
+/// The exit status reported when no command with the given name exists.
+const STATUS_NOT_FOUND: u8 = 127;
+
+/// All the commands supported by the shell, in the order they are listed by `help`.
+static COMMANDS: &[&dyn Command] = &[
+    &FnCommand {
+        name: "alarm",
+        summary: "Sets an alarm in the future",
+        usage: "<delay>",
+        run: alarm::alarm,
+    },
+    &FnCommand {
+        name: "balloon",
+        summary: "Shows or resizes the memory balloon, or shows or configures its policy",
+        usage: "[<MiB> | policy [set <min_pages> <max_pages> | thresholds <deflate_below_%> <inflate_above_%> | step <pages> | reset]]",
+        run: balloon::balloon,
+    },
+    &FnCommand {
+        name: "bench",
+        summary: "Runs, saves and compares benchmarks",
+        usage: "list | run [<benchmark>|all] | alloc | show | save <name> | compare <name> <name>",
+        run: bench::bench,
+    },
+    #[cfg(feature = "block")]
+    &FnCommand {
+        name: "blk",
+        summary: "Write-protects a block device, or puts a copy-on-write overlay in front of it",
+        usage: "ro <block> [on|off] | overlay <block> [on|discard]",
+        run: blk::blk,
+    },
+    #[cfg(feature = "block")]
+    &FnCommand {
+        name: "blkbench",
+        summary: "Compares synchronous and asynchronous read throughput of a VirtIO block device",
+        usage: "<block> [<blocks>]",
+        run: bench::blkbench,
+    },
+    #[cfg(feature = "block")]
+    &FnCommand {
+        name: "blkcache",
+        summary: "Sets the number of blocks cached for a block device, and whether writes are cached",
+        usage: "<block> <blocks> [writethrough|writeback]",
+        run: blk::blkcache,
+    },
+    #[cfg(feature = "block")]
+    &FnCommand {
+        name: "blkstat",
+        summary: "Prints block cache statistics",
+        usage: "[<block>]",
+        run: blk::blkstat,
+    },
+    &FnCommand {
+        name: "coredump",
+        summary: "Streams the image and heap, or the given memory regions, to a vsock peer",
+        usage: "<CID> <port> [<address>+<length>]...",
+        run: coredump::coredump,
+    },
+    &FnCommand {
+        name: "countdown",
+        summary: "Counts down the given number of seconds, then rings the bell",
+        usage: "<seconds>",
+        run: stopwatch::countdown,
+    },
+    &FnCommand {
+        name: "cp",
+        summary: "Copies between block ranges such as blk0:4096+512 and vsock peers like vsock:2:9000",
+        usage: "<source> <destination>",
+        run: cp::cp,
+    },
+    &FnCommand {
+        name: "cpus",
+        summary: "Lists the state of all CPUs",
+        usage: "",
+        run: cpus::cpus,
+    },
+    &FnCommand {
+        name: "crashdump",
+        summary: "Shows or sets where crash reports are written on panic, or shows the last one",
+        usage: "[target <destination>|off | show [<source>] | panic]",
+        run: crashdump::crashdump,
+    },
+    &FnCommand {
+        name: "dashboard",
+        summary: "Shows a full-screen dashboard of CPU, memory, IRQ, block and network statistics",
+        usage: "[<interval_ms>]",
+        run: dashboard::dashboard,
+    },
+    &FnCommand {
+        name: "date",
+        summary: "Prints the current date and time, in UTC with -u, or sets the RTC",
+        usage: "[-u] | set <YYYY-MM-DDTHH:MM[:SS][offset] | @unix-seconds>",
+        run: date,
+    },
+    &FnCommand {
+        name: "dmainfo",
+        summary: "Prints DMA pool usage",
+        usage: "",
+        run: dmainfo,
+    },
+    &FnCommand {
+        name: "dmesg",
+        summary: "Prints recent log messages, optionally only those at or above a level",
+        usage: "[error|warn|info|debug|trace]",
+        run: dmesg::dmesg,
+    },
+    &FnCommand {
+        name: "dtdump",
+        summary: "Dumps the device tree to the console",
+        usage: "",
+        run: dtdump,
+    },
+    &FnCommand {
+        name: "dtget",
+        summary: "Prints a device tree node's properties and children, or one property decoded",
+        usage: "<path> [<property>]",
+        run: dtget,
+    },
+    &FnCommand {
+        name: "el",
+        summary: "Prints the current exception level and interrupt routing",
+        usage: "",
+        run: el,
+    },
+    &FnCommand {
+        name: "events",
+        summary: "Prints device events as they happen, until a key is pressed",
+        usage: "[<count>]",
+        run: events::events,
+    },
+    &FnCommand {
+        name: "exec",
+        summary: "Runs one of the demo programs embedded in the image at EL0",
+        usage: "hello | yes [<count>] | fault | fork | mmap | futex [&]",
+        run: process::exec,
+    },
+    &FnCommand {
+        name: "exit",
+        summary: "Exits the shell and stops the system, with the given exit status",
+        usage: "[<status>]",
+        run: exit,
+    },
+    &FnCommand {
+        name: "flood",
+        summary: "Writes numbered lines at a given rate to load test the console or logger",
+        usage: "[console|log] [<lines/s> [<bytes> [<seconds>]]]",
+        run: flood::flood,
+    },
+    &FnCommand {
+        name: "free",
+        summary: "Shows the current and peak usage of the heap and page table allocators",
+        usage: "",
+        run: free,
+    },
+    &FnCommand {
+        name: "gicinfo",
+        summary: "Shows the GIC's regions, and which redistributor each CPU uses",
+        usage: "",
+        run: gicinfo,
+    },
+    #[cfg(feature = "heap-debug")]
+    &FnCommand {
+        name: "heapcheck",
+        summary: "Checks heap red zones and quarantined blocks for corruption",
+        usage: "",
+        run: heapcheck,
+    },
+    &FnCommand {
+        name: "help",
+        summary: "Prints this help, or the usage of the given command",
+        usage: "[<command>]",
+        run: help,
+    },
+    &FnCommand {
+        name: "hostcat",
+        summary: "Prints a file on the host, when running under QEMU with semihosting",
+        usage: "<path>",
+        run: hostcat::hostcat,
+    },
+    &FnCommand {
+        name: "hwreport",
+        summary: "Prints the hardware inventory as JSON, or sends it to a vsock peer",
+        usage: "[vsock:<cid>:<port>]",
+        run: hwreport::hwreport,
+    },
+    #[cfg(feature = "smp")]
+    &FnCommand {
+        name: "idle-inject",
+        summary: "Forces a CPU started with start_cpu to be idle for a share of the time, or 0 to stop",
+        usage: "<cpu_index> <percent>",
+        run: cpus::idle_inject,
+    },
+    #[cfg(feature = "net")]
+    &FnCommand {
+        name: "ifconfig",
+        summary: "Shows network interfaces, or sets an interface's MAC address or brings it up or down",
+        usage: "[<interface> [hw <mac>|up|down]]",
+        run: net::ifconfig,
+    },
+    #[cfg(feature = "smp")]
+    &FnCommand {
+        name: "ipi",
+        summary: "Runs work on a secondary CPU started with start_cpu, and measures the latency",
+        usage: "<cpu_index>",
+        run: cpus::ipi,
+    },
+    &FnCommand {
+        name: "kill",
+        summary: "Stops a user process and its descendants at their next syscall or interrupt",
+        usage: "<pid>",
+        run: process::kill,
+    },
+    &FnCommand {
+        name: "loglevel",
+        summary: "Prints or changes the maximum level of messages logged",
+        usage: "[off|error|warn|info|debug|trace]",
+        run: dmesg::loglevel,
+    },
+    &FnCommand {
+        name: "lsdev",
+        summary: "Lists all devices and what owns them, or describes the device with the given ID",
+        usage: "[<id>]",
+        run: lsdev,
+    },
+    &FnCommand {
+        name: "lspci",
+        summary: "Lists devices on the PCI bus",
+        usage: "[-v] [-x]",
+        run: lspci,
+    },
+    &FnCommand {
+        name: "meminfo",
+        summary: "Shows heap and DMA pool usage, and what the free page scrubber has found",
+        usage: "",
+        run: scrub::meminfo,
+    },
+    #[cfg(feature = "net")]
+    &FnCommand {
+        name: "net",
+        summary: "Configures a network interface, or sends or captures raw frames on it",
+        usage: "<interface> ip [<address>/<prefix> [<gateway>]] | <interface> send <text> | <interface> capture [<count>]",
+        run: net::net,
+    },
+    #[cfg(feature = "net")]
+    &FnCommand {
+        name: "nc",
+        summary: "Connects to a TCP port, until Ctrl-] is pressed",
+        usage: "<address> <port>",
+        run: net::nc,
+    },
+    #[cfg(feature = "net")]
+    &FnCommand {
+        name: "pcap",
+        summary: "Records frames on a network interface, and writes them as a pcap file when stopped",
+        usage: "start <interface> [<snaplen>] | stop [<destination>] | status",
+        run: net::pcap,
+    },
+    &FnCommand {
+        name: "pcidump",
+        summary: "Dumps the configuration space, BARs and capabilities of a PCI device",
+        usage: "<bus:device.function> [bar <index> [<offset> [<length>]]]",
+        run: pcidump::pcidump,
+    },
+    &FnCommand {
+        name: "perf",
+        summary: "Runs a command and prints the CPU cycles and instructions it took",
+        usage: "<command> [<args>...]",
+        run: perf::perf,
+    },
+    &FnCommand {
+        name: "power",
+        summary: "Prints energy telemetry",
+        usage: "",
+        run: power,
+    },
+    &FnCommand {
+        name: "prompt",
+        summary: "Prints or sets the prompt, with placeholders %t (time), %c (CPU) and %? (status)",
+        usage: "[<format>|reset]",
+        run: prompt::prompt,
+    },
+    &FnCommand {
+        name: "ps",
+        summary: "Lists the user processes which haven't exited, and with -l the CPU time they used",
+        usage: "[-l]",
+        run: process::ps,
+    },
+    &FnCommand {
+        name: "rc",
+        summary: "Runs the commands in a script on a block device or vsock peer, or the boot script",
+        usage: "[<source>]",
+        run: rc::rc,
+    },
+    &FnCommand {
+        name: "rescan",
+        summary: "Looks for PCI and VirtIO MMIO devices which have appeared since boot",
+        usage: "",
+        run: rescan,
+    },
+    #[cfg(feature = "net")]
+    &FnCommand {
+        name: "responder",
+        summary: "Shows or sets whether ARP and ICMP echo requests for our IP addresses are answered",
+        usage: "[on|off]",
+        run: net::responder,
+    },
+    &FnCommand {
+        name: "runuser",
+        summary: "Runs the embedded user program at EL0",
+        usage: "",
+        run: process::runuser,
+    },
+    &FnCommand {
+        name: "script",
+        summary: "Runs the built-in demo script",
+        usage: "",
+        run: script,
+    },
+    &FnCommand {
+        name: "scrub",
+        summary: "Shows or sets how free DMA pages are scrubbed while idle, checking for bit flips",
+        usage: "[off|zero|checksum]",
+        run: scrub::scrub,
+    },
+    &FnCommand {
+        name: "selftest",
+        summary: "Runs a self-test of each subsystem and reports which passed",
+        usage: "[<vsock_port>]",
+        run: selftest::selftest,
+    },
+    &FnCommand {
+        name: "sessions",
+        summary: "Lists shell sessions, accepts them on a vsock port, or detaches or reattaches one",
+        usage: "[listen <port> | unlisten | attach <index> | detach]",
+        run: session::sessions,
+    },
+    &FnCommand {
+        name: "set",
+        summary: "Shows or sets shell variables, such as RUSAGE=1 to report commands' resource usage",
+        usage: "[<name>=<value>]",
+        run: set,
+    },
+    &FnCommand {
+        name: "settings",
+        summary: "Shows, saves, loads or clears the settings kept on the first writable block device",
+        usage: "[save|load|clear]",
+        run: settings::settings,
+    },
+    #[cfg(feature = "smp")]
+    &FnCommand {
+        name: "sgi",
+        summary: "Sends a software-generated interrupt",
+        usage: "<id>",
+        run: cpus::sgi,
+    },
+    &FnCommand {
+        name: "sleep",
+        summary: "Waits for the given number of milliseconds",
+        usage: "<ms>",
+        run: sleep,
+    },
+    &FnCommand {
+        name: "srand",
+        summary: "Shows or sets the seed for tests which use random data",
+        usage: "[<seed>|random]",
+        run: random::srand,
+    },
+    #[cfg(feature = "smp")]
+    &FnCommand {
+        name: "start_cpu",
+        summary: "Starts a secondary CPU",
+        usage: "<cpu_index> <arg>",
+        run: cpus::start_cpu,
+    },
+    #[cfg(feature = "smp")]
+    &FnCommand {
+        name: "stop_cpu",
+        summary: "Stops a secondary CPU started with start_cpu",
+        usage: "<cpu_index>",
+        run: cpus::stop_cpu,
+    },
+    &FnCommand {
+        name: "stopwatch",
+        summary: "Runs a stopwatch, recording laps until stopped",
+        usage: "",
+        run: stopwatch::stopwatch,
+    },
+    &FnCommand {
+        name: "suspend",
+        summary: "Suspends the current CPU or the system until an RTC alarm, or lists the states",
+        usage: "[<state>|system [<seconds>]]",
+        run: suspend::suspend,
+    },
+    #[cfg(feature = "block")]
+    &FnCommand {
+        name: "sync",
+        summary: "Writes cached dirty blocks back to block devices",
+        usage: "[<block>]",
+        run: blk::sync,
+    },
+    #[cfg(feature = "net")]
+    &FnCommand {
+        name: "tcpconsole",
+        summary: "Shows or sets the TCP port which shell sessions are accepted on, raw or over telnet",
+        usage: "[<port> [raw|telnet] | off]",
+        run: net::tcpconsole,
+    },
+    &FnCommand {
+        name: "top",
+        summary: "Shows how much of the time each CPU spends idle, until q is pressed",
+        usage: "[<interval_ms>]",
+        run: top::top,
+    },
+    &FnCommand {
+        name: "trace",
+        summary: "Records allocations with their callers, or exceptions with timings, in dmesg",
+        usage: "alloc [on [<min_size>] | off] | exceptions [on [<max_per_second>] | off]",
+        run: trace::trace,
+    },
+    &FnCommand {
+        name: "tz",
+        summary: "Prints or sets the UTC offset which times are displayed in",
+        usage: "[+HH[:MM]|-HH[:MM]]",
+        run: timezone::tz,
+    },
+    #[cfg(feature = "net")]
+    &FnCommand {
+        name: "udp",
+        summary: "Sends a UDP datagram, or prints datagrams received on a port until Ctrl-] is pressed",
+        usage: "send <address> <port> [<text>...] | recv <port> [<count>]",
+        run: net::udp,
+    },
+    &FnCommand {
+        name: "vcat",
+        summary: "Communicates with a vsock port until Ctrl-] is pressed",
+        usage: "<CID> <port> [<connect timeout s> [<idle timeout s>]]",
+        run: vcat,
+    },
+    &FnCommand {
+        name: "vcread",
+        summary: "Prints input from port 0 of a VirtIO console until it is idle for the timeout",
+        usage: "<console> [<timeout ms>]",
+        run: vconsole::vcread,
+    },
+    &FnCommand {
+        name: "vcwrite",
+        summary: "Writes a line of text to port 0 of a VirtIO console",
+        usage: "<console> [<text>...]",
+        run: vconsole::vcwrite,
+    },
+    &FnCommand {
+        name: "vscript",
+        summary: "Runs commands read from a VirtIO console",
+        usage: "<console>",
+        run: vscript,
+    },
+];
+
+/// Runs the shell until it exits, and returns the status which the system should exit with.
 pub fn main(
-    console: &mut (impl Write + Read + ReadReady),
-    pci_roots: &mut [PciRoot<MmioCam>],
+    console: &mut Console<ConsoleImpl>,
+    pci_roots: &mut [PciRootComplex],
     devices: &mut Devices,
-    fdt: &Fdt,
-) {
+    fdt: &Fdt<'static>,
+) -> u32 {
     info!("Configuring IRQs...");
-    GicCpuInterface::set_priority_mask(0xff);
+    set_priority_mask(0xff);
+    shutdown::add_hook("block devices", sync_block_devices);
     alarm::irq_setup();
+    shutdown::add_hook("RTC alarm", |_| alarm::irq_remove());
+    timezone::init(fdt);
+    crashdump::init(fdt, devices);
+    shutdown::add_hook("crash reports", |_| crashdump::remove());
     irq_enable();
+    hwreport::send_at_boot(fdt, pci_roots, devices);
 
-    loop {
-        write!(console, "$ ").unwrap();
-        let line = read_line(console);
-        if line.as_ref() == [EOF] {
-            break;
+    let status = SessionManager::run(console, pci_roots, devices, fdt);
+    shutdown::run_hooks(devices);
+    status
+}
+
+/// Writes any changes cached for each block device to it, before powering off.
+fn sync_block_devices(devices: &mut Devices) {
+    for (index, device) in devices.block.iter_mut().enumerate() {
+        if let Err(e) = device.sync() {
+            warn!("Error syncing blk{index}: {e}");
         }
-        let Ok(line) = str::from_utf8(&line) else {
-            writeln!(console, "Invalid UTF-8").unwrap();
-            continue;
-        };
-        let mut parts = line.split(' ');
-        let Some(command) = parts.next() else {
-            continue;
-        };
-        match command {
-            "alarm" => alarm::alarm(console, parts, &mut devices.rtc),
-            "date" => date(console, &mut devices.rtc),
-            "dtdump" => dtdump(console, fdt),
-            "exit" => break,
-            "help" => help(console),
-            "sgi" => sgi(console, parts),
-            "lsdev" => lsdev(console, devices),
-            "lspci" => lspci(console, pci_roots),
-            "vcat" => vcat(console, parts, &mut devices.vsock),
-            "cpus" => cpus(console, fdt),
-            "start_cpu" => start_cpu(console, fdt, parts),
-            "" => {}
-            _ => {
-                writeln!(console, "Unrecognised command.").unwrap();
+    }
+}
+
+/// Returns the command with the given name, if there is one.
+fn find_command(name: &str) -> Option<&'static dyn Command> {
+    COMMANDS
+        .iter()
+        .find(|command| command.name() == name)
+        .copied()
+}
+
+/// Runs the given shell command line, reporting any error to the console.
+///
+/// If the line starts with `-v`, or `RUSAGE` is set, the resources used by the command are reported
+/// after it runs.
+///
+/// Returns the exit status of the command.
+pub fn run_command(context: &mut Context, line: &str) -> u8 {
+    let (verbose, line) = strip_verbose_flag(line);
+    let Some((name, args)) = split_command(line) else {
+        return STATUS_SUCCESS;
+    };
+    let Some(command) = find_command(name) else {
+        writeln!(context.console, "Unrecognised command.").unwrap();
+        return STATUS_NOT_FOUND;
+    };
+    if !verbose && !context.rusage {
+        return run_found_command(context, command, args);
+    }
+    let (status, usage) =
+        rusage::measure(context, |context| run_found_command(context, command, args));
+    writeln!(context.console, "{usage}").unwrap();
+    status
+}
+
+/// Splits a leading `-v` flag off the given command line, returning whether it was present and the
+/// rest of the line.
+fn strip_verbose_flag(line: &str) -> (bool, &str) {
+    match line.trim_start().strip_prefix("-v") {
+        Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => (true, rest),
+        _ => (false, line),
+    }
+}
+
+/// Runs the given command, reporting any error to the console, and returns its exit status.
+fn run_found_command(context: &mut Context, command: &dyn Command, args: Args) -> u8 {
+    match command.run(context, args) {
+        Ok(()) => STATUS_SUCCESS,
+        Err(e) => {
+            writeln!(context.console, "{}: {e}", command.name()).unwrap();
+            if e.show_usage() {
+                print_usage(context.console, command);
             }
+            e.exit_status()
+        }
+    }
+}
+
+/// Prints the usage of the given command.
+fn print_usage(console: &mut (impl Write + ?Sized), command: &dyn Command) {
+    writeln!(console, "Usage:").unwrap();
+    writeln!(console, "  {} {}", command.name(), command.usage()).unwrap();
+}
+
+/// Runs each line of the given script as a shell command, echoing it first.
+///
+/// Empty lines and lines starting with `#` are ignored. Stops early if a command asks the shell to
+/// exit.
+pub(super) fn run_script(context: &mut Context, script: &str) {
+    for line in script.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        writeln!(context.console, "$ {line}").unwrap();
+        run_command(context, line);
+        if context.exit.is_some() {
+            break;
         }
     }
-    alarm::irq_remove();
 }
 
-fn read_line(console: &mut (impl Write + Read)) -> ArrayVec<u8, 128> {
-    let mut line: ArrayVec<u8, 128> = ArrayVec::new();
+fn script(context: &mut Context, args: Args) -> Result<(), CommandError> {
+    args.finish()?;
+    run_script(context, DEMO_SCRIPT);
+    Ok(())
+}
+
+/// Reads shell commands from a VirtIO console and runs them, until an `exit` command or EOF.
+///
+/// Output is written to the main console.
+fn vscript(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let index = args.next_device(DeviceKind::Console, context.devices.console.len())?;
+    args.finish()?;
+    writeln!(
+        context.console,
+        "Reading commands from VirtIO console {index}..."
+    )
+    .unwrap();
+    let mut line = Line::new();
+    // Whether the rest of the current line is being ignored because it was too long.
+    let mut discarding = false;
     loop {
-        let mut c = [0];
-        console.read_exact(&mut c).unwrap();
-        match c[0] {
-            b'\r' | b'\n' => {
-                console.write_all(b"\r\n").unwrap();
-                return line;
-            }
-            EOF if line.is_empty() => {
-                console.write_all(b"\r\n").unwrap();
-                line.push(EOF);
-                return line;
+        let device = &mut context.devices.console[index];
+        let Some(c) = device.recv(true).context("reading from VirtIO console")? else {
+            device.wait_for_irq();
+            continue;
+        };
+        match c {
+            b'\r' => {}
+            b'\n' if discarding => discarding = false,
+            b'\n' => {
+                let Ok(command) = str::from_utf8(&line) else {
+                    writeln!(context.console, "Invalid UTF-8").unwrap();
+                    line.clear();
+                    continue;
+                };
+                let command = command.trim();
+                if command == "exit" {
+                    break;
+                }
+                if !command.is_empty() && !command.starts_with('#') {
+                    writeln!(context.console, "$ {command}").unwrap();
+                    run_command(context, command);
+                    if context.exit.is_some() {
+                        break;
+                    }
+                }
+                line.clear();
             }
+            EOF => break,
+            _ if discarding => {}
             c => {
-                if !c.is_ascii_control() {
-                    console.write_all(&[c]).unwrap();
-                    line.push(c);
+                if line.try_push(c).is_err() {
+                    writeln!(context.console, "Line too long, ignoring").unwrap();
+                    line.clear();
+                    discarding = true;
                 }
             }
         }
     }
+    writeln!(
+        context.console,
+        "Finished reading commands from VirtIO console {index}."
+    )
+    .unwrap();
+    Ok(())
 }
 
-fn date(console: &mut (impl Write + Read), rtc: &mut Rtc) {
-    let time = rtc.get_time();
-    writeln!(console, "{time}").unwrap();
+/// Exits the shell, and then stops the VM with the given status, or 0 if none is given.
+///
+/// In a session other than the UART's, only that session ends and the status is ignored.
+fn exit(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let status = args.optional("status")?.unwrap_or(0);
+    args.finish()?;
+    context.exit = Some(status);
+    Ok(())
 }
 
-fn dtdump(console: &mut impl Write, fdt: &Fdt) {
-    writeln!(console, "{fdt}").unwrap();
+fn help(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let name = args.next();
+    args.finish()?;
+    if let Some(name) = name {
+        let command = find_command(name).ok_or(CommandError::InvalidArgument {
+            name: "command",
+            value: name.into(),
+        })?;
+        writeln!(
+            context.console,
+            "{} - {}",
+            command.name(),
+            command.summary()
+        )
+        .unwrap();
+        print_usage(context.console, command);
+        return Ok(());
+    }
+    writeln!(context.console, "Commands:").unwrap();
+    for command in COMMANDS {
+        writeln!(
+            context.console,
+            "  {} - {}",
+            command.name(),
+            command.summary()
+        )
+        .unwrap();
+    }
+    Ok(())
 }
 
-fn help(console: &mut (impl Write + Read)) {
-    writeln!(console, "Commands:").unwrap();
-    writeln!(console, "  alarm - Sets an alarm in the future").unwrap();
-    writeln!(console, "  cpus - Lists the state of all CPUs").unwrap();
-    writeln!(console, "  date - Prints the current date and time").unwrap();
-    writeln!(console, "  dtdump - Dumps the device tree to the console").unwrap();
+#[cfg(feature = "heap-debug")]
+fn heapcheck(context: &mut Context, args: Args) -> Result<(), CommandError> {
+    args.finish()?;
+    let summary = crate::DEBUG_ALLOCATOR.check().context("checking heap")?;
     writeln!(
-        console,
-        "  exit - Exits the shell and powers off the system"
+        context.console,
+        "No corruption found in {} live allocations or {} quarantined blocks ({} bytes)",
+        summary.live, summary.quarantined, summary.quarantined_bytes
     )
     .unwrap();
-    writeln!(console, "  help - Prints this help").unwrap();
-    writeln!(console, "  sgi - Sends a software-generated interrupt").unwrap();
-    writeln!(console, "  lsdev - Lists devices").unwrap();
-    writeln!(console, "  lspci - Lists devices on the PCI bus").unwrap();
-    writeln!(console, "  start_cpu - Starts a secondary CPU").unwrap();
-    writeln!(console, "  vcat - Communicates with a vsock port").unwrap();
+    Ok(())
 }
 
-fn lsdev(console: &mut impl Write, devices: &mut Devices) {
-    writeln!(console, "Block devices:").unwrap();
-    for (i, device) in devices.block.iter_mut().enumerate() {
-        let mut id_buffer = [0; 20];
-        let id_len = match device.device_id(&mut id_buffer) {
-            Ok(id_len) => id_len,
-            Err(e) => {
-                writeln!(console, "Error getting ID: {e}").unwrap();
-                0
-            }
+fn date(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let utc = match args.next() {
+        None => false,
+        Some("-u") => true,
+        Some("set") => {
+            let time = args.required_str("time")?;
+            args.finish()?;
+            let time = parse_date_time(time, timezone::utc_offset()).ok_or_else(|| {
+                CommandError::InvalidArgument {
+                    name: "time",
+                    value: time.to_string(),
+                }
+            })?;
+            context.devices.rtc.set_time(time).context("setting time")?;
+            info!("Set RTC to {time}");
+            return Ok(());
+        }
+        Some(value) => {
+            return Err(CommandError::InvalidArgument {
+                name: "flag",
+                value: value.to_string(),
+            });
+        }
+    };
+    args.finish()?;
+    let time = context.devices.rtc.get_time();
+    if utc {
+        writeln!(context.console, "{time}").unwrap();
+    } else {
+        writeln!(context.console, "{}", timezone::local_time(time)).unwrap();
+    }
+    Ok(())
+}
+
+fn dmainfo(context: &mut Context, args: Args) -> Result<(), CommandError> {
+    args.finish()?;
+    let console = &mut *context.console;
+    if let Some(stats) = dma::stats() {
+        writeln!(console, "{stats}").unwrap();
+    } else {
+        writeln!(console, "DMA pool not initialised.").unwrap();
+    }
+    Ok(())
+}
+
+fn free(context: &mut Context, args: Args) -> Result<(), CommandError> {
+    args.finish()?;
+    let console = &mut *context.console;
+    writeln!(
+        console,
+        "{:<12} {:>10} {:>10} {:>10} {:>10}",
+        "", "total", "used", "free", "peak"
+    )
+    .unwrap();
+    let allocators = [
+        ("Heap", Some(heap_stats())),
+        (
+            "Page tables",
+            PAGETABLE
+                .get()
+                .map(|idmap| idmap.lock().page_allocator_stats()),
+        ),
+        ("Bounce pool", dma::bounce_stats()),
+    ];
+    for (name, stats) in allocators {
+        let Some(stats) = stats else {
+            continue;
         };
-        let id = str::from_utf8(&id_buffer[..id_len]).unwrap();
         writeln!(
             console,
-            "  {}: \"{}\", capacity {} sectors, {}",
-            i,
-            id,
-            device.capacity(),
-            if device.readonly() {
-                "read-only"
-            } else {
-                "read-write"
-            }
+            "{name:<12} {:>10} {:>10} {:>10} {:>10}",
+            stats.total_bytes,
+            stats.used_bytes,
+            stats.free_bytes(),
+            stats.peak_bytes
         )
         .unwrap();
     }
-    writeln!(console, "Console devices:").unwrap();
-    for (i, device) in devices.console.iter_mut().enumerate() {
-        writeln!(console, "  {}: {:?}", i, device.size().unwrap()).unwrap();
+    writeln!(
+        console,
+        "{} bytes of boot-time memory were reclaimed for the heap.",
+        reclaimed_boot_memory()
+    )
+    .unwrap();
+    Ok(())
+}
+
+fn dtdump(context: &mut Context, args: Args) -> Result<(), CommandError> {
+    args.finish()?;
+    writeln!(context.console, "{}", context.fdt).unwrap();
+    Ok(())
+}
+
+fn dtget(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let path = args.required_str("path")?;
+    let name = args.next();
+    args.finish()?;
+    let Context { console, fdt, .. } = context;
+    let node = fdt
+        .find_node(path)
+        .ok_or_else(|| CommandError::Failed(format!("No device tree node {path}")))?;
+    let Some(name) = name else {
+        for property in node.properties() {
+            match PropertyValue::parse(property.value()) {
+                PropertyValue::Empty => writeln!(console, "{};", property.name()).unwrap(),
+                value => writeln!(console, "{} = {value};", property.name()).unwrap(),
+            }
+        }
+        for child in node.children() {
+            writeln!(console, "{}/", child.name()).unwrap();
+        }
+        return Ok(());
+    };
+    let property = node
+        .property(name)
+        .ok_or_else(|| CommandError::Failed(format!("{path} has no property {name}")))?;
+    writeln!(console, "{}", PropertyValue::parse(property.value())).unwrap();
+    // Addresses and sizes depend on the cells of the node and its parent, so decode them too.
+    match name {
+        "reg" => {
+            let entries = reg_entries(&node).ok_or("Invalid reg property")?;
+            for entry in entries {
+                writeln!(console, "  {entry}").unwrap();
+            }
+        }
+        "ranges" => {
+            let Some((parent_path, _)) = path.trim_end_matches('/').rsplit_once('/') else {
+                return Err("The root node has no parent to translate ranges to".into());
+            };
+            let parent = fdt
+                .find_node(if parent_path.is_empty() {
+                    "/"
+                } else {
+                    parent_path
+                })
+                .ok_or("Parent node not found")?;
+            let (child_address_cells, size_cells) = child_cells(&node);
+            let (parent_address_cells, _) = child_cells(&parent);
+            let entries = property_cells(&node, "ranges")
+                .and_then(|cells| {
+                    decode_ranges(
+                        &cells,
+                        child_address_cells,
+                        parent_address_cells,
+                        size_cells,
+                    )
+                })
+                .ok_or("Invalid ranges property")?;
+            for entry in entries {
+                writeln!(console, "  {entry}").unwrap();
+            }
+        }
+        _ => {}
     }
-    writeln!(console, "Vsock devices:").unwrap();
-    for (i, device) in devices.vsock.iter_mut().enumerate() {
-        writeln!(console, "  {}: guest CID {}", i, device.guest_cid()).unwrap();
+    Ok(())
+}
+
+fn el(context: &mut Context, args: Args) -> Result<(), CommandError> {
+    args.finish()?;
+    let console = &mut *context.console;
+    writeln!(console, "Running at EL{}", current_el()).unwrap();
+    if let Some(hcr) = hcr_el2() {
+        writeln!(console, "HCR_EL2: {hcr:?}").unwrap();
+        for (flag, name) in [
+            (HcrEl2::IMO, "Physical IRQs"),
+            (HcrEl2::FMO, "Physical FIQs"),
+            (HcrEl2::AMO, "SErrors"),
+        ] {
+            writeln!(
+                console,
+                "  {name} routed to {}",
+                if hcr.contains(flag) { "EL2" } else { "EL1" }
+            )
+            .unwrap();
+        }
+    } else {
+        writeln!(console, "  Physical IRQs, FIQs and SErrors taken to EL1").unwrap();
     }
+    writeln!(
+        console,
+        "  Sleeping with the {} virtual timer, PPI {:?}",
+        if vhe_enabled() { "EL2" } else { "EL1" },
+        generic_timer::TimerChannel::Virtual.irq()
+    )
+    .unwrap();
+    Ok(())
 }
 
-fn lspci(console: &mut impl Write, pci_roots: &mut [PciRoot<MmioCam>]) {
+fn gicinfo(context: &mut Context, args: Args) -> Result<(), CommandError> {
+    args.finish()?;
+    let console = &mut *context.console;
+    match gic_layout() {
+        None => writeln!(console, "GIC not initialised.").unwrap(),
+        Some(GicLayout::V2(layout)) => {
+            writeln!(console, "GICv2").unwrap();
+            writeln!(console, "  GICD: {}", layout.distributor).unwrap();
+            writeln!(console, "  GICC: {}", layout.cpu_interface).unwrap();
+        }
+        Some(GicLayout::V3(layout)) => {
+            writeln!(console, "GICv3").unwrap();
+            writeln!(console, "  GICD: {}", layout.distributor).unwrap();
+            for (index, region) in layout.redistributor_regions.iter().enumerate() {
+                writeln!(console, "  GICR region {index}: {region}").unwrap();
+            }
+            match &layout.its {
+                Some(its) => writeln!(console, "  ITS: {its}").unwrap(),
+                None => writeln!(console, "  No ITS").unwrap(),
+            }
+            if layout.missing_its() {
+                writeln!(
+                    console,
+                    "  Warning: devices use MSIs, but there is no ITS to deliver them"
+                )
+                .unwrap();
+            }
+            for (cpu, redistributor) in layout.redistributors.iter().enumerate() {
+                writeln!(
+                    console,
+                    "  CPU {cpu}: redistributor at {:#x} in GICR region {}",
+                    redistributor.address, redistributor.region
+                )
+                .unwrap();
+            }
+            let fdt_cpus = context.fdt.cpus().unwrap().cpus().count();
+            if layout.redistributors.len() < fdt_cpus {
+                writeln!(
+                    console,
+                    "  Warning: CPUs {} to {} don't have contiguous redistributors, so aren't used",
+                    layout.redistributors.len(),
+                    fdt_cpus - 1
+                )
+                .unwrap();
+            }
+        }
+    }
+    Ok(())
+}
+
+fn lsdev(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let id = args.optional::<DeviceId>("device ID")?;
+    args.finish()?;
+    let Context {
+        console, devices, ..
+    } = context;
+    if let Some(id) = id {
+        let info = devices.find(id).ok_or(CommandError::NoSuchDevice {
+            kind: id.kind.name(),
+            index: id.number,
+        })?;
+        write_device_info(console, info, devices.owners.owner(id));
+    } else {
+        for info in devices.registry() {
+            write_device_info(console, info, devices.owners.owner(info.id));
+        }
+    }
+    Ok(())
+}
+
+/// Writes a line describing the given device and its current owner, if any, to the console.
+fn write_device_info(console: &mut (impl Write + ?Sized), info: &DeviceInfo, owner: Option<&str>) {
+    write!(
+        console,
+        "{:<7} {:<8} {:<26} {}",
+        info.id.to_string(),
+        info.driver,
+        info.location,
+        info.description
+    )
+    .unwrap();
+    match owner {
+        Some(owner) => writeln!(console, " (owned by {owner})").unwrap(),
+        None => writeln!(console).unwrap(),
+    }
+}
+
+fn lspci(context: &mut Context, args: Args) -> Result<(), CommandError> {
+    let mut verbose = false;
+    let mut hex_dump = false;
+    for arg in args {
+        match arg {
+            "-v" => verbose = true,
+            "-x" => hex_dump = true,
+            _ => return Err(CommandError::Usage),
+        }
+    }
+    let Context {
+        console, pci_roots, ..
+    } = context;
     writeln!(console, "{} PCI roots", pci_roots.len()).unwrap();
-    for pci_root in pci_roots {
-        for (device_function, info) in pci_root.enumerate_bus(0) {
+    for pci_root in pci_roots.iter_mut() {
+        for (device_function, info) in pci_root.enumerate_devices() {
             let (status, command) = pci_root.get_status_command(device_function);
             writeln!(
                 console,
@@ -178,9 +1058,12 @@ fn lspci(console: &mut impl Write, pci_roots: &mut [PciRoot<MmioCam>]) {
             if let Some(virtio_type) = virtio_device_type(&info) {
                 writeln!(console, "  VirtIO {virtio_type:?}").unwrap();
             }
+            if !pci_root.is_claimed(device_function) {
+                writeln!(console, "  No driver, see `pcidump {device_function}`").unwrap();
+            }
             for (bar_index, info) in pci_root
-                .bars(device_function)
-                .unwrap()
+                .device_bars(device_function)
+                .context("reading BARs")?
                 .into_iter()
                 .enumerate()
             {
@@ -188,69 +1071,241 @@ fn lspci(console: &mut impl Write, pci_roots: &mut [PciRoot<MmioCam>]) {
                     writeln!(console, "  BAR {bar_index}: {info}").unwrap();
                 }
             }
+            if verbose {
+                for (capability, description) in pci_root.describe_capabilities(device_function) {
+                    writeln!(
+                        console,
+                        "  Capability {:#04x}: {description}",
+                        capability.offset
+                    )
+                    .unwrap();
+                }
+            }
+            if hex_dump {
+                write!(
+                    console,
+                    "{}",
+                    HexDump {
+                        address: 0,
+                        data: &pci_root.config_space(device_function),
+                    }
+                )
+                .unwrap();
+            }
         }
     }
+    Ok(())
 }
 
-fn vcat<'a, H: Hal, T: Transport>(
-    console: &mut (impl Write + Read + ReadReady),
-    args: impl Iterator<Item = &'a str>,
-    vsock: &mut [VsockConnectionManager<H, T>],
-) {
-    let args = args.collect::<ArrayVec<_, 4>>();
-    if args.len() != 2 {
-        writeln!(console, "Usage:").unwrap();
-        writeln!(console, "  vcat <CID> <port>").unwrap();
-        return;
+fn power(context: &mut Context, args: Args) -> Result<(), CommandError> {
+    args.finish()?;
+    let Context {
+        console, devices, ..
+    } = context;
+    let meter = &mut devices.energy_meter;
+    writeln!(console, "Energy meter: {}", meter.name()).unwrap();
+    if let Some(energy_uj) = meter.energy_uj() {
+        writeln!(console, "  Total energy: {energy_uj} µJ").unwrap();
+    } else {
+        writeln!(console, "  No energy reading available").unwrap();
     }
-    let Ok(cid) = args[0].parse() else {
-        writeln!(console, "Invalid CID {}", args[0]).unwrap();
-        return;
-    };
-    let Ok(port) = args[1].parse() else {
-        writeln!(console, "Invalid port {}", args[1]).unwrap();
-        return;
+    Ok(())
+}
+
+fn rescan(context: &mut Context, args: Args) -> Result<(), CommandError> {
+    args.finish()?;
+    let Context {
+        console,
+        pci_roots,
+        devices,
+        fdt,
+        ..
+    } = context;
+    let counts = |devices: &Devices| {
+        (
+            devices.block.len(),
+            devices.net.len(),
+            devices.console.len(),
+            devices.vsock.len(),
+        )
     };
-    let Some(vsock) = vsock.get_mut(0) else {
-        writeln!(console, "No vsock device found.").unwrap();
-        return;
+    let before = counts(devices);
+    for (root_index, pci_root) in pci_roots.iter_mut().enumerate() {
+        for (device_function, info) in pci_root.rescan() {
+            writeln!(console, "New PCI device {info} at {device_function}").unwrap();
+        }
+        find_pci_devices(root_index, pci_root, devices);
+    }
+    // SAFETY: The FDT is the one we booted with, and VirtIO MMIO transports are only created by
+    // `find_virtio_mmio_devices`, which skips devices which already have one.
+    unsafe { find_virtio_mmio_devices(fdt, devices) };
+    let after = counts(devices);
+    writeln!(
+        console,
+        "Added {} block, {} network, {} console and {} vsock devices",
+        after.0 - before.0,
+        after.1 - before.1,
+        after.2 - before.2,
+        after.3 - before.3
+    )
+    .unwrap();
+    Ok(())
+}
+
+fn set(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let assignment = args.next();
+    args.finish()?;
+    let Some(assignment) = assignment else {
+        writeln!(context.console, "RUSAGE={}", u8::from(context.rusage)).unwrap();
+        return Ok(());
     };
+    let (name, value) = assignment.split_once('=').ok_or(CommandError::Usage)?;
+    match (name, value) {
+        ("RUSAGE", "0") => context.rusage = false,
+        ("RUSAGE", "1") => context.rusage = true,
+        ("RUSAGE", _) => {
+            return Err(CommandError::InvalidArgument {
+                name: "value",
+                value: value.into(),
+            });
+        }
+        _ => {
+            return Err(CommandError::InvalidArgument {
+                name: "name",
+                value: name.into(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn sleep(_context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let ms = args.required("ms")?;
+    args.finish()?;
+    generic_timer::sleep(Duration::from_millis(ms));
+    Ok(())
+}
+
+fn vcat(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let cid = args.next_cid()?;
+    let port = args.next_port()?;
+    let connect_timeout = args
+        .optional("connect timeout")?
+        .unwrap_or(DEFAULT_VSOCK_CONNECT_TIMEOUT_S);
+    let idle_timeout = args.optional("idle timeout")?.unwrap_or(0);
+    args.finish()?;
+    let connect_timeout = Duration::from_secs(connect_timeout);
+    let idle_timeout = (idle_timeout > 0).then(|| Duration::from_secs(idle_timeout));
+    let Context {
+        console, devices, ..
+    } = context;
+    let vsock = devices
+        .vsock
+        .first_mut()
+        .ok_or(CommandError::NoSuchDevice {
+            kind: "vsock",
+            index: 0,
+        })?;
+    let claim = devices.owners.claim(VSOCK0, "vcat")?;
     let local_port = 42;
     let peer = VsockAddr { cid, port };
     writeln!(console, "Connecting to {}:{}...", peer.cid, peer.port).unwrap();
-    vsock.connect(peer, local_port).unwrap();
+    if let Err(e) = vsock.connect(peer, local_port) {
+        devices.owners.release(claim);
+        return Err(e).context("connecting");
+    }
 
-    loop {
-        if console.read_ready().unwrap() {
-            let mut buffer = [0; 8];
-            let bytes_read = console.read(&mut buffer).unwrap();
-            vsock
-                .send(peer, local_port, &buffer[0..bytes_read])
-                .unwrap();
+    let result = block_on(async {
+        let connected = generic_timer::timeout(connect_timeout, async {
+            loop {
+                let event = next_vsock_event(vsock).await?;
+                if event.destination.port == local_port && event.source == peer {
+                    return Ok::<_, VirtioError>(event.event_type);
+                }
+            }
+        })
+        .await;
+        match connected {
+            Ok(event) => match event.context("polling vsock")? {
+                VsockEventType::Connected => {}
+                event => {
+                    return Err(CommandError::Failed(format!(
+                        "Connecting failed: {event:?}"
+                    )));
+                }
+            },
+            Err(TimedOut) => {
+                return Err(CommandError::Failed(format!(
+                    "Peer didn't accept the connection within {} s",
+                    connect_timeout.as_secs()
+                )));
+            }
         }
-        if let Some(event) = vsock.poll().unwrap() {
+        writeln!(console, "Connected. Press Ctrl-] to disconnect.").unwrap();
+
+        loop {
+            let mut buffer = [0; 8];
+            let next = select(console.read_async(&mut buffer), next_vsock_event(vsock));
+            let next = match idle_timeout {
+                Some(idle_timeout) => generic_timer::timeout(idle_timeout, next).await,
+                None => Ok(next.await),
+            };
+            let event = match next {
+                Err(TimedOut) => {
+                    writeln!(
+                        console,
+                        "No activity for {} s, disconnecting.",
+                        idle_timeout.unwrap_or_default().as_secs()
+                    )
+                    .unwrap();
+                    vsock
+                        .force_close(peer, local_port)
+                        .context("closing connection")?;
+                    return Ok(());
+                }
+                Ok(Either::Left(bytes_read)) => {
+                    let bytes_read = bytes_read.context("reading from console")?;
+                    let input = &buffer[0..bytes_read];
+                    let escape = input.iter().position(|&byte| byte == DISCONNECT_KEY);
+                    let to_send = &input[..escape.unwrap_or(input.len())];
+                    if !to_send.is_empty() {
+                        vsock.send(peer, local_port, to_send).context("sending")?;
+                    }
+                    if escape.is_some() {
+                        writeln!(console, "\r\nDisconnecting.").unwrap();
+                        vsock
+                            .force_close(peer, local_port)
+                            .context("closing connection")?;
+                        return Ok(());
+                    }
+                    continue;
+                }
+                Ok(Either::Right(event)) => event.context("polling vsock")?,
+            };
             if event.destination.port == local_port && event.source == peer {
                 match event.event_type {
-                    VsockEventType::Connected => {
-                        writeln!(console, "Connected.").unwrap();
-                    }
                     VsockEventType::Disconnected {
                         reason: DisconnectReason::Shutdown,
                     } => {
                         writeln!(console, "Connection shut down.").unwrap();
-                        return;
+                        return Ok(());
                     }
                     VsockEventType::Disconnected {
                         reason: DisconnectReason::Reset,
                     } => {
                         writeln!(console, "Connection reset.").unwrap();
-                        return;
+                        return Ok(());
                     }
                     VsockEventType::Received { .. } => {
-                        while vsock.recv_buffer_available_bytes(peer, local_port).unwrap() > 0 {
+                        while vsock
+                            .recv_buffer_available_bytes(peer, local_port)
+                            .context("receiving")?
+                            > 0
+                        {
                             let mut recv_buffer = [0; 10];
-                            let bytes_read =
-                                vsock.recv(peer, local_port, &mut recv_buffer).unwrap();
+                            let bytes_read = vsock
+                                .recv(peer, local_port, &mut recv_buffer)
+                                .context("receiving")?;
                             console.write_all(&recv_buffer[0..bytes_read]).unwrap();
                         }
                     }
@@ -267,5 +1322,11 @@ fn vcat<'a, H: Hal, T: Transport>(
                 .unwrap();
             }
         }
+    });
+    if result.is_err() {
+        // Don't leave the connection behind to block the next attempt from the same port.
+        let _ = vsock.force_close(peer, local_port);
     }
+    devices.owners.release(claim);
+    result
 }
@@ -0,0 +1,154 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Interactive stopwatch and countdown timers, which keep a live display on the console.
+
+use crate::apps::command::{Args, CommandError, Context, ErrorContext, Terminal};
+use core::{
+    fmt::{self, Display, Formatter},
+    time::Duration,
+};
+use embedded_io::Write;
+use osdemo_core::{
+    drivers::generic_timer::{Instant, TimedOut, sleep_until, timeout},
+    executor::{Either, block_on, select},
+};
+
+/// How often the stopwatch display is redrawn.
+const STOPWATCH_REFRESH: Duration = Duration::from_millis(100);
+
+/// The bell character, which makes most terminals beep.
+const BEL: u8 = 0x07;
+
+/// The end of text character, sent by Ctrl-C.
+const ETX: u8 = 0x03;
+
+/// The end of transmission character, sent by Ctrl-D.
+const EOF: u8 = 0x04;
+
+/// What a key pressed while a timer is running asks for.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Action {
+    Lap,
+    Stop,
+}
+
+impl Action {
+    /// Returns the action for the given key, if it has one.
+    fn for_key(key: u8) -> Option<Self> {
+        match key {
+            b'\r' | b'\n' | b'l' | b' ' => Some(Self::Lap),
+            b'q' | b's' | ETX | EOF => Some(Self::Stop),
+            _ => None,
+        }
+    }
+}
+
+/// A duration formatted as minutes, seconds and hundredths of a second.
+struct Elapsed(Duration);
+
+impl Display for Elapsed {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let seconds = self.0.as_secs();
+        write!(
+            f,
+            "{:02}:{:02}.{:02}",
+            seconds / 60,
+            seconds % 60,
+            self.0.subsec_millis() / 10
+        )
+    }
+}
+
+/// Runs a stopwatch until a stop key is pressed, recording a lap whenever a lap key is pressed.
+pub fn stopwatch(context: &mut Context, args: Args) -> Result<(), CommandError> {
+    args.finish()?;
+    let console = &mut *context.console;
+    writeln!(
+        console,
+        "Press Enter or l to record a lap, and q or Ctrl-C to stop."
+    )
+    .unwrap();
+    let start = Instant::now();
+    let mut lap_start = start;
+    let mut laps = 0;
+    block_on(async {
+        loop {
+            write!(console, "\r{}", Elapsed(start.elapsed())).unwrap();
+            let mut buffer = [0; 8];
+            let bytes_read = match timeout(STOPWATCH_REFRESH, console.read_async(&mut buffer)).await
+            {
+                Ok(bytes_read) => bytes_read.context("reading from console")?,
+                Err(TimedOut) => continue,
+            };
+            for action in buffer[..bytes_read]
+                .iter()
+                .copied()
+                .filter_map(Action::for_key)
+            {
+                let now = Instant::now();
+                match action {
+                    Action::Lap => {
+                        laps += 1;
+                        writeln!(
+                            console,
+                            "\r{}  lap {laps}: {}",
+                            Elapsed(now.duration_since(start)),
+                            Elapsed(now.duration_since(lap_start))
+                        )
+                        .unwrap();
+                        lap_start = now;
+                    }
+                    Action::Stop => {
+                        writeln!(console, "\r{}  stopped", Elapsed(now.duration_since(start)))
+                            .unwrap();
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Counts down the given number of seconds with a live display, and then rings the terminal bell.
+pub fn countdown(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let seconds: u64 = args.required("seconds")?;
+    args.finish()?;
+    let console = &mut *context.console;
+    let start = Instant::now();
+    block_on(async {
+        for remaining in (1..=seconds).rev() {
+            write!(console, "\r{remaining:>5} s remaining ").unwrap();
+            let tick = start + Duration::from_secs(seconds - remaining + 1);
+            if wait_or_stop(console, tick).await? {
+                writeln!(console, "\rCountdown cancelled with {remaining} s left.").unwrap();
+                return Ok(());
+            }
+        }
+        writeln!(console, "\r{:<22}{}", "Time's up!", char::from(BEL)).unwrap();
+        Ok(())
+    })
+}
+
+/// Waits until the given instant, returning early with `true` if a stop key is pressed first.
+pub async fn wait_or_stop(
+    console: &mut dyn Terminal,
+    until: Instant,
+) -> Result<bool, CommandError> {
+    loop {
+        let mut buffer = [0; 8];
+        match select(sleep_until(until), console.read_async(&mut buffer)).await {
+            Either::Left(()) => return Ok(false),
+            Either::Right(bytes_read) => {
+                let bytes_read = bytes_read.context("reading from console")?;
+                if buffer[..bytes_read]
+                    .iter()
+                    .any(|&key| Action::for_key(key) == Some(Action::Stop))
+                {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+}
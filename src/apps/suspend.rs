@@ -0,0 +1,189 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Suspending the current core with PSCI CPU_SUSPEND, or the whole system with SYSTEM_SUSPEND,
+//! until an RTC alarm wakes it.
+
+use crate::{
+    apps::{
+        alarm,
+        command::{Args, CommandError, Context, ErrorContext},
+        timezone,
+    },
+    platform::{Platform, PlatformImpl},
+};
+use alloc::{format, string::String, vec::Vec};
+use chrono::{DateTime, Duration, Utc};
+use embedded_io::Write;
+use osdemo_core::{
+    psci::suspend_current_cpu,
+    rtc::RtcDevice,
+    system_suspend::{SuspendOutcome, suspend_system},
+    timer::{counter, counter_frequency, ms_to_ticks, wait_until},
+};
+use percore::exception_free;
+
+/// The number of seconds to stay suspended for, unless otherwise specified.
+const DEFAULT_SUSPEND_SECONDS: i64 = 1;
+
+/// How long to keep waiting for the alarm after it was due, in milliseconds, before giving up.
+const WAKEUP_SLACK_MS: u64 = 2000;
+
+/// The name used to choose suspending the whole system rather than just the current core.
+const SYSTEM_STATE_NAME: &str = "system";
+
+/// Suspends the current core in the given platform power state, or the whole system, until an RTC
+/// alarm wakes it, and reports how long it was suspended for. Lists the supported states if none
+/// is given.
+pub fn suspend(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let Some(name) = args.next() else {
+        for state in PlatformImpl::suspend_states() {
+            writeln!(
+                context.console,
+                "{}: power state {:#010x}, {}",
+                state.name, state.power_state, state.description
+            )
+            .unwrap();
+        }
+        writeln!(
+            context.console,
+            "{SYSTEM_STATE_NAME}: the whole system with SYSTEM_SUSPEND, quiescing devices first"
+        )
+        .unwrap();
+        return Ok(());
+    };
+    let seconds = args.optional("seconds")?.unwrap_or(DEFAULT_SUSPEND_SECONDS);
+    args.finish()?;
+    if name == SYSTEM_STATE_NAME {
+        return suspend_system_until_alarm(context, seconds);
+    }
+    let state = PlatformImpl::suspend_states()
+        .iter()
+        .find(|state| state.name == name)
+        .ok_or_else(|| {
+            let names = PlatformImpl::suspend_states()
+                .iter()
+                .map(|state| state.name)
+                .collect::<Vec<_>>();
+            CommandError::Failed(format!(
+                "Unknown suspend state {name}, expected one of: {}, {SYSTEM_STATE_NAME}",
+                names.join(", ")
+            ))
+        })?;
+
+    let rtc = &mut *context.devices.rtc;
+    let alarm_time = set_wakeup_alarm(rtc, seconds)?;
+    writeln!(
+        context.console,
+        "Suspending in {} until {}",
+        state.name,
+        timezone::local_time(alarm_time)
+    )
+    .unwrap();
+
+    let start = counter();
+    let deadline = start + ms_to_ticks(seconds as u64 * 1000 + WAKEUP_SLACK_MS);
+    let mut wakeups = 0u64;
+    let mut result = Ok(());
+    while !alarm::fired() && counter() < deadline {
+        // Check with exceptions masked so that the alarm can't fire between the check and the
+        // call. If it does then it still wakes us, and its handler runs once they are unmasked.
+        result = exception_free(|_| {
+            if alarm::fired() {
+                Ok(())
+            } else {
+                suspend_current_cpu(state.power_state)
+            }
+        });
+        if result.is_err() {
+            break;
+        }
+        wakeups += 1;
+    }
+    let elapsed_ms = elapsed_ms(start);
+    let fired = alarm::fired();
+    finish_wakeup_alarm(rtc);
+
+    result.context("suspending")?;
+    if !fired {
+        return Err(CommandError::Failed(format!(
+            "Alarm didn't wake the core within {elapsed_ms} ms"
+        )));
+    }
+    let other_wakeups = if wakeups > 1 {
+        format!(", after {} other wakeups", wakeups - 1)
+    } else {
+        String::new()
+    };
+    writeln!(
+        context.console,
+        "Resumed after {elapsed_ms} ms{other_wakeups}"
+    )
+    .unwrap();
+    Ok(())
+}
+
+/// Quiesces all devices and suspends the whole system with PSCI SYSTEM_SUSPEND until an RTC alarm
+/// wakes it, then restores the devices and reports how long it was suspended for.
+fn suspend_system_until_alarm(context: &mut Context, seconds: i64) -> Result<(), CommandError> {
+    let Context {
+        console, devices, ..
+    } = context;
+
+    devices.quiesce().context("quiescing devices")?;
+    let alarm_time = match set_wakeup_alarm(&mut *devices.rtc, seconds) {
+        Ok(alarm_time) => alarm_time,
+        Err(e) => {
+            devices.resume();
+            return Err(e);
+        }
+    };
+    writeln!(
+        console,
+        "Sleeping until {}",
+        timezone::local_time(alarm_time)
+    )
+    .unwrap();
+
+    let start = counter();
+    let outcome = suspend_system();
+    devices.resume();
+    // The interrupt which woke the system is handled now that exceptions are unmasked again.
+    let fired =
+        outcome.is_ok() && wait_until(seconds as u64 * 1000 + WAKEUP_SLACK_MS, alarm::fired);
+    let elapsed_ms = elapsed_ms(start);
+    finish_wakeup_alarm(&mut *devices.rtc);
+
+    let how = match outcome.context("suspending system")? {
+        SuspendOutcome::Resumed => "Resumed",
+        SuspendOutcome::Returned => "SYSTEM_SUSPEND returned without losing state",
+    };
+    let woken_by = if fired { "the alarm" } else { "something else" };
+    writeln!(console, "{how} after {elapsed_ms} ms, woken by {woken_by}").unwrap();
+    Ok(())
+}
+
+/// Sets an RTC alarm for the given number of seconds from now, to wake the system, and returns the
+/// time it is set for.
+fn set_wakeup_alarm(rtc: &mut dyn RtcDevice, seconds: i64) -> Result<DateTime<Utc>, CommandError> {
+    if seconds <= 0 {
+        return Err("Must suspend for at least one second.".into());
+    }
+    alarm::irq_finish(rtc);
+    let alarm_time = rtc.get_time() + Duration::seconds(seconds);
+    rtc.set_match(alarm_time).context("setting alarm")?;
+    rtc.enable_interrupt(true);
+    Ok(alarm_time)
+}
+
+/// Clears and disables the alarm set by `set_wakeup_alarm`.
+fn finish_wakeup_alarm(rtc: &mut dyn RtcDevice) {
+    alarm::irq_finish(rtc);
+    rtc.enable_interrupt(false);
+}
+
+/// Returns the number of milliseconds since the given counter value.
+fn elapsed_ms(start: u64) -> u128 {
+    u128::from(counter() - start) * 1000 / u128::from(counter_frequency())
+}
@@ -0,0 +1,64 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! The offset from UTC which times are displayed in. The RTC itself is always kept in UTC.
+
+use crate::apps::command::{Args, CommandError, Context};
+use alloc::string::ToString;
+use chrono::{DateTime, FixedOffset, Utc};
+use core::sync::atomic::{AtomicI32, Ordering};
+use dtoolkit::fdt::Fdt;
+use embedded_io::Write;
+use log::{info, warn};
+use osdemo::utc_offset::{BOOTARG, parse_utc_offset};
+use osdemo_core::fdt::{bootarg_value, bootargs};
+
+/// The current UTC offset, in seconds east of UTC.
+static UTC_OFFSET_SECONDS: AtomicI32 = AtomicI32::new(0);
+
+/// Sets the UTC offset from the `utc_offset` argument in the kernel command line, if there is one.
+pub fn init(fdt: &Fdt) {
+    let Some(value) = bootargs(fdt).and_then(|args| bootarg_value(args, BOOTARG)) else {
+        return;
+    };
+    match parse_utc_offset(value) {
+        Some(offset) => {
+            set_utc_offset(offset);
+            info!("Using UTC offset {offset}");
+        }
+        None => warn!("Ignoring invalid {BOOTARG} {value:?}"),
+    }
+}
+
+/// Returns the offset which times are displayed in.
+pub fn utc_offset() -> FixedOffset {
+    FixedOffset::east_opt(UTC_OFFSET_SECONDS.load(Ordering::Relaxed)).unwrap()
+}
+
+/// Sets the offset which times are displayed in.
+pub fn set_utc_offset(offset: FixedOffset) {
+    UTC_OFFSET_SECONDS.store(offset.local_minus_utc(), Ordering::Relaxed);
+}
+
+/// Converts the given time to the configured UTC offset, for display.
+pub fn local_time(time: DateTime<Utc>) -> DateTime<FixedOffset> {
+    time.with_timezone(&utc_offset())
+}
+
+/// Prints or changes the UTC offset which times are displayed in.
+pub fn tz(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let value = args.next();
+    args.finish()?;
+    match value {
+        None => writeln!(context.console, "UTC{}", utc_offset()).unwrap(),
+        Some(value) => {
+            let offset = parse_utc_offset(value).ok_or_else(|| CommandError::InvalidArgument {
+                name: "offset",
+                value: value.to_string(),
+            })?;
+            set_utc_offset(offset);
+        }
+    }
+    Ok(())
+}
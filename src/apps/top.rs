@@ -0,0 +1,81 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Showing how much of the time each CPU core spends idle, waiting for interrupts.
+
+use crate::apps::{
+    command::{Args, CommandError, Context},
+    stopwatch::wait_or_stop,
+};
+use alloc::{string::ToString, vec::Vec};
+use core::time::Duration;
+use embedded_io::Write;
+use osdemo_core::{
+    cpus::cpu_count,
+    drivers::generic_timer::Instant,
+    executor::block_on,
+    idle::{IdleStats, idle_stats},
+};
+
+/// How often the display is refreshed if no interval is given, in milliseconds.
+const DEFAULT_INTERVAL_MS: u64 = 1000;
+
+/// The shortest refresh interval allowed, in milliseconds.
+const MIN_INTERVAL_MS: u64 = 10;
+
+/// Shows the share of time each core spent in `wfi` over each interval, until a stop key is
+/// pressed.
+pub fn top(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let interval_ms = args.optional("interval_ms")?.unwrap_or(DEFAULT_INTERVAL_MS);
+    args.finish()?;
+    if interval_ms < MIN_INTERVAL_MS {
+        return Err(CommandError::InvalidArgument {
+            name: "interval_ms",
+            value: interval_ms.to_string(),
+        });
+    }
+    let interval = Duration::from_millis(interval_ms);
+    let console = &mut *context.console;
+    writeln!(console, "Press q or Ctrl-C to stop.").unwrap();
+
+    block_on(async {
+        let mut previous = sample();
+        let mut previous_time = Instant::now();
+        loop {
+            if wait_or_stop(console, previous_time + interval).await? {
+                return Ok(());
+            }
+            let current = sample();
+            let now = Instant::now();
+            let elapsed_ticks = now.ticks() - previous_time.ticks();
+            let elapsed_ms = now.duration_since(previous_time).as_millis();
+            writeln!(console, "CPU   idle  wakeups/s  total idle").unwrap();
+            for (cpu, (before, after)) in previous.iter().zip(&current).enumerate() {
+                let idle_ticks = after.ticks - before.ticks;
+                let wakeups = after.wakeups - before.wakeups;
+                let idle_permille = u128::from(idle_ticks) * 1000 / u128::from(elapsed_ticks);
+                let wakeups_per_second = u128::from(wakeups) * 1000 / elapsed_ms;
+                let total = after.duration();
+                writeln!(
+                    console,
+                    "{cpu:>3} {:>3}.{}% {wakeups_per_second:>10} {:>7}.{:03} s",
+                    idle_permille / 10,
+                    idle_permille % 10,
+                    total.as_secs(),
+                    total.subsec_millis()
+                )
+                .unwrap();
+            }
+            previous = current;
+            previous_time = now;
+        }
+    })
+}
+
+/// Returns the idle statistics of every core, by CPU index.
+fn sample() -> Vec<IdleStats> {
+    (0..cpu_count())
+        .map(|cpu| idle_stats(cpu).unwrap_or_default())
+        .collect()
+}
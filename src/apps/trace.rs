@@ -0,0 +1,97 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Turning tracing of heap and DMA allocations, and of exceptions, on and off.
+
+use crate::{
+    alloc_trace,
+    apps::command::{Args, CommandError, Context},
+    exception_trace,
+};
+use embedded_io::Write;
+
+/// The smallest heap allocation traced if no threshold is given, in bytes.
+const DEFAULT_MIN_SIZE: usize = 1024;
+
+/// The most exceptions traced each second if no limit is given.
+const DEFAULT_MAX_EXCEPTIONS_PER_SECOND: u32 = 100;
+
+pub fn trace(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let console = &mut *context.console;
+    match args.required_str("subsystem")? {
+        "alloc" => trace_alloc(console, args),
+        "exceptions" => trace_exceptions(console, args),
+        _ => Err(CommandError::Usage),
+    }
+}
+
+/// Turns tracing of heap and DMA allocations on or off, or shows whether it is on.
+fn trace_alloc(console: &mut (impl Write + ?Sized), mut args: Args) -> Result<(), CommandError> {
+    match args.next() {
+        Some("on") => {
+            let min_size = args.optional("min_size")?.unwrap_or(DEFAULT_MIN_SIZE);
+            args.finish()?;
+            alloc_trace::enable(min_size);
+            writeln!(
+                console,
+                "Tracing heap allocations of at least {min_size} bytes and all DMA allocations to \
+                 dmesg."
+            )
+            .unwrap();
+        }
+        Some("off") => {
+            args.finish()?;
+            alloc_trace::disable();
+            writeln!(console, "Allocation tracing off.").unwrap();
+        }
+        None => match alloc_trace::min_size() {
+            Some(min_size) => writeln!(
+                console,
+                "Tracing heap allocations of at least {min_size} bytes and all DMA allocations."
+            )
+            .unwrap(),
+            None => writeln!(console, "Allocation tracing off.").unwrap(),
+        },
+        Some(_) => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+/// Turns tracing of exception entry and exit on or off, or shows whether it is on.
+fn trace_exceptions(
+    console: &mut (impl Write + ?Sized),
+    mut args: Args,
+) -> Result<(), CommandError> {
+    match args.next() {
+        Some("on") => {
+            let max_per_second = args
+                .optional("max_per_second")?
+                .unwrap_or(DEFAULT_MAX_EXCEPTIONS_PER_SECOND);
+            args.finish()?;
+            exception_trace::enable(max_per_second);
+            writeln!(
+                console,
+                "Tracing up to {max_per_second} exceptions per second to dmesg, timed in {}.",
+                exception_trace::time_unit()
+            )
+            .unwrap();
+        }
+        Some("off") => {
+            args.finish()?;
+            exception_trace::disable();
+            writeln!(console, "Exception tracing off.").unwrap();
+        }
+        None => match exception_trace::max_per_second() {
+            Some(max_per_second) => writeln!(
+                console,
+                "Tracing up to {max_per_second} exceptions per second, timed in {}.",
+                exception_trace::time_unit()
+            )
+            .unwrap(),
+            None => writeln!(console, "Exception tracing off.").unwrap(),
+        },
+        Some(_) => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
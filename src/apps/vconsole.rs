@@ -0,0 +1,80 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Writing to and reading from VirtIO consoles directly, rather than running a shell session on
+//! them.
+//!
+//! Only port 0 of each console can be used, which is the port QEMU's `virtconsole` device is
+//! attached to. Extra `virtserialport` ports need the multiport feature and its control queue for
+//! opening and closing them, which the console driver in `virtio-drivers` doesn't negotiate or
+//! expose.
+
+use crate::apps::command::{Args, CommandError, Context, ErrorContext};
+use alloc::vec::Vec;
+use core::time::Duration;
+use embedded_io::Write;
+use osdemo_core::{
+    device_id::DeviceKind,
+    drivers::generic_timer::{self, Instant},
+    executor::block_on,
+};
+
+/// How long `vcread` waits for more input by default before returning.
+const DEFAULT_READ_TIMEOUT_MS: u64 = 1000;
+
+/// Writes the remaining arguments, separated by spaces and followed by a newline, to a VirtIO
+/// console.
+pub fn vcwrite(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let index = args.next_device(DeviceKind::Console, context.devices.console.len())?;
+    let text = args.collect::<Vec<_>>().join(" ");
+    let device = &mut context.devices.console[index];
+    device
+        .send_bytes(text.as_bytes())
+        .and_then(|()| device.send_bytes(b"\n"))
+        .context("writing to VirtIO console")?;
+    Ok(())
+}
+
+/// Copies input from a VirtIO console to the shell's console, until none has arrived for the
+/// timeout.
+///
+/// Any session running on the same console doesn't see the input read here.
+pub fn vcread(context: &mut Context, mut args: Args) -> Result<(), CommandError> {
+    let index = args.next_device(DeviceKind::Console, context.devices.console.len())?;
+    let timeout_ms = args.optional("timeout")?.unwrap_or(DEFAULT_READ_TIMEOUT_MS);
+    args.finish()?;
+    let Context {
+        console, devices, ..
+    } = context;
+    let device = &mut devices.console[index];
+    let timeout = Duration::from_millis(timeout_ms);
+    let mut deadline = Instant::now() + timeout;
+    let mut total = 0;
+    loop {
+        device.ack_pending_irq();
+        match device.recv(true).context("reading from VirtIO console")? {
+            Some(byte) => {
+                console.write_all(&[byte]).unwrap();
+                total += 1;
+                deadline = Instant::now() + timeout;
+            }
+            None => {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                // Either the interrupt or the timeout is fine, as we check again either way.
+                let _ = block_on(generic_timer::timeout(
+                    deadline.duration_since(now),
+                    device.next_irq(),
+                ));
+            }
+        }
+    }
+    if total > 0 {
+        writeln!(console).unwrap();
+    }
+    writeln!(console, "Read {total} bytes from VirtIO console {index}").unwrap();
+    Ok(())
+}
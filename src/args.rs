@@ -0,0 +1,361 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Tokenising and parsing of shell command lines, and the errors which commands may return.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+use core::{
+    fmt::{self, Display, Formatter},
+    str::{FromStr, SplitWhitespace},
+};
+use osdemo_core::{
+    device_id::{DeviceId, DeviceKind},
+    device_owners::DeviceBusy,
+    fallible::OutOfMemory,
+    pci_config::parse_device_function,
+};
+use virtio_drivers::transport::pci::bus::DeviceFunction;
+
+/// Splits the given command line into the command name and its arguments.
+///
+/// Returns `None` if the line is empty or only whitespace.
+pub fn split_command(line: &str) -> Option<(&str, Args<'_>)> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+    let (name, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    Some((name, Args::new(args)))
+}
+
+/// An error running a command.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CommandError {
+    /// The arguments were wrong in some way not covered by a more specific error.
+    Usage,
+    /// A required argument was not provided.
+    MissingArgument(&'static str),
+    /// An argument couldn't be parsed.
+    InvalidArgument { name: &'static str, value: String },
+    /// More arguments were provided than the command accepts.
+    TooManyArguments,
+    /// The given device index doesn't exist.
+    NoSuchDevice { kind: &'static str, index: usize },
+    /// There wasn't enough memory to run the command.
+    OutOfMemory(OutOfMemory),
+    /// The command failed for some other reason.
+    Failed(String),
+}
+
+impl CommandError {
+    /// Returns the exit status to report for a command which failed with this error.
+    pub fn exit_status(&self) -> u8 {
+        if self.show_usage() { 2 } else { 1 }
+    }
+
+    /// Returns whether the command's usage should be shown after this error.
+    pub fn show_usage(&self) -> bool {
+        matches!(
+            self,
+            Self::Usage
+                | Self::MissingArgument(_)
+                | Self::InvalidArgument { .. }
+                | Self::TooManyArguments
+        )
+    }
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Usage => write!(f, "Invalid arguments"),
+            Self::MissingArgument(name) => write!(f, "Missing {name}"),
+            Self::InvalidArgument { name, value } => write!(f, "Invalid {name} {value:?}"),
+            Self::TooManyArguments => write!(f, "Too many arguments"),
+            Self::NoSuchDevice { kind, index } => write!(f, "No {kind} device {index}"),
+            Self::OutOfMemory(e) => write!(f, "{e}"),
+            Self::Failed(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl From<&str> for CommandError {
+    fn from(message: &str) -> Self {
+        Self::Failed(message.to_string())
+    }
+}
+
+impl From<OutOfMemory> for CommandError {
+    fn from(e: OutOfMemory) -> Self {
+        Self::OutOfMemory(e)
+    }
+}
+
+impl From<DeviceBusy> for CommandError {
+    fn from(e: DeviceBusy) -> Self {
+        Self::Failed(e.to_string())
+    }
+}
+
+/// Converts errors from devices and other subsystems into command errors, so that commands can
+/// propagate them with `?` rather than panicking.
+pub trait ErrorContext<T> {
+    /// Converts any error to `CommandError::Failed`, with a message saying what was being done when
+    /// it happened, such as `"sending frame"`.
+    fn context(self, action: impl Display) -> Result<T, CommandError>;
+}
+
+impl<T, E: Display> ErrorContext<T> for Result<T, E> {
+    fn context(self, action: impl Display) -> Result<T, CommandError> {
+        self.map_err(|e| CommandError::Failed(format!("Error {action}: {e}")))
+    }
+}
+
+/// The arguments passed to a command, with helpers to parse them.
+pub struct Args<'a> {
+    parts: SplitWhitespace<'a>,
+}
+
+impl<'a> Args<'a> {
+    /// Splits the given argument string on whitespace.
+    pub fn new(args: &'a str) -> Self {
+        Self {
+            parts: args.split_whitespace(),
+        }
+    }
+
+    /// Returns the next argument as a string, or an error if there are no more.
+    pub fn required_str(&mut self, name: &'static str) -> Result<&'a str, CommandError> {
+        self.parts.next().ok_or(CommandError::MissingArgument(name))
+    }
+
+    /// Parses the next argument, or returns an error if it is missing or invalid.
+    pub fn required<T: FromStr>(&mut self, name: &'static str) -> Result<T, CommandError> {
+        parse(name, self.required_str(name)?)
+    }
+
+    /// Parses the next argument if there is one.
+    pub fn optional<T: FromStr>(&mut self, name: &'static str) -> Result<Option<T>, CommandError> {
+        self.parts
+            .next()
+            .map(|value| parse(name, value))
+            .transpose()
+    }
+
+    /// Parses the next argument as the ID of a device of the given kind, such as `con1`, or just
+    /// its number, and returns the number if there are at least that many devices of the kind.
+    pub fn next_device(&mut self, kind: DeviceKind, count: usize) -> Result<usize, CommandError> {
+        let value = self.required_str(kind.name())?;
+        let index = match value.parse::<DeviceId>() {
+            Ok(id) if id.kind == kind => id.number,
+            Ok(_) => {
+                return Err(CommandError::InvalidArgument {
+                    name: kind.name(),
+                    value: value.to_string(),
+                });
+            }
+            Err(_) => parse(kind.name(), value)?,
+        };
+        let kind = kind.name();
+        if index < count {
+            Ok(index)
+        } else {
+            Err(CommandError::NoSuchDevice { kind, index })
+        }
+    }
+
+    /// Like `next_device`, but returns `None` if there are no more arguments.
+    pub fn optional_device(
+        &mut self,
+        kind: DeviceKind,
+        count: usize,
+    ) -> Result<Option<usize>, CommandError> {
+        if self.parts.clone().next().is_none() {
+            return Ok(None);
+        }
+        self.next_device(kind, count).map(Some)
+    }
+
+    /// Parses the next argument as a vsock context ID.
+    pub fn next_cid(&mut self) -> Result<u64, CommandError> {
+        self.required("CID")
+    }
+
+    /// Parses the next argument as a vsock port.
+    pub fn next_port(&mut self) -> Result<u32, CommandError> {
+        self.required("port")
+    }
+
+    /// Parses the next argument as a PCI bus, device and function in `bb:dd.f` form.
+    pub fn next_device_function(&mut self) -> Result<DeviceFunction, CommandError> {
+        let value = self.required_str("bdf")?;
+        parse_device_function(value).ok_or_else(|| CommandError::InvalidArgument {
+            name: "bdf",
+            value: value.to_string(),
+        })
+    }
+
+    /// Returns an error if there are any arguments left.
+    pub fn finish(mut self) -> Result<(), CommandError> {
+        if self.parts.next().is_some() {
+            Err(CommandError::TooManyArguments)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<'a> Iterator for Args<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.parts.next()
+    }
+}
+
+/// Parses the given argument value.
+fn parse<T: FromStr>(name: &'static str, value: &str) -> Result<T, CommandError> {
+    value.parse().map_err(|_| CommandError::InvalidArgument {
+        name,
+        value: value.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_empty() {
+        assert!(split_command("").is_none());
+        assert!(split_command("  \t ").is_none());
+    }
+
+    #[test]
+    fn split_name_and_args() {
+        let (name, args) = split_command("  vcat 3\t42  ").unwrap();
+        assert_eq!(name, "vcat");
+        assert_eq!(args.collect::<Vec<_>>(), ["3", "42"]);
+
+        let (name, mut args) = split_command("help").unwrap();
+        assert_eq!(name, "help");
+        assert_eq!(args.next(), None);
+    }
+
+    #[test]
+    fn required() {
+        let mut args = Args::new("12 -3 abc");
+        assert_eq!(args.required::<u32>("a"), Ok(12));
+        assert_eq!(args.required::<i64>("b"), Ok(-3));
+        assert_eq!(
+            args.required::<u8>("c"),
+            Err(CommandError::InvalidArgument {
+                name: "c",
+                value: "abc".to_string()
+            })
+        );
+        assert_eq!(
+            args.required::<u8>("d"),
+            Err(CommandError::MissingArgument("d"))
+        );
+    }
+
+    #[test]
+    fn optional() {
+        let mut args = Args::new("7 x");
+        assert_eq!(args.optional::<u8>("a"), Ok(Some(7)));
+        assert!(args.optional::<u8>("b").is_err());
+        assert_eq!(args.optional::<u8>("c"), Ok(None));
+    }
+
+    #[test]
+    fn device() {
+        assert_eq!(Args::new("1").next_device(DeviceKind::Console, 2), Ok(1));
+        assert_eq!(Args::new("con1").next_device(DeviceKind::Console, 2), Ok(1));
+        assert_eq!(
+            Args::new("2").next_device(DeviceKind::Console, 2),
+            Err(CommandError::NoSuchDevice {
+                kind: "console",
+                index: 2
+            })
+        );
+        assert_eq!(
+            Args::new("blk0").next_device(DeviceKind::Console, 2),
+            Err(CommandError::InvalidArgument {
+                name: "console",
+                value: "blk0".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn optional_device() {
+        let mut args = Args::new("blk1");
+        assert_eq!(args.optional_device(DeviceKind::Block, 2), Ok(Some(1)));
+        assert_eq!(args.optional_device(DeviceKind::Block, 2), Ok(None));
+        assert!(
+            Args::new("x")
+                .optional_device(DeviceKind::Block, 2)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn cid_and_port() {
+        let mut args = Args::new("3 4294967296");
+        assert_eq!(args.next_cid(), Ok(3));
+        assert!(matches!(
+            args.next_port(),
+            Err(CommandError::InvalidArgument { name: "port", .. })
+        ));
+    }
+
+    #[test]
+    fn device_function() {
+        let mut args = Args::new("00:03.0 00:03");
+        assert_eq!(
+            args.next_device_function(),
+            Ok(DeviceFunction {
+                bus: 0,
+                device: 3,
+                function: 0
+            })
+        );
+        assert!(matches!(
+            args.next_device_function(),
+            Err(CommandError::InvalidArgument { name: "bdf", .. })
+        ));
+    }
+
+    #[test]
+    fn finish() {
+        assert_eq!(Args::new("").finish(), Ok(()));
+        let mut args = Args::new("a b");
+        args.next();
+        assert_eq!(args.finish(), Err(CommandError::TooManyArguments));
+    }
+
+    #[test]
+    fn show_usage() {
+        assert!(CommandError::Usage.show_usage());
+        assert_eq!(CommandError::TooManyArguments.exit_status(), 2);
+        assert!(!CommandError::from("Failed").show_usage());
+        assert_eq!(CommandError::from("Failed").exit_status(), 1);
+    }
+
+    #[test]
+    fn error_context() {
+        let result: Result<(), &str> = Err("device on fire");
+        assert_eq!(
+            result.context(format_args!("reading blk{}", 2)),
+            Err(CommandError::Failed(
+                "Error reading blk2: device on fire".to_string()
+            ))
+        );
+        assert_eq!(Ok::<_, &str>(42).context("reading"), Ok(42));
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Frame-pointer based stack unwinding and symbolisation.
+//!
+//! This relies on the image being built with `-C force-frame-pointers=yes`, and on the symbol table
+//! being embedded by the build as described in the `Makefile`.
+
+use aarch64_rt::RegisterState;
+use core::{
+    arch::asm,
+    fmt::{self, Display, Formatter},
+    iter, str,
+};
+use embedded_io::{Write, WriteFmtError};
+
+/// The maximum number of frames to follow, in case the frame pointer chain is corrupted.
+const MAX_FRAMES: usize = 32;
+
+/// The symbol table, as output by `nm -n --defined-only`.
+#[unsafe(link_section = ".ksyms")]
+#[used]
+static SYMBOLS: [u8; include_bytes!(concat!(env!("OUT_DIR"), "/symbols.txt")).len()] =
+    *include_bytes!(concat!(env!("OUT_DIR"), "/symbols.txt"));
+
+/// Returns the current value of the frame pointer.
+#[inline(always)]
+pub fn current_frame_pointer() -> usize {
+    let fp;
+    // SAFETY: Reading the frame pointer has no side effects.
+    unsafe {
+        asm!("mov {}, x29", out(reg) fp, options(nomem, nostack, preserves_flags));
+    }
+    fp
+}
+
+/// Prints a backtrace by following the chain of frame records starting at the given frame pointer.
+pub fn print_backtrace<W: Write>(out: &mut W, fp: usize) -> Result<(), WriteFmtError<W::Error>> {
+    writeln!(out, "Backtrace:")?;
+    for (frame, (_, lr)) in frame_records(fp).enumerate() {
+        // The return address is the instruction after the call, so look up the call instruction.
+        print_address(out, frame, lr - 4)?;
+    }
+    Ok(())
+}
+
+/// Returns the address of the first call in the chain of frame records starting at the given frame
+/// pointer which isn't from a function for which `skip` returns true, given the function's name.
+///
+/// Calls from functions whose symbol isn't known are never skipped.
+pub fn first_caller(fp: usize, skip: impl Fn(&str) -> bool) -> Option<usize> {
+    frame_records(fp)
+        // The return address is the instruction after the call, so look up the call instruction.
+        .map(|(_, lr)| lr - 4)
+        .find(|&address| !symbolise(address).is_some_and(|(name, _)| skip(name)))
+}
+
+/// A code address, which is formatted along with the symbol it is in, if known.
+pub struct CodeAddress(pub usize);
+
+impl Display for CodeAddress {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if let Some((name, offset)) = symbolise(self.0) {
+            write!(f, "{:#x} {name}+{offset:#x}", self.0)
+        } else {
+            write!(f, "{:#x}", self.0)
+        }
+    }
+}
+
+/// Returns the address just above the outermost frame record in the chain starting at the given
+/// frame pointer, if there are any. The stack between the current stack pointer and this is in use.
+pub fn stack_top(fp: usize) -> Option<usize> {
+    frame_records(fp).last().map(|(record, _)| record + 16)
+}
+
+/// Returns the address and return address of each frame record in the chain starting at the given
+/// frame pointer, innermost first.
+fn frame_records(fp: usize) -> impl Iterator<Item = (usize, usize)> {
+    let mut next = Some(fp);
+    iter::from_fn(move || {
+        let fp = next.take()?;
+        // Frame records must be 16-byte aligned, and the stack grows downwards so each caller's
+        // frame must be above its callee's.
+        if fp == 0 || !fp.is_multiple_of(16) {
+            return None;
+        }
+        // SAFETY: The frame pointer is non-null and aligned, and we trust that the code was compiled
+        // with frame pointers so it points to a valid frame record of the previous frame pointer
+        // followed by the return address.
+        let (next_fp, lr) = unsafe {
+            let record = fp as *const usize;
+            (record.read(), record.add(1).read())
+        };
+        if lr == 0 {
+            return None;
+        }
+        if next_fp > fp {
+            next = Some(next_fp);
+        }
+        Some((fp, lr))
+    })
+    // In case the frame pointer chain is corrupted.
+    .take(MAX_FRAMES)
+}
+
+/// Prints the given saved register state, followed by a backtrace of the code it was saved from.
+pub fn print_register_state<W: Write>(
+    out: &mut W,
+    register_state: &RegisterState,
+) -> Result<(), WriteFmtError<W::Error>> {
+    for (i, pair) in register_state.registers.chunks(2).enumerate() {
+        for (j, value) in pair.iter().enumerate() {
+            write!(out, "  x{:<2} {value:#018x}", i * 2 + j)?;
+        }
+        writeln!(out)?;
+    }
+    writeln!(
+        out,
+        "  fp  {:#018x}  lr  {:#018x}",
+        // The link register is saved in the field confusingly named `sp`.
+        register_state.fp,
+        register_state.sp
+    )?;
+    writeln!(
+        out,
+        "  elr {:#018x}  spsr {:#018x}",
+        register_state.elr, register_state.spsr
+    )?;
+    print_address(out, 0, register_state.elr)?;
+    print_backtrace(out, register_state.fp as usize)?;
+    Ok(())
+}
+
+/// Prints the given code address along with the symbol it is in, if known.
+fn print_address<W: Write>(
+    out: &mut W,
+    frame: usize,
+    address: usize,
+) -> Result<(), WriteFmtError<W::Error>> {
+    if let Some((name, offset)) = symbolise(address) {
+        writeln!(out, "  #{frame:<2} {address:#018x} {name}+{offset:#x}")
+    } else {
+        writeln!(out, "  #{frame:<2} {address:#018x}")
+    }
+}
+
+/// Returns the name of the function containing the given address and the offset within it, if
+/// known.
+fn symbolise(address: usize) -> Option<(&'static str, usize)> {
+    let symbols = str::from_utf8(&SYMBOLS).ok()?;
+    let mut best = None;
+    for line in symbols.lines() {
+        let mut parts = line.splitn(3, ' ');
+        let (Some(symbol_address), Some(symbol_type), Some(name)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        // Only consider code symbols.
+        if !matches!(symbol_type, "t" | "T") {
+            continue;
+        }
+        let Ok(symbol_address) = usize::from_str_radix(symbol_address, 16) else {
+            continue;
+        };
+        if symbol_address > address {
+            // The symbol table is sorted by address, so there is no point looking further.
+            break;
+        }
+        best = Some((name, address - symbol_address));
+    }
+    best
+}
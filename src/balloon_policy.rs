@@ -0,0 +1,318 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Policy for deciding when to inflate or deflate a memory balloon, based on memory pressure.
+
+use core::fmt::{self, Display, Formatter};
+
+/// How much memory is in use.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MemoryUsage {
+    pub total_bytes: usize,
+    pub used_bytes: usize,
+}
+
+impl MemoryUsage {
+    /// Returns the percentage of memory which is free, rounded down.
+    pub fn free_percent(&self) -> usize {
+        if self.total_bytes == 0 {
+            return 0;
+        }
+        self.total_bytes.saturating_sub(self.used_bytes) * 100 / self.total_bytes
+    }
+}
+
+impl Display for MemoryUsage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} / {} bytes used, {}% free",
+            self.used_bytes,
+            self.total_bytes,
+            self.free_percent()
+        )
+    }
+}
+
+/// A change to make to the size of the balloon.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BalloonAction {
+    /// Leave the balloon as it is.
+    None,
+    /// Give the given number of pages back to the host.
+    Inflate(usize),
+    /// Reclaim the given number of pages from the host.
+    Deflate(usize),
+}
+
+impl Display for BalloonAction {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "no change"),
+            Self::Inflate(pages) => write!(f, "inflate by {pages} pages"),
+            Self::Deflate(pages) => write!(f, "deflate by {pages} pages"),
+        }
+    }
+}
+
+/// A balloon which a policy can resize.
+pub trait Balloon {
+    /// The error returned if resizing fails.
+    type Error;
+
+    /// Returns the number of pages currently in the balloon.
+    fn pages(&self) -> usize;
+
+    /// Inflates or deflates the balloon towards the given number of pages.
+    ///
+    /// This may stop short of the target, e.g. to leave enough memory free.
+    fn resize(&mut self, target_pages: usize) -> Result<(), Self::Error>;
+}
+
+/// Bounds and thresholds for resizing the balloon.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BalloonPolicy {
+    /// The minimum size of the balloon, in pages.
+    pub min_pages: usize,
+    /// The maximum size of the balloon, in pages.
+    pub max_pages: usize,
+    /// Deflate the balloon when less than this percentage of memory is free.
+    pub deflate_below_percent: usize,
+    /// Inflate the balloon when more than this percentage of memory is free.
+    pub inflate_above_percent: usize,
+    /// The maximum number of pages to inflate or deflate by at once.
+    pub step_pages: usize,
+}
+
+impl BalloonPolicy {
+    /// A policy which never inflates the balloon.
+    pub const DEFAULT: Self = Self {
+        min_pages: 0,
+        max_pages: 0,
+        deflate_below_percent: 25,
+        inflate_above_percent: 50,
+        step_pages: 16,
+    };
+
+    /// Returns whether the policy is self-consistent.
+    pub fn is_valid(&self) -> bool {
+        self.min_pages <= self.max_pages
+            && self.deflate_below_percent < self.inflate_above_percent
+            && self.inflate_above_percent <= 100
+            && self.step_pages > 0
+    }
+
+    /// Decides how to resize a balloon currently of the given size, given the current memory
+    /// usage.
+    ///
+    /// A balloon outside the policy's bounds is always brought back within them, one step at a
+    /// time.
+    pub fn decide(&self, usage: &MemoryUsage, balloon_pages: usize) -> BalloonAction {
+        let free_percent = usage.free_percent();
+        let (inflate, deflate) = if balloon_pages < self.min_pages {
+            (self.min_pages - balloon_pages, 0)
+        } else if balloon_pages > self.max_pages {
+            (0, balloon_pages - self.max_pages)
+        } else if free_percent < self.deflate_below_percent {
+            (0, balloon_pages - self.min_pages)
+        } else if free_percent > self.inflate_above_percent {
+            (self.max_pages - balloon_pages, 0)
+        } else {
+            (0, 0)
+        };
+        if inflate > 0 {
+            BalloonAction::Inflate(inflate.min(self.step_pages))
+        } else if deflate > 0 {
+            BalloonAction::Deflate(deflate.min(self.step_pages))
+        } else {
+            BalloonAction::None
+        }
+    }
+
+    /// Resizes the balloon by one step according to the policy, given the current memory usage,
+    /// and returns the action taken.
+    ///
+    /// While the host has asked for a non-zero size the host's target wins, so nothing is done.
+    pub fn step<B: Balloon>(
+        &self,
+        usage: &MemoryUsage,
+        host_target_pages: usize,
+        balloon: &mut B,
+    ) -> Result<BalloonAction, B::Error> {
+        if host_target_pages != 0 {
+            return Ok(BalloonAction::None);
+        }
+        let pages = balloon.pages();
+        let action = self.decide(usage, pages);
+        let target_pages = match action {
+            BalloonAction::None => return Ok(action),
+            BalloonAction::Inflate(step) => pages + step,
+            BalloonAction::Deflate(step) => pages - step,
+        };
+        balloon.resize(target_pages.clamp(self.min_pages, self.max_pages))?;
+        Ok(action)
+    }
+}
+
+impl Default for BalloonPolicy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Display for BalloonPolicy {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}-{} pages, deflate below {}% free, inflate above {}% free, step {} pages",
+            self.min_pages,
+            self.max_pages,
+            self.deflate_below_percent,
+            self.inflate_above_percent,
+            self.step_pages
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: BalloonPolicy = BalloonPolicy {
+        min_pages: 4,
+        max_pages: 64,
+        deflate_below_percent: 25,
+        inflate_above_percent: 50,
+        step_pages: 16,
+    };
+
+    struct FakeBalloon {
+        pages: usize,
+    }
+
+    impl Balloon for FakeBalloon {
+        type Error = ();
+
+        fn pages(&self) -> usize {
+            self.pages
+        }
+
+        fn resize(&mut self, target_pages: usize) -> Result<(), ()> {
+            self.pages = target_pages;
+            Ok(())
+        }
+    }
+
+    fn usage(free_percent: usize) -> MemoryUsage {
+        MemoryUsage {
+            total_bytes: 1000,
+            used_bytes: 1000 - free_percent * 10,
+        }
+    }
+
+    #[test]
+    fn free_percent() {
+        assert_eq!(usage(30).free_percent(), 30);
+        let empty = MemoryUsage {
+            total_bytes: 0,
+            used_bytes: 0,
+        };
+        assert_eq!(empty.free_percent(), 0);
+        let overcommitted = MemoryUsage {
+            total_bytes: 100,
+            used_bytes: 200,
+        };
+        assert_eq!(overcommitted.free_percent(), 0);
+    }
+
+    #[test]
+    fn validity() {
+        assert!(POLICY.is_valid());
+        assert!(BalloonPolicy::DEFAULT.is_valid());
+        assert!(
+            !BalloonPolicy {
+                min_pages: 65,
+                ..POLICY
+            }
+            .is_valid()
+        );
+        assert!(
+            !BalloonPolicy {
+                deflate_below_percent: 50,
+                ..POLICY
+            }
+            .is_valid()
+        );
+        assert!(
+            !BalloonPolicy {
+                inflate_above_percent: 101,
+                ..POLICY
+            }
+            .is_valid()
+        );
+        assert!(
+            !BalloonPolicy {
+                step_pages: 0,
+                ..POLICY
+            }
+            .is_valid()
+        );
+    }
+
+    #[test]
+    fn inflates_when_memory_is_free() {
+        assert_eq!(POLICY.decide(&usage(80), 10), BalloonAction::Inflate(16));
+        assert_eq!(POLICY.decide(&usage(80), 60), BalloonAction::Inflate(4));
+        assert_eq!(POLICY.decide(&usage(80), 64), BalloonAction::None);
+    }
+
+    #[test]
+    fn deflates_under_pressure() {
+        assert_eq!(POLICY.decide(&usage(10), 40), BalloonAction::Deflate(16));
+        assert_eq!(POLICY.decide(&usage(10), 6), BalloonAction::Deflate(2));
+        assert_eq!(POLICY.decide(&usage(10), 4), BalloonAction::None);
+    }
+
+    #[test]
+    fn steady_between_thresholds() {
+        assert_eq!(POLICY.decide(&usage(25), 10), BalloonAction::None);
+        assert_eq!(POLICY.decide(&usage(50), 10), BalloonAction::None);
+    }
+
+    #[test]
+    fn returns_to_bounds() {
+        assert_eq!(POLICY.decide(&usage(10), 0), BalloonAction::Inflate(4));
+        assert_eq!(POLICY.decide(&usage(80), 100), BalloonAction::Deflate(16));
+    }
+
+    #[test]
+    fn step_resizes_balloon() {
+        let mut balloon = FakeBalloon { pages: 10 };
+        assert_eq!(
+            POLICY.step(&usage(80), 0, &mut balloon),
+            Ok(BalloonAction::Inflate(16))
+        );
+        assert_eq!(balloon.pages(), 26);
+        assert_eq!(
+            POLICY.step(&usage(10), 0, &mut balloon),
+            Ok(BalloonAction::Deflate(16))
+        );
+        assert_eq!(balloon.pages(), 10);
+        assert_eq!(
+            POLICY.step(&usage(30), 0, &mut balloon),
+            Ok(BalloonAction::None)
+        );
+        assert_eq!(balloon.pages(), 10);
+    }
+
+    #[test]
+    fn host_target_wins() {
+        let mut balloon = FakeBalloon { pages: 10 };
+        assert_eq!(
+            POLICY.step(&usage(80), 100, &mut balloon),
+            Ok(BalloonAction::None)
+        );
+        assert_eq!(balloon.pages(), 10);
+    }
+}
@@ -2,115 +2,81 @@
 // This project is dual-licensed under Apache 2.0 and MIT terms.
 // See LICENSE-APACHE and LICENSE-MIT for details.
 
-use crate::{drivers::InterruptDriven, platform::ConsoleImpl, power_off};
+use crate::{
+    apps::crashdump,
+    backtrace::{current_frame_pointer, print_backtrace},
+    platform::{ConsoleImpl, Platform, PlatformImpl},
+};
 use arm_gic::IntId;
-use core::panic::PanicInfo;
-use embedded_io::{ErrorType, Read, ReadReady, Write};
-use percore::{ExceptionLock, exception_free};
-use spin::{Once, mutex::SpinMutex};
+use core::{
+    fmt::{self, Arguments},
+    panic::PanicInfo,
+};
+use embedded_io::Write;
+pub use osdemo_core::console::{Console, SharedConsole};
+use osdemo_core::{psci::power_off, semihosting::HostConsole};
+use percore::exception_free;
+use spin::Once;
 
 static CONSOLE: Once<SharedConsole<ConsoleImpl>> = Once::new();
 
-/// A console guarded by a spin mutex so that it may be shared between threads.
-///
-/// Any thread may write to it, but only a single thread may read from it.
-pub struct SharedConsole<T: Send> {
-    pub console: ExceptionLock<SpinMutex<T>>,
-}
-
-impl<T: ErrorType + Send> ErrorType for &SharedConsole<T> {
-    type Error = T::Error;
-}
-
-impl<T: ErrorType + Send + Write> Write for &SharedConsole<T> {
-    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
-        exception_free(|token| self.console.borrow(token).lock().write(buf))
-    }
-
-    fn flush(&mut self) -> Result<(), Self::Error> {
-        exception_free(|token| self.console.borrow(token).lock().flush())
-    }
-}
-
-/// The owner of a shared console, who has unique read access.
-///
-/// The reading side can't be shared, as the caller of `ReadReady::read_ready` needs to be
-/// guaranteed that bytes will be available to read when the next call `Read::read`.
-pub struct Console<T: Send + 'static> {
-    shared: &'static SharedConsole<T>,
-}
-
-impl<T: Send + 'static> Console<T> {
-    /// Returns a shared writer for the console. This may be copied freely.
-    pub fn shared(&self) -> &'static SharedConsole<T> {
-        self.shared
-    }
+/// Initialises the shared console.
+pub fn init(console: ConsoleImpl) -> Console<ConsoleImpl> {
+    Console::new(CONSOLE.call_once(|| SharedConsole::new(console)))
 }
 
-impl<T: ErrorType + Send + Write> Write for Console<T> {
-    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
-        self.shared.write(buf)
-    }
-
-    fn flush(&mut self) -> Result<(), Self::Error> {
-        self.shared.flush()
-    }
+/// Returns the shared console, if it has been initialised.
+pub fn shared() -> Option<&'static SharedConsole<ConsoleImpl>> {
+    CONSOLE.get()
 }
 
-impl<T: ErrorType + Send + 'static> ErrorType for Console<T> {
-    type Error = T::Error;
+/// Returns whether `early_write` has anywhere to write to.
+pub fn has_early_output() -> bool {
+    cfg!(feature = "semihosting-console") || PlatformImpl::early_putchar().is_some()
 }
 
-impl<T: ErrorType + InterruptDriven + Read + ReadReady + Send + 'static> Read for Console<T> {
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-        // Wait until the console has some data to read, without holding the lock and keeping
-        // exceptions masked the whole time.
-        loop {
-            if let Some(result) = exception_free(|token| {
-                let mut console = self.shared.console.borrow(token).lock();
-                match console.read_ready()? {
-                    true => Ok::<_, Self::Error>(Some(console.read(buf)?)),
-                    false => Ok(None),
-                }
-            })? {
-                break Ok(result);
-            }
-            T::wait_for_irq();
-        }
+/// Writes the given message for output before the shared console is initialised: to the host's
+/// console with semihosting if the `semihosting-console` feature is enabled, or otherwise directly
+/// to the console UART if the platform supports that. Otherwise the message is lost.
+pub fn early_write(message: Arguments) {
+    // Ignore errors, as there is nowhere else to report them.
+    if cfg!(feature = "semihosting-console")
+        && let Ok(mut host_console) = HostConsole::open()
+    {
+        let _ = host_console.write_fmt(message);
+    } else if let Some(putchar) = PlatformImpl::early_putchar() {
+        let _ = fmt::Write::write_fmt(&mut EarlyUart(putchar), message);
     }
 }
 
-impl<T: ErrorType + ReadReady + Send + 'static> ReadReady for Console<T> {
-    fn read_ready(&mut self) -> Result<bool, Self::Error> {
-        exception_free(|token| self.shared.console.borrow(token).lock().read_ready())
-    }
-}
+/// Writes to the console UART with the platform's early putchar function.
+struct EarlyUart(fn(u8));
 
-impl<T: Send + InterruptDriven> Console<T> {
-    /// Lets the underlying UART driver handle the given interrupt.
-    pub fn handle_irq(intid: IntId) {
-        let console = CONSOLE.get().unwrap();
-        exception_free(|token| {
-            console.console.borrow(token).lock().handle_irq(intid);
-        });
+impl fmt::Write for EarlyUart {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        s.bytes().for_each(self.0);
+        Ok(())
     }
 }
 
-/// Initialises the shared console.
-pub fn init(console: ConsoleImpl) -> Console<ConsoleImpl> {
-    let shared = CONSOLE.call_once(|| SharedConsole {
-        console: ExceptionLock::new(SpinMutex::new(console)),
-    });
-    Console { shared }
+/// Lets the console UART driver handle the given interrupt.
+pub fn handle_irq(intid: IntId) {
+    CONSOLE.get().unwrap().handle_irq(intid);
 }
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    let registers = crashdump::capture_registers();
     if let Some(console) = CONSOLE.get() {
         exception_free(|token| {
+            let console = &mut *console.console.borrow(token).lock();
             // Ignore any errors writing to the console, to avoid panicking recursively.
-            let _ = writeln!(console.console.borrow(token).lock(), "{info}");
+            let _ = writeln!(console, "{info}");
+            let _ = print_backtrace(console, current_frame_pointer());
         });
+    } else {
+        early_write(format_args!("{info}\n"));
     }
+    crashdump::save_on_panic(info, &registers);
     power_off();
 }
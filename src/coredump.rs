@@ -0,0 +1,194 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! The framed format which `coredump` streams memory in, so that it can be analysed on the host.
+//!
+//! All integers are little-endian. A dump consists of:
+//!
+//! 1. A 16 byte file header: the magic bytes `OSDCORE\0`, the format version as a `u32` (currently
+//!    1), and the number of regions which follow as a `u32`.
+//! 2. For each region, a 24 byte region header: the magic bytes `RGN\0`, a reserved `u32` which is
+//!    currently 0, the physical start address as a `u64` and the length in bytes as a `u64`,
+//!    followed by exactly that many bytes of memory contents.
+//! 3. An 8 byte trailer: the magic bytes `END\0`, and the Adler-32 checksum of all the region
+//!    contents in order, excluding the headers, as a `u32`.
+//!
+//! Memory is identity-mapped, so the addresses are also the virtual addresses which pointers in the
+//! dump refer to.
+
+use crate::endpoint::parse_number;
+
+/// The magic bytes at the start of a dump.
+pub const FILE_MAGIC: [u8; 8] = *b"OSDCORE\0";
+
+/// The version of the format described above.
+pub const VERSION: u32 = 1;
+
+/// The size of the file header.
+pub const FILE_HEADER_SIZE: usize = 16;
+
+/// The magic bytes at the start of each region header.
+pub const REGION_MAGIC: [u8; 4] = *b"RGN\0";
+
+/// The size of a region header.
+pub const REGION_HEADER_SIZE: usize = 24;
+
+/// The magic bytes at the start of the trailer.
+pub const TRAILER_MAGIC: [u8; 4] = *b"END\0";
+
+/// The size of the trailer.
+pub const TRAILER_SIZE: usize = 8;
+
+/// The modulus used by Adler-32.
+const ADLER_MODULUS: u32 = 65521;
+
+/// Returns the file header for a dump with the given number of regions.
+pub fn file_header(region_count: u32) -> [u8; FILE_HEADER_SIZE] {
+    let mut header = [0; FILE_HEADER_SIZE];
+    header[0..8].copy_from_slice(&FILE_MAGIC);
+    header[8..12].copy_from_slice(&VERSION.to_le_bytes());
+    header[12..16].copy_from_slice(&region_count.to_le_bytes());
+    header
+}
+
+/// A range of physical memory included in a dump.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Region {
+    pub address: u64,
+    pub length: u64,
+}
+
+impl Region {
+    /// Parses a region of the form `<address>+<length>`, where each may be decimal or hexadecimal
+    /// with a `0x` prefix.
+    ///
+    /// Returns `None` if it is invalid, empty or wraps around the end of the address space.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (address, length) = s.split_once('+')?;
+        let region = Self {
+            address: parse_number(address)?,
+            length: parse_number(length)?,
+        };
+        (region.length > 0 && region.end().is_some()).then_some(region)
+    }
+
+    /// Returns the address just after the end of the region, or `None` if it would overflow.
+    pub fn end(&self) -> Option<u64> {
+        self.address.checked_add(self.length)
+    }
+
+    /// Returns whether this region lies entirely within `other`.
+    pub fn is_within(&self, other: &Self) -> bool {
+        self.address >= other.address && self.end() <= other.end()
+    }
+
+    /// Returns the header which precedes the region's contents in a dump.
+    pub fn header(&self) -> [u8; REGION_HEADER_SIZE] {
+        let mut header = [0; REGION_HEADER_SIZE];
+        header[0..4].copy_from_slice(&REGION_MAGIC);
+        header[8..16].copy_from_slice(&self.address.to_le_bytes());
+        header[16..24].copy_from_slice(&self.length.to_le_bytes());
+        header
+    }
+}
+
+/// Returns the trailer for a dump whose region contents have the given checksum.
+pub fn trailer(checksum: u32) -> [u8; TRAILER_SIZE] {
+    let mut trailer = [0; TRAILER_SIZE];
+    trailer[0..4].copy_from_slice(&TRAILER_MAGIC);
+    trailer[4..8].copy_from_slice(&checksum.to_le_bytes());
+    trailer
+}
+
+/// A running Adler-32 checksum.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Default for Adler32 {
+    fn default() -> Self {
+        Self { a: 1, b: 0 }
+    }
+}
+
+impl Adler32 {
+    /// Adds the given bytes to the checksum.
+    pub fn update(&mut self, bytes: &[u8]) {
+        // 5552 is the most bytes which can be summed before `b` could overflow a `u32`.
+        for chunk in bytes.chunks(5552) {
+            for &byte in chunk {
+                self.a += u32::from(byte);
+                self.b += self.a;
+            }
+            self.a %= ADLER_MODULUS;
+            self.b %= ADLER_MODULUS;
+        }
+    }
+
+    /// Returns the checksum of all the bytes added so far.
+    pub fn finish(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_region() {
+        assert_eq!(
+            Region::parse("0x40000000+4096"),
+            Some(Region {
+                address: 0x4000_0000,
+                length: 4096
+            })
+        );
+        assert_eq!(Region::parse("0x40000000"), None);
+        assert_eq!(Region::parse("0x40000000+0"), None);
+        assert_eq!(Region::parse("0xffffffffffffffff+2"), None);
+    }
+
+    #[test]
+    fn region_within() {
+        let ram = Region {
+            address: 0x4000_0000,
+            length: 0x1000_0000,
+        };
+        assert!(Region::parse("0x40000000+0x1000").unwrap().is_within(&ram));
+        assert!(Region::parse("0x4ffff000+0x1000").unwrap().is_within(&ram));
+        assert!(!Region::parse("0x4ffff000+0x1001").unwrap().is_within(&ram));
+        assert!(!Region::parse("0x3ffff000+0x1000").unwrap().is_within(&ram));
+    }
+
+    #[test]
+    fn headers() {
+        assert_eq!(file_header(2), *b"OSDCORE\0\x01\0\0\0\x02\0\0\0");
+        assert_eq!(
+            Region {
+                address: 0x4008_0000,
+                length: 0x10
+            }
+            .header(),
+            *b"RGN\0\0\0\0\0\0\0\x08\x40\0\0\0\0\x10\0\0\0\0\0\0\0"
+        );
+        assert_eq!(trailer(0x11e6_0398), *b"END\0\x98\x03\xe6\x11");
+    }
+
+    #[test]
+    fn adler32() {
+        let mut checksum = Adler32::default();
+        assert_eq!(checksum.finish(), 1);
+        checksum.update(b"Wiki");
+        checksum.update(b"pedia");
+        assert_eq!(checksum.finish(), 0x11e6_0398);
+
+        // Enough bytes to need reducing several times.
+        let mut checksum = Adler32::default();
+        checksum.update(&[0xff; 100_000]);
+        assert_eq!(checksum.finish(), 0x149a_302c);
+    }
+}
@@ -0,0 +1,370 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! The format of the crash report written on panic, so that it can be read back after a reboot or
+//! analysed on the host.
+//!
+//! All integers are little-endian. A report consists of:
+//!
+//! 1. A 24 byte header: the magic bytes `OSDCRASH`, the format version as a `u32` (currently 1),
+//!    the length of the sections which follow as a `u32`, their Adler-32 checksum as a `u32`, and a
+//!    reserved `u32` which is currently 0.
+//! 2. The sections, each a `u32` tag, the length of the contents as a `u32`, and then the contents:
+//!    - `SECTION_MESSAGE`: the panic message and location, as UTF-8.
+//!    - `SECTION_REGISTERS`: the registers named in `REGISTER_NAMES`, in that order, as `u64`s.
+//!    - `SECTION_STACK`: the address of the stack pointer as a `u64`, followed by the bytes of the
+//!      stack from there upwards.
+//!    - `SECTION_LOG`: the most recent lines of the log, oldest first.
+//!
+//! Reports are written with an `Encoder` into a fixed buffer, as the heap may not be usable during
+//! a panic. Sections which don't fit are truncated.
+
+use crate::coredump::Adler32;
+use core::{
+    fmt::{self, Arguments, Display, Formatter, Write},
+    str,
+};
+
+/// The name of the kernel command line argument which gives where to write crash reports, such as
+/// `crashdump=blk0:1048576+32768`.
+pub const BOOTARG: &str = "crashdump";
+
+/// The magic bytes at the start of a report.
+pub const MAGIC: [u8; 8] = *b"OSDCRASH";
+
+/// The version of the format described above.
+pub const VERSION: u32 = 1;
+
+/// The size of the header.
+pub const HEADER_SIZE: usize = 24;
+
+/// The size of the tag and length before the contents of each section.
+const SECTION_HEADER_SIZE: usize = 8;
+
+/// The panic message and location.
+pub const SECTION_MESSAGE: u32 = 1;
+/// The registers named in `REGISTER_NAMES`.
+pub const SECTION_REGISTERS: u32 = 2;
+/// The stack pointer and the stack above it.
+pub const SECTION_STACK: u32 = 3;
+/// Recent log lines.
+pub const SECTION_LOG: u32 = 4;
+
+/// The names of the registers in a `SECTION_REGISTERS`, in order.
+pub const REGISTER_NAMES: [&str; REGISTER_COUNT] = [
+    "x19", "x20", "x21", "x22", "x23", "x24", "x25", "x26", "x27", "x28", "fp", "lr", "sp", "esr",
+    "far",
+];
+
+/// The number of registers in a `SECTION_REGISTERS`.
+pub const REGISTER_COUNT: usize = 15;
+
+/// Writes a crash report into a fixed buffer.
+pub struct Encoder<'a> {
+    buffer: &'a mut [u8],
+    /// The number of bytes of `buffer` used so far, including the header.
+    length: usize,
+}
+
+impl<'a> Encoder<'a> {
+    /// Creates an encoder which writes to the given buffer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer is too small for even the header.
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        assert!(buffer.len() >= HEADER_SIZE);
+        Self {
+            buffer,
+            length: HEADER_SIZE,
+        }
+    }
+
+    /// Appends a section with the given tag, whose contents are written by `write` into the given
+    /// space, returning how many bytes it wrote.
+    ///
+    /// The section is left out if there isn't room for its header.
+    pub fn section(&mut self, tag: u32, write: impl FnOnce(&mut [u8]) -> usize) {
+        let Some(space) = self
+            .buffer
+            .len()
+            .checked_sub(self.length + SECTION_HEADER_SIZE)
+        else {
+            return;
+        };
+        let start = self.length + SECTION_HEADER_SIZE;
+        let length = write(&mut self.buffer[start..]).min(space);
+        self.buffer[self.length..self.length + 4].copy_from_slice(&tag.to_le_bytes());
+        self.buffer[self.length + 4..start].copy_from_slice(&(length as u32).to_le_bytes());
+        self.length = start + length;
+    }
+
+    /// Appends a section with the given tag and contents, truncated to the space available.
+    pub fn bytes_section(&mut self, tag: u32, data: &[u8]) {
+        self.section(tag, |space| {
+            let length = data.len().min(space.len());
+            space[..length].copy_from_slice(&data[..length]);
+            length
+        });
+    }
+
+    /// Appends a section with the given tag, containing the given formatted text truncated to the
+    /// space available.
+    pub fn text_section(&mut self, tag: u32, args: Arguments) {
+        self.section(tag, |space| {
+            let mut writer = SliceWriter {
+                buffer: space,
+                length: 0,
+            };
+            // `SliceWriter` truncates rather than failing.
+            let _ = writer.write_fmt(args);
+            writer.length
+        });
+    }
+
+    /// Fills in the header, and returns the report.
+    pub fn finish(self) -> &'a [u8] {
+        let sections = &self.buffer[HEADER_SIZE..self.length];
+        let mut checksum = Adler32::default();
+        checksum.update(sections);
+        let checksum = checksum.finish();
+        let sections_length = (self.length - HEADER_SIZE) as u32;
+        let header = &mut self.buffer[..HEADER_SIZE];
+        header[0..8].copy_from_slice(&MAGIC);
+        header[8..12].copy_from_slice(&VERSION.to_le_bytes());
+        header[12..16].copy_from_slice(&sections_length.to_le_bytes());
+        header[16..20].copy_from_slice(&checksum.to_le_bytes());
+        header[20..24].copy_from_slice(&0u32.to_le_bytes());
+        &self.buffer[..self.length]
+    }
+}
+
+/// Formats text into a slice, silently truncating anything past the end.
+struct SliceWriter<'a> {
+    buffer: &'a mut [u8],
+    length: usize,
+}
+
+impl Write for SliceWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let length = s.len().min(self.buffer.len() - self.length);
+        self.buffer[self.length..self.length + length].copy_from_slice(&s.as_bytes()[..length]);
+        self.length += length;
+        Ok(())
+    }
+}
+
+/// A crash report read back from storage.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CrashReport<'a> {
+    /// The panic message and location, if recorded. It is cut off before any invalid UTF-8, such as
+    /// from a message which was truncated within a character.
+    pub message: Option<&'a str>,
+    /// The values of the registers named in `REGISTER_NAMES`, if recorded.
+    pub registers: Option<[u64; REGISTER_COUNT]>,
+    /// The stack pointer and the bytes of the stack above it, if recorded.
+    pub stack: Option<(u64, &'a [u8])>,
+    /// Recent log lines, oldest first.
+    pub log: &'a [u8],
+}
+
+/// An error reading a crash report.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CrashReportError {
+    /// The data doesn't start with the magic bytes, so no report has been written.
+    NotFound,
+    /// The report was written by a newer version of the format.
+    UnsupportedVersion(u32),
+    /// The sections don't match their checksum.
+    BadChecksum,
+    /// The header or a section claims to be longer than the data available.
+    Truncated,
+}
+
+impl Display for CrashReportError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "No crash report found"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "Unsupported crash report version {version}")
+            }
+            Self::BadChecksum => write!(f, "Crash report checksum mismatch"),
+            Self::Truncated => write!(f, "Crash report truncated"),
+        }
+    }
+}
+
+impl<'a> CrashReport<'a> {
+    /// Parses a report, which may be followed by unused space.
+    ///
+    /// Sections with unknown tags or invalid contents are ignored.
+    pub fn decode(data: &'a [u8]) -> Result<Self, CrashReportError> {
+        if data.len() < HEADER_SIZE || data[0..8] != MAGIC {
+            return Err(CrashReportError::NotFound);
+        }
+        let version = read_u32(&data[8..12]);
+        if version > VERSION {
+            return Err(CrashReportError::UnsupportedVersion(version));
+        }
+        let length = read_u32(&data[12..16]) as usize;
+        let sections = data[HEADER_SIZE..]
+            .get(..length)
+            .ok_or(CrashReportError::Truncated)?;
+        let mut checksum = Adler32::default();
+        checksum.update(sections);
+        if checksum.finish() != read_u32(&data[16..20]) {
+            return Err(CrashReportError::BadChecksum);
+        }
+
+        let mut report = Self::default();
+        let mut rest = sections;
+        while !rest.is_empty() {
+            let (header, remaining) = rest
+                .split_at_checked(SECTION_HEADER_SIZE)
+                .ok_or(CrashReportError::Truncated)?;
+            let (contents, remaining) = remaining
+                .split_at_checked(read_u32(&header[4..8]) as usize)
+                .ok_or(CrashReportError::Truncated)?;
+            rest = remaining;
+            match read_u32(&header[0..4]) {
+                SECTION_MESSAGE => report.message = Some(valid_prefix(contents)),
+                SECTION_REGISTERS => report.registers = parse_registers(contents),
+                SECTION_STACK => {
+                    if let Some((address, stack)) = contents.split_first_chunk() {
+                        report.stack = Some((u64::from_le_bytes(*address), stack));
+                    }
+                }
+                SECTION_LOG => report.log = contents,
+                _ => {}
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Parses the contents of a `SECTION_REGISTERS`, returning `None` if it is the wrong length.
+fn parse_registers(contents: &[u8]) -> Option<[u64; REGISTER_COUNT]> {
+    if contents.len() != REGISTER_COUNT * 8 {
+        return None;
+    }
+    let mut registers = [0; REGISTER_COUNT];
+    for (register, bytes) in registers.iter_mut().zip(contents.chunks_exact(8)) {
+        *register = u64::from_le_bytes(bytes.try_into().unwrap());
+    }
+    Some(registers)
+}
+
+/// Returns the longest prefix of the given bytes which is valid UTF-8.
+fn valid_prefix(bytes: &[u8]) -> &str {
+    match str::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(e) => str::from_utf8(&bytes[..e.valid_up_to()]).unwrap(),
+    }
+}
+
+/// Reads a little-endian `u32` from the given 4 bytes.
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes.try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGISTERS: [u64; REGISTER_COUNT] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+
+    fn registers_bytes() -> Vec<u8> {
+        REGISTERS.iter().flat_map(|r| r.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut buffer = [0xaa; 512];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.text_section(SECTION_MESSAGE, format_args!("panicked at {}", "main.rs:1"));
+        encoder.bytes_section(SECTION_REGISTERS, &registers_bytes());
+        encoder.section(SECTION_STACK, |space| {
+            space[..8].copy_from_slice(&0x4000u64.to_le_bytes());
+            space[8..12].copy_from_slice(&[1, 2, 3, 4]);
+            12
+        });
+        encoder.bytes_section(SECTION_LOG, b"[INFO] booted\n");
+        let report = encoder.finish().to_vec();
+
+        assert_eq!(report[0..8], MAGIC);
+        assert_eq!(
+            CrashReport::decode(&report),
+            Ok(CrashReport {
+                message: Some("panicked at main.rs:1"),
+                registers: Some(REGISTERS),
+                stack: Some((0x4000, &[1, 2, 3, 4])),
+                log: b"[INFO] booted\n",
+            })
+        );
+        // Unused space after the report is ignored.
+        assert_eq!(
+            CrashReport::decode(&buffer).map(|report| report.message),
+            Ok(Some("panicked at main.rs:1"))
+        );
+    }
+
+    #[test]
+    fn truncation() {
+        let mut buffer = [0; HEADER_SIZE + SECTION_HEADER_SIZE + 4];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.text_section(SECTION_MESSAGE, format_args!("a télé message"));
+        // There is no room left for this at all.
+        encoder.bytes_section(SECTION_LOG, b"log");
+        let report = encoder.finish();
+        assert_eq!(
+            CrashReport::decode(report),
+            Ok(CrashReport {
+                message: Some("a t"),
+                ..CrashReport::default()
+            })
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            CrashReport::decode(&[0; 64]),
+            Err(CrashReportError::NotFound)
+        );
+
+        let mut buffer = [0; 64];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.bytes_section(SECTION_LOG, b"log");
+        let length = encoder.finish().len();
+
+        let mut report = buffer;
+        report[8] = 2;
+        assert_eq!(
+            CrashReport::decode(&report),
+            Err(CrashReportError::UnsupportedVersion(2))
+        );
+
+        let mut report = buffer;
+        report[length - 1] ^= 1;
+        assert_eq!(
+            CrashReport::decode(&report),
+            Err(CrashReportError::BadChecksum)
+        );
+
+        assert_eq!(
+            CrashReport::decode(&buffer[..length - 1]),
+            Err(CrashReportError::Truncated)
+        );
+
+        // Sections with unknown tags and registers of the wrong length are ignored.
+        let mut buffer = [0; 64];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.bytes_section(42, b"from the future");
+        encoder.bytes_section(SECTION_REGISTERS, &[0; 8]);
+        assert_eq!(
+            CrashReport::decode(encoder.finish()),
+            Ok(CrashReport::default())
+        );
+    }
+}
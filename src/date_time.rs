@@ -0,0 +1,126 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Parsing of the dates and times which the RTC can be set to.
+
+use crate::utc_offset::parse_utc_offset;
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime, Utc};
+
+/// Parses a time of the form `YYYY-MM-DDTHH:MM` or `YYYY-MM-DDTHH:MM:SS`, optionally followed by a
+/// UTC offset as accepted by `parse_utc_offset`, or `@` followed by a number of seconds since the
+/// Unix epoch.
+///
+/// Times without an offset are taken to be in `default_offset`.
+pub fn parse_date_time(s: &str, default_offset: FixedOffset) -> Option<DateTime<Utc>> {
+    if let Some(timestamp) = s.strip_prefix('@') {
+        return DateTime::from_timestamp(timestamp.parse().ok()?, 0);
+    }
+    let (date, time) = s.split_once('T')?;
+    let (time, offset) = match time.find(['+', '-', 'Z']) {
+        Some(index) => {
+            let (time, offset) = time.split_at(index);
+            (time, parse_utc_offset(offset)?)
+        }
+        None => (time, default_offset),
+    };
+    let date = parse_date(date)?;
+    let time = parse_time(time)?;
+    let local = date.and_time(time).and_local_timezone(offset).single()?;
+    Some(local.to_utc())
+}
+
+/// Parses a date of the form `YYYY-MM-DD`.
+fn parse_date(s: &str) -> Option<NaiveDate> {
+    let mut parts = s.split('-');
+    let year = parts.next()?.parse().ok()?;
+    let month = parts.next()?.parse().ok()?;
+    let day = parts.next()?.parse().ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    NaiveDate::from_ymd_opt(year, month, day)
+}
+
+/// Parses a time of day of the form `HH:MM` or `HH:MM:SS`.
+fn parse_time(s: &str) -> Option<NaiveTime> {
+    let mut parts = s.split(':');
+    let hours = parts.next()?.parse().ok()?;
+    let minutes = parts.next()?.parse().ok()?;
+    let seconds = parts
+        .next()
+        .map_or(Some(0), |seconds| seconds.parse().ok())?;
+    if parts.next().is_some() {
+        return None;
+    }
+    NaiveTime::from_hms_opt(hours, minutes, seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(timestamp: i64) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp(timestamp, 0)
+    }
+
+    #[test]
+    fn unix_timestamp() {
+        let offset = FixedOffset::east_opt(3600).unwrap();
+        assert_eq!(parse_date_time("@0", offset), utc(0));
+        assert_eq!(parse_date_time("@1700000000", offset), utc(1_700_000_000));
+        assert_eq!(parse_date_time("@", offset), None);
+        assert_eq!(parse_date_time("@1.5", offset), None);
+    }
+
+    #[test]
+    fn with_offset() {
+        let offset = FixedOffset::east_opt(3600).unwrap();
+        assert_eq!(
+            parse_date_time("2023-11-14T22:13:20Z", offset),
+            utc(1_700_000_000)
+        );
+        assert_eq!(
+            parse_date_time("2023-11-15T03:43:20+05:30", offset),
+            utc(1_700_000_000)
+        );
+        assert_eq!(
+            parse_date_time("2023-11-14T14:13:20-08", offset),
+            utc(1_700_000_000)
+        );
+        assert_eq!(
+            parse_date_time("2023-11-14T22:13Z", offset),
+            utc(1_699_999_980)
+        );
+    }
+
+    #[test]
+    fn default_offset() {
+        let offset = FixedOffset::east_opt(3600).unwrap();
+        assert_eq!(
+            parse_date_time("2023-11-14T23:13:20", offset),
+            utc(1_700_000_000)
+        );
+        let offset = FixedOffset::east_opt(0).unwrap();
+        assert_eq!(
+            parse_date_time("2023-11-14T22:13:20", offset),
+            utc(1_700_000_000)
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let offset = FixedOffset::east_opt(0).unwrap();
+        assert_eq!(parse_date_time("", offset), None);
+        assert_eq!(parse_date_time("2023-11-14", offset), None);
+        assert_eq!(parse_date_time("2023-11-14T", offset), None);
+        assert_eq!(parse_date_time("2023-11T22:13", offset), None);
+        assert_eq!(parse_date_time("2023-11-14-1T22:13", offset), None);
+        assert_eq!(parse_date_time("2023-13-14T22:13", offset), None);
+        assert_eq!(parse_date_time("2023-02-30T22:13", offset), None);
+        assert_eq!(parse_date_time("2023-11-14T24:00", offset), None);
+        assert_eq!(parse_date_time("2023-11-14T22:13:20:00", offset), None);
+        assert_eq!(parse_date_time("2023-11-14T22:13+25", offset), None);
+        assert_eq!(parse_date_time("2023-11-14T22", offset), None);
+    }
+}
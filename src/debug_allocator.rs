@@ -0,0 +1,210 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A global allocator for the `heap-debug` feature, which wraps the heap allocator to detect buffer
+//! overruns and use after free.
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ptr::{self, null_mut},
+    slice,
+};
+use osdemo::redzone::{
+    BlockTable, Corruption, FREED_POISON, PaddedLayout, Quarantine, RED_ZONE_POISON,
+};
+use spin::mutex::SpinMutex;
+
+/// The maximum number of live allocations tracked for periodic checks. Allocations beyond this
+/// still get red zones, but are only checked when they are freed.
+const MAX_TRACKED: usize = 512;
+
+/// The maximum number of freed blocks held in quarantine.
+const MAX_QUARANTINED: usize = 256;
+
+/// The maximum total size of the freed blocks held in quarantine.
+const MAX_QUARANTINED_BYTES: usize = 32 * 1024;
+
+/// How many allocations are made between checks of all tracked and quarantined blocks.
+const CHECK_INTERVAL: usize = 256;
+
+/// Wraps another allocator to surround each allocation with poisoned red zones, and to hold freed
+/// blocks in quarantine for a while before they are reused.
+///
+/// Red zones are checked when an allocation is freed, freed blocks are checked when they leave
+/// quarantine, and everything is checked every `CHECK_INTERVAL` allocations. Any corruption found
+/// causes a panic.
+pub struct DebugAllocator<A: 'static> {
+    inner: &'static A,
+    state: SpinMutex<State>,
+}
+
+struct State {
+    live: BlockTable<MAX_TRACKED>,
+    quarantine: Quarantine<MAX_QUARANTINED>,
+    allocations_since_check: usize,
+}
+
+/// The number of blocks checked by `DebugAllocator::check`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CheckSummary {
+    pub live: usize,
+    pub quarantined: usize,
+    pub quarantined_bytes: usize,
+}
+
+impl<A: GlobalAlloc> DebugAllocator<A> {
+    /// Creates a new debug allocator which allocates blocks from `inner`.
+    pub const fn new(inner: &'static A) -> Self {
+        Self {
+            inner,
+            state: SpinMutex::new(State {
+                live: BlockTable::new(),
+                quarantine: Quarantine::new(MAX_QUARANTINED_BYTES),
+                allocations_since_check: 0,
+            }),
+        }
+    }
+
+    /// Checks the red zones of all tracked allocations and the contents of all quarantined blocks.
+    pub fn check(&self) -> Result<CheckSummary, Corruption> {
+        let state = self.state.lock();
+        state.check()
+    }
+}
+
+impl State {
+    fn check(&self) -> Result<CheckSummary, Corruption> {
+        let mut summary = CheckSummary::default();
+        for (address, layout) in self.live.iter() {
+            // SAFETY: The block is live, and we allocated it with this layout.
+            unsafe { check_red_zones(address, &layout) }?;
+            summary.live += 1;
+        }
+        for (address, layout) in self.quarantine.iter() {
+            // SAFETY: The block is in quarantine, so nothing else should be using it.
+            unsafe { check_freed(address, &layout) }?;
+            summary.quarantined += 1;
+        }
+        summary.quarantined_bytes = self.quarantine.bytes();
+        Ok(summary)
+    }
+}
+
+// SAFETY: Allocations are passed through to the inner allocator, with a larger layout to include
+// red zones, and returned to it with the same layout. Blocks in quarantine aren't returned to the
+// inner allocator until they are evicted, so they can't be given out again in the meantime.
+unsafe impl<A: GlobalAlloc> GlobalAlloc for DebugAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let Some(padded) = PaddedLayout::new(layout) else {
+            return null_mut();
+        };
+        // SAFETY: The padded layout has non-zero size, because it includes the red zones.
+        let block = unsafe { self.inner.alloc(padded.block) };
+        if block.is_null() {
+            return block;
+        }
+        // SAFETY: The red zones are within the block we just allocated.
+        unsafe {
+            poison(block, padded.front_red_zone().len(), RED_ZONE_POISON);
+            poison(
+                block.add(padded.back_red_zone().start),
+                padded.back_red_zone().len(),
+                RED_ZONE_POISON,
+            );
+        }
+
+        let mut state = self.state.lock();
+        state.live.insert(block as usize, padded);
+        state.allocations_since_check += 1;
+        let result = if state.allocations_since_check >= CHECK_INTERVAL {
+            state.allocations_since_check = 0;
+            state.check().map(|_| ())
+        } else {
+            Ok(())
+        };
+        // Release the lock before panicking, in case the panic handler allocates.
+        drop(state);
+        if let Err(corruption) = result {
+            panic!("Heap corruption found by periodic check: {corruption}");
+        }
+
+        // SAFETY: The offset is within the block.
+        unsafe { block.add(padded.offset) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let padded = PaddedLayout::new(layout).unwrap();
+        // SAFETY: The caller promises that `ptr` was returned by `alloc` with the same layout, so
+        // the block starts `padded.offset` bytes before it.
+        let block = unsafe { ptr.sub(padded.offset) };
+        // SAFETY: The block is still allocated, and the caller is giving up ownership of it.
+        if let Err(corruption) = unsafe { check_red_zones(block as usize, &padded) } {
+            panic!("Heap corruption found on free of {ptr:?}: {corruption}");
+        }
+        // SAFETY: As above, nothing else may use the block any more.
+        unsafe { poison(block, padded.block.size(), FREED_POISON) };
+
+        let mut state = self.state.lock();
+        state.live.remove(block as usize);
+        let mut result = Ok(());
+        state
+            .quarantine
+            .push(block as usize, padded, |address, evicted| {
+                // SAFETY: The block was in quarantine, so nothing else should have been using it.
+                let check = unsafe { check_freed(address, &evicted) };
+                if result.is_ok() {
+                    result = check;
+                }
+                // SAFETY: We allocated the block from the inner allocator with this layout.
+                unsafe { self.inner.dealloc(address as *mut u8, evicted.block) };
+            });
+        drop(state);
+        if let Err(corruption) = result {
+            panic!("Heap corruption found in freed block: {corruption}");
+        }
+    }
+}
+
+/// Fills `len` bytes starting at `start` with the given value.
+///
+/// # Safety
+///
+/// The range must be valid for writes, and not be accessed concurrently.
+unsafe fn poison(start: *mut u8, len: usize, value: u8) {
+    // SAFETY: Our caller promises that the range is valid.
+    unsafe { ptr::write_bytes(start, value, len) };
+}
+
+/// Checks the red zones of the block at the given address.
+///
+/// # Safety
+///
+/// The block must have been allocated with the given layout, and not yet returned to the inner
+/// allocator.
+unsafe fn check_red_zones(address: usize, layout: &PaddedLayout) -> Result<(), Corruption> {
+    let front = layout.front_red_zone();
+    let back = layout.back_red_zone();
+    // SAFETY: Our caller promises that the block is allocated, and nothing should be using its red
+    // zones. The user data between them isn't borrowed, as its owner may be using it.
+    let (front, back) = unsafe {
+        (
+            slice::from_raw_parts((address + front.start) as *const u8, front.len()),
+            slice::from_raw_parts((address + back.start) as *const u8, back.len()),
+        )
+    };
+    layout.check_red_zones(front, back)
+}
+
+/// Checks that the whole of the freed block at the given address still contains its poison.
+///
+/// # Safety
+///
+/// The block must have been allocated with the given layout, and not yet returned to the inner
+/// allocator.
+unsafe fn check_freed(address: usize, layout: &PaddedLayout) -> Result<(), Corruption> {
+    // SAFETY: Our caller promises that the block is allocated, and it has been freed so nothing
+    // else should be using it.
+    let block = unsafe { slice::from_raw_parts(address as *const u8, layout.block.size()) };
+    layout.check_freed(block)
+}
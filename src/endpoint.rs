@@ -0,0 +1,194 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Parsing of the URI-like sources and destinations which `cp` copies between, such as
+//! `blk0:4096+512` or `vsock:2:9000`.
+
+use core::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+use osdemo_core::device_id::{DeviceId, DeviceKind};
+
+/// The prefix of a vsock endpoint.
+const VSOCK_PREFIX: &str = "vsock:";
+
+/// A source or destination to copy data from or to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Endpoint<'a> {
+    /// A range of bytes on a block device, such as `blk0:4096+512`. The range may be left out to
+    /// mean the whole device, and the length to mean the rest of the device after the offset.
+    Block {
+        /// The number of the block device, e.g. 0 for `blk0`.
+        device: usize,
+        /// The offset in bytes from the start of the device.
+        offset: u64,
+        /// The number of bytes, or `None` for the rest of the device.
+        length: Option<u64>,
+    },
+    /// A stream connection to a vsock peer, such as `vsock:2:9000`.
+    Vsock { cid: u64, port: u32 },
+    /// A path in a filesystem.
+    Path(&'a str),
+}
+
+impl<'a> Endpoint<'a> {
+    /// Parses the given endpoint. Anything which isn't a block device or vsock endpoint is taken to
+    /// be a path.
+    pub fn parse(s: &'a str) -> Result<Self, ParseEndpointError> {
+        if let Some(address) = s.strip_prefix(VSOCK_PREFIX) {
+            let (cid, port) = address
+                .split_once(':')
+                .ok_or(ParseEndpointError::InvalidVsockAddress)?;
+            return Ok(Self::Vsock {
+                cid: parse_number(cid).ok_or(ParseEndpointError::InvalidVsockAddress)?,
+                port: parse_number(port).ok_or(ParseEndpointError::InvalidVsockAddress)?,
+            });
+        }
+        let (device, range) = match s.split_once(':') {
+            Some((device, range)) => (device, Some(range)),
+            None => (s, None),
+        };
+        match DeviceId::from_str(device) {
+            Ok(DeviceId {
+                kind: DeviceKind::Block,
+                number,
+            }) => {
+                let (offset, length) = match range {
+                    None | Some("") => (0, None),
+                    Some(range) => parse_range(range).ok_or(ParseEndpointError::InvalidRange)?,
+                };
+                Ok(Self::Block {
+                    device: number,
+                    offset,
+                    length,
+                })
+            }
+            _ => Ok(Self::Path(s)),
+        }
+    }
+}
+
+impl Display for Endpoint<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Block {
+                device,
+                offset,
+                length: Some(length),
+            } => write!(f, "blk{device}:{offset}+{length}"),
+            Self::Block {
+                device,
+                offset,
+                length: None,
+            } => write!(f, "blk{device}:{offset}"),
+            Self::Vsock { cid, port } => write!(f, "{VSOCK_PREFIX}{cid}:{port}"),
+            Self::Path(path) => write!(f, "{path}"),
+        }
+    }
+}
+
+/// An error parsing an endpoint.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ParseEndpointError {
+    /// A block device range wasn't of the form `<offset>[+<length>]`.
+    InvalidRange,
+    /// A vsock address wasn't of the form `<cid>:<port>`.
+    InvalidVsockAddress,
+}
+
+impl Display for ParseEndpointError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidRange => write!(f, "Block range must be <offset>[+<length>]"),
+            Self::InvalidVsockAddress => write!(f, "vsock address must be vsock:<cid>:<port>"),
+        }
+    }
+}
+
+/// Parses a block range of the form `<offset>[+<length>]`.
+fn parse_range(range: &str) -> Option<(u64, Option<u64>)> {
+    match range.split_once('+') {
+        Some((offset, length)) => Some((parse_number(offset)?, Some(parse_number(length)?))),
+        None => Some((parse_number(range)?, None)),
+    }
+}
+
+/// Parses a decimal number, or a hexadecimal one with a `0x` prefix.
+pub(crate) fn parse_number<T: FromStr + TryFrom<u64>>(s: &str) -> Option<T> {
+    if let Some(hex) = s.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()?.try_into().ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_block() {
+        assert_eq!(
+            Endpoint::parse("blk0:4096+512"),
+            Ok(Endpoint::Block {
+                device: 0,
+                offset: 4096,
+                length: Some(512),
+            })
+        );
+        assert_eq!(
+            Endpoint::parse("blk2:0x1000"),
+            Ok(Endpoint::Block {
+                device: 2,
+                offset: 0x1000,
+                length: None,
+            })
+        );
+        assert_eq!(
+            Endpoint::parse("blk1"),
+            Ok(Endpoint::Block {
+                device: 1,
+                offset: 0,
+                length: None,
+            })
+        );
+        assert_eq!(
+            Endpoint::parse("blk0:12+"),
+            Err(ParseEndpointError::InvalidRange)
+        );
+    }
+
+    #[test]
+    fn parse_vsock() {
+        assert_eq!(
+            Endpoint::parse("vsock:2:9000"),
+            Ok(Endpoint::Vsock { cid: 2, port: 9000 })
+        );
+        assert_eq!(
+            Endpoint::parse("vsock:2"),
+            Err(ParseEndpointError::InvalidVsockAddress)
+        );
+        assert_eq!(
+            Endpoint::parse("vsock:2:99999999999"),
+            Err(ParseEndpointError::InvalidVsockAddress)
+        );
+    }
+
+    #[test]
+    fn parse_path() {
+        assert_eq!(
+            Endpoint::parse("/boot/kernel"),
+            Ok(Endpoint::Path("/boot/kernel"))
+        );
+        assert_eq!(Endpoint::parse("con0:1"), Ok(Endpoint::Path("con0:1")));
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for s in ["blk0:4096+512", "blk3:0", "vsock:3:1234", "file.txt"] {
+            assert_eq!(Endpoint::parse(s).unwrap().to_string(), s);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Decoding of the exception class from an exception syndrome register value.
+
+/// The shift of the exception class field in an ESR.
+const EC_SHIFT: u64 = 26;
+/// The mask of the exception class field in an ESR, after shifting.
+const EC_MASK: u64 = 0x3f;
+
+/// Returns the exception class field of the given ESR value.
+pub fn exception_class(esr: u64) -> u8 {
+    ((esr >> EC_SHIFT) & EC_MASK) as u8
+}
+
+/// Returns a short name for the given exception class, or `"other"` if it isn't one which is
+/// expected at EL1 or EL2 in AArch64 state.
+pub fn exception_class_name(class: u8) -> &'static str {
+    match class {
+        0x00 => "unknown",
+        0x01 => "WFI/WFE",
+        0x07 => "SIMD/FP access",
+        0x0e => "illegal execution state",
+        0x15 => "SVC",
+        0x16 => "HVC",
+        0x17 => "SMC",
+        0x18 => "MSR/MRS",
+        0x20 => "instruction abort from lower EL",
+        0x21 => "instruction abort",
+        0x22 => "PC alignment",
+        0x24 => "data abort from lower EL",
+        0x25 => "data abort",
+        0x26 => "SP alignment",
+        0x2c => "FP exception",
+        0x2f => "SError",
+        0x30 => "breakpoint from lower EL",
+        0x31 => "breakpoint",
+        0x32 => "step from lower EL",
+        0x33 => "step",
+        0x34 => "watchpoint from lower EL",
+        0x35 => "watchpoint",
+        0x3c => "BRK",
+        _ => "other",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn class() {
+        assert_eq!(exception_class(0x5600_0000), 0x15);
+        assert_eq!(exception_class(0x9600_0045), 0x25);
+        assert_eq!(exception_class(0xf200_03e8), 0x3c);
+        assert_eq!(exception_class(0x1_5600_0000), 0x15);
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(exception_class_name(0x15), "SVC");
+        assert_eq!(exception_class_name(0x24), "data abort from lower EL");
+        assert_eq!(exception_class_name(0x3f), "other");
+    }
+}
@@ -0,0 +1,131 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Tracing of exception entry and exit, recorded in the log ring buffer with a limit on how many
+//! exceptions are traced each second.
+//!
+//! This is for characterising interrupt storms and unexpected trap loops, where logging every
+//! exception to the console would slow things down so much that the behaviour changes, or drown
+//! out everything else. Exceptions over the limit are counted, and the count is recorded with the
+//! next exception which is traced.
+//!
+//! The time each exception takes is measured in CPU cycles if the core has a PMU, or otherwise in
+//! generic timer ticks, which are much coarser.
+
+use crate::logger;
+use core::sync::atomic::{AtomicBool, Ordering};
+use osdemo::{
+    esr::{exception_class, exception_class_name},
+    rate_limit::RateLimit,
+};
+use osdemo_core::{
+    exceptions::current_el,
+    pmu,
+    timer::{counter, uptime_us},
+};
+use spin::mutex::SpinMutex;
+
+/// Whether exceptions are currently being traced.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The limit on how many exceptions are traced each second.
+static LIMIT: SpinMutex<RateLimit> = SpinMutex::new(RateLimit::new(0));
+
+/// An exception which is being traced, from its entry until its exit.
+pub struct TracedException {
+    kind: &'static str,
+    /// Whether `start` is from the PMU cycle counter rather than the generic timer counter.
+    cycles: bool,
+    /// The value of the counter on entry.
+    start: u64,
+}
+
+/// Starts tracing up to `max_per_second` exceptions each second.
+pub fn enable(max_per_second: u32) {
+    LIMIT.lock().set_max_per_second(max_per_second);
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Stops tracing exceptions.
+pub fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+/// Returns the most exceptions traced each second, or `None` if tracing is disabled.
+pub fn max_per_second() -> Option<u32> {
+    ENABLED
+        .load(Ordering::Relaxed)
+        .then(|| LIMIT.lock().max_per_second())
+}
+
+/// Returns the unit which the time exceptions take on the current core is measured in.
+pub fn time_unit() -> &'static str {
+    if pmu::is_available() {
+        "CPU cycles"
+    } else {
+        "generic timer ticks"
+    }
+}
+
+/// Records entry to an exception handler of the given kind, with the given syndrome for
+/// synchronous exceptions, if tracing is enabled and the limit allows.
+///
+/// The result should be passed to `exit` when the handler returns.
+pub fn entry(kind: &'static str, esr: Option<u64>) -> Option<TracedException> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+    // Don't wait for the lock, in case the exception interrupted code which held it on this core.
+    let suppressed = LIMIT.try_lock()?.check(uptime_us())?;
+    if suppressed > 0 {
+        logger::trace(format_args!(
+            "exception trace: {suppressed} exceptions not traced due to rate limit"
+        ));
+    }
+    let el = current_el();
+    match esr {
+        Some(esr) => {
+            let class = exception_class(esr);
+            logger::trace(format_args!(
+                "exception entry {kind} at EL{el}, ESR {esr:#x} class {class:#04x} ({})",
+                exception_class_name(class)
+            ));
+        }
+        None => logger::trace(format_args!("exception entry {kind} at EL{el}")),
+    }
+    let cycles = pmu::is_available();
+    let start = if cycles {
+        // This leaves the counters alone if they are already running.
+        pmu::enable();
+        pmu::read_cycles()
+    } else {
+        counter()
+    };
+    Some(TracedException {
+        kind,
+        cycles,
+        start,
+    })
+}
+
+/// Records exit from an exception handler which was traced by `entry`, along with how many CPU
+/// cycles it took, or generic timer ticks if the core has no PMU.
+pub fn exit(traced: Option<TracedException>) {
+    if let Some(TracedException {
+        kind,
+        cycles,
+        start,
+    }) = traced
+    {
+        if cycles {
+            let cycles = pmu::read_cycles().wrapping_sub(start);
+            logger::trace(format_args!("exception exit {kind} after {cycles} cycles"));
+        } else {
+            let ticks = counter().wrapping_sub(start);
+            logger::trace(format_args!(
+                "exception exit {kind} after {ticks} timer ticks"
+            ));
+        }
+    }
+}
@@ -2,13 +2,19 @@
 // This project is dual-licensed under Apache 2.0 and MIT terms.
 // See LICENSE-APACHE and LICENSE-MIT for details.
 
-use crate::interrupts::handle_irq;
-use aarch64_rt::{ExceptionHandlers, RegisterStateRef, exception_handlers};
-use arm_sysregs::{
-    HcrEl2, read_currentel, read_esr_el1, read_esr_el2, read_far_el1, read_far_el2, read_hcr_el2,
-    write_hcr_el2,
+use crate::{
+    backtrace::print_register_state,
+    console, exception_trace,
+    user::{
+        account_exception_entry, account_exception_return, deliver_signals, handle_anon_fault,
+        handle_sync_lower, trace_lower_exception_entry, trace_lower_exception_exit,
+    },
 };
+use aarch64_rt::{ExceptionHandlers, RegisterStateRef, exception_handlers};
+use arm_sysregs::{read_esr_el1, read_esr_el2, read_far_el1, read_far_el2};
+use embedded_io::Write;
 use log::trace;
+use osdemo_core::{exceptions::current_el, interrupts::handle_irq};
 
 exception_handlers!(Exceptions);
 
@@ -16,20 +22,53 @@ pub struct Exceptions;
 
 impl ExceptionHandlers for Exceptions {
     extern "C" fn sync_current(register_state: RegisterStateRef) {
-        panic!(
-            "Unexpected sync_exception_current, esr={:#x}, far={:#x}; saved register state: {register_state:#018x?}",
-            esr(),
-            far(),
-        );
+        let (esr, far) = (esr(), far());
+        let traced = exception_trace::entry("sync_current", Some(esr));
+        // The kernel may touch anonymous memory which hasn't been allocated yet while handling a
+        // syscall.
+        if handle_anon_fault(esr, far) {
+            exception_trace::exit(traced);
+            return;
+        }
+        if let Some(mut console) = console::shared() {
+            // Ignore any errors writing to the console, as we are about to panic anyway.
+            let _ = writeln!(
+                console,
+                "Unexpected sync_exception_current, esr={esr:#x}, far={far:#x}"
+            );
+            let _ = print_register_state(&mut console, &register_state);
+        }
+        panic!("Unexpected sync_exception_current, esr={esr:#x}, far={far:#x}");
     }
 
     extern "C" fn irq_current(register_state: RegisterStateRef) {
+        let traced = exception_trace::entry("irq_current", None);
         trace!("irq_current, register_state: {register_state:#018x?}");
         handle_irq();
+        exception_trace::exit(traced);
+    }
+
+    extern "C" fn sync_lower(register_state: RegisterStateRef) {
+        let esr = esr();
+        trace_lower_exception_entry(exception_trace::entry("sync_lower", Some(esr)));
+        trace!("sync_lower, register_state: {register_state:#018x?}");
+        handle_sync_lower(register_state, esr, far());
+        trace_lower_exception_exit();
+    }
+
+    extern "C" fn irq_lower(register_state: RegisterStateRef) {
+        trace_lower_exception_entry(exception_trace::entry("irq_lower", None));
+        trace!("irq_lower, register_state: {register_state:#018x?}");
+        account_exception_entry();
+        handle_irq();
+        deliver_signals();
+        account_exception_return();
+        trace_lower_exception_exit();
     }
 }
 
-fn esr() -> u64 {
+/// Returns the exception syndrome register for the current exception level.
+pub fn esr() -> u64 {
     if current_el() == 2 {
         read_esr_el2().bits()
     } else {
@@ -37,26 +76,11 @@ fn esr() -> u64 {
     }
 }
 
-fn far() -> u64 {
+/// Returns the fault address register for the current exception level.
+pub fn far() -> u64 {
     if current_el() == 2 {
         read_far_el2().bits()
     } else {
         read_far_el1().bits()
     }
 }
-
-/// Returns the current exception level.
-pub fn current_el() -> u8 {
-    read_currentel().el()
-}
-
-/// Makes sure Physical IRQs are routed to the current exception level.
-pub fn init_irq_routing() {
-    if current_el() == 2 {
-        // SAFETY: We only set the IMO bit, which is safe.
-        unsafe {
-            // Route Physical IRQs to EL2.
-            write_hcr_el2(read_hcr_el2() | HcrEl2::IMO);
-        }
-    }
-}
@@ -0,0 +1,239 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! The hardware inventory which is sent to the host at boot, so that test harnesses can check the
+//! VM configuration which the guest actually saw.
+//!
+//! The report is a single JSON object, with:
+//!
+//! - `version`: the format version, currently 1.
+//! - `cpus`: each CPU in the device tree, with its `index` and `mpidr` affinity.
+//! - `memory`: each memory range in the device tree, with its `address` and `size` in bytes.
+//! - `pci`: each PCI function found, with its `root` complex index, `bdf`, `vendor_id`,
+//!   `device_id`, `class` and `subclass`.
+//! - `devices`: each entry in the device registry, including UARTs and VirtIO devices, with its
+//!   `id`, `kind`, `driver`, `location` and `description`.
+//!
+//! All numbers are plain JSON integers.
+
+use alloc::vec::Vec;
+use core::fmt::{self, Display, Formatter, Write};
+use osdemo_core::device_id::DeviceId;
+
+/// The name of the kernel command line argument which gives where to send the report, such as
+/// `hwreport=vsock:2:9002`.
+pub const BOOTARG: &str = "hwreport";
+
+/// The version of the format described above.
+pub const VERSION: u32 = 1;
+
+/// A CPU core from the device tree.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Cpu {
+    /// The index of the CPU in the device tree.
+    pub index: usize,
+    /// The MPIDR affinity fields of the CPU.
+    pub mpidr: u64,
+}
+
+/// A range of memory from the device tree.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MemoryRange {
+    pub address: u64,
+    pub size: u64,
+}
+
+/// A function found on a PCI bus.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PciFunction {
+    /// The index of the root complex which the function is under.
+    pub root: usize,
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+}
+
+/// An entry in the device registry.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Device<'a> {
+    pub id: DeviceId,
+    pub driver: &'a str,
+    pub location: &'a str,
+    pub description: &'a str,
+}
+
+/// The hardware inventory which the guest found at boot.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct HwReport<'a> {
+    pub cpus: Vec<Cpu>,
+    pub memory: Vec<MemoryRange>,
+    pub pci: Vec<PciFunction>,
+    pub devices: Vec<Device<'a>>,
+}
+
+impl Display for HwReport<'_> {
+    /// Writes the report as JSON, in the format described in the module documentation.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{{\"version\":{VERSION},\"cpus\":[")?;
+        for (i, cpu) in self.cpus.iter().enumerate() {
+            write_separator(f, i)?;
+            write!(f, "{{\"index\":{},\"mpidr\":{}}}", cpu.index, cpu.mpidr)?;
+        }
+        f.write_str("],\"memory\":[")?;
+        for (i, range) in self.memory.iter().enumerate() {
+            write_separator(f, i)?;
+            write!(
+                f,
+                "{{\"address\":{},\"size\":{}}}",
+                range.address, range.size
+            )?;
+        }
+        f.write_str("],\"pci\":[")?;
+        for (i, function) in self.pci.iter().enumerate() {
+            write_separator(f, i)?;
+            write!(
+                f,
+                "{{\"root\":{},\"bdf\":\"{:02x}:{:02x}.{}\",\"vendor_id\":{},\"device_id\":{},\
+                 \"class\":{},\"subclass\":{}}}",
+                function.root,
+                function.bus,
+                function.device,
+                function.function,
+                function.vendor_id,
+                function.device_id,
+                function.class,
+                function.subclass,
+            )?;
+        }
+        f.write_str("],\"devices\":[")?;
+        for (i, device) in self.devices.iter().enumerate() {
+            write_separator(f, i)?;
+            write!(
+                f,
+                "{{\"id\":{},\"kind\":{},\"driver\":{},\"location\":{},\"description\":{}}}",
+                JsonString(&device.id),
+                JsonString(&device.id.kind.prefix()),
+                JsonString(&device.driver),
+                JsonString(&device.location),
+                JsonString(&device.description),
+            )?;
+        }
+        f.write_str("]}")
+    }
+}
+
+/// Writes the comma before each element of a JSON array except the first.
+fn write_separator(f: &mut Formatter, index: usize) -> fmt::Result {
+    if index > 0 { f.write_char(',') } else { Ok(()) }
+}
+
+/// Formats the `Display` output of the wrapped value as a quoted and escaped JSON string.
+struct JsonString<'a, T: Display + ?Sized>(&'a T);
+
+impl<T: Display + ?Sized> Display for JsonString<'_, T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_char('"')?;
+        write!(JsonEscaper(f), "{}", self.0)?;
+        f.write_char('"')
+    }
+}
+
+/// Escapes characters which aren't allowed unescaped in a JSON string.
+struct JsonEscaper<'a, 'b>(&'a mut Formatter<'b>);
+
+impl Write for JsonEscaper<'_, '_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            match c {
+                '"' => self.0.write_str("\\\"")?,
+                '\\' => self.0.write_str("\\\\")?,
+                '\n' => self.0.write_str("\\n")?,
+                c if c.is_control() => write!(self.0, "\\u{:04x}", u32::from(c))?,
+                c => self.0.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{string::ToString, vec};
+    use osdemo_core::device_id::DeviceKind;
+
+    #[test]
+    fn empty() {
+        assert_eq!(
+            HwReport::default().to_string(),
+            r#"{"version":1,"cpus":[],"memory":[],"pci":[],"devices":[]}"#
+        );
+    }
+
+    #[test]
+    fn full() {
+        let report = HwReport {
+            cpus: vec![Cpu { index: 0, mpidr: 0 }, Cpu { index: 1, mpidr: 1 }],
+            memory: vec![MemoryRange {
+                address: 0x4000_0000,
+                size: 0x1000_0000,
+            }],
+            pci: vec![PciFunction {
+                root: 0,
+                bus: 0,
+                device: 3,
+                function: 0,
+                vendor_id: 0x1af4,
+                device_id: 0x1042,
+                class: 1,
+                subclass: 0,
+            }],
+            devices: vec![
+                Device {
+                    id: DeviceId {
+                        kind: DeviceKind::Uart,
+                        number: 0,
+                    },
+                    driver: "pl011",
+                    location: "MMIO 0x9000000",
+                    description: "Primary console",
+                },
+                Device {
+                    id: DeviceId {
+                        kind: DeviceKind::Block,
+                        number: 0,
+                    },
+                    driver: "virtio-blk",
+                    location: "PCI 0:00:03.0",
+                    description: "16 MiB",
+                },
+            ],
+        };
+        assert_eq!(
+            report.to_string(),
+            concat!(
+                r#"{"version":1,"cpus":[{"index":0,"mpidr":0},{"index":1,"mpidr":1}],"#,
+                r#""memory":[{"address":1073741824,"size":268435456}],"#,
+                r#""pci":[{"root":0,"bdf":"00:03.0","vendor_id":6900,"device_id":4162,"#,
+                r#""class":1,"subclass":0}],"#,
+                r#""devices":[{"id":"uart0","kind":"uart","driver":"pl011","#,
+                r#""location":"MMIO 0x9000000","description":"Primary console"},"#,
+                r#"{"id":"blk0","kind":"blk","driver":"virtio-blk","#,
+                r#""location":"PCI 0:00:03.0","description":"16 MiB"}]}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(
+            JsonString("a \"quoted\" \\ path\n\t").to_string(),
+            r#""a \"quoted\" \\ path\n\u0009""#
+        );
+    }
+}
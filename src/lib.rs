@@ -0,0 +1,31 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Pure logic used by the osdemo shell which doesn't depend on the platform, so that it can be
+//! unit tested on the host with `make test`.
+//!
+//! Logic used by the reusable subsystems lives in `osdemo-core` instead.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+pub mod args;
+pub mod balloon_policy;
+pub mod coredump;
+pub mod crashdump;
+pub mod date_time;
+pub mod endpoint;
+pub mod esr;
+pub mod hw_report;
+pub mod log_buffer;
+pub mod prng;
+pub mod rate_limit;
+pub mod rc;
+pub mod redzone;
+pub mod scrub;
+pub mod settings;
+pub mod telnet;
+pub mod terminal;
+pub mod utc_offset;
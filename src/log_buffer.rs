@@ -0,0 +1,266 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A fixed-size ring buffer of recent log lines, which can be appended to from any context without
+//! taking a lock.
+
+use alloc::vec::Vec;
+use core::{
+    fmt::{self, Arguments, Write},
+    str::FromStr,
+    sync::atomic::{AtomicU8, AtomicUsize, Ordering},
+};
+use log::{Level, LevelFilter};
+
+/// The longest line which will be stored, in bytes. Longer lines are truncated.
+pub const MAX_LINE_LENGTH: usize = 256;
+
+/// A ring buffer of bytes which keeps the most recently written `N` bytes.
+///
+/// Each writer reserves space by atomically advancing the write position, so concurrent writers
+/// never overwrite each other's lines unless the buffer wraps around while they are writing. A
+/// reader racing with a writer may see a partially written line.
+pub struct LogBuffer<const N: usize> {
+    data: [AtomicU8; N],
+    /// The total number of bytes ever written. The next byte will be written at this modulo `N`.
+    written: AtomicUsize,
+}
+
+impl<const N: usize> LogBuffer<N> {
+    /// Creates a new empty buffer.
+    pub const fn new() -> Self {
+        Self {
+            data: [const { AtomicU8::new(0) }; N],
+            written: AtomicUsize::new(0),
+        }
+    }
+
+    /// Appends the given bytes to the buffer. If there are more than `N` of them, only the last `N`
+    /// are kept.
+    pub fn push(&self, bytes: &[u8]) {
+        let bytes = &bytes[bytes.len().saturating_sub(N)..];
+        let start = self.written.fetch_add(bytes.len(), Ordering::AcqRel);
+        for (i, &byte) in bytes.iter().enumerate() {
+            self.data[(start + i) % N].store(byte, Ordering::Relaxed);
+        }
+    }
+
+    /// Formats the given arguments followed by a newline, truncated to `MAX_LINE_LENGTH`, and
+    /// appends them to the buffer as a single line.
+    ///
+    /// Returns the line which was appended.
+    pub fn push_line(&self, args: Arguments) -> LineBuffer {
+        let mut line = LineBuffer::default();
+        // `LineBuffer` truncates rather than failing.
+        let _ = line.write_fmt(args);
+        line.terminate();
+        self.push(line.as_bytes());
+        line
+    }
+
+    /// Returns a copy of the contents of the buffer, oldest first.
+    ///
+    /// If older lines have been overwritten then the partial line left at the start is skipped, so
+    /// that the result starts at the beginning of a line.
+    pub fn contents(&self) -> Vec<u8> {
+        let end = self.written.load(Ordering::Acquire);
+        let start = end.saturating_sub(N);
+        let mut contents = (start..end)
+            .map(|position| self.data[position % N].load(Ordering::Relaxed))
+            .collect::<Vec<_>>();
+        if start > 0 {
+            let partial_line_length = contents
+                .iter()
+                .position(|&byte| byte == b'\n')
+                .map_or(contents.len(), |newline| newline + 1);
+            contents.drain(..partial_line_length);
+        }
+        contents
+    }
+
+    /// Copies as much of the end of the buffer as fits into `out`, without allocating, so that it
+    /// can be used while panicking.
+    ///
+    /// As with `contents`, a partial line at the start is skipped. Returns the number of bytes
+    /// copied.
+    pub fn copy_recent(&self, out: &mut [u8]) -> usize {
+        let end = self.written.load(Ordering::Acquire);
+        let mut start = end - end.min(N).min(out.len());
+        if start > 0 {
+            match (start..end)
+                .find(|&position| self.data[position % N].load(Ordering::Relaxed) == b'\n')
+            {
+                Some(newline) => start = newline + 1,
+                None => return 0,
+            }
+        }
+        for (byte, position) in out.iter_mut().zip(start..end) {
+            *byte = self.data[position % N].load(Ordering::Relaxed);
+        }
+        end - start
+    }
+}
+
+impl<const N: usize> Default for LogBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A single line of up to `MAX_LINE_LENGTH` bytes, which silently truncates anything written past
+/// the end.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LineBuffer {
+    data: [u8; MAX_LINE_LENGTH],
+    length: usize,
+}
+
+impl LineBuffer {
+    /// Returns the bytes written so far.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.length]
+    }
+
+    /// Ends the line with a newline, replacing the last byte if it is full.
+    fn terminate(&mut self) {
+        self.length = self.length.min(MAX_LINE_LENGTH - 1);
+        self.data[self.length] = b'\n';
+        self.length += 1;
+    }
+}
+
+impl Default for LineBuffer {
+    fn default() -> Self {
+        Self {
+            data: [0; MAX_LINE_LENGTH],
+            length: 0,
+        }
+    }
+}
+
+impl Write for LineBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let count = s.len().min(MAX_LINE_LENGTH - self.length);
+        self.data[self.length..self.length + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.length += count;
+        Ok(())
+    }
+}
+
+/// Returns the level of a log line of the form `[LEVEL] message`, if it has one.
+///
+/// The level may be preceded by other bracketed fields, such as a timestamp or CPU index.
+pub fn line_level(line: &str) -> Option<Level> {
+    let mut rest = line;
+    while let Some((field, after)) = rest.strip_prefix('[')?.split_once(']') {
+        if let Ok(level) = Level::from_str(field) {
+            return Some(level);
+        }
+        rest = after.trim_start();
+    }
+    None
+}
+
+/// Returns whether the given log line should be shown with the given filter. Lines without a level
+/// are always shown.
+pub fn line_enabled(line: &str, filter: LevelFilter) -> bool {
+    line_level(line).is_none_or(|level| level <= filter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty() {
+        let buffer = LogBuffer::<16>::new();
+        assert_eq!(buffer.contents(), b"");
+    }
+
+    #[test]
+    fn push_without_wrapping() {
+        let buffer = LogBuffer::<16>::new();
+        buffer.push(b"one\n");
+        buffer.push(b"two\n");
+        assert_eq!(buffer.contents(), b"one\ntwo\n");
+    }
+
+    #[test]
+    fn wrapping_skips_partial_line() {
+        let buffer = LogBuffer::<16>::new();
+        buffer.push(b"first line\n");
+        buffer.push(b"second\n");
+        // The last 16 bytes start partway through the first line.
+        assert_eq!(buffer.contents(), b"second\n");
+        buffer.push(b"3\n");
+        assert_eq!(buffer.contents(), b"second\n3\n");
+    }
+
+    #[test]
+    fn push_longer_than_buffer() {
+        let buffer = LogBuffer::<8>::new();
+        buffer.push(b"0123456789\n");
+        assert_eq!(buffer.contents(), b"3456789\n");
+        buffer.push(b"ab\n");
+        assert_eq!(buffer.contents(), b"ab\n");
+    }
+
+    #[test]
+    fn copy_recent_lines() {
+        let buffer = LogBuffer::<16>::new();
+        buffer.push(b"one\ntwo\nthree\n");
+        let mut out = [0; 32];
+        assert_eq!(buffer.copy_recent(&mut out), 14);
+        assert_eq!(out[..14], *b"one\ntwo\nthree\n");
+        // Only the last 8 bytes fit, which start partway through the second line.
+        assert_eq!(buffer.copy_recent(&mut out[..8]), 6);
+        assert_eq!(out[..6], *b"three\n");
+        assert_eq!(buffer.copy_recent(&mut out[..4]), 0);
+    }
+
+    #[test]
+    fn push_line_formats_and_terminates() {
+        let buffer = LogBuffer::<64>::new();
+        let line = buffer.push_line(format_args!("[{}] {}", Level::Info, 42));
+        assert_eq!(line.as_bytes(), b"[INFO] 42\n");
+        assert_eq!(buffer.contents(), b"[INFO] 42\n");
+    }
+
+    #[test]
+    fn push_line_truncates() {
+        let buffer = LogBuffer::<1024>::new();
+        let long = "x".repeat(MAX_LINE_LENGTH * 2);
+        let line = buffer.push_line(format_args!("{long}"));
+        assert_eq!(line.as_bytes().len(), MAX_LINE_LENGTH);
+        assert_eq!(line.as_bytes().last(), Some(&b'\n'));
+        assert_eq!(buffer.contents().len(), MAX_LINE_LENGTH);
+    }
+
+    #[test]
+    fn parse_level() {
+        assert_eq!(line_level("[WARN] disk full"), Some(Level::Warn));
+        assert_eq!(line_level("[trace] x"), Some(Level::Trace));
+        assert_eq!(line_level("no level"), None);
+        assert_eq!(line_level("[BOGUS] x"), None);
+    }
+
+    #[test]
+    fn parse_level_after_prefix() {
+        assert_eq!(
+            line_level("[    1.000250] [cpu2] [DEBUG] x"),
+            Some(Level::Debug)
+        );
+        assert_eq!(line_level("[cpu?] [ERROR] x"), Some(Level::Error));
+        assert_eq!(line_level("[cpu0] no level"), None);
+    }
+
+    #[test]
+    fn filter_lines() {
+        assert!(line_enabled("[ERROR] x", LevelFilter::Warn));
+        assert!(line_enabled("[WARN] x", LevelFilter::Warn));
+        assert!(!line_enabled("[INFO] x", LevelFilter::Warn));
+        assert!(!line_enabled("[ERROR] x", LevelFilter::Off));
+        assert!(line_enabled("continuation", LevelFilter::Error));
+    }
+}
@@ -2,29 +2,248 @@
 // This project is dual-licensed under Apache 2.0 and MIT terms.
 // See LICENSE-APACHE and LICENSE-MIT for details.
 
-use crate::console::SharedConsole;
+use crate::{
+    console::{self, SharedConsole},
+    platform::ConsoleImpl,
+};
+use alloc::vec::Vec;
+use arrayvec::ArrayVec;
+use core::{
+    arch::asm,
+    fmt::{self, Arguments, Display, Formatter, Write as _},
+    hint::spin_loop,
+    mem::take,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 use embedded_io::Write;
-use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
-use percore::exception_free;
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use osdemo::log_buffer::{LineBuffer, LogBuffer};
+use osdemo_core::{cpus::try_current_cpu_index, timer::uptime_us};
+use percore::{ExceptionLock, exception_free};
+use spin::{Once, mutex::SpinMutex};
 
-impl<T: Send + Write> Log for SharedConsole<T> {
+/// The size of the ring buffer of recent log records, in bytes.
+const LOG_BUFFER_SIZE: usize = 16 * 1024;
+
+/// How many times to try to lock the console before giving up on writing a record to it.
+const CONSOLE_LOCK_ATTEMPTS: usize = 100_000;
+
+/// The maximum number of records which can be queued to be written to the console later, because
+/// they were logged with IRQs masked or before the console was set up. Any more are only kept in
+/// the ring buffer.
+const MAX_DEFERRED_LINES: usize = 16;
+
+/// The IRQ mask bit in DAIF.
+const DAIF_I: u64 = 1 << 7;
+
+static LOG_BUFFER: LogBuffer<LOG_BUFFER_SIZE> = LogBuffer::new();
+/// Records logged with IRQs masked or before the console was set up, waiting to be written to the
+/// console.
+static DEFERRED_LINES: ExceptionLock<SpinMutex<ArrayVec<LineBuffer, MAX_DEFERRED_LINES>>> =
+    ExceptionLock::new(SpinMutex::new(ArrayVec::new_const()));
+/// The number of records which didn't fit in `DEFERRED_LINES`.
+static DROPPED_LINES: AtomicUsize = AtomicUsize::new(0);
+static LOGGER: Once<Logger> = Once::new();
+
+/// What to prefix each log line with, in addition to the level.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LogFormat {
+    /// Whether to include the time since the generic timer's counter started, in seconds.
+    pub timestamp: bool,
+    /// Whether to include the index of the CPU core which logged the record.
+    pub cpu: bool,
+}
+
+/// The prefix of a log line, formatted according to a `LogFormat`.
+struct Prefix {
+    format: LogFormat,
+    level: Level,
+}
+
+impl Display for Prefix {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.format.timestamp {
+            let uptime_us = uptime_us();
+            write!(
+                f,
+                "[{:5}.{:06}] ",
+                uptime_us / 1_000_000,
+                uptime_us % 1_000_000
+            )?;
+        }
+        if self.format.cpu {
+            // The CPU index can't be found until the FDT has been set.
+            match try_current_cpu_index() {
+                Some(cpu) => write!(f, "[cpu{cpu}] ")?,
+                None => write!(f, "[cpu?] ")?,
+            }
+        }
+        write!(f, "[{}]", self.level)
+    }
+}
+
+/// Stores each record in the ring buffer, and then writes it to the console if it can.
+///
+/// Records logged with IRQs masked, such as from an exception handler, may be logged while this
+/// core holds the console lock, so they are queued instead and written the next time a record is
+/// logged or the logger is flushed with IRQs unmasked.
+///
+/// Records logged before the console is set up are written with `console::early_write` if it has
+/// anywhere to write to, and otherwise queued until the console is set up.
+struct Logger {
+    /// The console to write records to, once it has been set up.
+    console: Once<&'static SharedConsole<ConsoleImpl>>,
+    format: LogFormat,
+}
+
+impl Logger {
+    /// Writes the given bytes to the console, unless it stays locked for too long.
+    fn write_to_console(console: &SharedConsole<ConsoleImpl>, bytes: &[u8]) {
+        // The console may already be locked by another core, so don't wait for it forever. The
+        // record is still in the ring buffer.
+        exception_free(|token| {
+            let console = console.console.borrow(token);
+            for _ in 0..CONSOLE_LOCK_ATTEMPTS {
+                if let Some(mut console) = console.try_lock() {
+                    let _ = console.write_all(bytes);
+                    return;
+                }
+                spin_loop();
+            }
+        });
+    }
+
+    /// Queues the given record to be written to the console later, or counts it as dropped if the
+    /// queue is full.
+    fn defer(line: LineBuffer) {
+        exception_free(|token| {
+            if DEFERRED_LINES.borrow(token).lock().try_push(line).is_err() {
+                DROPPED_LINES.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+    }
+
+    /// Writes any queued records to the console, if it has been set up.
+    fn write_deferred(&self) {
+        let Some(console) = self.console.get() else {
+            return;
+        };
+        let (lines, dropped) = exception_free(|token| {
+            (
+                take(&mut *DEFERRED_LINES.borrow(token).lock()),
+                DROPPED_LINES.swap(0, Ordering::Relaxed),
+            )
+        });
+        for line in &lines {
+            Self::write_to_console(console, line.as_bytes());
+        }
+        if dropped > 0 {
+            let mut message = LineBuffer::default();
+            let _ = writeln!(
+                message,
+                "({dropped} records logged with IRQs masked or during early boot are only in dmesg)"
+            );
+            Self::write_to_console(console, message.as_bytes());
+        }
+    }
+}
+
+impl Log for Logger {
     fn enabled(&self, _metadata: &Metadata) -> bool {
         true
     }
 
     fn log(&self, record: &Record) {
-        exception_free(|token| {
-            let console = &mut *self.console.borrow(token).lock();
-            writeln!(console, "[{}] {}", record.level(), record.args()).unwrap();
-        });
+        let prefix = Prefix {
+            format: self.format,
+            level: record.level(),
+        };
+        let line = LOG_BUFFER.push_line(format_args!("{prefix} {}", record.args()));
+        let Some(console) = self.console.get() else {
+            if console::has_early_output() {
+                // Write each valid part separately rather than allocating, as the heap may not be
+                // ready yet.
+                for chunk in line.as_bytes().utf8_chunks() {
+                    console::early_write(format_args!("{}", chunk.valid()));
+                }
+            } else {
+                Self::defer(line);
+            }
+            return;
+        };
+        if irqs_masked() {
+            Self::defer(line);
+            return;
+        }
+        // Keep records in order.
+        self.write_deferred();
+        Self::write_to_console(console, line.as_bytes());
     }
 
-    fn flush(&self) {}
+    fn flush(&self) {
+        if !irqs_masked() {
+            self.write_deferred();
+        }
+    }
+}
+
+/// Returns whether IRQs are masked on the current core, as they are while handling an exception.
+fn irqs_masked() -> bool {
+    let daif: u64;
+    // SAFETY: Reading DAIF has no side effects.
+    unsafe {
+        asm!("mrs {}, daif", out(reg) daif, options(nomem, nostack));
+    }
+    daif & DAIF_I != 0
 }
 
-/// Initialises the logger with the given shared console.
-pub fn init(console: &'static impl Log, max_level: LevelFilter) -> Result<(), SetLoggerError> {
-    log::set_logger(console)?;
+/// Initialises the logger before the console is set up, prefixing each line as described by the
+/// given format.
+///
+/// Records are written with `console::early_write` or queued until `set_console` is called.
+pub fn init_early(max_level: LevelFilter, format: LogFormat) -> Result<(), SetLoggerError> {
+    log::set_logger(LOGGER.call_once(|| Logger {
+        console: Once::new(),
+        format,
+    }))?;
     log::set_max_level(max_level);
     Ok(())
 }
+
+/// Starts writing records to the given shared console, beginning with any which were queued while
+/// there was no console.
+///
+/// Panics if the logger hasn't been initialised with `init_early`.
+pub fn set_console(console: &'static SharedConsole<ConsoleImpl>) {
+    let logger = LOGGER.get().expect("Logger not yet initialised");
+    logger.console.call_once(|| console);
+    if !irqs_masked() {
+        logger.write_deferred();
+    }
+}
+
+/// Appends a trace record with the given message to the ring buffer, without writing it to the
+/// console.
+///
+/// This doesn't allocate or wait for any locks, so can be used from within the heap allocator.
+pub fn trace(args: Arguments) {
+    let Some(logger) = LOGGER.get() else {
+        return;
+    };
+    let prefix = Prefix {
+        format: logger.format,
+        level: Level::Trace,
+    };
+    LOG_BUFFER.push_line(format_args!("{prefix} {args}"));
+}
+
+/// Returns a copy of the recent log records, oldest first.
+pub fn recent() -> Vec<u8> {
+    LOG_BUFFER.contents()
+}
+
+/// Copies as many of the most recent log records as fit into `out`, without allocating. Returns
+/// the number of bytes copied.
+pub fn copy_recent(out: &mut [u8]) -> usize {
+    LOG_BUFFER.copy_recent(out)
+}
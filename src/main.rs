@@ -9,46 +9,60 @@
 
 extern crate alloc;
 
+mod alloc_trace;
 mod apps;
+mod backtrace;
 mod console;
-mod cpus;
-pub mod devices;
-pub mod drivers;
+#[cfg(feature = "heap-debug")]
+mod debug_allocator;
+mod exception_trace;
 mod exceptions;
-mod interrupts;
 mod logger;
-mod pagetable;
-pub mod pci;
 mod platform;
-pub mod secondary_entry;
-mod virtio;
+mod target_tests;
+mod user;
 
-use crate::{exceptions::current_el, interrupts::init_gic};
 use aarch64_paging::paging::{MemoryRegion, PAGE_SIZE};
-use aarch64_rt::entry;
-use alloc::vec::Vec;
+use aarch64_rt::{entry, initial_pagetable};
+use alloc::{boxed::Box, format, string::ToString, vec::Vec};
 use apps::shell;
-use buddy_system_allocator::{Heap, LockedHeap};
-use core::ops::DerefMut;
-use devices::Devices;
+use core::{
+    slice,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 use dtoolkit::{
-    Node, Property,
+    Node,
     fdt::{Fdt, FdtNode},
     standard::{NodeStandard, Reg},
 };
-use embedded_io::Write;
-use log::{LevelFilter, debug, error, info};
-use pagetable::{IdMap, PAGETABLE};
-use pci::{PCI_COMPATIBLE, PCIE_COMPATIBLE, find_pci_roots};
-use platform::{Platform, PlatformImpl};
-use smccc::{Hvc, Smc, psci::system_off};
-use spin::{
-    Once,
-    mutex::{SpinMutex, SpinMutexGuard},
+use log::{LevelFilter, debug, info};
+use logger::LogFormat;
+use osdemo::balloon_policy::MemoryUsage;
+#[cfg(all(feature = "drivers", feature = "block"))]
+use osdemo_core::drivers::sdhci::{SDHCI_COMPATIBLE, find_sdhci_mmio_devices};
+use osdemo_core::{
+    FDT,
+    device_id::DeviceKind,
+    devices::{Devices, find_pci_devices},
+    dma::{self, BOUNCE_POOL_MEMORY, DMA_POOL_MEMORY},
+    exceptions::{check_el, current_el},
+    fdt::{fdt_to_pagetable_region, is_compatible},
+    interrupts::init_gic,
+    pagetable::{IdMap, PAGETABLE},
+    pci::{PCI_COMPATIBLE, PCIE_COMPATIBLE, find_pci_roots},
+    power::NoEnergyMeter,
+    shutdown::init_power_button,
+    tracked_heap::{HeapStats, TrackedHeap},
+    virtio::find_virtio_mmio_devices,
 };
-use virtio::{find_virtio_mmio_devices, find_virtio_pci_devices};
+use platform::{Platform, PlatformImpl, is_ns16550};
+use spin::mutex::{SpinMutex, SpinMutexGuard};
 
 const LOG_LEVEL: LevelFilter = LevelFilter::Debug;
+const LOG_FORMAT: LogFormat = LogFormat {
+    timestamp: true,
+    cpu: true,
+};
 
 const PAGE_HEAP_SIZE: usize = 10 * PAGE_SIZE;
 static PAGE_HEAP: SpinMutex<[u8; PAGE_HEAP_SIZE]> = SpinMutex::new([0; PAGE_HEAP_SIZE]);
@@ -56,24 +70,56 @@ static PAGE_HEAP: SpinMutex<[u8; PAGE_HEAP_SIZE]> = SpinMutex::new([0; PAGE_HEAP
 const HEAP_SIZE: usize = 40 * PAGE_SIZE;
 static HEAP: SpinMutex<[u8; HEAP_SIZE]> = SpinMutex::new([0; HEAP_SIZE]);
 
+/// The number of bytes of memory set aside for boot which were given to the heap once boot no
+/// longer needed them.
+static RECLAIMED_BOOT_MEMORY: AtomicUsize = AtomicUsize::new(0);
+
+/// The heap allocator: a buddy system allocator, or a TLSF allocator with bounded allocation times
+/// when the `tlsf` feature is enabled.
+#[cfg(not(feature = "tlsf"))]
+type HeapAllocator = osdemo_core::tracked_heap::LockedTrackedHeap<32>;
+#[cfg(feature = "tlsf")]
+type HeapAllocator = osdemo_core::tlsf::LockedTlsf;
+
+static HEAP_ALLOCATOR: HeapAllocator = HeapAllocator::new();
+
+/// Wraps the heap allocator to detect heap corruption, when the `heap-debug` feature is enabled.
+#[cfg(feature = "heap-debug")]
+static DEBUG_ALLOCATOR: debug_allocator::DebugAllocator<HeapAllocator> =
+    debug_allocator::DebugAllocator::new(&HEAP_ALLOCATOR);
+
+/// Wraps the heap allocator to trace allocations when asked to by the `trace` command.
+#[cfg(not(feature = "heap-debug"))]
+#[global_allocator]
+static GLOBAL_ALLOCATOR: alloc_trace::TracingAllocator<HeapAllocator> =
+    alloc_trace::TracingAllocator::new(&HEAP_ALLOCATOR);
+#[cfg(feature = "heap-debug")]
 #[global_allocator]
-static HEAP_ALLOCATOR: LockedHeap<32> = LockedHeap::new();
+static GLOBAL_ALLOCATOR: alloc_trace::TracingAllocator<
+    debug_allocator::DebugAllocator<HeapAllocator>,
+> = alloc_trace::TracingAllocator::new(&DEBUG_ALLOCATOR);
 
-static FDT: Once<Fdt<'static>> = Once::new();
+// The initial hardcoded page table used before the Rust code starts and activates the main page
+// table.
+initial_pagetable!(PlatformImpl::initial_idmap());
 
 entry!(main);
 fn main(x0: u64, _x1: u64, _x2: u64, _x3: u64) -> ! {
-    let fdt_address = x0 as *const u8;
-    // SAFETY: We only call `PlatformImpl::create` here, once on boot.
-    let mut platform = unsafe { PlatformImpl::create() };
-    let mut parts = platform.parts().unwrap();
-    writeln!(parts.console, "DemoOS starting at EL{}...", current_el()).unwrap();
-    let mut console = console::init(parts.console);
-    logger::init(console.shared(), LOG_LEVEL).unwrap();
-    info!("FDT address: {fdt_address:?}");
+    let fdt_address = PlatformImpl::fdt_address(x0);
+    // Records logged before the console is set up are written with `console::early_write`, or
+    // queued until then if there is nowhere to write them.
+    logger::init_early(LOG_LEVEL, LOG_FORMAT).unwrap();
     // SAFETY: We trust that the FDT pointer we were given is valid, and this is the only time we
     // use it.
     let fdt = unsafe { Fdt::from_raw(fdt_address).unwrap() };
+    // SAFETY: We only call `PlatformImpl::create` here, once on boot.
+    let mut platform = unsafe { PlatformImpl::create(&fdt) };
+    console::early_write(format_args!("DemoOS starting at EL{}...\n", current_el()));
+    let parts = platform.parts().unwrap();
+    let mut console = console::init(parts.console);
+    logger::set_console(console.shared());
+    check_el();
+    info!("FDT address: {fdt_address:?}");
     info!("FDT size: {} bytes", fdt.data().len());
     debug!("FDT: {fdt}");
     for reserved in fdt.memory_reservations() {
@@ -82,13 +128,14 @@ fn main(x0: u64, _x1: u64, _x2: u64, _x3: u64) -> ! {
     FDT.call_once(|| fdt);
 
     // Give the allocator some memory to allocate.
-    add_to_heap(
-        HEAP_ALLOCATOR.lock().deref_mut(),
-        SpinMutexGuard::leak(HEAP.try_lock().unwrap()).as_mut_slice(),
-    );
+    add_to_main_heap(SpinMutexGuard::leak(HEAP.try_lock().unwrap()).as_mut_slice());
+    dma::init(SpinMutexGuard::leak(DMA_POOL_MEMORY.try_lock().unwrap()));
+    if PlatformImpl::bounce_buffers() {
+        dma::init_bounce_pool(SpinMutexGuard::leak(BOUNCE_POOL_MEMORY.try_lock().unwrap()));
+    }
 
     info!("Initialising page table...");
-    let mut page_allocator = Heap::new();
+    let mut page_allocator = TrackedHeap::new();
     add_to_heap(
         &mut page_allocator,
         SpinMutexGuard::leak(PAGE_HEAP.try_lock().unwrap()).as_mut_slice(),
@@ -96,6 +143,7 @@ fn main(x0: u64, _x1: u64, _x2: u64, _x3: u64) -> ! {
     let mut idmap = IdMap::new(page_allocator);
     info!("IdMap size is {} GiB", idmap.size() / (1024 * 1024 * 1024));
     map_fdt_regions(&fdt, &mut idmap);
+    user::map_user_regions(&mut idmap);
 
     let pci_roots_info = find_pci_roots(&fdt, idmap.size());
     for pci_root in &pci_roots_info {
@@ -110,19 +158,55 @@ fn main(x0: u64, _x1: u64, _x2: u64, _x3: u64) -> ! {
     unsafe {
         idmap.activate();
     }
-    PAGETABLE.call_once(|| idmap);
+    // Nothing else is mapped once the page table is active, so the page table pages which haven't
+    // been used yet can go to the heap instead.
+    let reclaimed = idmap.reclaim_page_allocator(|block, size| {
+        // SAFETY: The block is part of `PAGE_HEAP`, which was leaked to the page allocator, and the
+        // page allocator will never use it again.
+        let block = unsafe { slice::from_raw_parts_mut(block.as_ptr(), size) };
+        add_to_main_heap(block);
+    });
+    RECLAIMED_BOOT_MEMORY.store(reclaimed, Ordering::Relaxed);
+    info!("Reclaimed {reclaimed} bytes of unused page table memory for the heap");
+    PAGETABLE.call_once(|| SpinMutex::new(idmap));
 
     info!("Initialising GIC...");
     // SAFETY: We trust that the FDT is accurate, and we've already mapped things and activated the
     // pagetable.
     unsafe {
-        init_gic(&fdt);
+        init_gic(&fdt, PlatformImpl::setup_gic);
+    }
+    // SAFETY: `map_fdt_regions` mapped any PL061 GPIO controller, and nothing else uses it.
+    unsafe {
+        init_power_button(&fdt);
     }
 
-    let mut devices = Devices::new(parts.rtc);
+    let mut devices = Devices::new(Box::new(parts.rtc), Box::new(NoEnergyMeter));
+    let rtc_driver = devices.rtc.name();
+    devices
+        .register(
+            DeviceKind::Uart,
+            PlatformImpl::console_driver(),
+            format!("MMIO {:#x}", PlatformImpl::console_address()),
+            "Primary console".to_string(),
+        )
+        .unwrap();
+    devices
+        .register(
+            DeviceKind::Rtc,
+            rtc_driver,
+            format!("MMIO {:#x}", PlatformImpl::rtc_address()),
+            "Real-time clock".to_string(),
+        )
+        .unwrap();
     // SAFETY: We only call this once, and we trust that the FDT is correct and the platform has
     // mapped all MMIO regions appropriately.
     unsafe { find_virtio_mmio_devices(&fdt, &mut devices) };
+    #[cfg(all(feature = "drivers", feature = "block"))]
+    // SAFETY: As above, and `map_fdt_regions` mapped the SD host controllers.
+    unsafe {
+        find_sdhci_mmio_devices(&fdt, &mut devices)
+    };
 
     let mut pci_roots = pci_roots_info
         .into_iter()
@@ -130,18 +214,19 @@ fn main(x0: u64, _x1: u64, _x2: u64, _x3: u64) -> ! {
         .map(|pci_root_info| unsafe { pci_root_info.init_pci() })
         .collect::<Vec<_>>();
 
-    for pci_root in &mut pci_roots {
-        find_virtio_pci_devices(pci_root, &mut devices);
+    for (root_index, pci_root) in pci_roots.iter_mut().enumerate() {
+        find_pci_devices(root_index, pci_root, &mut devices);
     }
 
-    shell::main(&mut console, &mut pci_roots, &mut devices, &fdt);
-
-    info!("Powering off.");
-    power_off();
+    if cfg!(feature = "target-tests") {
+        target_tests::main(&mut console, &mut pci_roots, &fdt);
+    }
+    let status = shell::main(&mut console, &mut pci_roots, &mut devices, &fdt);
+    PlatformImpl::exit(status);
 }
 
 /// Adds the given memory range to the given heap.
-fn add_to_heap<const ORDER: usize>(heap: &mut Heap<ORDER>, range: &'static mut [u8]) {
+fn add_to_heap<const ORDER: usize>(heap: &mut TrackedHeap<ORDER>, range: &'static mut [u8]) {
     // SAFETY: The range we pass is valid because it comes from a mutable static reference, which it
     // effectively takes ownership of.
     unsafe {
@@ -149,6 +234,17 @@ fn add_to_heap<const ORDER: usize>(heap: &mut Heap<ORDER>, range: &'static mut [
     }
 }
 
+/// Adds the given memory range to the global heap allocator.
+fn add_to_main_heap(range: &'static mut [u8]) {
+    // SAFETY: The range we pass is valid because it comes from a mutable static reference, which it
+    // effectively takes ownership of.
+    unsafe {
+        HEAP_ALLOCATOR
+            .lock()
+            .init(range.as_mut_ptr() as usize, range.len());
+    }
+}
+
 /// Maps memory and device regions from the FDT.
 fn map_fdt_regions(fdt: &Fdt, idmap: &mut IdMap) {
     // Map memory.
@@ -175,16 +271,20 @@ fn map_fdt_node_regions(node: &FdtNode, idmap: &mut IdMap) {
         &[
             PCI_COMPATIBLE,
             PCIE_COMPATIBLE,
+            "arm,cortex-a9-gic",
+            "arm,cortex-a15-gic",
+            "arm,gic-400",
             "arm,gic-v3",
             "arm,gic-v3-its",
             "arm,pl011",
             "arm,pl031",
             "arm,pl061",
             "arm,primecell",
-            "ns16550a",
             "virtio,mmio",
         ],
-    ) {
+    ) || is_ns16550(node)
+        || is_sdhci(node)
+    {
         for fdt_region in node.reg().unwrap().unwrap() {
             let region = fdt_to_pagetable_region(&fdt_region);
             info!(
@@ -209,46 +309,33 @@ fn map_fdt_node_regions(node: &FdtNode, idmap: &mut IdMap) {
     }
 }
 
-fn fdt_to_pagetable_region(region: &Reg) -> MemoryRegion {
-    let address = region.address::<u64>().unwrap();
-    let size = region.size::<u64>().unwrap();
-    MemoryRegion::new(address as _, (address + size) as usize)
+/// Returns whether the given FDT node is an SD host controller which we have a driver for.
+#[cfg(all(feature = "drivers", feature = "block"))]
+fn is_sdhci(node: &FdtNode) -> bool {
+    is_compatible(node, SDHCI_COMPATIBLE)
 }
 
-fn is_compatible(node: &FdtNode, with: &[&str]) -> bool {
-    if let Some(mut compatible) = node.compatible() {
-        compatible.any(|c| with.contains(&c))
-    } else {
-        false
-    }
+/// Returns whether the given FDT node is an SD host controller which we have a driver for.
+#[cfg(not(all(feature = "drivers", feature = "block")))]
+fn is_sdhci(_node: &FdtNode) -> bool {
+    false
 }
 
-/// Powers off the system via PSCI.
-fn power_off() -> ! {
-    let result = if smc_for_psci() {
-        system_off::<Smc>()
-    } else {
-        system_off::<Hvc>()
-    };
-    if let Err(e) = result {
-        error!("PSCI_SYSTEM_OFF failed: {e}");
-    } else {
-        error!("PSCI_SYSTEM_OFF returned unexpectedly");
+/// Returns how much of the heap is currently allocated.
+fn heap_usage() -> MemoryUsage {
+    let stats = heap_stats();
+    MemoryUsage {
+        total_bytes: stats.total_bytes,
+        used_bytes: stats.used_bytes,
     }
-    #[allow(clippy::empty_loop)]
-    loop {}
 }
 
-/// Returns whether to use SMC calls for PSCI rather than HVCs.
-fn smc_for_psci() -> bool {
-    let Some(fdt) = FDT.get() else {
-        return false;
-    };
-    let Some(psci_node) = fdt.find_node("/psci") else {
-        return false;
-    };
-    let Some(method) = psci_node.property("method") else {
-        return false;
-    };
-    method.value() == b"smc\0"
+/// Returns the current and peak usage of the heap.
+fn heap_stats() -> HeapStats {
+    HEAP_ALLOCATOR.lock().stats()
+}
+
+/// Returns the number of bytes of boot-time memory which were given to the heap after boot.
+fn reclaimed_boot_memory() -> usize {
+    RECLAIMED_BOOT_MEMORY.load(Ordering::Relaxed)
 }
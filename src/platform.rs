@@ -2,13 +2,27 @@
 // This project is dual-licensed under Apache 2.0 and MIT terms.
 // See LICENSE-APACHE and LICENSE-MIT for details.
 
+#[cfg(platform = "auto")]
+mod auto;
 mod crosvm;
 mod qemu;
+mod uart;
 
-use arm_gic::{IntId, gicv3::GicV3};
+use arm_gic::{IntId, Trigger};
+#[cfg(platform = "auto")]
+pub use auto::Auto as PlatformImpl;
 #[cfg(platform = "crosvm")]
 pub use crosvm::Crosvm as PlatformImpl;
+use dtoolkit::fdt::{Fdt, FdtNode};
 use embedded_io::{Read, ReadReady, Write, WriteReady};
+use log::{info, warn};
+use osdemo_core::{
+    fdt::{MmioDevice, find_matching_node, is_compatible, stdout_node},
+    interrupts::Gic,
+    psci::SuspendState,
+    rtc::RtcDevice,
+    uart16550_config::Uart16550Config,
+};
 #[cfg(platform = "qemu")]
 pub use qemu::Qemu as PlatformImpl;
 
@@ -17,10 +31,32 @@ pub type ConsoleImpl = <PlatformImpl as Platform>::Console;
 /// Platform-specific code.
 pub trait Platform {
     type Console: Read + ReadReady + Send + Write + WriteReady;
-    type Rtc;
+    type Rtc: RtcDevice + Send + 'static;
 
-    /// The IRQ used by the RTC.
-    const RTC_IRQ: IntId;
+    /// Returns the IRQ used by the RTC.
+    fn rtc_irq() -> IntId;
+
+    /// Returns the name of the driver for the primary console UART.
+    fn console_driver() -> &'static str;
+    /// Returns the physical address of the primary console UART.
+    fn console_address() -> usize;
+    /// Returns the physical address of the RTC.
+    fn rtc_address() -> usize;
+    /// Returns the PSCI CPU_SUSPEND power states which the platform supports, shallowest first.
+    fn suspend_states() -> &'static [SuspendState];
+    /// Returns whether the host can only access memory which has been shared with it, so VirtIO
+    /// devices must be given copies of buffers in the pre-shared bounce buffer pool.
+    fn bounce_buffers() -> bool;
+    /// Returns a function to write a byte directly to the console UART, if the platform has one
+    /// which can be used before the console driver is set up.
+    fn early_putchar() -> Option<fn(u8)> {
+        None
+    }
+
+    /// Returns the address of the FDT, given the value of `x0` on entry.
+    fn fdt_address(x0: u64) -> *const u8 {
+        x0 as _
+    }
 
     /// Creates an instance of the platform.
     ///
@@ -28,7 +64,7 @@ pub trait Platform {
     ///
     /// This method must only be called once. Calling it multiple times would result in unsound
     /// mutable aliasing.
-    unsafe fn create() -> Self;
+    unsafe fn create(fdt: &Fdt) -> Self;
 
     /// Returns the drivers provided by the platform.
     ///
@@ -36,7 +72,10 @@ pub trait Platform {
     /// calls.
     fn parts(&mut self) -> Option<PlatformParts<Self::Console, Self::Rtc>>;
 
-    fn setup_gic(_gic: &mut GicV3) {}
+    fn setup_gic(_gic: &mut Gic) {}
+
+    /// Stops the VM, reporting the given exit status to the host if the platform has a way to.
+    fn exit(code: u32) -> !;
 }
 
 /// The drivers provided by each platform.
@@ -46,3 +85,91 @@ pub struct PlatformParts<Console, Rtc> {
     /// The real-time clock.
     pub rtc: Rtc,
 }
+
+/// The MMIO address and IRQ of one of the platform's devices.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DeviceLocation {
+    pub address: usize,
+    pub irq: IntId,
+    pub trigger: Trigger,
+}
+
+impl DeviceLocation {
+    /// Returns the location of the device which the given node describes, falling back to `self`
+    /// for anything which it doesn't describe.
+    fn or_node(self, name: &str, node: Option<FdtNode>) -> Self {
+        let Some(device) = node.as_ref().and_then(MmioDevice::from_node) else {
+            warn!(
+                "No {name} found in FDT, using default address {:#x}",
+                self.address
+            );
+            return self;
+        };
+        let (irq, trigger) = device.interrupt.unwrap_or((self.irq, self.trigger));
+        info!(
+            "Found {name} at {:#x} with IRQ {irq:?} in FDT",
+            device.address
+        );
+        Self {
+            address: device.address as usize,
+            irq,
+            trigger,
+        }
+    }
+}
+
+/// Where the platform's console UART and RTC are.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DeviceLocations {
+    pub console: DeviceLocation,
+    pub rtc: DeviceLocation,
+}
+
+impl DeviceLocations {
+    /// Finds the console UART and RTC in the FDT, falling back to `self` for any which the FDT
+    /// doesn't describe.
+    ///
+    /// The console is found with `console_node`. This doesn't allocate, so can be used before the
+    /// heap is set up.
+    pub fn discover(
+        self,
+        fdt: &Fdt,
+        is_console: impl Fn(&FdtNode) -> bool,
+        is_rtc: impl Fn(&FdtNode) -> bool,
+    ) -> Self {
+        let console = console_node(fdt, &is_console);
+        let rtc = find_matching_node(fdt.root(), &is_rtc);
+        Self {
+            console: self.console.or_node("console UART", console),
+            rtc: self.rtc.or_node("RTC", rtc),
+        }
+    }
+}
+
+/// Returns the node of the console UART, which is the node which `stdout-path` refers to if it is
+/// a UART which the platform supports, or otherwise the first such UART.
+fn console_node<'a>(fdt: &Fdt<'a>, is_console: &impl Fn(&FdtNode) -> bool) -> Option<FdtNode<'a>> {
+    stdout_node(fdt)
+        .filter(|node| is_console(node))
+        .or_else(|| find_matching_node(fdt.root(), is_console))
+}
+
+/// Returns whether the given FDT node is a 16550-compatible UART.
+pub fn is_ns16550(node: &FdtNode) -> bool {
+    is_compatible(node, &["ns16550a", "ns16550"])
+}
+
+/// Reads the register layout and line settings of the UART which the given node describes,
+/// falling back to the defaults if there is no node or its layout isn't supported.
+fn ns16550_config(node: Option<FdtNode>) -> Uart16550Config {
+    let Some(node) = node else {
+        return Uart16550Config::DEFAULT;
+    };
+    Uart16550Config::from_node(&node).unwrap_or_else(|| {
+        warn!(
+            "Unsupported reg-io-width for {}, using byte access",
+            node.name()
+        );
+        Uart16550Config::DEFAULT
+    })
+}
@@ -0,0 +1,159 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A platform which is detected at runtime from the FDT, so that a single image can boot on both
+//! crosvm and QEMU.
+//!
+//! The image is linked at crosvm's load address, so on QEMU it must be loaded as an ELF file with
+//! at least 2 GiB of RAM, so that the RAM covers the image. QEMU doesn't pass the FDT address to
+//! ELF images, but puts the FDT at the start of RAM instead.
+
+use super::{Platform, PlatformParts, crosvm::Crosvm, is_ns16550, qemu::Qemu, uart::ConsoleUart};
+use aarch64_rt::InitialPagetable;
+use arm_gic::IntId;
+use arm_pl031::Rtc;
+use dtoolkit::fdt::Fdt;
+use osdemo_core::{
+    fdt::{find_matching_node, has_compatible_node},
+    interrupts::Gic,
+    pagetable::{EL1_DEVICE_ATTRIBUTES, EL1_MEMORY_ATTRIBUTES},
+    psci::SuspendState,
+};
+use spin::Once;
+
+/// The address at which QEMU puts the FDT for images which it doesn't boot as Linux.
+const QEMU_FDT_ADDRESS: usize = 0x4000_0000;
+
+/// The platform which was detected, once `Auto::create` has been called.
+static DETECTED: Once<Detected> = Once::new();
+
+/// The platforms which can be detected.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Detected {
+    Crosvm,
+    Qemu,
+}
+
+impl Detected {
+    /// Works out which platform we are running on from the UART in the FDT.
+    fn from_fdt(fdt: &Fdt) -> Option<Self> {
+        let root = fdt.root();
+        if has_compatible_node(&root, &["arm,pl011"]) {
+            Some(Self::Qemu)
+        } else if find_matching_node(root, &is_ns16550).is_some() {
+            Some(Self::Crosvm)
+        } else {
+            None
+        }
+    }
+}
+
+/// Calls the given `Platform` method of the detected platform.
+macro_rules! dispatch {
+    ($method:ident($($arg:expr),*)) => {
+        match DETECTED.get().expect("Platform not detected yet") {
+            Detected::Crosvm => Crosvm::$method($($arg),*),
+            Detected::Qemu => Qemu::$method($($arg),*),
+        }
+    };
+}
+
+/// Either of the supported platforms, detected at runtime.
+pub enum Auto {
+    Crosvm(Crosvm),
+    Qemu(Qemu),
+}
+
+impl Auto {
+    /// Returns the initial hard-coded page table to use before the Rust code starts.
+    ///
+    /// This covers the devices and RAM of both platforms. The second GiB is RAM on QEMU, and must
+    /// be mapped as normal memory so that the FDT can be read from it, but nothing there is
+    /// accessed before the main page table is activated on crosvm.
+    pub const fn initial_idmap() -> InitialPagetable {
+        let mut idmap = [0; 512];
+        idmap[0] = EL1_DEVICE_ATTRIBUTES.bits();
+        idmap[1] = EL1_MEMORY_ATTRIBUTES.bits() | 0x40000000;
+        idmap[2] = EL1_MEMORY_ATTRIBUTES.bits() | 0x80000000;
+        idmap[256] = EL1_DEVICE_ATTRIBUTES.bits() | 0x4000000000;
+        InitialPagetable(idmap)
+    }
+}
+
+impl Platform for Auto {
+    type Console = ConsoleUart;
+    type Rtc = Rtc;
+
+    fn rtc_irq() -> IntId {
+        dispatch!(rtc_irq())
+    }
+
+    fn console_driver() -> &'static str {
+        dispatch!(console_driver())
+    }
+
+    fn console_address() -> usize {
+        dispatch!(console_address())
+    }
+
+    fn rtc_address() -> usize {
+        dispatch!(rtc_address())
+    }
+
+    fn suspend_states() -> &'static [SuspendState] {
+        dispatch!(suspend_states())
+    }
+
+    fn bounce_buffers() -> bool {
+        dispatch!(bounce_buffers())
+    }
+
+    /// Returns `None` until the platform has been detected, as we don't know which UART to use.
+    fn early_putchar() -> Option<fn(u8)> {
+        match DETECTED.get()? {
+            Detected::Crosvm => Crosvm::early_putchar(),
+            Detected::Qemu => Qemu::early_putchar(),
+        }
+    }
+
+    fn fdt_address(x0: u64) -> *const u8 {
+        if x0 == 0 {
+            QEMU_FDT_ADDRESS as _
+        } else {
+            x0 as _
+        }
+    }
+
+    unsafe fn create(fdt: &Fdt) -> Self {
+        let detected = *DETECTED.call_once(|| {
+            Detected::from_fdt(fdt).expect("FDT has neither a PL011 nor an 8250 UART")
+        });
+        // SAFETY: Our caller promises that `create` is only called once, so the platform-specific
+        // `create` is only called once too.
+        unsafe {
+            match detected {
+                Detected::Crosvm => Self::Crosvm(Crosvm::create(fdt)),
+                Detected::Qemu => Self::Qemu(Qemu::create(fdt)),
+            }
+        }
+    }
+
+    fn parts(&mut self) -> Option<PlatformParts<ConsoleUart, Rtc>> {
+        match self {
+            Self::Crosvm(crosvm) => crosvm.parts().map(|parts| PlatformParts {
+                console: ConsoleUart::Ns16550(parts.console),
+                rtc: parts.rtc,
+            }),
+            Self::Qemu(qemu) => qemu.parts(),
+        }
+    }
+
+    fn setup_gic(gic: &mut Gic) {
+        dispatch!(setup_gic(gic))
+    }
+
+    fn exit(code: u32) -> ! {
+        dispatch!(exit(code))
+    }
+}
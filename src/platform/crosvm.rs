@@ -2,31 +2,75 @@
 // This project is dual-licensed under Apache 2.0 and MIT terms.
 // See LICENSE-APACHE and LICENSE-MIT for details.
 
-use super::{Platform, PlatformParts};
-use crate::{
-    console::Console,
-    interrupts::set_shared_irq_handler,
-    pagetable::{EL1_DEVICE_ATTRIBUTES, EL1_MEMORY_ATTRIBUTES},
+use super::{
+    DeviceLocation, DeviceLocations, Platform, PlatformParts, console_node, is_ns16550,
+    ns16550_config,
 };
+use crate::console;
 use aarch64_rt::InitialPagetable;
-use arm_gic::{IntId, Trigger, gicv3::GicV3};
+use arm_gic::{IntId, Trigger};
 use arm_pl031::Rtc;
 use core::ptr::NonNull;
-use uart_16550::{Config, Uart16550, backend::MmioBackend};
+use dtoolkit::fdt::{Fdt, FdtNode};
+use log::info;
+use osdemo_core::{
+    drivers::uart16550::{Uart16550, write_byte_polled},
+    fdt::is_compatible,
+    interrupts::{Gic, set_shared_irq_handler},
+    pagetable::{EL1_DEVICE_ATTRIBUTES, EL1_MEMORY_ATTRIBUTES},
+    psci::{SuspendState, power_off, reset},
+    uart16550_config::Uart16550Config,
+};
+use spin::Once;
+
+/// The AMBA peripheral ID of the PL030 RTC.
+const PL030_PERIPHERAL_ID: u32 = 0x0004_1030;
+
+/// Where the console UART and RTC are if the FDT doesn't say.
+const DEFAULT_DEVICES: DeviceLocations = DeviceLocations {
+    console: DeviceLocation {
+        address: 0x03f8,
+        irq: IntId::spi(0),
+        trigger: Trigger::Edge,
+    },
+    rtc: DeviceLocation {
+        address: 0x2000,
+        irq: IntId::spi(1),
+        trigger: Trigger::Level,
+    },
+};
+
+/// Where the console UART and RTC are, once `Crosvm::create` has found them in the FDT.
+static DEVICES: Once<DeviceLocations> = Once::new();
+
+/// The register layout and line settings of the console UART, once `Crosvm::create` has read
+/// them from the FDT.
+static UART_CONFIG: Once<Uart16550Config> = Once::new();
 
-/// Base address of the first 8250 UART.
-const UART_BASE_ADDRESS: NonNull<u8> = NonNull::new(0x03f8 as _).unwrap();
+/// Returns where the console UART and RTC are.
+fn devices() -> &'static DeviceLocations {
+    DEVICES.get().unwrap_or(&DEFAULT_DEVICES)
+}
+
+/// Returns the register layout and line settings of the console UART.
+fn uart_config() -> &'static Uart16550Config {
+    UART_CONFIG.get().unwrap_or(&Uart16550Config::DEFAULT)
+}
 
-/// Base address of the PL030 RTC.
-const PL030_BASE_ADDRESS: *mut u32 = 0x2000 as _;
+/// Returns whether the given FDT node is a PL030 RTC. crosvm describes it only as a generic
+/// PrimeCell, with its peripheral ID.
+fn is_pl030(node: &FdtNode) -> bool {
+    let peripheral_id = node.property("arm,primecell-periphid");
+    is_compatible(node, &["arm,pl030"])
+        || (is_compatible(node, &["arm,primecell"])
+            && peripheral_id.is_some_and(|id| id.value() == PL030_PERIPHERAL_ID.to_be_bytes()))
+}
 
 pub struct Crosvm {
-    parts: Option<PlatformParts<Uart16550<MmioBackend>, Rtc>>,
+    parts: Option<PlatformParts<Uart16550, Rtc>>,
 }
 
 impl Crosvm {
-    const CONSOLE_IRQ: IntId = IntId::spi(0);
-
     /// Returns the initial hard-coded page table to use before the Rust code starts.
     pub const fn initial_idmap() -> InitialPagetable {
         let mut idmap = [0; 512];
@@ -40,43 +84,93 @@ impl Crosvm {
     }
 }
 
+/// Writes a byte to the 16550 UART directly, without going through the driver.
+fn early_putchar(byte: u8) {
+    // SAFETY: The UART is mapped by the initial page table. Only the console driver otherwise
+    // accesses it, and this is only used before the driver is set up or once it is no longer used.
+    unsafe { write_byte_polled(devices().console.address, uart_config(), byte) }
+}
+
 impl Platform for Crosvm {
-    type Console = Uart16550<MmioBackend>;
+    type Console = Uart16550;
     type Rtc = Rtc;
 
-    const RTC_IRQ: IntId = IntId::spi(1);
+    fn rtc_irq() -> IntId {
+        devices().rtc.irq
+    }
 
-    unsafe fn create() -> Self {
-        // SAFETY: There is a suitable UART at this base address on crosvm, and we have mapped it
-        // with an appropriate device mapping. `create` is only called once so there are no aliases.
-        let mut uart = unsafe { Uart16550::new_mmio(UART_BASE_ADDRESS, 1) }.unwrap();
-        // Enables the RBR data available interrupt.
-        uart.init(Config::default()).unwrap();
+    fn console_driver() -> &'static str {
+        "ns16550"
+    }
+
+    fn console_address() -> usize {
+        devices().console.address
+    }
+
+    fn rtc_address() -> usize {
+        devices().rtc.address
+    }
+
+    fn suspend_states() -> &'static [SuspendState] {
+        &[SuspendState {
+            name: "standby",
+            power_state: 0,
+            description: "core standby; KVM implements this as WFI, with no loss of context",
+        }]
+    }
+
+    fn bounce_buffers() -> bool {
+        false
+    }
+
+    fn early_putchar() -> Option<fn(u8)> {
+        Some(early_putchar)
+    }
+
+    unsafe fn create(fdt: &Fdt) -> Self {
+        let devices = DEVICES.call_once(|| DEFAULT_DEVICES.discover(fdt, is_ns16550, is_pl030));
+        let config = *UART_CONFIG.call_once(|| ns16550_config(console_node(fdt, &is_ns16550)));
+        let uart_address = NonNull::new(devices.console.address as *mut u8).unwrap();
+        // SAFETY: We trust the FDT that there is a suitable UART at this address with this layout,
+        // and it is in the device region mapped by the initial page table. `create` is only called
+        // once so there are no aliases.
+        let mut uart = unsafe { Uart16550::new(uart_address, config) };
+        // Enables the received data available interrupt.
+        uart.init();
         Self {
-            // SAFETY: The various base addresses are valid and mapped, and `create` is only called
-            // once so there are no aliases.
+            // SAFETY: We trust the FDT that there is a PL030 at this address, and it is mapped.
+            // `create` is only called once so there are no aliases.
             parts: Some(unsafe {
                 PlatformParts {
                     console: uart,
-                    rtc: Rtc::new(PL030_BASE_ADDRESS),
+                    rtc: Rtc::new(devices.rtc.address as _),
                 }
             }),
         }
     }
 
-    fn parts(&mut self) -> Option<PlatformParts<Uart16550<MmioBackend>, Rtc>> {
+    fn parts(&mut self) -> Option<PlatformParts<Uart16550, Rtc>> {
         self.parts.take()
     }
 
-    fn setup_gic(gic: &mut GicV3) {
-        gic.set_interrupt_priority(Self::CONSOLE_IRQ, None, 0x10)
-            .unwrap();
-        gic.set_trigger(Self::CONSOLE_IRQ, None, Trigger::Edge)
-            .unwrap();
-        gic.enable_interrupt(Self::CONSOLE_IRQ, None, true).unwrap();
-        set_shared_irq_handler(
-            Self::CONSOLE_IRQ,
-            &Console::<Uart16550<MmioBackend>>::handle_irq,
-        );
+    fn setup_gic(gic: &mut Gic) {
+        let uart = devices().console;
+        gic.set_interrupt_priority(uart.irq, None, 0x10).unwrap();
+        gic.set_trigger(uart.irq, None, uart.trigger).unwrap();
+        gic.enable_interrupt(uart.irq, None, true).unwrap();
+        set_shared_irq_handler(uart.irq, &console::handle_irq);
+    }
+
+    /// crosvm doesn't support semihosting, so a non-zero status is reported by resetting rather
+    /// than powering off. With `--extended-status`, `crosvm run` then exits with status 32 rather
+    /// than 0, though the status itself is lost.
+    fn exit(code: u32) -> ! {
+        if code == 0 {
+            info!("Powering off.");
+            power_off();
+        } else {
+            info!("Resetting to report exit status {code}.");
+            reset();
+        }
     }
 }
@@ -2,32 +2,88 @@
 // This project is dual-licensed under Apache 2.0 and MIT terms.
 // See LICENSE-APACHE and LICENSE-MIT for details.
 
-use super::{Platform, PlatformParts};
-use crate::{
-    console::Console,
-    interrupts::set_shared_irq_handler,
-    pagetable::{EL1_DEVICE_ATTRIBUTES, EL1_MEMORY_ATTRIBUTES},
+use super::{
+    DeviceLocation, DeviceLocations, Platform, PlatformParts, console_node, is_ns16550,
+    ns16550_config, uart::ConsoleUart,
 };
+use crate::console;
 use aarch64_rt::InitialPagetable;
-use arm_gic::{IntId, Trigger, gicv3::GicV3};
+use arm_gic::{IntId, Trigger};
 use arm_pl011_uart::{Interrupts, PL011Registers, Uart, UniqueMmioPointer};
 use arm_pl031::Rtc;
-use core::ptr::NonNull;
+use core::{hint::spin_loop, ptr::NonNull};
+use dtoolkit::fdt::{Fdt, FdtNode};
+use log::{info, warn};
+use osdemo_core::{
+    drivers::uart16550::{Uart16550, write_byte_polled},
+    fdt::is_compatible,
+    interrupts::{Gic, set_shared_irq_handler},
+    pagetable::{EL1_DEVICE_ATTRIBUTES, EL1_MEMORY_ATTRIBUTES},
+    psci::{SuspendState, power_off},
+    semihosting,
+    uart16550_config::Uart16550Config,
+};
+use spin::Once;
+
+/// The offset of the PL011 data register, in 32-bit words.
+const UARTDR: usize = 0;
+
+/// The offset of the PL011 flag register, in 32-bit words.
+const UARTFR: usize = 6;
+
+/// The transmit FIFO full bit of the PL011 flag register.
+const UARTFR_TXFF: u32 = 1 << 5;
+
+/// Where the console UART and RTC are if the FDT doesn't say.
+const DEFAULT_DEVICES: DeviceLocations = DeviceLocations {
+    console: DeviceLocation {
+        address: 0x900_0000,
+        irq: IntId::spi(1),
+        trigger: Trigger::Level,
+    },
+    rtc: DeviceLocation {
+        address: 0x901_0000,
+        irq: IntId::spi(2),
+        trigger: Trigger::Level,
+    },
+};
+
+/// Where the console UART and RTC are, once `Qemu::create` has found them in the FDT.
+static DEVICES: Once<DeviceLocations> = Once::new();
+
+/// The kind of the console UART, once `Qemu::create` has found it in the FDT.
+static CONSOLE_KIND: Once<ConsoleKind> = Once::new();
 
-/// Base address of the first PL011 UART.
-const UART_BASE_ADDRESS: *mut PL011Registers = 0x900_0000 as _;
+/// The kinds of console UART which are supported.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ConsoleKind {
+    /// The PL011 which the virt machine has.
+    Pl011,
+    /// A 16550-compatible UART with the given layout, as other machines and boards may have.
+    Ns16550(Uart16550Config),
+}
+
+/// Returns where the console UART and RTC are.
+fn devices() -> &'static DeviceLocations {
+    DEVICES.get().unwrap_or(&DEFAULT_DEVICES)
+}
+
+/// Returns the kind of the console UART.
+fn console_kind() -> ConsoleKind {
+    CONSOLE_KIND.get().copied().unwrap_or(ConsoleKind::Pl011)
+}
 
-/// Base address of the PL031 RTC.
-const PL031_BASE_ADDRESS: *mut u32 = 0x901_0000 as _;
+/// Returns whether the given FDT node is a UART which can be used as the console.
+fn is_console(node: &FdtNode) -> bool {
+    is_compatible(node, &["arm,pl011"]) || is_ns16550(node)
+}
 
 /// The QEMU aarch64 virt platform.
 pub struct Qemu {
-    parts: Option<PlatformParts<Uart<'static>, Rtc>>,
+    parts: Option<PlatformParts<ConsoleUart, Rtc>>,
 }
 
 impl Qemu {
-    const CONSOLE_IRQ: IntId = IntId::spi(1);
-
     /// Returns the initial hard-coded page table to use before the Rust code starts.
     pub const fn initial_idmap() -> InitialPagetable {
         let mut idmap = [0; 512];
@@ -38,41 +94,133 @@ impl Qemu {
     }
 }
 
+/// Writes a byte to the console UART directly, without going through the driver.
+fn early_putchar(byte: u8) {
+    if let ConsoleKind::Ns16550(config) = console_kind() {
+        // SAFETY: The UART is mapped by the initial page table. Only the console driver otherwise
+        // accesses it, and this is only used before the driver is set up or once it is no longer
+        // used.
+        unsafe { write_byte_polled(devices().console.address, &config, byte) };
+        return;
+    }
+    let registers = devices().console.address as *mut u32;
+    // SAFETY: The UART is mapped by the initial page table, and reading its flag register and
+    // writing its data register don't affect anything else. Only the console driver otherwise
+    // accesses it, and this is only used before the driver is set up or once it is no longer used.
+    unsafe {
+        while registers.add(UARTFR).read_volatile() & UARTFR_TXFF != 0 {
+            spin_loop();
+        }
+        registers.add(UARTDR).write_volatile(byte.into());
+    }
+}
+
 impl Platform for Qemu {
-    type Console = Uart<'static>;
+    type Console = ConsoleUart;
     type Rtc = Rtc;
 
-    const RTC_IRQ: IntId = IntId::spi(2);
+    fn rtc_irq() -> IntId {
+        devices().rtc.irq
+    }
+
+    fn console_driver() -> &'static str {
+        match console_kind() {
+            ConsoleKind::Pl011 => "pl011",
+            ConsoleKind::Ns16550(_) => "ns16550",
+        }
+    }
+
+    fn console_address() -> usize {
+        devices().console.address
+    }
+
+    fn rtc_address() -> usize {
+        devices().rtc.address
+    }
+
+    fn suspend_states() -> &'static [SuspendState] {
+        &[SuspendState {
+            name: "standby",
+            power_state: 0,
+            description: "core standby; QEMU implements every CPU_SUSPEND state as WFI",
+        }]
+    }
+
+    fn bounce_buffers() -> bool {
+        false
+    }
+
+    fn early_putchar() -> Option<fn(u8)> {
+        Some(early_putchar)
+    }
 
-    unsafe fn create() -> Self {
-        let mut uart = Uart::new(
-            // SAFETY: UART_BASE_ADDRESS is valid and mapped, and `create` is only called once so
-            // there are no aliases
-            unsafe { UniqueMmioPointer::new(NonNull::new(UART_BASE_ADDRESS).unwrap()) },
-        );
-        uart.set_interrupt_masks(Interrupts::RXI);
+    unsafe fn create(fdt: &Fdt) -> Self {
+        let devices = DEVICES.call_once(|| {
+            DEFAULT_DEVICES.discover(fdt, is_console, |node| is_compatible(node, &["arm,pl031"]))
+        });
+        let kind = *CONSOLE_KIND.call_once(|| match console_node(fdt, &is_console) {
+            Some(node) if is_ns16550(&node) => ConsoleKind::Ns16550(ns16550_config(Some(node))),
+            _ => ConsoleKind::Pl011,
+        });
+        let uart = match kind {
+            ConsoleKind::Pl011 => {
+                let uart_address = devices.console.address as *mut PL011Registers;
+                let mut uart = Uart::new(
+                    // SAFETY: We trust the FDT that there is a PL011 at this address, and it is in
+                    // the device region mapped by the initial page table. `create` is only called
+                    // once so there are no aliases.
+                    unsafe { UniqueMmioPointer::new(NonNull::new(uart_address).unwrap()) },
+                );
+                uart.set_interrupt_masks(Interrupts::RXI);
+                ConsoleUart::Pl011(uart)
+            }
+            ConsoleKind::Ns16550(config) => {
+                let uart_address = NonNull::new(devices.console.address as *mut u8).unwrap();
+                // SAFETY: We trust the FDT that there is a suitable UART at this address with this
+                // layout, and that it is in the device region mapped by the initial page table.
+                // `create` is only called once so there are no aliases.
+                let mut uart = unsafe { Uart16550::new(uart_address, config) };
+                uart.init();
+                ConsoleUart::Ns16550(uart)
+            }
+        };
         Self {
-            // SAFETY: The various base addresses are valid and mapped, and `create` is only called
-            // once so there are no aliases.
+            // SAFETY: We trust the FDT that there is a PL031 at this address, and it is mapped.
+            // `create` is only called once so there are no aliases.
             parts: Some(unsafe {
                 PlatformParts {
                     console: uart,
-                    rtc: Rtc::new(PL031_BASE_ADDRESS),
+                    rtc: Rtc::new(devices.rtc.address as _),
                 }
             }),
         }
     }
 
-    fn parts(&mut self) -> Option<PlatformParts<Uart<'static>, Rtc>> {
+    fn parts(&mut self) -> Option<PlatformParts<ConsoleUart, Rtc>> {
         self.parts.take()
     }
 
-    fn setup_gic(gic: &mut GicV3) {
-        gic.set_interrupt_priority(Self::CONSOLE_IRQ, None, 0x10)
-            .unwrap();
-        gic.set_trigger(Self::CONSOLE_IRQ, None, Trigger::Level)
-            .unwrap();
-        gic.enable_interrupt(Self::CONSOLE_IRQ, None, true).unwrap();
-        set_shared_irq_handler(Self::CONSOLE_IRQ, &Console::<Uart>::handle_irq);
+    fn setup_gic(gic: &mut Gic) {
+        let uart = devices().console;
+        gic.set_interrupt_priority(uart.irq, None, 0x10).unwrap();
+        gic.set_trigger(uart.irq, None, uart.trigger).unwrap();
+        gic.enable_interrupt(uart.irq, None, true).unwrap();
+        set_shared_irq_handler(uart.irq, &console::handle_irq);
+    }
+
+    fn exit(code: u32) -> ! {
+        if semihosting::enabled() {
+            info!("Exiting with status {code}.");
+            semihosting::exit(code);
+        }
+        if code != 0 {
+            warn!(
+                "Can't report exit status {code} without semihosting; run QEMU with `-semihosting \
+                 -append {}`",
+                semihosting::BOOTARG
+            );
+        }
+        info!("Powering off.");
+        power_off();
     }
 }
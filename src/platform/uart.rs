@@ -0,0 +1,73 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A console UART which may be either of the kinds supported, for platforms where which one is
+//! used is only known at runtime.
+
+use arm_gic::IntId;
+use arm_pl011_uart::Uart;
+use embedded_io::{Error, ErrorKind, ErrorType, Read, ReadReady, Write, WriteReady};
+use osdemo_core::drivers::{InterruptDriven, uart16550::Uart16550};
+
+/// Either a 16550-compatible UART or a PL011.
+pub enum ConsoleUart {
+    Ns16550(Uart16550),
+    Pl011(Uart<'static>),
+}
+
+impl InterruptDriven for ConsoleUart {
+    fn handle_irq(&mut self, intid: IntId) {
+        match self {
+            Self::Ns16550(uart) => uart.handle_irq(intid),
+            Self::Pl011(uart) => uart.handle_irq(intid),
+        }
+    }
+}
+
+impl ErrorType for ConsoleUart {
+    type Error = ErrorKind;
+}
+
+impl Read for ConsoleUart {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ErrorKind> {
+        match self {
+            Self::Ns16550(uart) => uart.read(buf).map_err(|e| e.kind()),
+            Self::Pl011(uart) => uart.read(buf).map_err(|e| e.kind()),
+        }
+    }
+}
+
+impl ReadReady for ConsoleUart {
+    fn read_ready(&mut self) -> Result<bool, ErrorKind> {
+        match self {
+            Self::Ns16550(uart) => uart.read_ready().map_err(|e| e.kind()),
+            Self::Pl011(uart) => uart.read_ready().map_err(|e| e.kind()),
+        }
+    }
+}
+
+impl Write for ConsoleUart {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, ErrorKind> {
+        match self {
+            Self::Ns16550(uart) => uart.write(buf).map_err(|e| e.kind()),
+            Self::Pl011(uart) => uart.write(buf).map_err(|e| e.kind()),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), ErrorKind> {
+        match self {
+            Self::Ns16550(uart) => uart.flush().map_err(|e| e.kind()),
+            Self::Pl011(uart) => uart.flush().map_err(|e| e.kind()),
+        }
+    }
+}
+
+impl WriteReady for ConsoleUart {
+    fn write_ready(&mut self) -> Result<bool, ErrorKind> {
+        match self {
+            Self::Ns16550(uart) => uart.write_ready().map_err(|e| e.kind()),
+            Self::Pl011(uart) => uart.write_ready().map_err(|e| e.kind()),
+        }
+    }
+}
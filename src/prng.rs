@@ -0,0 +1,127 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A small deterministic pseudorandom number generator, so that tests using random data can be
+//! reproduced exactly from their seed.
+//!
+//! This is not suitable for anything security sensitive.
+
+use crate::endpoint::parse_number;
+
+/// A SplitMix64 pseudorandom number generator.
+///
+/// Every seed, including 0, gives a full-period sequence, and the same seed always gives the same
+/// sequence on every platform.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Prng {
+    state: u64,
+}
+
+impl Prng {
+    /// Creates a new generator with the given seed.
+    pub const fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Returns the next 64 random bits.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        mix(self.state)
+    }
+
+    /// Returns the next 32 random bits.
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Returns a random number less than `bound`, which must not be 0.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        assert_ne!(bound, 0);
+        // Reject values from the incomplete final span so that the result isn't biased.
+        let limit = u64::MAX - u64::MAX % bound;
+        loop {
+            let value = self.next_u64();
+            if value < limit {
+                return value % bound;
+            }
+        }
+    }
+
+    /// Fills the given buffer with random bytes.
+    pub fn fill_bytes(&mut self, bytes: &mut [u8]) {
+        for chunk in bytes.chunks_mut(8) {
+            chunk.copy_from_slice(&self.next_u64().to_le_bytes()[..chunk.len()]);
+        }
+    }
+}
+
+/// Scrambles the bits of the given value, so that similar values give very different results.
+///
+/// This is the SplitMix64 output function, which is also useful for turning a low-quality source
+/// of entropy such as a counter into a seed.
+pub fn mix(value: u64) -> u64 {
+    let mut z = value;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Parses a seed, which may be decimal or hexadecimal with a `0x` prefix.
+pub fn parse_seed(s: &str) -> Option<u64> {
+    parse_number(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_sequence() {
+        // Reference values from the SplitMix64 paper's implementation.
+        let mut prng = Prng::new(1234567);
+        assert_eq!(prng.next_u64(), 6457827717110365317);
+        assert_eq!(prng.next_u64(), 3203168211198807973);
+        assert_eq!(prng.next_u64(), 9817491932198370423);
+    }
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = Prng::new(42);
+        let mut b = Prng::new(42);
+        let mut c = Prng::new(43);
+        let a_values = [a.next_u64(), a.next_u64()];
+        assert_eq!(a_values, [b.next_u64(), b.next_u64()]);
+        assert_ne!(a_values, [c.next_u64(), c.next_u64()]);
+    }
+
+    #[test]
+    fn below_bound() {
+        let mut prng = Prng::new(0);
+        let mut seen = [false; 7];
+        for _ in 0..1000 {
+            let value = prng.below(7);
+            seen[value as usize] = true;
+        }
+        assert!(seen.iter().all(|&seen| seen));
+        assert_eq!(prng.below(1), 0);
+    }
+
+    #[test]
+    fn fill_bytes() {
+        let mut prng = Prng::new(7);
+        let mut bytes = [0; 11];
+        prng.fill_bytes(&mut bytes);
+        let mut expected = Prng::new(7);
+        assert_eq!(bytes[..8], expected.next_u64().to_le_bytes());
+        assert_eq!(bytes[8..], expected.next_u64().to_le_bytes()[..3]);
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(parse_seed("1234"), Some(1234));
+        assert_eq!(parse_seed("0xdeadbeef"), Some(0xdead_beef));
+        assert_eq!(parse_seed("-1"), None);
+        assert_eq!(parse_seed("seed"), None);
+    }
+}
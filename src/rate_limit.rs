@@ -0,0 +1,92 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Limiting how many records a tracer writes per second, so that a flood of events doesn't drown
+//! out everything else in the log.
+
+/// The length of each window which the limit applies to, in microseconds.
+const WINDOW_US: u64 = 1_000_000;
+
+/// Allows up to a maximum number of events in each one second window, and counts the rest.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RateLimit {
+    /// The most events allowed in each window.
+    max_per_window: u32,
+    /// The time at which the current window started, in microseconds.
+    window_start_us: u64,
+    /// The number of events allowed so far in the current window.
+    allowed: u32,
+    /// The number of events suppressed since one was last allowed.
+    suppressed: u64,
+}
+
+impl RateLimit {
+    /// Creates a limit which allows up to `max_per_second` events each second.
+    pub const fn new(max_per_second: u32) -> Self {
+        Self {
+            max_per_window: max_per_second,
+            window_start_us: 0,
+            allowed: 0,
+            suppressed: 0,
+        }
+    }
+
+    /// Returns the most events allowed each second.
+    pub fn max_per_second(&self) -> u32 {
+        self.max_per_window
+    }
+
+    /// Changes the most events allowed each second, and starts a new window at the next event.
+    pub fn set_max_per_second(&mut self, max_per_second: u32) {
+        *self = Self {
+            suppressed: self.suppressed,
+            ..Self::new(max_per_second)
+        };
+    }
+
+    /// Checks whether an event at the given time, in microseconds, is allowed.
+    ///
+    /// Returns the number of events which were suppressed before it if it is allowed, or `None` if
+    /// it should be suppressed too.
+    pub fn check(&mut self, now_us: u64) -> Option<u64> {
+        if self.allowed == 0 || now_us.saturating_sub(self.window_start_us) >= WINDOW_US {
+            self.window_start_us = now_us;
+            self.allowed = 0;
+        }
+        if self.allowed < self.max_per_window {
+            self.allowed += 1;
+            Some(core::mem::take(&mut self.suppressed))
+        } else {
+            self.suppressed += 1;
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_each_window() {
+        let mut limit = RateLimit::new(2);
+        assert_eq!(limit.check(5_000_000), Some(0));
+        assert_eq!(limit.check(5_100_000), Some(0));
+        assert_eq!(limit.check(5_200_000), None);
+        assert_eq!(limit.check(5_999_999), None);
+        assert_eq!(limit.check(6_000_000), Some(2));
+        assert_eq!(limit.check(6_000_001), Some(0));
+        assert_eq!(limit.check(6_000_002), None);
+    }
+
+    #[test]
+    fn zero_suppresses_everything() {
+        let mut limit = RateLimit::new(0);
+        assert_eq!(limit.check(0), None);
+        assert_eq!(limit.check(2_000_000), None);
+        limit.set_max_per_second(1);
+        assert_eq!(limit.max_per_second(), 1);
+        assert_eq!(limit.check(2_000_001), Some(2));
+    }
+}
@@ -0,0 +1,40 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Parsing of rc scripts: lists of shell commands which are run at boot before the interactive
+//! prompt, so that demos and soak tests can start automatically.
+
+use core::str::{self, Utf8Error};
+
+/// The name of the kernel command line argument which gives where to read the boot script from,
+/// such as `rc=blk0:0+4096`.
+pub const BOOTARG: &str = "rc";
+
+/// Returns the text of a script read from a block device range or vsock peer.
+///
+/// The script ends at the first NUL byte, if any, so that it can be written to the start of a
+/// zero-filled block device range.
+pub fn script_text(script: &[u8]) -> Result<&str, Utf8Error> {
+    let end = script
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(script.len());
+    str::from_utf8(&script[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text() {
+        assert_eq!(script_text(b"date\nuptime\n"), Ok("date\nuptime\n"));
+        assert_eq!(
+            script_text(b"date\nuptime\0\0garbage\xff"),
+            Ok("date\nuptime")
+        );
+        assert_eq!(script_text(&[0; 16]), Ok(""));
+        assert!(script_text(b"date\n\xff\n").is_err());
+    }
+}
@@ -0,0 +1,374 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! The bookkeeping for the `heap-debug` allocator, which surrounds allocations with poisoned red
+//! zones and delays reusing freed blocks, to catch buffer overruns and use after free.
+//!
+//! Each allocation is padded to a block laid out as:
+//!
+//! ```text
+//! | front red zone | user data | back red zone |
+//! ```
+//!
+//! The front red zone is at least `RED_ZONE_SIZE` bytes, and more if needed for alignment. Both
+//! red zones are filled with `RED_ZONE_POISON`, and freed blocks are filled with `FREED_POISON`
+//! while they wait in quarantine.
+
+use core::{
+    alloc::Layout,
+    fmt::{self, Display, Formatter},
+    ops::Range,
+};
+
+/// The minimum size of each red zone, in bytes.
+pub const RED_ZONE_SIZE: usize = 16;
+
+/// The value which red zones are filled with.
+pub const RED_ZONE_POISON: u8 = 0xfa;
+
+/// The value which freed blocks are filled with while in quarantine.
+pub const FREED_POISON: u8 = 0xfd;
+
+/// The layout of a padded block for an allocation.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PaddedLayout {
+    /// The layout of the whole block, to allocate from the underlying allocator.
+    pub block: Layout,
+    /// The offset of the user data from the start of the block.
+    pub offset: usize,
+    /// The size of the user data.
+    pub size: usize,
+}
+
+impl PaddedLayout {
+    /// Returns the layout of the padded block for an allocation with the given layout, or `None`
+    /// if it would be too large.
+    pub fn new(layout: Layout) -> Option<Self> {
+        let offset = RED_ZONE_SIZE.next_multiple_of(layout.align());
+        let block_size = offset
+            .checked_add(layout.size())?
+            .checked_add(RED_ZONE_SIZE)?;
+        Some(Self {
+            block: Layout::from_size_align(block_size, layout.align()).ok()?,
+            offset,
+            size: layout.size(),
+        })
+    }
+
+    /// Returns the range of the front red zone within the block.
+    pub fn front_red_zone(&self) -> Range<usize> {
+        0..self.offset
+    }
+
+    /// Returns the range of the back red zone within the block.
+    pub fn back_red_zone(&self) -> Range<usize> {
+        self.offset + self.size..self.block.size()
+    }
+
+    /// Checks that the given front and back red zones of a block are still intact.
+    ///
+    /// The red zones are passed separately so that the caller needn't borrow the user data, which
+    /// may be in use.
+    pub fn check_red_zones(&self, front: &[u8], back: &[u8]) -> Result<(), Corruption> {
+        check_filled(front, RED_ZONE_POISON)
+            .map_err(|index| self.corruption(CorruptionKind::Underrun, index))?;
+        check_filled(back, RED_ZONE_POISON).map_err(|index| {
+            self.corruption(CorruptionKind::Overrun, self.back_red_zone().start + index)
+        })
+    }
+
+    /// Checks that the whole of the given freed block still contains `FREED_POISON`.
+    pub fn check_freed(&self, block: &[u8]) -> Result<(), Corruption> {
+        check_filled(block, FREED_POISON)
+            .map_err(|index| self.corruption(CorruptionKind::UseAfterFree, index))
+    }
+
+    /// Returns a corruption of the given kind at the given offset from the start of the block.
+    fn corruption(&self, kind: CorruptionKind, index: usize) -> Corruption {
+        Corruption {
+            kind,
+            offset: index as isize - self.offset as isize,
+            size: self.size,
+        }
+    }
+}
+
+/// Returns the index of the first byte of the given slice which isn't `value`, if there is one.
+fn check_filled(bytes: &[u8], value: u8) -> Result<(), usize> {
+    match bytes.iter().position(|&byte| byte != value) {
+        Some(index) => Err(index),
+        None => Ok(()),
+    }
+}
+
+/// What kind of heap corruption was found.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CorruptionKind {
+    /// Something wrote before the start of an allocation.
+    Underrun,
+    /// Something wrote past the end of an allocation.
+    Overrun,
+    /// Something wrote to an allocation after it was freed.
+    UseAfterFree,
+}
+
+/// Heap corruption found in or around an allocation.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Corruption {
+    pub kind: CorruptionKind,
+    /// The offset of the first corrupted byte from the start of the allocation's user data. This
+    /// is negative for an underrun.
+    pub offset: isize,
+    /// The size of the allocation.
+    pub size: usize,
+}
+
+impl Display for Corruption {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            CorruptionKind::Underrun => "Buffer underrun",
+            CorruptionKind::Overrun => "Buffer overrun",
+            CorruptionKind::UseAfterFree => "Write after free",
+        };
+        write!(
+            f,
+            "{kind} at offset {} of {} byte allocation",
+            self.offset, self.size
+        )
+    }
+}
+
+/// A fixed-size table of blocks, identified by their addresses.
+#[derive(Debug)]
+pub struct BlockTable<const N: usize> {
+    entries: [Option<(usize, PaddedLayout)>; N],
+}
+
+impl<const N: usize> BlockTable<N> {
+    /// Creates an empty table.
+    pub const fn new() -> Self {
+        Self { entries: [None; N] }
+    }
+
+    /// Adds the block at the given address, returning `false` if the table is full.
+    pub fn insert(&mut self, address: usize, layout: PaddedLayout) -> bool {
+        match self.entries.iter_mut().find(|entry| entry.is_none()) {
+            Some(entry) => {
+                *entry = Some((address, layout));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the block at the given address, returning whether it was in the table.
+    pub fn remove(&mut self, address: usize) -> bool {
+        match self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.is_some_and(|(entry_address, _)| entry_address == address))
+        {
+            Some(entry) => {
+                *entry = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns an iterator over the addresses and layouts of the blocks in the table.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, PaddedLayout)> + '_ {
+        self.entries.iter().flatten().copied()
+    }
+}
+
+impl<const N: usize> Default for BlockTable<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Freed blocks waiting to be reused, oldest first, limited both in number and total size.
+#[derive(Debug)]
+pub struct Quarantine<const N: usize> {
+    /// A ring buffer of blocks, with the oldest at `start`.
+    entries: [Option<(usize, PaddedLayout)>; N],
+    start: usize,
+    len: usize,
+    bytes: usize,
+    max_bytes: usize,
+}
+
+impl<const N: usize> Quarantine<N> {
+    /// Creates an empty quarantine which holds up to `N` blocks and `max_bytes` bytes.
+    pub const fn new(max_bytes: usize) -> Self {
+        Self {
+            entries: [None; N],
+            start: 0,
+            len: 0,
+            bytes: 0,
+            max_bytes,
+        }
+    }
+
+    /// Adds the given freed block, and then evicts the oldest blocks until it is within its
+    /// limits, passing each to `release`.
+    ///
+    /// A block larger than the size limit is released immediately.
+    pub fn push(
+        &mut self,
+        address: usize,
+        layout: PaddedLayout,
+        mut release: impl FnMut(usize, PaddedLayout),
+    ) {
+        if layout.block.size() > self.max_bytes {
+            release(address, layout);
+            return;
+        }
+        while self.len == N || self.bytes + layout.block.size() > self.max_bytes {
+            let (oldest_address, oldest_layout) = self.pop().unwrap();
+            release(oldest_address, oldest_layout);
+        }
+        self.entries[(self.start + self.len) % N] = Some((address, layout));
+        self.len += 1;
+        self.bytes += layout.block.size();
+    }
+
+    /// Removes and returns the oldest block, if there is one.
+    pub fn pop(&mut self) -> Option<(usize, PaddedLayout)> {
+        if self.len == 0 {
+            return None;
+        }
+        let entry = self.entries[self.start].take().unwrap();
+        self.start = (self.start + 1) % N;
+        self.len -= 1;
+        self.bytes -= entry.1.block.size();
+        Some(entry)
+    }
+
+    /// Returns an iterator over the addresses and layouts of the blocks in quarantine.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, PaddedLayout)> + '_ {
+        self.entries.iter().flatten().copied()
+    }
+
+    /// Returns the total size of the blocks in quarantine.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(size: usize, align: usize) -> PaddedLayout {
+        PaddedLayout::new(Layout::from_size_align(size, align).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn padded_layout() {
+        let padded = layout(10, 4);
+        assert_eq!(padded.offset, RED_ZONE_SIZE);
+        assert_eq!(padded.block.size(), RED_ZONE_SIZE + 10 + RED_ZONE_SIZE);
+        assert_eq!(padded.block.align(), 4);
+
+        let padded = layout(64, 64);
+        assert_eq!(padded.offset, 64);
+        assert_eq!(padded.block.size(), 64 + 64 + RED_ZONE_SIZE);
+        assert_eq!(padded.block.align(), 64);
+
+        assert_eq!(
+            PaddedLayout::new(Layout::from_size_align(isize::MAX as usize - 8, 1).unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn detect_overrun_and_underrun() {
+        let padded = layout(8, 8);
+        let mut front = [RED_ZONE_POISON; RED_ZONE_SIZE];
+        let mut back = [RED_ZONE_POISON; RED_ZONE_SIZE];
+        assert_eq!(padded.front_red_zone().len(), front.len());
+        assert_eq!(padded.back_red_zone().len(), back.len());
+        assert_eq!(padded.check_red_zones(&front, &back), Ok(()));
+
+        back[1] = 0;
+        assert_eq!(
+            padded.check_red_zones(&front, &back),
+            Err(Corruption {
+                kind: CorruptionKind::Overrun,
+                offset: 9,
+                size: 8,
+            })
+        );
+
+        back[1] = RED_ZONE_POISON;
+        front[RED_ZONE_SIZE - 1] = 0;
+        let corruption = padded.check_red_zones(&front, &back).unwrap_err();
+        assert_eq!(corruption.kind, CorruptionKind::Underrun);
+        assert_eq!(corruption.offset, -1);
+        assert_eq!(
+            corruption.to_string(),
+            "Buffer underrun at offset -1 of 8 byte allocation"
+        );
+    }
+
+    #[test]
+    fn detect_use_after_free() {
+        let padded = layout(4, 1);
+        let mut block = [FREED_POISON; 2 * RED_ZONE_SIZE + 4];
+        assert_eq!(padded.check_freed(&block), Ok(()));
+        block[RED_ZONE_SIZE + 2] = 1;
+        assert_eq!(
+            padded.check_freed(&block),
+            Err(Corruption {
+                kind: CorruptionKind::UseAfterFree,
+                offset: 2,
+                size: 4,
+            })
+        );
+    }
+
+    #[test]
+    fn block_table() {
+        let mut table = BlockTable::<2>::new();
+        assert!(table.insert(0x1000, layout(1, 1)));
+        assert!(table.insert(0x2000, layout(2, 1)));
+        assert!(!table.insert(0x3000, layout(3, 1)));
+        assert!(table.remove(0x1000));
+        assert!(!table.remove(0x1000));
+        assert!(table.insert(0x3000, layout(3, 1)));
+        let mut addresses = table.iter().map(|(address, _)| address).collect::<Vec<_>>();
+        addresses.sort();
+        assert_eq!(addresses, [0x2000, 0x3000]);
+    }
+
+    #[test]
+    fn quarantine_limits() {
+        let small = layout(8, 1);
+        let block_size = small.block.size();
+        let mut quarantine = Quarantine::<3>::new(block_size * 10);
+        let mut released = Vec::new();
+        for address in 1..=4 {
+            quarantine.push(address, small, |address, _| released.push(address));
+        }
+        // Limited by the number of entries.
+        assert_eq!(released, [1]);
+        assert_eq!(quarantine.bytes(), 3 * block_size);
+
+        // Limited by size.
+        let mut quarantine = Quarantine::<8>::new(block_size * 2);
+        released.clear();
+        for address in 1..=4 {
+            quarantine.push(address, small, |address, _| released.push(address));
+        }
+        assert_eq!(released, [1, 2]);
+        assert_eq!(quarantine.pop().map(|(address, _)| address), Some(3));
+
+        // Too large to quarantine at all.
+        let large = layout(block_size * 2, 1);
+        quarantine.push(5, large, |address, _| released.push(address));
+        assert_eq!(released, [1, 2, 5]);
+    }
+}
@@ -0,0 +1,161 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Scrubbing free memory while the system is idle: re-zeroing free pages, and optionally checking
+//! that pages which were zeroed on an earlier pass are still zero, to detect bit flips.
+
+use core::fmt::{self, Display, Formatter};
+
+/// What the scrubber does with each free page.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ScrubMode {
+    /// Free pages are left alone.
+    #[default]
+    Off,
+    /// Free pages are zeroed.
+    Zero,
+    /// Free pages which were zeroed on an earlier pass are checked for bits which have been set
+    /// since, and then zeroed again.
+    Checksum,
+}
+
+impl ScrubMode {
+    /// Parses a mode from its name, as printed by `Display`.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "off" => Some(Self::Off),
+            "zero" => Some(Self::Zero),
+            "checksum" => Some(Self::Checksum),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the scrubber should run at all.
+    pub fn is_enabled(self) -> bool {
+        self != Self::Off
+    }
+}
+
+impl Display for ScrubMode {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Off => "off",
+            Self::Zero => "zero",
+            Self::Checksum => "checksum",
+        })
+    }
+}
+
+/// Counts of what the scrubber has done since it was last reset.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ScrubStats {
+    /// The number of complete passes over the free pages.
+    pub passes: u64,
+    /// The number of pages which have been zeroed.
+    pub pages_scrubbed: u64,
+    /// The number of pages which were checked for bit flips before being zeroed.
+    pub pages_checked: u64,
+    /// The number of checked pages which had any bits set.
+    pub corrupted_pages: u64,
+    /// The total number of bits found set in checked pages.
+    pub bit_flips: u64,
+}
+
+impl ScrubStats {
+    /// Scrubs the given free page according to the given mode, and counts what was found.
+    ///
+    /// `was_scrubbed` is whether the page has been zeroed by the scrubber since it was last freed,
+    /// so that it is expected to be all zeroes. Returns the number of bits found set, if the page
+    /// was checked.
+    pub fn scrub_page(&mut self, mode: ScrubMode, page: &mut [u64], was_scrubbed: bool) -> u32 {
+        let mut flips = 0;
+        if mode == ScrubMode::Checksum && was_scrubbed {
+            flips = count_set_bits(page);
+            self.pages_checked += 1;
+            if flips > 0 {
+                self.corrupted_pages += 1;
+                self.bit_flips += u64::from(flips);
+            }
+        }
+        page.fill(0);
+        self.pages_scrubbed += 1;
+        flips
+    }
+}
+
+impl Display for ScrubStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} passes, {} pages scrubbed, {} checked, {} bit flips in {} pages",
+            self.passes,
+            self.pages_scrubbed,
+            self.pages_checked,
+            self.bit_flips,
+            self.corrupted_pages
+        )
+    }
+}
+
+/// Returns the number of bits set in the given words.
+fn count_set_bits(words: &[u64]) -> u32 {
+    words.iter().map(|word| word.count_ones()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_mode() {
+        for mode in [ScrubMode::Off, ScrubMode::Zero, ScrubMode::Checksum] {
+            assert_eq!(ScrubMode::parse(&mode.to_string()), Some(mode));
+        }
+        assert_eq!(ScrubMode::parse("on"), None);
+    }
+
+    #[test]
+    fn zero_mode_doesnt_check() {
+        let mut stats = ScrubStats::default();
+        let mut page = [0x0101; 4];
+        assert_eq!(stats.scrub_page(ScrubMode::Zero, &mut page, true), 0);
+        assert_eq!(page, [0; 4]);
+        assert_eq!(
+            stats,
+            ScrubStats {
+                pages_scrubbed: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn checksum_mode_counts_flips() {
+        let mut stats = ScrubStats::default();
+        let mut page = [0, 0b1001, 0, 1 << 63];
+        assert_eq!(stats.scrub_page(ScrubMode::Checksum, &mut page, true), 3);
+        assert_eq!(page, [0; 4]);
+        assert_eq!(stats.scrub_page(ScrubMode::Checksum, &mut page, true), 0);
+        assert_eq!(
+            stats,
+            ScrubStats {
+                passes: 0,
+                pages_scrubbed: 2,
+                pages_checked: 2,
+                corrupted_pages: 1,
+                bit_flips: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn checksum_mode_skips_newly_freed_pages() {
+        let mut stats = ScrubStats::default();
+        let mut page = [u64::MAX; 4];
+        assert_eq!(stats.scrub_page(ScrubMode::Checksum, &mut page, false), 0);
+        assert_eq!(page, [0; 4]);
+        assert_eq!(stats.pages_checked, 0);
+        assert_eq!(stats.bit_flips, 0);
+    }
+}
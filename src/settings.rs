@@ -0,0 +1,363 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! The format of the settings store, which keeps shell history, the log level, a pending RTC alarm
+//! and saved benchmark results on a block device across reboots.
+//!
+//! All integers are little-endian. The store consists of:
+//!
+//! 1. A 24 byte header: the magic bytes `OSDSET\0\0`, the format version as a `u32` (currently 1),
+//!    the length of the entries which follow as a `u32`, their Adler-32 checksum as a `u32`, and a
+//!    reserved `u32` which is currently 0.
+//! 2. The entries, each a `u8` key, the length of the value as a `u16`, and then the value.
+//!
+//! The value of an entry for saved benchmark results is their name, followed by the benchmark,
+//! name, value as a `u64` and unit of each metric. Each string is its length as a `u8` followed by
+//! its UTF-8 bytes.
+//!
+//! Entries with unknown keys are skipped, so that new settings can be added without changing the
+//! version. The version only changes if existing entries change meaning.
+
+use crate::coredump::Adler32;
+use alloc::{string::String, vec::Vec};
+use core::{
+    fmt::{self, Display, Formatter},
+    str,
+};
+
+/// The magic bytes at the start of the store.
+pub const MAGIC: [u8; 8] = *b"OSDSET\0\0";
+
+/// The version of the format described above.
+pub const VERSION: u32 = 1;
+
+/// The size of the header.
+pub const HEADER_SIZE: usize = 24;
+
+/// The size of the key and length before each value.
+const ENTRY_HEADER_SIZE: usize = 3;
+
+/// The maximum log level, as its name such as `info`.
+const KEY_LOG_LEVEL: u8 = 1;
+/// A line of shell history. There is an entry for each line, oldest first.
+const KEY_HISTORY: u8 = 2;
+/// The time of the pending RTC alarm, as a `u64` number of seconds since the Unix epoch.
+const KEY_ALARM: u8 = 3;
+/// A named set of benchmark results. There is an entry for each set, in the order they were saved.
+const KEY_BENCH_RESULTS: u8 = 4;
+
+/// The settings kept in the store.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Settings {
+    /// The maximum level of log records to keep, if it has been set.
+    pub log_level: Option<String>,
+    /// Lines entered in the shell, oldest first.
+    pub history: Vec<Vec<u8>>,
+    /// When the pending RTC alarm is due, in seconds since the Unix epoch, if there is one.
+    pub alarm: Option<u64>,
+    /// Benchmark results saved with `bench save`.
+    pub bench_results: Vec<SavedBenchResults>,
+}
+
+/// A named set of benchmark results.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SavedBenchResults {
+    pub name: String,
+    pub metrics: Vec<SavedMetric>,
+}
+
+/// A single measurement in a set of saved benchmark results.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SavedMetric {
+    /// The benchmark which the measurement is from, such as `cpu`.
+    pub benchmark: String,
+    /// The name of the measurement within the benchmark, such as `time`.
+    pub name: String,
+    pub value: u64,
+    pub unit: String,
+}
+
+/// An error reading the settings store.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SettingsError {
+    /// The store doesn't start with the magic bytes, so has never been written.
+    NotFound,
+    /// The store was written by a newer version of the format.
+    UnsupportedVersion(u32),
+    /// The entries don't match their checksum.
+    BadChecksum,
+    /// The header or an entry claims to be longer than the data available.
+    Truncated,
+}
+
+impl Display for SettingsError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "No settings stored"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "Unsupported settings version {version}")
+            }
+            Self::BadChecksum => write!(f, "Settings checksum mismatch"),
+            Self::Truncated => write!(f, "Settings truncated"),
+        }
+    }
+}
+
+impl Settings {
+    /// Returns the settings in the store format.
+    ///
+    /// Values too long to fit in an entry are left out.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut entries = Vec::new();
+        if let Some(log_level) = &self.log_level {
+            push_entry(&mut entries, KEY_LOG_LEVEL, log_level.as_bytes());
+        }
+        for line in &self.history {
+            push_entry(&mut entries, KEY_HISTORY, line);
+        }
+        if let Some(alarm) = self.alarm {
+            push_entry(&mut entries, KEY_ALARM, &alarm.to_le_bytes());
+        }
+        for results in &self.bench_results {
+            if let Some(value) = results.encode() {
+                push_entry(&mut entries, KEY_BENCH_RESULTS, &value);
+            }
+        }
+        let mut checksum = Adler32::default();
+        checksum.update(&entries);
+
+        let mut store = Vec::with_capacity(HEADER_SIZE + entries.len());
+        store.extend_from_slice(&MAGIC);
+        store.extend_from_slice(&VERSION.to_le_bytes());
+        store.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        store.extend_from_slice(&checksum.finish().to_le_bytes());
+        store.extend_from_slice(&0u32.to_le_bytes());
+        store.extend_from_slice(&entries);
+        store
+    }
+
+    /// Parses a store, which may be followed by unused space.
+    ///
+    /// Entries with unknown keys or invalid values are ignored.
+    pub fn decode(store: &[u8]) -> Result<Self, SettingsError> {
+        if store.len() < HEADER_SIZE || store[0..8] != MAGIC {
+            return Err(SettingsError::NotFound);
+        }
+        let version = read_u32(&store[8..12]);
+        if version > VERSION {
+            return Err(SettingsError::UnsupportedVersion(version));
+        }
+        let length = read_u32(&store[12..16]) as usize;
+        let entries = store[HEADER_SIZE..]
+            .get(..length)
+            .ok_or(SettingsError::Truncated)?;
+        let mut checksum = Adler32::default();
+        checksum.update(entries);
+        if checksum.finish() != read_u32(&store[16..20]) {
+            return Err(SettingsError::BadChecksum);
+        }
+
+        let mut settings = Self::default();
+        let mut rest = entries;
+        while !rest.is_empty() {
+            let (key, value, remaining) = split_entry(rest).ok_or(SettingsError::Truncated)?;
+            rest = remaining;
+            match key {
+                KEY_LOG_LEVEL => {
+                    settings.log_level = str::from_utf8(value).ok().map(String::from);
+                }
+                KEY_HISTORY => settings.history.push(value.to_vec()),
+                KEY_ALARM => settings.alarm = value.try_into().ok().map(u64::from_le_bytes),
+                KEY_BENCH_RESULTS => settings
+                    .bench_results
+                    .extend(SavedBenchResults::decode(value)),
+                _ => {}
+            }
+        }
+        Ok(settings)
+    }
+}
+
+impl SavedBenchResults {
+    /// Returns the value of an entry for the results, or `None` if any string is too long.
+    fn encode(&self) -> Option<Vec<u8>> {
+        let mut value = Vec::new();
+        push_str(&mut value, &self.name)?;
+        for metric in &self.metrics {
+            push_str(&mut value, &metric.benchmark)?;
+            push_str(&mut value, &metric.name)?;
+            value.extend_from_slice(&metric.value.to_le_bytes());
+            push_str(&mut value, &metric.unit)?;
+        }
+        Some(value)
+    }
+
+    /// Parses the value of an entry, or returns `None` if it is invalid.
+    fn decode(value: &[u8]) -> Option<Self> {
+        let (name, mut rest) = split_str(value)?;
+        let mut metrics = Vec::new();
+        while !rest.is_empty() {
+            let (benchmark, remaining) = split_str(rest)?;
+            let (metric_name, remaining) = split_str(remaining)?;
+            let (metric_value, remaining) = remaining.split_first_chunk()?;
+            let (unit, remaining) = split_str(remaining)?;
+            metrics.push(SavedMetric {
+                benchmark: benchmark.into(),
+                name: metric_name.into(),
+                value: u64::from_le_bytes(*metric_value),
+                unit: unit.into(),
+            });
+            rest = remaining;
+        }
+        Some(Self {
+            name: name.into(),
+            metrics,
+        })
+    }
+}
+
+/// Appends an entry with the given key and value, unless the value is too long.
+fn push_entry(entries: &mut Vec<u8>, key: u8, value: &[u8]) {
+    let Ok(length) = u16::try_from(value.len()) else {
+        return;
+    };
+    entries.push(key);
+    entries.extend_from_slice(&length.to_le_bytes());
+    entries.extend_from_slice(value);
+}
+
+/// Appends the given string with its length, or returns `None` if it is too long.
+fn push_str(value: &mut Vec<u8>, s: &str) -> Option<()> {
+    value.push(u8::try_from(s.len()).ok()?);
+    value.extend_from_slice(s.as_bytes());
+    Some(())
+}
+
+/// Splits a string written by `push_str` off the given bytes, returning it and the remaining bytes.
+fn split_str(bytes: &[u8]) -> Option<(&str, &[u8])> {
+    let (&length, rest) = bytes.split_first()?;
+    let (s, rest) = rest.split_at_checked(length.into())?;
+    Some((str::from_utf8(s).ok()?, rest))
+}
+
+/// Splits the first entry off the given entries, returning its key, its value and the remaining
+/// entries.
+fn split_entry(entries: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (header, rest) = entries.split_at_checked(ENTRY_HEADER_SIZE)?;
+    let length = u16::from_le_bytes([header[1], header[2]]);
+    let (value, rest) = rest.split_at_checked(length.into())?;
+    Some((header[0], value, rest))
+}
+
+/// Reads a little-endian `u32` from the given 4 bytes.
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes.try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> Settings {
+        Settings {
+            log_level: Some("debug".into()),
+            history: vec![b"date".to_vec(), b"alarm 60".to_vec()],
+            alarm: Some(1_700_000_000),
+            bench_results: vec![SavedBenchResults {
+                name: "before".into(),
+                metrics: vec![
+                    SavedMetric {
+                        benchmark: "cpu".into(),
+                        name: "time".into(),
+                        value: 1234,
+                        unit: "us".into(),
+                    },
+                    SavedMetric {
+                        benchmark: "memory".into(),
+                        name: "bandwidth".into(),
+                        value: 5678,
+                        unit: "MiB/s".into(),
+                    },
+                ],
+            }],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut store = example().encode();
+        assert_eq!(store[0..8], MAGIC);
+        assert_eq!(store[8..12], [1, 0, 0, 0]);
+        assert_eq!(Settings::decode(&store), Ok(example()));
+
+        // Unused space after the store is ignored.
+        store.resize(4096, 0);
+        assert_eq!(Settings::decode(&store), Ok(example()));
+
+        assert_eq!(
+            Settings::decode(&Settings::default().encode()),
+            Ok(Settings::default())
+        );
+    }
+
+    #[test]
+    fn header_errors() {
+        assert_eq!(Settings::decode(&[0; 4096]), Err(SettingsError::NotFound));
+        assert_eq!(Settings::decode(&MAGIC), Err(SettingsError::NotFound));
+
+        let mut store = example().encode();
+        store[8] = 2;
+        assert_eq!(
+            Settings::decode(&store),
+            Err(SettingsError::UnsupportedVersion(2))
+        );
+
+        let mut store = example().encode();
+        let last = store.len() - 1;
+        store[last] ^= 1;
+        assert_eq!(Settings::decode(&store), Err(SettingsError::BadChecksum));
+
+        let store = example().encode();
+        assert_eq!(
+            Settings::decode(&store[..store.len() - 1]),
+            Err(SettingsError::Truncated)
+        );
+    }
+
+    #[test]
+    fn unknown_and_invalid_entries() {
+        let mut entries = Vec::new();
+        push_entry(&mut entries, 42, b"from the future");
+        push_entry(&mut entries, KEY_ALARM, &[1, 2, 3]);
+        push_entry(&mut entries, KEY_HISTORY, b"help");
+        // The metric is truncated.
+        push_entry(&mut entries, KEY_BENCH_RESULTS, b"\x04name\x03cp");
+        let mut checksum = Adler32::default();
+        checksum.update(&entries);
+        let mut store = Vec::new();
+        store.extend_from_slice(&MAGIC);
+        store.extend_from_slice(&1u32.to_le_bytes());
+        store.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        store.extend_from_slice(&checksum.finish().to_le_bytes());
+        store.extend_from_slice(&[0; 4]);
+        store.extend_from_slice(&entries);
+        assert_eq!(
+            Settings::decode(&store),
+            Ok(Settings {
+                history: vec![b"help".to_vec()],
+                ..Settings::default()
+            })
+        );
+    }
+
+    #[test]
+    fn long_bench_names_left_out() {
+        let mut settings = example();
+        settings.bench_results.push(SavedBenchResults {
+            name: "x".repeat(256),
+            metrics: Vec::new(),
+        });
+        assert_eq!(Settings::decode(&settings.encode()), Ok(example()));
+    }
+}
@@ -0,0 +1,194 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A test runner which is run instead of the shell when the `target-tests` feature is enabled, for
+//! unit tests of code which only runs on the target. It runs each registered test once, prints the
+//! results, and then exits with a status reporting whether they all passed, so it can be run
+//! automatically.
+//!
+//! The status is reported with `Platform::exit`: on QEMU this needs semihosting, which
+//! `make test.qemu` enables, and on crosvm a failure is reported as a reset.
+
+use crate::{
+    apps::selftest::{Outcome, test_alloc_stress, test_heap, test_pagetable},
+    console::Console,
+    platform::{ConsoleImpl, Platform, PlatformImpl},
+};
+use alloc::{format, string::String, vec::Vec};
+use dtoolkit::{
+    Node,
+    fdt::{Fdt, FdtNode},
+};
+use embedded_io::Write;
+use osdemo_core::{
+    fdt::{child_cells, decode_ranges, property_cells, reg_entries},
+    pci::PciRootComplex,
+};
+use virtio_drivers::transport::pci::bus::BarInfo;
+
+/// What the tests may use.
+struct TestContext<'a> {
+    pci_roots: &'a mut [PciRootComplex],
+    fdt: &'a Fdt<'static>,
+}
+
+/// A test registered with the runner.
+struct TestCase {
+    name: &'static str,
+    run: fn(&mut TestContext) -> Outcome,
+}
+
+/// All the tests, in the order they are run.
+const TESTS: [TestCase; 5] = [
+    TestCase {
+        name: "heap",
+        run: |_| test_heap(),
+    },
+    TestCase {
+        name: "alloc_stress",
+        run: |_| test_alloc_stress(),
+    },
+    TestCase {
+        name: "pagetable",
+        run: |_| test_pagetable(),
+    },
+    TestCase {
+        name: "fdt",
+        run: test_fdt,
+    },
+    TestCase {
+        name: "pci_bars",
+        run: test_pci_bars,
+    },
+];
+
+/// Runs all the tests, prints their results, and then exits the VMM.
+pub fn main(
+    console: &mut Console<ConsoleImpl>,
+    pci_roots: &mut [PciRootComplex],
+    fdt: &Fdt<'static>,
+) -> ! {
+    let mut context = TestContext { pci_roots, fdt };
+    let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+    writeln!(console, "running {} tests", TESTS.len()).unwrap();
+    for test in &TESTS {
+        let outcome = (test.run)(&mut context);
+        match outcome {
+            Outcome::Pass(_) => passed += 1,
+            Outcome::Fail(_) => failed += 1,
+            Outcome::Skip(_) => skipped += 1,
+        }
+        writeln!(console, "test {} ... {outcome}", test.name).unwrap();
+    }
+    writeln!(
+        console,
+        "test result: {}. {passed} passed; {failed} failed; {skipped} skipped",
+        if failed == 0 { "ok" } else { "FAILED" }
+    )
+    .unwrap();
+    PlatformImpl::exit(if failed == 0 { 0 } else { 1 });
+}
+
+/// Checks that the memory node describes the memory the image is running from, and that every
+/// `reg` and `ranges` property in the device tree can be decoded.
+fn test_fdt(context: &mut TestContext) -> Outcome {
+    let fdt = context.fdt;
+    let Some(memory) = fdt.find_node("/memory") else {
+        return Outcome::Fail("no /memory node".into());
+    };
+    let Some(memory_regions) = reg_entries(&memory) else {
+        return Outcome::Fail("invalid reg property on /memory".into());
+    };
+    let image_address = test_fdt as usize as u64;
+    if !memory_regions
+        .iter()
+        .any(|region| (region.address..region.address + region.size).contains(&image_address))
+    {
+        return Outcome::Fail(format!(
+            "image at {image_address:#x} isn't in any memory region"
+        ));
+    }
+    let mut node_count = 0;
+    if let Err(e) = check_fdt_node(&fdt.root(), 2, &mut node_count) {
+        return Outcome::Fail(e);
+    }
+    Outcome::Pass(format!("{node_count} nodes"))
+}
+
+/// Checks that the `reg` and `ranges` properties of the given node and its children can be decoded,
+/// given the `#address-cells` of its parent, and counts the nodes checked.
+fn check_fdt_node(
+    node: &FdtNode,
+    parent_address_cells: usize,
+    node_count: &mut usize,
+) -> Result<(), String> {
+    *node_count += 1;
+    if node.property("reg").is_some() && reg_entries(node).is_none() {
+        return Err(format!("invalid reg property on {}", node.name()));
+    }
+    let (address_cells, size_cells) = child_cells(node);
+    if let Some(ranges) = property_cells(node, "ranges")
+        && decode_ranges(&ranges, address_cells, parent_address_cells, size_cells).is_none()
+    {
+        return Err(format!("invalid ranges property on {}", node.name()));
+    }
+    for child in node.children() {
+        check_fdt_node(&child, address_cells, node_count)?;
+    }
+    Ok(())
+}
+
+/// Checks that the memory BARs allocated for each PCI device are aligned to their size and don't
+/// overlap.
+fn test_pci_bars(context: &mut TestContext) -> Outcome {
+    if context.pci_roots.is_empty() {
+        return Outcome::Skip("no PCI roots".into());
+    }
+    // The allocated region of each BAR, with the device function and BAR index for errors.
+    let mut allocations = Vec::new();
+    for pci_root in context.pci_roots.iter_mut() {
+        for (device_function, _) in pci_root.enumerate_devices() {
+            let bars = match pci_root.device_bars(device_function) {
+                Ok(bars) => bars,
+                Err(e) => {
+                    return Outcome::Fail(format!("reading BARs of {device_function} failed: {e}"));
+                }
+            };
+            for (bar_index, bar) in bars.iter().enumerate() {
+                let Some(BarInfo::Memory { address, size, .. }) = *bar else {
+                    continue;
+                };
+                if size == 0 {
+                    continue;
+                }
+                if address == 0 {
+                    return Outcome::Fail(format!(
+                        "BAR {bar_index} of {device_function} wasn't allocated"
+                    ));
+                }
+                if !address.is_multiple_of(size) {
+                    return Outcome::Fail(format!(
+                        "BAR {bar_index} of {device_function} at {address:#x} isn't aligned to \
+                         its size {size:#x}"
+                    ));
+                }
+                allocations.push((address, address + size, device_function, bar_index));
+            }
+        }
+    }
+    if allocations.is_empty() {
+        return Outcome::Skip("no memory BARs".into());
+    }
+    allocations.sort_unstable_by_key(|&(start, ..)| start);
+    for pair in allocations.windows(2) {
+        let (_, end, first_device, first_bar) = pair[0];
+        let (start, _, second_device, second_bar) = pair[1];
+        if start < end {
+            return Outcome::Fail(format!(
+                "BAR {first_bar} of {first_device} overlaps BAR {second_bar} of {second_device}"
+            ));
+        }
+    }
+    Outcome::Pass(format!("{} BARs", allocations.len()))
+}
@@ -0,0 +1,229 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Minimal telnet protocol handling, so that a telnet client can be used as a terminal.
+//!
+//! The server offers to echo and to suppress go-ahead, which puts clients into character at a time
+//! mode so that the shell's line editor works. All other options are refused.
+
+use alloc::vec::Vec;
+
+/// Interpret as command: the start of every telnet command.
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+/// Subnegotiation begin.
+const SB: u8 = 250;
+/// Subnegotiation end.
+const SE: u8 = 240;
+
+/// The echo option.
+const OPTION_ECHO: u8 = 1;
+/// The suppress go-ahead option.
+const OPTION_SUPPRESS_GO_AHEAD: u8 = 3;
+
+/// The commands the server sends when a client connects, offering to echo and to suppress
+/// go-ahead.
+pub const INITIAL_NEGOTIATION: [u8; 6] =
+    [IAC, WILL, OPTION_ECHO, IAC, WILL, OPTION_SUPPRESS_GO_AHEAD];
+
+/// Something decoded from the bytes sent by a telnet client.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TelnetEvent {
+    /// A byte of data.
+    Data(u8),
+    /// A command which should be sent back to the client, in answer to an option request.
+    Reply([u8; 3]),
+}
+
+/// The state of a `TelnetDecoder` between bytes.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+enum State {
+    #[default]
+    Data,
+    /// The previous data byte was a carriage return, so a following line feed or NUL is dropped.
+    CarriageReturn,
+    /// After an IAC.
+    Command,
+    /// After an IAC and an option negotiation command.
+    Option(u8),
+    /// Within a subnegotiation, which is ignored.
+    Subnegotiation,
+    /// After an IAC within a subnegotiation.
+    SubnegotiationCommand,
+}
+
+/// Decodes the bytes sent by a telnet client into data and answers to its option requests.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TelnetDecoder {
+    state: State,
+    /// Whether we have agreed that the client suppresses go-ahead.
+    client_suppresses_go_ahead: bool,
+}
+
+impl TelnetDecoder {
+    /// Creates a decoder for a newly connected client.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handles the next byte received from the client.
+    pub fn push(&mut self, byte: u8) -> Option<TelnetEvent> {
+        match self.state {
+            State::Data | State::CarriageReturn => {
+                let after_carriage_return = self.state == State::CarriageReturn;
+                self.state = State::Data;
+                match byte {
+                    IAC => self.state = State::Command,
+                    // A newline is sent as CR LF, and a bare carriage return as CR NUL.
+                    b'\n' | 0 if after_carriage_return => {}
+                    b'\r' => {
+                        self.state = State::CarriageReturn;
+                        return Some(TelnetEvent::Data(byte));
+                    }
+                    _ => return Some(TelnetEvent::Data(byte)),
+                }
+                None
+            }
+            State::Command => {
+                self.state = State::Data;
+                match byte {
+                    // An escaped 255 data byte.
+                    IAC => return Some(TelnetEvent::Data(IAC)),
+                    WILL | WONT | DO | DONT => self.state = State::Option(byte),
+                    SB => self.state = State::Subnegotiation,
+                    // Other commands, such as no-op or are-you-there, are ignored.
+                    _ => {}
+                }
+                None
+            }
+            State::Option(command) => {
+                self.state = State::Data;
+                self.answer(command, byte).map(TelnetEvent::Reply)
+            }
+            State::Subnegotiation => {
+                if byte == IAC {
+                    self.state = State::SubnegotiationCommand;
+                }
+                None
+            }
+            State::SubnegotiationCommand => {
+                self.state = if byte == SE {
+                    State::Data
+                } else {
+                    State::Subnegotiation
+                };
+                None
+            }
+        }
+    }
+
+    /// Returns the answer to the given option negotiation command from the client, if one is
+    /// needed.
+    fn answer(&mut self, command: u8, option: u8) -> Option<[u8; 3]> {
+        let supported = matches!(option, OPTION_ECHO | OPTION_SUPPRESS_GO_AHEAD);
+        match command {
+            // We already offered the options we support, so only refuse the others.
+            DO if !supported => Some([IAC, WONT, option]),
+            WILL if option == OPTION_SUPPRESS_GO_AHEAD => {
+                if self.client_suppresses_go_ahead {
+                    None
+                } else {
+                    self.client_suppresses_go_ahead = true;
+                    Some([IAC, DO, option])
+                }
+            }
+            WILL => Some([IAC, DONT, option]),
+            _ => None,
+        }
+    }
+}
+
+/// Appends the given output to `encoded` in the form telnet expects, with newlines as CR LF and
+/// 255 bytes escaped.
+pub fn encode(data: &[u8], encoded: &mut Vec<u8>) {
+    let mut previous = 0;
+    for &byte in data {
+        match byte {
+            b'\n' if previous != b'\r' => encoded.extend_from_slice(b"\r\n"),
+            IAC => encoded.extend_from_slice(&[IAC, IAC]),
+            _ => encoded.push(byte),
+        }
+        previous = byte;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(decoder: &mut TelnetDecoder, bytes: &[u8]) -> Vec<TelnetEvent> {
+        bytes
+            .iter()
+            .filter_map(|&byte| decoder.push(byte))
+            .collect()
+    }
+
+    #[test]
+    fn data_and_newlines() {
+        let mut decoder = TelnetDecoder::new();
+        assert_eq!(
+            decode(&mut decoder, b"ls\r\n\r\0a\n"),
+            [
+                TelnetEvent::Data(b'l'),
+                TelnetEvent::Data(b's'),
+                TelnetEvent::Data(b'\r'),
+                TelnetEvent::Data(b'\r'),
+                TelnetEvent::Data(b'a'),
+                TelnetEvent::Data(b'\n'),
+            ]
+        );
+        assert_eq!(decode(&mut decoder, &[IAC, IAC]), [TelnetEvent::Data(IAC)]);
+    }
+
+    #[test]
+    fn negotiation() {
+        let mut decoder = TelnetDecoder::new();
+        assert_eq!(
+            decode(
+                &mut decoder,
+                &[
+                    [IAC, DO, OPTION_ECHO],
+                    [IAC, DO, 24],
+                    [IAC, WILL, OPTION_SUPPRESS_GO_AHEAD],
+                    [IAC, WILL, 31],
+                    [IAC, WILL, OPTION_SUPPRESS_GO_AHEAD],
+                    [IAC, DONT, OPTION_ECHO],
+                ]
+                .concat()
+            ),
+            [
+                TelnetEvent::Reply([IAC, WONT, 24]),
+                TelnetEvent::Reply([IAC, DO, OPTION_SUPPRESS_GO_AHEAD]),
+                TelnetEvent::Reply([IAC, DONT, 31]),
+            ]
+        );
+    }
+
+    #[test]
+    fn skip_subnegotiation() {
+        let mut decoder = TelnetDecoder::new();
+        assert_eq!(
+            decode(
+                &mut decoder,
+                &[IAC, SB, 24, 0, IAC, IAC, b'x', IAC, SE, b'y']
+            ),
+            [TelnetEvent::Data(b'y')]
+        );
+    }
+
+    #[test]
+    fn encode_output() {
+        let mut encoded = Vec::new();
+        encode(b"a\nb\r\n\xff", &mut encoded);
+        assert_eq!(encoded, b"a\r\nb\r\n\xff\xff");
+    }
+}
@@ -0,0 +1,291 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Decoding of keys and ANSI escape sequences received from a terminal, and helpers for drawing
+//! full-screen displays on it.
+
+use arrayvec::ArrayVec;
+use core::fmt::{self, Display, Formatter};
+
+/// The maximum number of parameter bytes we keep from a control sequence. Anything longer isn't a
+/// key we recognise.
+const MAX_PARAMETER_LENGTH: usize = 4;
+
+/// Clears the whole screen.
+pub const CLEAR_SCREEN: &str = "\x1b[2J";
+/// Moves the cursor to the top left corner of the screen.
+pub const CURSOR_HOME: &str = "\x1b[H";
+/// Clears from the cursor to the end of the line.
+pub const CLEAR_TO_END_OF_LINE: &str = "\x1b[K";
+/// Clears from the cursor to the end of the screen.
+pub const CLEAR_TO_END_OF_SCREEN: &str = "\x1b[J";
+/// Hides the cursor, to avoid flicker while redrawing.
+pub const HIDE_CURSOR: &str = "\x1b[?25l";
+/// Shows the cursor again after `HIDE_CURSOR`.
+pub const SHOW_CURSOR: &str = "\x1b[?25h";
+
+/// The binary unit prefixes used by `Bytes`, after plain bytes.
+const BYTE_UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+/// A key press decoded from the bytes sent by a terminal.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Key {
+    /// A printable ASCII character.
+    Char(u8),
+    /// Carriage return or line feed.
+    Enter,
+    /// Backspace or DEL, to delete the character before the cursor.
+    Backspace,
+    /// The delete key, to delete the character under the cursor.
+    Delete,
+    /// Ctrl-D.
+    Eof,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+}
+
+/// The state of a `KeyDecoder` part-way through an escape sequence.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+enum State {
+    #[default]
+    Ground,
+    /// We have received an ESC.
+    Escape,
+    /// We have received `ESC [`, and possibly some parameter bytes.
+    ControlSequence(ArrayVec<u8, MAX_PARAMETER_LENGTH>),
+    /// We have received `ESC O`.
+    SingleShift,
+}
+
+/// Decodes a stream of bytes from a terminal into key presses, handling ANSI escape sequences.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct KeyDecoder {
+    state: State,
+}
+
+impl KeyDecoder {
+    /// Processes the next byte received from the terminal.
+    ///
+    /// Returns the key pressed, if the byte completes one. Unrecognised control characters and
+    /// escape sequences are ignored.
+    pub fn push(&mut self, byte: u8) -> Option<Key> {
+        match &mut self.state {
+            State::Ground => match byte {
+                0x1b => {
+                    self.state = State::Escape;
+                    None
+                }
+                b'\r' | b'\n' => Some(Key::Enter),
+                0x04 => Some(Key::Eof),
+                0x08 | 0x7f => Some(Key::Backspace),
+                c if !c.is_ascii_control() && c.is_ascii() => Some(Key::Char(c)),
+                _ => None,
+            },
+            State::Escape => {
+                self.state = match byte {
+                    b'[' => State::ControlSequence(ArrayVec::new()),
+                    b'O' => State::SingleShift,
+                    _ => State::Ground,
+                };
+                None
+            }
+            State::SingleShift => {
+                self.state = State::Ground;
+                match byte {
+                    b'H' => Some(Key::Home),
+                    b'F' => Some(Key::End),
+                    _ => None,
+                }
+            }
+            State::ControlSequence(parameters) => {
+                if (0x30..=0x3f).contains(&byte) {
+                    // Parameter byte. If there are too many then we won't recognise the sequence
+                    // anyway, but still need to consume it.
+                    let _ = parameters.try_push(byte);
+                    return None;
+                }
+                if (0x20..=0x2f).contains(&byte) {
+                    // Intermediate byte, ignore.
+                    return None;
+                }
+                // Any other byte terminates the sequence.
+                let key = match (parameters.as_slice(), byte) {
+                    (_, b'A') => Some(Key::Up),
+                    (_, b'B') => Some(Key::Down),
+                    (_, b'C') => Some(Key::Right),
+                    (_, b'D') => Some(Key::Left),
+                    (_, b'H') | (b"1" | b"7", b'~') => Some(Key::Home),
+                    (_, b'F') | (b"4" | b"8", b'~') => Some(Key::End),
+                    (b"3", b'~') => Some(Key::Delete),
+                    _ => None,
+                };
+                self.state = State::Ground;
+                key
+            }
+        }
+    }
+}
+
+/// A horizontal bar `width` characters wide between brackets, filled in proportion to `value` out
+/// of `max`, such as `[#####     ]`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Bar {
+    pub value: u64,
+    pub max: u64,
+    pub width: usize,
+}
+
+impl Display for Bar {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let filled = (u128::from(self.value.min(self.max)) * self.width as u128)
+            .checked_div(u128::from(self.max))
+            .unwrap_or(0) as usize;
+        write!(
+            f,
+            "[{:#<filled$}{:<empty$}]",
+            "",
+            "",
+            empty = self.width - filled
+        )
+    }
+}
+
+/// A number of bytes, shown with a binary unit prefix and one decimal place if it is at least
+/// 1 KiB, such as `12.5 KiB`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Bytes(pub u64);
+
+impl Display for Bytes {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.0 < 1024 {
+            return write!(f, "{} B", self.0);
+        }
+        let mut tenths = u128::from(self.0) * 10;
+        let mut unit = 0;
+        loop {
+            tenths /= 1024;
+            if tenths < 1024 * 10 || unit == BYTE_UNITS.len() - 1 {
+                break;
+            }
+            unit += 1;
+        }
+        write!(f, "{}.{} {}", tenths / 10, tenths % 10, BYTE_UNITS[unit])
+    }
+}
+
+/// Returns how many times a second something happened, given that it happened `count` times in
+/// `elapsed_us` microseconds.
+pub fn per_second(count: u64, elapsed_us: u64) -> u64 {
+    (u128::from(count) * 1_000_000)
+        .checked_div(u128::from(elapsed_us))
+        .unwrap_or(0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8]) -> Vec<Key> {
+        let mut decoder = KeyDecoder::default();
+        bytes
+            .iter()
+            .filter_map(|&byte| decoder.push(byte))
+            .collect()
+    }
+
+    #[test]
+    fn plain_characters() {
+        assert_eq!(
+            decode(b"a1 \r\n"),
+            [
+                Key::Char(b'a'),
+                Key::Char(b'1'),
+                Key::Char(b' '),
+                Key::Enter,
+                Key::Enter
+            ]
+        );
+        assert_eq!(
+            decode(b"\x04\x08\x7f"),
+            [Key::Eof, Key::Backspace, Key::Backspace]
+        );
+    }
+
+    #[test]
+    fn ignores_control_characters() {
+        assert_eq!(decode(b"\x01\x07x\x80"), [Key::Char(b'x')]);
+    }
+
+    #[test]
+    fn arrow_keys() {
+        assert_eq!(
+            decode(b"\x1b[A\x1b[B\x1b[C\x1b[D"),
+            [Key::Up, Key::Down, Key::Right, Key::Left]
+        );
+    }
+
+    #[test]
+    fn home_end_delete() {
+        assert_eq!(
+            decode(b"\x1b[H\x1b[F\x1bOH\x1bOF\x1b[1~\x1b[4~\x1b[7~\x1b[8~\x1b[3~"),
+            [
+                Key::Home,
+                Key::End,
+                Key::Home,
+                Key::End,
+                Key::Home,
+                Key::End,
+                Key::Home,
+                Key::End,
+                Key::Delete
+            ]
+        );
+    }
+
+    #[test]
+    fn unrecognised_sequences() {
+        // Page up, a long parameter list, and an unknown SS3 key are all ignored.
+        assert_eq!(decode(b"\x1b[5~\x1b[1;2;3;4;5~\x1bOPa"), [Key::Char(b'a')]);
+        // An escape followed by an ordinary character drops both.
+        assert_eq!(decode(b"\x1bxy"), [Key::Char(b'y')]);
+    }
+
+    #[test]
+    fn bars() {
+        let bar = |value, max| {
+            Bar {
+                value,
+                max,
+                width: 10,
+            }
+            .to_string()
+        };
+        assert_eq!(bar(0, 100), "[          ]");
+        assert_eq!(bar(55, 100), "[#####     ]");
+        assert_eq!(bar(100, 100), "[##########]");
+        assert_eq!(bar(200, 100), "[##########]");
+        assert_eq!(bar(1, 0), "[          ]");
+    }
+
+    #[test]
+    fn bytes() {
+        assert_eq!(Bytes(0).to_string(), "0 B");
+        assert_eq!(Bytes(1023).to_string(), "1023 B");
+        assert_eq!(Bytes(1024).to_string(), "1.0 KiB");
+        assert_eq!(Bytes(12_800).to_string(), "12.5 KiB");
+        assert_eq!(Bytes(3 * 1024 * 1024).to_string(), "3.0 MiB");
+        assert_eq!(Bytes(u64::MAX).to_string(), "16777215.9 TiB");
+    }
+
+    #[test]
+    fn rates() {
+        assert_eq!(per_second(5, 500_000), 10);
+        assert_eq!(per_second(1, 3_000_000), 0);
+        assert_eq!(per_second(1, 0), 0);
+    }
+}
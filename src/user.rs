@@ -0,0 +1,1406 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Support for running small demo programs embedded in the image at EL0.
+//!
+//! Each run of a program is a process with its own PID. A process can fork a child, which runs
+//! until it exits before its parent continues. Only one process and its forked descendants can
+//! exist at a time, as they share a stack and all run in the same identity-mapped page table.
+//!
+//! While a child is running, the stack is copy-on-write: its pages are read-only, and the first
+//! write to each saves its contents so that they can be restored for the parent once the child
+//! exits. A process can be killed, along with its descendants, which takes effect the next time it
+//! makes a syscall or is interrupted.
+//!
+//! The CPU time each process uses is measured with the generic timer's counter, at each exception
+//! from EL0 and each return to it, and split into time running at EL0 and time in the kernel
+//! handling its syscalls, faults and interrupts. Time spent running a forked child is charged to
+//! the child, and time blocked in `futex_wait` isn't charged to anyone.
+//!
+//! Processes can also map anonymous memory with `mmap`, in a window of virtual addresses which
+//! isn't otherwise used. Nothing is allocated for it until each page is first touched, either by
+//! the process or by the kernel handling a syscall, when the resulting translation fault allocates
+//! a zeroed page from the heap and maps it. A forked child shares its parent's anonymous memory
+//! rather than getting a copy, and a process's anonymous memory is unmapped and freed when it
+//! exits.
+//!
+//! A process can block on a word of its memory with `futex_wait` until another wakes it with
+//! `futex_wake`, sleeping with IRQs unmasked rather than spinning. Only one process runs at a time
+//! for now, so there is nothing else to wake it and waits should use a timeout, but the wait queue
+//! doesn't depend on that.
+
+use crate::{
+    console,
+    exception_trace::{self, TracedException},
+};
+use aarch64_paging::paging::{MemoryRegion, PAGE_SIZE};
+use aarch64_rt::RegisterStateRef;
+use alloc::{
+    alloc::{Layout, alloc_zeroed},
+    boxed::Box,
+    vec::Vec,
+};
+use arm_gic::{irq_disable, irq_enable};
+use core::{
+    arch::{asm, global_asm, naked_asm},
+    fmt::{self, Display, Formatter},
+    future::poll_fn,
+    ptr::{self, null_mut},
+    slice,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicU64, Ordering},
+    task::Poll,
+    time::Duration,
+};
+use embedded_io::Write;
+use log::{debug, info, warn};
+use osdemo_core::{
+    cpus::current_cpu_index,
+    drivers::generic_timer::{self, Instant, ticks_to_duration},
+    exceptions::current_el,
+    executor::{WakerSlot, block_on},
+    fallible::{try_push, try_reserve, try_vec},
+    pagetable::{IdMap, PAGETABLE, virt_to_phys},
+};
+#[cfg(feature = "smp")]
+use osdemo_core::{interrupts::send_sgi_to_all, workqueue::WORK_SGI};
+use spin::mutex::SpinMutex;
+
+/// The number of pages in the stack used by the user program.
+const USER_STACK_PAGES: usize = 4;
+
+/// The size in bytes of the stack used by the user program.
+const USER_STACK_SIZE: usize = USER_STACK_PAGES * PAGE_SIZE;
+
+/// The start of the window of virtual addresses in which anonymous memory is mapped for user
+/// programs. This is above the RAM and devices of the supported platforms, and is checked to be
+/// unused before it is reserved.
+const ANON_WINDOW_START: usize = 0x7f_0000_0000;
+
+/// The number of pages in the anonymous memory window.
+const ANON_WINDOW_PAGES: usize = 256;
+
+/// Exception class for an SVC instruction executed in AArch64 state.
+const ESR_EC_SVC64: u64 = 0x15;
+
+/// Exception class for a data abort from a lower exception level.
+const ESR_EC_DATA_ABORT_LOWER: u64 = 0x24;
+
+/// Exception class for a data abort from the current exception level.
+const ESR_EC_DATA_ABORT_CURRENT: u64 = 0x25;
+
+/// The bit of a data abort's syndrome which is set if it was caused by a write.
+const ESR_ISS_WNR: u64 = 1 << 6;
+
+/// The bits of a data abort's fault status code which identify the type of fault, ignoring the
+/// level.
+const ESR_DFSC_TYPE_MASK: u64 = 0x3c;
+
+/// The fault status code of a translation fault, ignoring the level.
+const ESR_DFSC_TRANSLATION: u64 = 0x04;
+
+/// The fault status code of a permission fault, ignoring the level.
+const ESR_DFSC_PERMISSION: u64 = 0x0c;
+
+/// The syscalls which user programs may make.
+///
+/// The syscall number is passed in `x8` and arguments in `x0` onwards. The result is returned in
+/// `x0`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u64)]
+pub enum Syscall {
+    /// Exits the user program with the status code in `x0`.
+    Exit = 0,
+    /// Writes `x1` bytes from the buffer at `x0` to the console, and returns the number of bytes
+    /// written.
+    Write = 1,
+    /// Forks the process. The child runs first, and returns 0 with the parent's stack pointer but
+    /// with its other registers cleared, so anything it needs must be on the stack. Once the child
+    /// has exited, the parent returns the child's PID.
+    Fork = 2,
+    /// Maps `x0` bytes of zeroed anonymous memory, rounded up to whole pages, and returns its
+    /// address. Each page is only allocated when it is first touched.
+    Mmap = 3,
+    /// Unmaps and frees the anonymous memory at `x0` with length `x1`, which must be the whole of a
+    /// single mapping made by the same process, and returns 0.
+    Munmap = 4,
+    /// Blocks until woken by `FutexWake` on the same address, if the aligned 32-bit word at `x0`
+    /// still has the value `x1`, and returns 0. Fails straight away if the value differs, or after
+    /// `x2` microseconds if it isn't woken first, unless `x2` is 0.
+    FutexWait = 5,
+    /// Wakes up to `x1` processes blocked in `FutexWait` on the address `x0`, and returns how many
+    /// were woken.
+    FutexWake = 6,
+}
+
+impl TryFrom<u64> for Syscall {
+    type Error = u64;
+
+    fn try_from(value: u64) -> Result<Self, u64> {
+        match value {
+            0 => Ok(Self::Exit),
+            1 => Ok(Self::Write),
+            2 => Ok(Self::Fork),
+            3 => Ok(Self::Mmap),
+            4 => Ok(Self::Munmap),
+            5 => Ok(Self::FutexWait),
+            6 => Ok(Self::FutexWake),
+            _ => Err(value),
+        }
+    }
+}
+
+/// Error value returned to the user program from a failed syscall.
+const SYSCALL_ERROR: u64 = u64::MAX;
+
+/// Exit status reported when the user program is terminated due to a fault.
+pub const FAULT_EXIT_STATUS: u64 = u64::MAX;
+
+/// Exit status reported when the user program is terminated by `kill`.
+pub const KILLED_EXIT_STATUS: u64 = u64::MAX - 1;
+
+/// A process ID.
+pub type Pid = u32;
+
+/// A run of a user program which has been created and hasn't yet exited.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Process {
+    pub pid: Pid,
+    pub program: UserProgram,
+    pub argument: u64,
+    /// The index of the CPU core which the process is running on, or `None` if it hasn't started
+    /// yet.
+    pub cpu: Option<usize>,
+    /// The PID of the process which forked this one, if any.
+    pub parent: Option<Pid>,
+    /// Whether the process has been killed, but hasn't yet been stopped.
+    pub killed: bool,
+    /// The CPU time the process has used, as of its last exception or return to EL0.
+    pub times: CpuTimes,
+}
+
+/// The CPU time used by a process.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CpuTimes {
+    /// Generic timer ticks spent running at EL0.
+    user_ticks: u64,
+    /// Generic timer ticks spent in the kernel handling exceptions from the process.
+    kernel_ticks: u64,
+}
+
+impl CpuTimes {
+    /// Returns the time spent running at EL0.
+    pub fn user(&self) -> Duration {
+        ticks_to_duration(self.user_ticks)
+    }
+
+    /// Returns the time spent in the kernel handling exceptions from the process.
+    pub fn kernel(&self) -> Duration {
+        ticks_to_duration(self.kernel_ticks)
+    }
+}
+
+impl Display for CpuTimes {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "user {:?}, kernel {:?}", self.user(), self.kernel())
+    }
+}
+
+/// How a process exited.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ProcessExit {
+    /// The exit status.
+    pub status: u64,
+    /// The CPU time which the process used, not including its children.
+    pub times: CpuTimes,
+}
+
+/// An error creating, running or signalling a process.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProcessError {
+    /// User programs can't be run at the current exception level.
+    UnsupportedEl,
+    /// Another process already exists.
+    Busy(Pid),
+    /// There is no process with the given PID.
+    NoSuchProcess(Pid),
+}
+
+impl Display for ProcessError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::UnsupportedEl => write!(f, "User programs are only supported at EL1"),
+            Self::Busy(pid) => write!(f, "Process {pid} is already running"),
+            Self::NoSuchProcess(pid) => write!(f, "No process {pid}"),
+        }
+    }
+}
+
+/// The processes which haven't yet exited: the one created by `create_process`, followed by each
+/// process forked from the one before it. All but the last are waiting for their child to exit.
+static PROCESSES: SpinMutex<Vec<Process>> = SpinMutex::new(Vec::new());
+
+/// The contents of the user stack pages, as saved for a process waiting for its child to exit.
+///
+/// Only the pages which have been written since the fork are saved.
+type SavedStack = [Option<Vec<u8>>; USER_STACK_PAGES];
+
+/// The saved stack of each process waiting for its child to exit, outermost first.
+static SAVED_STACKS: SpinMutex<Vec<SavedStack>> = SpinMutex::new(Vec::new());
+
+/// A page of anonymous memory.
+#[repr(C, align(4096))]
+struct AnonPage([u8; PAGE_SIZE]);
+
+/// A range of anonymous memory mapped by `mmap`.
+struct AnonMapping {
+    /// The PID of the process which mapped it.
+    owner: Pid,
+    /// The virtual address of the first page.
+    start: usize,
+    /// The page backing each page of the mapping, or `None` if it hasn't been touched yet.
+    pages: Vec<Option<Box<AnonPage>>>,
+}
+
+impl AnonMapping {
+    /// Returns the range of virtual addresses which the mapping covers.
+    fn region(&self) -> MemoryRegion {
+        MemoryRegion::new(self.start, self.start + self.pages.len() * PAGE_SIZE)
+    }
+}
+
+/// Whether the anonymous memory window was reserved in the page table by `map_user_regions`.
+static ANON_WINDOW_RESERVED: AtomicBool = AtomicBool::new(false);
+
+/// The anonymous memory mapped by all processes, in order of address.
+static ANON_MAPPINGS: SpinMutex<Vec<AnonMapping>> = SpinMutex::new(Vec::new());
+
+/// A process blocked in `FutexWait`.
+struct FutexWaiter {
+    pid: Pid,
+    /// The address of the word it is waiting on.
+    address: usize,
+    /// Whether it has been woken by `FutexWake`.
+    woken: bool,
+}
+
+/// The processes blocked in `FutexWait`, in the order they started waiting.
+static FUTEX_WAITERS: SpinMutex<Vec<FutexWaiter>> = SpinMutex::new(Vec::new());
+
+/// Wakes a process blocked in `FutexWait` once it has been woken or killed.
+static FUTEX_WAKER: WakerSlot = WakerSlot::new();
+
+/// The PID to give the next process created.
+static NEXT_PID: AtomicU32 = AtomicU32::new(1);
+
+/// The generic timer counter value when CPU time was last charged to the running process.
+static TIME_MARK: AtomicU64 = AtomicU64::new(0);
+
+/// Whether the running process has been killed, but hasn't yet been stopped.
+static KILL_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Page-aligned stack for the user program.
+#[repr(C, align(4096))]
+struct UserStack([u8; USER_STACK_SIZE]);
+
+static mut USER_STACK: UserStack = UserStack([0; USER_STACK_SIZE]);
+
+/// Callee-saved kernel state to restore when the user program exits.
+#[derive(Debug, Default)]
+#[repr(C)]
+struct KernelContext {
+    /// x19 to x30.
+    registers: [u64; 12],
+    sp: u64,
+}
+
+/// The output of the currently running user program, if it is being captured rather than written to
+/// the console.
+static CAPTURED_OUTPUT: SpinMutex<Option<Vec<u8>>> = SpinMutex::new(None);
+
+/// The kernel context to return to when the currently running user program exits, or null if no
+/// user program is running.
+static KERNEL_CONTEXT: AtomicPtr<KernelContext> = AtomicPtr::new(null_mut());
+
+/// The trace of the exception from EL0 which is being handled for the currently running user
+/// program, so that its exit can still be recorded if the program is stopped rather than returned
+/// to.
+static LOWER_EXCEPTION_TRACE: SpinMutex<Option<TracedException>> = SpinMutex::new(None);
+
+/// The demo programs embedded in the image, which can be run at EL0.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UserProgram {
+    /// Prints a greeting and exits with status 42.
+    Hello,
+    /// Prints `y` on a line the number of times given by its argument, and exits with status 0.
+    Yes,
+    /// Reads from an unmapped address, so is terminated with a fault.
+    Fault,
+    /// Forks, overwrites a message on the stack in the child, and prints the message in both, to
+    /// show that the child's writes don't affect the parent. Exits with status 0.
+    Fork,
+    /// Maps two pages of anonymous memory, copies a message to the second so that only it is
+    /// allocated, prints the message from there, and unmaps them. Exits with status 0, or 1 if the
+    /// mapping failed.
+    Mmap,
+    /// Waits on a word on its stack for a value it doesn't have, and then for the value it has with
+    /// a 1 ms timeout, as there is nothing else to wake it. Prints a message once that times out,
+    /// and then wakes the word. Exits with status 0 if each syscall returned what was expected, or
+    /// 1 otherwise.
+    Futex,
+}
+
+impl UserProgram {
+    /// All the user programs.
+    pub const ALL: [Self; 6] = [
+        Self::Hello,
+        Self::Yes,
+        Self::Fault,
+        Self::Fork,
+        Self::Mmap,
+        Self::Futex,
+    ];
+
+    /// Returns the name of the program, as used by the `exec` command.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Hello => "hello",
+            Self::Yes => "yes",
+            Self::Fault => "fault",
+            Self::Fork => "fork",
+            Self::Mmap => "mmap",
+            Self::Futex => "futex",
+        }
+    }
+
+    /// Returns the program with the given name, if there is one.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|program| program.name() == name)
+    }
+
+    /// Returns the argument passed to the program if none is given.
+    pub fn default_argument(self) -> u64 {
+        match self {
+            Self::Yes => 10,
+            Self::Hello | Self::Fault | Self::Fork | Self::Mmap | Self::Futex => 0,
+        }
+    }
+
+    /// Returns the address of the program's entry point.
+    fn entry(self) -> usize {
+        match self {
+            Self::Hello => &raw const user_hello_entry as usize,
+            Self::Yes => &raw const user_yes_entry as usize,
+            Self::Fault => &raw const user_fault_entry as usize,
+            Self::Fork => &raw const user_fork_entry as usize,
+            Self::Mmap => &raw const user_mmap_entry as usize,
+            Self::Futex => &raw const user_futex_entry as usize,
+        }
+    }
+}
+
+// The embedded user programs. These are placed in their own page-aligned section so that they can
+// be mapped as accessible from EL0 without exposing any kernel code or data. Each is entered with
+// its argument in x0.
+global_asm!(
+    ".pushsection .text.user_program, \"ax\"",
+    ".balign 4096",
+    ".global user_program_start",
+    "user_program_start:",
+    "user_message:",
+    ".ascii \"Hello from EL0!\\n\"",
+    "user_message_end:",
+    ".balign 4",
+    ".global user_hello_entry",
+    "user_hello_entry:",
+    "adr x0, user_message",
+    "mov x1, #(user_message_end - user_message)",
+    "mov x8, #{write}",
+    "svc #0",
+    "mov x0, #42",
+    "mov x8, #{exit}",
+    "svc #0",
+    "b .",
+    "user_yes_message:",
+    ".ascii \"y\\n\"",
+    "user_yes_message_end:",
+    ".balign 4",
+    ".global user_yes_entry",
+    "user_yes_entry:",
+    "mov x19, x0",
+    "1:",
+    "cbz x19, 2f",
+    "adr x0, user_yes_message",
+    "mov x1, #(user_yes_message_end - user_yes_message)",
+    "mov x8, #{write}",
+    "svc #0",
+    "sub x19, x19, #1",
+    "b 1b",
+    "2:",
+    "mov x0, #0",
+    "mov x8, #{exit}",
+    "svc #0",
+    "b .",
+    ".global user_fault_entry",
+    "user_fault_entry:",
+    "mov x0, #0",
+    "ldr x0, [x0]",
+    "b .",
+    ".balign 8",
+    "user_fork_parent_message:",
+    ".ascii \"parent\\n\\0\"",
+    "user_fork_child_message:",
+    ".ascii \"child\\n\\0\\0\"",
+    ".global user_fork_entry",
+    "user_fork_entry:",
+    // Put the parent's message on the stack, where the child overwrites it.
+    "sub sp, sp, #16",
+    "adr x9, user_fork_parent_message",
+    "ldr x9, [x9]",
+    "str x9, [sp]",
+    "mov x8, #{fork}",
+    "svc #0",
+    "cbnz x0, 1f",
+    "adr x9, user_fork_child_message",
+    "ldr x9, [x9]",
+    "str x9, [sp]",
+    "mov x0, sp",
+    "mov x1, #6",
+    "mov x8, #{write}",
+    "svc #0",
+    "mov x0, #0",
+    "mov x8, #{exit}",
+    "svc #0",
+    "b .",
+    // The parent's copy of the message is unchanged once the child has exited.
+    "1:",
+    "mov x0, sp",
+    "mov x1, #7",
+    "mov x8, #{write}",
+    "svc #0",
+    "mov x0, #0",
+    "mov x8, #{exit}",
+    "svc #0",
+    "b .",
+    "user_mmap_message:",
+    ".ascii \"mapped\\n\\0\"",
+    ".balign 4",
+    ".global user_mmap_entry",
+    "user_mmap_entry:",
+    "mov x0, #(2 * {page_size})",
+    "mov x8, #{mmap}",
+    "svc #0",
+    "cmn x0, #1",
+    "b.eq 1f",
+    "mov x19, x0",
+    // The first write to the second page allocates it.
+    "adr x9, user_mmap_message",
+    "ldr x9, [x9]",
+    "str x9, [x19, #{page_size}]",
+    "add x0, x19, #{page_size}",
+    "mov x1, #7",
+    "mov x8, #{write}",
+    "svc #0",
+    "mov x0, x19",
+    "mov x1, #(2 * {page_size})",
+    "mov x8, #{munmap}",
+    "svc #0",
+    "mov x0, #0",
+    "mov x8, #{exit}",
+    "svc #0",
+    "b .",
+    "1:",
+    "mov x0, #1",
+    "mov x8, #{exit}",
+    "svc #0",
+    "b .",
+    "user_futex_message:",
+    ".ascii \"timed out\\n\"",
+    "user_futex_message_end:",
+    ".balign 4",
+    ".global user_futex_entry",
+    "user_futex_entry:",
+    "sub sp, sp, #16",
+    "str wzr, [sp]",
+    // The word isn't 1, so this fails straight away.
+    "mov x0, sp",
+    "mov x1, #1",
+    "mov x2, #0",
+    "mov x8, #{futex_wait}",
+    "svc #0",
+    "cmn x0, #1",
+    "b.ne 1f",
+    // The word is 0, but nothing wakes it, so this times out.
+    "mov x0, sp",
+    "mov x1, #0",
+    "mov x2, #1000",
+    "mov x8, #{futex_wait}",
+    "svc #0",
+    "cmn x0, #1",
+    "b.ne 1f",
+    "adr x0, user_futex_message",
+    "mov x1, #(user_futex_message_end - user_futex_message)",
+    "mov x8, #{write}",
+    "svc #0",
+    // Nothing is waiting any more, so this wakes nothing.
+    "mov x0, sp",
+    "mov x1, #1",
+    "mov x8, #{futex_wake}",
+    "svc #0",
+    "cbnz x0, 1f",
+    "mov x0, #0",
+    "mov x8, #{exit}",
+    "svc #0",
+    "b .",
+    "1:",
+    "mov x0, #1",
+    "mov x8, #{exit}",
+    "svc #0",
+    "b .",
+    ".balign 4096",
+    ".global user_program_end",
+    "user_program_end:",
+    ".popsection",
+    write = const Syscall::Write as u64,
+    exit = const Syscall::Exit as u64,
+    fork = const Syscall::Fork as u64,
+    mmap = const Syscall::Mmap as u64,
+    munmap = const Syscall::Munmap as u64,
+    futex_wait = const Syscall::FutexWait as u64,
+    futex_wake = const Syscall::FutexWake as u64,
+    page_size = const PAGE_SIZE,
+);
+
+unsafe extern "C" {
+    static user_program_start: u8;
+    static user_hello_entry: u8;
+    static user_yes_entry: u8;
+    static user_fault_entry: u8;
+    static user_fork_entry: u8;
+    static user_mmap_entry: u8;
+    static user_futex_entry: u8;
+    static user_program_end: u8;
+}
+
+/// Returns the memory region containing the user program code.
+fn user_program_region() -> MemoryRegion {
+    MemoryRegion::new(
+        &raw const user_program_start as usize,
+        &raw const user_program_end as usize,
+    )
+}
+
+/// Returns the memory region used for the user program stack.
+fn user_stack_region() -> MemoryRegion {
+    let start = &raw const USER_STACK as usize;
+    MemoryRegion::new(start, start + USER_STACK_SIZE)
+}
+
+/// Returns the window of virtual addresses in which anonymous memory is mapped.
+fn anon_window_region() -> MemoryRegion {
+    MemoryRegion::new(
+        ANON_WINDOW_START,
+        ANON_WINDOW_START + ANON_WINDOW_PAGES * PAGE_SIZE,
+    )
+}
+
+/// Returns a pointer to the start of the given page of the user program stack.
+fn user_stack_page(index: usize) -> *mut u8 {
+    (&raw mut USER_STACK)
+        .cast::<u8>()
+        .wrapping_add(index * PAGE_SIZE)
+}
+
+/// Makes the given pages of the user program stack read-only or writable again.
+fn set_user_stack_read_only(pages: &MemoryRegion, read_only: bool) {
+    PAGETABLE
+        .get()
+        .expect("Page table not yet activated")
+        .lock()
+        .set_user_data_read_only(pages, read_only)
+        .expect("Failed to change user stack permissions");
+}
+
+/// Maps the user program and its stack so that they are accessible from EL0.
+///
+/// This must be called after normal memory has been mapped, as it replaces the kernel-only
+/// mappings for the relevant pages.
+pub fn map_user_regions(idmap: &mut IdMap) {
+    if current_el() != 1 {
+        info!("Not mapping user program, as EL0 is only supported when running at EL1");
+        return;
+    }
+    let program = user_program_region();
+    let stack = user_stack_region();
+    info!("Mapping user program {program} and stack {stack}");
+    idmap.map_user(&program, true).unwrap();
+    idmap.map_user(&stack, false).unwrap();
+
+    let window = anon_window_region();
+    if window.end().0 > idmap.size()
+        || idmap.translate(window.start().0).is_some()
+        || idmap.translate(window.end().0 - 1).is_some()
+    {
+        warn!("Not reserving anonymous memory window {window}, as it is already in use");
+        return;
+    }
+    info!("Reserving anonymous memory window {window}");
+    idmap.reserve_user_range(&window).unwrap();
+    ANON_WINDOW_RESERVED.store(true, Ordering::Relaxed);
+}
+
+/// Creates a process to run the given embedded user program with the given argument, and returns
+/// its PID.
+///
+/// The process doesn't start until `run_process` is called with its PID.
+pub fn create_process(program: UserProgram, argument: u64) -> Result<Pid, ProcessError> {
+    if current_el() != 1 {
+        return Err(ProcessError::UnsupportedEl);
+    }
+    let mut processes = PROCESSES.lock();
+    if let Some(existing) = processes.first() {
+        return Err(ProcessError::Busy(existing.pid));
+    }
+    let pid = NEXT_PID.fetch_add(1, Ordering::Relaxed);
+    KILL_PENDING.store(false, Ordering::SeqCst);
+    processes.push(Process {
+        pid,
+        program,
+        argument,
+        cpu: None,
+        parent: None,
+        killed: false,
+        times: CpuTimes::default(),
+    });
+    Ok(pid)
+}
+
+/// Runs the process with the given PID at EL0 on the current core until it exits, and returns its
+/// exit status and the CPU time it used.
+///
+/// If the process was killed before it started, it isn't run and `KILLED_EXIT_STATUS` is returned.
+pub fn run_process(pid: Pid) -> Result<ProcessExit, ProcessError> {
+    let (program, argument) = {
+        let mut processes = PROCESSES.lock();
+        let process = processes
+            .first_mut()
+            .filter(|process| process.pid == pid && process.cpu.is_none())
+            .ok_or(ProcessError::NoSuchProcess(pid))?;
+        process.cpu = Some(current_cpu_index());
+        (process.program, process.argument)
+    };
+    let status = if KILL_PENDING.load(Ordering::SeqCst) {
+        KILLED_EXIT_STATUS
+    } else {
+        let mut context = KernelContext::default();
+        let stack_top = user_stack_region().end().0;
+        KERNEL_CONTEXT.store(&mut context, Ordering::SeqCst);
+        reset_time_mark();
+        // SAFETY: The user program and stack have been mapped for EL0 by `map_user_regions`, and
+        // the context will be restored when the program exits.
+        let status = unsafe { enter_user(program.entry(), stack_top, &mut context, argument) };
+        KERNEL_CONTEXT.store(null_mut(), Ordering::SeqCst);
+        // The user program exits from an exception handler, which leaves IRQs masked.
+        irq_enable();
+        status
+    };
+    release_anon_memory(pid);
+    let times = {
+        let mut processes = PROCESSES.lock();
+        let times = processes.first().map(|process| process.times);
+        processes.clear();
+        times.unwrap_or_default()
+    };
+    Ok(ProcessExit { status, times })
+}
+
+/// Runs the given embedded user program at EL0 with the given argument until it exits, and returns
+/// its exit status.
+pub fn run_user_program(program: UserProgram, argument: u64) -> Result<u64, ProcessError> {
+    Ok(run_process(create_process(program, argument)?)?.status)
+}
+
+/// Runs the given embedded user program like `run_user_program`, but collects what it writes
+/// rather than writing it to the console, and returns that along with its exit status.
+pub fn run_user_program_captured(
+    program: UserProgram,
+    argument: u64,
+) -> Result<(u64, Vec<u8>), ProcessError> {
+    let pid = create_process(program, argument)?;
+    *CAPTURED_OUTPUT.lock() = Some(Vec::new());
+    let status = run_process(pid);
+    let output = CAPTURED_OUTPUT.lock().take().unwrap_or_default();
+    Ok((status?.status, output))
+}
+
+/// Returns the processes which haven't yet exited, each followed by its child.
+pub fn processes() -> Vec<Process> {
+    PROCESSES.lock().clone()
+}
+
+/// Kills the process with the given PID, along with its descendants.
+///
+/// The running process is stopped the next time it makes a syscall or is interrupted, and then each
+/// of its ancestors which was killed is stopped in turn, all with exit status `KILLED_EXIT_STATUS`.
+/// A process which hasn't started yet is removed straight away, so `run_process` won't run it.
+pub fn kill(pid: Pid) -> Result<(), ProcessError> {
+    let mut processes = PROCESSES.lock();
+    let index = processes
+        .iter()
+        .position(|process| process.pid == pid)
+        .ok_or(ProcessError::NoSuchProcess(pid))?;
+    if processes[index].cpu.is_none() {
+        // Only the root process can be waiting to start, as children start as they are created.
+        processes.clear();
+        return Ok(());
+    }
+    // Descendants run on the same stack, so can't outlive their ancestors.
+    for process in &mut processes[index..] {
+        process.killed = true;
+    }
+    KILL_PENDING.store(true, Ordering::SeqCst);
+    interrupt_process_cpu(&processes[index]);
+    Ok(())
+}
+
+/// Interrupts the core running the given process, if it is another core, in case the process isn't
+/// making any syscalls.
+#[cfg(feature = "smp")]
+fn interrupt_process_cpu(process: &Process) {
+    if process.cpu.is_some_and(|cpu| cpu != current_cpu_index()) {
+        // Secondary cores started by `start_cpu` handle the work SGI, and there is no way to target
+        // a single core yet.
+        send_sgi_to_all(WORK_SGI);
+    }
+}
+
+#[cfg(not(feature = "smp"))]
+fn interrupt_process_cpu(_process: &Process) {}
+
+/// Stops the running process if it has been killed.
+///
+/// This should be called before returning to EL0 from an IRQ.
+pub fn deliver_signals() {
+    if KILL_PENDING.load(Ordering::SeqCst) && !KERNEL_CONTEXT.load(Ordering::SeqCst).is_null() {
+        exit_user_program(KILLED_EXIT_STATUS);
+    }
+}
+
+/// Saves the callee-saved registers to `context` and then jumps to `entry` at EL0 with the given
+/// stack pointer, passing `argument` in `x0`.
+///
+/// Returns the exit status once `exit_to_kernel` is called with the same context.
+///
+/// # Safety
+///
+/// `entry` and `stack_top` must be mapped appropriately for EL0, and `context` must remain valid
+/// until the user program exits.
+#[unsafe(naked)]
+unsafe extern "C" fn enter_user(
+    entry: usize,
+    stack_top: usize,
+    context: *mut KernelContext,
+    argument: u64,
+) -> u64 {
+    naked_asm!(
+        "stp x19, x20, [x2, #0]",
+        "stp x21, x22, [x2, #16]",
+        "stp x23, x24, [x2, #32]",
+        "stp x25, x26, [x2, #48]",
+        "stp x27, x28, [x2, #64]",
+        "stp x29, x30, [x2, #80]",
+        "mov x9, sp",
+        "str x9, [x2, #96]",
+        "msr sp_el0, x1",
+        "msr elr_el1, x0",
+        // EL0t with all exceptions unmasked.
+        "msr spsr_el1, xzr",
+        // Avoid leaking kernel state to the user program.
+        "mov x0, x3",
+        "mov x1, xzr",
+        "mov x2, xzr",
+        "mov x3, xzr",
+        "mov x9, xzr",
+        "mov x19, xzr",
+        "mov x20, xzr",
+        "mov x21, xzr",
+        "mov x22, xzr",
+        "mov x23, xzr",
+        "mov x24, xzr",
+        "mov x25, xzr",
+        "mov x26, xzr",
+        "mov x27, xzr",
+        "mov x28, xzr",
+        "mov x29, xzr",
+        "mov x30, xzr",
+        "eret",
+    )
+}
+
+/// Restores the kernel context saved by `enter_user`, making it return `status`.
+///
+/// # Safety
+///
+/// `context` must have been saved by `enter_user`, which must not yet have returned.
+#[unsafe(naked)]
+unsafe extern "C" fn exit_to_kernel(context: *const KernelContext, status: u64) -> ! {
+    naked_asm!(
+        "ldp x19, x20, [x0, #0]",
+        "ldp x21, x22, [x0, #16]",
+        "ldp x23, x24, [x0, #32]",
+        "ldp x25, x26, [x0, #48]",
+        "ldp x27, x28, [x0, #64]",
+        "ldp x29, x30, [x0, #80]",
+        "ldr x9, [x0, #96]",
+        "mov sp, x9",
+        "mov x0, x1",
+        "ret",
+    )
+}
+
+/// Abandons the current user program and returns to the kernel with the given exit status.
+fn exit_user_program(status: u64) -> ! {
+    charge_time(false);
+    // The exception handler won't return, so record its exit here.
+    trace_lower_exception_exit();
+    let context = KERNEL_CONTEXT.load(Ordering::SeqCst);
+    assert!(!context.is_null(), "No user program running");
+    // SAFETY: `KERNEL_CONTEXT` is only set while `enter_user` is running.
+    unsafe { exit_to_kernel(context, status) }
+}
+
+/// Records entry to the exception handler for an exception from EL0, as returned by
+/// `exception_trace::entry`.
+///
+/// This must be paired with a call to `trace_lower_exception_exit` when the handler returns. If the
+/// handler stops the running process instead, the exit is recorded when it does so.
+pub fn trace_lower_exception_entry(traced: Option<TracedException>) {
+    *LOWER_EXCEPTION_TRACE.lock() = traced;
+}
+
+/// Records exit from the exception handler for an exception from EL0 which was passed to
+/// `trace_lower_exception_entry`.
+pub fn trace_lower_exception_exit() {
+    let traced = LOWER_EXCEPTION_TRACE.lock().take();
+    exception_trace::exit(traced);
+}
+
+/// Charges the CPU time since it was last charged to the running process, as user time if it was
+/// running at EL0 or kernel time otherwise.
+fn charge_time(user: bool) {
+    let now = Instant::now().ticks();
+    let elapsed = now.saturating_sub(TIME_MARK.swap(now, Ordering::Relaxed));
+    if let Some(process) = PROCESSES.lock().last_mut() {
+        if user {
+            process.times.user_ticks += elapsed;
+        } else {
+            process.times.kernel_ticks += elapsed;
+        }
+    }
+}
+
+/// Starts measuring CPU time from now, without charging the time since it was last charged to
+/// anyone.
+fn reset_time_mark() {
+    TIME_MARK.store(Instant::now().ticks(), Ordering::Relaxed);
+}
+
+/// Charges the time the running process has spent at EL0 since it last entered it.
+///
+/// This must be called on each exception from EL0, before handling it.
+pub fn account_exception_entry() {
+    charge_time(true);
+}
+
+/// Charges the time the kernel has spent handling an exception from the running process.
+///
+/// This must be called before returning to EL0 from each exception.
+pub fn account_exception_return() {
+    charge_time(false);
+}
+
+/// Handles a synchronous exception from EL0, either a syscall or a fault.
+pub fn handle_sync_lower(register_state: RegisterStateRef, esr: u64, far: u64) {
+    account_exception_entry();
+    handle_sync_lower_exception(register_state, esr, far);
+    account_exception_return();
+}
+
+/// Handles a syscall or fault from EL0, returning if the process should continue.
+fn handle_sync_lower_exception(mut register_state: RegisterStateRef, esr: u64, far: u64) {
+    if esr >> 26 != ESR_EC_SVC64 {
+        if handle_stack_write_fault(esr, far) || handle_anon_fault(esr, far) {
+            return;
+        }
+        warn!(
+            "User program fault, esr={esr:#x}, far={far:#x}, elr={:#x}",
+            register_state.elr
+        );
+        exit_user_program(FAULT_EXIT_STATUS);
+    }
+    deliver_signals();
+
+    let syscall = register_state.registers[8];
+    let result = match Syscall::try_from(syscall) {
+        Ok(Syscall::Exit) => exit_user_program(register_state.registers[0]),
+        Ok(Syscall::Write) => {
+            syscall_write(register_state.registers[0], register_state.registers[1])
+        }
+        Ok(Syscall::Fork) => syscall_fork(register_state.elr),
+        Ok(Syscall::Mmap) => syscall_mmap(register_state.registers[0]),
+        Ok(Syscall::Munmap) => {
+            syscall_munmap(register_state.registers[0], register_state.registers[1])
+        }
+        Ok(Syscall::FutexWait) => syscall_futex_wait(
+            register_state.registers[0],
+            register_state.registers[1],
+            register_state.registers[2],
+        ),
+        Ok(Syscall::FutexWake) => {
+            syscall_futex_wake(register_state.registers[0], register_state.registers[1])
+        }
+        Err(number) => {
+            warn!("Unknown syscall {number}");
+            SYSCALL_ERROR
+        }
+    };
+    // SAFETY: We only modify x0 to return the result, which doesn't affect the kernel.
+    unsafe {
+        register_state.get_mut().registers[0] = result;
+    }
+}
+
+/// Checks that the given user buffer is entirely within memory accessible to the user program, and
+/// returns its address and length if so.
+fn user_buffer(address: u64, length: u64) -> Option<(usize, usize)> {
+    let (Ok(address), Ok(length)) = (usize::try_from(address), usize::try_from(length)) else {
+        return None;
+    };
+    let buffer_region = MemoryRegion::new(address, address.checked_add(length)?);
+    let contains_buffer = |region: &MemoryRegion| {
+        region.start() <= buffer_region.start() && buffer_region.end() <= region.end()
+    };
+    if [user_program_region(), user_stack_region()]
+        .iter()
+        .any(contains_buffer)
+        || ANON_MAPPINGS
+            .lock()
+            .iter()
+            .any(|mapping| contains_buffer(&mapping.region()))
+    {
+        Some((address, length))
+    } else {
+        None
+    }
+}
+
+/// Writes the given user buffer to the console.
+fn syscall_write(address: u64, length: u64) -> u64 {
+    let Some((address, length)) = user_buffer(address, length) else {
+        return SYSCALL_ERROR;
+    };
+    // SAFETY: We just checked that the buffer is entirely within memory accessible to the user
+    // program, which is mapped and not mutably aliased while we are handling the syscall. Any
+    // anonymous memory which hasn't been touched yet is allocated by `handle_anon_fault` when we
+    // read it.
+    let buffer = unsafe { core::slice::from_raw_parts(address as *const u8, length) };
+    if let Some(output) = CAPTURED_OUTPUT.lock().as_mut() {
+        if output.try_reserve(length).is_err() {
+            return SYSCALL_ERROR;
+        }
+        output.extend_from_slice(buffer);
+        return length as u64;
+    }
+    let Some(mut console) = console::shared() else {
+        return SYSCALL_ERROR;
+    };
+    match console.write_all(buffer) {
+        Ok(()) => length as u64,
+        Err(_) => SYSCALL_ERROR,
+    }
+}
+
+/// Forks the running process, and runs the child at the given address until it exits. Returns the
+/// child's PID, or `SYSCALL_ERROR` if there wasn't enough memory to create it.
+///
+/// The child runs nested within this syscall, on the same kernel stack, so the parent's registers
+/// are preserved in its exception frame and in the callee-saved registers of this function.
+fn syscall_fork(return_address: usize) -> u64 {
+    let stack_pointer = user_stack_pointer();
+    // Charge the parent for the syscall so far, so that the child is only charged for its own time.
+    charge_time(false);
+    let child = {
+        let mut processes = PROCESSES.lock();
+        let parent = processes.last().expect("No process running").clone();
+        let child = Process {
+            pid: NEXT_PID.fetch_add(1, Ordering::Relaxed),
+            parent: Some(parent.pid),
+            killed: false,
+            times: CpuTimes::default(),
+            ..parent
+        };
+        let pid = child.pid;
+        if try_push(&mut processes, child).is_err() {
+            return SYSCALL_ERROR;
+        }
+        if try_push(&mut SAVED_STACKS.lock(), SavedStack::default()).is_err() {
+            processes.pop();
+            return SYSCALL_ERROR;
+        }
+        pid
+    };
+    set_user_stack_read_only(&user_stack_region(), true);
+
+    let parent_context = KERNEL_CONTEXT.load(Ordering::SeqCst);
+    let parent_trace = LOWER_EXCEPTION_TRACE.lock().take();
+    let mut context = KernelContext::default();
+    KERNEL_CONTEXT.store(&mut context, Ordering::SeqCst);
+    // SAFETY: The child runs the parent's code on the parent's stack, which are mapped for EL0, and
+    // the context will be restored when it exits.
+    let status = unsafe { enter_user(return_address, stack_pointer, &mut context, 0) };
+    KERNEL_CONTEXT.store(parent_context, Ordering::SeqCst);
+    *LOWER_EXCEPTION_TRACE.lock() = parent_trace;
+    set_user_stack_pointer(stack_pointer);
+
+    let restored = restore_user_stack();
+    release_anon_memory(child);
+    let child_times = {
+        let mut processes = PROCESSES.lock();
+        let child_times = processes.pop().map(|process| process.times);
+        let parent_killed = processes.last().is_some_and(|process| process.killed);
+        KILL_PENDING.store(parent_killed, Ordering::SeqCst);
+        child_times.unwrap_or_default()
+    };
+    debug!(
+        "Process {child} exited with status {status} after {child_times}, having written \
+         {restored} of {USER_STACK_PAGES} stack pages"
+    );
+    deliver_signals();
+    child.into()
+}
+
+/// Maps the given number of bytes of anonymous memory for the running process, rounded up to whole
+/// pages, and returns its address. Returns `SYSCALL_ERROR` if the length is 0, there isn't a large
+/// enough gap in the window, or there wasn't enough memory to track the mapping.
+///
+/// The pages are allocated by `handle_anon_fault` when they are first touched.
+fn syscall_mmap(length: u64) -> u64 {
+    let Ok(length) = usize::try_from(length) else {
+        return SYSCALL_ERROR;
+    };
+    let page_count = length.div_ceil(PAGE_SIZE);
+    if !ANON_WINDOW_RESERVED.load(Ordering::Relaxed)
+        || page_count == 0
+        || page_count > ANON_WINDOW_PAGES
+    {
+        return SYSCALL_ERROR;
+    }
+    let owner = PROCESSES.lock().last().expect("No process running").pid;
+    let mut mappings = ANON_MAPPINGS.lock();
+    // Find the first gap which is large enough.
+    let size = page_count * PAGE_SIZE;
+    let mut start = ANON_WINDOW_START;
+    let mut index = mappings.len();
+    for (i, mapping) in mappings.iter().enumerate() {
+        if mapping.start - start >= size {
+            index = i;
+            break;
+        }
+        start = mapping.region().end().0;
+    }
+    if start + size > anon_window_region().end().0 {
+        return SYSCALL_ERROR;
+    }
+    let mut pages = Vec::new();
+    if try_reserve(&mut pages, page_count).is_err() || try_reserve(&mut mappings, 1).is_err() {
+        return SYSCALL_ERROR;
+    }
+    pages.resize_with(page_count, || None);
+    mappings.insert(
+        index,
+        AnonMapping {
+            owner,
+            start,
+            pages,
+        },
+    );
+    start as u64
+}
+
+/// Unmaps and frees the anonymous memory mapped by the running process at the given address, which
+/// must have the given length. Returns 0, or `SYSCALL_ERROR` if there is no such mapping.
+fn syscall_munmap(address: u64, length: u64) -> u64 {
+    let (Ok(address), Ok(length)) = (usize::try_from(address), usize::try_from(length)) else {
+        return SYSCALL_ERROR;
+    };
+    let owner = PROCESSES.lock().last().expect("No process running").pid;
+    let mut mappings = ANON_MAPPINGS.lock();
+    let Some(index) = mappings.iter().position(|mapping| {
+        mapping.owner == owner
+            && mapping.start == address
+            && mapping.pages.len() == length.div_ceil(PAGE_SIZE)
+    }) else {
+        return SYSCALL_ERROR;
+    };
+    unmap_anon(mappings.remove(index));
+    0
+}
+
+/// Blocks the running process until it is woken by `syscall_futex_wake` on the given address, if
+/// the 32-bit word there has the expected value. Returns 0 once woken, or `SYSCALL_ERROR` if the
+/// value differs, the address isn't aligned user memory, or the timeout in microseconds expires
+/// first.
+fn syscall_futex_wait(address: u64, expected: u64, timeout_us: u64) -> u64 {
+    let Some((address, _)) = user_buffer(address, size_of::<u32>() as u64) else {
+        return SYSCALL_ERROR;
+    };
+    if !address.is_multiple_of(align_of::<u32>()) {
+        return SYSCALL_ERROR;
+    }
+    // SAFETY: We just checked that the word is aligned and within memory accessible to the user
+    // program, which is mapped. It is only accessed atomically while we are handling the syscall.
+    let word = unsafe { AtomicU32::from_ptr(address as *mut u32) };
+    let pid = PROCESSES.lock().last().expect("No process running").pid;
+    {
+        // Check the value with the lock held, so that a wake can't be missed between checking it
+        // and waiting.
+        let mut waiters = FUTEX_WAITERS.lock();
+        if u64::from(word.load(Ordering::SeqCst)) != expected {
+            return SYSCALL_ERROR;
+        }
+        let waiter = FutexWaiter {
+            pid,
+            address,
+            woken: false,
+        };
+        if try_push(&mut waiters, waiter).is_err() {
+            return SYSCALL_ERROR;
+        }
+    }
+    let woken_or_killed = poll_fn(|context| {
+        FUTEX_WAKER.register(context.waker());
+        if KILL_PENDING.load(Ordering::SeqCst)
+            || FUTEX_WAITERS
+                .lock()
+                .iter()
+                .any(|waiter| waiter.pid == pid && waiter.woken)
+        {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    });
+    // IRQs are masked while handling the syscall, but the timer and `kill` need them to interrupt
+    // the wait. The process isn't using the CPU while it waits.
+    charge_time(false);
+    irq_enable();
+    if timeout_us == 0 {
+        block_on(woken_or_killed);
+    } else {
+        let _ = block_on(generic_timer::timeout(
+            Duration::from_micros(timeout_us),
+            woken_or_killed,
+        ));
+    }
+    irq_disable();
+    reset_time_mark();
+
+    let woken = {
+        let mut waiters = FUTEX_WAITERS.lock();
+        let index = waiters
+            .iter()
+            .position(|waiter| waiter.pid == pid)
+            .expect("Futex waiter missing");
+        waiters.remove(index).woken
+    };
+    deliver_signals();
+    if woken { 0 } else { SYSCALL_ERROR }
+}
+
+/// Wakes up to the given number of processes blocked in `syscall_futex_wait` on the given address,
+/// and returns how many were woken.
+fn syscall_futex_wake(address: u64, count: u64) -> u64 {
+    let Ok(address) = usize::try_from(address) else {
+        return SYSCALL_ERROR;
+    };
+    let count = usize::try_from(count).unwrap_or(usize::MAX);
+    let mut woken = 0;
+    for waiter in FUTEX_WAITERS
+        .lock()
+        .iter_mut()
+        .filter(|waiter| waiter.address == address && !waiter.woken)
+        .take(count)
+    {
+        waiter.woken = true;
+        woken += 1;
+    }
+    if woken > 0 {
+        FUTEX_WAKER.wake();
+    }
+    woken as u64
+}
+
+/// Unmaps and frees all the anonymous memory mapped by the process with the given PID.
+fn release_anon_memory(pid: Pid) {
+    let mut mappings = ANON_MAPPINGS.lock();
+    let mut index = 0;
+    while index < mappings.len() {
+        if mappings[index].owner == pid {
+            unmap_anon(mappings.remove(index));
+        } else {
+            index += 1;
+        }
+    }
+}
+
+/// Unmaps the given anonymous memory, and then frees its pages by dropping it.
+fn unmap_anon(mapping: AnonMapping) {
+    PAGETABLE
+        .get()
+        .expect("Page table not yet activated")
+        .lock()
+        .unmap_user_data(&mapping.region())
+        .expect("Failed to unmap anonymous memory");
+}
+
+/// Handles a translation fault on a page of anonymous memory which hasn't been touched yet, from
+/// either the running process or the kernel accessing its memory, by allocating a zeroed page and
+/// mapping it.
+///
+/// Returns false if the exception wasn't such a fault, or there wasn't enough memory for the page.
+/// This doesn't log anything, as the kernel may be holding the console lock.
+pub fn handle_anon_fault(esr: u64, far: u64) -> bool {
+    let exception_class = esr >> 26;
+    if (exception_class != ESR_EC_DATA_ABORT_LOWER && exception_class != ESR_EC_DATA_ABORT_CURRENT)
+        || esr & ESR_DFSC_TYPE_MASK != ESR_DFSC_TRANSLATION
+    {
+        return false;
+    }
+    let Ok(far) = usize::try_from(far) else {
+        return false;
+    };
+    let mut mappings = ANON_MAPPINGS.lock();
+    let Some(mapping) = mappings.iter_mut().find(|mapping| {
+        let region = mapping.region();
+        (region.start().0..region.end().0).contains(&far)
+    }) else {
+        return false;
+    };
+    let index = (far - mapping.start) / PAGE_SIZE;
+    if mapping.pages[index].is_some() {
+        return false;
+    }
+    let Some(page) = alloc_zeroed_page() else {
+        return false;
+    };
+    let page_start = mapping.start + index * PAGE_SIZE;
+    PAGETABLE
+        .get()
+        .expect("Page table not yet activated")
+        .lock()
+        .map_user_data(
+            &MemoryRegion::new(page_start, page_start + PAGE_SIZE),
+            virt_to_phys(&raw const *page as usize),
+        )
+        .expect("Failed to map anonymous memory");
+    mapping.pages[index] = Some(page);
+    true
+}
+
+/// Allocates a zeroed page of anonymous memory from the heap, or returns `None` if there isn't
+/// enough memory.
+fn alloc_zeroed_page() -> Option<Box<AnonPage>> {
+    // SAFETY: `AnonPage` doesn't have a size of zero.
+    let page = unsafe { alloc_zeroed(Layout::new::<AnonPage>()) }.cast::<AnonPage>();
+    if page.is_null() {
+        return None;
+    }
+    // SAFETY: The page was just allocated by the global allocator with the layout of `AnonPage`,
+    // and all zeroes is a valid `AnonPage`.
+    Some(unsafe { Box::from_raw(page) })
+}
+
+/// Restores the pages of the user stack saved for the process whose child has just exited, and
+/// returns how many there were.
+///
+/// The stack is left read-only if that process is itself a child, so that its own writes are saved
+/// for its parent.
+fn restore_user_stack() -> usize {
+    let saved = SAVED_STACKS.lock().pop().unwrap_or_default();
+    let stack = user_stack_region();
+    set_user_stack_read_only(&stack, false);
+    let mut restored = 0;
+    for (index, contents) in saved.iter().enumerate() {
+        if let Some(contents) = contents {
+            // SAFETY: The page is part of the user stack, which is writable and isn't accessed by
+            // anything else while the kernel is handling a syscall.
+            unsafe {
+                ptr::copy_nonoverlapping(contents.as_ptr(), user_stack_page(index), PAGE_SIZE);
+            }
+            restored += 1;
+        }
+    }
+    if !SAVED_STACKS.lock().is_empty() {
+        set_user_stack_read_only(&stack, true);
+    }
+    restored
+}
+
+/// Handles a write by the running process to a page of the user stack which was made read-only by
+/// `fork`. The page's contents are saved for the waiting parent if this is the first write since
+/// the fork, and then the page is made writable.
+///
+/// Returns false if the exception wasn't such a write, or there wasn't enough memory to save the
+/// page.
+fn handle_stack_write_fault(esr: u64, far: u64) -> bool {
+    if esr >> 26 != ESR_EC_DATA_ABORT_LOWER
+        || esr & ESR_ISS_WNR == 0
+        || esr & ESR_DFSC_TYPE_MASK != ESR_DFSC_PERMISSION
+    {
+        return false;
+    }
+    let stack = user_stack_region();
+    let Ok(far) = usize::try_from(far) else {
+        return false;
+    };
+    if !(stack.start().0..stack.end().0).contains(&far) {
+        return false;
+    }
+    let index = (far - stack.start().0) / PAGE_SIZE;
+    if let Some(saved) = SAVED_STACKS.lock().last_mut()
+        && saved[index].is_none()
+    {
+        let Ok(mut contents) = try_vec(0u8, PAGE_SIZE) else {
+            return false;
+        };
+        // SAFETY: The page is part of the user stack, which is mapped and isn't written by anything
+        // else while the kernel is handling the exception.
+        contents
+            .copy_from_slice(unsafe { slice::from_raw_parts(user_stack_page(index), PAGE_SIZE) });
+        saved[index] = Some(contents);
+    }
+    let page_start = user_stack_page(index) as usize;
+    set_user_stack_read_only(
+        &MemoryRegion::new(page_start, page_start + PAGE_SIZE),
+        false,
+    );
+    true
+}
+
+/// Returns the stack pointer of the user program which caused the current exception.
+fn user_stack_pointer() -> usize {
+    let sp;
+    // SAFETY: Reading SP_EL0 has no side effects.
+    unsafe {
+        asm!("mrs {}, sp_el0", out(reg) sp, options(nomem, nostack));
+    }
+    sp
+}
+
+/// Sets the stack pointer which the user program will have when the current exception returns.
+fn set_user_stack_pointer(sp: usize) {
+    // SAFETY: SP_EL0 is only used by the user program, as the kernel uses SP_EL1.
+    unsafe {
+        asm!("msr sp_el0, {}", in(reg) sp, options(nomem, nostack));
+    }
+}
@@ -0,0 +1,66 @@
+// Copyright 2026 Google LLC.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Parsing of the offset from UTC which times from the RTC are displayed in.
+
+use chrono::FixedOffset;
+
+/// The name of the kernel command line argument which sets the UTC offset, such as
+/// `utc_offset=+05:30`.
+pub const BOOTARG: &str = "utc_offset";
+
+/// Parses a UTC offset of the form `+HH`, `+HHMM` or `+HH:MM`, or `-` in place of `+`. `0`, `Z`
+/// and `UTC` are also accepted for UTC itself.
+pub fn parse_utc_offset(s: &str) -> Option<FixedOffset> {
+    if matches!(s, "0" | "Z" | "UTC") {
+        return FixedOffset::east_opt(0);
+    }
+    let (sign, rest) = match s.split_at_checked(1)? {
+        ("+", rest) => (1, rest),
+        ("-", rest) => (-1, rest),
+        _ => return None,
+    };
+    let (hours, minutes) = match rest.split_once(':') {
+        Some((hours, minutes)) => (hours, minutes),
+        None if rest.len() == 4 => rest.split_at(2),
+        None => (rest, "0"),
+    };
+    if hours.is_empty() || minutes.is_empty() || hours.len() > 2 || minutes.len() > 2 {
+        return None;
+    }
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_valid() {
+        assert_eq!(parse_utc_offset("UTC"), FixedOffset::east_opt(0));
+        assert_eq!(parse_utc_offset("0"), FixedOffset::east_opt(0));
+        assert_eq!(parse_utc_offset("+1"), FixedOffset::east_opt(3600));
+        assert_eq!(parse_utc_offset("+05:30"), FixedOffset::east_opt(19800));
+        assert_eq!(parse_utc_offset("+0545"), FixedOffset::east_opt(20700));
+        assert_eq!(parse_utc_offset("-08"), FixedOffset::west_opt(28800));
+        assert_eq!(parse_utc_offset("-03:30"), FixedOffset::west_opt(12600));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert_eq!(parse_utc_offset(""), None);
+        assert_eq!(parse_utc_offset("5"), None);
+        assert_eq!(parse_utc_offset("+"), None);
+        assert_eq!(parse_utc_offset("+24"), None);
+        assert_eq!(parse_utc_offset("+05:60"), None);
+        assert_eq!(parse_utc_offset("+5:"), None);
+        assert_eq!(parse_utc_offset("+123"), None);
+        assert_eq!(parse_utc_offset("+aa"), None);
+    }
+}